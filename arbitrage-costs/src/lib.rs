@@ -0,0 +1,117 @@
+//! What a round of cross-chain arbitrage costs and earns, the same for the
+//! agent tool (listen-kit) and the indexer's scanner (listen-data)
+use serde::{Deserialize, Serialize};
+
+/// what an opportunity is sized at, the profit is of a trade this big
+pub const DEFAULT_TRADE_SIZE_USD: f64 = 1_000.;
+
+/// a router swap on the evm side
+const EVM_SWAP_GAS: u64 = 150_000;
+/// the wormhole token bridge transfer and its redeem, one of them is on
+/// the evm side whichever way the tokens go
+const WORMHOLE_EVM_GAS: u64 = 250_000;
+/// posting the vaa and the solana side of the transfer, a few signatures
+const WORMHOLE_SOLANA_LAMPORTS: u64 = 25_000;
+
+const EVM_NATIVE_DECIMALS: i32 = 18;
+const SOL_DECIMALS: i32 = 9;
+
+/// what a round of the arbitrage costs besides the price itself
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ArbitrageCosts {
+    pub bridge_usd: f64,
+    /// the swaps on both chains
+    pub gas_usd: f64,
+}
+
+impl ArbitrageCosts {
+    /// at `evm_gas_price` wei, `solana_swap_fee_lamports` is the base and
+    /// priority fee of the swap on solana
+    pub fn new(
+        evm_gas_price: u128,
+        evm_native_price_usd: f64,
+        solana_swap_fee_lamports: u64,
+        sol_price_usd: f64,
+    ) -> Self {
+        let evm_usd = |gas: u64| {
+            (gas as u128 * evm_gas_price) as f64 / 10f64.powi(EVM_NATIVE_DECIMALS)
+                * evm_native_price_usd
+        };
+        let sol_usd = |lamports: u64| lamports as f64 / 10f64.powi(SOL_DECIMALS) * sol_price_usd;
+        Self {
+            bridge_usd: evm_usd(WORMHOLE_EVM_GAS) + sol_usd(WORMHOLE_SOLANA_LAMPORTS),
+            gas_usd: evm_usd(EVM_SWAP_GAS) + sol_usd(solana_swap_fee_lamports),
+        }
+    }
+
+    pub fn total_usd(&self) -> f64 {
+        self.bridge_usd + self.gas_usd
+    }
+}
+
+/// how far apart the chains price a token and what closing the gap earns
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Spread {
+    /// of the evm price over the solana one, positive when the token is
+    /// dearer on the evm chain
+    pub price_diff_pct: f64,
+    /// of buying `trade_size_usd` where the token is cheaper, bridging it
+    /// and selling it on the other chain, after costs
+    pub estimated_profit_usd: f64,
+}
+
+/// `None` unless both prices are positive
+pub fn spread(
+    solana_price_usd: f64,
+    evm_price_usd: f64,
+    costs: &ArbitrageCosts,
+    trade_size_usd: f64,
+) -> Option<Spread> {
+    if solana_price_usd <= 0. || evm_price_usd <= 0. {
+        return None;
+    }
+    let gross_profit_usd = trade_size_usd * (evm_price_usd - solana_price_usd).abs()
+        / evm_price_usd.min(solana_price_usd);
+    Some(Spread {
+        price_diff_pct: (evm_price_usd - solana_price_usd) / solana_price_usd * 100.,
+        estimated_profit_usd: gross_profit_usd - costs.total_usd(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_COSTS: ArbitrageCosts = ArbitrageCosts {
+        bridge_usd: 0.,
+        gas_usd: 0.,
+    };
+
+    #[test]
+    fn test_arbitrage_costs() {
+        // 1 gwei on an eth at $2000, a signature without priority fee on
+        // solana at $100
+        let costs = ArbitrageCosts::new(1_000_000_000, 2_000., 5_000, 100.);
+        assert!((costs.bridge_usd - (0.5 + 0.0025)).abs() < 1e-9);
+        assert!((costs.gas_usd - (0.3 + 0.0005)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_spread() {
+        let costs = ArbitrageCosts {
+            bridge_usd: 5.,
+            gas_usd: 3.,
+        };
+        // 2% dearer on the evm chain, $20 on $1000 less $8 of fees
+        let evm_dearer = spread(1., 1.02, &costs, 1_000.).unwrap();
+        assert!((evm_dearer.price_diff_pct - 2.).abs() < 1e-9);
+        assert!((evm_dearer.estimated_profit_usd - 12.).abs() < 1e-9);
+
+        // the other way around, the fees eat the 0.5%
+        let solana_dearer = spread(1.005, 1., &costs, 1_000.).unwrap();
+        assert!(solana_dearer.price_diff_pct < 0.);
+        assert!((solana_dearer.estimated_profit_usd - (-3.)).abs() < 1e-9);
+
+        assert_eq!(spread(0., 1., &NO_COSTS, 1_000.), None);
+    }
+}
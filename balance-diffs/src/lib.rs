@@ -0,0 +1,179 @@
+//! What a transaction changed, summarized the same way by the indexer (from
+//! the side of the pool) and by the agent tools (from the side of the user)
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const WSOL_MINT: &str = "So11111111111111111111111111111111111111112";
+
+/// changes smaller than this are rounding, not transfers
+const DUST: f64 = 1e-9;
+
+/// A token account balance before or after a transaction, native SOL can be
+/// passed in as `WSOL_MINT`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenBalance {
+    pub mint: String,
+    pub owner: String,
+    pub ui_amount: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SwapSummary {
+    /// the mint whose balance went down the most
+    pub sold_mint: Option<String>,
+    pub sold_amount: f64,
+    /// the mint whose balance went up the most
+    pub bought_mint: Option<String>,
+    pub bought_amount: f64,
+    /// net change of SOL, negative when it was spent
+    pub sol_delta: f64,
+}
+
+/// net change per mint over all of the given balances, callers narrow the
+/// balances down to the owner they summarize for
+pub fn summarize_balance_changes(pre: &[TokenBalance], post: &[TokenBalance]) -> SwapSummary {
+    // first appearance order keeps the summary deterministic on ties
+    let mut deltas: Vec<(&str, f64)> = Vec::new();
+    for (balances, sign) in [(pre, -1.0), (post, 1.0)] {
+        for balance in balances {
+            let delta = sign * balance.ui_amount;
+            match deltas.iter_mut().find(|(mint, _)| *mint == balance.mint) {
+                Some((_, total)) => *total += delta,
+                None => deltas.push((&balance.mint, delta)),
+            }
+        }
+    }
+
+    let mut summary = SwapSummary::default();
+    for (mint, delta) in deltas {
+        if mint == WSOL_MINT {
+            summary.sol_delta = delta;
+        }
+        if delta < -DUST && -delta > summary.sold_amount {
+            summary.sold_mint = Some(mint.to_string());
+            summary.sold_amount = -delta;
+        } else if delta > DUST && delta > summary.bought_amount {
+            summary.bought_mint = Some(mint.to_string());
+            summary.bought_amount = delta;
+        }
+    }
+    summary
+}
+
+fn symbol(mint: &str) -> &str {
+    if mint == WSOL_MINT {
+        "SOL"
+    } else {
+        mint
+    }
+}
+
+fn amount(amount: f64) -> String {
+    let formatted = format!("{:.6}", amount);
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+impl fmt::Display for SwapSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.sold_mint, &self.bought_mint) {
+            (Some(sold), Some(bought)) => write!(
+                f,
+                "sold {} {} for {} {}",
+                amount(self.sold_amount),
+                symbol(sold),
+                amount(self.bought_amount),
+                symbol(bought)
+            ),
+            (Some(sold), None) => {
+                write!(f, "sent {} {}", amount(self.sold_amount), symbol(sold))
+            }
+            (None, Some(bought)) => write!(
+                f,
+                "received {} {}",
+                amount(self.bought_amount),
+                symbol(bought)
+            ),
+            (None, None) => write!(f, "no balance changes"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAYDIUM_AUTHORITY: &str = "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1";
+
+    fn balance(mint: &str, ui_amount: f64) -> TokenBalance {
+        TokenBalance {
+            mint: mint.to_string(),
+            owner: RAYDIUM_AUTHORITY.to_string(),
+            ui_amount,
+        }
+    }
+
+    fn round(amount: f64) -> f64 {
+        (amount * 1e6).round() / 1e6
+    }
+
+    #[test]
+    fn test_pool_sells_token_for_sol() {
+        // the pool side of the `test_sol_for_token_2` swap in listen-data
+        let mint = "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon";
+        let summary = summarize_balance_changes(
+            &[
+                balance(WSOL_MINT, 450.295597127),
+                balance(mint, 61602947.9232689),
+            ],
+            &[
+                balance(WSOL_MINT, 450.345597127),
+                balance(mint, 61596125.50088912),
+            ],
+        );
+        assert_eq!(summary.sold_mint.as_deref(), Some(mint));
+        assert_eq!(round(summary.sold_amount), 6822.42238);
+        assert_eq!(summary.bought_mint.as_deref(), Some(WSOL_MINT));
+        assert_eq!(round(summary.bought_amount), 0.05);
+        assert_eq!(round(summary.sol_delta), 0.05);
+        assert_eq!(
+            summary.to_string(),
+            format!("sold 6822.42238 {} for 0.05 SOL", mint)
+        );
+    }
+
+    #[test]
+    fn test_new_and_closed_accounts() {
+        // a bought token lands in a freshly created account, the sold one
+        // is emptied and closed
+        let (sold, bought) = (
+            "G6ZaVuWEuGtFRooaiHQWjDzoCzr2f7BWr3PhsQRnjSTE",
+            "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon",
+        );
+        let summary =
+            summarize_balance_changes(&[balance(sold, 8907.148685)], &[balance(bought, 3.352408)]);
+        assert_eq!(summary.sold_mint.as_deref(), Some(sold));
+        assert_eq!(summary.sold_amount, 8907.148685);
+        assert_eq!(summary.bought_mint.as_deref(), Some(bought));
+        assert_eq!(summary.bought_amount, 3.352408);
+        assert_eq!(summary.sol_delta, 0.0);
+    }
+
+    #[test]
+    fn test_one_sided_and_unchanged() {
+        let summary = summarize_balance_changes(
+            &[balance(WSOL_MINT, 145.774357667)],
+            &[balance(WSOL_MINT, 142.421949398)],
+        );
+        assert_eq!(summary.bought_mint, None);
+        assert_eq!(round(summary.sol_delta), -3.352408);
+        assert_eq!(summary.to_string(), "sent 3.352408 SOL");
+
+        let unchanged = [balance(WSOL_MINT, 1.0)];
+        let summary = summarize_balance_changes(&unchanged, &unchanged);
+        assert_eq!(summary, SwapSummary::default());
+        assert_eq!(summary.to_string(), "no balance changes");
+    }
+}
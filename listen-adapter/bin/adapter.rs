@@ -6,11 +6,13 @@ use tracing::info;
 
 use listen_adapter::{
     db::make_db,
+    market_overview::spawn_market_overview_refresher,
     redis_client::make_redis_client,
     redis_subscriber::create_redis_subscriber,
     routes::{
-        get_candlesticks, get_chat, get_metadata, get_price, health_check, query_db, save_chat,
-        top_tokens, ws_route,
+        get_candlesticks, get_chat, get_gainers_losers, get_market_overview, get_metadata,
+        get_price, get_smart_money, get_swaps, get_trades, health_check, price_feed_route,
+        query_db, save_chat, top_tokens, ws_route,
     },
     state::AppState,
 };
@@ -33,6 +35,8 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("Failed to create Redis client");
 
+    spawn_market_overview_refresher(clickhouse_db.clone(), redis_client.clone());
+
     let app_state = AppState {
         redis_subscriber,
         redis_client,
@@ -46,12 +50,18 @@ async fn main() -> std::io::Result<()> {
             .wrap(Cors::permissive())
             .app_data(app_data.clone())
             .route("/ws", web::get().to(ws_route))
+            .route("/ws/prices", web::get().to(price_feed_route))
             .route("/healthz", web::get().to(health_check))
             .route("/top-tokens", web::get().to(top_tokens))
+            .route("/market-overview", web::get().to(get_market_overview))
+            .route("/gainers-losers", web::get().to(get_gainers_losers))
             .route("/candlesticks", web::get().to(get_candlesticks))
             .route("/metadata", web::get().to(get_metadata))
             .route("/query", web::post().to(query_db))
             .route("/price", web::get().to(get_price))
+            .route("/smart-money", web::get().to(get_smart_money))
+            .route("/swaps", web::get().to(get_swaps))
+            .route("/trades", web::get().to(get_trades))
             // get and save chat routes are unauthenticated, those are for "shared" chats
             .route("/get-chat", web::get().to(get_chat))
             .route("/save-chat", web::post().to(save_chat))
@@ -0,0 +1,100 @@
+use super::ClickhouseDb;
+use anyhow::Result;
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+
+/// tokens with fewer swaps in the window are left out, a couple of trades
+/// on a micro cap move its price by any amount
+pub const MIN_SWAPS: u64 = 10;
+
+/// a token counts as wash-traded when a single wallet made more than this
+/// share of its swaps in the window
+pub const WASH_TRADER_SHARE: f64 = 0.5;
+
+pub const MAX_WINDOW_MINUTES: u64 = 7 * 24 * 60;
+pub const MAX_LIMIT: u32 = 50;
+
+#[derive(Debug, Serialize, Deserialize, Row)]
+pub struct TokenPriceChange {
+    pub mint: String,
+    pub name: String,
+    pub pct_change: f64,
+    pub volume_usd: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GainersLosersReport {
+    pub gainers: Vec<TokenPriceChange>,
+    pub losers: Vec<TokenPriceChange>,
+}
+
+impl ClickhouseDb {
+    async fn get_price_changes(
+        &self,
+        start_time: u64,
+        order: &str,
+        limit: u32,
+    ) -> Result<Vec<TokenPriceChange>> {
+        let query = format!(
+            r#"
+            SELECT
+                pubkey AS mint,
+                argMax(name, timestamp) AS name,
+                (argMax(price, timestamp) - argMin(price, timestamp))
+                    / argMin(price, timestamp) * 100 AS pct_change,
+                sum(swap_amount) AS volume_usd
+            FROM price_updates
+            WHERE timestamp >= {start_time} AND price > 0
+                AND pubkey NOT IN (
+                    SELECT pubkey FROM (
+                        SELECT pubkey, owner, count() AS swaps
+                        FROM price_updates
+                        WHERE timestamp >= {start_time}
+                        GROUP BY pubkey, owner
+                    )
+                    GROUP BY pubkey
+                    HAVING max(swaps) > sum(swaps) * {WASH_TRADER_SHARE}
+                )
+            GROUP BY pubkey
+            HAVING count() >= {MIN_SWAPS}
+            ORDER BY pct_change {order}
+            LIMIT {limit}
+            "#
+        );
+
+        Ok(self
+            .client
+            .query(&query)
+            .fetch_all::<TokenPriceChange>()
+            .await?)
+    }
+
+    /// by price change over the last `window_minutes`, of tokens with at
+    /// least `MIN_SWAPS` swaps that aren't wash-traded
+    pub async fn get_gainers_losers(
+        &self,
+        window_minutes: u64,
+        limit: u32,
+    ) -> Result<GainersLosersReport> {
+        let now = chrono::Utc::now().timestamp() as u64;
+        let start_time = now.saturating_sub(window_minutes * 60);
+
+        Ok(GainersLosersReport {
+            gainers: self.get_price_changes(start_time, "DESC", limit).await?,
+            losers: self.get_price_changes(start_time, "ASC", limit).await?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::make_db;
+
+    #[tokio::test]
+    async fn test_get_gainers_losers() {
+        let db = make_db().unwrap();
+        let report = db.get_gainers_losers(60, 5).await.unwrap();
+        assert!(report.gainers.len() <= 5);
+        assert!(report.losers.len() <= 5);
+    }
+}
@@ -0,0 +1,178 @@
+use super::top_tokens::TopToken;
+use super::ClickhouseDb;
+use anyhow::Result;
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+
+/// same threshold as the large swap notifications of the indexer
+pub const LARGE_SWAP_USD: f64 = 50_000.0;
+
+/// tokens below this 24h volume are left out of the gainers and losers,
+/// a single swap moves their price by any amount
+pub const MOVERS_MIN_VOLUME_USD: f64 = 10_000.0;
+
+const TOP_LIMIT: usize = 10;
+const NEW_TOKENS_LIMIT: usize = 50;
+
+const HOUR: u64 = 3600;
+const DAY: u64 = 24 * HOUR;
+
+#[derive(Debug, Serialize, Deserialize, Row)]
+pub struct NewToken {
+    pub name: String,
+    pub pubkey: String,
+    /// first swap of the token the indexer saw
+    pub launched_at: u64,
+    pub market_cap: f64,
+}
+
+#[derive(Debug, Row, Deserialize)]
+struct Totals {
+    tokens_tracked: u64,
+    volume_24h: f64,
+    swaps_24h: u64,
+    large_swaps_1h: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MarketOverview {
+    pub total_tokens_tracked: u64,
+    pub total_volume_24h: f64,
+    pub total_swaps_24h: u64,
+    pub top_by_volume: Vec<TopToken>,
+    /// by 24h price change, of tokens with at least `MOVERS_MIN_VOLUME_USD`
+    pub top_gainers: Vec<TopToken>,
+    pub top_losers: Vec<TopToken>,
+    pub new_tokens_1h: Vec<NewToken>,
+    pub graduations_24h: u64,
+    pub large_swaps_1h: u64,
+    /// unix seconds
+    pub computed_at: u64,
+}
+
+impl ClickhouseDb {
+    /// tokens traded since `start_time`, ordered by `order_by`
+    async fn get_token_stats(
+        &self,
+        start_time: u64,
+        order_by: &str,
+        min_volume: f64,
+    ) -> Result<Vec<TopToken>> {
+        let query = format!(
+            r#"
+            SELECT
+                name,
+                pubkey,
+                argMax(price, timestamp) AS price,
+                argMax(market_cap, timestamp) AS market_cap,
+                sum(swap_amount) AS volume_24h,
+                (argMax(price, timestamp) - argMin(price, timestamp))
+                    / argMin(price, timestamp) * 100 AS price_change_24h,
+                count() AS trade_count,
+                uniqExact(owner) AS unique_traders
+            FROM price_updates
+            WHERE timestamp >= {start_time} AND price > 0
+            GROUP BY name, pubkey
+            HAVING volume_24h >= {min_volume}
+            ORDER BY {order_by}
+            LIMIT {TOP_LIMIT}
+            "#
+        );
+
+        Ok(self.client.query(&query).fetch_all::<TopToken>().await?)
+    }
+
+    pub async fn get_market_overview(&self) -> Result<MarketOverview> {
+        let now = chrono::Utc::now().timestamp() as u64;
+        let (day_ago, hour_ago) = (now - DAY, now - HOUR);
+
+        let totals = self
+            .client
+            .query(&format!(
+                r#"
+                SELECT
+                    uniq(pubkey) AS tokens_tracked,
+                    sumIf(swap_amount, timestamp >= {day_ago}) AS volume_24h,
+                    countIf(timestamp >= {day_ago}) AS swaps_24h,
+                    countIf(timestamp >= {hour_ago} AND swap_amount >= {LARGE_SWAP_USD})
+                        AS large_swaps_1h
+                FROM price_updates
+                "#
+            ))
+            .fetch_one::<Totals>()
+            .await?;
+
+        let top_by_volume = self
+            .get_token_stats(day_ago, "volume_24h DESC", 0.0)
+            .await?;
+        let top_gainers = self
+            .get_token_stats(day_ago, "price_change_24h DESC", MOVERS_MIN_VOLUME_USD)
+            .await?;
+        let top_losers = self
+            .get_token_stats(day_ago, "price_change_24h ASC", MOVERS_MIN_VOLUME_USD)
+            .await?;
+
+        let new_tokens_1h = self
+            .client
+            .query(&format!(
+                r#"
+                SELECT
+                    name,
+                    pubkey,
+                    min(timestamp) AS launched_at,
+                    argMax(market_cap, timestamp) AS market_cap
+                FROM price_updates
+                GROUP BY name, pubkey
+                HAVING launched_at >= {hour_ago}
+                ORDER BY launched_at DESC
+                LIMIT {NEW_TOKENS_LIMIT}
+                "#
+            ))
+            .fetch_all::<NewToken>()
+            .await?;
+
+        // pump.fun tokens only trade on raydium once they graduate, so
+        // their first indexed swap is the graduation
+        let graduations_24h = self
+            .client
+            .query(&format!(
+                r#"
+                SELECT count() FROM (
+                    SELECT pubkey, min(timestamp) AS first_seen
+                    FROM price_updates
+                    WHERE is_pump
+                    GROUP BY pubkey
+                    HAVING first_seen >= {day_ago}
+                )
+                "#
+            ))
+            .fetch_one::<u64>()
+            .await?;
+
+        Ok(MarketOverview {
+            total_tokens_tracked: totals.tokens_tracked,
+            total_volume_24h: totals.volume_24h,
+            total_swaps_24h: totals.swaps_24h,
+            top_by_volume,
+            top_gainers,
+            top_losers,
+            new_tokens_1h,
+            graduations_24h,
+            large_swaps_1h: totals.large_swaps_1h,
+            computed_at: now,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::make_db;
+
+    #[tokio::test]
+    async fn test_get_market_overview() {
+        let db = make_db().unwrap();
+        let overview = db.get_market_overview().await.unwrap();
+        println!("{:#?}", overview);
+        assert!(overview.top_by_volume.len() <= 10);
+    }
+}
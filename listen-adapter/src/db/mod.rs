@@ -5,6 +5,8 @@ use std::sync::Arc;
 use tracing::debug;
 
 pub mod candlesticks;
+pub mod gainers_losers;
+pub mod market_overview;
 pub mod query;
 pub mod top_tokens;
 
@@ -22,6 +24,16 @@ pub struct PriceUpdate {
     pub multi_hop: bool,
     pub is_buy: bool,
     pub is_pump: bool,
+    /// human, bot or unknown
+    pub trader_type: String,
+    /// launching, pumping, peaking, declining, dead or unknown
+    pub lifecycle_stage: String,
+    /// version of the `price_updates` schema the row was written with
+    pub schema_version: u8,
+    /// rolling VWAP or median, if the indexer smooths prices
+    pub smoothed_price: Option<f64>,
+    /// false while the slot of the swap may still be rolled back
+    pub finalized: bool,
 }
 
 pub struct ClickhouseDb {
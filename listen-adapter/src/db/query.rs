@@ -17,6 +17,47 @@ impl ClickhouseDb {
         Ok(result)
     }
 
+    /// the latest `limit` swaps of a mint, newest first
+    pub async fn get_recent_trades(&self, mint: &str, limit: usize) -> Result<Vec<PriceUpdate>> {
+        let result = self
+            .client
+            .query(
+                r#"
+                SELECT ?fields FROM price_updates
+                WHERE pubkey = ? AND NOT multi_hop
+                ORDER BY timestamp DESC, slot DESC
+                LIMIT ?
+                "#,
+            )
+            .bind(mint)
+            .bind(limit as u64)
+            .fetch_all::<PriceUpdate>()
+            .await?;
+
+        Ok(result)
+    }
+
+    /// the swaps recorded for any of `signatures`, newest first
+    pub async fn get_by_signatures(&self, signatures: &[String]) -> Result<Vec<PriceUpdate>> {
+        if signatures.is_empty() {
+            return Ok(vec![]);
+        }
+        let result = self
+            .client
+            .query(
+                r#"
+                SELECT ?fields FROM price_updates
+                WHERE signature IN ?
+                ORDER BY timestamp DESC
+                "#,
+            )
+            .bind(signatures)
+            .fetch_all::<PriceUpdate>()
+            .await?;
+
+        Ok(result)
+    }
+
     pub async fn generic_query(&self, sql: &str) -> Result<Vec<PriceUpdate>> {
         let result = self.client.query(sql).fetch_all::<PriceUpdate>().await?;
 
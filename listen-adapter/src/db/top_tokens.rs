@@ -11,6 +11,11 @@ pub struct TopToken {
     pub market_cap: f64,
     pub volume_24h: f64,
     pub price_change_24h: f64,
+    /// swaps over the timeframe, absent from overviews cached before it
+    #[serde(default)]
+    pub trade_count: u64,
+    #[serde(default)]
+    pub unique_traders: u64,
 }
 
 impl ClickhouseDb {
@@ -48,7 +53,9 @@ impl ClickhouseDb {
                     SELECT
                         name,
                         pubkey,
-                        sum(swap_amount) as volume_24h
+                        sum(swap_amount) as volume_24h,
+                        count() as trade_count,
+                        uniqExact(owner) as unique_traders
                     FROM price_updates
                     WHERE timestamp >= {start_time}
                     GROUP BY name, pubkey
@@ -68,7 +75,9 @@ impl ClickhouseDb {
                 lp.price,
                 lp.market_cap,
                 v.volume_24h,
-                pc.price_change_24h
+                pc.price_change_24h,
+                v.trade_count,
+                v.unique_traders
             FROM latest_prices lp
             LEFT JOIN volumes v ON lp.name = v.name AND lp.pubkey = v.pubkey
             LEFT JOIN price_changes pc ON lp.name = pc.name AND lp.pubkey = pc.pubkey
@@ -180,4 +189,18 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_get_top_tokens_trade_counts() -> Result<()> {
+        let db = make_db()?;
+        let tokens = db.get_top_tokens(10, None, None, Some(3600), false).await?;
+
+        assert!(tokens.len() <= 10);
+        assert!(tokens
+            .windows(2)
+            .all(|w| w[0].volume_24h >= w[1].volume_24h));
+        assert!(tokens.iter().all(|t| t.unique_traders <= t.trade_count));
+
+        Ok(())
+    }
 }
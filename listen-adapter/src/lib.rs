@@ -1,5 +1,6 @@
 pub mod db;
 pub mod error;
+pub mod market_overview;
 pub mod redis_client;
 pub mod redis_subscriber;
 pub mod routes;
@@ -0,0 +1,35 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tracing::{debug, error};
+
+use crate::db::ClickhouseDb;
+use crate::redis_client::RedisClient;
+
+pub const MARKET_OVERVIEW_INTERVAL: Duration = Duration::from_secs(60);
+
+/// recomputes the market overview every `MARKET_OVERVIEW_INTERVAL` and
+/// caches it for the `/market-overview` route
+pub fn spawn_market_overview_refresher(
+    clickhouse_db: Arc<ClickhouseDb>,
+    redis_client: Arc<RedisClient>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(MARKET_OVERVIEW_INTERVAL);
+        loop {
+            interval.tick().await;
+            let overview = match clickhouse_db.get_market_overview().await {
+                Ok(overview) => overview,
+                Err(e) => {
+                    error!("Error computing market overview: {}", e);
+                    continue;
+                }
+            };
+            match redis_client.set_market_overview(&overview).await {
+                Ok(()) => debug!(computed_at = overview.computed_at, "market overview cached"),
+                Err(e) => error!("Error caching market overview: {}", e),
+            }
+        }
+    })
+}
@@ -4,6 +4,14 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::debug;
 
+use crate::db::gainers_losers::GainersLosersReport;
+use crate::db::market_overview::MarketOverview;
+
+const MARKET_OVERVIEW_KEY: &str = "solana:market_overview";
+/// a few missed refreshes before the overview counts as stale
+const MARKET_OVERVIEW_TTL_SECS: u64 = 300;
+const GAINERS_LOSERS_TTL_SECS: u64 = 60;
+
 pub struct RedisClient {
     pool: bb8::Pool<RedisConnectionManager>,
 }
@@ -99,6 +107,132 @@ impl RedisClient {
         }
     }
 
+    /// smart money activity emitted by the indexer since `since_ms` (unix
+    /// millis), oldest first
+    pub async fn get_smart_money_activity(&self, since_ms: u64) -> Result<Vec<serde_json::Value>> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .context("Failed to get Redis connection")?;
+
+        // stream ids are `<millis>-<seq>`, so the range is by time
+        let entries: Vec<(String, Vec<String>)> = cmd("XRANGE")
+            .arg("smart_money_events")
+            .arg(since_ms)
+            .arg("+")
+            .query_async(&mut *conn)
+            .await
+            .context("Failed to read smart money events")?;
+
+        entries
+            .into_iter()
+            .filter_map(|(_, fields)| {
+                fields
+                    .chunks(2)
+                    .find(|kv| kv[0] == "activity")
+                    .and_then(|kv| kv.get(1).cloned())
+            })
+            .map(|activity| {
+                serde_json::from_str(&activity)
+                    .context("Failed to deserialize smart money activity")
+            })
+            .collect()
+    }
+
+    /// written by the market overview refresher, expires when it stops
+    pub async fn set_market_overview(&self, overview: &MarketOverview) -> Result<()> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .context("Failed to get Redis connection")?;
+
+        let _: () = cmd("SET")
+            .arg(MARKET_OVERVIEW_KEY)
+            .arg(serde_json::to_string(overview)?)
+            .arg("EX")
+            .arg(MARKET_OVERVIEW_TTL_SECS)
+            .query_async(&mut *conn)
+            .await
+            .context("Failed to save market overview")?;
+
+        Ok(())
+    }
+
+    pub async fn get_market_overview(&self) -> Result<Option<MarketOverview>> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .context("Failed to get Redis connection")?;
+
+        let value: Option<String> = cmd("GET")
+            .arg(MARKET_OVERVIEW_KEY)
+            .query_async(&mut *conn)
+            .await
+            .context("Failed to get market overview")?;
+
+        value
+            .map(|json_str| {
+                serde_json::from_str(&json_str).context("Failed to deserialize market overview")
+            })
+            .transpose()
+    }
+
+    fn make_gainers_losers_key(&self, window_minutes: u64, limit: u32) -> String {
+        format!("solana:gainers_losers:{}:{}", window_minutes, limit)
+    }
+
+    /// cached for `GAINERS_LOSERS_TTL_SECS` per window and limit
+    pub async fn set_gainers_losers(
+        &self,
+        window_minutes: u64,
+        limit: u32,
+        report: &GainersLosersReport,
+    ) -> Result<()> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .context("Failed to get Redis connection")?;
+
+        let _: () = cmd("SET")
+            .arg(self.make_gainers_losers_key(window_minutes, limit))
+            .arg(serde_json::to_string(report)?)
+            .arg("EX")
+            .arg(GAINERS_LOSERS_TTL_SECS)
+            .query_async(&mut *conn)
+            .await
+            .context("Failed to save gainers and losers")?;
+
+        Ok(())
+    }
+
+    pub async fn get_gainers_losers(
+        &self,
+        window_minutes: u64,
+        limit: u32,
+    ) -> Result<Option<GainersLosersReport>> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .context("Failed to get Redis connection")?;
+
+        let value: Option<String> = cmd("GET")
+            .arg(self.make_gainers_losers_key(window_minutes, limit))
+            .query_async(&mut *conn)
+            .await
+            .context("Failed to get gainers and losers")?;
+
+        value
+            .map(|json_str| {
+                serde_json::from_str(&json_str).context("Failed to deserialize gainers and losers")
+            })
+            .transpose()
+    }
+
     fn make_chat_key(&self, chat_id: &str) -> String {
         format!("chats:shared:{}", chat_id)
     }
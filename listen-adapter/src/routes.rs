@@ -1,4 +1,5 @@
-use crate::websocket::handle_ws_connection;
+use crate::db::gainers_losers::{MAX_LIMIT, MAX_WINDOW_MINUTES};
+use crate::websocket::{handle_price_feed_connection, handle_ws_connection};
 use crate::{db::candlesticks::CandlestickInterval, state::AppState};
 use actix_web::{error::InternalError, http::StatusCode, web, Error, HttpRequest, HttpResponse};
 use regex::Regex;
@@ -23,6 +24,22 @@ pub async fn ws_route(
     Ok(res)
 }
 
+pub async fn price_feed_route(
+    req: HttpRequest,
+    stream: web::Payload,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (res, session, msg_stream) = actix_ws::handle(&req, stream)?;
+
+    actix_web::rt::spawn(handle_price_feed_connection(
+        session,
+        msg_stream,
+        state.redis_subscriber.clone(),
+    ));
+
+    Ok(res)
+}
+
 pub async fn health_check() -> HttpResponse {
     let timestamp = chrono::Utc::now().timestamp();
     HttpResponse::Ok().json(json!({
@@ -64,6 +81,75 @@ pub async fn top_tokens(
     }
 }
 
+pub async fn get_market_overview(state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    match state.redis_client.get_market_overview().await {
+        Ok(Some(overview)) => Ok(HttpResponse::Ok().json(overview)),
+        Ok(None) => Ok(HttpResponse::ServiceUnavailable().json(json!({
+            "error": "Market overview not computed yet"
+        }))),
+        Err(e) => {
+            error!("Error getting market overview: {}", e);
+            Err(InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct GainersLosersQuery {
+    pub window_minutes: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+pub async fn get_gainers_losers(
+    state: web::Data<AppState>,
+    query: web::Query<GainersLosersQuery>,
+) -> Result<HttpResponse, Error> {
+    let window_minutes = query.window_minutes.unwrap_or(60);
+    let limit = query.limit.unwrap_or(10);
+    if window_minutes == 0 || window_minutes > MAX_WINDOW_MINUTES {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("window_minutes must be between 1 and {}", MAX_WINDOW_MINUTES)
+        })));
+    }
+    if limit == 0 || limit > MAX_LIMIT {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("limit must be between 1 and {}", MAX_LIMIT)
+        })));
+    }
+
+    // a cache miss or a redis error falls through to clickhouse
+    match state
+        .redis_client
+        .get_gainers_losers(window_minutes, limit)
+        .await
+    {
+        Ok(Some(report)) => return Ok(HttpResponse::Ok().json(report)),
+        Ok(None) => {}
+        Err(e) => error!("Error getting cached gainers and losers: {}", e),
+    }
+
+    match state
+        .clickhouse_db
+        .get_gainers_losers(window_minutes, limit)
+        .await
+    {
+        Ok(report) => {
+            if let Err(e) = state
+                .redis_client
+                .set_gainers_losers(window_minutes, limit, &report)
+                .await
+            {
+                error!("Error caching gainers and losers: {}", e);
+            }
+            Ok(HttpResponse::Ok().json(report))
+        }
+        Err(e) => {
+            error!("Error getting gainers and losers: {}", e);
+            Err(InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct CandlestickParams {
     pub mint: String,
@@ -175,6 +261,63 @@ pub async fn query_db(
     }
 }
 
+/// signatures per request, the rest are ignored
+pub const MAX_SWAP_SIGNATURES: usize = 100;
+
+#[derive(Deserialize)]
+pub struct SwapsQuery {
+    /// comma separated
+    pub signatures: String,
+}
+
+pub async fn get_swaps(
+    state: web::Data<AppState>,
+    query: web::Query<SwapsQuery>,
+) -> Result<HttpResponse, Error> {
+    let signatures: Vec<String> = query
+        .signatures
+        .split(',')
+        .map(str::trim)
+        .filter(|signature| !signature.is_empty())
+        .take(MAX_SWAP_SIGNATURES)
+        .map(String::from)
+        .collect();
+    match state.clickhouse_db.get_by_signatures(&signatures).await {
+        Ok(swaps) => Ok(HttpResponse::Ok().json(swaps)),
+        Err(e) => {
+            error!("Error getting swaps by signature: {}", e);
+            Err(InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
+        }
+    }
+}
+
+/// trades per request, larger limits are capped
+pub const MAX_TRADES: usize = 100;
+
+#[derive(Deserialize)]
+pub struct TradesQuery {
+    pub mint: String,
+    pub limit: Option<usize>,
+}
+
+pub async fn get_trades(
+    state: web::Data<AppState>,
+    query: web::Query<TradesQuery>,
+) -> Result<HttpResponse, Error> {
+    let limit = query.limit.unwrap_or(MAX_TRADES).min(MAX_TRADES);
+    match state
+        .clickhouse_db
+        .get_recent_trades(&query.mint, limit)
+        .await
+    {
+        Ok(trades) => Ok(HttpResponse::Ok().json(trades)),
+        Err(e) => {
+            error!("Error getting trades: {}", e);
+            Err(InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct MetadataQuery {
     mint: String,
@@ -228,3 +371,35 @@ pub async fn save_chat(
         }
     }
 }
+
+#[derive(Deserialize)]
+pub struct SmartMoneyQuery {
+    pub last_minutes: Option<u64>,
+    /// buy or sell, both if not set
+    pub action: Option<String>,
+}
+
+pub async fn get_smart_money(
+    state: web::Data<AppState>,
+    query: web::Query<SmartMoneyQuery>,
+) -> Result<HttpResponse, Error> {
+    let last_minutes = query.last_minutes.unwrap_or(60);
+    let since_ms =
+        (chrono::Utc::now().timestamp_millis() as u64).saturating_sub(last_minutes * 60 * 1000);
+    match state.redis_client.get_smart_money_activity(since_ms).await {
+        Ok(activity) => {
+            let activity: Vec<_> = activity
+                .into_iter()
+                .filter(|a| match &query.action {
+                    Some(action) => a["action"] == action.as_str(),
+                    None => true,
+                })
+                .collect();
+            Ok(HttpResponse::Ok().json(activity))
+        }
+        Err(e) => {
+            error!("Error getting smart money activity: {}", e);
+            Err(InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
+        }
+    }
+}
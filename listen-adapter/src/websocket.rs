@@ -2,7 +2,9 @@ use actix_ws::{Message, Session};
 use futures::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashSet;
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use tracing::{error, info};
 
 use crate::redis_subscriber::RedisSubscriber;
@@ -106,3 +108,204 @@ pub async fn handle_ws_connection(
 
     let _ = session.close(None).await;
 }
+
+/// Messages of the `/ws/prices` feed, unlike `/ws` these change the
+/// subscriptions incrementally, e.g.
+/// `{"action": "subscribe", "mints": ["..."]}`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum PriceFeedMessage {
+    Subscribe { mints: Vec<String> },
+    Unsubscribe { mints: Vec<String> },
+}
+
+#[derive(Debug, Default)]
+struct PriceSubscriptions {
+    all: bool,
+    mints: HashSet<String>,
+}
+
+impl PriceSubscriptions {
+    fn apply(&mut self, msg: PriceFeedMessage) {
+        match msg {
+            PriceFeedMessage::Subscribe { mints } => {
+                for mint in mints {
+                    if mint == "*" {
+                        self.all = true;
+                    } else {
+                        self.mints.insert(mint);
+                    }
+                }
+            }
+            PriceFeedMessage::Unsubscribe { mints } => {
+                for mint in mints {
+                    if mint == "*" {
+                        self.all = false;
+                    } else {
+                        self.mints.remove(&mint);
+                    }
+                }
+            }
+        }
+    }
+
+    fn matches(&self, update: &str) -> bool {
+        let Ok(json) = serde_json::from_str::<Value>(update) else {
+            return false;
+        };
+        match json.get("pubkey").and_then(|m| m.as_str()) {
+            Some(mint) => self.all || self.mints.contains(mint),
+            None => false,
+        }
+    }
+}
+
+/// where the price feed is written to
+pub(crate) trait PriceFeedSink {
+    async fn send_text(&mut self, text: String) -> Result<(), actix_ws::Closed>;
+}
+
+impl PriceFeedSink for Session {
+    async fn send_text(&mut self, text: String) -> Result<(), actix_ws::Closed> {
+        self.text(text).await
+    }
+}
+
+pub async fn handle_price_feed_connection(
+    mut session: Session,
+    msg_stream: impl Stream<Item = Result<Message, actix_ws::ProtocolError>> + Unpin,
+    redis_subscriber: Arc<RedisSubscriber>,
+) {
+    info!("Price feed connection established");
+    run_price_feed(&mut session, msg_stream, redis_subscriber.subscribe()).await;
+    let _ = session.close(None).await;
+}
+
+pub(crate) async fn run_price_feed(
+    sink: &mut impl PriceFeedSink,
+    mut msg_stream: impl Stream<Item = Result<Message, actix_ws::ProtocolError>> + Unpin,
+    mut redis_rx: broadcast::Receiver<String>,
+) {
+    let mut subscriptions = PriceSubscriptions::default();
+
+    loop {
+        tokio::select! {
+            // subscription changes take effect before any further updates
+            // are forwarded, so unsubscribed mints stop right away
+            biased;
+
+            Some(Ok(msg)) = msg_stream.next() => {
+                match msg {
+                    Message::Close(reason) => {
+                        info!("Price feed connection closed: {:?}", reason);
+                        break;
+                    }
+                    Message::Text(text) => {
+                        match serde_json::from_str::<PriceFeedMessage>(&text) {
+                            Ok(msg) => {
+                                subscriptions.apply(msg);
+                                info!("Updated price feed subscriptions: {:?}", subscriptions);
+                            }
+                            Err(e) => {
+                                let error_msg = ErrorMessage {
+                                    error: format!("Invalid message format: {}", e),
+                                };
+                                if let Err(e) = sink.send_text(serde_json::to_string(&error_msg).unwrap()).await {
+                                    error!("Failed to send error message: {}", e);
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            Ok(msg) = redis_rx.recv() => {
+                if subscriptions.matches(&msg) {
+                    if let Err(e) = sink.send_text(msg).await {
+                        error!("Failed to send message: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            else => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::mpsc;
+    use std::time::Duration;
+
+    impl PriceFeedSink for mpsc::UnboundedSender<String> {
+        async fn send_text(&mut self, text: String) -> Result<(), actix_ws::Closed> {
+            self.unbounded_send(text).map_err(|_| actix_ws::Closed)
+        }
+    }
+
+    fn price_update(mint: &str) -> String {
+        serde_json::json!({ "pubkey": mint, "price": 1.0 }).to_string()
+    }
+
+    fn text(msg: Value) -> Result<Message, actix_ws::ProtocolError> {
+        Ok(Message::Text(msg.to_string().into()))
+    }
+
+    #[tokio::test]
+    async fn test_price_feed_forwards_only_subscribed_mints() {
+        let (client_tx, client_rx) = mpsc::unbounded();
+        let (out_tx, mut out_rx) = mpsc::unbounded();
+        let (redis_tx, redis_rx) = broadcast::channel(16);
+
+        let feed = tokio::spawn(async move {
+            let mut sink = out_tx;
+            run_price_feed(&mut sink, client_rx, redis_rx).await;
+        });
+
+        client_tx
+            .unbounded_send(text(serde_json::json!({
+                "action": "subscribe",
+                "mints": ["mint_a"]
+            })))
+            .unwrap();
+        redis_tx.send(price_update("mint_b")).unwrap();
+        redis_tx.send(price_update("mint_a")).unwrap();
+
+        let received = out_rx.next().await.unwrap();
+        assert_eq!(received, price_update("mint_a"));
+
+        client_tx
+            .unbounded_send(text(serde_json::json!({
+                "action": "unsubscribe",
+                "mints": ["mint_a"]
+            })))
+            .unwrap();
+        redis_tx.send(price_update("mint_a")).unwrap();
+
+        drop(client_tx);
+        drop(redis_tx);
+        tokio::time::timeout(Duration::from_secs(1), feed)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(out_rx.next().await, None);
+    }
+
+    #[test]
+    fn test_price_subscriptions_wildcard() {
+        let mut subscriptions = PriceSubscriptions::default();
+        subscriptions.apply(PriceFeedMessage::Subscribe {
+            mints: vec!["*".to_string()],
+        });
+        assert!(subscriptions.matches(&price_update("mint_a")));
+        subscriptions.apply(PriceFeedMessage::Unsubscribe {
+            mints: vec!["*".to_string()],
+        });
+        assert!(!subscriptions.matches(&price_update("mint_a")));
+        assert!(!subscriptions.matches("not json"));
+    }
+}
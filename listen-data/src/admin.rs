@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use actix_web::{
+    get, middleware::Compress, post, web, App, HttpRequest, HttpResponse,
+    HttpServer, Responder,
+};
+use serde_json::json;
+use tracing::{error, info};
+
+use crate::db::ClickhouseDb;
+use crate::kv_store::RedisKVStore;
+use crate::metrics::metrics_handler;
+use crate::price_history::{price_history, PriceHistoryState};
+
+pub struct AdminState {
+    pub db: Arc<ClickhouseDb>,
+    /// admin endpoints reject every request without a token
+    pub token: Option<String>,
+}
+
+fn is_authorized(req: &HttpRequest, token: &Option<String>) -> bool {
+    let Some(token) = token else {
+        return false;
+    };
+    req.headers()
+        .get("authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .is_some_and(|t| t == token.as_str())
+}
+
+#[post("/admin/flush")]
+async fn flush(
+    req: HttpRequest,
+    state: web::Data<AdminState>,
+) -> impl Responder {
+    if !is_authorized(&req, &state.token) {
+        return HttpResponse::Unauthorized()
+            .json(json!({ "error": "Unauthorized" }));
+    }
+
+    match state.db.flush().await {
+        Ok(rows) => HttpResponse::Ok().json(json!({ "rows_written": rows })),
+        Err(e) => {
+            error!("Failed to flush buffered writes: {}", e);
+            HttpResponse::InternalServerError()
+                .json(json!({ "error": e.to_string() }))
+        }
+    }
+}
+
+#[get("/admin/buffer")]
+async fn buffer(
+    req: HttpRequest,
+    state: web::Data<AdminState>,
+) -> impl Responder {
+    if !is_authorized(&req, &state.token) {
+        return HttpResponse::Unauthorized()
+            .json(json!({ "error": "Unauthorized" }));
+    }
+
+    HttpResponse::Ok().json(state.db.buffered_rows().await)
+}
+
+/// referral fees earned per token, see `ReferralRevenueTracker`
+#[get("/admin/revenue")]
+async fn revenue(
+    req: HttpRequest,
+    state: web::Data<AdminState>,
+) -> impl Responder {
+    if !is_authorized(&req, &state.token) {
+        return HttpResponse::Unauthorized()
+            .json(json!({ "error": "Unauthorized" }));
+    }
+
+    match state.db.get_referral_revenue().await {
+        Ok(revenue) => HttpResponse::Ok().json(json!({ "tokens": revenue })),
+        Err(e) => {
+            error!("Failed to get referral revenue: {}", e);
+            HttpResponse::InternalServerError()
+                .json(json!({ "error": e.to_string() }))
+        }
+    }
+}
+
+/// serves the admin endpoints and the public token endpoints
+pub async fn run_admin_server(
+    db: Arc<ClickhouseDb>,
+    kv_store: Option<Arc<RedisKVStore>>,
+    token: Option<String>,
+    port: u16,
+) -> std::io::Result<()> {
+    let state = web::Data::new(AdminState {
+        db: db.clone(),
+        token,
+    });
+    let price_history_state =
+        web::Data::new(PriceHistoryState { db, kv_store });
+
+    info!("Starting admin server on port {}", port);
+    HttpServer::new(move || {
+        App::new()
+            .wrap(Compress::default())
+            .app_data(state.clone())
+            .app_data(price_history_state.clone())
+            .service(flush)
+            .service(buffer)
+            .service(revenue)
+            .service(price_history)
+            .route("/metrics", web::get().to(metrics_handler))
+    })
+    .workers(1)
+    .bind(("0.0.0.0", port))?
+    .run()
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::Database,
+        price::{PriceUpdate, PRICE_UPDATE_SCHEMA_VERSION},
+        util::make_db,
+    };
+    use actix_web::test;
+    use std::collections::HashMap;
+
+    fn make_price_update(i: u64) -> PriceUpdate {
+        PriceUpdate {
+            name: "admin-test".to_string(),
+            pubkey: "admin-test".to_string(),
+            price: 1.0,
+            market_cap: 1.0,
+            timestamp: i,
+            slot: i,
+            swap_amount: 1.0,
+            owner: "admin-test".to_string(),
+            signature: format!("admin-test-{}", i),
+            multi_hop: false,
+            is_buy: true,
+            is_pump: false,
+            trader_type: Default::default(),
+            lifecycle_stage: Default::default(),
+            schema_version: PRICE_UPDATE_SCHEMA_VERSION,
+            smoothed_price: None,
+            finalized: false,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_flush_empties_buffer() {
+        let db = make_db().await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AdminState {
+                    db: db.clone(),
+                    token: Some("test-token".to_string()),
+                }))
+                .service(flush)
+                .service(buffer),
+        )
+        .await;
+
+        for i in 0..5 {
+            db.insert_price(&make_price_update(i)).await.unwrap();
+        }
+        assert_eq!(db.buffered_rows().await["price_updates"], 5);
+
+        let req = test::TestRequest::post().uri("/admin/flush").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), 401);
+
+        let req = test::TestRequest::post()
+            .uri("/admin/flush")
+            .insert_header(("authorization", "Bearer test-token"))
+            .to_request();
+        let res: serde_json::Value =
+            test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res["rows_written"], 5);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/buffer")
+            .insert_header(("authorization", "Bearer test-token"))
+            .to_request();
+        let res: HashMap<String, u64> =
+            test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res["price_updates"], 0);
+    }
+}
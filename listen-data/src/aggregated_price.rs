@@ -0,0 +1,200 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::kv_store::KVStore;
+
+/// quotes older than this are left out of the aggregate
+pub const QUOTE_WINDOW_SECS: u64 = 30;
+
+pub const RAYDIUM_DEX: &str = "raydium";
+pub const METEORA_DLMM_DEX: &str = "meteora_dlmm";
+pub const PHOENIX_DEX: &str = "phoenix";
+pub const OPENBOOK_V2_DEX: &str = "openbook_v2";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuoteSide {
+    /// a sell into the pool
+    Bid,
+    /// a buy from the pool
+    Ask,
+}
+
+impl QuoteSide {
+    pub fn from_is_buy(is_buy: bool) -> Self {
+        if is_buy {
+            Self::Ask
+        } else {
+            Self::Bid
+        }
+    }
+}
+
+/// The last swap price of a mint on a dex, on one side
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DexQuote {
+    pub dex: String,
+    pub side: QuoteSide,
+    pub price: f64,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AggregatedPrice {
+    /// highest price a seller got
+    pub best_bid: Option<f64>,
+    /// lowest price a buyer paid
+    pub best_ask: Option<f64>,
+    pub mid_price: Option<f64>,
+    /// negative when the best bid is above the best ask, across dexes
+    pub spread_bps: Option<f64>,
+    /// dexes with a quote in the window
+    pub source_count: usize,
+    /// median of the latest price of every dex
+    pub median_price: Option<f64>,
+}
+
+fn median(mut prices: Vec<f64>) -> Option<f64> {
+    if prices.is_empty() {
+        return None;
+    }
+    prices.sort_by(|a, b| a.total_cmp(b));
+    let middle = prices.len() / 2;
+    Some(if prices.len() % 2 == 0 {
+        (prices[middle - 1] + prices[middle]) / 2.0
+    } else {
+        prices[middle]
+    })
+}
+
+/// aggregate of the quotes at most `QUOTE_WINDOW_SECS` old at `now`
+pub fn aggregate_quotes(quotes: &[DexQuote], now: u64) -> AggregatedPrice {
+    let fresh: Vec<&DexQuote> = quotes
+        .iter()
+        .filter(|quote| {
+            quote.price > 0.0
+                && now.saturating_sub(quote.timestamp) <= QUOTE_WINDOW_SECS
+        })
+        .collect();
+
+    let best = |side: QuoteSide| {
+        fresh
+            .iter()
+            .filter(|quote| quote.side == side)
+            .map(|quote| quote.price)
+    };
+    let best_bid = best(QuoteSide::Bid).reduce(f64::max);
+    let best_ask = best(QuoteSide::Ask).reduce(f64::min);
+    let mid_price = match (best_bid, best_ask) {
+        (Some(bid), Some(ask)) => Some((bid + ask) / 2.0),
+        (bid, ask) => bid.or(ask),
+    };
+    let spread_bps = match (best_bid, best_ask, mid_price) {
+        (Some(bid), Some(ask), Some(mid)) => Some((ask - bid) / mid * 10_000.0),
+        _ => None,
+    };
+
+    let mut latest: Vec<&DexQuote> = Vec::new();
+    for quote in fresh {
+        match latest.iter_mut().find(|latest| latest.dex == quote.dex) {
+            Some(latest) if latest.timestamp < quote.timestamp => {
+                *latest = quote
+            }
+            Some(_) => {}
+            None => latest.push(quote),
+        }
+    }
+
+    AggregatedPrice {
+        best_bid,
+        best_ask,
+        mid_price,
+        spread_bps,
+        source_count: latest.len(),
+        median_price: median(latest.iter().map(|quote| quote.price).collect()),
+    }
+}
+
+/// prices of `mint` across the dexes it traded on in the last
+/// `QUOTE_WINDOW_SECS`
+pub async fn get_aggregated_price<K: KVStore>(
+    mint: &str,
+    kv_store: &K,
+) -> Result<AggregatedPrice> {
+    let quotes = kv_store.get_dex_quotes(mint).await?;
+    let now = chrono::Utc::now().timestamp() as u64;
+    Ok(aggregate_quotes(&quotes, now))
+}
+
+/// records a swap on `dex` as its quote and returns the price the market
+/// cap is computed from: the median once other dexes quote the mint too,
+/// the swap price otherwise
+pub async fn market_cap_price<K: KVStore>(
+    kv_store: &K,
+    mint: &str,
+    quote: DexQuote,
+) -> Result<f64> {
+    let price = quote.price;
+    kv_store.insert_dex_quote(mint, &quote).await?;
+    let aggregated = get_aggregated_price(mint, kv_store).await?;
+    Ok(match aggregated.median_price {
+        Some(median) if aggregated.source_count > 1 => median,
+        _ => price,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn quote(dex: &str, side: QuoteSide, price: f64, age: u64) -> DexQuote {
+        DexQuote {
+            dex: dex.to_string(),
+            side,
+            price,
+            timestamp: NOW - age,
+        }
+    }
+
+    #[test]
+    fn test_aggregate_quotes() {
+        let quotes = [
+            quote("raydium", QuoteSide::Ask, 1.02, 5),
+            quote("raydium", QuoteSide::Bid, 0.99, 2),
+            quote("orca", QuoteSide::Ask, 1.01, 10),
+            quote("orca", QuoteSide::Bid, 0.98, 12),
+            // wash trades on a third dex, most recent but a minute old
+            quote("meteora", QuoteSide::Ask, 5.0, 60),
+        ];
+        let price = aggregate_quotes(&quotes, NOW);
+        assert_eq!(price.best_bid, Some(0.99));
+        assert_eq!(price.best_ask, Some(1.01));
+        assert_eq!(price.mid_price, Some(1.0));
+        assert_eq!(price.spread_bps.map(f64::round), Some(200.0));
+        assert_eq!(price.source_count, 2);
+        // the latest of raydium (0.99) and orca (1.01)
+        assert_eq!(price.median_price, Some(1.0));
+    }
+
+    #[test]
+    fn test_single_source() {
+        let price =
+            aggregate_quotes(&[quote("raydium", QuoteSide::Ask, 1.5, 0)], NOW);
+        assert_eq!(price.best_bid, None);
+        assert_eq!(price.best_ask, Some(1.5));
+        assert_eq!(price.mid_price, Some(1.5));
+        assert_eq!(price.spread_bps, None);
+        assert_eq!(price.source_count, 1);
+
+        assert_eq!(aggregate_quotes(&[], NOW), AggregatedPrice::default());
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(vec![3.0, 1.0, 2.0]), Some(2.0));
+        assert_eq!(median(vec![4.0, 1.0, 100.0, 2.0]), Some(3.0));
+        assert_eq!(median(vec![]), None);
+    }
+}
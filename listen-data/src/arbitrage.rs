@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use arbitrage_costs::{spread, ArbitrageCosts, DEFAULT_TRADE_SIZE_USD};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::message_queue::{MessageQueue, RedisMessageQueue};
+use crate::sol_price_stream::get_sol_price;
+
+pub const ARBITRAGE_OPPORTUNITIES_CHANNEL: &str = "arbitrage_opportunities";
+
+const SCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// a signature and the 300k compute units of a swap at 0.1 lamports each
+const SOLANA_SWAP_FEE_LAMPORTS: u64 = 5_000 + 30_000;
+
+const JUPITER_PRICE_URL: &str = "https://api.jup.ag/price/v2";
+const LIFI_TOKEN_URL: &str = "https://li.quest/v1/token";
+/// how li.fi names the gas token of a chain
+const EVM_NATIVE_TOKEN_ADDRESS: &str =
+    "0x0000000000000000000000000000000000000000";
+
+/// the evm rpc of each chain the scanner prices gas on, from
+/// `ETHEREUM_RPC_URL` and `ARBITRUM_RPC_URL`
+pub fn evm_rpc_urls_from_env() -> HashMap<u64, String> {
+    [(1, "ETHEREUM_RPC_URL"), (42161, "ARBITRUM_RPC_URL")]
+        .into_iter()
+        .filter_map(|(chain_id, var)| match std::env::var(var) {
+            Ok(url) if !url.is_empty() => Some((chain_id, url)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// a token on solana and its wormhole-bridged counterpart on an evm chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BridgedPair {
+    pub symbol: String,
+    pub solana_mint: String,
+    pub evm_chain_id: u64,
+    pub evm_address: String,
+}
+
+impl BridgedPair {
+    fn new(
+        symbol: &str,
+        solana_mint: &str,
+        evm_chain_id: u64,
+        evm_address: &str,
+    ) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            solana_mint: solana_mint.to_string(),
+            evm_chain_id,
+            evm_address: evm_address.to_string(),
+        }
+    }
+}
+
+pub fn default_bridged_pairs() -> Vec<BridgedPair> {
+    vec![
+        BridgedPair::new(
+            "WETH",
+            "7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs",
+            1,
+            "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+        ),
+        BridgedPair::new(
+            "W",
+            "85VBFQZC9TZkfaptBWjvUw7YbZjy52A6mjtPGjstQAmQ",
+            42161,
+            "0xB0fFa8000886e57F86dd5264b9582b2Ad87b2b91",
+        ),
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArbitrageOpportunity {
+    pub pair: BridgedPair,
+    pub solana_price_usd: f64,
+    pub evm_price_usd: f64,
+    /// positive when the token is dearer on the evm chain
+    pub price_diff_pct: f64,
+    /// of buying a trade on the cheaper chain and selling it on the other,
+    /// after costs
+    pub estimated_profit_usd: f64,
+    pub timestamp: u64,
+}
+
+/// `None` unless the price gap pays for the `costs`
+pub fn find_opportunity(
+    pair: &BridgedPair,
+    solana_price_usd: f64,
+    evm_price_usd: f64,
+    trade_size_usd: f64,
+    costs: &ArbitrageCosts,
+    timestamp: u64,
+) -> Option<ArbitrageOpportunity> {
+    let spread =
+        spread(solana_price_usd, evm_price_usd, costs, trade_size_usd)?;
+    if spread.estimated_profit_usd <= 0. {
+        return None;
+    }
+    Some(ArbitrageOpportunity {
+        pair: pair.clone(),
+        solana_price_usd,
+        evm_price_usd,
+        price_diff_pct: spread.price_diff_pct,
+        estimated_profit_usd: spread.estimated_profit_usd,
+        timestamp,
+    })
+}
+
+fn parse_price(value: &serde_json::Value) -> Result<f64> {
+    value
+        .as_str()
+        .ok_or_else(|| anyhow!("missing price"))?
+        .parse()
+        .context("invalid price")
+}
+
+/// Compares the prices of bridged tokens on solana and the evm chains
+/// every minute and publishes the gaps wide enough to pay for bridging and
+/// gas at the current gas prices
+pub struct ArbitrageScanner {
+    client: reqwest::Client,
+    message_queue: Arc<RedisMessageQueue>,
+    pairs: Vec<BridgedPair>,
+    evm_rpc_urls: HashMap<u64, String>,
+    trade_size_usd: f64,
+}
+
+impl ArbitrageScanner {
+    /// pairs on chains without an rpc in `evm_rpc_urls` are not scanned
+    pub fn new(
+        message_queue: Arc<RedisMessageQueue>,
+        evm_rpc_urls: HashMap<u64, String>,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            message_queue,
+            pairs: default_bridged_pairs(),
+            evm_rpc_urls,
+            trade_size_usd: DEFAULT_TRADE_SIZE_USD,
+        }
+    }
+
+    pub fn with_pairs(mut self, pairs: Vec<BridgedPair>) -> Self {
+        self.pairs = pairs;
+        self
+    }
+
+    async fn evm_gas_price(&self, rpc_url: &str) -> Result<u128> {
+        let res = self
+            .client
+            .post(rpc_url)
+            .json(&serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "eth_gasPrice",
+                "params": [],
+            }))
+            .send()
+            .await?
+            .json::<serde_json::Value>()
+            .await?;
+        let gas_price = res["result"]
+            .as_str()
+            .ok_or_else(|| anyhow!("missing gas price"))?;
+        u128::from_str_radix(gas_price.trim_start_matches("0x"), 16)
+            .context("invalid gas price")
+    }
+
+    async fn costs(&self, chain_id: u64) -> Result<ArbitrageCosts> {
+        let rpc_url = self
+            .evm_rpc_urls
+            .get(&chain_id)
+            .ok_or_else(|| anyhow!("no rpc for chain {}", chain_id))?;
+        let (gas_price, native_price) = tokio::try_join!(
+            self.evm_gas_price(rpc_url),
+            self.evm_price(chain_id, EVM_NATIVE_TOKEN_ADDRESS),
+        )?;
+        Ok(ArbitrageCosts::new(
+            gas_price,
+            native_price,
+            SOLANA_SWAP_FEE_LAMPORTS,
+            get_sol_price().await,
+        ))
+    }
+
+    async fn solana_price(&self, mint: &str) -> Result<f64> {
+        let res = self
+            .client
+            .get(JUPITER_PRICE_URL)
+            .query(&[("ids", mint)])
+            .send()
+            .await?
+            .json::<serde_json::Value>()
+            .await?;
+        parse_price(&res["data"][mint]["price"])
+    }
+
+    async fn evm_price(&self, chain_id: u64, address: &str) -> Result<f64> {
+        let res = self
+            .client
+            .get(LIFI_TOKEN_URL)
+            .query(&[("chain", chain_id.to_string().as_str())])
+            .query(&[("token", address)])
+            .send()
+            .await?
+            .json::<serde_json::Value>()
+            .await?;
+        parse_price(&res["priceUSD"])
+    }
+
+    pub async fn scan(&self) -> Vec<ArbitrageOpportunity> {
+        let timestamp = chrono::Utc::now().timestamp() as u64;
+        let mut opportunities = Vec::new();
+        for pair in &self.pairs {
+            if !self.evm_rpc_urls.contains_key(&pair.evm_chain_id) {
+                continue;
+            }
+            let prices = tokio::try_join!(
+                self.solana_price(&pair.solana_mint),
+                self.evm_price(pair.evm_chain_id, &pair.evm_address),
+                self.costs(pair.evm_chain_id),
+            );
+            let (solana_price, evm_price, costs) = match prices {
+                Ok(prices) => prices,
+                Err(e) => {
+                    error!("failed to price {}: {}", pair.symbol, e);
+                    continue;
+                }
+            };
+            opportunities.extend(find_opportunity(
+                pair,
+                solana_price,
+                evm_price,
+                self.trade_size_usd,
+                &costs,
+                timestamp,
+            ));
+        }
+        opportunities
+    }
+
+    pub async fn run(self) {
+        for pair in &self.pairs {
+            if !self.evm_rpc_urls.contains_key(&pair.evm_chain_id) {
+                warn!(
+                    "no rpc for chain {}, not scanning {}",
+                    pair.evm_chain_id, pair.symbol
+                );
+            }
+        }
+        info!("scanning {} bridged pairs for arbitrage", self.pairs.len());
+        loop {
+            for opportunity in self.scan().await {
+                info!(
+                    "{} is {:.2}% apart across chains",
+                    opportunity.pair.symbol, opportunity.price_diff_pct
+                );
+                if let Err(e) = self
+                    .message_queue
+                    .publish_arbitrage_opportunity(opportunity)
+                    .await
+                {
+                    error!("failed to publish arbitrage opportunity: {}", e);
+                }
+            }
+            tokio::time::sleep(SCAN_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COSTS: ArbitrageCosts = ArbitrageCosts {
+        bridge_usd: 6.,
+        gas_usd: 4.,
+    };
+
+    #[test]
+    fn test_find_opportunity() {
+        let pair = &default_bridged_pairs()[0];
+        // 2% dearer on the evm chain, $20 on $1000 less $10 of costs
+        let opportunity =
+            find_opportunity(pair, 2_000., 2_040., 1_000., &COSTS, 42).unwrap();
+        assert!((opportunity.price_diff_pct - 2.).abs() < 1e-9);
+        assert!((opportunity.estimated_profit_usd - 10.).abs() < 1e-9);
+        assert_eq!(opportunity.timestamp, 42);
+
+        // cheaper on the evm chain works the other way around
+        let opportunity =
+            find_opportunity(pair, 2_040., 2_000., 1_000., &COSTS, 42).unwrap();
+        assert!(opportunity.price_diff_pct < 0.);
+
+        // a 0.5% gap doesn't pay for the bridge
+        assert_eq!(
+            find_opportunity(pair, 2_000., 2_010., 1_000., &COSTS, 42),
+            None
+        );
+        assert_eq!(
+            find_opportunity(pair, 0., 2_010., 1_000., &COSTS, 42),
+            None
+        );
+    }
+}
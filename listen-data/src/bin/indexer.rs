@@ -1,11 +1,21 @@
 use anyhow::Result;
 use clap::Parser;
 use listen_data::{
-    geyser::make_raydium_geyser_instruction_pipeline,
-    sol_price_stream::SolPriceCache,
+    admin::run_admin_server,
+    arbitrage::{evm_rpc_urls_from_env, ArbitrageScanner},
+    geyser::make_geyser_instruction_pipeline,
+    metrics::setup_metrics_exporter,
+    new_pool::NewPoolDetector,
+    pool_monitor::PoolMonitor,
+    referral_fees::ReferralRevenueTracker,
+    slot_lag::SlotLagMonitor,
+    smart_money::SmartMoneyTracker,
+    sol_price_stream::{configure_sol_price_fallbacks, SolPriceCache},
     util::{make_db, make_kv_store, make_message_queue},
 };
-use std::sync::Arc;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::{str::FromStr, sync::Arc};
 use tracing::{error, info};
 
 #[derive(Parser)]
@@ -25,11 +35,98 @@ async fn main() -> Result<()> {
     let price_cache =
         SolPriceCache::new(Some(kv_store.clone()), Some(message_queue.clone()));
     let price_cache = Arc::new(price_cache);
+    configure_sol_price_fallbacks(
+        std::env::var("RPC_URL")
+            .ok()
+            .map(|rpc_url| Arc::new(RpcClient::new(rpc_url))),
+        Some(kv_store.clone()),
+    )
+    .await;
 
     info!("Solana price: {}", price_cache.get_price().await);
 
+    setup_metrics_exporter()?;
+
+    // admin endpoints are only usable when a token is configured
+    let token = std::env::var("ADMIN_TOKEN").ok();
+    let port = std::env::var("ADMIN_PORT")
+        .ok()
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(6970);
+    let (server_db, server_kv_store) = (db.clone(), kv_store.clone());
+    tokio::spawn(async move {
+        if let Err(e) =
+            run_admin_server(server_db, Some(server_kv_store), token, port)
+                .await
+        {
+            error!("Error in admin server: {}", e);
+        }
+    });
+
+    tokio::spawn(SmartMoneyTracker::new(db.clone(), kv_store.clone()).run());
+
+    // gas is priced on the evm chains, pairs on chains without an rpc are
+    // skipped
+    let evm_rpc_urls = evm_rpc_urls_from_env();
+    if evm_rpc_urls.is_empty() {
+        info!(
+            "ETHEREUM_RPC_URL and ARBITRUM_RPC_URL not set, arbitrage \
+            scanner is disabled"
+        );
+    } else {
+        let scanner =
+            ArbitrageScanner::new(message_queue.clone(), evm_rpc_urls);
+        tokio::spawn(scanner.run());
+    }
+
+    // vault and log subscriptions need both an RPC and a websocket endpoint
+    match (std::env::var("RPC_URL"), std::env::var("WS_URL")) {
+        (Ok(rpc_url), Ok(ws_url)) => {
+            let monitor = PoolMonitor::new(
+                db.clone(),
+                Arc::new(RpcClient::new(rpc_url.clone())),
+                ws_url.clone(),
+                message_queue.clone(),
+            );
+            tokio::spawn(monitor.run());
+            let detector = NewPoolDetector::new(
+                Arc::new(RpcClient::new(rpc_url)),
+                ws_url.clone(),
+                message_queue.clone(),
+            );
+            tokio::spawn(detector.run());
+            let slot_lag = SlotLagMonitor::new(ws_url, message_queue.clone());
+            tokio::spawn(slot_lag.run());
+        }
+        _ => info!(
+            "RPC_URL or WS_URL not set, pool TVL monitor, new pool \
+            detector and slot lag monitor are disabled"
+        ),
+    }
+
+    match (
+        std::env::var("RPC_URL"),
+        std::env::var("JUPITER_REFERRAL_ACCOUNT"),
+    ) {
+        (Ok(rpc_url), Ok(referral_account)) => {
+            let referral_account = Pubkey::from_str(&referral_account)?;
+            let tracker = ReferralRevenueTracker::new(
+                Arc::new(RpcClient::new(rpc_url)),
+                referral_account,
+                db.clone(),
+                message_queue.clone(),
+                Some(kv_store.clone()),
+            );
+            tokio::spawn(tracker.run());
+        }
+        _ => info!(
+            "RPC_URL or JUPITER_REFERRAL_ACCOUNT not set, referral fee \
+            tracking is disabled"
+        ),
+    }
+
     let mut pipeline =
-        make_raydium_geyser_instruction_pipeline(kv_store, message_queue, db)?;
+        make_geyser_instruction_pipeline(kv_store, message_queue, db)?;
 
     tokio::spawn(async move {
         if let Err(e) = price_cache.start_price_stream().await {
@@ -17,6 +17,9 @@ pub const WSOL_MINT_KEY_STR: &str =
 pub const USDC_MINT_KEY_STR: &str =
     "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
 
+pub const TOKEN_2022_PROGRAM_ID: Pubkey =
+    pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PeZgSNxgJj6BCd");
+
 pub const RAYDIUM_AUTHORITY_MINT_KEY: Pubkey =
     pubkey!("5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1");
 
@@ -37,3 +40,15 @@ pub const METEORA_DLMM_PROGRAM_ID: Pubkey =
 
 pub const METEORA_DLMM_PROGRAM_ID_STR: &str =
     "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+
+pub const PHOENIX_PROGRAM_ID: Pubkey =
+    pubkey!("PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY");
+
+pub const PHOENIX_PROGRAM_ID_STR: &str =
+    "PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY";
+
+pub const OPENBOOK_V2_PROGRAM_ID: Pubkey =
+    pubkey!("opnb2LAfJYbRMAHHvqjCwQxanZn7ReEHp1k81EohpZb");
+
+pub const OPENBOOK_V2_PROGRAM_ID_STR: &str =
+    "opnb2LAfJYbRMAHHvqjCwQxanZn7ReEHp1k81EohpZb";
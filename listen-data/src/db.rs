@@ -1,14 +1,20 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
+use crate::lifecycle::LifecycleMetrics;
 use crate::price::PriceUpdate;
+use crate::price_history::{Candle, PricePoint, PriceStats};
+use crate::referral_fees::FeeAccrued;
+use crate::smart_money::SmartMoneyCriteria;
+use crate::trader_type::TraderType;
 use anyhow::{Context, Result};
 use clickhouse::inserter::Inserter;
-use clickhouse::Client;
+use clickhouse::{Client, Row};
+use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 use tracing::{debug, info};
 
 #[async_trait::async_trait]
-pub trait Database {
+pub trait Database: Send + Sync + 'static {
     fn new(
         database_url: &str,
         password: &str,
@@ -22,6 +28,168 @@ pub trait Database {
     async fn health_check(&self) -> Result<()>;
 
     async fn insert_price(&self, price: &PriceUpdate) -> Result<()>;
+
+    /// price and volume trends of a mint for the lifecycle stage
+    async fn get_lifecycle_metrics(
+        &self,
+        mint: &str,
+    ) -> Result<LifecycleMetrics>;
+}
+
+pub const PRICE_UPDATES_TABLE: &str = "price_updates";
+
+/// fees accrued in the jupiter referral token accounts, one row per
+/// balance increase seen by the `ReferralRevenueTracker`
+pub const REFERRAL_FEES_TABLE: &str = "referral_fees";
+
+/// applied schema versions per table, read on startup so that migrations
+/// are only replayed for versions that haven't been recorded
+pub const SCHEMA_META_TABLE: &str = "_schema_meta";
+
+/// columns of `price_updates`, in the field order of `PriceUpdate`; new
+/// fields are appended here and get a migration in `MIGRATIONS`
+pub const PRICE_UPDATES_COLUMNS: &[(&str, &str)] = &[
+    ("name", "String"),
+    ("pubkey", "String"),
+    ("price", "Float64"),
+    ("market_cap", "Float64"),
+    ("timestamp", "UInt64"),
+    ("slot", "UInt64"),
+    ("swap_amount", "Float64"),
+    ("owner", "String"),
+    ("signature", "String"),
+    ("multi_hop", "Bool"),
+    ("is_buy", "Bool"),
+    ("is_pump", "Bool"),
+    ("trader_type", "LowCardinality(String) DEFAULT 'unknown'"),
+    (
+        "lifecycle_stage",
+        "LowCardinality(String) DEFAULT 'unknown'",
+    ),
+    ("schema_version", "UInt8 DEFAULT 1"),
+    ("smoothed_price", "Nullable(Float64)"),
+    ("finalized", "Bool DEFAULT false"),
+];
+
+/// a statement bringing `price_updates` to `version`; a version can take
+/// several statements, each has to be safe to replay (`IF NOT EXISTS`)
+#[derive(Debug)]
+pub struct Migration {
+    pub version: u8,
+    pub sql: &'static str,
+}
+
+/// applied in order on startup, versions must not decrease and the last
+/// one is `PRICE_UPDATE_SCHEMA_VERSION`
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        sql: r#"
+            CREATE TABLE IF NOT EXISTS price_updates (
+                name String,
+                pubkey String,
+                price Float64,
+                market_cap Float64,
+                timestamp UInt64,
+                slot UInt64,
+                swap_amount Float64,
+                owner String,
+                signature String,
+                multi_hop Bool,
+                is_buy Bool,
+                is_pump Bool,
+                INDEX idx_mints (name, pubkey) TYPE minmax GRANULARITY 1
+            )
+            ENGINE = MergeTree()
+            ORDER BY (name, pubkey, timestamp)
+        "#,
+    },
+    // tables created before versioning may be missing any of these
+    Migration {
+        version: 1,
+        sql: "ALTER TABLE price_updates ADD COLUMN IF NOT EXISTS \
+              trader_type LowCardinality(String) DEFAULT 'unknown'",
+    },
+    Migration {
+        version: 1,
+        sql: "ALTER TABLE price_updates ADD COLUMN IF NOT EXISTS \
+              lifecycle_stage LowCardinality(String) DEFAULT 'unknown'",
+    },
+    Migration {
+        version: 1,
+        sql: "ALTER TABLE price_updates ADD COLUMN IF NOT EXISTS \
+              schema_version UInt8 DEFAULT 1",
+    },
+    Migration {
+        version: 2,
+        sql: "ALTER TABLE price_updates ADD COLUMN IF NOT EXISTS \
+              smoothed_price Nullable(Float64)",
+    },
+    Migration {
+        version: 3,
+        sql: "ALTER TABLE price_updates ADD COLUMN IF NOT EXISTS \
+              finalized Bool DEFAULT false",
+    },
+];
+
+#[derive(Debug, Row, Deserialize)]
+struct SchemaVersionRow {
+    version: u8,
+}
+
+/// volume in periods where only bots traded is not counted as organic
+pub const ORGANIC_VOLUME_PERIOD_SECS: u64 = 60;
+
+#[derive(Debug, Row, Deserialize)]
+struct LifecycleRow {
+    swaps: u64,
+    first_seen: u64,
+    price: f64,
+    price_1h_ago: f64,
+    price_24h_ago: f64,
+    peak_24h: f64,
+    volume_1h: f64,
+    volume_prev_1h: f64,
+    volume_24h: f64,
+}
+
+#[derive(Debug, Row, Deserialize)]
+struct PriceStatsRow {
+    swaps: u64,
+    min_price: f64,
+    max_price: f64,
+    avg_price: f64,
+    buy_volume: f64,
+    sell_volume: f64,
+}
+
+/// A mint ranked by its usd volume over a window
+#[derive(Debug, Clone, PartialEq, Row, Serialize, Deserialize)]
+pub struct TrendingToken {
+    pub mint: String,
+    pub name: String,
+    pub volume_usd: f64,
+    pub swap_count: u64,
+    /// from the first to the last swap of the window, in percent
+    pub price_change_pct: f64,
+}
+
+/// referral fees earned in a token since tracking started
+#[derive(Debug, Clone, PartialEq, Row, Serialize, Deserialize)]
+pub struct TokenRevenue {
+    pub token: String,
+    /// in ui units of the token
+    pub total_amount: f64,
+    pub accruals: u64,
+    pub last_accrued: u64,
+}
+
+fn change(from: f64, to: f64) -> f64 {
+    if from > 0. {
+        to / from - 1.
+    } else {
+        0.
+    }
 }
 
 pub struct ClickhouseDb {
@@ -45,6 +213,451 @@ impl ClickhouseDb {
             .with_max_bytes(1_000_000) // price update is roughly ~200 bytes
             .with_period(Some(Duration::from_secs(15))))
     }
+
+    /// the highest version recorded in `_schema_meta`, 0 if none
+    pub async fn schema_version(&self) -> Result<u8> {
+        let row = self
+            .client
+            .query(&format!(
+                "SELECT max(version) AS version FROM {} WHERE table_name = ?",
+                SCHEMA_META_TABLE
+            ))
+            .bind(PRICE_UPDATES_TABLE)
+            .fetch_one::<SchemaVersionRow>()
+            .await
+            .context("Failed to read the schema version")?;
+        Ok(row.version)
+    }
+
+    /// applies the migrations newer than the recorded schema version and
+    /// records each version once all its statements succeeded; returns the
+    /// versions applied
+    pub async fn migrate(&self) -> Result<Vec<u8>> {
+        self.client
+            .query(&format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {} (
+                    table_name String,
+                    version UInt8,
+                    applied_at DateTime DEFAULT now()
+                )
+                ENGINE = MergeTree()
+                ORDER BY (table_name, version)
+                "#,
+                SCHEMA_META_TABLE
+            ))
+            .execute()
+            .await
+            .context("Failed to create the schema meta table")?;
+
+        let current = self.schema_version().await?;
+        let mut applied = Vec::new();
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            if migration.version <= current {
+                continue;
+            }
+            info!(
+                "applying {} migration to version {}",
+                PRICE_UPDATES_TABLE, migration.version
+            );
+            self.client
+                .query(migration.sql)
+                .execute()
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to apply migration to version {}",
+                        migration.version
+                    )
+                })?;
+
+            let version_done = MIGRATIONS
+                .get(i + 1)
+                .is_none_or(|next| next.version != migration.version);
+            if version_done {
+                self.client
+                    .query(&format!(
+                        "INSERT INTO {} (table_name, version) VALUES (?, ?)",
+                        SCHEMA_META_TABLE
+                    ))
+                    .bind(PRICE_UPDATES_TABLE)
+                    .bind(migration.version)
+                    .execute()
+                    .await
+                    .context("Failed to record the schema version")?;
+                applied.push(migration.version);
+            }
+        }
+
+        Ok(applied)
+    }
+
+    /// low volume, written directly instead of through the batch writer
+    pub async fn create_referral_fees_table(&self) -> Result<()> {
+        self.client
+            .query(&format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {} (
+                    token String,
+                    amount Float64,
+                    timestamp UInt64
+                )
+                ENGINE = MergeTree()
+                ORDER BY (token, timestamp)
+                "#,
+                REFERRAL_FEES_TABLE
+            ))
+            .execute()
+            .await
+            .context("Failed to create the referral fees table")?;
+        Ok(())
+    }
+
+    pub async fn insert_fee_accrued(&self, fee: &FeeAccrued) -> Result<()> {
+        let mut insert = self
+            .client
+            .insert::<FeeAccrued>(REFERRAL_FEES_TABLE)
+            .context("failed to prepare referral fee insert")?;
+        insert
+            .write(fee)
+            .await
+            .context("Failed to write referral fee")?;
+        insert
+            .end()
+            .await
+            .context("Failed to insert referral fee")?;
+        Ok(())
+    }
+
+    /// total referral fees per token, largest first
+    pub async fn get_referral_revenue(&self) -> Result<Vec<TokenRevenue>> {
+        self.client
+            .query(&format!(
+                r#"
+                SELECT
+                    token,
+                    sum(amount) AS total_amount,
+                    count() AS accruals,
+                    max(timestamp) AS last_accrued
+                FROM {}
+                GROUP BY token
+                ORDER BY total_amount DESC
+                "#,
+                REFERRAL_FEES_TABLE
+            ))
+            .fetch_all::<TokenRevenue>()
+            .await
+            .context("Failed to query referral revenue")
+    }
+
+    /// owners of the swaps with a high enough share of profitable closed
+    /// positions, a position being all swaps of a wallet in a mint with the
+    /// average sell price compared against the average buy price
+    pub async fn get_smart_money_wallets(
+        &self,
+        criteria: &SmartMoneyCriteria,
+    ) -> Result<Vec<String>> {
+        let since =
+            chrono::Utc::now().timestamp() as u64 - criteria.lookback.as_secs();
+        self.client
+            .query(
+                r#"
+                SELECT owner
+                FROM (
+                    SELECT
+                        owner,
+                        pubkey,
+                        sumIf(swap_amount, is_buy)
+                            / sumIf(swap_amount / price, is_buy) AS avg_buy,
+                        sumIf(swap_amount, NOT is_buy)
+                            / sumIf(swap_amount / price, NOT is_buy) AS avg_sell
+                    FROM price_updates
+                    WHERE timestamp >= ? AND price > 0 AND NOT multi_hop
+                    GROUP BY owner, pubkey
+                    HAVING countIf(is_buy) > 0 AND countIf(NOT is_buy) > 0
+                )
+                GROUP BY owner
+                HAVING count() >= ?
+                    AND countIf(avg_sell > avg_buy * (1 + ?)) / count() > ?
+                "#,
+            )
+            .bind(since)
+            .bind(criteria.min_trades)
+            .bind(criteria.min_gain)
+            .bind(criteria.min_win_rate)
+            .fetch_all::<String>()
+            .await
+            .context("Failed to query smart money wallets")
+    }
+
+    /// usd volume of a mint since `since` (unix seconds), by trader type
+    pub async fn get_volume_by_trader_type(
+        &self,
+        mint: &str,
+        since: u64,
+    ) -> Result<HashMap<TraderType, f64>> {
+        let rows = self
+            .client
+            .query(
+                "SELECT trader_type, sum(swap_amount) FROM price_updates \
+                 WHERE pubkey = ? AND timestamp >= ? GROUP BY trader_type",
+            )
+            .bind(mint)
+            .bind(since)
+            .fetch_all::<(String, f64)>()
+            .await
+            .context("Failed to query volume by trader type")?;
+
+        let mut volumes = HashMap::new();
+        for (trader_type, volume) in rows {
+            *volumes.entry(TraderType::from(trader_type)).or_default() +=
+                volume;
+        }
+        Ok(volumes)
+    }
+
+    /// usd volume of a mint since `since` (unix seconds), leaving out the
+    /// periods of `ORGANIC_VOLUME_PERIOD_SECS` in which only bots traded
+    pub async fn get_organic_volume(
+        &self,
+        mint: &str,
+        since: u64,
+    ) -> Result<f64> {
+        self.client
+            .query(
+                r#"
+                SELECT sum(volume)
+                FROM (
+                    SELECT
+                        intDiv(timestamp, ?) AS period,
+                        sum(swap_amount) AS volume,
+                        countIf(trader_type != 'bot') AS non_bot_trades
+                    FROM price_updates
+                    WHERE pubkey = ? AND timestamp >= ?
+                    GROUP BY period
+                )
+                WHERE non_bot_trades > 0
+                "#,
+            )
+            .bind(ORGANIC_VOLUME_PERIOD_SECS)
+            .bind(mint)
+            .bind(since)
+            .fetch_one::<f64>()
+            .await
+            .context("Failed to query organic volume")
+    }
+
+    /// the `limit` mints with the most usd volume since `since` (unix
+    /// seconds), highest first
+    pub async fn get_top_mints_by_volume(
+        &self,
+        since: u64,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.client
+            .query(
+                r#"
+                SELECT pubkey
+                FROM price_updates
+                WHERE timestamp >= ? AND NOT multi_hop
+                GROUP BY pubkey
+                ORDER BY sum(swap_amount) DESC
+                LIMIT ?
+                "#,
+            )
+            .bind(since)
+            .bind(limit as u64)
+            .fetch_all::<String>()
+            .await
+            .context("Failed to query top mints by volume")
+    }
+
+    /// the `limit` mints with the most usd volume in the last
+    /// `window_secs`, highest first
+    pub async fn get_trending(
+        &self,
+        window_secs: u64,
+        limit: u32,
+    ) -> Result<Vec<TrendingToken>> {
+        let since =
+            (chrono::Utc::now().timestamp() as u64).saturating_sub(window_secs);
+        self.client
+            .query(
+                r#"
+                SELECT
+                    pubkey AS mint,
+                    argMax(name, timestamp) AS name,
+                    sum(swap_amount) AS volume_usd,
+                    count() AS swap_count,
+                    if(
+                        argMin(price, timestamp) > 0,
+                        (argMax(price, timestamp) / argMin(price, timestamp)
+                            - 1) * 100,
+                        0
+                    ) AS price_change_pct
+                FROM price_updates
+                WHERE timestamp >= ? AND NOT multi_hop
+                GROUP BY pubkey
+                ORDER BY volume_usd DESC
+                LIMIT ?
+                "#,
+            )
+            .bind(since)
+            .bind(limit)
+            .fetch_all::<TrendingToken>()
+            .await
+            .context("Failed to query trending tokens")
+    }
+
+    /// the latest `limit` swaps of a mint between `from` and `to` (unix
+    /// seconds, inclusive), oldest first
+    pub async fn get_price_points(
+        &self,
+        mint: &str,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<PricePoint>> {
+        let mut points = self
+            .client
+            .query(
+                r#"
+                SELECT timestamp, price, market_cap, swap_amount, is_buy
+                FROM price_updates
+                WHERE pubkey = ? AND timestamp >= ? AND timestamp <= ?
+                    AND NOT multi_hop
+                ORDER BY timestamp DESC, slot DESC
+                LIMIT ?
+                "#,
+            )
+            .bind(mint)
+            .bind(from)
+            .bind(to)
+            .bind(limit as u64)
+            .fetch_all::<PricePoint>()
+            .await
+            .context("Failed to query price points")?;
+        points.reverse();
+        Ok(points)
+    }
+
+    /// the latest `limit` OHLCV candles of `interval_secs` of a mint
+    /// between `from` and `to`, oldest first
+    pub async fn get_candles(
+        &self,
+        mint: &str,
+        from: u64,
+        to: u64,
+        interval_secs: u64,
+        limit: usize,
+    ) -> Result<Vec<Candle>> {
+        let mut candles = self
+            .client
+            .query(
+                r#"
+                SELECT
+                    intDiv(timestamp, ?) * ? AS bucket,
+                    argMin(price, (timestamp, slot)) AS open,
+                    max(price) AS high,
+                    min(price) AS low,
+                    argMax(price, (timestamp, slot)) AS close,
+                    sum(swap_amount) AS volume,
+                    argMax(market_cap, (timestamp, slot)) AS market_cap
+                FROM price_updates
+                WHERE pubkey = ? AND timestamp >= ? AND timestamp <= ?
+                    AND NOT multi_hop
+                GROUP BY bucket
+                ORDER BY bucket DESC
+                LIMIT ?
+                "#,
+            )
+            .bind(interval_secs)
+            .bind(interval_secs)
+            .bind(mint)
+            .bind(from)
+            .bind(to)
+            .bind(limit as u64)
+            .fetch_all::<Candle>()
+            .await
+            .context("Failed to query candles")?;
+        candles.reverse();
+        Ok(candles)
+    }
+
+    /// price and volume statistics of a mint between `from` and `to`
+    pub async fn get_price_stats(
+        &self,
+        mint: &str,
+        from: u64,
+        to: u64,
+    ) -> Result<PriceStats> {
+        let row = self
+            .client
+            .query(
+                r#"
+                SELECT
+                    count() AS swaps,
+                    min(price) AS min_price,
+                    max(price) AS max_price,
+                    avg(price) AS avg_price,
+                    sumIf(swap_amount, is_buy) AS buy_volume,
+                    sumIf(swap_amount, NOT is_buy) AS sell_volume
+                FROM price_updates
+                WHERE pubkey = ? AND timestamp >= ? AND timestamp <= ?
+                    AND NOT multi_hop
+                "#,
+            )
+            .bind(mint)
+            .bind(from)
+            .bind(to)
+            .fetch_one::<PriceStatsRow>()
+            .await
+            .context("Failed to query price stats")?;
+
+        // avg is nan over no rows
+        if row.swaps == 0 {
+            return Ok(PriceStats::default());
+        }
+        Ok(PriceStats {
+            min_price: row.min_price,
+            max_price: row.max_price,
+            avg_price: row.avg_price,
+            total_volume_usd: row.buy_volume + row.sell_volume,
+            buy_sell_ratio: if row.sell_volume > 0. {
+                Some(row.buy_volume / row.sell_volume)
+            } else {
+                None
+            },
+        })
+    }
+
+    /// forces the batch writer to write out everything it has buffered,
+    /// returns the number of rows written
+    pub async fn flush(&self) -> Result<u64> {
+        let mut inserter = self
+            .inserter
+            .as_ref()
+            .context("inserter not initialized")?
+            .write()
+            .await;
+
+        let stats = inserter
+            .force_commit()
+            .await
+            .context("Failed to flush insert buffer")?;
+        info!("Flushed {} rows ({} bytes)", stats.rows, stats.bytes);
+
+        Ok(stats.rows)
+    }
+
+    /// buffered (not yet committed) row counts, keyed by table
+    pub async fn buffered_rows(&self) -> HashMap<String, u64> {
+        let rows = match self.inserter.as_ref() {
+            Some(inserter) => inserter.read().await.pending().rows,
+            None => 0,
+        };
+        HashMap::from([("price_updates".to_string(), rows)])
+    }
 }
 
 #[async_trait::async_trait]
@@ -85,31 +698,8 @@ impl Database for ClickhouseDb {
 
     async fn initialize(&mut self) -> Result<()> {
         debug!("initializing clickhouse");
-        self.client
-            .query(
-                r#"
-                CREATE TABLE IF NOT EXISTS price_updates (
-                    name String,
-                    pubkey String,
-                    price Float64,
-                    market_cap Float64,
-                    timestamp UInt64,
-                    slot UInt64,
-                    swap_amount Float64,
-                    owner String,
-                    signature String,
-                    multi_hop Bool,
-                    is_buy Bool,
-                    is_pump Bool,
-                    INDEX idx_mints (name, pubkey) TYPE minmax GRANULARITY 1
-                ) 
-                ENGINE = MergeTree()
-                ORDER BY (name, pubkey, timestamp)
-                "#,
-            )
-            .execute()
-            .await
-            .context("Failed to create price_updates table")?;
+        self.migrate().await?;
+        self.create_referral_fees_table().await?;
 
         self.inserter = Some(Arc::new(RwLock::new(self.create_inserter()?)));
         self.is_initialized = true;
@@ -143,6 +733,67 @@ impl Database for ClickhouseDb {
 
         Ok(())
     }
+
+    async fn get_lifecycle_metrics(
+        &self,
+        mint: &str,
+    ) -> Result<LifecycleMetrics> {
+        let now = chrono::Utc::now().timestamp() as u64;
+        let hour_ago = now - 60 * 60;
+        let two_hours_ago = now - 2 * 60 * 60;
+        let day_ago = now - 24 * 60 * 60;
+        let row = self
+            .client
+            .query(
+                r#"
+                SELECT
+                    count() AS swaps,
+                    min(timestamp) AS first_seen,
+                    argMax(price, timestamp) AS price,
+                    argMinIf(price, timestamp, timestamp >= ?) AS price_1h_ago,
+                    argMinIf(price, timestamp, timestamp >= ?) AS price_24h_ago,
+                    maxIf(price, timestamp >= ?) AS peak_24h,
+                    sumIf(swap_amount, timestamp >= ?) AS volume_1h,
+                    sumIf(swap_amount, timestamp >= ? AND timestamp < ?)
+                        AS volume_prev_1h,
+                    sumIf(swap_amount, timestamp >= ?) AS volume_24h
+                FROM price_updates
+                WHERE pubkey = ? AND NOT multi_hop
+                "#,
+            )
+            .bind(hour_ago)
+            .bind(day_ago)
+            .bind(day_ago)
+            .bind(hour_ago)
+            .bind(two_hours_ago)
+            .bind(hour_ago)
+            .bind(day_ago)
+            .bind(mint)
+            .fetch_one::<LifecycleRow>()
+            .await
+            .context("Failed to query lifecycle metrics")?;
+
+        Ok(LifecycleMetrics {
+            // a mint without swaps yet has just launched
+            age_secs: if row.swaps > 0 {
+                now.saturating_sub(row.first_seen)
+            } else {
+                0
+            },
+            price_change_1h: change(row.price_1h_ago, row.price),
+            price_change_24h: change(row.price_24h_ago, row.price),
+            drawdown_from_peak: if row.peak_24h > 0. {
+                1. - row.price / row.peak_24h
+            } else {
+                0.
+            },
+            volume_1h: row.volume_1h,
+            volume_prev_1h: row.volume_prev_1h,
+            volume_24h: row.volume_24h,
+            holder_change_rate: None,
+            liquidity_change: None,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -156,4 +807,156 @@ mod tests {
         let db = make_db().await.unwrap();
         db.health_check().await.unwrap();
     }
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        assert!(MIGRATIONS
+            .windows(2)
+            .all(|pair| pair[0].version <= pair[1].version));
+        assert_eq!(
+            MIGRATIONS.last().unwrap().version,
+            crate::price::PRICE_UPDATE_SCHEMA_VERSION
+        );
+        // every column is created by some migration
+        for (name, _) in PRICE_UPDATES_COLUMNS {
+            assert!(
+                MIGRATIONS.iter().any(|m| m.sql.contains(name)),
+                "no migration creates {}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_columns_match_price_update() {
+        use clickhouse::Row;
+        let columns = PRICE_UPDATES_COLUMNS
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        assert_eq!(columns, PriceUpdate::COLUMN_NAMES);
+    }
+
+    #[tokio::test]
+    async fn test_volume_by_trader_type() {
+        let db = make_db().await.unwrap();
+        let mint = "So11111111111111111111111111111111111111112";
+        let volumes = db.get_volume_by_trader_type(mint, 0).await.unwrap();
+        let organic = db.get_organic_volume(mint, 0).await.unwrap();
+        assert!(organic <= volumes.values().sum::<f64>());
+    }
+
+    /// an empty database `name`, next to the one of `make_db`
+    async fn fresh_db(db: &ClickhouseDb, name: &str) -> ClickhouseDb {
+        db.client
+            .query(&format!("CREATE DATABASE IF NOT EXISTS {}", name))
+            .execute()
+            .await
+            .unwrap();
+        ClickhouseDb {
+            client: db.client.clone().with_database(name),
+            inserter: None,
+            is_initialized: false,
+            max_rows: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrate_is_idempotent() {
+        let db = make_db().await.unwrap();
+        let database = format!("test_migrate_{}", std::process::id());
+        let fresh = fresh_db(&db, &database).await;
+
+        // a fresh database gets the full table, the second run is a no-op
+        let applied = fresh.migrate().await.unwrap();
+        assert_eq!(
+            applied.last(),
+            Some(&crate::price::PRICE_UPDATE_SCHEMA_VERSION)
+        );
+        let applied = fresh.migrate().await.unwrap();
+        assert!(applied.is_empty());
+
+        let columns = fresh
+            .client
+            .query(
+                "SELECT name FROM system.columns \
+                 WHERE database = currentDatabase() AND table = ? \
+                 ORDER BY position",
+            )
+            .bind(PRICE_UPDATES_TABLE)
+            .fetch_all::<String>()
+            .await
+            .unwrap();
+        assert_eq!(
+            columns,
+            PRICE_UPDATES_COLUMNS
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .collect::<Vec<_>>()
+        );
+
+        db.client
+            .query(&format!("DROP DATABASE {}", database))
+            .execute()
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_trending() {
+        let db = make_db().await.unwrap();
+        let database = format!("test_trending_{}", std::process::id());
+        let fresh = fresh_db(&db, &database).await;
+        fresh.migrate().await.unwrap();
+
+        let now = chrono::Utc::now().timestamp() as u64;
+        // (mint, usd amount, seconds ago) of every swap
+        let swaps = [
+            ("small", 100.0, 60),
+            ("big", 5_000.0, 120),
+            ("big", 3_000.0, 30),
+            ("medium", 1_000.0, 90),
+            ("medium", 1_500.0, 10),
+            // the biggest, but outside the window
+            ("stale", 100_000.0, 7_200),
+        ];
+        let mut insert = fresh
+            .client
+            .insert::<PriceUpdate>(PRICE_UPDATES_TABLE)
+            .unwrap();
+        for (i, (mint, swap_amount, ago)) in swaps.into_iter().enumerate() {
+            insert
+                .write(&PriceUpdate {
+                    name: mint.to_uppercase(),
+                    pubkey: mint.to_string(),
+                    price: 1.0 + i as f64,
+                    timestamp: now - ago,
+                    swap_amount,
+                    ..crate::notifications::make_test_update(0.0, 0.0)
+                })
+                .await
+                .unwrap();
+        }
+        insert.end().await.unwrap();
+
+        let trending = fresh.get_trending(3_600, 10).await.unwrap();
+        assert_eq!(
+            trending
+                .iter()
+                .map(|token| (token.mint.as_str(), token.swap_count))
+                .collect::<Vec<_>>(),
+            vec![("big", 2), ("medium", 2), ("small", 1)]
+        );
+        assert_eq!(trending[0].name, "BIG");
+        assert_eq!(trending[0].volume_usd, 8_000.0);
+        // 2.0 at the first swap, 3.0 at the last
+        assert_eq!(trending[0].price_change_pct, 50.0);
+        assert_eq!(fresh.get_trending(3_600, 1).await.unwrap().len(), 1);
+
+        db.client
+            .query(&format!("DROP DATABASE {}", database))
+            .execute()
+            .await
+            .unwrap();
+    }
 }
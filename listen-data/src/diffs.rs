@@ -1,11 +1,20 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
 
 use anyhow::Result;
+use solana_sdk::pubkey::Pubkey;
 use solana_transaction_status::{
     TransactionTokenBalance, UiTransactionTokenBalance,
 };
 
-use crate::constants::{RAYDIUM_AUTHORITY_MINT_KEY_STR, WSOL_MINT_KEY_STR};
+use crate::constants::{
+    METEORA_DLMM_PROGRAM_ID, METEORA_DLMM_PROGRAM_ID_STR,
+    OPENBOOK_V2_PROGRAM_ID, OPENBOOK_V2_PROGRAM_ID_STR, PHOENIX_PROGRAM_ID,
+    PHOENIX_PROGRAM_ID_STR, RAYDIUM_AMM_V4_PROGRAM_ID,
+    RAYDIUM_AUTHORITY_MINT_KEY_STR, WSOL_MINT_KEY_STR,
+};
+
+pub use balance_diffs::{summarize_balance_changes, SwapSummary, TokenBalance};
 
 pub trait TokenBalanceInfo {
     fn get_mint(&self) -> &str;
@@ -41,10 +50,44 @@ impl TokenBalanceInfo for UiTransactionTokenBalance {
     }
 }
 
+/// The dex a swap went through, which decides whose balances are the pool's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dex {
+    RaydiumAmmV4,
+    MeteoraDlmm,
+    /// an order book, makers' funds sit in the market's vaults, so a fill
+    /// shows as the taker's net change and the opposite on the vaults, see
+    /// `crate::phoenix` for how its fills are priced
+    Phoenix,
+    /// an order book too, settled by a crank, see `crate::openbook` for
+    /// which of its transactions are trades
+    OpenBookV2,
+}
+
+/// the dex of the transaction, `None` for routes through more than one,
+/// their pool balances mix the legs of the route
+pub fn detect_dex(account_keys: &[Pubkey]) -> Option<Dex> {
+    let mut dexes = [
+        (RAYDIUM_AMM_V4_PROGRAM_ID, Dex::RaydiumAmmV4),
+        (METEORA_DLMM_PROGRAM_ID, Dex::MeteoraDlmm),
+        (PHOENIX_PROGRAM_ID, Dex::Phoenix),
+        (OPENBOOK_V2_PROGRAM_ID, Dex::OpenBookV2),
+    ]
+    .into_iter()
+    .filter(|(program_id, _)| account_keys.contains(program_id))
+    .map(|(_, dex)| dex);
+    match (dexes.next(), dexes.next()) {
+        (Some(dex), None) => Some(dex),
+        _ => None,
+    }
+}
+
 #[derive(Debug)]
 pub struct DiffsResult {
     pub price: f64,
     pub swap_amount: f64,
+    /// SOL side of the swap, in SOL
+    pub sol_amount: f64,
     pub coin_mint: String,
     pub is_buy: bool,
 }
@@ -89,6 +132,7 @@ pub fn process_diffs(
     Ok(DiffsResult {
         price,
         swap_amount,
+        sol_amount: sol_amount_abs,
         coin_mint: coin_mint.to_string(),
         is_buy,
     })
@@ -103,9 +147,93 @@ pub struct Diff {
     pub owner: String,
 }
 
+/// the swap the diffs describe, from the side of their owner (the pool)
+pub fn summarize_diffs(diffs: &[Diff]) -> SwapSummary {
+    let balance = |diff: &Diff, ui_amount| TokenBalance {
+        mint: diff.mint.clone(),
+        owner: diff.owner.clone(),
+        ui_amount,
+    };
+    let (pre, post): (Vec<_>, Vec<_>) = diffs
+        .iter()
+        .map(|diff| {
+            (
+                balance(diff, diff.pre_amount),
+                balance(diff, diff.post_amount),
+            )
+        })
+        .unzip();
+    summarize_balance_changes(&pre, &post)
+}
+
+/// the pool side diffs of a swap on `dex`, one per mint, ready for
+/// `process_diffs`
+pub fn get_dex_token_balance_diff<T: TokenBalanceInfo + std::fmt::Debug>(
+    dex: Dex,
+    pre_balances: &[T],
+    post_balances: &[T],
+) -> Vec<Diff> {
+    match dex {
+        Dex::RaydiumAmmV4 => {
+            get_token_balance_diff(pre_balances, post_balances)
+        }
+        // the liquidity of a DLMM pair is held by accounts of several
+        // program derived owners rather than one authority, the trader's
+        // accounts are owned by its wallet, which is on the curve
+        Dex::MeteoraDlmm => sum_by_mint(
+            collect_diffs(pre_balances, post_balances, is_program_owned),
+            METEORA_DLMM_PROGRAM_ID_STR,
+        ),
+        // the vaults are the maker side of every fill, the same as the
+        // pool of an AMM
+        Dex::Phoenix => sum_by_mint(
+            collect_diffs(pre_balances, post_balances, is_program_owned),
+            PHOENIX_PROGRAM_ID_STR,
+        ),
+        // the vaults belong to the market authority, a program address
+        Dex::OpenBookV2 => sum_by_mint(
+            collect_diffs(pre_balances, post_balances, is_program_owned),
+            OPENBOOK_V2_PROGRAM_ID_STR,
+        ),
+    }
+}
+
+fn is_program_owned(diff: &Diff) -> bool {
+    Pubkey::from_str(&diff.owner).is_ok_and(|owner| !owner.is_on_curve())
+}
+
+/// a single diff per mint, attributed to `owner`
+fn sum_by_mint(diffs: Vec<Diff>, owner: &str) -> Vec<Diff> {
+    let mut by_mint: BTreeMap<String, Diff> = BTreeMap::new();
+    for diff in diffs {
+        by_mint
+            .entry(diff.mint.clone())
+            .and_modify(|sum| {
+                sum.pre_amount += diff.pre_amount;
+                sum.post_amount += diff.post_amount;
+                sum.diff += diff.diff;
+            })
+            .or_insert(Diff {
+                owner: owner.to_string(),
+                ..diff
+            });
+    }
+    by_mint.into_values().collect()
+}
+
 pub fn get_token_balance_diff<T: TokenBalanceInfo + std::fmt::Debug>(
     pre_balances: &[T],
     post_balances: &[T],
+) -> Vec<Diff> {
+    collect_diffs(pre_balances, post_balances, |diff| {
+        diff.owner == RAYDIUM_AUTHORITY_MINT_KEY_STR
+    })
+}
+
+fn collect_diffs<T: TokenBalanceInfo + std::fmt::Debug>(
+    pre_balances: &[T],
+    post_balances: &[T],
+    should_collect: impl Fn(&Diff) -> bool,
 ) -> Vec<Diff> {
     let mut diffs = Vec::new();
     let mut pre_balances_map = HashMap::new();
@@ -131,9 +259,6 @@ pub fn get_token_balance_diff<T: TokenBalanceInfo + std::fmt::Debug>(
         }
     }
 
-    let should_collect =
-        |diff: &Diff| diff.owner == RAYDIUM_AUTHORITY_MINT_KEY_STR;
-
     for ((mint, owner), pre_amount) in pre_balances_map.iter() {
         if let Some(post_amount) =
             post_balances_map.get(&(mint.clone(), owner.clone()))
@@ -14,30 +14,57 @@ use yellowstone_grpc_proto::geyser::{
 };
 
 use crate::{
-    constants::RAYDIUM_AMM_V4_PROGRAM_ID, db::ClickhouseDb,
-    kv_store::RedisKVStore, message_queue::RedisMessageQueue,
+    constants::{
+        METEORA_DLMM_PROGRAM_ID, OPENBOOK_V2_PROGRAM_ID, PHOENIX_PROGRAM_ID,
+        RAYDIUM_AMM_V4_PROGRAM_ID,
+    },
+    db::ClickhouseDb,
+    instruction_decoders::{
+        MeteoraDlmmDecoder, OpenBookV2Decoder, PhoenixDecoder,
+        TokenMetadataDecoder,
+    },
+    kv_store::RedisKVStore,
+    message_queue::RedisMessageQueue,
+    metadata_update_processor::MetadataUpdateProcessor,
     raydium_intruction_processor::RaydiumAmmV4InstructionProcessor,
+    swap_instruction_processor::SwapInstructionProcessor,
     util::must_get_env,
 };
 
-pub fn make_raydium_geyser_instruction_pipeline(
+/// a filter per program, the stream carries the transactions matching any
+/// of them and each program's decoder picks its own instructions out
+pub fn transaction_filters(
+) -> HashMap<String, SubscribeRequestFilterTransactions> {
+    [
+        ("raydium_transaction_filter", RAYDIUM_AMM_V4_PROGRAM_ID),
+        ("meteora_dlmm_transaction_filter", METEORA_DLMM_PROGRAM_ID),
+        ("phoenix_transaction_filter", PHOENIX_PROGRAM_ID),
+        ("openbook_v2_transaction_filter", OPENBOOK_V2_PROGRAM_ID),
+        ("token_metadata_transaction_filter", mpl_token_metadata::ID),
+    ]
+    .into_iter()
+    .map(|(name, program_id)| {
+        (
+            name.to_string(),
+            SubscribeRequestFilterTransactions {
+                vote: Some(false),
+                failed: Some(false),
+                account_include: vec![],
+                account_exclude: vec![],
+                account_required: vec![program_id.to_string()],
+                signature: None,
+            },
+        )
+    })
+    .collect()
+}
+
+pub fn make_geyser_instruction_pipeline(
     kv_store: Arc<RedisKVStore>,
     message_queue: Arc<RedisMessageQueue>,
     db: Arc<ClickhouseDb>,
 ) -> Result<Pipeline> {
-    // Set up transaction filters to only process Raydium transactions
-    let mut transaction_filters = HashMap::new();
-    transaction_filters.insert(
-        "raydium_transaction_filter".to_string(),
-        SubscribeRequestFilterTransactions {
-            vote: Some(false),
-            failed: Some(false),
-            account_include: vec![],
-            account_exclude: vec![],
-            account_required: vec![RAYDIUM_AMM_V4_PROGRAM_ID.to_string()],
-            signature: None,
-        },
-    );
+    let transaction_filters = transaction_filters();
 
     // Create empty account filters since we only care about transactions
     let account_filters: HashMap<String, SubscribeRequestFilterAccounts> =
@@ -56,9 +83,82 @@ pub fn make_raydium_geyser_instruction_pipeline(
         .shutdown_strategy(ShutdownStrategy::Immediate)
         .instruction(
             RaydiumAmmV4Decoder,
-            RaydiumAmmV4InstructionProcessor::new(kv_store, message_queue, db),
+            RaydiumAmmV4InstructionProcessor::new(
+                kv_store.clone(),
+                message_queue.clone(),
+                db.clone(),
+                solana_sdk::commitment_config::CommitmentLevel::Processed,
+            ),
+        )
+        .instruction(
+            MeteoraDlmmDecoder,
+            SwapInstructionProcessor::new(
+                kv_store.clone(),
+                message_queue.clone(),
+                db.clone(),
+                solana_sdk::commitment_config::CommitmentLevel::Processed,
+            ),
+        )
+        .instruction(
+            PhoenixDecoder,
+            SwapInstructionProcessor::new(
+                kv_store.clone(),
+                message_queue.clone(),
+                db.clone(),
+                solana_sdk::commitment_config::CommitmentLevel::Processed,
+            ),
+        )
+        .instruction(
+            OpenBookV2Decoder,
+            SwapInstructionProcessor::new(
+                kv_store.clone(),
+                message_queue.clone(),
+                db,
+                solana_sdk::commitment_config::CommitmentLevel::Processed,
+            ),
+        )
+        .instruction(
+            TokenMetadataDecoder,
+            MetadataUpdateProcessor::new(kv_store, message_queue),
         )
         .build()?;
 
     Ok(pipeline)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_filters() {
+        let filters = transaction_filters();
+        let required = |name: &str| filters[name].account_required.clone();
+        assert_eq!(
+            required("raydium_transaction_filter"),
+            vec![RAYDIUM_AMM_V4_PROGRAM_ID.to_string()]
+        );
+        // a dlmm only swap doesn't have to touch raydium to be streamed
+        assert_eq!(
+            required("meteora_dlmm_transaction_filter"),
+            vec![METEORA_DLMM_PROGRAM_ID.to_string()]
+        );
+        assert_eq!(
+            required("phoenix_transaction_filter"),
+            vec![PHOENIX_PROGRAM_ID.to_string()]
+        );
+        assert_eq!(
+            required("openbook_v2_transaction_filter"),
+            vec![OPENBOOK_V2_PROGRAM_ID.to_string()]
+        );
+        // metadata updates are rarely sent alongside a swap
+        assert_eq!(
+            required("token_metadata_transaction_filter"),
+            vec![mpl_token_metadata::ID.to_string()]
+        );
+        assert!(filters
+            .values()
+            .all(|filter| filter.vote == Some(false)
+                && filter.failed == Some(false)));
+    }
+}
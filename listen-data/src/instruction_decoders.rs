@@ -0,0 +1,313 @@
+//! decoders for the programs carbon has no decoder for here, only the
+//! instructions the indexer acts on are decoded, the rest are skipped
+use carbon_core::instruction::{DecodedInstruction, InstructionDecoder};
+use once_cell::sync::Lazy;
+use solana_sdk::hash::hashv;
+use solana_sdk::instruction::Instruction;
+
+use crate::constants::{
+    METEORA_DLMM_PROGRAM_ID, OPENBOOK_V2_PROGRAM_ID, PHOENIX_PROGRAM_ID,
+};
+use crate::metadata_update::parse_update_metadata_v2;
+use crate::swap_instruction_processor::SwapInstruction;
+
+/// anchor instructions start with the first 8 bytes of
+/// sha256("global:<name>")
+fn anchor_discriminator(name: &str) -> [u8; 8] {
+    hashv(&[format!("global:{}", name).as_bytes()]).to_bytes()[..8]
+        .try_into()
+        .unwrap()
+}
+
+fn decoded<T>(instruction: &Instruction, data: T) -> DecodedInstruction<T> {
+    DecodedInstruction {
+        program_id: instruction.program_id,
+        data,
+        accounts: instruction.accounts.clone(),
+    }
+}
+
+/// the swaps of the dlmm program, the `2` ones take remaining accounts
+/// for token 2022 transfer hooks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeteoraDlmmInstruction {
+    Swap,
+    SwapExactOut,
+    SwapWithPriceImpact,
+}
+
+static METEORA_DLMM_SWAPS: Lazy<Vec<([u8; 8], MeteoraDlmmInstruction)>> =
+    Lazy::new(|| {
+        use MeteoraDlmmInstruction::*;
+        [
+            ("swap", Swap),
+            ("swap2", Swap),
+            ("swap_exact_out", SwapExactOut),
+            ("swap_exact_out2", SwapExactOut),
+            ("swap_with_price_impact", SwapWithPriceImpact),
+            ("swap_with_price_impact2", SwapWithPriceImpact),
+        ]
+        .into_iter()
+        .map(|(name, instruction)| (anchor_discriminator(name), instruction))
+        .collect()
+    });
+
+pub struct MeteoraDlmmDecoder;
+
+impl<'a> InstructionDecoder<'a> for MeteoraDlmmDecoder {
+    type InstructionType = MeteoraDlmmInstruction;
+
+    fn decode_instruction(
+        &self,
+        instruction: &'a Instruction,
+    ) -> Option<DecodedInstruction<Self::InstructionType>> {
+        if instruction.program_id != METEORA_DLMM_PROGRAM_ID {
+            return None;
+        }
+        let discriminator = instruction.data.get(..8)?;
+        METEORA_DLMM_SWAPS
+            .iter()
+            .find(|(swap, _)| swap.as_slice() == discriminator)
+            .map(|(_, swap)| decoded(instruction, *swap))
+    }
+}
+
+impl SwapInstruction for MeteoraDlmmInstruction {
+    fn is_swap(&self) -> bool {
+        true
+    }
+}
+
+/// the phoenix instructions that can take liquidity, limit orders cross
+/// the book when priced through it; the `Log` the program invokes for its
+/// events isn't one of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhoenixInstruction {
+    Swap,
+    SwapWithFreeFunds,
+    PlaceLimitOrder,
+    PlaceLimitOrderWithFreeFunds,
+}
+
+pub struct PhoenixDecoder;
+
+impl<'a> InstructionDecoder<'a> for PhoenixDecoder {
+    type InstructionType = PhoenixInstruction;
+
+    fn decode_instruction(
+        &self,
+        instruction: &'a Instruction,
+    ) -> Option<DecodedInstruction<Self::InstructionType>> {
+        if instruction.program_id != PHOENIX_PROGRAM_ID {
+            return None;
+        }
+        let data = match instruction.data.first()? {
+            0 => PhoenixInstruction::Swap,
+            1 => PhoenixInstruction::SwapWithFreeFunds,
+            2 => PhoenixInstruction::PlaceLimitOrder,
+            3 => PhoenixInstruction::PlaceLimitOrderWithFreeFunds,
+            _ => return None,
+        };
+        Some(decoded(instruction, data))
+    }
+}
+
+/// resting orders that don't cross fill nothing, the processor skips
+/// transactions without fills
+impl SwapInstruction for PhoenixInstruction {
+    fn is_swap(&self) -> bool {
+        true
+    }
+}
+
+/// the openbook instructions that can match against the book, the fills
+/// are taken from their logs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenBookV2Instruction {
+    PlaceOrder,
+    PlaceOrderPegged,
+    PlaceTakeOrder,
+}
+
+static OPENBOOK_V2_TAKES: Lazy<Vec<([u8; 8], OpenBookV2Instruction)>> =
+    Lazy::new(|| {
+        use OpenBookV2Instruction::*;
+        [
+            ("place_order", PlaceOrder),
+            ("place_order_pegged", PlaceOrderPegged),
+            ("place_take_order", PlaceTakeOrder),
+        ]
+        .into_iter()
+        .map(|(name, instruction)| (anchor_discriminator(name), instruction))
+        .collect()
+    });
+
+pub struct OpenBookV2Decoder;
+
+impl<'a> InstructionDecoder<'a> for OpenBookV2Decoder {
+    type InstructionType = OpenBookV2Instruction;
+
+    fn decode_instruction(
+        &self,
+        instruction: &'a Instruction,
+    ) -> Option<DecodedInstruction<Self::InstructionType>> {
+        if instruction.program_id != OPENBOOK_V2_PROGRAM_ID {
+            return None;
+        }
+        let discriminator = instruction.data.get(..8)?;
+        OPENBOOK_V2_TAKES
+            .iter()
+            .find(|(take, _)| take.as_slice() == discriminator)
+            .map(|(_, take)| decoded(instruction, *take))
+    }
+}
+
+/// orders that rest on the book log no fills, the processor skips them
+impl SwapInstruction for OpenBookV2Instruction {
+    fn is_swap(&self) -> bool {
+        true
+    }
+}
+
+/// a token metadata change that renames the token or points it elsewhere
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenMetadataInstruction {
+    UpdateMetadataAccountV2 { name: String, uri: String },
+}
+
+pub struct TokenMetadataDecoder;
+
+impl<'a> InstructionDecoder<'a> for TokenMetadataDecoder {
+    type InstructionType = TokenMetadataInstruction;
+
+    fn decode_instruction(
+        &self,
+        instruction: &'a Instruction,
+    ) -> Option<DecodedInstruction<Self::InstructionType>> {
+        if instruction.program_id != mpl_token_metadata::ID {
+            return None;
+        }
+        let (name, uri) = parse_update_metadata_v2(&instruction.data)?;
+        Some(decoded(
+            instruction,
+            TokenMetadataInstruction::UpdateMetadataAccountV2 { name, uri },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn instruction(program_id: Pubkey, data: Vec<u8>) -> Instruction {
+        Instruction::new_with_bytes(program_id, &data, vec![])
+    }
+
+    fn anchor_data(name: &str) -> Vec<u8> {
+        // amount in and min amount out
+        let mut data = anchor_discriminator(name).to_vec();
+        data.extend_from_slice(&[0; 16]);
+        data
+    }
+
+    #[test]
+    fn test_meteora_dlmm_decoder() {
+        let decode = |instruction: &Instruction| {
+            MeteoraDlmmDecoder
+                .decode_instruction(instruction)
+                .map(|decoded| decoded.data)
+        };
+        assert_eq!(
+            decode(&instruction(METEORA_DLMM_PROGRAM_ID, anchor_data("swap"))),
+            Some(MeteoraDlmmInstruction::Swap)
+        );
+        assert_eq!(
+            decode(&instruction(
+                METEORA_DLMM_PROGRAM_ID,
+                anchor_data("swap_exact_out2")
+            )),
+            Some(MeteoraDlmmInstruction::SwapExactOut)
+        );
+        // liquidity moves the pool's balances without a trade
+        assert_eq!(
+            decode(&instruction(
+                METEORA_DLMM_PROGRAM_ID,
+                anchor_data("add_liquidity")
+            )),
+            None
+        );
+        assert_eq!(
+            decode(&instruction(Pubkey::new_unique(), anchor_data("swap"))),
+            None
+        );
+        assert_eq!(decode(&instruction(METEORA_DLMM_PROGRAM_ID, vec![])), None);
+    }
+
+    #[test]
+    fn test_phoenix_decoder() {
+        let decode = |data: Vec<u8>| {
+            PhoenixDecoder
+                .decode_instruction(&instruction(PHOENIX_PROGRAM_ID, data))
+                .map(|decoded| decoded.data)
+        };
+        assert_eq!(decode(vec![0, 1, 2]), Some(PhoenixInstruction::Swap));
+        assert_eq!(
+            decode(vec![3]),
+            Some(PhoenixInstruction::PlaceLimitOrderWithFreeFunds)
+        );
+        // the log of the fills, cancels and deposits
+        assert_eq!(decode(vec![15]), None);
+        assert_eq!(decode(vec![6]), None);
+        assert_eq!(decode(vec![]), None);
+        assert!(PhoenixDecoder
+            .decode_instruction(&instruction(Pubkey::new_unique(), vec![0]))
+            .is_none());
+    }
+
+    #[test]
+    fn test_openbook_v2_decoder() {
+        let decode = |data: Vec<u8>| {
+            OpenBookV2Decoder
+                .decode_instruction(&instruction(OPENBOOK_V2_PROGRAM_ID, data))
+                .map(|decoded| decoded.data)
+        };
+        assert_eq!(
+            decode(anchor_data("place_take_order")),
+            Some(OpenBookV2Instruction::PlaceTakeOrder)
+        );
+        assert_eq!(
+            decode(anchor_data("place_order")),
+            Some(OpenBookV2Instruction::PlaceOrder)
+        );
+        // the crank settles makers, it isn't a trade of its own
+        assert_eq!(decode(anchor_data("consume_events")), None);
+        assert_eq!(decode(anchor_data("cancel_order")), None);
+        assert_eq!(decode(vec![1, 2, 3]), None);
+    }
+
+    #[test]
+    fn test_token_metadata_decoder() {
+        let decode = |data: Vec<u8>| {
+            TokenMetadataDecoder
+                .decode_instruction(&instruction(mpl_token_metadata::ID, data))
+                .map(|decoded| decoded.data)
+        };
+        // `UpdateMetadataAccountV2` with new data: name, symbol and uri
+        let mut data = vec![15, 1];
+        for field in ["Rugged", "RUG", "https://example.com/new.json"] {
+            data.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            data.extend_from_slice(field.as_bytes());
+        }
+        assert_eq!(
+            decode(data),
+            Some(TokenMetadataInstruction::UpdateMetadataAccountV2 {
+                name: "Rugged".to_string(),
+                uri: "https://example.com/new.json".to_string(),
+            })
+        );
+        // an authority only update and a `CreateMetadataAccountV3`
+        assert_eq!(decode(vec![15, 0, 0]), None);
+        assert_eq!(decode(vec![33, 1]), None);
+    }
+}
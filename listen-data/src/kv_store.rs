@@ -3,9 +3,44 @@ use bb8_redis::{bb8, redis::cmd, RedisConnectionManager};
 use serde::{de::DeserializeOwned, Serialize};
 use tracing::{debug, info};
 
+use crate::aggregated_price::{DexQuote, QUOTE_WINDOW_SECS};
+use crate::lifecycle::{LifecycleStage, LIFECYCLE_STAGE_TTL_SECS};
 use crate::metadata::TokenMetadata;
 use crate::price::PriceUpdate;
-use crate::util::create_redis_pool;
+use crate::util::{create_redis_pool, RedisPoolConfig};
+
+/// the cached state the swap processing reads and writes
+#[async_trait::async_trait]
+pub trait KVStore: Send + Sync + 'static {
+    async fn insert_price(&self, price: &PriceUpdate) -> Result<()>;
+
+    async fn get_price(&self, pubkey: &str) -> Result<Option<PriceUpdate>>;
+
+    async fn insert_metadata(&self, metadata: &TokenMetadata) -> Result<()>;
+
+    async fn get_metadata(&self, mint: &str) -> Result<Option<TokenMetadata>>;
+
+    async fn delete_metadata(&self, mint: &str) -> Result<()>;
+
+    async fn insert_dex_quote(
+        &self,
+        mint: &str,
+        quote: &DexQuote,
+    ) -> Result<()>;
+
+    async fn get_dex_quotes(&self, mint: &str) -> Result<Vec<DexQuote>>;
+
+    async fn insert_lifecycle_stage(
+        &self,
+        mint: &str,
+        stage: LifecycleStage,
+    ) -> Result<()>;
+
+    async fn get_lifecycle_stage(
+        &self,
+        mint: &str,
+    ) -> Result<Option<LifecycleStage>>;
+}
 
 #[derive(Debug, Clone)]
 pub struct RedisKVStore {
@@ -14,8 +49,18 @@ pub struct RedisKVStore {
 
 impl RedisKVStore {
     pub async fn new(redis_url: &str) -> Result<Self> {
-        let pool = create_redis_pool(redis_url).await?;
-        info!("Connected to Redis KV store at {}", redis_url);
+        Self::with_config(redis_url, &RedisPoolConfig::from_env()).await
+    }
+
+    pub async fn with_config(
+        redis_url: &str,
+        config: &RedisPoolConfig,
+    ) -> Result<Self> {
+        let pool = create_redis_pool(redis_url, config).await?;
+        info!(
+            "Connected to Redis KV store at {} (pool size {})",
+            redis_url, config.max_size
+        );
         Ok(Self { pool })
     }
 
@@ -67,6 +112,30 @@ impl RedisKVStore {
         Ok(())
     }
 
+    /// like `set`, expiring after `ttl_secs`
+    pub async fn set_ex<T: Serialize + Send + Sync>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl_secs: u64,
+    ) -> Result<()> {
+        let mut conn = self.pool.get().await.context(format!(
+            "Failed to get Redis connection: {:#?}",
+            self.pool.state().statistics
+        ))?;
+        let json_str = serde_json::to_string(value)?;
+        let _: () = cmd("SET")
+            .arg(key)
+            .arg(json_str)
+            .arg("EX")
+            .arg(ttl_secs)
+            .query_async(&mut *conn)
+            .await
+            .with_context(|| format!("Failed to set key: {}", key))?;
+        debug!(key, ttl_secs, "redis set ex ok");
+        Ok(())
+    }
+
     pub async fn exists(&self, key: &str) -> Result<bool> {
         let mut conn = self.pool.get().await.context(format!(
             "Failed to get Redis connection: {:#?}",
@@ -83,6 +152,43 @@ impl RedisKVStore {
         Ok(exists)
     }
 
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        let mut conn = self.pool.get().await.context(format!(
+            "Failed to get Redis connection: {:#?}",
+            self.pool.state().statistics
+        ))?;
+        let _: () = cmd("DEL")
+            .arg(key)
+            .query_async(&mut *conn)
+            .await
+            .with_context(|| format!("Failed to delete key: {}", key))?;
+        debug!(key, "redis del ok");
+        Ok(())
+    }
+
+    /// atomically replaces the members of the set at `key`
+    pub async fn replace_set(
+        &self,
+        key: &str,
+        members: &[String],
+    ) -> Result<()> {
+        let mut conn = self.pool.get().await.context(format!(
+            "Failed to get Redis connection: {:#?}",
+            self.pool.state().statistics
+        ))?;
+        let mut pipe = bb8_redis::redis::pipe();
+        pipe.atomic().cmd("DEL").arg(key).ignore();
+        if !members.is_empty() {
+            pipe.cmd("SADD").arg(key).arg(members).ignore();
+        }
+        let _: () = pipe
+            .query_async(&mut *conn)
+            .await
+            .with_context(|| format!("Failed to replace set: {}", key))?;
+        debug!(key, members = members.len(), "redis set replaced");
+        Ok(())
+    }
+
     fn make_price_key(&self, mint: &str) -> String {
         format!("solana:price:{}", mint)
     }
@@ -91,34 +197,161 @@ impl RedisKVStore {
         format!("solana:metadata:{}", mint)
     }
 
-    pub async fn insert_price(&self, price: &PriceUpdate) -> Result<()> {
+    fn make_dex_quotes_key(&self, mint: &str) -> String {
+        format!("solana:dex_quotes:{}", mint)
+    }
+
+    fn make_lifecycle_key(&self, mint: &str) -> String {
+        format!("solana:lifecycle:{}", mint)
+    }
+
+    pub async fn has_metadata(&self, mint: &str) -> Result<bool> {
+        let key = self.make_metadata_key(mint);
+        self.exists(&key).await
+    }
+}
+
+#[async_trait::async_trait]
+impl KVStore for RedisKVStore {
+    async fn insert_price(&self, price: &PriceUpdate) -> Result<()> {
         let key = self.make_price_key(&price.pubkey);
         self.set(&key, price).await
     }
 
-    pub async fn get_price(&self, pubkey: &str) -> Result<Option<PriceUpdate>> {
+    async fn get_price(&self, pubkey: &str) -> Result<Option<PriceUpdate>> {
         let key = self.make_price_key(pubkey);
         self.get(&key).await
     }
 
-    pub async fn insert_metadata(
-        &self,
-        metadata: &TokenMetadata,
-    ) -> Result<()> {
+    async fn insert_metadata(&self, metadata: &TokenMetadata) -> Result<()> {
         let key = self.make_metadata_key(&metadata.mint);
         self.set(&key, metadata).await
     }
 
-    pub async fn get_metadata(
-        &self,
-        mint: &str,
-    ) -> Result<Option<TokenMetadata>> {
+    async fn get_metadata(&self, mint: &str) -> Result<Option<TokenMetadata>> {
         let key = self.make_metadata_key(mint);
         self.get(&key).await
     }
 
-    pub async fn has_metadata(&self, mint: &str) -> Result<bool> {
+    async fn delete_metadata(&self, mint: &str) -> Result<()> {
         let key = self.make_metadata_key(mint);
-        self.exists(&key).await
+        self.delete(&key).await
+    }
+
+    /// one quote per dex and side, the hash expires once no dex has quoted
+    /// the mint for a while
+    async fn insert_dex_quote(
+        &self,
+        mint: &str,
+        quote: &DexQuote,
+    ) -> Result<()> {
+        let key = self.make_dex_quotes_key(mint);
+        let mut conn = self.pool.get().await.context(format!(
+            "Failed to get Redis connection: {:#?}",
+            self.pool.state().statistics
+        ))?;
+        let field = format!(
+            "{}:{}",
+            quote.dex,
+            serde_json::to_value(quote.side)?
+                .as_str()
+                .unwrap_or_default()
+        );
+        let _: () = bb8_redis::redis::pipe()
+            .cmd("HSET")
+            .arg(&key)
+            .arg(field)
+            .arg(serde_json::to_string(quote)?)
+            .ignore()
+            .cmd("EXPIRE")
+            .arg(&key)
+            .arg(QUOTE_WINDOW_SECS * 2)
+            .ignore()
+            .query_async(&mut *conn)
+            .await
+            .with_context(|| format!("Failed to insert dex quote: {}", key))?;
+        Ok(())
+    }
+
+    async fn get_dex_quotes(&self, mint: &str) -> Result<Vec<DexQuote>> {
+        let key = self.make_dex_quotes_key(mint);
+        let mut conn = self.pool.get().await.context(format!(
+            "Failed to get Redis connection: {:#?}",
+            self.pool.state().statistics
+        ))?;
+        let values: Vec<String> = cmd("HVALS")
+            .arg(&key)
+            .query_async(&mut *conn)
+            .await
+            .with_context(|| format!("Failed to get dex quotes: {}", key))?;
+        values
+            .iter()
+            .map(|value| {
+                serde_json::from_str(value).with_context(|| {
+                    format!("Failed to deserialize dex quote: {}", key)
+                })
+            })
+            .collect()
+    }
+
+    async fn insert_lifecycle_stage(
+        &self,
+        mint: &str,
+        stage: LifecycleStage,
+    ) -> Result<()> {
+        let key = self.make_lifecycle_key(mint);
+        self.set_ex(&key, &stage, LIFECYCLE_STAGE_TTL_SECS).await
+    }
+
+    async fn get_lifecycle_stage(
+        &self,
+        mint: &str,
+    ) -> Result<Option<LifecycleStage>> {
+        let key = self.make_lifecycle_key(mint);
+        self.get(&key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::is_local;
+
+    fn redis_url() -> String {
+        match is_local() {
+            true => "redis://localhost:6379".to_string(),
+            false => crate::util::must_get_env("REDIS_URL"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_inserts() {
+        // deliberately smaller than the number of concurrent inserts, so
+        // that tasks have to wait for connections to be returned
+        let config = RedisPoolConfig {
+            max_size: 10,
+            min_idle: Some(2),
+            ..RedisPoolConfig::default()
+        };
+        let kv_store = std::sync::Arc::new(
+            RedisKVStore::with_config(&redis_url(), &config)
+                .await
+                .unwrap(),
+        );
+
+        let handles = (0..500).map(|i| {
+            let kv_store = kv_store.clone();
+            tokio::spawn(async move {
+                kv_store.set(&format!("test:concurrent:{}", i), &i).await
+            })
+        });
+
+        for result in futures_util::future::join_all(handles).await {
+            result.unwrap().unwrap();
+        }
+
+        let value: Option<u64> =
+            kv_store.get("test:concurrent:499").await.unwrap();
+        assert_eq!(value, Some(499));
     }
 }
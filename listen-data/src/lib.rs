@@ -6,6 +6,9 @@ fn init() {
     let _ = tracing_subscriber::fmt::try_init();
 }
 
+pub mod admin;
+pub mod aggregated_price;
+pub mod arbitrage;
 pub mod constants;
 pub mod diffs;
 
@@ -16,16 +19,36 @@ pub mod rpc;
 pub mod geyser;
 
 pub mod db;
+pub mod instruction_decoders;
 pub mod kv_store;
+pub mod lifecycle;
 pub mod message_queue;
 pub mod metadata;
+pub mod metadata_update;
+pub mod metadata_update_processor;
 pub mod metrics;
+pub mod new_pool;
+pub mod notifications;
+pub mod openbook;
+pub mod phoenix;
+pub mod pool_monitor;
 pub mod price;
+pub mod price_history;
+pub mod price_smoothing;
 pub mod process_swap;
 pub mod raydium_intruction_processor;
 pub mod raydium_processor;
+pub mod referral_fees;
+pub mod slot_lag;
+pub mod smart_money;
+pub mod sol_price_fallback;
 pub mod sol_price_stream;
+pub mod swap_instruction_processor;
+pub mod trader_type;
 pub mod util;
 
 #[cfg(test)]
 pub mod debug;
+
+#[cfg(test)]
+pub mod testing;
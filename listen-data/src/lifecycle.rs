@@ -0,0 +1,248 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::db::Database;
+use crate::kv_store::KVStore;
+
+/// how long a computed stage is reused before it is recomputed
+pub const LIFECYCLE_STAGE_TTL_SECS: u64 = 60;
+
+const LAUNCHING_MAX_AGE_SECS: u64 = 30 * 60;
+const PUMPING_MIN_CHANGE_1H: f64 = 0.5;
+const PEAKING_MAX_CHANGE_1H: f64 = 0.05;
+const DECLINING_MIN_DRAWDOWN: f64 = 0.2;
+const DEAD_MAX_VOLUME_24H: f64 = 100.;
+/// holders or liquidity shrinking faster than this count as declining
+const DECLINING_MIN_OUTFLOW: f64 = 0.2;
+
+/// mints with a stage computation in flight, so that a burst of swaps in a
+/// fresh mint doesn't query clickhouse once per swap
+static PENDING: Lazy<Mutex<HashSet<String>>> =
+    Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// stored as a string in `price_updates.lifecycle_stage`; `Unknown` until
+/// the stage has been computed or if the token matches none of the stages
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[serde(from = "String", into = "String")]
+pub enum LifecycleStage {
+    /// less than 30 minutes old and rising
+    Launching,
+    /// price up more than 50% in the last hour
+    Pumping,
+    /// price flat and volume decreasing
+    Peaking,
+    /// price more than 20% below the 24h peak
+    Declining,
+    /// less than $100 volume over the last day
+    Dead,
+    #[default]
+    Unknown,
+}
+
+impl LifecycleStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LifecycleStage::Launching => "launching",
+            LifecycleStage::Pumping => "pumping",
+            LifecycleStage::Peaking => "peaking",
+            LifecycleStage::Declining => "declining",
+            LifecycleStage::Dead => "dead",
+            LifecycleStage::Unknown => "unknown",
+        }
+    }
+}
+
+impl From<String> for LifecycleStage {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "launching" => LifecycleStage::Launching,
+            "pumping" => LifecycleStage::Pumping,
+            "peaking" => LifecycleStage::Peaking,
+            "declining" => LifecycleStage::Declining,
+            "dead" => LifecycleStage::Dead,
+            _ => LifecycleStage::Unknown,
+        }
+    }
+}
+
+impl From<LifecycleStage> for String {
+    fn from(stage: LifecycleStage) -> Self {
+        stage.as_str().to_string()
+    }
+}
+
+/// price and volume history of a mint, changes are fractions (0.5 = +50%)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LifecycleMetrics {
+    pub age_secs: u64,
+    pub price_change_1h: f64,
+    pub price_change_24h: f64,
+    /// how far the current price is below the 24h high
+    pub drawdown_from_peak: f64,
+    pub volume_1h: f64,
+    /// volume of the hour before the last one
+    pub volume_prev_1h: f64,
+    pub volume_24h: f64,
+    /// change of the holder count over the last hour, not indexed yet
+    pub holder_change_rate: Option<f64>,
+    /// change of the pool liquidity over the last hour, not indexed yet
+    pub liquidity_change: Option<f64>,
+}
+
+fn is_outflow(change: Option<f64>) -> bool {
+    change.is_some_and(|change| change < -DECLINING_MIN_OUTFLOW)
+}
+
+/// the first matching stage, checked from the most to the least decisive
+pub fn stage_from_metrics(metrics: &LifecycleMetrics) -> LifecycleStage {
+    if metrics.age_secs >= 24 * 60 * 60
+        && metrics.volume_24h < DEAD_MAX_VOLUME_24H
+    {
+        return LifecycleStage::Dead;
+    }
+    if metrics.age_secs < LAUNCHING_MAX_AGE_SECS && metrics.price_change_1h > 0.
+    {
+        return LifecycleStage::Launching;
+    }
+    if metrics.price_change_1h > PUMPING_MIN_CHANGE_1H {
+        return LifecycleStage::Pumping;
+    }
+    if metrics.drawdown_from_peak > DECLINING_MIN_DRAWDOWN
+        || is_outflow(metrics.holder_change_rate)
+        || is_outflow(metrics.liquidity_change)
+    {
+        return LifecycleStage::Declining;
+    }
+    if metrics.price_change_1h.abs() < PEAKING_MAX_CHANGE_1H
+        && metrics.volume_1h < metrics.volume_prev_1h
+    {
+        return LifecycleStage::Peaking;
+    }
+    LifecycleStage::Unknown
+}
+
+/// computes the stage from the price history and caches it for
+/// `LIFECYCLE_STAGE_TTL_SECS`, `Unknown` if the history can't be read
+pub async fn classify_lifecycle_stage<K: KVStore, D: Database>(
+    mint: &str,
+    kv_store: &K,
+    db: &D,
+) -> LifecycleStage {
+    if let Ok(Some(stage)) = kv_store.get_lifecycle_stage(mint).await {
+        return stage;
+    }
+    let stage = match db.get_lifecycle_metrics(mint).await {
+        Ok(metrics) => stage_from_metrics(&metrics),
+        Err(e) => {
+            warn!("failed to get lifecycle metrics for {}: {}", mint, e);
+            return LifecycleStage::Unknown;
+        }
+    };
+    if let Err(e) = kv_store.insert_lifecycle_stage(mint, stage).await {
+        warn!("failed to cache lifecycle stage for {}: {}", mint, e);
+    }
+    stage
+}
+
+/// the cached stage without blocking on clickhouse, on a miss the stage is
+/// computed in the background and `Unknown` is returned in the meantime
+pub async fn cached_lifecycle_stage<K: KVStore, D: Database>(
+    mint: &str,
+    kv_store: &Arc<K>,
+    db: &Arc<D>,
+) -> LifecycleStage {
+    match kv_store.get_lifecycle_stage(mint).await {
+        Ok(Some(stage)) => return stage,
+        Ok(None) => {}
+        Err(e) => {
+            warn!("failed to get lifecycle stage for {}: {}", mint, e);
+            return LifecycleStage::Unknown;
+        }
+    }
+    if !PENDING.lock().unwrap().insert(mint.to_string()) {
+        return LifecycleStage::Unknown;
+    }
+    let (mint, kv_store, db) = (mint.to_string(), kv_store.clone(), db.clone());
+    tokio::spawn(async move {
+        classify_lifecycle_stage(&mint, kv_store.as_ref(), db.as_ref()).await;
+        PENDING.lock().unwrap().remove(&mint);
+    });
+    LifecycleStage::Unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics() -> LifecycleMetrics {
+        LifecycleMetrics {
+            age_secs: 3 * 24 * 60 * 60,
+            volume_1h: 10_000.,
+            volume_prev_1h: 10_000.,
+            volume_24h: 200_000.,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_stage_from_metrics() {
+        let launching = LifecycleMetrics {
+            age_secs: 10 * 60,
+            price_change_1h: 0.1,
+            volume_24h: 50.,
+            ..metrics()
+        };
+        assert_eq!(stage_from_metrics(&launching), LifecycleStage::Launching);
+
+        let pumping = LifecycleMetrics {
+            price_change_1h: 0.8,
+            ..metrics()
+        };
+        assert_eq!(stage_from_metrics(&pumping), LifecycleStage::Pumping);
+
+        let peaking = LifecycleMetrics {
+            price_change_1h: 0.01,
+            volume_1h: 5_000.,
+            ..metrics()
+        };
+        assert_eq!(stage_from_metrics(&peaking), LifecycleStage::Peaking);
+
+        let declining = LifecycleMetrics {
+            price_change_1h: -0.1,
+            drawdown_from_peak: 0.4,
+            ..metrics()
+        };
+        assert_eq!(stage_from_metrics(&declining), LifecycleStage::Declining);
+
+        let liquidity_pulled = LifecycleMetrics {
+            liquidity_change: Some(-0.5),
+            ..metrics()
+        };
+        assert_eq!(
+            stage_from_metrics(&liquidity_pulled),
+            LifecycleStage::Declining
+        );
+
+        let dead = LifecycleMetrics {
+            volume_24h: 20.,
+            ..metrics()
+        };
+        assert_eq!(stage_from_metrics(&dead), LifecycleStage::Dead);
+
+        assert_eq!(stage_from_metrics(&metrics()), LifecycleStage::Unknown);
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_metrics_query() {
+        let db = crate::util::make_db().await.unwrap();
+        db.get_lifecycle_metrics(crate::constants::WSOL_MINT_KEY_STR)
+            .await
+            .unwrap();
+    }
+}
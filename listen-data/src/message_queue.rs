@@ -1,9 +1,19 @@
-use crate::util::create_redis_pool;
+use crate::util::{create_redis_pool, RedisPoolConfig};
 use anyhow::{Context, Result};
 use bb8_redis::{bb8, RedisConnectionManager};
 use tracing::info;
 
+use crate::arbitrage::{ArbitrageOpportunity, ARBITRAGE_OPPORTUNITIES_CHANNEL};
+use crate::metadata_update::{MetadataUpdateEvent, METADATA_UPDATES_CHANNEL};
+use crate::new_pool::{NewPoolEvent, NEW_POOL_EVENTS_CHANNEL};
+use crate::pool_monitor::{PoolTvlUpdate, POOL_TVL_UPDATES_CHANNEL};
 use crate::price::PriceUpdate;
+use crate::referral_fees::{FeeAccrued, REFERRAL_FEES_CHANNEL};
+use crate::slot_lag::{SlotLagAlert, SYSTEM_ALERTS_CHANNEL};
+use crate::smart_money::{SmartMoneyActivity, SMART_MONEY_EVENTS_STREAM};
+
+/// approximate cap of the smart money events stream
+const SMART_MONEY_EVENTS_MAXLEN: usize = 10_000;
 
 #[async_trait::async_trait]
 pub trait MessageQueue: Send + Sync + 'static {
@@ -13,6 +23,41 @@ pub trait MessageQueue: Send + Sync + 'static {
         &self,
         price_update: PriceUpdate,
     ) -> Result<(), Self::Error>;
+
+    async fn publish_smart_money_activity(
+        &self,
+        activity: SmartMoneyActivity,
+    ) -> Result<(), Self::Error>;
+
+    async fn publish_pool_tvl_update(
+        &self,
+        update: PoolTvlUpdate,
+    ) -> Result<(), Self::Error>;
+
+    async fn publish_metadata_update(
+        &self,
+        event: MetadataUpdateEvent,
+    ) -> Result<(), Self::Error>;
+
+    async fn publish_new_pool_event(
+        &self,
+        event: NewPoolEvent,
+    ) -> Result<(), Self::Error>;
+
+    async fn publish_fee_accrued(
+        &self,
+        event: FeeAccrued,
+    ) -> Result<(), Self::Error>;
+
+    async fn publish_arbitrage_opportunity(
+        &self,
+        opportunity: ArbitrageOpportunity,
+    ) -> Result<(), Self::Error>;
+
+    async fn publish_system_alert(
+        &self,
+        alert: SlotLagAlert,
+    ) -> Result<(), Self::Error>;
 }
 
 // Redis implementation of MessageQueue
@@ -23,8 +68,18 @@ pub struct RedisMessageQueue {
 
 impl RedisMessageQueue {
     pub async fn new(redis_url: &str) -> Result<Self> {
-        let pool = create_redis_pool(redis_url).await?;
-        info!("Connected to Redis message queue at {}", redis_url);
+        Self::with_config(redis_url, &RedisPoolConfig::from_env()).await
+    }
+
+    pub async fn with_config(
+        redis_url: &str,
+        config: &RedisPoolConfig,
+    ) -> Result<Self> {
+        let pool = create_redis_pool(redis_url, config).await?;
+        info!(
+            "Connected to Redis message queue at {} (pool size {})",
+            redis_url, config.max_size
+        );
         Ok(Self { pool })
     }
 }
@@ -66,4 +121,193 @@ impl MessageQueue for RedisMessageQueue {
             .query_async(&mut *conn)
             .await
     }
+
+    /// appended to a stream rather than published, so that recent activity
+    /// can be read back by time
+    async fn publish_smart_money_activity(
+        &self,
+        activity: SmartMoneyActivity,
+    ) -> Result<(), Self::Error> {
+        let mut conn = self.pool.get().await.map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Failed to get Redis connection",
+                e.to_string(),
+            ))
+        })?;
+        let payload = serde_json::to_string(&activity).map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Serialization error",
+                e.to_string(),
+            ))
+        })?;
+
+        redis::cmd("XADD")
+            .arg(SMART_MONEY_EVENTS_STREAM)
+            .arg("MAXLEN")
+            .arg("~")
+            .arg(SMART_MONEY_EVENTS_MAXLEN)
+            .arg("*")
+            .arg("activity")
+            .arg(payload)
+            .query_async(&mut *conn)
+            .await
+    }
+
+    async fn publish_pool_tvl_update(
+        &self,
+        update: PoolTvlUpdate,
+    ) -> Result<(), Self::Error> {
+        let mut conn = self.pool.get().await.map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Failed to get Redis connection",
+                e.to_string(),
+            ))
+        })?;
+        let payload = serde_json::to_string(&update).map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Serialization error",
+                e.to_string(),
+            ))
+        })?;
+
+        redis::cmd("PUBLISH")
+            .arg(POOL_TVL_UPDATES_CHANNEL)
+            .arg(payload)
+            .query_async(&mut *conn)
+            .await
+    }
+
+    async fn publish_metadata_update(
+        &self,
+        event: MetadataUpdateEvent,
+    ) -> Result<(), Self::Error> {
+        let mut conn = self.pool.get().await.map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Failed to get Redis connection",
+                e.to_string(),
+            ))
+        })?;
+        let payload = serde_json::to_string(&event).map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Serialization error",
+                e.to_string(),
+            ))
+        })?;
+
+        redis::cmd("PUBLISH")
+            .arg(METADATA_UPDATES_CHANNEL)
+            .arg(payload)
+            .query_async(&mut *conn)
+            .await
+    }
+
+    async fn publish_new_pool_event(
+        &self,
+        event: NewPoolEvent,
+    ) -> Result<(), Self::Error> {
+        let mut conn = self.pool.get().await.map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Failed to get Redis connection",
+                e.to_string(),
+            ))
+        })?;
+        let payload = serde_json::to_string(&event).map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Serialization error",
+                e.to_string(),
+            ))
+        })?;
+
+        redis::cmd("PUBLISH")
+            .arg(NEW_POOL_EVENTS_CHANNEL)
+            .arg(payload)
+            .query_async(&mut *conn)
+            .await
+    }
+
+    async fn publish_fee_accrued(
+        &self,
+        event: FeeAccrued,
+    ) -> Result<(), Self::Error> {
+        let mut conn = self.pool.get().await.map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Failed to get Redis connection",
+                e.to_string(),
+            ))
+        })?;
+        let payload = serde_json::to_string(&event).map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Serialization error",
+                e.to_string(),
+            ))
+        })?;
+
+        redis::cmd("PUBLISH")
+            .arg(REFERRAL_FEES_CHANNEL)
+            .arg(payload)
+            .query_async(&mut *conn)
+            .await
+    }
+
+    async fn publish_arbitrage_opportunity(
+        &self,
+        opportunity: ArbitrageOpportunity,
+    ) -> Result<(), Self::Error> {
+        let mut conn = self.pool.get().await.map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Failed to get Redis connection",
+                e.to_string(),
+            ))
+        })?;
+        let payload = serde_json::to_string(&opportunity).map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Serialization error",
+                e.to_string(),
+            ))
+        })?;
+
+        redis::cmd("PUBLISH")
+            .arg(ARBITRAGE_OPPORTUNITIES_CHANNEL)
+            .arg(payload)
+            .query_async(&mut *conn)
+            .await
+    }
+
+    async fn publish_system_alert(
+        &self,
+        alert: SlotLagAlert,
+    ) -> Result<(), Self::Error> {
+        let mut conn = self.pool.get().await.map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Failed to get Redis connection",
+                e.to_string(),
+            ))
+        })?;
+        let payload = serde_json::to_string(&alert).map_err(|e| {
+            redis::RedisError::from((
+                redis::ErrorKind::IoError,
+                "Serialization error",
+                e.to_string(),
+            ))
+        })?;
+
+        redis::cmd("PUBLISH")
+            .arg(SYSTEM_ALERTS_CHANNEL)
+            .arg(payload)
+            .query_async(&mut *conn)
+            .await
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{kv_store::RedisKVStore, util::make_rpc_client};
+use crate::{kv_store::KVStore, util::make_rpc_client};
 use anyhow::{Context, Result};
 use mpl_token_metadata::accounts::Metadata;
 use serde::{Deserialize, Serialize};
@@ -51,8 +51,8 @@ fn convert_ipfs_uri(uri: &str) -> String {
     }
 }
 
-pub async fn get_token_metadata(
-    kv_store: &Arc<RedisKVStore>,
+pub async fn get_token_metadata<K: KVStore>(
+    kv_store: &Arc<K>,
     mint: &str,
 ) -> Result<Option<TokenMetadata>> {
     // Try to get from cache first
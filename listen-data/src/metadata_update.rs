@@ -0,0 +1,270 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use carbon_core::transaction::TransactionMetadata;
+use mpl_token_metadata::accounts::Metadata;
+use serde::{Deserialize, Serialize};
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, info};
+
+use crate::kv_store::KVStore;
+use crate::message_queue::MessageQueue;
+use crate::metadata::get_token_metadata;
+use crate::util::make_rpc_client;
+
+pub const METADATA_UPDATES_CHANNEL: &str = "metadata_updates";
+
+/// `UpdateMetadataAccountV2` discriminator of the token metadata program
+const UPDATE_METADATA_ACCOUNT_V2: u8 = 15;
+
+/// A name or uri change of a token, tokens rugging through their metadata
+/// usually change both
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetadataUpdateEvent {
+    /// empty until resolved when the mint isn't among the accounts of the
+    /// transaction
+    pub mint: String,
+    pub metadata_account: String,
+    /// `None` when the token wasn't cached before the update
+    pub old_name: Option<String>,
+    pub new_name: String,
+    pub old_uri: Option<String>,
+    pub new_uri: String,
+    /// the update authority that signed the change
+    pub updater: String,
+    pub signature: String,
+    pub slot: u64,
+}
+
+/// borsh string of the instruction data, names and uris can be null padded
+fn read_string(data: &[u8], offset: &mut usize) -> Option<String> {
+    let len_bytes = data.get(*offset..*offset + 4)?;
+    let len = u32::from_le_bytes(len_bytes.try_into().ok()?) as usize;
+    let bytes = data.get(*offset + 4..*offset + 4 + len)?;
+    *offset += 4 + len;
+    Some(
+        String::from_utf8_lossy(bytes)
+            .trim_matches(char::from(0))
+            .to_string(),
+    )
+}
+
+/// new name and uri of `UpdateMetadataAccountV2` instruction data, `None`
+/// for other instructions and for updates that leave the data as it is
+pub fn parse_update_metadata_v2(data: &[u8]) -> Option<(String, String)> {
+    if data.first() != Some(&UPDATE_METADATA_ACCOUNT_V2) {
+        return None;
+    }
+    // `Option<DataV2>`
+    if data.get(1) != Some(&1) {
+        return None;
+    }
+    let mut offset = 2;
+    let name = read_string(data, &mut offset)?;
+    let _symbol = read_string(data, &mut offset)?;
+    let uri = read_string(data, &mut offset)?;
+    Some((name, uri))
+}
+
+/// the first metadata update among `instructions`, compiled against
+/// `account_keys`
+pub fn find_metadata_update<'a>(
+    account_keys: &[Pubkey],
+    instructions: impl IntoIterator<Item = &'a CompiledInstruction>,
+) -> Option<MetadataUpdateEvent> {
+    instructions.into_iter().find_map(|instruction| {
+        let program =
+            account_keys.get(instruction.program_id_index as usize)?;
+        if *program != mpl_token_metadata::ID {
+            return None;
+        }
+        let (new_name, new_uri) = parse_update_metadata_v2(&instruction.data)?;
+        let account = |index: usize| {
+            account_keys.get(*instruction.accounts.get(index)? as usize)
+        };
+        let metadata_account = account(0)?;
+        let updater = account(1)?;
+        let mint = account_keys
+            .iter()
+            .find(|key| Metadata::find_pda(key).0 == *metadata_account)
+            .map(|mint| mint.to_string())
+            .unwrap_or_default();
+        Some(MetadataUpdateEvent {
+            mint,
+            metadata_account: metadata_account.to_string(),
+            old_name: None,
+            new_name,
+            old_uri: None,
+            new_uri,
+            updater: updater.to_string(),
+            signature: String::new(),
+            slot: 0,
+        })
+    })
+}
+
+/// a metadata update made in the transaction, top level or through CPI
+pub fn detect_metadata_update(
+    transaction_metadata: &TransactionMetadata,
+) -> Option<MetadataUpdateEvent> {
+    let meta = &transaction_metadata.meta;
+    let mut account_keys =
+        transaction_metadata.message.static_account_keys().to_vec();
+    account_keys.extend(&meta.loaded_addresses.writable);
+    account_keys.extend(&meta.loaded_addresses.readonly);
+
+    let inner_instructions = meta
+        .inner_instructions
+        .iter()
+        .flatten()
+        .flat_map(|inner| &inner.instructions)
+        .map(|inner| &inner.instruction);
+    let mut event = find_metadata_update(
+        &account_keys,
+        transaction_metadata
+            .message
+            .instructions()
+            .iter()
+            .chain(inner_instructions),
+    )?;
+    event.signature = transaction_metadata.signature.to_string();
+    event.slot = transaction_metadata.slot;
+    Some(event)
+}
+
+async fn fetch_mint(metadata_account: &str) -> Result<String> {
+    let account = make_rpc_client()?
+        .get_account(&Pubkey::from_str(metadata_account)?)
+        .await
+        .context("failed to get metadata account")?;
+    let metadata = Metadata::from_bytes(&account.data)
+        .map_err(|e| anyhow!("failed to parse metadata account: {}", e))?;
+    Ok(metadata.mint.to_string())
+}
+
+/// publishes the metadata update of the transaction, if any, and refreshes
+/// the cached metadata of the token
+pub async fn handle_metadata_update<Q: MessageQueue, K: KVStore>(
+    transaction_metadata: &TransactionMetadata,
+    message_queue: &Q,
+    kv_store: &Arc<K>,
+) -> Result<()> {
+    let Some(mut event) = detect_metadata_update(transaction_metadata) else {
+        return Ok(());
+    };
+    if event.mint.is_empty() {
+        event.mint = fetch_mint(&event.metadata_account).await?;
+    }
+
+    if let Some(cached) = kv_store.get_metadata(&event.mint).await? {
+        event.old_name = Some(cached.mpl.name);
+        event.old_uri = Some(cached.mpl.uri);
+    }
+    kv_store.delete_metadata(&event.mint).await?;
+    // a failed re-fetch is retried by the next swap of the token
+    if let Err(e) = get_token_metadata(kv_store, &event.mint).await {
+        debug!(mint = event.mint, ?e, "failed to re-fetch metadata");
+    }
+
+    info!(
+        mint = event.mint,
+        old_name = event.old_name,
+        new_name = event.new_name,
+        "https://solscan.io/tx/{} token metadata updated",
+        event.signature
+    );
+    message_queue
+        .publish_metadata_update(event)
+        .await
+        .context("failed to publish metadata update")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(data: &mut Vec<u8>, s: &str) {
+        data.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        data.extend_from_slice(s.as_bytes());
+    }
+
+    fn update_data(name: &str, uri: &str) -> Vec<u8> {
+        let mut data = vec![UPDATE_METADATA_ACCOUNT_V2, 1];
+        string(&mut data, name);
+        string(&mut data, "RUG");
+        string(&mut data, uri);
+        data.extend_from_slice(&0u16.to_le_bytes());
+        // creators, collection, uses, new update authority, primary sale
+        // happened, is mutable
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        data
+    }
+
+    #[test]
+    fn test_parse_update_metadata_v2() {
+        assert_eq!(
+            parse_update_metadata_v2(&update_data(
+                "Rugged\0\0",
+                "https://example.com/new.json"
+            )),
+            Some((
+                "Rugged".to_string(),
+                "https://example.com/new.json".to_string()
+            ))
+        );
+        // authority only update
+        assert_eq!(
+            parse_update_metadata_v2(&[UPDATE_METADATA_ACCOUNT_V2, 0, 0]),
+            None
+        );
+        assert_eq!(parse_update_metadata_v2(&[33, 1]), None);
+        assert_eq!(parse_update_metadata_v2(&update_data("a", "b")[..8]), None);
+    }
+
+    #[test]
+    fn test_find_metadata_update() {
+        let (mint, updater) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let metadata_account = Metadata::find_pda(&mint).0;
+        let account_keys = vec![
+            updater,
+            metadata_account,
+            mint,
+            mpl_token_metadata::ID,
+            spl_token::ID,
+        ];
+        let instructions = [
+            CompiledInstruction::new_from_raw_parts(4, vec![3], vec![2]),
+            CompiledInstruction::new_from_raw_parts(
+                3,
+                update_data("New", "https://example.com/new.json"),
+                vec![1, 0],
+            ),
+        ];
+
+        let event = find_metadata_update(&account_keys, &instructions)
+            .expect("update instruction");
+        assert_eq!(event.mint, mint.to_string());
+        assert_eq!(event.metadata_account, metadata_account.to_string());
+        assert_eq!(event.updater, updater.to_string());
+        assert_eq!(event.new_name, "New");
+        assert_eq!(event.new_uri, "https://example.com/new.json");
+
+        let account_keys = [updater, metadata_account, updater];
+        let instructions = [CompiledInstruction::new_from_raw_parts(
+            2,
+            update_data("New", "uri"),
+            vec![1, 0],
+        )];
+        assert_eq!(
+            find_metadata_update(&account_keys, &instructions),
+            None,
+            "not the metadata program"
+        );
+        // without the mint in the accounts it is resolved later
+        let account_keys = [updater, metadata_account, mpl_token_metadata::ID];
+        let event = find_metadata_update(&account_keys, &instructions).unwrap();
+        assert!(event.mint.is_empty());
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use crate::{
+    instruction_decoders::TokenMetadataInstruction, kv_store::RedisKVStore,
+    message_queue::RedisMessageQueue, metadata_update::handle_metadata_update,
+};
+use carbon_core::{
+    error::CarbonResult, instruction::InstructionProcessorInputType,
+    metrics::MetricsCollection, processor::Processor,
+    transaction::TransactionMetadata,
+};
+
+/// publishes the token metadata updates of the metadata program's
+/// transactions, which rarely trade in the same transaction
+pub struct MetadataUpdateProcessor {
+    pub kv_store: Arc<RedisKVStore>,
+    pub message_queue: Arc<RedisMessageQueue>,
+}
+
+#[async_trait::async_trait]
+impl Processor for MetadataUpdateProcessor {
+    type InputType = InstructionProcessorInputType<TokenMetadataInstruction>;
+
+    async fn process(
+        &mut self,
+        data: Self::InputType,
+        _metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        let (meta, instruction, _nested_instructions) = data;
+        let TokenMetadataInstruction::UpdateMetadataAccountV2 { name, .. } =
+            &instruction.data;
+        debug!(
+            name,
+            "https://solscan.io/tx/{}", meta.transaction_metadata.signature
+        );
+
+        let tx_meta = TransactionMetadata::clone(&meta.transaction_metadata);
+        let message_queue = self.message_queue.clone();
+        let kv_store = self.kv_store.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_metadata_update(
+                &tx_meta,
+                message_queue.as_ref(),
+                &kv_store,
+            )
+            .await
+            {
+                warn!(
+                    ?e,
+                    "https://solscan.io/tx/{} failed to handle metadata update",
+                    tx_meta.signature
+                );
+            }
+        });
+
+        Ok(())
+    }
+}
+
+impl MetadataUpdateProcessor {
+    pub fn new(
+        kv_store: Arc<RedisKVStore>,
+        message_queue: Arc<RedisMessageQueue>,
+    ) -> Self {
+        Self {
+            kv_store,
+            message_queue,
+        }
+    }
+}
@@ -1,6 +1,36 @@
+use actix_web::{HttpResponse, Responder};
+use anyhow::{anyhow, Result};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use once_cell::sync::OnceCell;
 use std::sync::atomic::{AtomicU64, Ordering};
 use tracing::info;
 
+static PROMETHEUS_HANDLE: OnceCell<PrometheusHandle> = OnceCell::new();
+
+/// installs the recorder `GET /metrics` renders, once per process
+pub fn setup_metrics_exporter() -> Result<()> {
+    let handle = PrometheusBuilder::new()
+        .add_global_label("service", "listen-data")
+        .install_recorder()
+        .map_err(|e| anyhow!("Failed to install metrics recorder: {}", e))?;
+    let _ = PROMETHEUS_HANDLE.set(handle);
+
+    metrics::describe_gauge!(
+        "slot_lag_gauge",
+        "Slots the processed transactions trail the chain by"
+    );
+    Ok(())
+}
+
+pub async fn metrics_handler() -> impl Responder {
+    match PROMETHEUS_HANDLE.get() {
+        Some(handle) => HttpResponse::Ok()
+            .content_type("text/plain")
+            .body(handle.render()),
+        None => HttpResponse::ServiceUnavailable().finish(),
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct SwapMetrics {
     pub total_swaps_processed: AtomicU64,
@@ -18,6 +48,7 @@ pub struct SwapMetrics {
     pub multi_hop_swap: AtomicU64,
     pub kv_insert_success: AtomicU64,
     pub kv_insert_failure: AtomicU64,
+    pub held_back_swaps: AtomicU64,
 }
 
 impl SwapMetrics {
@@ -89,6 +120,10 @@ impl SwapMetrics {
         self.kv_insert_failure.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn increment_held_back_swaps(&self) {
+        self.held_back_swaps.fetch_add(1, Ordering::Relaxed);
+    }
+
     fn log_metrics(&self) {
         let total = self.total_swaps_processed.load(Ordering::Relaxed);
         let successful = self.successful_swaps.load(Ordering::Relaxed);
@@ -109,6 +144,7 @@ impl SwapMetrics {
         let multi_hop = self.multi_hop_swap.load(Ordering::Relaxed);
         let kv_insert_success = self.kv_insert_success.load(Ordering::Relaxed);
         let kv_insert_failure = self.kv_insert_failure.load(Ordering::Relaxed);
+        let held_back = self.held_back_swaps.load(Ordering::Relaxed);
 
         let success_rate = if total > 0 {
             (successful as f64 / total as f64) * 100.0
@@ -132,7 +168,8 @@ impl SwapMetrics {
              DB Insert Failure: {}\n\
              Multi-hop Swaps: {}\n\
              KV Insert Success: {}\n\
-             KV Insert Failure: {}",
+             KV Insert Failure: {}\n\
+             Held back (lagging): {}",
             total,
             successful,
             success_rate,
@@ -149,6 +186,7 @@ impl SwapMetrics {
             multi_hop,
             kv_insert_success,
             kv_insert_failure,
+            held_back,
         );
     }
 }
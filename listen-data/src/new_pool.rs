@@ -0,0 +1,648 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcTransactionConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter,
+};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::hashv;
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::message::v0::LoadedAddresses;
+use solana_sdk::message::VersionedMessage;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::{
+    TransactionStatusMeta, UiInnerInstructions, UiInstruction,
+    UiLoadedAddresses, UiTransactionEncoding, UiTransactionTokenBalance,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::constants::{
+    RAYDIUM_AMM_V4_PROGRAM_ID, USDC_MINT_KEY, USDT_MINT_KEY,
+    WHIRLPOOLS_PROGRAM_ID, WSOL_MINT_KEY,
+};
+use crate::diffs::TokenBalanceInfo;
+use crate::message_queue::{MessageQueue, RedisMessageQueue};
+use crate::sol_price_stream::get_sol_price;
+
+pub const NEW_POOL_EVENTS_CHANNEL: &str = "new_pool_events";
+
+/// wait before re-subscribing after the subscriptions failed
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// the transaction of a log notification can take a moment to be served
+const FETCH_ATTEMPTS: usize = 5;
+const FETCH_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// what the programs log for the instructions, only transactions logging
+/// one of these are fetched
+const POOL_INIT_LOGS: [&str; 2] = [
+    "Program log: initialize2: InitializeInstruction2",
+    // both `initialize_pool` and `initialize_pool_v2`
+    "Program log: Instruction: InitializePool",
+];
+
+/// raydium amm v4 `Initialize2` tag and its accounts
+const RAYDIUM_INITIALIZE2: u8 = 1;
+const RAYDIUM_INITIALIZE2_ACCOUNTS: usize = 21;
+const RAYDIUM_AMM: usize = 4;
+const RAYDIUM_AUTHORITY: usize = 5;
+const RAYDIUM_COIN_MINT: usize = 8;
+const RAYDIUM_PC_MINT: usize = 9;
+const RAYDIUM_USER_WALLET: usize = 17;
+
+fn anchor_discriminator(name: &str) -> [u8; 8] {
+    hashv(&[format!("global:{}", name).as_bytes()]).to_bytes()[..8]
+        .try_into()
+        .unwrap()
+}
+
+static WHIRLPOOL_INITIALIZE_POOL: Lazy<[u8; 8]> =
+    Lazy::new(|| anchor_discriminator("initialize_pool"));
+static WHIRLPOOL_INITIALIZE_POOL_V2: Lazy<[u8; 8]> =
+    Lazy::new(|| anchor_discriminator("initialize_pool_v2"));
+
+/// A pool created on Raydium or Orca, published before anyone trades it,
+/// the earliest sign of a new token launch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewPoolEvent {
+    pub program: String,
+    pub pool_address: String,
+    pub token_a_mint: String,
+    pub token_b_mint: String,
+    /// twice the usd value of the SOL or stablecoin side after creation, 0
+    /// when neither side is one, and for orca pools, which get their
+    /// liquidity through positions opened later
+    pub initial_liquidity_usd: f64,
+    /// the wallet that created the pool
+    pub creator: String,
+    pub signature: String,
+    pub slot: u64,
+    pub timestamp: u64,
+}
+
+/// a pool initialization instruction, resolved against the accounts of its
+/// transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolInit {
+    pub program: Pubkey,
+    pub pool: Pubkey,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub creator: Pubkey,
+    /// owner of the vaults of the pool
+    pub vault_authority: Pubkey,
+}
+
+fn parse_pool_init(
+    program: &Pubkey,
+    accounts: &[Pubkey],
+    data: &[u8],
+) -> Option<PoolInit> {
+    if *program == RAYDIUM_AMM_V4_PROGRAM_ID {
+        if data.first() != Some(&RAYDIUM_INITIALIZE2)
+            || accounts.len() < RAYDIUM_INITIALIZE2_ACCOUNTS
+        {
+            return None;
+        }
+        return Some(PoolInit {
+            program: *program,
+            pool: accounts[RAYDIUM_AMM],
+            token_a_mint: accounts[RAYDIUM_COIN_MINT],
+            token_b_mint: accounts[RAYDIUM_PC_MINT],
+            creator: accounts[RAYDIUM_USER_WALLET],
+            vault_authority: accounts[RAYDIUM_AUTHORITY],
+        });
+    }
+    if *program == WHIRLPOOLS_PROGRAM_ID {
+        // mint a, mint b, funder and whirlpool, v2 adds the token badges
+        // of both mints after the mints
+        let (funder, whirlpool) =
+            if data.starts_with(WHIRLPOOL_INITIALIZE_POOL_V2.as_slice()) {
+                (5, 6)
+            } else if data.starts_with(WHIRLPOOL_INITIALIZE_POOL.as_slice()) {
+                (3, 4)
+            } else {
+                return None;
+            };
+        return Some(PoolInit {
+            program: *program,
+            pool: *accounts.get(whirlpool)?,
+            token_a_mint: *accounts.get(1)?,
+            token_b_mint: *accounts.get(2)?,
+            creator: *accounts.get(funder)?,
+            vault_authority: *accounts.get(whirlpool)?,
+        });
+    }
+    None
+}
+
+/// the pools `instructions` initialize, compiled against `account_keys`
+pub fn find_pool_inits<'a>(
+    account_keys: &[Pubkey],
+    instructions: impl IntoIterator<Item = &'a CompiledInstruction>,
+) -> Vec<PoolInit> {
+    instructions
+        .into_iter()
+        .filter_map(|instruction| {
+            let program =
+                account_keys.get(instruction.program_id_index as usize)?;
+            let accounts = instruction
+                .accounts
+                .iter()
+                .map(|&index| account_keys.get(index as usize).copied())
+                .collect::<Option<Vec<_>>>()?;
+            parse_pool_init(program, &accounts, &instruction.data)
+        })
+        .collect()
+}
+
+fn account_keys(
+    message: &VersionedMessage,
+    loaded_addresses: &LoadedAddresses,
+) -> Vec<Pubkey> {
+    let mut account_keys = message.static_account_keys().to_vec();
+    account_keys.extend(&loaded_addresses.writable);
+    account_keys.extend(&loaded_addresses.readonly);
+    account_keys
+}
+
+/// whether the transaction creates a pool, top level or through CPI. The
+/// first deposit of a pool moves its vaults like a trade would, so such
+/// transactions are left to the `NewPoolDetector` rather than priced
+pub fn initializes_pool(
+    message: &VersionedMessage,
+    meta: &TransactionStatusMeta,
+) -> bool {
+    let inner_instructions = meta
+        .inner_instructions
+        .iter()
+        .flatten()
+        .flat_map(|inner| &inner.instructions)
+        .map(|inner| &inner.instruction);
+    !find_pool_inits(
+        &account_keys(message, &meta.loaded_addresses),
+        message.instructions().iter().chain(inner_instructions),
+    )
+    .is_empty()
+}
+
+pub fn is_pool_init_log(logs: &[String]) -> bool {
+    logs.iter()
+        .any(|log| POOL_INIT_LOGS.iter().any(|init| log.starts_with(init)))
+}
+
+fn quote_price(mint: &Pubkey, sol_price: f64) -> Option<f64> {
+    if *mint == WSOL_MINT_KEY {
+        Some(sol_price)
+    } else if *mint == USDC_MINT_KEY || *mint == USDT_MINT_KEY {
+        Some(1.0)
+    } else {
+        None
+    }
+}
+
+/// twice the value of the SOL or stablecoin vault of the pool in
+/// `post_balances`, the other side is worth the same at the initial price
+pub fn initial_liquidity_usd<T: TokenBalanceInfo>(
+    init: &PoolInit,
+    post_balances: &[T],
+    sol_price: f64,
+) -> f64 {
+    let Some((quote_mint, price)) = [init.token_b_mint, init.token_a_mint]
+        .into_iter()
+        .find_map(|mint| Some((mint, quote_price(&mint, sol_price)?)))
+    else {
+        return 0.0;
+    };
+    let (quote_mint, vault_authority) =
+        (quote_mint.to_string(), init.vault_authority.to_string());
+    post_balances
+        .iter()
+        .find(|balance| {
+            balance.get_mint() == quote_mint
+                && balance.get_owner() == vault_authority
+        })
+        .and_then(|balance| balance.get_ui_amount())
+        .map_or(0.0, |amount| amount * price * 2.0)
+}
+
+fn parse_loaded_addresses(
+    loaded_addresses: Option<UiLoadedAddresses>,
+) -> Result<LoadedAddresses> {
+    let Some(loaded_addresses) = loaded_addresses else {
+        return Ok(LoadedAddresses::default());
+    };
+    let parse = |keys: &[String]| {
+        keys.iter()
+            .map(|key| Pubkey::from_str(key))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    Ok(LoadedAddresses {
+        writable: parse(&loaded_addresses.writable)?,
+        readonly: parse(&loaded_addresses.readonly)?,
+    })
+}
+
+/// the instructions the transaction invoked through CPI, e.g. a launchpad
+/// migrating its tokens to a pool; base64 encoded transactions report them
+/// compiled with base58 data
+fn compiled_inner_instructions(
+    inner_instructions: Vec<UiInnerInstructions>,
+) -> Vec<CompiledInstruction> {
+    inner_instructions
+        .into_iter()
+        .flat_map(|inner| inner.instructions)
+        .filter_map(|instruction| match instruction {
+            UiInstruction::Compiled(instruction) => Some(CompiledInstruction {
+                program_id_index: instruction.program_id_index,
+                accounts: instruction.accounts,
+                data: bs58::decode(instruction.data).into_vec().ok()?,
+            }),
+            UiInstruction::Parsed(_) => None,
+        })
+        .collect()
+}
+
+/// Publishes a `NewPoolEvent` to `new_pool_events` for every Raydium AMM v4
+/// or Orca Whirlpool pool created. Follows the logs of both programs and
+/// fetches the transactions that log a pool initialization.
+#[derive(Clone)]
+pub struct NewPoolDetector {
+    rpc_client: Arc<RpcClient>,
+    ws_url: String,
+    message_queue: Arc<RedisMessageQueue>,
+}
+
+impl NewPoolDetector {
+    pub fn new(
+        rpc_client: Arc<RpcClient>,
+        ws_url: String,
+        message_queue: Arc<RedisMessageQueue>,
+    ) -> Self {
+        Self {
+            rpc_client,
+            ws_url,
+            message_queue,
+        }
+    }
+
+    /// the new pool events of the transaction, none if it created no pool
+    pub async fn detect(
+        &self,
+        signature: &Signature,
+    ) -> Result<Vec<NewPoolEvent>> {
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
+        };
+        let mut attempt = 1;
+        let transaction = loop {
+            match self
+                .rpc_client
+                .get_transaction_with_config(signature, config)
+                .await
+            {
+                Ok(transaction) => break transaction,
+                Err(e) if attempt < FETCH_ATTEMPTS => {
+                    debug!(%signature, ?e, attempt, "transaction not found");
+                    attempt += 1;
+                    tokio::time::sleep(FETCH_RETRY_DELAY).await;
+                }
+                Err(e) => return Err(e).context("Failed to fetch transaction"),
+            }
+        };
+
+        let slot = transaction.slot;
+        let meta = transaction
+            .transaction
+            .meta
+            .context("Transaction has no meta")?;
+        let tx = transaction
+            .transaction
+            .transaction
+            .decode()
+            .context("Failed to decode transaction")?;
+        let loaded_addresses =
+            parse_loaded_addresses(meta.loaded_addresses.into())?;
+        let post_balances: Vec<UiTransactionTokenBalance> =
+            Option::from(meta.post_token_balances).unwrap_or_default();
+
+        let inner_instructions = compiled_inner_instructions(
+            Option::from(meta.inner_instructions).unwrap_or_default(),
+        );
+        let inits = find_pool_inits(
+            &account_keys(&tx.message, &loaded_addresses),
+            tx.message.instructions().iter().chain(&inner_instructions),
+        );
+        if inits.is_empty() {
+            return Ok(vec![]);
+        }
+        let sol_price = get_sol_price().await;
+        let timestamp = chrono::Utc::now().timestamp() as u64;
+        Ok(inits
+            .iter()
+            .map(|init| NewPoolEvent {
+                program: init.program.to_string(),
+                pool_address: init.pool.to_string(),
+                token_a_mint: init.token_a_mint.to_string(),
+                token_b_mint: init.token_b_mint.to_string(),
+                initial_liquidity_usd: initial_liquidity_usd(
+                    init,
+                    &post_balances,
+                    sol_price,
+                ),
+                creator: init.creator.to_string(),
+                signature: signature.to_string(),
+                slot,
+                timestamp,
+            })
+            .collect())
+    }
+
+    async fn publish(&self, signature: Signature) {
+        let events = match self.detect(&signature).await {
+            Ok(events) => events,
+            Err(e) => {
+                error!(%signature, "failed to detect new pool: {}", e);
+                return;
+            }
+        };
+        for event in events {
+            info!(
+                pool = event.pool_address,
+                token_a = event.token_a_mint,
+                token_b = event.token_b_mint,
+                liquidity = event.initial_liquidity_usd,
+                "https://solscan.io/tx/{} new pool",
+                event.signature
+            );
+            if let Err(e) =
+                self.message_queue.publish_new_pool_event(event).await
+            {
+                error!("failed to publish new pool event: {}", e);
+            }
+        }
+    }
+
+    /// returns once any of the subscriptions ends
+    async fn subscribe(&self) -> Result<()> {
+        let client = PubsubClient::new(&self.ws_url)
+            .await
+            .context("Failed to connect to the websocket")?;
+
+        let mut streams = Vec::new();
+        for program in [RAYDIUM_AMM_V4_PROGRAM_ID, WHIRLPOOLS_PROGRAM_ID] {
+            let (stream, _unsubscribe) = client
+                .logs_subscribe(
+                    RpcTransactionLogsFilter::Mentions(vec![
+                        program.to_string()
+                    ]),
+                    RpcTransactionLogsConfig {
+                        commitment: Some(CommitmentConfig::confirmed()),
+                    },
+                )
+                .await
+                .with_context(|| {
+                    format!("Failed to subscribe to the logs of {}", program)
+                })?;
+            streams.push(stream);
+        }
+
+        let mut notifications = futures_util::stream::select_all(streams);
+        while let Some(response) = notifications.next().await {
+            let logs = response.value;
+            if logs.err.is_some() || !is_pool_init_log(&logs.logs) {
+                continue;
+            }
+            let Ok(signature) = logs.signature.parse::<Signature>() else {
+                warn!("invalid signature in logs: {}", logs.signature);
+                continue;
+            };
+            // fetching can take a few retries, the next pools shouldn't wait
+            let detector = self.clone();
+            tokio::spawn(async move { detector.publish(signature).await });
+        }
+        Ok(())
+    }
+
+    pub async fn run(self) {
+        loop {
+            match self.subscribe().await {
+                Ok(()) => warn!("new pool subscriptions ended"),
+                Err(e) => error!("new pool detector failed: {}", e),
+            }
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_account_decoder::parse_token::UiTokenAmount;
+    use solana_sdk::message::Message;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_transaction_status::{
+        InnerInstruction, InnerInstructions, TransactionTokenBalance,
+    };
+
+    fn token_balance(
+        mint: &Pubkey,
+        owner: &Pubkey,
+        ui_amount: f64,
+    ) -> TransactionTokenBalance {
+        TransactionTokenBalance {
+            account_index: 0,
+            mint: mint.to_string(),
+            ui_token_amount: UiTokenAmount {
+                ui_amount: Some(ui_amount),
+                decimals: 9,
+                amount: String::new(),
+                ui_amount_string: ui_amount.to_string(),
+            },
+            owner: owner.to_string(),
+            program_id: spl_token::ID.to_string(),
+        }
+    }
+
+    /// an `Initialize2` compiled into a message, with the keys of its
+    /// accounts in order
+    fn raydium_initialize2(
+        creator: &Pubkey,
+        coin_mint: &Pubkey,
+    ) -> (Message, Vec<Pubkey>) {
+        let mut accounts = (0..RAYDIUM_INITIALIZE2_ACCOUNTS)
+            .map(|_| Pubkey::new_unique())
+            .collect::<Vec<_>>();
+        accounts[RAYDIUM_COIN_MINT] = *coin_mint;
+        accounts[RAYDIUM_PC_MINT] = WSOL_MINT_KEY;
+        accounts[RAYDIUM_USER_WALLET] = *creator;
+        let mut data = vec![RAYDIUM_INITIALIZE2, 254];
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.extend_from_slice(&(80 * 10u64.pow(9)).to_le_bytes());
+        data.extend_from_slice(&(200_000_000 * 10u64.pow(6)).to_le_bytes());
+        let instruction = solana_sdk::instruction::Instruction::new_with_bytes(
+            RAYDIUM_AMM_V4_PROGRAM_ID,
+            &data,
+            accounts
+                .iter()
+                .map(|key| {
+                    solana_sdk::instruction::AccountMeta::new(
+                        *key,
+                        key == creator,
+                    )
+                })
+                .collect(),
+        );
+        (Message::new(&[instruction], Some(creator)), accounts)
+    }
+
+    #[test]
+    fn test_find_raydium_pool_init() {
+        let (creator, coin) = (Keypair::new().pubkey(), Pubkey::new_unique());
+        let (message, accounts) = raydium_initialize2(&creator, &coin);
+
+        let inits =
+            find_pool_inits(&message.account_keys, &message.instructions);
+        assert_eq!(
+            inits,
+            vec![PoolInit {
+                program: RAYDIUM_AMM_V4_PROGRAM_ID,
+                pool: accounts[RAYDIUM_AMM],
+                token_a_mint: coin,
+                token_b_mint: WSOL_MINT_KEY,
+                creator,
+                vault_authority: accounts[RAYDIUM_AUTHORITY],
+            }]
+        );
+
+        // 80 SOL deposited, the coin side is worth as much
+        let post = vec![
+            token_balance(&coin, &accounts[RAYDIUM_AUTHORITY], 2e8),
+            token_balance(&WSOL_MINT_KEY, &accounts[RAYDIUM_AUTHORITY], 80.0),
+            token_balance(&WSOL_MINT_KEY, &creator, 3.0),
+        ];
+        assert_eq!(initial_liquidity_usd(&inits[0], &post, 150.0), 24_000.0);
+    }
+
+    #[test]
+    fn test_find_whirlpool_init() {
+        let mut keys =
+            (0..14).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        keys[0] = WHIRLPOOLS_PROGRAM_ID;
+        let instruction = |data: &[u8]| CompiledInstruction {
+            program_id_index: 0,
+            accounts: (1..14).collect(),
+            data: data.to_vec(),
+        };
+
+        let mut data = WHIRLPOOL_INITIALIZE_POOL_V2.to_vec();
+        data.extend_from_slice(&[0; 18]);
+        let inits = find_pool_inits(&keys, &[instruction(&data)]);
+        assert_eq!(inits.len(), 1);
+        // accounts start at key 1, funder 5 and whirlpool 6
+        assert_eq!(inits[0].token_a_mint, keys[2]);
+        assert_eq!(inits[0].token_b_mint, keys[3]);
+        assert_eq!(inits[0].creator, keys[6]);
+        assert_eq!(inits[0].pool, keys[7]);
+
+        let mut data = WHIRLPOOL_INITIALIZE_POOL.to_vec();
+        data.extend_from_slice(&[0; 18]);
+        let inits = find_pool_inits(&keys, &[instruction(&data)]);
+        assert_eq!(inits[0].creator, keys[4]);
+        assert_eq!(inits[0].pool, keys[5]);
+        // no SOL or stablecoin side
+        assert_eq!(
+            initial_liquidity_usd::<TransactionTokenBalance>(
+                &inits[0],
+                &[],
+                150.0
+            ),
+            0.0
+        );
+
+        // a swap
+        let swap = instruction(&anchor_discriminator("swap"));
+        assert!(find_pool_inits(&keys, &[swap]).is_empty());
+    }
+
+    #[test]
+    fn test_initializes_pool() {
+        let creator = Keypair::new().pubkey();
+        let (message, _) = raydium_initialize2(&creator, &Pubkey::new_unique());
+        let meta = TransactionStatusMeta::default();
+        assert!(initializes_pool(
+            &VersionedMessage::Legacy(message.clone()),
+            &meta
+        ));
+
+        // a swap instruction of the same program isn't one
+        let mut swap = message.clone();
+        swap.instructions[0].data[0] = 9;
+        assert!(!initializes_pool(
+            &VersionedMessage::Legacy(swap.clone()),
+            &meta
+        ));
+
+        // through CPI, e.g. a launchpad migrating its tokens
+        let meta = TransactionStatusMeta {
+            inner_instructions: Some(vec![InnerInstructions {
+                index: 0,
+                instructions: vec![InnerInstruction {
+                    instruction: message.instructions[0].clone(),
+                    stack_height: Some(2),
+                }],
+            }]),
+            ..Default::default()
+        };
+        assert!(initializes_pool(&VersionedMessage::Legacy(swap), &meta));
+    }
+
+    #[test]
+    fn test_compiled_inner_instructions() {
+        let creator = Keypair::new().pubkey();
+        let (message, _) = raydium_initialize2(&creator, &Pubkey::new_unique());
+        let instruction = &message.instructions[0];
+        let inner = vec![UiInnerInstructions {
+            index: 0,
+            instructions: vec![UiInstruction::Compiled(
+                solana_transaction_status::UiCompiledInstruction {
+                    program_id_index: instruction.program_id_index,
+                    accounts: instruction.accounts.clone(),
+                    data: bs58::encode(&instruction.data).into_string(),
+                    stack_height: Some(2),
+                },
+            )],
+        }];
+
+        let inner = compiled_inner_instructions(inner);
+        assert_eq!(inner, vec![instruction.clone()]);
+        assert_eq!(find_pool_inits(&message.account_keys, &inner).len(), 1);
+    }
+
+    #[test]
+    fn test_is_pool_init_log() {
+        assert!(is_pool_init_log(&[
+            "Program 675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8 invoke [1]"
+                .to_string(),
+            "Program log: initialize2: InitializeInstruction2 { nonce: 254, open_time: 0, init_pc_amount: 80000000000, init_coin_amount: 200000000000000 }".to_string(),
+        ]));
+        assert!(is_pool_init_log(&[
+            "Program log: Instruction: InitializePoolV2".to_string()
+        ]));
+        assert!(!is_pool_init_log(&[
+            "Program log: ray_log: AwDh9QUAAAAA".to_string(),
+            "Program log: Instruction: Swap".to_string(),
+        ]));
+    }
+}
@@ -0,0 +1,206 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::json;
+use tracing::{debug, info};
+
+use super::{
+    format_price, format_usd, solscan_token_url, solscan_tx_url,
+    NotificationEvent, RateLimiter,
+};
+
+const DISCORD_RATE_LIMIT: Duration = Duration::from_secs(60);
+
+pub const COLOR_GREEN: u32 = 0x2ecc71;
+pub const COLOR_RED: u32 = 0xe74c3c;
+
+static DISCORD_NOTIFIER: Lazy<Option<DiscordNotifier>> =
+    Lazy::new(DiscordNotifier::from_env);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiscordEmbedField {
+    pub name: String,
+    pub value: String,
+    pub inline: bool,
+}
+
+/// https://discord.com/developers/docs/resources/message#embed-object
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiscordEmbed {
+    pub title: String,
+    pub url: String,
+    pub color: u32,
+    pub fields: Vec<DiscordEmbedField>,
+}
+
+impl DiscordEmbed {
+    fn field(name: &str, value: String) -> DiscordEmbedField {
+        DiscordEmbedField {
+            name: name.to_string(),
+            value,
+            inline: true,
+        }
+    }
+}
+
+impl From<&NotificationEvent> for DiscordEmbed {
+    fn from(event: &NotificationEvent) -> Self {
+        let update = event.update();
+        let color = match event {
+            NotificationEvent::RugPull { .. } => COLOR_RED,
+            _ if update.is_buy => COLOR_GREEN,
+            _ => COLOR_RED,
+        };
+        let (title, url) = match event {
+            NotificationEvent::LargeSwap(_) => (
+                format!(
+                    "🐋 {} large {} of {}",
+                    update.name,
+                    if update.is_buy { "buy" } else { "sell" },
+                    format_usd(update.swap_amount)
+                ),
+                solscan_tx_url(&update.signature),
+            ),
+            NotificationEvent::Graduation(_) => (
+                format!("🎓 {} graduated from pump.fun", update.name),
+                solscan_token_url(&update.pubkey),
+            ),
+            NotificationEvent::MarketCapMilestone { milestone, .. } => (
+                format!(
+                    "💎 {} hit {} market cap",
+                    update.name,
+                    format_usd(*milestone)
+                ),
+                solscan_token_url(&update.pubkey),
+            ),
+            NotificationEvent::RugPull { .. } => (
+                format!("🚨 {} possible rug pull", update.name),
+                solscan_tx_url(&update.signature),
+            ),
+        };
+
+        let mut fields = vec![
+            Self::field("Token", update.name.clone()),
+            Self::field("Price", format_price(update.price)),
+            Self::field("Market cap", format_usd(update.market_cap)),
+        ];
+        if let NotificationEvent::RugPull { previous_price, .. } = event {
+            fields.push(Self::field(
+                "Previous price",
+                format_price(*previous_price),
+            ));
+        }
+        fields.push(Self::field("Swap", format_usd(update.swap_amount)));
+
+        Self {
+            title,
+            url,
+            color,
+            fields,
+        }
+    }
+}
+
+pub struct DiscordNotifier {
+    webhook_url: String,
+    rate_limiter: RateLimiter,
+    client: reqwest::Client,
+}
+
+impl DiscordNotifier {
+    pub fn new(webhook_url: String) -> Self {
+        Self {
+            webhook_url,
+            rate_limiter: RateLimiter::new(DISCORD_RATE_LIMIT),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// requires DISCORD_WEBHOOK_URL
+    pub fn from_env() -> Option<Self> {
+        let webhook_url = std::env::var("DISCORD_WEBHOOK_URL").ok()?;
+        if webhook_url.is_empty() {
+            return None;
+        }
+        info!("Discord notifications enabled");
+        Some(Self::new(webhook_url))
+    }
+
+    pub async fn notify(&self, event: &NotificationEvent) -> Result<()> {
+        if !self.rate_limiter.try_acquire(&event.update().pubkey) {
+            debug!(
+                "rate limited discord notification for {}",
+                event.update().pubkey
+            );
+            return Ok(());
+        }
+
+        let res = self
+            .client
+            .post(&self.webhook_url)
+            .json(&json!({ "embeds": [DiscordEmbed::from(event)] }))
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            let status = res.status();
+            let body = res.text().await.unwrap_or_default();
+            return Err(anyhow!("Discord API error {}: {}", status, body));
+        }
+
+        Ok(())
+    }
+}
+
+/// no-op unless DISCORD_WEBHOOK_URL is set
+pub async fn notify_discord(event: &NotificationEvent) -> Result<()> {
+    match DISCORD_NOTIFIER.as_ref() {
+        Some(notifier) => notifier.notify(event).await,
+        None => Ok(()),
+    }
+}
+
+pub fn is_discord_enabled() -> bool {
+    DISCORD_NOTIFIER.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::make_test_update;
+    use crate::price::PriceUpdate;
+
+    #[test]
+    fn test_large_swap_embed() {
+        let event = NotificationEvent::LargeSwap(make_test_update(
+            1_000_000.0,
+            60_000.0,
+        ));
+        let embed = serde_json::to_value(DiscordEmbed::from(&event)).unwrap();
+        assert_eq!(embed["title"], "🐋 TOKEN large buy of $60K");
+        assert_eq!(embed["url"], "https://solscan.io/tx/sig");
+        assert_eq!(embed["color"], COLOR_GREEN);
+        assert_eq!(embed["fields"][1]["name"], "Price");
+        assert_eq!(embed["fields"][1]["value"], "$0.001234");
+        assert_eq!(embed["fields"][2]["value"], "$1M");
+    }
+
+    #[test]
+    fn test_rug_pull_embed_is_red() {
+        let event = NotificationEvent::RugPull {
+            update: PriceUpdate {
+                is_buy: false,
+                ..make_test_update(10_000.0, 5_000.0)
+            },
+            previous_price: 0.01,
+        };
+        let embed = DiscordEmbed::from(&event);
+        assert_eq!(embed.color, COLOR_RED);
+        assert!(embed
+            .fields
+            .iter()
+            .any(|f| f.name == "Previous price" && f.value == "$0.010000"));
+    }
+}
@@ -0,0 +1,269 @@
+pub mod discord;
+pub mod telegram;
+
+pub use discord::{
+    is_discord_enabled, notify_discord, DiscordEmbed, DiscordNotifier,
+};
+pub use telegram::{is_telegram_enabled, notify_telegram, TelegramNotifier};
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::price::PriceUpdate;
+
+pub const LARGE_SWAP_USD: f64 = 50_000.0;
+pub const MARKET_CAP_MILESTONES: [f64; 4] =
+    [1_000_000.0, 10_000_000.0, 100_000_000.0, 1_000_000_000.0];
+/// a sell that takes the price down by this fraction of the previous price
+pub const RUG_PULL_PRICE_DROP: f64 = 0.8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NotificationKind {
+    LargeSwap,
+    Graduation,
+    MarketCapMilestone,
+    RugPull,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 4] = [
+        NotificationKind::LargeSwap,
+        NotificationKind::Graduation,
+        NotificationKind::MarketCapMilestone,
+        NotificationKind::RugPull,
+    ];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "large_swap" => Some(NotificationKind::LargeSwap),
+            "graduation" => Some(NotificationKind::Graduation),
+            "market_cap_milestone" => {
+                Some(NotificationKind::MarketCapMilestone)
+            }
+            "rug_pull" => Some(NotificationKind::RugPull),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum NotificationEvent {
+    LargeSwap(PriceUpdate),
+    /// pump.fun tokens only show up on raydium once they graduate
+    Graduation(PriceUpdate),
+    MarketCapMilestone {
+        update: PriceUpdate,
+        milestone: f64,
+    },
+    RugPull {
+        update: PriceUpdate,
+        previous_price: f64,
+    },
+}
+
+impl NotificationEvent {
+    pub fn kind(&self) -> NotificationKind {
+        match self {
+            NotificationEvent::LargeSwap(_) => NotificationKind::LargeSwap,
+            NotificationEvent::Graduation(_) => NotificationKind::Graduation,
+            NotificationEvent::MarketCapMilestone { .. } => {
+                NotificationKind::MarketCapMilestone
+            }
+            NotificationEvent::RugPull { .. } => NotificationKind::RugPull,
+        }
+    }
+
+    pub fn update(&self) -> &PriceUpdate {
+        match self {
+            NotificationEvent::LargeSwap(update)
+            | NotificationEvent::Graduation(update)
+            | NotificationEvent::MarketCapMilestone { update, .. }
+            | NotificationEvent::RugPull { update, .. } => update,
+        }
+    }
+}
+
+/// derives the events worth notifying about from the previous and the
+/// current price update of a token
+pub fn detect_events(
+    previous: Option<&PriceUpdate>,
+    update: &PriceUpdate,
+) -> Vec<NotificationEvent> {
+    let mut events = Vec::new();
+
+    if update.swap_amount >= LARGE_SWAP_USD {
+        events.push(NotificationEvent::LargeSwap(update.clone()));
+    }
+
+    match previous {
+        None if update.is_pump => {
+            events.push(NotificationEvent::Graduation(update.clone()));
+        }
+        Some(previous) => {
+            if let Some(milestone) = MARKET_CAP_MILESTONES
+                .iter()
+                .rev()
+                .find(|m| previous.market_cap < **m && update.market_cap >= **m)
+            {
+                events.push(NotificationEvent::MarketCapMilestone {
+                    update: update.clone(),
+                    milestone: *milestone,
+                });
+            }
+            if !update.is_buy
+                && previous.price > 0.0
+                && update.price <= previous.price * (1.0 - RUG_PULL_PRICE_DROP)
+            {
+                events.push(NotificationEvent::RugPull {
+                    update: update.clone(),
+                    previous_price: previous.price,
+                });
+            }
+        }
+        None => {}
+    }
+
+    events
+}
+
+/// $1.2B, $1M, $50K, $420
+pub fn format_usd(amount: f64) -> String {
+    let (value, suffix) = if amount >= 1_000_000_000.0 {
+        (amount / 1_000_000_000.0, "B")
+    } else if amount >= 1_000_000.0 {
+        (amount / 1_000_000.0, "M")
+    } else if amount >= 1_000.0 {
+        (amount / 1_000.0, "K")
+    } else {
+        (amount, "")
+    };
+    let value = format!("{:.1}", value);
+    let value = value.strip_suffix(".0").unwrap_or(&value);
+    format!("${}{}", value, suffix)
+}
+
+pub fn format_price(price: f64) -> String {
+    if price >= 1.0 {
+        format!("${:.2}", price)
+    } else {
+        format!("${:.6}", price)
+    }
+}
+
+pub fn solscan_tx_url(signature: &str) -> String {
+    format!("https://solscan.io/tx/{}", signature)
+}
+
+pub fn solscan_token_url(mint: &str) -> String {
+    format!("https://solscan.io/token/{}", mint)
+}
+
+/// allows one message per key within the window
+pub struct RateLimiter {
+    window: Duration,
+    last_sent: Mutex<HashMap<String, Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last_sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn try_acquire(&self, key: &str) -> bool {
+        let mut last_sent = self.last_sent.lock().unwrap();
+        let now = Instant::now();
+        // drop expired entries so the map doesn't grow with every token
+        last_sent.retain(|_, sent| now.duration_since(*sent) < self.window);
+        if last_sent.contains_key(key) {
+            return false;
+        }
+        last_sent.insert(key.to_string(), now);
+        true
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn make_test_update(
+    market_cap: f64,
+    swap_amount: f64,
+) -> PriceUpdate {
+    PriceUpdate {
+        name: "TOKEN".to_string(),
+        pubkey: "mint".to_string(),
+        price: 0.001234,
+        market_cap,
+        timestamp: 0,
+        slot: 0,
+        swap_amount,
+        owner: "owner".to_string(),
+        signature: "sig".to_string(),
+        multi_hop: false,
+        is_buy: true,
+        is_pump: true,
+        trader_type: Default::default(),
+        lifecycle_stage: Default::default(),
+        schema_version: crate::price::PRICE_UPDATE_SCHEMA_VERSION,
+        smoothed_price: None,
+        finalized: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_usd() {
+        assert_eq!(format_usd(1_000_000.0), "$1M");
+        assert_eq!(format_usd(50_000.0), "$50K");
+        assert_eq!(format_usd(1_250_000_000.0), "$1.2B");
+        assert_eq!(format_usd(420.0), "$420");
+    }
+
+    #[test]
+    fn test_detect_events() {
+        let previous = make_test_update(900_000.0, 100.0);
+        let update = make_test_update(1_100_000.0, 60_000.0);
+        let events = detect_events(Some(&previous), &update);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind(), NotificationKind::LargeSwap);
+        assert!(matches!(
+            events[1],
+            NotificationEvent::MarketCapMilestone { milestone, .. }
+                if milestone == 1_000_000.0
+        ));
+
+        let events = detect_events(None, &make_test_update(70_000.0, 100.0));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind(), NotificationKind::Graduation);
+
+        let events = detect_events(Some(&update), &update);
+        assert_eq!(events.len(), 1);
+
+        let rug = PriceUpdate {
+            price: update.price * 0.1,
+            market_cap: update.market_cap * 0.1,
+            is_buy: false,
+            ..make_test_update(0.0, 100.0)
+        };
+        let events = detect_events(Some(&update), &rug);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind(), NotificationKind::RugPull);
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(Duration::from_secs(300));
+        assert!(limiter.try_acquire("mint1"));
+        assert!(!limiter.try_acquire("mint1"));
+        assert!(limiter.try_acquire("mint2"));
+
+        let limiter = RateLimiter::new(Duration::ZERO);
+        assert!(limiter.try_acquire("mint1"));
+        assert!(limiter.try_acquire("mint1"));
+    }
+}
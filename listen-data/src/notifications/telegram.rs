@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use serde_json::json;
+use tracing::{debug, info};
+
+use super::{
+    format_price, format_usd, solscan_token_url, solscan_tx_url,
+    NotificationEvent, NotificationKind, RateLimiter,
+};
+
+const TELEGRAM_RATE_LIMIT: Duration = Duration::from_secs(5 * 60);
+
+static TELEGRAM_NOTIFIER: Lazy<Option<TelegramNotifier>> =
+    Lazy::new(TelegramNotifier::from_env);
+
+pub struct TelegramNotifier {
+    bot_token: String,
+    chat_id: String,
+    events: HashSet<NotificationKind>,
+    rate_limiter: RateLimiter,
+    client: reqwest::Client,
+}
+
+impl TelegramNotifier {
+    pub fn new(
+        bot_token: String,
+        chat_id: String,
+        events: HashSet<NotificationKind>,
+    ) -> Self {
+        Self {
+            bot_token,
+            chat_id,
+            events,
+            rate_limiter: RateLimiter::new(TELEGRAM_RATE_LIMIT),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// requires TELEGRAM_BOT_TOKEN and TELEGRAM_CHAT_ID, TELEGRAM_EVENTS
+    /// is an optional comma-separated subset of
+    /// large_swap,graduation,market_cap_milestone,rug_pull (all by default)
+    pub fn from_env() -> Option<Self> {
+        let bot_token = std::env::var("TELEGRAM_BOT_TOKEN").ok()?;
+        let chat_id = std::env::var("TELEGRAM_CHAT_ID").ok()?;
+        let events = match std::env::var("TELEGRAM_EVENTS") {
+            Ok(events) => events
+                .split(',')
+                .filter_map(NotificationKind::parse)
+                .collect(),
+            Err(_) => NotificationKind::ALL.into_iter().collect(),
+        };
+        info!("Telegram notifications enabled for {:?}", events);
+        Some(Self::new(bot_token, chat_id, events))
+    }
+
+    pub fn format_message(event: &NotificationEvent) -> String {
+        let update = event.update();
+        let name = escape_markdown(&update.name);
+        match event {
+            NotificationEvent::MarketCapMilestone { milestone, .. } => {
+                format!(
+                    "💎 *{}* hit {} market cap! Price: {} | Swap: {} | [Solscan]({})",
+                    name,
+                    format_usd(*milestone),
+                    format_price(update.price),
+                    format_usd(update.swap_amount),
+                    solscan_token_url(&update.pubkey),
+                )
+            }
+            NotificationEvent::LargeSwap(_) => format!(
+                "🐋 *{}* large {} of {}! Price: {} | Market cap: {} | [Solscan]({})",
+                name,
+                if update.is_buy { "buy" } else { "sell" },
+                format_usd(update.swap_amount),
+                format_price(update.price),
+                format_usd(update.market_cap),
+                solscan_tx_url(&update.signature),
+            ),
+            NotificationEvent::Graduation(_) => format!(
+                "🎓 *{}* graduated from pump.fun! Price: {} | Market cap: {} | [Solscan]({})",
+                name,
+                format_price(update.price),
+                format_usd(update.market_cap),
+                solscan_token_url(&update.pubkey),
+            ),
+            NotificationEvent::RugPull { previous_price, .. } => format!(
+                "🚨 *{}* possible rug pull! Price: {} → {} | Sell: {} | [Solscan]({})",
+                name,
+                format_price(*previous_price),
+                format_price(update.price),
+                format_usd(update.swap_amount),
+                solscan_tx_url(&update.signature),
+            ),
+        }
+    }
+
+    pub async fn notify(&self, event: &NotificationEvent) -> Result<()> {
+        if !self.events.contains(&event.kind()) {
+            return Ok(());
+        }
+        if !self.rate_limiter.try_acquire(&event.update().pubkey) {
+            debug!(
+                "rate limited telegram notification for {}",
+                event.update().pubkey
+            );
+            return Ok(());
+        }
+
+        let res = self
+            .client
+            .post(format!(
+                "https://api.telegram.org/bot{}/sendMessage",
+                self.bot_token
+            ))
+            .json(&json!({
+                "chat_id": self.chat_id,
+                "text": Self::format_message(event),
+                "parse_mode": "Markdown",
+                "disable_web_page_preview": true,
+            }))
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            let status = res.status();
+            let body = res.text().await.unwrap_or_default();
+            return Err(anyhow!("Telegram API error {}: {}", status, body));
+        }
+
+        Ok(())
+    }
+}
+
+/// legacy Markdown only treats these as special
+fn escape_markdown(s: &str) -> String {
+    s.chars()
+        .filter(|c| !matches!(c, '*' | '_' | '`' | '['))
+        .collect()
+}
+
+/// no-op unless TELEGRAM_BOT_TOKEN and TELEGRAM_CHAT_ID are set
+pub async fn notify_telegram(event: &NotificationEvent) -> Result<()> {
+    match TELEGRAM_NOTIFIER.as_ref() {
+        Some(notifier) => notifier.notify(event).await,
+        None => Ok(()),
+    }
+}
+
+pub fn is_telegram_enabled() -> bool {
+    TELEGRAM_NOTIFIER.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::make_test_update;
+
+    #[test]
+    fn test_format_milestone_message() {
+        let event = NotificationEvent::MarketCapMilestone {
+            update: make_test_update(1_050_000.0, 50_000.0),
+            milestone: 1_000_000.0,
+        };
+        assert_eq!(
+            TelegramNotifier::format_message(&event),
+            "💎 *TOKEN* hit $1M market cap! Price: $0.001234 | Swap: $50K | [Solscan](https://solscan.io/token/mint)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notify_skips_disabled_events() {
+        let notifier = TelegramNotifier::new(
+            "invalid".to_string(),
+            "invalid".to_string(),
+            HashSet::from([NotificationKind::Graduation]),
+        );
+        let event = NotificationEvent::LargeSwap(make_test_update(1.0, 1.0));
+        // would fail against the telegram api if it was sent
+        notifier.notify(&event).await.unwrap();
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use once_cell::sync::Lazy;
+use solana_sdk::hash::hashv;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::constants::OPENBOOK_V2_PROGRAM_ID_STR;
+use crate::diffs::{get_dex_token_balance_diff, Dex, Diff, TokenBalanceInfo};
+use crate::util::make_rpc_client;
+
+const PROGRAM_DATA: &str = "Program data: ";
+
+/// anchor events are logged as the first 8 bytes of
+/// sha256("event:<name>") followed by the borsh encoded fields
+static FILL_LOG_DISCRIMINATOR: Lazy<[u8; 8]> = Lazy::new(|| {
+    hashv(&[b"event:FillLog"]).to_bytes()[..8]
+        .try_into()
+        .unwrap()
+});
+
+/// borsh size of `FillLog`, without the discriminator
+const FILL_LOG_LEN: usize = 171;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+/// a taker matched against a resting order, from the `FillLog` event the
+/// program logs for every match. The crank settles the maker side later,
+/// the taker side moves in the same transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    pub market: Pubkey,
+    pub taker_side: Side,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    /// quote lots per base lot, the lot sizes are set per market
+    pub price_lots: i64,
+    /// base lots
+    pub quantity_lots: i64,
+}
+
+fn pubkey_at(data: &[u8], offset: usize) -> Pubkey {
+    Pubkey::try_from(&data[offset..offset + 32]).unwrap()
+}
+
+fn i64_at(data: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+impl Fill {
+    /// `data` without the discriminator
+    fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < FILL_LOG_LEN {
+            return None;
+        }
+        // market, taker_side, maker_slot, maker_out, timestamp, seq_num,
+        // maker, maker_client_order_id, maker_fee, maker_timestamp, taker,
+        // taker_client_order_id, taker_fee_ceil, price, quantity
+        let taker_side = match data[32] {
+            0 => Side::Bid,
+            1 => Side::Ask,
+            _ => return None,
+        };
+        Some(Self {
+            market: pubkey_at(data, 0),
+            taker_side,
+            maker: pubkey_at(data, 51),
+            taker: pubkey_at(data, 107),
+            price_lots: i64_at(data, 155),
+            quantity_lots: i64_at(data, 163),
+        })
+    }
+}
+
+/// the fills logged by `log_messages`, other events and logs are ignored
+pub fn decode_fills(log_messages: &[String]) -> Vec<Fill> {
+    log_messages
+        .iter()
+        .filter_map(|log| log.strip_prefix(PROGRAM_DATA))
+        .filter_map(|data| BASE64_STANDARD.decode(data).ok())
+        .filter_map(|data| {
+            data.strip_prefix(FILL_LOG_DISCRIMINATOR.as_slice())
+                .and_then(Fill::decode)
+        })
+        .collect()
+}
+
+/// the part of the `Market` account that turns lots into token amounts,
+/// none of it changes once the market is created
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketInfo {
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+    /// quote native per quote lot
+    pub quote_lot_size: i64,
+    /// base native per base lot
+    pub base_lot_size: i64,
+}
+
+/// size of the `Market` account, with the anchor discriminator
+const MARKET_LEN: usize = 848;
+
+impl MarketInfo {
+    /// `data` of the `Market` account, with the discriminator
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < MARKET_LEN {
+            return None;
+        }
+        // discriminator, bump, base_decimals, quote_decimals, padding, five
+        // admin keys and time_expiry, name, bids, asks, event_heap, two
+        // oracles and their config, quote_lot_size, base_lot_size, ...,
+        // base_mint, quote_mint
+        Some(Self {
+            base_decimals: data[9],
+            quote_decimals: data[10],
+            quote_lot_size: i64_at(data, 448),
+            base_lot_size: i64_at(data, 456),
+            base_mint: pubkey_at(data, 576),
+            quote_mint: pubkey_at(data, 608),
+        })
+    }
+}
+
+static MARKETS: Lazy<Mutex<HashMap<Pubkey, MarketInfo>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// the market account, fetched once per market
+pub async fn get_market_info(market: &Pubkey) -> Result<MarketInfo> {
+    if let Some(info) = MARKETS.lock().unwrap().get(market) {
+        return Ok(info.clone());
+    }
+    let data = make_rpc_client()?
+        .get_account_data(market)
+        .await
+        .context("failed to get openbook market account")?;
+    let info = MarketInfo::decode(&data)
+        .ok_or_else(|| anyhow!("{} is not an openbook market", market))?;
+    MARKETS.lock().unwrap().insert(*market, info.clone());
+    Ok(info)
+}
+
+/// the fills of a taker trade against a single market, `None` for
+/// transactions that didn't trade, e.g. deposits, cancels, settles or the
+/// crank, and for routes through several markets or takers, which mix
+/// prices
+pub fn single_market_fills(log_messages: &[String]) -> Option<Vec<Fill>> {
+    let fills = decode_fills(log_messages);
+    let first = fills.first()?;
+    if fills
+        .iter()
+        .any(|fill| fill.market != first.market || fill.taker != first.taker)
+    {
+        return None;
+    }
+    Some(fills)
+}
+
+/// the diffs of the market for `fills`, priced at the fill prices.
+///
+/// The vaults show the taker fee on top of the trade and only move both
+/// legs when it settles in the same transaction, the fills are what
+/// matched. The amounts before the trade come from the vaults, when they
+/// are among the balances.
+pub fn fill_diffs<T: TokenBalanceInfo + std::fmt::Debug>(
+    fills: &[Fill],
+    market: &MarketInfo,
+    pre_balances: &[T],
+    post_balances: &[T],
+) -> Vec<Diff> {
+    let (base_lots, quote_lots) =
+        fills.iter().fold((0i128, 0i128), |(base, quote), fill| {
+            (
+                base + fill.quantity_lots as i128,
+                quote + fill.price_lots as i128 * fill.quantity_lots as i128,
+            )
+        });
+    let ui = |native: i128, decimals: u8| {
+        native as f64 / 10f64.powi(decimals as i32)
+    };
+    let base = ui(
+        base_lots * market.base_lot_size as i128,
+        market.base_decimals,
+    );
+    let quote = ui(
+        quote_lots * market.quote_lot_size as i128,
+        market.quote_decimals,
+    );
+    // the market is the maker side, it gives the base to a buying taker
+    let (base_diff, quote_diff) = match fills[0].taker_side {
+        Side::Bid => (-base, quote),
+        Side::Ask => (base, -quote),
+    };
+
+    let vaults = get_dex_token_balance_diff(
+        Dex::OpenBookV2,
+        pre_balances,
+        post_balances,
+    );
+    let diff = |mint: &Pubkey, diff: f64| {
+        let mint = mint.to_string();
+        let pre_amount = vaults
+            .iter()
+            .find(|vault| vault.mint == mint)
+            .map_or(0.0, |vault| vault.pre_amount);
+        Diff {
+            mint,
+            pre_amount,
+            post_amount: pre_amount + diff,
+            diff,
+            owner: OPENBOOK_V2_PROGRAM_ID_STR.to_string(),
+        }
+    };
+    vec![
+        diff(&market.base_mint, base_diff),
+        diff(&market.quote_mint, quote_diff),
+    ]
+}
+
+/// the diffs of a taker trade against a single market, priced from its
+/// fills, `None` for transactions that didn't trade
+pub async fn process_openbook_swap<T: TokenBalanceInfo + std::fmt::Debug>(
+    log_messages: &[String],
+    pre_balances: &[T],
+    post_balances: &[T],
+) -> Result<Option<Vec<Diff>>> {
+    let Some(fills) = single_market_fills(log_messages) else {
+        return Ok(None);
+    };
+    let market = get_market_info(&fills[0].market).await?;
+    Ok(Some(fill_diffs(
+        &fills,
+        &market,
+        pre_balances,
+        post_balances,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{OPENBOOK_V2_PROGRAM_ID, WSOL_MINT_KEY_STR};
+    use crate::diffs::process_diffs;
+    use solana_account_decoder::parse_token::UiTokenAmount;
+    use solana_transaction_status::TransactionTokenBalance;
+
+    fn token_balance(
+        mint: &str,
+        owner: &Pubkey,
+        ui_amount: f64,
+    ) -> TransactionTokenBalance {
+        TransactionTokenBalance {
+            account_index: 0,
+            mint: mint.to_string(),
+            ui_token_amount: UiTokenAmount {
+                ui_amount: Some(ui_amount),
+                decimals: 6,
+                amount: String::new(),
+                ui_amount_string: ui_amount.to_string(),
+            },
+            owner: owner.to_string(),
+            program_id: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"
+                .to_string(),
+        }
+    }
+
+    fn fill_log(
+        market: &Pubkey,
+        taker: &Pubkey,
+        side: u8,
+        price_lots: i64,
+        quantity_lots: i64,
+    ) -> String {
+        let mut data = FILL_LOG_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(market.as_ref());
+        data.extend_from_slice(&[side, 0, 1]);
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(&[0; 24]);
+        data.extend_from_slice(taker.as_ref());
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(&price_lots.to_le_bytes());
+        data.extend_from_slice(&quantity_lots.to_le_bytes());
+        format!("{}{}", PROGRAM_DATA, BASE64_STANDARD.encode(data))
+    }
+
+    #[test]
+    fn test_decode_fills() {
+        let (market, taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let logs = vec![
+            format!("Program {} invoke [1]", Pubkey::new_unique()),
+            "Program log: Instruction: PlaceTakeOrder".to_string(),
+            fill_log(&market, &taker, 1, 250, 40),
+            // another event of the program
+            format!("{}{}", PROGRAM_DATA, BASE64_STANDARD.encode([7; 64])),
+            fill_log(&market, &taker, 1, 250, 40),
+        ];
+
+        let fills = decode_fills(&logs);
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[0].market, market);
+        assert_eq!(fills[0].taker, taker);
+        assert_eq!(fills[0].taker_side, Side::Ask);
+        assert_eq!(fills[0].price_lots, 250);
+        assert_eq!(fills[0].quantity_lots, 40);
+    }
+
+    fn market_info(base_mint: &str) -> MarketInfo {
+        MarketInfo {
+            base_mint: base_mint.parse().unwrap(),
+            quote_mint: WSOL_MINT_KEY_STR.parse().unwrap(),
+            base_decimals: 6,
+            quote_decimals: 9,
+            quote_lot_size: 1_000,
+            base_lot_size: 1_000_000,
+        }
+    }
+
+    #[test]
+    fn test_decode_market() {
+        let info = market_info("CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon");
+        let mut data = vec![0; MARKET_LEN];
+        data[9] = info.base_decimals;
+        data[10] = info.quote_decimals;
+        data[448..456].copy_from_slice(&info.quote_lot_size.to_le_bytes());
+        data[456..464].copy_from_slice(&info.base_lot_size.to_le_bytes());
+        data[576..608].copy_from_slice(info.base_mint.as_ref());
+        data[608..640].copy_from_slice(info.quote_mint.as_ref());
+        assert_eq!(MarketInfo::decode(&data), Some(info));
+
+        assert_eq!(MarketInfo::decode(&data[..MARKET_LEN - 1]), None);
+    }
+
+    #[test]
+    fn test_no_fills_no_swap() {
+        let logs = vec!["Program log: Instruction: SettleFunds".to_string()];
+        assert!(single_market_fills(&logs).is_none());
+
+        // a route across two markets
+        let taker = Pubkey::new_unique();
+        let logs = vec![
+            fill_log(&Pubkey::new_unique(), &taker, 0, 250, 40),
+            fill_log(&Pubkey::new_unique(), &taker, 1, 250, 40),
+        ];
+        assert!(single_market_fills(&logs).is_none());
+    }
+
+    #[test]
+    fn test_take_order_diffs() {
+        let coin = "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon";
+        let market = Pubkey::new_unique();
+        let authority = Pubkey::find_program_address(
+            &[b"Market", market.as_ref()],
+            &OPENBOOK_V2_PROGRAM_ID,
+        )
+        .0;
+        let taker = Pubkey::new_unique();
+        // 500 lots of a coin at 200 quote lots each
+        let logs = vec![
+            fill_log(&market, &taker, 0, 200, 250),
+            fill_log(&market, &taker, 0, 200, 250),
+        ];
+        let fills = single_market_fills(&logs).unwrap();
+
+        // the taker buys 500 of the coin for 0.1 SOL, the quote vault also
+        // keeps the taker fee
+        let pre = vec![
+            token_balance(coin, &authority, 10_000.0),
+            token_balance(WSOL_MINT_KEY_STR, &authority, 5.0),
+        ];
+        let post = vec![
+            token_balance(coin, &authority, 9_500.0),
+            token_balance(WSOL_MINT_KEY_STR, &authority, 5.10004),
+        ];
+        let diffs = fill_diffs(&fills, &market_info(coin), &pre, &post);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].pre_amount, 10_000.0);
+
+        let result = process_diffs(&diffs, 200.0).unwrap();
+        assert_eq!(result.coin_mint, coin);
+        assert!(result.is_buy);
+        assert!((result.sol_amount - 0.1).abs() < 1e-9);
+        assert!((result.price - 0.04).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use carbon_core::transaction::TransactionMetadata;
+use once_cell::sync::Lazy;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::constants::{PHOENIX_PROGRAM_ID, PHOENIX_PROGRAM_ID_STR};
+use crate::diffs::{get_dex_token_balance_diff, Dex, Diff, TokenBalanceInfo};
+use crate::util::make_rpc_client;
+
+/// the program records its events by invoking its own `Log` instruction
+const LOG_INSTRUCTION_TAG: u8 = 15;
+
+/// borsh size of `AuditLogHeader`: instruction, sequence number,
+/// timestamp, slot, market, signer and the number of events
+const HEADER_LEN: usize = 91;
+
+/// a taker matched against a resting order, from the `Fill` event of the
+/// audit log
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    pub market: Pubkey,
+    pub maker: Pubkey,
+    /// the market's ticks, see `MarketInfo::quote_atoms`
+    pub price_in_ticks: u64,
+    pub base_lots: u64,
+}
+
+fn pubkey_at(data: &[u8], offset: usize) -> Pubkey {
+    Pubkey::try_from(&data[offset..offset + 32]).unwrap()
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// borsh size of the fields of each `PhoenixMarketEvent` variant, by its
+/// index: uninitialized, header, fill, place, reduce, evict, fill summary,
+/// fee, time in force and expired order
+const EVENT_LENS: [usize; 10] = [0, HEADER_LEN, 66, 42, 34, 58, 42, 10, 26, 58];
+
+const FILL_EVENT: u8 = 2;
+
+/// the fills of a single `Log` instruction, `data` with its tag: the
+/// header event followed by a borsh vec of events
+fn decode_log(data: &[u8]) -> Option<Vec<Fill>> {
+    let data = data.strip_prefix(&[LOG_INSTRUCTION_TAG])?;
+    // the header event: its variant, instruction, sequence number,
+    // timestamp and slot, then the market
+    if data.len() < 1 + HEADER_LEN + 4 || data[0] != 1 {
+        return None;
+    }
+    let market = pubkey_at(data, 1 + 25);
+    let mut offset = 1 + HEADER_LEN;
+    let len = u32_at(data, offset);
+    offset += 4;
+
+    let mut fills = Vec::new();
+    for _ in 0..len {
+        let variant = *data.get(offset)?;
+        let event_len = *EVENT_LENS.get(variant as usize)?;
+        let event = data.get(offset + 1..offset + 1 + event_len)?;
+        // index, maker, order sequence number, price, filled and
+        // remaining base lots
+        if variant == FILL_EVENT {
+            fills.push(Fill {
+                market,
+                maker: pubkey_at(event, 2),
+                price_in_ticks: u64_at(event, 42),
+                base_lots: u64_at(event, 50),
+            });
+        }
+        offset += 1 + event_len;
+    }
+    Some(fills)
+}
+
+/// the fills logged by the transaction, top level or through CPI, other
+/// events and instructions are ignored
+pub fn decode_fills(transaction_metadata: &TransactionMetadata) -> Vec<Fill> {
+    let meta = &transaction_metadata.meta;
+    let mut account_keys =
+        transaction_metadata.message.static_account_keys().to_vec();
+    account_keys.extend(&meta.loaded_addresses.writable);
+    account_keys.extend(&meta.loaded_addresses.readonly);
+
+    meta.inner_instructions
+        .iter()
+        .flatten()
+        .flat_map(|inner| &inner.instructions)
+        .filter(|inner| {
+            account_keys.get(inner.instruction.program_id_index as usize)
+                == Some(&PHOENIX_PROGRAM_ID)
+        })
+        .filter_map(|inner| decode_log(&inner.instruction.data))
+        .flatten()
+        .collect()
+}
+
+/// the part of the `MarketHeader` that turns ticks and lots into token
+/// amounts, none of it changes once the market is created
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketInfo {
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub base_decimals: u32,
+    pub quote_decimals: u32,
+    /// base atoms per base lot
+    pub base_lot_size: u64,
+    /// quote atoms per base unit per tick
+    pub tick_size: u64,
+    /// a base unit is this many whole tokens
+    pub raw_base_units_per_base_unit: u32,
+}
+
+/// size of the `MarketHeader` the order book follows
+const MARKET_HEADER_LEN: usize = 576;
+
+impl MarketInfo {
+    /// `data` of the market account
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < MARKET_HEADER_LEN {
+            return None;
+        }
+        // discriminant, status, size params, base params (decimals, vault
+        // bump, mint, vault), base lot size, quote params, quote lot size,
+        // tick size, authority, fee recipient, sequence number, successor,
+        // raw base units per base unit
+        Some(Self {
+            base_decimals: u32_at(data, 40),
+            base_mint: pubkey_at(data, 48),
+            base_lot_size: u64_at(data, 112),
+            quote_decimals: u32_at(data, 120),
+            quote_mint: pubkey_at(data, 128),
+            tick_size: u64_at(data, 200),
+            raw_base_units_per_base_unit: u32_at(data, 312).max(1),
+        })
+    }
+
+    fn base_atoms(&self, fill: &Fill) -> u128 {
+        fill.base_lots as u128 * self.base_lot_size as u128
+    }
+
+    /// what `fill` paid, ticks are quote atoms per base unit
+    fn quote_atoms(&self, fill: &Fill) -> f64 {
+        let base_unit_atoms = self.raw_base_units_per_base_unit as f64
+            * 10f64.powi(self.base_decimals as i32);
+        (fill.price_in_ticks as u128
+            * self.tick_size as u128
+            * self.base_atoms(fill)) as f64
+            / base_unit_atoms
+    }
+}
+
+static MARKETS: Lazy<Mutex<HashMap<Pubkey, MarketInfo>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// the market header, fetched once per market
+pub async fn get_market_info(market: &Pubkey) -> Result<MarketInfo> {
+    if let Some(info) = MARKETS.lock().unwrap().get(market) {
+        return Ok(info.clone());
+    }
+    let data = make_rpc_client()?
+        .get_account_data(market)
+        .await
+        .context("failed to get phoenix market account")?;
+    let info = MarketInfo::decode(&data)
+        .ok_or_else(|| anyhow!("{} is not a phoenix market", market))?;
+    MARKETS.lock().unwrap().insert(*market, info.clone());
+    Ok(info)
+}
+
+/// the fills of a trade against a single market, `None` for transactions
+/// that didn't trade, e.g. deposits, cancels or placing a resting order,
+/// and for routes through several markets, which mix prices
+pub fn single_market_fills(fills: Vec<Fill>) -> Option<Vec<Fill>> {
+    let first = fills.first()?;
+    if fills.iter().any(|fill| fill.market != first.market) {
+        return None;
+    }
+    Some(fills)
+}
+
+/// the diffs of the market for `fills`, priced at the fill prices.
+///
+/// The fills don't say which side the taker was on, that comes from the
+/// base vault, `None` when it didn't move. The amounts before the trade
+/// come from the vaults too, the fees they keep don't reach the price.
+pub fn fill_diffs<T: TokenBalanceInfo + std::fmt::Debug>(
+    fills: &[Fill],
+    market: &MarketInfo,
+    pre_balances: &[T],
+    post_balances: &[T],
+) -> Option<Vec<Diff>> {
+    let base_atoms: u128 =
+        fills.iter().map(|fill| market.base_atoms(fill)).sum();
+    let quote_atoms: f64 =
+        fills.iter().map(|fill| market.quote_atoms(fill)).sum();
+    let base = base_atoms as f64 / 10f64.powi(market.base_decimals as i32);
+    let quote = quote_atoms / 10f64.powi(market.quote_decimals as i32);
+
+    let vaults =
+        get_dex_token_balance_diff(Dex::Phoenix, pre_balances, post_balances);
+    let vault = |mint: &Pubkey| {
+        let mint = mint.to_string();
+        vaults.iter().find(|vault| vault.mint == mint).cloned()
+    };
+    let base_vault = vault(&market.base_mint)?;
+    // a selling taker leaves its base in the vault
+    let (base_diff, quote_diff) = if base_vault.diff > 0.0 {
+        (base, -quote)
+    } else if base_vault.diff < 0.0 {
+        (-base, quote)
+    } else {
+        return None;
+    };
+
+    let diff = |mint: &Pubkey, pre_amount: f64, diff: f64| Diff {
+        mint: mint.to_string(),
+        pre_amount,
+        post_amount: pre_amount + diff,
+        diff,
+        owner: PHOENIX_PROGRAM_ID_STR.to_string(),
+    };
+    let quote_pre =
+        vault(&market.quote_mint).map_or(0.0, |vault| vault.pre_amount);
+    Some(vec![
+        diff(&market.base_mint, base_vault.pre_amount, base_diff),
+        diff(&market.quote_mint, quote_pre, quote_diff),
+    ])
+}
+
+/// the diffs of a trade against a single market, priced from its fills,
+/// `None` for transactions that didn't trade
+pub async fn process_phoenix_swap(
+    transaction_metadata: &TransactionMetadata,
+) -> Result<Option<Vec<Diff>>> {
+    let Some(fills) = single_market_fills(decode_fills(transaction_metadata))
+    else {
+        return Ok(None);
+    };
+    let market = get_market_info(&fills[0].market).await?;
+    let meta = &transaction_metadata.meta;
+    let (Some(pre_balances), Some(post_balances)) =
+        (&meta.pre_token_balances, &meta.post_token_balances)
+    else {
+        return Ok(None);
+    };
+    Ok(fill_diffs(&fills, &market, pre_balances, post_balances))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::WSOL_MINT_KEY_STR;
+    use crate::diffs::process_diffs;
+    use solana_account_decoder::parse_token::UiTokenAmount;
+    use solana_transaction_status::TransactionTokenBalance;
+
+    const COIN: &str = "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon";
+
+    fn token_balance(
+        mint: &str,
+        owner: &Pubkey,
+        ui_amount: f64,
+    ) -> TransactionTokenBalance {
+        TransactionTokenBalance {
+            account_index: 0,
+            mint: mint.to_string(),
+            ui_token_amount: UiTokenAmount {
+                ui_amount: Some(ui_amount),
+                decimals: 6,
+                amount: String::new(),
+                ui_amount_string: ui_amount.to_string(),
+            },
+            owner: owner.to_string(),
+            program_id: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"
+                .to_string(),
+        }
+    }
+
+    /// a 0.001 coin lot and a tick of 1e-6 SOL per coin
+    fn market_info() -> MarketInfo {
+        MarketInfo {
+            base_mint: COIN.parse().unwrap(),
+            quote_mint: WSOL_MINT_KEY_STR.parse().unwrap(),
+            base_decimals: 6,
+            quote_decimals: 9,
+            base_lot_size: 1_000,
+            tick_size: 1_000,
+            raw_base_units_per_base_unit: 1,
+        }
+    }
+
+    fn fill_event(price_in_ticks: u64, base_lots: u64) -> Vec<u8> {
+        let mut event = vec![FILL_EVENT, 0, 0];
+        event.extend_from_slice(Pubkey::new_unique().as_ref());
+        event.extend_from_slice(&7u64.to_le_bytes());
+        event.extend_from_slice(&price_in_ticks.to_le_bytes());
+        event.extend_from_slice(&base_lots.to_le_bytes());
+        event.extend_from_slice(&0u64.to_le_bytes());
+        event
+    }
+
+    fn log_data(market: &Pubkey, events: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![LOG_INSTRUCTION_TAG, 1];
+        data.extend_from_slice(&[0; 25]);
+        data.extend_from_slice(market.as_ref());
+        data.extend_from_slice(&[0; 34]);
+        data.extend_from_slice(&(events.len() as u32).to_le_bytes());
+        for event in events {
+            data.extend_from_slice(event);
+        }
+        data
+    }
+
+    #[test]
+    fn test_decode_log() {
+        let market = Pubkey::new_unique();
+        // a fee event between the fills
+        let fee = [vec![7], vec![0; 10]].concat();
+        let data = log_data(
+            &market,
+            &[fill_event(200, 600_000), fee, fill_event(201, 400_000)],
+        );
+
+        let fills = decode_log(&data).unwrap();
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[0].market, market);
+        assert_eq!(fills[0].price_in_ticks, 200);
+        assert_eq!(fills[0].base_lots, 600_000);
+        assert_eq!(fills[1].price_in_ticks, 201);
+
+        // another instruction of the program
+        assert!(decode_log(&[2, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn test_decode_market() {
+        let info = market_info();
+        let mut data = vec![0; MARKET_HEADER_LEN];
+        data[40..44].copy_from_slice(&info.base_decimals.to_le_bytes());
+        data[48..80].copy_from_slice(info.base_mint.as_ref());
+        data[112..120].copy_from_slice(&info.base_lot_size.to_le_bytes());
+        data[120..124].copy_from_slice(&info.quote_decimals.to_le_bytes());
+        data[128..160].copy_from_slice(info.quote_mint.as_ref());
+        data[200..208].copy_from_slice(&info.tick_size.to_le_bytes());
+        data[312..316].copy_from_slice(&1u32.to_le_bytes());
+        assert_eq!(MarketInfo::decode(&data), Some(info));
+    }
+
+    #[test]
+    fn test_fill_diffs() {
+        let market = Pubkey::new_unique();
+        let data = log_data(
+            &market,
+            &[fill_event(200, 600_000), fill_event(200, 400_000)],
+        );
+        let fills = single_market_fills(decode_log(&data).unwrap()).unwrap();
+        let vault = Pubkey::find_program_address(
+            &[b"vault", market.as_ref()],
+            &PHOENIX_PROGRAM_ID,
+        )
+        .0;
+
+        // the taker sells 1000 of the coin for 0.2 SOL into resting bids,
+        // the quote vault also keeps the fee
+        let pre = vec![
+            token_balance(COIN, &vault, 50_000.0),
+            token_balance(WSOL_MINT_KEY_STR, &vault, 30.0),
+        ];
+        let post = vec![
+            token_balance(COIN, &vault, 51_000.0),
+            token_balance(WSOL_MINT_KEY_STR, &vault, 29.8001),
+        ];
+        let diffs = fill_diffs(&fills, &market_info(), &pre, &post).unwrap();
+        assert_eq!(diffs.len(), 2);
+
+        let result = process_diffs(&diffs, 200.0).unwrap();
+        assert_eq!(result.coin_mint, COIN);
+        assert!(!result.is_buy);
+        assert!((result.sol_amount - 0.2).abs() < 1e-9);
+        assert!((result.price - 0.04).abs() < 1e-9);
+
+        // a resting order that didn't move the base vault
+        assert!(fill_diffs(&fills, &market_info(), &pre, &pre).is_none());
+    }
+
+    #[test]
+    fn test_routes_are_skipped() {
+        let fills = [Pubkey::new_unique(), Pubkey::new_unique()]
+            .iter()
+            .flat_map(|market| {
+                decode_log(&log_data(market, &[fill_event(200, 1)])).unwrap()
+            })
+            .collect();
+        assert!(single_market_fills(fills).is_none());
+        assert!(single_market_fills(vec![]).is_none());
+    }
+}
@@ -0,0 +1,415 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcProgramAccountsConfig,
+};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use tracing::{error, info, warn};
+
+use crate::constants::{
+    RAYDIUM_AMM_V4_PROGRAM_ID, WHIRLPOOLS_PROGRAM_ID, WSOL_MINT_KEY,
+};
+use crate::db::ClickhouseDb;
+use crate::message_queue::{MessageQueue, RedisMessageQueue};
+use crate::sol_price_stream::get_sol_price;
+
+pub const POOL_TVL_UPDATES_CHANNEL: &str = "pool_tvl_updates";
+pub const DEFAULT_TOP_POOLS: usize = 100;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const VOLUME_LOOKBACK: Duration = Duration::from_secs(24 * 60 * 60);
+/// wait before re-seeding after the pool list or the subscriptions failed
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// raydium amm v4 `AmmInfo`
+const RAYDIUM_POOL_SIZE: u64 = 752;
+const RAYDIUM_COIN_VAULT_OFFSET: usize = 336;
+const RAYDIUM_PC_VAULT_OFFSET: usize = 368;
+const RAYDIUM_COIN_MINT_OFFSET: usize = 400;
+const RAYDIUM_PC_MINT_OFFSET: usize = 432;
+
+/// orca `Whirlpool`
+const WHIRLPOOL_SIZE: u64 = 653;
+const WHIRLPOOL_MINT_A_OFFSET: usize = 101;
+const WHIRLPOOL_VAULT_A_OFFSET: usize = 133;
+const WHIRLPOOL_MINT_B_OFFSET: usize = 181;
+const WHIRLPOOL_VAULT_B_OFFSET: usize = 213;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PoolDex {
+    Raydium,
+    Orca,
+}
+
+/// a token/SOL pool, watched through its SOL vault
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitoredPool {
+    pub pool: Pubkey,
+    pub dex: PoolDex,
+    pub mint: Pubkey,
+    pub sol_vault: Pubkey,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolTvlUpdate {
+    pub pool: String,
+    pub dex: PoolDex,
+    pub mint: String,
+    pub sol_vault: String,
+    pub lamports: u64,
+    /// usd value of the SOL vault
+    pub tvl: f64,
+    pub slot: u64,
+    pub timestamp: u64,
+}
+
+pub fn compute_tvl(lamports: u64, sol_price: f64) -> f64 {
+    lamports as f64 / 1e9 * sol_price
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Option<Pubkey> {
+    Pubkey::try_from(data.get(offset..offset + 32)?).ok()
+}
+
+/// `None` unless one side of the pool is SOL
+fn sol_pool(
+    pool: Pubkey,
+    dex: PoolDex,
+    (mint_a, vault_a): (Pubkey, Pubkey),
+    (mint_b, vault_b): (Pubkey, Pubkey),
+) -> Option<MonitoredPool> {
+    let (mint, sol_vault) = if mint_a == WSOL_MINT_KEY {
+        (mint_b, vault_a)
+    } else if mint_b == WSOL_MINT_KEY {
+        (mint_a, vault_b)
+    } else {
+        return None;
+    };
+    Some(MonitoredPool {
+        pool,
+        dex,
+        mint,
+        sol_vault,
+    })
+}
+
+pub fn parse_raydium_pool(pool: Pubkey, data: &[u8]) -> Option<MonitoredPool> {
+    sol_pool(
+        pool,
+        PoolDex::Raydium,
+        (
+            read_pubkey(data, RAYDIUM_COIN_MINT_OFFSET)?,
+            read_pubkey(data, RAYDIUM_COIN_VAULT_OFFSET)?,
+        ),
+        (
+            read_pubkey(data, RAYDIUM_PC_MINT_OFFSET)?,
+            read_pubkey(data, RAYDIUM_PC_VAULT_OFFSET)?,
+        ),
+    )
+}
+
+pub fn parse_whirlpool(pool: Pubkey, data: &[u8]) -> Option<MonitoredPool> {
+    sol_pool(
+        pool,
+        PoolDex::Orca,
+        (
+            read_pubkey(data, WHIRLPOOL_MINT_A_OFFSET)?,
+            read_pubkey(data, WHIRLPOOL_VAULT_A_OFFSET)?,
+        ),
+        (
+            read_pubkey(data, WHIRLPOOL_MINT_B_OFFSET)?,
+            read_pubkey(data, WHIRLPOOL_VAULT_B_OFFSET)?,
+        ),
+    )
+}
+
+/// Publishes the TVL of the top pools whenever their SOL vault changes,
+/// including deposits and withdrawals that don't show up as swaps. The
+/// pools are the deepest Raydium or Orca SOL pool of each of the mints with
+/// the most volume over the last day, re-seeded daily.
+pub struct PoolMonitor {
+    db: Arc<ClickhouseDb>,
+    rpc_client: Arc<RpcClient>,
+    ws_url: String,
+    message_queue: Arc<RedisMessageQueue>,
+    top_pools: usize,
+}
+
+impl PoolMonitor {
+    pub fn new(
+        db: Arc<ClickhouseDb>,
+        rpc_client: Arc<RpcClient>,
+        ws_url: String,
+        message_queue: Arc<RedisMessageQueue>,
+    ) -> Self {
+        Self {
+            db,
+            rpc_client,
+            ws_url,
+            message_queue,
+            top_pools: DEFAULT_TOP_POOLS,
+        }
+    }
+
+    pub fn with_top_pools(mut self, top_pools: usize) -> Self {
+        self.top_pools = top_pools;
+        self
+    }
+
+    async fn find_pools(
+        &self,
+        program_id: &Pubkey,
+        size: u64,
+        mint_offset: usize,
+        mint: &Pubkey,
+        parse: fn(Pubkey, &[u8]) -> Option<MonitoredPool>,
+    ) -> Result<Vec<MonitoredPool>> {
+        let accounts = self
+            .rpc_client
+            .get_program_accounts_with_config(
+                program_id,
+                RpcProgramAccountsConfig {
+                    filters: Some(vec![
+                        RpcFilterType::DataSize(size),
+                        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                            mint_offset,
+                            mint.as_ref(),
+                        )),
+                    ]),
+                    account_config: RpcAccountInfoConfig {
+                        encoding: Some(UiAccountEncoding::Base64),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+            .await
+            .context("Failed to query pools")?;
+        Ok(accounts
+            .iter()
+            .filter_map(|(pool, account)| parse(*pool, &account.data))
+            .collect())
+    }
+
+    /// the SOL pool of `mint` with the most SOL in it
+    async fn find_deepest_pool(
+        &self,
+        mint: &Pubkey,
+    ) -> Result<Option<MonitoredPool>> {
+        let mut pools = Vec::new();
+        // the mint can be either side of the pair
+        for mint_offset in [RAYDIUM_COIN_MINT_OFFSET, RAYDIUM_PC_MINT_OFFSET] {
+            pools.extend(
+                self.find_pools(
+                    &RAYDIUM_AMM_V4_PROGRAM_ID,
+                    RAYDIUM_POOL_SIZE,
+                    mint_offset,
+                    mint,
+                    parse_raydium_pool,
+                )
+                .await?,
+            );
+        }
+        for mint_offset in [WHIRLPOOL_MINT_A_OFFSET, WHIRLPOOL_MINT_B_OFFSET] {
+            pools.extend(
+                self.find_pools(
+                    &WHIRLPOOLS_PROGRAM_ID,
+                    WHIRLPOOL_SIZE,
+                    mint_offset,
+                    mint,
+                    parse_whirlpool,
+                )
+                .await?,
+            );
+        }
+        if pools.is_empty() {
+            return Ok(None);
+        }
+
+        let mut deepest = None;
+        let mut max_lamports = 0;
+        for chunk in pools.chunks(100) {
+            let vaults: Vec<Pubkey> =
+                chunk.iter().map(|pool| pool.sol_vault).collect();
+            let accounts =
+                self.rpc_client.get_multiple_accounts(&vaults).await?;
+            for (pool, account) in chunk.iter().zip(accounts) {
+                let lamports = account.map_or(0, |account| account.lamports);
+                if lamports > max_lamports {
+                    max_lamports = lamports;
+                    deepest = Some(pool.clone());
+                }
+            }
+        }
+        Ok(deepest)
+    }
+
+    pub async fn seed(&self) -> Result<Vec<MonitoredPool>> {
+        let since =
+            chrono::Utc::now().timestamp() as u64 - VOLUME_LOOKBACK.as_secs();
+        let mints = self
+            .db
+            .get_top_mints_by_volume(since, self.top_pools)
+            .await?;
+
+        let mut pools = Vec::with_capacity(mints.len());
+        for mint in mints {
+            let Ok(mint) = mint.parse::<Pubkey>() else {
+                continue;
+            };
+            match self.find_deepest_pool(&mint).await {
+                Ok(Some(pool)) => pools.push(pool),
+                Ok(None) => warn!("no SOL pool found for {}", mint),
+                Err(e) => warn!("failed to find pools for {}: {}", mint, e),
+            }
+        }
+        info!("monitoring the TVL of {} pools", pools.len());
+        Ok(pools)
+    }
+
+    /// returns once any of the subscriptions ends
+    async fn monitor(&self, pools: &[MonitoredPool]) -> Result<()> {
+        let client = PubsubClient::new(&self.ws_url)
+            .await
+            .context("Failed to connect to the websocket")?;
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            ..Default::default()
+        };
+
+        let mut streams = Vec::with_capacity(pools.len());
+        for pool in pools {
+            let (stream, _unsubscribe) = client
+                .account_subscribe(&pool.sol_vault, Some(config.clone()))
+                .await
+                .with_context(|| {
+                    format!("Failed to subscribe to {}", pool.sol_vault)
+                })?;
+            streams.push(stream.map(move |response| (pool, response)));
+        }
+
+        let mut updates = futures_util::stream::select_all(streams);
+        while let Some((pool, response)) = updates.next().await {
+            let lamports = response.value.lamports;
+            let update = PoolTvlUpdate {
+                pool: pool.pool.to_string(),
+                dex: pool.dex,
+                mint: pool.mint.to_string(),
+                sol_vault: pool.sol_vault.to_string(),
+                lamports,
+                tvl: compute_tvl(lamports, get_sol_price().await),
+                slot: response.context.slot,
+                timestamp: chrono::Utc::now().timestamp() as u64,
+            };
+            if let Err(e) =
+                self.message_queue.publish_pool_tvl_update(update).await
+            {
+                error!("failed to publish pool TVL update: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn run(self) {
+        loop {
+            let pools = match self.seed().await {
+                Ok(pools) if !pools.is_empty() => pools,
+                Ok(_) => {
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    continue;
+                }
+                Err(e) => {
+                    error!("failed to seed monitored pools: {}", e);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    continue;
+                }
+            };
+
+            match tokio::time::timeout(REFRESH_INTERVAL, self.monitor(&pools))
+                .await
+            {
+                Err(_) => info!("refreshing monitored pools"),
+                Ok(Ok(())) => {
+                    warn!("pool subscriptions ended");
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+                Ok(Err(e)) => {
+                    error!("pool monitor failed: {}", e);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_tvl() {
+        assert_eq!(compute_tvl(2_500_000_000, 200.0), 500.0);
+        assert_eq!(compute_tvl(0, 200.0), 0.0);
+    }
+
+    fn write_pubkey(data: &mut [u8], offset: usize, pubkey: &Pubkey) {
+        data[offset..offset + 32].copy_from_slice(pubkey.as_ref());
+    }
+
+    #[test]
+    fn test_parse_raydium_pool() {
+        let (pool, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (coin_vault, pc_vault) =
+            (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = vec![0; RAYDIUM_POOL_SIZE as usize];
+        write_pubkey(&mut data, RAYDIUM_COIN_VAULT_OFFSET, &coin_vault);
+        write_pubkey(&mut data, RAYDIUM_PC_VAULT_OFFSET, &pc_vault);
+        write_pubkey(&mut data, RAYDIUM_COIN_MINT_OFFSET, &mint);
+        write_pubkey(&mut data, RAYDIUM_PC_MINT_OFFSET, &WSOL_MINT_KEY);
+
+        assert_eq!(
+            parse_raydium_pool(pool, &data),
+            Some(MonitoredPool {
+                pool,
+                dex: PoolDex::Raydium,
+                mint,
+                sol_vault: pc_vault,
+            })
+        );
+
+        // no SOL side
+        write_pubkey(&mut data, RAYDIUM_PC_MINT_OFFSET, &Pubkey::new_unique());
+        assert_eq!(parse_raydium_pool(pool, &data), None);
+    }
+
+    #[test]
+    fn test_parse_whirlpool() {
+        let (pool, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let vault_a = Pubkey::new_unique();
+        let mut data = vec![0; WHIRLPOOL_SIZE as usize];
+        write_pubkey(&mut data, WHIRLPOOL_MINT_A_OFFSET, &WSOL_MINT_KEY);
+        write_pubkey(&mut data, WHIRLPOOL_VAULT_A_OFFSET, &vault_a);
+        write_pubkey(&mut data, WHIRLPOOL_MINT_B_OFFSET, &mint);
+        write_pubkey(
+            &mut data,
+            WHIRLPOOL_VAULT_B_OFFSET,
+            &Pubkey::new_unique(),
+        );
+
+        let pool = parse_whirlpool(pool, &data).unwrap();
+        assert_eq!(pool.dex, PoolDex::Orca);
+        assert_eq!(pool.mint, mint);
+        assert_eq!(pool.sol_vault, vault_a);
+    }
+}
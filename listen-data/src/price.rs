@@ -1,5 +1,9 @@
 use clickhouse::Row;
 use serde::{Deserialize, Serialize};
+use solana_sdk::commitment_config::CommitmentLevel;
+
+use crate::lifecycle::LifecycleStage;
+use crate::trader_type::TraderType;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Price {
@@ -10,6 +14,14 @@ pub struct Price {
     pub pc_decimals: u64,
 }
 
+/// `price_updates` schema version new rows are written with, the target
+/// version of the last entry in `db::MIGRATIONS`
+pub const PRICE_UPDATE_SCHEMA_VERSION: u8 = 3;
+
+fn default_schema_version() -> u8 {
+    PRICE_UPDATE_SCHEMA_VERSION
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Row)]
 pub struct PriceUpdate {
     pub name: String,
@@ -24,4 +36,55 @@ pub struct PriceUpdate {
     pub multi_hop: bool,
     pub is_buy: bool,
     pub is_pump: bool,
+    #[serde(default)]
+    pub trader_type: TraderType,
+    #[serde(default)]
+    pub lifecycle_stage: LifecycleStage,
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u8,
+    /// rolling VWAP or median of the mint, only set when the indexer runs
+    /// with `PRICE_SMOOTHING_WINDOW_SECS`, see `price_smoothing`
+    #[serde(default)]
+    pub smoothed_price: Option<f64>,
+    /// whether the swap was seen at finalized commitment; otherwise the
+    /// update is provisional and its slot may still be rolled back
+    #[serde(default)]
+    pub finalized: bool,
+}
+
+/// only a finalized slot can't be rolled back
+pub fn is_finalized(commitment: CommitmentLevel) -> bool {
+    commitment == CommitmentLevel::Finalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finalized_reflects_commitment() {
+        assert!(!is_finalized(CommitmentLevel::Processed));
+        assert!(!is_finalized(CommitmentLevel::Confirmed));
+        assert!(is_finalized(CommitmentLevel::Finalized));
+    }
+
+    #[test]
+    fn test_updates_without_the_flag_are_provisional() {
+        let update: PriceUpdate = serde_json::from_value(serde_json::json!({
+            "name": "BONK",
+            "pubkey": "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263",
+            "price": 0.00002,
+            "market_cap": 1.0,
+            "timestamp": 1,
+            "slot": 1,
+            "swap_amount": 1.0,
+            "owner": "owner",
+            "signature": "signature",
+            "multi_hop": false,
+            "is_buy": true,
+            "is_pump": false,
+        }))
+        .unwrap();
+        assert!(!update.finalized);
+    }
 }
@@ -0,0 +1,333 @@
+use std::sync::Arc;
+
+use actix_web::{get, web, HttpResponse, Responder};
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{error, warn};
+
+use crate::db::ClickhouseDb;
+use crate::kv_store::RedisKVStore;
+
+pub const PRICE_HISTORY_CACHE_TTL_SECS: u64 = 30;
+pub const DEFAULT_PRICE_HISTORY_RANGE_SECS: u64 = 24 * 60 * 60;
+pub const DEFAULT_PRICE_HISTORY_LIMIT: usize = 1000;
+pub const MAX_PRICE_HISTORY_LIMIT: usize = 10_000;
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum PriceHistoryInterval {
+    /// every swap
+    #[default]
+    #[serde(rename = "raw")]
+    Raw,
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+impl PriceHistoryInterval {
+    /// candle length, `None` for raw swaps
+    pub fn secs(&self) -> Option<u64> {
+        match self {
+            Self::Raw => None,
+            Self::OneMinute => Some(60),
+            Self::FiveMinutes => Some(5 * 60),
+            Self::OneHour => Some(60 * 60),
+            Self::OneDay => Some(24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PriceHistoryQuery {
+    /// unix seconds, defaults to a day before `to`
+    pub from: Option<u64>,
+    /// unix seconds, defaults to now
+    pub to: Option<u64>,
+    #[serde(default)]
+    pub interval: PriceHistoryInterval,
+    pub limit: Option<usize>,
+}
+
+impl PriceHistoryQuery {
+    /// the time range and row limit to query
+    pub fn resolve(&self, now: u64) -> Result<(u64, u64, usize), String> {
+        let to = self.to.unwrap_or(now);
+        let from = self
+            .from
+            .unwrap_or(to.saturating_sub(DEFAULT_PRICE_HISTORY_RANGE_SECS));
+        if from > to {
+            return Err("from must not be after to".to_string());
+        }
+        let limit = self.limit.unwrap_or(DEFAULT_PRICE_HISTORY_LIMIT);
+        if limit == 0 {
+            return Err("limit must be positive".to_string());
+        }
+        Ok((from, to, limit.min(MAX_PRICE_HISTORY_LIMIT)))
+    }
+
+    /// unset bounds stay unset so that "latest" requests share an entry
+    pub fn cache_key(&self, mint: &str) -> String {
+        let bound =
+            |b: Option<u64>| b.map_or("-".to_string(), |b| b.to_string());
+        format!(
+            "price_history:{}:{}:{}:{}:{}",
+            mint,
+            bound(self.from),
+            bound(self.to),
+            self.interval.secs().unwrap_or(0),
+            self.limit.unwrap_or(DEFAULT_PRICE_HISTORY_LIMIT)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Row, Serialize, Deserialize)]
+pub struct PricePoint {
+    pub timestamp: u64,
+    pub price: f64,
+    pub market_cap: f64,
+    pub swap_amount: f64, // denoted as usd
+    pub is_buy: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Row, Serialize, Deserialize)]
+pub struct Candle {
+    /// start of the interval
+    pub timestamp: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// usd
+    pub volume: f64,
+    pub market_cap: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PriceStats {
+    pub min_price: f64,
+    pub max_price: f64,
+    pub avg_price: f64,
+    pub total_volume_usd: f64,
+    /// buy over sell volume, `None` without sells
+    pub buy_sell_ratio: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceHistory {
+    pub mint: String,
+    pub from: u64,
+    pub to: u64,
+    pub interval: PriceHistoryInterval,
+    /// swaps for the `raw` interval, oldest first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub points: Option<Vec<PricePoint>>,
+    /// OHLCV for the other intervals, oldest first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candles: Option<Vec<Candle>>,
+    /// over the whole range, regardless of `limit`
+    pub stats: PriceStats,
+}
+
+pub struct PriceHistoryState {
+    pub db: Arc<ClickhouseDb>,
+    /// responses are served uncached without redis
+    pub kv_store: Option<Arc<RedisKVStore>>,
+}
+
+async fn get_price_history(
+    db: &ClickhouseDb,
+    mint: &str,
+    query: &PriceHistoryQuery,
+    (from, to, limit): (u64, u64, usize),
+) -> anyhow::Result<PriceHistory> {
+    let (points, candles) = match query.interval.secs() {
+        None => (
+            Some(db.get_price_points(mint, from, to, limit).await?),
+            None,
+        ),
+        Some(interval_secs) => (
+            None,
+            Some(db.get_candles(mint, from, to, interval_secs, limit).await?),
+        ),
+    };
+    Ok(PriceHistory {
+        mint: mint.to_string(),
+        from,
+        to,
+        interval: query.interval,
+        points,
+        candles,
+        stats: db.get_price_stats(mint, from, to).await?,
+    })
+}
+
+#[get("/tokens/{mint}/price-history")]
+async fn price_history(
+    mint: web::Path<String>,
+    query: web::Query<PriceHistoryQuery>,
+    state: web::Data<PriceHistoryState>,
+) -> impl Responder {
+    let now = chrono::Utc::now().timestamp() as u64;
+    let range = match query.resolve(now) {
+        Ok(range) => range,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(json!({ "error": e }))
+        }
+    };
+
+    let cache_key = query.cache_key(&mint);
+    if let Some(kv_store) = &state.kv_store {
+        match kv_store.get::<PriceHistory>(&cache_key).await {
+            Ok(Some(history)) => return HttpResponse::Ok().json(history),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to read cached price history: {}", e),
+        }
+    }
+
+    match get_price_history(&state.db, &mint, &query, range).await {
+        Ok(history) => {
+            if let Some(kv_store) = &state.kv_store {
+                if let Err(e) = kv_store
+                    .set_ex(&cache_key, &history, PRICE_HISTORY_CACHE_TTL_SECS)
+                    .await
+                {
+                    warn!("Failed to cache price history: {}", e);
+                }
+            }
+            HttpResponse::Ok().json(history)
+        }
+        Err(e) => {
+            error!("Failed to query price history: {}", e);
+            HttpResponse::InternalServerError()
+                .json(json!({ "error": e.to_string() }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use crate::price::{PriceUpdate, PRICE_UPDATE_SCHEMA_VERSION};
+    use crate::util::make_db;
+    use actix_web::{middleware::Compress, test, App};
+
+    fn query(
+        from: Option<u64>,
+        to: Option<u64>,
+        limit: Option<usize>,
+    ) -> PriceHistoryQuery {
+        PriceHistoryQuery {
+            from,
+            to,
+            interval: PriceHistoryInterval::Raw,
+            limit,
+        }
+    }
+
+    #[test]
+    fn test_resolve_query() {
+        let now = 1_700_000_000;
+        assert_eq!(
+            query(None, None, None).resolve(now).unwrap(),
+            (
+                now - DEFAULT_PRICE_HISTORY_RANGE_SECS,
+                now,
+                DEFAULT_PRICE_HISTORY_LIMIT
+            )
+        );
+        assert_eq!(
+            query(Some(10), Some(20), Some(1_000_000))
+                .resolve(now)
+                .unwrap(),
+            (10, 20, MAX_PRICE_HISTORY_LIMIT)
+        );
+        assert!(query(Some(20), Some(10), None).resolve(now).is_err());
+        assert!(query(None, None, Some(0)).resolve(now).is_err());
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        let query: PriceHistoryQuery =
+            serde_json::from_str(r#"{"interval": "5m"}"#).unwrap();
+        assert_eq!(query.interval.secs(), Some(300));
+        assert_eq!(query.cache_key("mint"), "price_history:mint:-:-:300:1000");
+        assert!(serde_json::from_str::<PriceHistoryQuery>(
+            r#"{"interval": "2m"}"#
+        )
+        .is_err());
+    }
+
+    fn make_price_update(mint: &str, i: u64, is_buy: bool) -> PriceUpdate {
+        PriceUpdate {
+            name: "price-history-test".to_string(),
+            pubkey: mint.to_string(),
+            price: 1.0 + i as f64,
+            market_cap: 1000.0,
+            timestamp: 1_700_000_000 + i * 30,
+            slot: i,
+            swap_amount: 10.0,
+            owner: "price-history-test".to_string(),
+            signature: format!("price-history-test-{}", i),
+            multi_hop: false,
+            is_buy,
+            is_pump: false,
+            trader_type: Default::default(),
+            lifecycle_stage: Default::default(),
+            schema_version: PRICE_UPDATE_SCHEMA_VERSION,
+            smoothed_price: None,
+            finalized: false,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_price_history() {
+        let db = make_db().await.unwrap();
+        let mint = format!("price-history-test-{}", chrono::Utc::now());
+        for i in 0..4 {
+            db.insert_price(&make_price_update(&mint, i, i % 2 == 0))
+                .await
+                .unwrap();
+        }
+        db.flush().await.unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .wrap(Compress::default())
+                .app_data(web::Data::new(PriceHistoryState {
+                    db,
+                    kv_store: None,
+                }))
+                .service(price_history),
+        )
+        .await;
+
+        let uri = format!(
+            "/tokens/{}/price-history?from=1700000000&to=1700000200",
+            mint
+        );
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let history: PriceHistory =
+            test::call_and_read_body_json(&app, req).await;
+        assert_eq!(history.points.unwrap().len(), 4);
+        assert_eq!(history.stats.min_price, 1.0);
+        assert_eq!(history.stats.max_price, 4.0);
+        assert_eq!(history.stats.total_volume_usd, 40.0);
+        assert_eq!(history.stats.buy_sell_ratio, Some(1.0));
+
+        let req = test::TestRequest::get()
+            .uri(&format!("{}&interval=1m", uri))
+            .insert_header(("accept-encoding", "gzip"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.headers()["content-encoding"], "gzip");
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use tracing::{info, warn};
+
+/// mints with a window, pruned of the quiet ones once it grows past this
+const MAX_TRACKED_MINTS: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmoothingMode {
+    /// weighted by the usd amount of each swap
+    Vwap,
+    Median,
+}
+
+impl std::str::FromStr for SmoothingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vwap" => Ok(Self::Vwap),
+            "median" => Ok(Self::Median),
+            _ => Err(anyhow::anyhow!("unknown smoothing mode: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmoothingConfig {
+    pub window_secs: u64,
+    pub mode: SmoothingMode,
+}
+
+impl SmoothingConfig {
+    /// `PRICE_SMOOTHING_WINDOW_SECS` enables smoothing, with
+    /// `PRICE_SMOOTHING_MODE` (vwap or median, vwap by default)
+    pub fn from_env() -> Option<Self> {
+        let window_secs = std::env::var("PRICE_SMOOTHING_WINDOW_SECS")
+            .ok()?
+            .parse::<u64>()
+            .ok()
+            .filter(|secs| *secs > 0)?;
+        let mode = match std::env::var("PRICE_SMOOTHING_MODE") {
+            Ok(mode) => mode.parse().unwrap_or_else(|e| {
+                warn!("{}, smoothing with vwap", e);
+                SmoothingMode::Vwap
+            }),
+            Err(_) => SmoothingMode::Vwap,
+        };
+        Some(Self { window_secs, mode })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WindowSwap {
+    price: f64,
+    swap_amount: f64,
+    timestamp: u64,
+}
+
+/// The swaps of the last `window_secs` per mint
+#[derive(Debug)]
+pub struct PriceSmoother {
+    config: SmoothingConfig,
+    windows: HashMap<String, VecDeque<WindowSwap>>,
+}
+
+impl PriceSmoother {
+    pub fn new(config: SmoothingConfig) -> Self {
+        Self {
+            config,
+            windows: HashMap::new(),
+        }
+    }
+
+    /// adds the swap to the window of `mint` and returns the smoothed price
+    /// over the window, the swap included
+    pub fn record(
+        &mut self,
+        mint: &str,
+        price: f64,
+        swap_amount: f64,
+        timestamp: u64,
+    ) -> f64 {
+        let window_secs = self.config.window_secs;
+        if self.windows.len() >= MAX_TRACKED_MINTS {
+            self.windows.retain(|_, window| {
+                window.back().is_some_and(|swap| {
+                    swap.timestamp + window_secs > timestamp
+                })
+            });
+        }
+        let window = self.windows.entry(mint.to_string()).or_default();
+        window.push_back(WindowSwap {
+            price,
+            swap_amount,
+            timestamp,
+        });
+        while window
+            .front()
+            .is_some_and(|swap| swap.timestamp + window_secs <= timestamp)
+        {
+            window.pop_front();
+        }
+
+        match self.config.mode {
+            SmoothingMode::Vwap => vwap(window),
+            SmoothingMode::Median => median(window),
+        }
+    }
+}
+
+/// falls back to the plain mean when the window has no volume
+fn vwap(window: &VecDeque<WindowSwap>) -> f64 {
+    let volume: f64 = window.iter().map(|swap| swap.swap_amount).sum();
+    if volume > 0. {
+        window
+            .iter()
+            .map(|swap| swap.price * swap.swap_amount)
+            .sum::<f64>()
+            / volume
+    } else {
+        window.iter().map(|swap| swap.price).sum::<f64>() / window.len() as f64
+    }
+}
+
+fn median(window: &VecDeque<WindowSwap>) -> f64 {
+    let mut prices: Vec<f64> = window.iter().map(|swap| swap.price).collect();
+    prices.sort_by(|a, b| a.total_cmp(b));
+    let mid = prices.len() / 2;
+    if prices.len() % 2 == 0 {
+        (prices[mid - 1] + prices[mid]) / 2.
+    } else {
+        prices[mid]
+    }
+}
+
+static PRICE_SMOOTHER: Lazy<Option<Mutex<PriceSmoother>>> = Lazy::new(|| {
+    let config = SmoothingConfig::from_env()?;
+    info!(
+        "smoothing prices with a {}s {:?} window",
+        config.window_secs, config.mode
+    );
+    Some(Mutex::new(PriceSmoother::new(config)))
+});
+
+/// the smoothed price of `mint` with this swap in the window, none unless
+/// smoothing is enabled
+pub fn smoothed_price(
+    mint: &str,
+    price: f64,
+    swap_amount: f64,
+    timestamp: u64,
+) -> Option<f64> {
+    let smoother = PRICE_SMOOTHER.as_ref()?;
+    Some(
+        smoother
+            .lock()
+            .unwrap()
+            .record(mint, price, swap_amount, timestamp),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn smoother(mode: SmoothingMode) -> PriceSmoother {
+        PriceSmoother::new(SmoothingConfig {
+            window_secs: 60,
+            mode,
+        })
+    }
+
+    #[test]
+    fn test_vwap_over_window() {
+        let mut smoother = smoother(SmoothingMode::Vwap);
+        assert_eq!(smoother.record("mint", 1.0, 100.0, 1_000), 1.0);
+        smoother.record("mint", 2.0, 300.0, 1_010);
+        // a thin outlier barely moves the smoothed price
+        let smoothed = smoother.record("mint", 10.0, 1.0, 1_020);
+        let expected = (1.0 * 100.0 + 2.0 * 300.0 + 10.0 * 1.0) / 401.0;
+        assert!((smoothed - expected).abs() < 1e-12);
+
+        // the first swap has left the window
+        let smoothed = smoother.record("mint", 2.0, 100.0, 1_060);
+        let expected = (2.0 * 300.0 + 10.0 * 1.0 + 2.0 * 100.0) / 401.0;
+        assert!((smoothed - expected).abs() < 1e-12);
+
+        // windows are per mint
+        assert_eq!(smoother.record("other", 5.0, 10.0, 1_060), 5.0);
+    }
+
+    #[test]
+    fn test_median_over_window() {
+        let mut smoother = smoother(SmoothingMode::Median);
+        smoother.record("mint", 1.0, 100.0, 1_000);
+        smoother.record("mint", 10.0, 1.0, 1_001);
+        assert_eq!(smoother.record("mint", 2.0, 300.0, 1_002), 2.0);
+        assert_eq!(smoother.record("mint", 3.0, 300.0, 1_003), 2.5);
+    }
+}
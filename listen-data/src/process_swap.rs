@@ -2,41 +2,124 @@ use std::sync::Arc;
 
 use crate::constants::WSOL_MINT_KEY_STR;
 use crate::diffs::{
-    get_token_balance_diff, process_diffs, Diff, DiffsError, DiffsResult,
+    detect_dex, get_dex_token_balance_diff, process_diffs, summarize_diffs,
+    Dex, Diff, DiffsError, DiffsResult,
 };
 use crate::{
-    db::{ClickhouseDb, Database},
-    kv_store::RedisKVStore,
-    message_queue::{MessageQueue, RedisMessageQueue},
+    aggregated_price::{
+        market_cap_price, DexQuote, QuoteSide, METEORA_DLMM_DEX,
+        OPENBOOK_V2_DEX, PHOENIX_DEX, RAYDIUM_DEX,
+    },
+    db::Database,
+    kv_store::KVStore,
+    lifecycle::cached_lifecycle_stage,
+    message_queue::MessageQueue,
     metadata::get_token_metadata,
     metrics::SwapMetrics,
-    price::PriceUpdate,
+    new_pool::initializes_pool,
+    notifications::{
+        detect_events, is_discord_enabled, is_telegram_enabled, notify_discord,
+        notify_telegram,
+    },
+    openbook::process_openbook_swap,
+    phoenix::process_phoenix_swap,
+    price::{is_finalized, PriceUpdate, PRICE_UPDATE_SCHEMA_VERSION},
+    price_smoothing::smoothed_price,
+    smart_money::{is_smart_money, SmartMoneyActivity},
     sol_price_stream::get_sol_price,
+    trader_type::classify_trader,
 };
 use anyhow::{Context, Result};
 use carbon_core::transaction::TransactionMetadata;
 use chrono::Utc;
+use solana_sdk::commitment_config::CommitmentLevel;
 use tracing::{debug, warn};
 
-pub async fn process_swap(
+pub async fn process_swap<Q: MessageQueue, K: KVStore, D: Database>(
     transaction_metadata: &TransactionMetadata,
-    message_queue: &RedisMessageQueue,
-    kv_store: &Arc<RedisKVStore>,
-    db: &Arc<ClickhouseDb>,
+    message_queue: &Q,
+    kv_store: &Arc<K>,
+    db: &Arc<D>,
     metrics: &SwapMetrics,
+    commitment: CommitmentLevel,
 ) -> Result<()> {
-    let diffs = get_token_balance_diff(
-        transaction_metadata
-            .meta
-            .pre_token_balances
-            .as_ref()
-            .unwrap(),
-        transaction_metadata
-            .meta
-            .post_token_balances
-            .as_ref()
-            .unwrap(),
-    );
+    // the first deposit isn't a trade, pricing starts at the first swap
+    if initializes_pool(
+        &transaction_metadata.message,
+        &transaction_metadata.meta,
+    ) {
+        debug!(
+            "https://solscan.io/tx/{} skipping pool initialization",
+            transaction_metadata.signature
+        );
+        return Ok(());
+    }
+
+    // programs reached through cpi can come from lookup tables
+    let mut account_keys =
+        transaction_metadata.message.static_account_keys().to_vec();
+    account_keys.extend(&transaction_metadata.meta.loaded_addresses.writable);
+    account_keys.extend(&transaction_metadata.meta.loaded_addresses.readonly);
+    let Some(dex) = detect_dex(&account_keys) else {
+        debug!(
+            "https://solscan.io/tx/{} skipping swap on unsupported dex or across dexes",
+            transaction_metadata.signature
+        );
+        return Ok(());
+    };
+
+    let pre_balances = transaction_metadata
+        .meta
+        .pre_token_balances
+        .as_ref()
+        .unwrap();
+    let post_balances = transaction_metadata
+        .meta
+        .post_token_balances
+        .as_ref()
+        .unwrap();
+    let diffs = match dex {
+        Dex::OpenBookV2 => {
+            let log_messages = transaction_metadata
+                .meta
+                .log_messages
+                .as_deref()
+                .unwrap_or_default();
+            match process_openbook_swap(
+                log_messages,
+                pre_balances,
+                post_balances,
+            )
+            .await
+            .context("failed to price openbook fills")?
+            {
+                Some(diffs) => diffs,
+                None => {
+                    debug!(
+                        "https://solscan.io/tx/{} skipping openbook transaction without a single market fill",
+                        transaction_metadata.signature
+                    );
+                    return Ok(());
+                }
+            }
+        }
+        Dex::Phoenix => {
+            match process_phoenix_swap(transaction_metadata)
+                .await
+                .context("failed to price phoenix fills")?
+            {
+                Some(diffs) => diffs,
+                None => {
+                    debug!(
+                        "https://solscan.io/tx/{} skipping phoenix transaction without a single market fill",
+                        transaction_metadata.signature
+                    );
+                    return Ok(());
+                }
+            }
+        }
+        _ => get_dex_token_balance_diff(dex, pre_balances, post_balances),
+    };
 
     if diffs.iter().all(|d| d.diff.abs() < 0.01) {
         debug!("skipping tiny diffs");
@@ -54,8 +137,8 @@ pub async fn process_swap(
 
     if diffs.len() > 3 || diffs.len() < 2 {
         debug!(
-            "https://solscan.io/tx/{} skipping swap with unexpected number of tokens: {}",
-            transaction_metadata.signature, diffs.len()
+            "https://solscan.io/tx/{} skipping swap with unexpected number of tokens: {} ({})",
+            transaction_metadata.signature, diffs.len(), summarize_diffs(&diffs)
         );
         metrics.increment_skipped_unexpected_number_of_tokens();
         return Ok(());
@@ -140,6 +223,7 @@ pub async fn process_swap(
             // Process first hop: token being sold to SOL
             process_two_token_swap(
                 &[neg.clone(), sol.clone()],
+                dex,
                 transaction_metadata,
                 message_queue,
                 kv_store,
@@ -154,6 +238,7 @@ pub async fn process_swap(
             // Process second hop: SOL to token being bought
             process_two_token_swap(
                 &[pos.clone(), sol.clone()],
+                dex,
                 transaction_metadata,
                 message_queue,
                 kv_store,
@@ -171,6 +256,7 @@ pub async fn process_swap(
 
     process_two_token_swap(
         &diffs,
+        dex,
         transaction_metadata,
         message_queue,
         kv_store,
@@ -185,12 +271,13 @@ pub async fn process_swap(
 
 // Helper function to process a single two-token swap
 #[allow(clippy::too_many_arguments)]
-async fn process_two_token_swap(
+async fn process_two_token_swap<Q: MessageQueue, K: KVStore, D: Database>(
     diffs: &[Diff],
+    dex: Dex,
     transaction_metadata: &TransactionMetadata,
-    message_queue: &RedisMessageQueue,
-    kv_store: &Arc<RedisKVStore>,
-    db: &Arc<ClickhouseDb>,
+    message_queue: &Q,
+    kv_store: &Arc<K>,
+    db: &Arc<D>,
     metrics: &SwapMetrics,
     sol_price: f64,
     multi_hop: bool,
@@ -198,6 +285,7 @@ async fn process_two_token_swap(
     let DiffsResult {
         price,
         swap_amount,
+        sol_amount,
         coin_mint,
         is_buy,
     } = match process_diffs(diffs, sol_price) {
@@ -236,12 +324,39 @@ async fn process_two_token_swap(
         }
     };
 
+    // wash trades on a single dex shouldn't move the market cap while other
+    // dexes quote the token too
+    let timestamp = Utc::now().timestamp() as u64;
+    let quote = DexQuote {
+        dex: match dex {
+            Dex::RaydiumAmmV4 => RAYDIUM_DEX,
+            Dex::MeteoraDlmm => METEORA_DLMM_DEX,
+            Dex::Phoenix => PHOENIX_DEX,
+            Dex::OpenBookV2 => OPENBOOK_V2_DEX,
+        }
+        .to_string(),
+        side: QuoteSide::from_is_buy(is_buy),
+        price,
+        timestamp,
+    };
+    let market_cap_price =
+        match market_cap_price(kv_store.as_ref(), &coin_mint, quote).await {
+            Ok(market_cap_price) => market_cap_price,
+            Err(e) => {
+                warn!(
+                "https://solscan.io/tx/{} failed to aggregate dex prices: {}",
+                transaction_metadata.signature, e
+            );
+                price
+            }
+        };
+
     // Calculate market cap if we have the metadata
     let market_cap = {
         let supply = token_metadata.spl.supply as f64;
         let adjusted_supply =
             supply / (10_f64.powi(token_metadata.spl.decimals as i32));
-        price * adjusted_supply
+        market_cap_price * adjusted_supply
     };
 
     let is_pump = token_metadata
@@ -253,21 +368,52 @@ async fn process_two_token_swap(
             value.as_str().is_some_and(|s| s.contains("pump.fun"))
         });
 
+    let owner = transaction_metadata.fee_payer.to_string();
+    let trader_type =
+        classify_trader(&owner, sol_amount, transaction_metadata.slot);
+    let lifecycle_stage =
+        cached_lifecycle_stage(&coin_mint, kv_store, db).await;
+    let smoothed_price =
+        smoothed_price(&coin_mint, price, swap_amount, timestamp);
     let price_update = PriceUpdate {
         name: token_metadata.mpl.name,
         pubkey: coin_mint,
         price,
         market_cap,
-        timestamp: Utc::now().timestamp() as u64,
+        timestamp,
         slot: transaction_metadata.slot,
         swap_amount,
-        owner: transaction_metadata.fee_payer.to_string(),
+        owner,
         signature: transaction_metadata.signature.to_string(),
         multi_hop,
         is_buy,
         is_pump,
+        trader_type,
+        lifecycle_stage,
+        schema_version: PRICE_UPDATE_SCHEMA_VERSION,
+        smoothed_price,
+        finalized: is_finalized(commitment),
     };
 
+    // previous price has to be read before it gets overwritten below
+    if is_telegram_enabled() || is_discord_enabled() {
+        let previous = kv_store
+            .get_price(&price_update.pubkey)
+            .await
+            .ok()
+            .flatten();
+        for event in detect_events(previous.as_ref(), &price_update) {
+            tokio::spawn(async move {
+                if let Err(e) = notify_telegram(&event).await {
+                    warn!("failed to send telegram notification: {}", e);
+                }
+                if let Err(e) = notify_discord(&event).await {
+                    warn!("failed to send discord notification: {}", e);
+                }
+            });
+        }
+    }
+
     match db.insert_price(&price_update).await {
         Ok(_) => metrics.increment_db_insert_success(),
         Err(e) => {
@@ -287,6 +433,17 @@ async fn process_two_token_swap(
         }
     }
 
+    if is_smart_money(&price_update.owner).await {
+        if let Err(e) = message_queue
+            .publish_smart_money_activity(SmartMoneyActivity::from(
+                &price_update,
+            ))
+            .await
+        {
+            warn!("failed to publish smart money activity: {}", e);
+        }
+    }
+
     match kv_store.insert_price(&price_update).await {
         Ok(_) => metrics.increment_kv_insert_success(),
         Err(e) => {
@@ -301,11 +458,26 @@ async fn process_two_token_swap(
 #[cfg(test)]
 mod tests {
     use crate::{
-        diffs::Diff,
+        diffs::{get_token_balance_diff, Diff, TokenBalanceInfo},
+        metadata::{SplTokenMetadata, TokenMetadata},
+        sol_price_stream::with_sol_price,
+        testing::{MemoryDb, MemoryKVStore, MemoryMessageQueue},
         util::{make_rpc_client, round_to_decimals},
     };
 
     use super::*;
+    use crate::constants::{
+        METEORA_DLMM_PROGRAM_ID, METEORA_DLMM_PROGRAM_ID_STR,
+        PHOENIX_PROGRAM_ID, PHOENIX_PROGRAM_ID_STR, RAYDIUM_AMM_V4_PROGRAM_ID,
+        RAYDIUM_AUTHORITY_MINT_KEY_STR,
+    };
+    use solana_account_decoder::parse_token::UiTokenAmount;
+    use solana_sdk::message::{Message, VersionedMessage};
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::{Keypair, Signature, Signer};
+    use solana_transaction_status::{
+        TransactionStatusMeta, TransactionTokenBalance,
+    };
 
     #[tokio::test]
     async fn test_sol_for_token() {
@@ -363,6 +535,16 @@ mod tests {
             },
         ];
 
+        // the pool sold the token, the trader bought it
+        let summary = summarize_diffs(&diffs);
+        assert_eq!(
+            summary.sold_mint.as_deref(),
+            Some("CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon")
+        );
+        assert_eq!(round_to_decimals(summary.sold_amount, 4), 6822.4224);
+        assert_eq!(summary.bought_mint.as_deref(), Some(WSOL_MINT_KEY_STR));
+        assert_eq!(round_to_decimals(summary.sol_delta, 2), 0.05);
+
         let DiffsResult {
             price, swap_amount, ..
         } = process_diffs(&diffs, 202.12).unwrap();
@@ -375,6 +557,303 @@ mod tests {
         );
     }
 
+    fn token_balance(
+        mint: &str,
+        owner: &str,
+        ui_amount: f64,
+    ) -> TransactionTokenBalance {
+        TransactionTokenBalance {
+            account_index: 0,
+            mint: mint.to_string(),
+            ui_token_amount: UiTokenAmount {
+                ui_amount: Some(ui_amount),
+                decimals: 6,
+                amount: String::new(),
+                ui_amount_string: ui_amount.to_string(),
+            },
+            owner: owner.to_string(),
+            program_id: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"
+                .to_string(),
+        }
+    }
+
+    #[test]
+    fn test_meteora_dlmm_diffs() {
+        let coin = "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon";
+        // the pair holds its liquidity under more than one derived owner
+        let pda = |seed: &[u8]| {
+            Pubkey::find_program_address(&[seed], &METEORA_DLMM_PROGRAM_ID)
+                .0
+                .to_string()
+        };
+        let (pool_a, pool_b) = (pda(b"bin_array_a"), pda(b"bin_array_b"));
+        let trader = Keypair::new().pubkey().to_string();
+
+        // the trader buys 3000 of the coin for 0.5 SOL, filled from two bins
+        let pre = vec![
+            token_balance(coin, &pool_a, 10_000.0),
+            token_balance(coin, &pool_b, 20_000.0),
+            token_balance(WSOL_MINT_KEY_STR, &pool_a, 4.0),
+            token_balance(WSOL_MINT_KEY_STR, &pool_b, 6.0),
+            token_balance(coin, &trader, 0.0),
+            token_balance(WSOL_MINT_KEY_STR, &trader, 1.0),
+        ];
+        let post = vec![
+            token_balance(coin, &pool_a, 9_000.0),
+            token_balance(coin, &pool_b, 18_000.0),
+            token_balance(WSOL_MINT_KEY_STR, &pool_a, 4.2),
+            token_balance(WSOL_MINT_KEY_STR, &pool_b, 6.3),
+            token_balance(coin, &trader, 3_000.0),
+            token_balance(WSOL_MINT_KEY_STR, &trader, 0.5),
+        ];
+
+        let dex = detect_dex(&[Pubkey::new_unique(), METEORA_DLMM_PROGRAM_ID])
+            .unwrap();
+        assert_eq!(dex, Dex::MeteoraDlmm);
+        // a route through both is priced by neither, whichever of their
+        // processors gets it
+        assert_eq!(
+            detect_dex(&[METEORA_DLMM_PROGRAM_ID, RAYDIUM_AMM_V4_PROGRAM_ID]),
+            None
+        );
+        assert_eq!(
+            detect_dex(&[RAYDIUM_AMM_V4_PROGRAM_ID, PHOENIX_PROGRAM_ID]),
+            None
+        );
+        assert_eq!(detect_dex(&[Pubkey::new_unique()]), None);
+
+        // raydium normalization finds no pool side at all
+        assert!(get_dex_token_balance_diff(Dex::RaydiumAmmV4, &pre, &post)
+            .is_empty());
+
+        let diffs = get_dex_token_balance_diff(dex, &pre, &post);
+        assert_eq!(diffs.len(), 2);
+        assert!(diffs
+            .iter()
+            .all(|diff| diff.owner == METEORA_DLMM_PROGRAM_ID_STR));
+
+        let DiffsResult {
+            price,
+            sol_amount,
+            coin_mint,
+            is_buy,
+            ..
+        } = process_diffs(&diffs, 200.0).unwrap();
+        assert_eq!(coin_mint, coin);
+        assert!(is_buy);
+        assert_eq!(round_to_decimals(sol_amount, 4), 0.5);
+        assert_eq!(round_to_decimals(price, 6), 0.033333);
+    }
+
+    /// what `owner` holds of `mint` across its token accounts
+    fn owner_amount<T: TokenBalanceInfo>(
+        balances: &[T],
+        owner: &str,
+        mint: &str,
+    ) -> f64 {
+        balances
+            .iter()
+            .filter(|b| b.get_owner() == owner && b.get_mint() == mint)
+            .filter_map(|b| b.get_ui_amount())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn test_meteora_dlmm_latest_swaps() {
+        // the latest swaps of the program, the pool side has to mirror what
+        // the fee payer got or gave of the coin
+        let rpc_client = make_rpc_client().unwrap();
+        let statuses = rpc_client
+            .get_signatures_for_address(&METEORA_DLMM_PROGRAM_ID)
+            .await
+            .unwrap();
+        let mut checked = 0;
+        for status in statuses.iter().filter(|s| s.err.is_none()).take(100) {
+            let transaction = rpc_client
+                .get_transaction_with_config(
+                    &status.signature.parse().unwrap(),
+                    solana_client::rpc_config::RpcTransactionConfig {
+                        encoding: Some(solana_transaction_status::UiTransactionEncoding::Base64),
+                        max_supported_transaction_version: Some(0),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+            let account_keys = transaction
+                .transaction
+                .transaction
+                .decode()
+                .unwrap()
+                .message
+                .static_account_keys()
+                .to_vec();
+            if detect_dex(&account_keys) != Some(Dex::MeteoraDlmm) {
+                continue;
+            }
+            let meta = transaction.transaction.meta.unwrap();
+            let pre = meta.pre_token_balances.as_ref().unwrap();
+            let post = meta.post_token_balances.as_ref().unwrap();
+
+            let diffs = get_dex_token_balance_diff(Dex::MeteoraDlmm, pre, post);
+            // routes through other pools and pairs without SOL
+            let Ok(DiffsResult {
+                coin_mint, is_buy, ..
+            }) = process_diffs(&diffs, 200.0)
+            else {
+                continue;
+            };
+            let trader = account_keys[0].to_string();
+            let trader_change = owner_amount(post, &trader, &coin_mint)
+                - owner_amount(pre, &trader, &coin_mint);
+            // swapped on behalf of another wallet
+            if trader_change == 0.0 {
+                continue;
+            }
+            let pool_change = diffs
+                .iter()
+                .find(|diff| diff.mint == coin_mint)
+                .unwrap()
+                .diff;
+            assert_eq!(
+                is_buy,
+                trader_change > 0.0,
+                "https://solscan.io/tx/{}",
+                status.signature
+            );
+            assert!(
+                (trader_change + pool_change).abs() <= pool_change.abs() * 0.01,
+                "https://solscan.io/tx/{} pool {} trader {}",
+                status.signature,
+                pool_change,
+                trader_change
+            );
+            checked += 1;
+        }
+        assert!(checked > 0, "no plain DLMM swap among the latest");
+    }
+
+    #[test]
+    fn test_phoenix_diffs() {
+        let coin = "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon";
+        let vault = |mint: &str| {
+            Pubkey::find_program_address(
+                &[b"vault", mint.as_bytes()],
+                &PHOENIX_PROGRAM_ID,
+            )
+            .0
+            .to_string()
+        };
+        let (base_vault, quote_vault) = (vault(coin), vault(WSOL_MINT_KEY_STR));
+        let taker = Keypair::new().pubkey().to_string();
+
+        // the taker sells 1000 of the coin for 0.2 SOL into resting bids,
+        // the vaults show the opposite of the taker's change
+        let pre = vec![
+            token_balance(coin, &base_vault, 50_000.0),
+            token_balance(WSOL_MINT_KEY_STR, &quote_vault, 30.0),
+            token_balance(coin, &taker, 1_000.0),
+            token_balance(WSOL_MINT_KEY_STR, &taker, 0.0),
+        ];
+        let post = vec![
+            token_balance(coin, &base_vault, 51_000.0),
+            token_balance(WSOL_MINT_KEY_STR, &quote_vault, 29.8),
+            token_balance(coin, &taker, 0.0),
+            token_balance(WSOL_MINT_KEY_STR, &taker, 0.2),
+        ];
+
+        let dex =
+            detect_dex(&[Pubkey::new_unique(), PHOENIX_PROGRAM_ID]).unwrap();
+        assert_eq!(dex, Dex::Phoenix);
+
+        let diffs = get_dex_token_balance_diff(dex, &pre, &post);
+        assert_eq!(diffs.len(), 2);
+        assert!(diffs
+            .iter()
+            .all(|diff| diff.owner == PHOENIX_PROGRAM_ID_STR));
+
+        let DiffsResult {
+            price,
+            sol_amount,
+            coin_mint,
+            is_buy,
+            ..
+        } = process_diffs(&diffs, 200.0).unwrap();
+        assert_eq!(coin_mint, coin);
+        assert!(!is_buy);
+        assert_eq!(round_to_decimals(sol_amount, 4), 0.2);
+        assert_eq!(round_to_decimals(price, 4), 0.04);
+    }
+
+    #[tokio::test]
+    async fn test_process_swap_with_injected_sol_price() {
+        let coin = Keypair::new().pubkey().to_string();
+        let pool = RAYDIUM_AUTHORITY_MINT_KEY_STR;
+        // the trader buys 1000 of the coin for 2 SOL
+        let pre = vec![
+            token_balance(&coin, pool, 100_000.0),
+            token_balance(WSOL_MINT_KEY_STR, pool, 50.0),
+        ];
+        let post = vec![
+            token_balance(&coin, pool, 99_000.0),
+            token_balance(WSOL_MINT_KEY_STR, pool, 52.0),
+        ];
+        let fee_payer = Keypair::new().pubkey();
+        let transaction_metadata = TransactionMetadata {
+            slot: 1,
+            signature: Signature::new_unique(),
+            fee_payer,
+            meta: TransactionStatusMeta {
+                pre_token_balances: Some(pre),
+                post_token_balances: Some(post),
+                ..Default::default()
+            },
+            message: VersionedMessage::Legacy(Message {
+                account_keys: vec![fee_payer, RAYDIUM_AMM_V4_PROGRAM_ID],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let kv_store = Arc::new(MemoryKVStore::default());
+        let message_queue = MemoryMessageQueue::default();
+        let db = Arc::new(MemoryDb::default());
+        kv_store
+            .insert_metadata(&TokenMetadata {
+                mint: coin.clone(),
+                spl: SplTokenMetadata {
+                    supply: 1_000_000_000_000,
+                    decimals: 6,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        with_sol_price(
+            100.0,
+            process_swap(
+                &transaction_metadata,
+                &message_queue,
+                &kv_store,
+                &db,
+                &SwapMetrics::new(),
+                CommitmentLevel::Confirmed,
+            ),
+        )
+        .await
+        .unwrap();
+
+        let price_update = kv_store.get_price(&coin).await.unwrap().unwrap();
+        // 2 SOL for 1000 tokens at $100 a SOL
+        assert_eq!(round_to_decimals(price_update.price, 6), 0.2);
+        assert_eq!(round_to_decimals(price_update.swap_amount, 6), 200.0);
+        assert!(price_update.is_buy);
+        assert_eq!(message_queue.price_updates.lock().unwrap().len(), 1);
+        assert_eq!(db.prices.lock().unwrap().len(), 1);
+    }
+
     #[tokio::test]
     async fn test_by_signature() {
         let signature = "538voMuFQKp3oE6Tu598R8kJN12sum2cGMxZBxrV2Vuip1TL4qdWaXiJ8u3yRxgJy9SFX4faP2zC83oDX68D2wuW";
@@ -0,0 +1,230 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::{
+    parse_account_data::ParsedAccount, UiAccountData,
+};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_request::TokenAccountsFilter,
+};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{error, info};
+
+use crate::{
+    constants::TOKEN_2022_PROGRAM_ID,
+    db::ClickhouseDb,
+    kv_store::RedisKVStore,
+    message_queue::{MessageQueue, RedisMessageQueue},
+};
+
+pub const REFERRAL_FEES_CHANNEL: &str = "referral_fees";
+
+const REFERRAL_BALANCES_KEY: &str = "referral_fees:balances";
+const POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// A referral fee token account grew by `amount` (in ui units of `token`)
+#[derive(Debug, Clone, PartialEq, Row, Serialize, Deserialize)]
+pub struct FeeAccrued {
+    pub token: String,
+    pub amount: f64,
+    pub timestamp: u64,
+}
+
+/// balance of a referral fee token account, raw amounts
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeBalance {
+    pub mint: String,
+    pub amount: u64,
+    pub decimals: u8,
+}
+
+/// the increases since `previous`; a balance that went down was claimed and
+/// only becomes the baseline for the next poll
+pub fn accrued_fees(
+    previous: &HashMap<String, u64>,
+    balances: &[FeeBalance],
+    timestamp: u64,
+) -> Vec<FeeAccrued> {
+    balances
+        .iter()
+        .filter_map(|balance| {
+            let before = previous.get(&balance.mint).copied().unwrap_or(0);
+            let gained = balance.amount.checked_sub(before)?;
+            (gained > 0).then(|| FeeAccrued {
+                token: balance.mint.clone(),
+                amount: gained as f64 / 10f64.powi(balance.decimals as i32),
+                timestamp,
+            })
+        })
+        .collect()
+}
+
+fn parse_fee_balance(data: UiAccountData) -> Result<FeeBalance> {
+    let UiAccountData::Json(ParsedAccount { parsed, .. }) = data else {
+        return Err(anyhow!("referral token account is not json parsed"));
+    };
+    let info = &parsed["info"];
+    let mint = info["mint"]
+        .as_str()
+        .ok_or_else(|| anyhow!("referral token account without a mint"))?;
+    let amount = info["tokenAmount"]["amount"]
+        .as_str()
+        .ok_or_else(|| anyhow!("referral token account without an amount"))?
+        .parse()?;
+    let decimals = info["tokenAmount"]["decimals"]
+        .as_u64()
+        .ok_or_else(|| anyhow!("referral token account without decimals"))?
+        as u8;
+    Ok(FeeBalance {
+        mint: mint.to_string(),
+        amount,
+        decimals,
+    })
+}
+
+/// Polls the token accounts of the jupiter referral account and records
+/// every balance increase as an accrued fee
+pub struct ReferralRevenueTracker {
+    rpc_client: Arc<RpcClient>,
+    referral_account: Pubkey,
+    db: Arc<ClickhouseDb>,
+    message_queue: Arc<RedisMessageQueue>,
+    /// keeps the balances across restarts, fees accrued while down are
+    /// counted on the first poll then
+    kv_store: Option<Arc<RedisKVStore>>,
+    balances: HashMap<String, u64>,
+}
+
+impl ReferralRevenueTracker {
+    pub fn new(
+        rpc_client: Arc<RpcClient>,
+        referral_account: Pubkey,
+        db: Arc<ClickhouseDb>,
+        message_queue: Arc<RedisMessageQueue>,
+        kv_store: Option<Arc<RedisKVStore>>,
+    ) -> Self {
+        Self {
+            rpc_client,
+            referral_account,
+            db,
+            message_queue,
+            kv_store,
+            balances: HashMap::new(),
+        }
+    }
+
+    pub async fn get_fee_balances(&self) -> Result<Vec<FeeBalance>> {
+        let mut balances = Vec::new();
+        for program_id in [spl_token::ID, TOKEN_2022_PROGRAM_ID] {
+            let accounts = self
+                .rpc_client
+                .get_token_accounts_by_owner(
+                    &self.referral_account,
+                    TokenAccountsFilter::ProgramId(program_id),
+                )
+                .await?;
+            for account in accounts {
+                balances.push(parse_fee_balance(account.account.data)?);
+            }
+        }
+        Ok(balances)
+    }
+
+    /// returns the fees accrued since the last poll
+    pub async fn poll(&mut self) -> Result<Vec<FeeAccrued>> {
+        let balances = self.get_fee_balances().await?;
+        let timestamp = chrono::Utc::now().timestamp() as u64;
+        let fees = accrued_fees(&self.balances, &balances, timestamp);
+
+        for fee in &fees {
+            self.db.insert_fee_accrued(fee).await?;
+            if let Err(e) =
+                self.message_queue.publish_fee_accrued(fee.clone()).await
+            {
+                error!("failed to publish accrued referral fee: {}", e);
+            }
+        }
+
+        self.balances = balances
+            .into_iter()
+            .map(|balance| (balance.mint, balance.amount))
+            .collect();
+        if let Some(kv_store) = &self.kv_store {
+            kv_store.set(REFERRAL_BALANCES_KEY, &self.balances).await?;
+        }
+        Ok(fees)
+    }
+
+    pub async fn run(mut self) {
+        if let Some(kv_store) = &self.kv_store {
+            match kv_store
+                .get::<HashMap<String, u64>>(REFERRAL_BALANCES_KEY)
+                .await
+            {
+                Ok(balances) => self.balances = balances.unwrap_or_default(),
+                Err(e) => error!("failed to load referral balances: {}", e),
+            }
+        }
+        info!(
+            "tracking referral fees of {} across {} tokens",
+            self.referral_account,
+            self.balances.len()
+        );
+        loop {
+            match self.poll().await {
+                Ok(fees) if !fees.is_empty() => {
+                    info!("{} referral fees accrued", fees.len())
+                }
+                Ok(_) => {}
+                Err(e) => error!("failed to poll referral fees: {}", e),
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balance(mint: &str, amount: u64) -> FeeBalance {
+        FeeBalance {
+            mint: mint.to_string(),
+            amount,
+            decimals: 6,
+        }
+    }
+
+    #[test]
+    fn test_accrued_fees() {
+        let previous =
+            HashMap::from([("usdc".to_string(), 1_000_000), ("sol".into(), 5)]);
+        let fees = accrued_fees(
+            &previous,
+            &[
+                balance("usdc", 3_500_000),
+                // claimed since the last poll
+                balance("sol", 2),
+                balance("new", 250_000),
+            ],
+            42,
+        );
+        assert_eq!(
+            fees,
+            vec![
+                FeeAccrued {
+                    token: "usdc".to_string(),
+                    amount: 2.5,
+                    timestamp: 42,
+                },
+                FeeAccrued {
+                    token: "new".to_string(),
+                    amount: 0.25,
+                    timestamp: 42,
+                },
+            ]
+        );
+    }
+}
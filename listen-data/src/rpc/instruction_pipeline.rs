@@ -5,6 +5,7 @@ use carbon_raydium_amm_v4_decoder::RaydiumAmmV4Decoder;
 use carbon_rpc_transaction_crawler_datasource::{
     Filters, RpcTransactionCrawler,
 };
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
 use std::{sync::Arc, time::Duration};
 
 use crate::{
@@ -25,14 +26,19 @@ pub fn make_raydium_rpc_instruction_pipeline(
             500,
             Duration::from_secs(1),
             Filters::new(None, None, None),
-            None,
+            Some(CommitmentConfig::confirmed()),
             100,
         ))
         .metrics(Arc::new(LogMetrics::new()))
         .shutdown_strategy(ShutdownStrategy::Immediate)
         .instruction(
             RaydiumAmmV4Decoder,
-            RaydiumAmmV4InstructionProcessor::new(kv_store, message_queue, db),
+            RaydiumAmmV4InstructionProcessor::new(
+                kv_store,
+                message_queue,
+                db,
+                CommitmentLevel::Confirmed,
+            ),
         )
         .build()?;
 
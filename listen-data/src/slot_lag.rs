@@ -0,0 +1,273 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use tracing::{error, info, warn};
+
+use crate::message_queue::{MessageQueue, RedisMessageQueue};
+
+pub const SYSTEM_ALERTS_CHANNEL: &str = "system_alerts";
+
+/// slots the pipeline may trail the chain by before a lag alert goes out
+pub const LAG_ALERT_SLOTS: u64 = 5;
+/// slots behind the chain at which swaps are held back until the stream
+/// caught up, a backlog this deep would insert its price updates out of
+/// order with the fresh ones
+pub const CIRCUIT_BREAKER_SLOTS: u64 = 20;
+
+/// while the lag lasts, alerts go out at most this often
+const ALERT_INTERVAL: Duration = Duration::from_secs(60);
+/// wait before resubscribing after the slot subscription failed
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// the slots the instruction processor got to, shared with the monitor
+pub static SLOT_LAG: SlotLag = SlotLag::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitBreaker {
+    Unchanged,
+    Opened,
+    Closed,
+}
+
+/// how far the processed slots trail the tip of the chain
+#[derive(Debug, Default)]
+pub struct SlotLag {
+    processed_slot: AtomicU64,
+    paused: AtomicBool,
+}
+
+impl SlotLag {
+    pub const fn new() -> Self {
+        Self {
+            processed_slot: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+        }
+    }
+
+    pub fn record_processed(&self, slot: u64) {
+        self.processed_slot.fetch_max(slot, Ordering::Relaxed);
+    }
+
+    pub fn processed_slot(&self) -> u64 {
+        self.processed_slot.load(Ordering::Relaxed)
+    }
+
+    /// whether swaps are held back until the backlog clears
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Compares a new slot of the chain with the processed ones. The
+    /// breaker opens past `CIRCUIT_BREAKER_SLOTS` and closes once the lag
+    /// is back within `LAG_ALERT_SLOTS`. Nothing lags before the first
+    /// slot is processed
+    pub fn observe_tip(&self, slot: u64) -> (u64, CircuitBreaker) {
+        let processed = self.processed_slot();
+        let lag = if processed == 0 {
+            0
+        } else {
+            slot.saturating_sub(processed)
+        };
+
+        let breaker = if lag > CIRCUIT_BREAKER_SLOTS {
+            match self.paused.swap(true, Ordering::Relaxed) {
+                false => CircuitBreaker::Opened,
+                true => CircuitBreaker::Unchanged,
+            }
+        } else if lag <= LAG_ALERT_SLOTS
+            && self.paused.swap(false, Ordering::Relaxed)
+        {
+            CircuitBreaker::Closed
+        } else {
+            CircuitBreaker::Unchanged
+        };
+        (lag, breaker)
+    }
+}
+
+/// swaps held back while the breaker is open, replayed in slot order once
+/// it closes
+#[derive(Debug)]
+pub struct HeldBack<T> {
+    items: Vec<(u64, T)>,
+}
+
+impl<T> Default for HeldBack<T> {
+    fn default() -> Self {
+        Self { items: Vec::new() }
+    }
+}
+
+impl<T> HeldBack<T> {
+    pub fn push(&mut self, slot: u64, item: T) {
+        self.items.push((slot, item));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// oldest slot first, in the order they came in within a slot
+    pub fn drain_in_slot_order(&mut self) -> Vec<T> {
+        let mut items = std::mem::take(&mut self.items);
+        items.sort_by_key(|(slot, _)| *slot);
+        items.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlotLagAlert {
+    pub chain_slot: u64,
+    pub processed_slot: u64,
+    pub lag: u64,
+    /// swaps are held back by the circuit breaker
+    pub paused: bool,
+    pub timestamp: u64,
+}
+
+/// Probes the latency of the transaction stream with a slot subscription
+/// of its own, alerting on `SYSTEM_ALERTS_CHANNEL` and keeping the
+/// `slot_lag_gauge` metric as the pipeline falls behind
+pub struct SlotLagMonitor {
+    ws_url: String,
+    message_queue: Arc<RedisMessageQueue>,
+    last_alert: Option<Instant>,
+}
+
+impl SlotLagMonitor {
+    pub fn new(ws_url: String, message_queue: Arc<RedisMessageQueue>) -> Self {
+        Self {
+            ws_url,
+            message_queue,
+            last_alert: None,
+        }
+    }
+
+    /// alerts on every change of the breaker, otherwise at most once per
+    /// `ALERT_INTERVAL` while the lag is above `LAG_ALERT_SLOTS`
+    fn should_alert(&mut self, lag: u64, breaker: CircuitBreaker) -> bool {
+        let due = breaker != CircuitBreaker::Unchanged
+            || (lag > LAG_ALERT_SLOTS
+                && self
+                    .last_alert
+                    .map_or(true, |last| last.elapsed() >= ALERT_INTERVAL));
+        if due {
+            self.last_alert = Some(Instant::now());
+        }
+        due
+    }
+
+    /// returns once the subscription ends
+    async fn monitor(&mut self) -> Result<()> {
+        let client = PubsubClient::new(&self.ws_url)
+            .await
+            .context("Failed to connect to the websocket")?;
+        let (mut slots, _unsubscribe) = client
+            .slot_subscribe()
+            .await
+            .context("Failed to subscribe to slots")?;
+
+        while let Some(slot) = slots.next().await {
+            let (lag, breaker) = SLOT_LAG.observe_tip(slot.slot);
+            metrics::gauge!("slot_lag_gauge", lag as f64);
+            match breaker {
+                CircuitBreaker::Opened => warn!(
+                    lag,
+                    "pipeline is {} slots behind, holding back swaps", lag
+                ),
+                CircuitBreaker::Closed => {
+                    info!(lag, "pipeline caught up, resuming swaps")
+                }
+                CircuitBreaker::Unchanged => {}
+            }
+            if !self.should_alert(lag, breaker) {
+                continue;
+            }
+
+            let alert = SlotLagAlert {
+                chain_slot: slot.slot,
+                processed_slot: SLOT_LAG.processed_slot(),
+                lag,
+                paused: SLOT_LAG.is_paused(),
+                timestamp: chrono::Utc::now().timestamp() as u64,
+            };
+            if let Err(e) = self.message_queue.publish_system_alert(alert).await
+            {
+                error!("failed to publish slot lag alert: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn run(mut self) {
+        loop {
+            match self.monitor().await {
+                Ok(()) => warn!("slot subscription ended"),
+                Err(e) => error!("slot lag monitor failed: {}", e),
+            }
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let slot_lag = SlotLag::new();
+        // nothing processed yet
+        assert_eq!(slot_lag.observe_tip(1_000), (0, CircuitBreaker::Unchanged));
+
+        slot_lag.record_processed(1_000);
+        // slots of spawned swaps may come in out of order
+        slot_lag.record_processed(990);
+        assert_eq!(slot_lag.processed_slot(), 1_000);
+        assert_eq!(
+            slot_lag.observe_tip(1_010),
+            (10, CircuitBreaker::Unchanged)
+        );
+        assert!(!slot_lag.is_paused());
+
+        assert_eq!(slot_lag.observe_tip(1_021), (21, CircuitBreaker::Opened));
+        assert!(slot_lag.is_paused());
+        assert_eq!(
+            slot_lag.observe_tip(1_030),
+            (30, CircuitBreaker::Unchanged)
+        );
+
+        // a lag within the alert threshold is a cleared backlog
+        slot_lag.record_processed(1_020);
+        assert_eq!(
+            slot_lag.observe_tip(1_030),
+            (10, CircuitBreaker::Unchanged)
+        );
+        assert!(slot_lag.is_paused());
+        slot_lag.record_processed(1_026);
+        assert_eq!(slot_lag.observe_tip(1_031), (5, CircuitBreaker::Closed));
+        assert!(!slot_lag.is_paused());
+    }
+
+    #[test]
+    fn test_held_back_swaps_replay_in_slot_order() {
+        let mut held_back = HeldBack::default();
+        held_back.push(1_003, "c");
+        held_back.push(1_001, "a");
+        held_back.push(1_003, "d");
+        held_back.push(1_002, "b");
+        assert_eq!(held_back.len(), 4);
+
+        assert_eq!(held_back.drain_in_slot_order(), ["a", "b", "c", "d"]);
+        assert!(held_back.is_empty());
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+use crate::db::ClickhouseDb;
+use crate::kv_store::RedisKVStore;
+use crate::price::PriceUpdate;
+
+pub const SMART_MONEY_WALLETS_KEY: &str = "smart_money_wallets";
+pub const SMART_MONEY_EVENTS_STREAM: &str = "smart_money_events";
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// wallets currently in the smart money set, mirrors the redis set so that
+/// `process_swap` doesn't have to hit redis on every swap
+static SMART_MONEY_WALLETS: Lazy<RwLock<HashSet<String>>> =
+    Lazy::new(|| RwLock::new(HashSet::new()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmartMoneyAction {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmartMoneyActivity {
+    pub wallet: String,
+    pub mint: String,
+    pub action: SmartMoneyAction,
+    pub amount_usd: f64,
+    pub timestamp: u64,
+}
+
+impl From<&PriceUpdate> for SmartMoneyActivity {
+    fn from(update: &PriceUpdate) -> Self {
+        Self {
+            wallet: update.owner.clone(),
+            mint: update.pubkey.clone(),
+            action: if update.is_buy {
+                SmartMoneyAction::Buy
+            } else {
+                SmartMoneyAction::Sell
+            },
+            amount_usd: update.swap_amount,
+            timestamp: update.timestamp,
+        }
+    }
+}
+
+/// what makes a wallet "smart money"
+#[derive(Debug, Clone)]
+pub struct SmartMoneyCriteria {
+    pub lookback: Duration,
+    /// a closed position counts as profitable above this gain
+    pub min_gain: f64,
+    /// share of profitable closed positions
+    pub min_win_rate: f64,
+    pub min_trades: u64,
+}
+
+impl Default for SmartMoneyCriteria {
+    fn default() -> Self {
+        Self {
+            lookback: Duration::from_secs(30 * 24 * 60 * 60),
+            min_gain: 0.2,
+            min_win_rate: 0.8,
+            min_trades: 10,
+        }
+    }
+}
+
+pub async fn is_smart_money(wallet: &str) -> bool {
+    SMART_MONEY_WALLETS.read().await.contains(wallet)
+}
+
+/// Periodically recomputes the smart money wallets from the price history
+pub struct SmartMoneyTracker {
+    db: Arc<ClickhouseDb>,
+    kv_store: Arc<RedisKVStore>,
+    criteria: SmartMoneyCriteria,
+}
+
+impl SmartMoneyTracker {
+    pub fn new(db: Arc<ClickhouseDb>, kv_store: Arc<RedisKVStore>) -> Self {
+        Self {
+            db,
+            kv_store,
+            criteria: SmartMoneyCriteria::default(),
+        }
+    }
+
+    /// returns the number of smart money wallets
+    pub async fn refresh(&self) -> Result<usize> {
+        let wallets = self.db.get_smart_money_wallets(&self.criteria).await?;
+        self.kv_store
+            .replace_set(SMART_MONEY_WALLETS_KEY, &wallets)
+            .await?;
+        let count = wallets.len();
+        *SMART_MONEY_WALLETS.write().await = wallets.into_iter().collect();
+        info!("tracking {} smart money wallets", count);
+        Ok(count)
+    }
+
+    pub async fn run(self) {
+        loop {
+            if let Err(e) = self.refresh().await {
+                error!("failed to refresh smart money wallets: {}", e);
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::price::PRICE_UPDATE_SCHEMA_VERSION;
+    use crate::trader_type::TraderType;
+
+    #[test]
+    fn test_activity_from_price_update() {
+        let update = PriceUpdate {
+            name: "TOKEN".to_string(),
+            pubkey: "mint".to_string(),
+            price: 0.001,
+            market_cap: 1_000_000.0,
+            timestamp: 1_700_000_000,
+            slot: 1,
+            swap_amount: 2_500.0,
+            owner: "wallet".to_string(),
+            signature: "sig".to_string(),
+            multi_hop: false,
+            is_buy: false,
+            is_pump: true,
+            trader_type: TraderType::Unknown,
+            lifecycle_stage: Default::default(),
+            schema_version: PRICE_UPDATE_SCHEMA_VERSION,
+            smoothed_price: None,
+            finalized: false,
+        };
+        let activity = SmartMoneyActivity::from(&update);
+        assert_eq!(activity.action, SmartMoneyAction::Sell);
+        assert_eq!(activity.amount_usd, 2_500.0);
+        assert_eq!(serde_json::to_value(&activity).unwrap()["action"], "sell");
+    }
+
+    #[tokio::test]
+    async fn test_smart_money_wallets_query() {
+        let db = crate::util::make_db().await.unwrap();
+        db.get_smart_money_wallets(&SmartMoneyCriteria::default())
+            .await
+            .unwrap();
+    }
+}
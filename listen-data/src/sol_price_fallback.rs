@@ -0,0 +1,169 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+/// Pyth SOL/USD push oracle (`PriceUpdateV2`, shard 0)
+pub const PYTH_SOL_USD_ACCOUNT: &str =
+    "7UVimffxr9ow1uXYxsr4LHAcV58mLzhmwaeKvJ1pjLiE";
+
+/// Chainlink SOL/USD transmissions account of the OCR2 store program
+pub const CHAINLINK_SOL_USD_FEED: &str =
+    "CH31Xns5z3M1cTAbKW34jcxPPciazARpijcHj9rxtemt";
+
+/// anchor discriminator, write authority
+const PYTH_VERIFICATION_LEVEL_OFFSET: usize = 8 + 32;
+
+/// anchor discriminator and the fixed size transmissions header
+const CHAINLINK_HEADER_SIZE: usize = 8 + 192;
+const CHAINLINK_DECIMALS_OFFSET: usize = 8 + 130;
+const CHAINLINK_LIVE_LENGTH_OFFSET: usize = 8 + 140;
+const CHAINLINK_LIVE_CURSOR_OFFSET: usize = 8 + 144;
+/// slot, timestamp, padding, answer, padding
+const CHAINLINK_TRANSMISSION_SIZE: usize = 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SolPriceSource {
+    Binance,
+    Pyth,
+    Chainlink,
+    /// the last price the indexer published to redis
+    RedisCache,
+    /// nothing could be fetched, the price is 0
+    Unavailable,
+    /// set by the caller with `with_sol_price`
+    Override,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SolPriceResult {
+    pub price: f64,
+    pub source: SolPriceSource,
+    /// age of the price at the source
+    pub staleness_secs: u64,
+}
+
+fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N]> {
+    Ok(data
+        .get(offset..offset + N)
+        .ok_or_else(|| anyhow!("account data too short"))?
+        .try_into()?)
+}
+
+/// price and publish time (unix secs) of a pyth `PriceUpdateV2` account
+pub fn parse_pyth_price_update(data: &[u8]) -> Result<(f64, i64)> {
+    // borsh enum, `Partial { num_signatures: u8 }` or `Full`
+    let message_offset = match data.get(PYTH_VERIFICATION_LEVEL_OFFSET) {
+        Some(0) => PYTH_VERIFICATION_LEVEL_OFFSET + 2,
+        Some(1) => PYTH_VERIFICATION_LEVEL_OFFSET + 1,
+        _ => return Err(anyhow!("invalid pyth verification level")),
+    };
+    // feed id precedes the price
+    let offset = message_offset + 32;
+    let price = i64::from_le_bytes(read_bytes(data, offset)?);
+    // conf sits between price and exponent
+    let exponent = i32::from_le_bytes(read_bytes(data, offset + 16)?);
+    let publish_time = i64::from_le_bytes(read_bytes(data, offset + 20)?);
+    Ok((price as f64 * 10f64.powi(exponent), publish_time))
+}
+
+/// latest answer and its timestamp (unix secs) of a chainlink feed
+pub fn parse_chainlink_transmissions(data: &[u8]) -> Result<(f64, i64)> {
+    let decimals = read_bytes::<1>(data, CHAINLINK_DECIMALS_OFFSET)?[0];
+    let live_length =
+        u32::from_le_bytes(read_bytes(data, CHAINLINK_LIVE_LENGTH_OFFSET)?);
+    let live_cursor =
+        u32::from_le_bytes(read_bytes(data, CHAINLINK_LIVE_CURSOR_OFFSET)?);
+    if live_length == 0 {
+        return Err(anyhow!("chainlink feed has no transmissions"));
+    }
+    // the cursor points at the slot the next transmission is written to
+    let latest = (live_cursor + live_length - 1) % live_length;
+    let offset =
+        CHAINLINK_HEADER_SIZE + latest as usize * CHAINLINK_TRANSMISSION_SIZE;
+    let timestamp = u32::from_le_bytes(read_bytes(data, offset + 8)?);
+    let answer = i128::from_le_bytes(read_bytes(data, offset + 16)?);
+    if answer <= 0 {
+        return Err(anyhow!("chainlink feed has no answer"));
+    }
+    Ok((
+        answer as f64 / 10f64.powi(decimals as i32),
+        timestamp as i64,
+    ))
+}
+
+async fn read_feed(
+    rpc_client: &RpcClient,
+    account: &str,
+    parse: fn(&[u8]) -> Result<(f64, i64)>,
+) -> Result<(f64, i64)> {
+    let account = rpc_client.get_account(&Pubkey::from_str(account)?).await?;
+    parse(&account.data)
+}
+
+pub async fn fetch_pyth_price(rpc_client: &RpcClient) -> Result<(f64, i64)> {
+    read_feed(rpc_client, PYTH_SOL_USD_ACCOUNT, parse_pyth_price_update).await
+}
+
+pub async fn fetch_chainlink_price(
+    rpc_client: &RpcClient,
+) -> Result<(f64, i64)> {
+    read_feed(
+        rpc_client,
+        CHAINLINK_SOL_USD_FEED,
+        parse_chainlink_transmissions,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    #[test]
+    fn test_parse_pyth_price_update() {
+        let mut data = vec![0u8; 134];
+        // full verification
+        data[40] = 1;
+        write(&mut data, 73, &15_012_345_678i64.to_le_bytes());
+        write(&mut data, 89, &(-8i32).to_le_bytes());
+        write(&mut data, 93, &1_700_000_000i64.to_le_bytes());
+        let (price, publish_time) = parse_pyth_price_update(&data).unwrap();
+        assert!((price - 150.12345678).abs() < 1e-9);
+        assert_eq!(publish_time, 1_700_000_000);
+
+        // partial verification carries the signature count
+        let mut partial = vec![0u8, 0u8];
+        partial.splice(0..0, data[..40].iter().copied());
+        partial.extend_from_slice(&data[41..]);
+        assert_eq!(parse_pyth_price_update(&partial).unwrap().0, price);
+
+        data[40] = 7;
+        assert!(parse_pyth_price_update(&data).is_err());
+    }
+
+    #[test]
+    fn test_parse_chainlink_transmissions() {
+        let mut data = vec![0u8; CHAINLINK_HEADER_SIZE + 3 * 48];
+        data[CHAINLINK_DECIMALS_OFFSET] = 8;
+        write(&mut data, CHAINLINK_LIVE_LENGTH_OFFSET, &3u32.to_le_bytes());
+        // wrapped around, the latest transmission is the last one
+        write(&mut data, CHAINLINK_LIVE_CURSOR_OFFSET, &0u32.to_le_bytes());
+        let latest = CHAINLINK_HEADER_SIZE + 2 * 48;
+        write(&mut data, latest + 8, &1_700_000_000u32.to_le_bytes());
+        write(&mut data, latest + 16, &14_950_000_000i128.to_le_bytes());
+
+        let (price, timestamp) = parse_chainlink_transmissions(&data).unwrap();
+        assert!((price - 149.5).abs() < 1e-9);
+        assert_eq!(timestamp, 1_700_000_000);
+
+        write(&mut data, CHAINLINK_LIVE_CURSOR_OFFSET, &1u32.to_le_bytes());
+        assert!(parse_chainlink_transmissions(&data).is_err());
+    }
+}
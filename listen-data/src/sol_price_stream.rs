@@ -1,24 +1,65 @@
 use crate::{
-    kv_store::RedisKVStore,
+    kv_store::{KVStore, RedisKVStore},
+    lifecycle::LifecycleStage,
     message_queue::{MessageQueue, RedisMessageQueue},
-    price::PriceUpdate,
+    price::{PriceUpdate, PRICE_UPDATE_SCHEMA_VERSION},
+    sol_price_fallback::{
+        fetch_chainlink_price, fetch_pyth_price, SolPriceResult, SolPriceSource,
+    },
+    trader_type::TraderType,
 };
 use anyhow::Result;
 use chrono::Utc;
 use futures_util::{SinkExt, StreamExt};
 use once_cell::sync::Lazy;
 use serde::Deserialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use std::future::Future;
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::sync::RwLock;
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use url::Url;
 
 // Change the global cache to be just the price without Redis connections
 pub static SOL_PRICE_CACHE: Lazy<Arc<RwLock<f64>>> =
     Lazy::new(|| Arc::new(RwLock::new(0.0)));
 
+/// unix secs of the last price written to `SOL_PRICE_CACHE`
+static SOL_PRICE_UPDATED_AT: AtomicI64 = AtomicI64::new(0);
+
+/// the binance stream trades several times a second, a price older than
+/// this means the stream is down
+pub const PRIMARY_MAX_STALENESS_SECS: u64 = 30;
+
+/// a fallback price is reused for this long so that swaps don't each hit
+/// the fallback sources while the stream is down
+pub const FALLBACK_REFRESH_SECS: u64 = 10;
+
+/// cached redis prices older than this are logged as stale
+pub const REDIS_PRICE_STALE_SECS: u64 = 5 * 60;
+
+/// where to read prices from when the binance stream is down
+#[derive(Default)]
+struct SolPriceFallbacks {
+    rpc_client: Option<Arc<RpcClient>>,
+    kv_store: Option<Arc<RedisKVStore>>,
+}
+
+static FALLBACKS: Lazy<RwLock<SolPriceFallbacks>> =
+    Lazy::new(|| RwLock::new(SolPriceFallbacks::default()));
+
+/// the last fallback result and when it was fetched (unix secs), the mutex
+/// also keeps concurrent swaps from fetching at the same time
+static FALLBACK_CACHE: Lazy<Mutex<Option<(i64, SolPriceResult)>>> =
+    Lazy::new(|| Mutex::new(None));
+
+tokio::task_local! {
+    static SOL_PRICE_OVERRIDE: f64;
+}
+
 #[derive(Debug, Deserialize)]
 struct TradeData {
     p: String,
@@ -62,6 +103,11 @@ impl SolPriceCache {
             multi_hop: false,
             is_buy: false,
             is_pump: false,
+            trader_type: TraderType::Unknown, // Not a trade
+            lifecycle_stage: LifecycleStage::Unknown,
+            schema_version: PRICE_UPDATE_SCHEMA_VERSION,
+            smoothed_price: None,
+            finalized: false,
         };
         if let Some(kv_store) = &self.kv_store {
             kv_store.insert_price(&price_update).await?;
@@ -74,14 +120,15 @@ impl SolPriceCache {
 
     pub async fn set_price(&self, price: f64) {
         *self.price.write().await = price;
+        SOL_PRICE_UPDATED_AT.store(Utc::now().timestamp(), Ordering::Relaxed);
     }
 
     pub async fn get_price(&self) -> f64 {
         let current_price = *self.price.read().await;
         if current_price == 0.0 {
-            match self.fetch_rest_price().await {
+            match fetch_rest_price().await {
                 Ok(rest_price) => {
-                    *self.price.write().await = rest_price;
+                    self.set_price(rest_price).await;
                     rest_price
                 }
                 Err(e) => {
@@ -94,14 +141,6 @@ impl SolPriceCache {
         }
     }
 
-    async fn fetch_rest_price(&self) -> Result<f64> {
-        let rest_url =
-            "https://api.binance.com/api/v3/ticker/price?symbol=SOLUSDT";
-        let response = reqwest::get(rest_url).await?;
-        let price_data: BinancePrice = response.json().await?;
-        price_data.price.parse::<f64>().map_err(Into::into)
-    }
-
     pub async fn start_price_stream(&self) -> Result<()> {
         loop {
             info!("Connecting to Binance WebSocket...");
@@ -153,6 +192,11 @@ impl SolPriceCache {
                                 if let Ok(new_price) = trade.p.parse::<f64>() {
                                     let current_price =
                                         price_cache.get_price().await;
+                                    // an unchanged price is still fresh
+                                    SOL_PRICE_UPDATED_AT.store(
+                                        Utc::now().timestamp(),
+                                        Ordering::Relaxed,
+                                    );
                                     if current_price != new_price {
                                         price_cache.set_price(new_price).await;
                                         let price_cache = price_cache.clone();
@@ -198,9 +242,146 @@ impl SolPriceCache {
     }
 }
 
+async fn fetch_rest_price() -> Result<f64> {
+    let rest_url = "https://api.binance.com/api/v3/ticker/price?symbol=SOLUSDT";
+    let response = reqwest::get(rest_url).await?;
+    let price_data: BinancePrice = response.json().await?;
+    price_data.price.parse::<f64>().map_err(Into::into)
+}
+
+/// sources tried in order once the binance stream is stale; without them
+/// only the binance REST endpoint is tried
+pub async fn configure_sol_price_fallbacks(
+    rpc_client: Option<Arc<RpcClient>>,
+    kv_store: Option<Arc<RedisKVStore>>,
+) {
+    *FALLBACKS.write().await = SolPriceFallbacks {
+        rpc_client,
+        kv_store,
+    };
+}
+
+fn staleness_secs(timestamp: i64, now: i64) -> u64 {
+    now.saturating_sub(timestamp).max(0) as u64
+}
+
+/// runs `f` with `get_sol_price` returning `sol_price` instead of the live
+/// price, so that backfills can price swaps at the historical SOL price of
+/// their block and tests don't depend on the stream; tasks spawned by `f`
+/// read the live price again
+pub async fn with_sol_price<F: Future>(sol_price: f64, f: F) -> F::Output {
+    SOL_PRICE_OVERRIDE.scope(sol_price, f).await
+}
+
 // Add a convenience function for getting the global price
 pub async fn get_sol_price() -> f64 {
-    *SOL_PRICE_CACHE.read().await
+    get_sol_price_with_metadata().await.price
+}
+
+/// the price from the binance stream, or from the first fallback that
+/// answers if the stream is stale (binance REST, pyth, chainlink, redis)
+pub async fn get_sol_price_with_metadata() -> SolPriceResult {
+    if let Ok(price) = SOL_PRICE_OVERRIDE.try_with(|price| *price) {
+        return SolPriceResult {
+            price,
+            source: SolPriceSource::Override,
+            staleness_secs: 0,
+        };
+    }
+    let now = Utc::now().timestamp();
+    let price = *SOL_PRICE_CACHE.read().await;
+    let primary = SolPriceResult {
+        price,
+        source: SolPriceSource::Binance,
+        staleness_secs: staleness_secs(
+            SOL_PRICE_UPDATED_AT.load(Ordering::Relaxed),
+            now,
+        ),
+    };
+    if price > 0.0 && primary.staleness_secs <= PRIMARY_MAX_STALENESS_SECS {
+        return primary;
+    }
+
+    let mut cache = FALLBACK_CACHE.lock().await;
+    if let Some((fetched_at, result)) = *cache {
+        if staleness_secs(fetched_at, now) <= FALLBACK_REFRESH_SECS {
+            return result;
+        }
+    }
+    let result = fetch_fallback_price(now).await.unwrap_or_else(|| {
+        error!("all SOL price sources failed");
+        match price > 0.0 {
+            true => primary,
+            false => SolPriceResult {
+                price: 0.0,
+                source: SolPriceSource::Unavailable,
+                staleness_secs: 0,
+            },
+        }
+    });
+    *cache = Some((now, result));
+    result
+}
+
+async fn fetch_fallback_price(now: i64) -> Option<SolPriceResult> {
+    match fetch_rest_price().await {
+        Ok(price) => {
+            return Some(SolPriceResult {
+                price,
+                source: SolPriceSource::Binance,
+                staleness_secs: 0,
+            })
+        }
+        Err(e) => warn!("binance REST SOL price failed: {}", e),
+    }
+
+    let fallbacks = FALLBACKS.read().await;
+    if let Some(rpc_client) = &fallbacks.rpc_client {
+        match fetch_pyth_price(rpc_client).await {
+            Ok((price, publish_time)) => {
+                return Some(SolPriceResult {
+                    price,
+                    source: SolPriceSource::Pyth,
+                    staleness_secs: staleness_secs(publish_time, now),
+                })
+            }
+            Err(e) => warn!("pyth SOL price failed: {}", e),
+        }
+        match fetch_chainlink_price(rpc_client).await {
+            Ok((price, timestamp)) => {
+                return Some(SolPriceResult {
+                    price,
+                    source: SolPriceSource::Chainlink,
+                    staleness_secs: staleness_secs(timestamp, now),
+                })
+            }
+            Err(e) => warn!("chainlink SOL price failed: {}", e),
+        }
+    }
+    if let Some(kv_store) = &fallbacks.kv_store {
+        match kv_store
+            .get_price(crate::constants::WSOL_MINT_KEY_STR)
+            .await
+        {
+            Ok(Some(update)) if update.price > 0.0 => {
+                let staleness = staleness_secs(update.timestamp as i64, now);
+                if staleness > REDIS_PRICE_STALE_SECS {
+                    warn!(
+                        staleness_secs = staleness,
+                        "using stale SOL price from redis"
+                    );
+                }
+                return Some(SolPriceResult {
+                    price: update.price,
+                    source: SolPriceSource::RedisCache,
+                    staleness_secs: staleness,
+                });
+            }
+            Ok(_) => warn!("no SOL price cached in redis"),
+            Err(e) => warn!("redis SOL price failed: {}", e),
+        }
+    }
+    None
 }
 
 #[cfg(test)]
@@ -228,6 +409,25 @@ mod tests {
         assert!(price > 0.0, "Price should be greater than 0");
     }
 
+    #[tokio::test]
+    async fn test_fresh_stream_price_is_used() {
+        SolPriceCache::new(None, None).set_price(150.0).await;
+        let result = get_sol_price_with_metadata().await;
+        assert_eq!(result.source, SolPriceSource::Binance);
+        assert!(result.staleness_secs <= PRIMARY_MAX_STALENESS_SECS);
+    }
+
+    #[tokio::test]
+    async fn test_overridden_price() {
+        SolPriceCache::new(None, None).set_price(150.0).await;
+        let result = with_sol_price(21.5, get_sol_price_with_metadata()).await;
+        assert_eq!(result.price, 21.5);
+        assert_eq!(result.source, SolPriceSource::Override);
+        // only inside the scope
+        let result = get_sol_price_with_metadata().await;
+        assert_ne!(result.source, SolPriceSource::Override);
+    }
+
     #[tokio::test]
     async fn test_rest_fallback() {
         let price_cache = SolPriceCache::new(None, None);
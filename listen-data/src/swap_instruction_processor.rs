@@ -0,0 +1,151 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use tracing::{debug, error, info};
+
+use crate::{
+    db::ClickhouseDb,
+    kv_store::RedisKVStore,
+    message_queue::RedisMessageQueue,
+    metrics::SwapMetrics,
+    process_swap::process_swap,
+    slot_lag::{HeldBack, SLOT_LAG},
+};
+use carbon_core::{
+    error::CarbonResult, instruction::InstructionProcessorInputType,
+    metrics::MetricsCollection, processor::Processor,
+    transaction::TransactionMetadata,
+};
+use solana_sdk::commitment_config::CommitmentLevel;
+
+/// the decoded instructions of a dex, the processor prices the
+/// transactions of those that trade
+pub trait SwapInstruction: Send + Sync + 'static {
+    fn is_swap(&self) -> bool;
+}
+
+/// prices the transactions of the swap instructions `I` decodes to, every
+/// dex gets its own so that its transactions are told apart by the decoder
+pub struct SwapInstructionProcessor<I> {
+    pub kv_store: Arc<RedisKVStore>,
+    pub message_queue: Arc<RedisMessageQueue>,
+    pub db: Arc<ClickhouseDb>,
+    pub metrics: Arc<SwapMetrics>,
+    /// of the datasource, decides whether the price updates are final
+    pub commitment: CommitmentLevel,
+    /// swaps that came in while the pipeline lagged, see `handle_swap`
+    held_back: HeldBack<TransactionMetadata>,
+    instruction: PhantomData<fn() -> I>,
+}
+
+#[async_trait::async_trait]
+impl<I: SwapInstruction> Processor for SwapInstructionProcessor<I> {
+    type InputType = InstructionProcessorInputType<I>;
+
+    async fn process(
+        &mut self,
+        data: Self::InputType,
+        _metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        let (meta, instruction, _nested_instructions) = data;
+        if instruction.data.is_swap() {
+            self.handle_swap(&meta).await;
+        }
+
+        Ok(())
+    }
+}
+
+impl<I> SwapInstructionProcessor<I> {
+    pub fn new(
+        kv_store: Arc<RedisKVStore>,
+        message_queue: Arc<RedisMessageQueue>,
+        db: Arc<ClickhouseDb>,
+        commitment: CommitmentLevel,
+    ) -> Self {
+        Self {
+            kv_store,
+            message_queue,
+            db,
+            metrics: Arc::new(SwapMetrics::new()),
+            commitment,
+            held_back: HeldBack::default(),
+            instruction: PhantomData,
+        }
+    }
+
+    /// Swaps are processed concurrently, unless the circuit breaker of
+    /// `SLOT_LAG` is open; then they are held back and, once it closes,
+    /// processed one by one in slot order before any newer swap, which
+    /// holds up the stream until they are in
+    async fn handle_swap(
+        &mut self,
+        meta: &carbon_core::instruction::InstructionMetadata,
+    ) {
+        debug!(
+            "https://solscan.io/tx/{}",
+            meta.transaction_metadata.signature
+        );
+        let tx_meta = TransactionMetadata::clone(&meta.transaction_metadata);
+        self.metrics.increment_total_swaps();
+
+        // the slot counts as processed either way, which is how the stream
+        // catches up while swaps are held back
+        SLOT_LAG.record_processed(tx_meta.slot);
+        if SLOT_LAG.is_paused() {
+            self.metrics.increment_held_back_swaps();
+            self.held_back.push(tx_meta.slot, tx_meta);
+            return;
+        }
+        if self.held_back.is_empty() {
+            self.spawn_swap_processor(tx_meta);
+            return;
+        }
+
+        self.held_back.push(tx_meta.slot, tx_meta);
+        info!("replaying {} held back swaps", self.held_back.len());
+        for tx_meta in self.held_back.drain_in_slot_order() {
+            self.swap_processor(tx_meta).await;
+        }
+    }
+
+    fn spawn_swap_processor(&self, tx_meta: TransactionMetadata) {
+        tokio::spawn(self.swap_processor(tx_meta));
+    }
+
+    fn swap_processor(
+        &self,
+        tx_meta: TransactionMetadata,
+    ) -> impl Future<Output = ()> + Send + 'static {
+        let message_queue = self.message_queue.clone();
+        let kv_store = self.kv_store.clone();
+        let db = self.db.clone();
+        let metrics = self.metrics.clone();
+        let commitment = self.commitment;
+
+        async move {
+            match process_swap(
+                &tx_meta,
+                message_queue.as_ref(),
+                &kv_store,
+                &db,
+                &metrics,
+                commitment,
+            )
+            .await
+            {
+                Ok(_) => {
+                    metrics.increment_successful_swaps();
+                }
+                Err(e) => {
+                    metrics.increment_failed_swaps();
+                    error!(
+                        ?e,
+                        "Transaction: https://solscan.io/tx/{}",
+                        tx_meta.signature
+                    );
+                }
+            }
+        }
+    }
+}
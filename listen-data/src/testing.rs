@@ -0,0 +1,207 @@
+//! in-memory stand-ins for redis and clickhouse, so that the swap processing
+//! can be tested without either running
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Mutex;
+
+use anyhow::Result;
+
+use crate::aggregated_price::DexQuote;
+use crate::arbitrage::ArbitrageOpportunity;
+use crate::db::Database;
+use crate::kv_store::KVStore;
+use crate::lifecycle::{LifecycleMetrics, LifecycleStage};
+use crate::message_queue::MessageQueue;
+use crate::metadata::TokenMetadata;
+use crate::metadata_update::MetadataUpdateEvent;
+use crate::new_pool::NewPoolEvent;
+use crate::pool_monitor::PoolTvlUpdate;
+use crate::price::PriceUpdate;
+use crate::referral_fees::FeeAccrued;
+use crate::slot_lag::SlotLagAlert;
+use crate::smart_money::SmartMoneyActivity;
+
+/// the redis cache as maps, nothing expires
+#[derive(Default)]
+pub struct MemoryKVStore {
+    prices: Mutex<HashMap<String, PriceUpdate>>,
+    metadata: Mutex<HashMap<String, TokenMetadata>>,
+    dex_quotes: Mutex<HashMap<String, Vec<DexQuote>>>,
+    lifecycle_stages: Mutex<HashMap<String, LifecycleStage>>,
+}
+
+#[async_trait::async_trait]
+impl KVStore for MemoryKVStore {
+    async fn insert_price(&self, price: &PriceUpdate) -> Result<()> {
+        self.prices
+            .lock()
+            .unwrap()
+            .insert(price.pubkey.clone(), price.clone());
+        Ok(())
+    }
+
+    async fn get_price(&self, pubkey: &str) -> Result<Option<PriceUpdate>> {
+        Ok(self.prices.lock().unwrap().get(pubkey).cloned())
+    }
+
+    async fn insert_metadata(&self, metadata: &TokenMetadata) -> Result<()> {
+        self.metadata
+            .lock()
+            .unwrap()
+            .insert(metadata.mint.clone(), metadata.clone());
+        Ok(())
+    }
+
+    async fn get_metadata(&self, mint: &str) -> Result<Option<TokenMetadata>> {
+        Ok(self.metadata.lock().unwrap().get(mint).cloned())
+    }
+
+    async fn delete_metadata(&self, mint: &str) -> Result<()> {
+        self.metadata.lock().unwrap().remove(mint);
+        Ok(())
+    }
+
+    /// one quote per dex and side, like the redis hash
+    async fn insert_dex_quote(
+        &self,
+        mint: &str,
+        quote: &DexQuote,
+    ) -> Result<()> {
+        let mut dex_quotes = self.dex_quotes.lock().unwrap();
+        let quotes = dex_quotes.entry(mint.to_string()).or_default();
+        quotes.retain(|q| q.dex != quote.dex || q.side != quote.side);
+        quotes.push(quote.clone());
+        Ok(())
+    }
+
+    async fn get_dex_quotes(&self, mint: &str) -> Result<Vec<DexQuote>> {
+        Ok(self
+            .dex_quotes
+            .lock()
+            .unwrap()
+            .get(mint)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn insert_lifecycle_stage(
+        &self,
+        mint: &str,
+        stage: LifecycleStage,
+    ) -> Result<()> {
+        self.lifecycle_stages
+            .lock()
+            .unwrap()
+            .insert(mint.to_string(), stage);
+        Ok(())
+    }
+
+    async fn get_lifecycle_stage(
+        &self,
+        mint: &str,
+    ) -> Result<Option<LifecycleStage>> {
+        Ok(self.lifecycle_stages.lock().unwrap().get(mint).copied())
+    }
+}
+
+/// keeps the published price updates, drops the other events
+#[derive(Default)]
+pub struct MemoryMessageQueue {
+    pub price_updates: Mutex<Vec<PriceUpdate>>,
+}
+
+#[async_trait::async_trait]
+impl MessageQueue for MemoryMessageQueue {
+    type Error = Infallible;
+
+    async fn publish_price_update(
+        &self,
+        price_update: PriceUpdate,
+    ) -> Result<(), Self::Error> {
+        self.price_updates.lock().unwrap().push(price_update);
+        Ok(())
+    }
+
+    async fn publish_smart_money_activity(
+        &self,
+        _activity: SmartMoneyActivity,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn publish_pool_tvl_update(
+        &self,
+        _update: PoolTvlUpdate,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn publish_metadata_update(
+        &self,
+        _event: MetadataUpdateEvent,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn publish_new_pool_event(
+        &self,
+        _event: NewPoolEvent,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn publish_fee_accrued(
+        &self,
+        _event: FeeAccrued,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn publish_arbitrage_opportunity(
+        &self,
+        _opportunity: ArbitrageOpportunity,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn publish_system_alert(
+        &self,
+        _alert: SlotLagAlert,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// keeps the inserted price updates, every mint has an empty history
+#[derive(Default)]
+pub struct MemoryDb {
+    pub prices: Mutex<Vec<PriceUpdate>>,
+}
+
+#[async_trait::async_trait]
+impl Database for MemoryDb {
+    fn new(_: &str, _: &str, _: &str, _: &str) -> Self {
+        Self::default()
+    }
+
+    async fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn health_check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn insert_price(&self, price: &PriceUpdate) -> Result<()> {
+        self.prices.lock().unwrap().push(price.clone());
+        Ok(())
+    }
+
+    async fn get_lifecycle_metrics(
+        &self,
+        _mint: &str,
+    ) -> Result<LifecycleMetrics> {
+        Ok(LifecycleMetrics::default())
+    }
+}
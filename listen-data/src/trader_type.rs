@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+/// swaps of the same wallet at most this many slots apart are machine paced
+pub const RAPID_TRADE_SLOTS: u64 = 2;
+
+/// bot amounts are computed in lamports and come out as round numbers
+pub const ROUND_LAMPORTS: u64 = 1000;
+
+/// sandwich and arbitrage bots active on raydium
+pub const KNOWN_MEV_BOTS: &[&str] = &[
+    "arsc4jbDnzaqcCLByyGo7fg7S2SmcFsWUzQuDtLZh2y",
+    "vpeNALD89BZ4KxNUFjdLmFXBCwtyqBDQ85ouNoax38b",
+];
+
+/// last slot each wallet was seen trading in, pruned once it grows past
+/// `MAX_TRACKED_WALLETS`
+static LAST_TRADE_SLOT: Lazy<Mutex<HashMap<String, u64>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+const MAX_TRACKED_WALLETS: usize = 100_000;
+
+/// stored as a string in `price_updates.trader_type`, rows written before
+/// the column existed read back as `Unknown`
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[serde(from = "String", into = "String")]
+pub enum TraderType {
+    Human,
+    Bot,
+    #[default]
+    Unknown,
+}
+
+impl TraderType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TraderType::Human => "human",
+            TraderType::Bot => "bot",
+            TraderType::Unknown => "unknown",
+        }
+    }
+}
+
+impl From<String> for TraderType {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "human" => TraderType::Human,
+            "bot" => TraderType::Bot,
+            _ => TraderType::Unknown,
+        }
+    }
+}
+
+impl From<TraderType> for String {
+    fn from(trader_type: TraderType) -> Self {
+        trader_type.as_str().to_string()
+    }
+}
+
+pub fn is_known_mev_bot(owner: &str) -> bool {
+    KNOWN_MEV_BOTS.contains(&owner)
+}
+
+/// `sol_amount` is the SOL side of the swap in SOL, wSOL having 9 decimals
+/// it converts to lamports exactly
+pub fn is_round_amount(sol_amount: f64) -> bool {
+    let lamports = (sol_amount.abs() * 1e9).round() as u64;
+    lamports != 0 && lamports % ROUND_LAMPORTS == 0
+}
+
+/// records the trade and returns whether the wallet has traded within the
+/// last `RAPID_TRADE_SLOTS` slots
+fn record_trade(owner: &str, slot: u64) -> bool {
+    let mut last_trade_slot = LAST_TRADE_SLOT.lock().unwrap();
+    if last_trade_slot.len() >= MAX_TRACKED_WALLETS {
+        last_trade_slot
+            .retain(|_, last| last.saturating_add(RAPID_TRADE_SLOTS) >= slot);
+    }
+    let previous = last_trade_slot.insert(owner.to_string(), slot);
+    previous
+        .is_some_and(|previous| slot.abs_diff(previous) <= RAPID_TRADE_SLOTS)
+}
+
+/// Known MEV bots are always bots; otherwise a wallet both trading within
+/// the last couple of blocks and with a round lamport amount is a bot,
+/// showing neither is a human and showing only one is undecided. Round
+/// amounts alone are common for humans typing "0.1 SOL" into a UI.
+///
+/// `swap_amount` is the SOL side of the swap (in SOL), see
+/// [`is_round_amount`]
+pub fn classify_trader(owner: &str, swap_amount: f64, slot: u64) -> TraderType {
+    let rapid = record_trade(owner, slot);
+    if is_known_mev_bot(owner) {
+        return TraderType::Bot;
+    }
+    match (rapid, is_round_amount(swap_amount)) {
+        (true, true) => TraderType::Bot,
+        (false, false) => TraderType::Human,
+        _ => TraderType::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_round_amount() {
+        assert!(is_round_amount(0.1));
+        assert!(is_round_amount(1.000001));
+        assert!(!is_round_amount(0.123456789));
+        assert!(!is_round_amount(0.0));
+    }
+
+    #[test]
+    fn test_classify_trader() {
+        let owner = "test_classify_trader_wallet";
+        assert_eq!(classify_trader(owner, 0.123456789, 100), TraderType::Human);
+        // same wallet a slot later with a round amount
+        assert_eq!(classify_trader(owner, 0.5, 101), TraderType::Bot);
+        // round amount but after a pause
+        assert_eq!(classify_trader(owner, 0.5, 200), TraderType::Unknown);
+        assert_eq!(
+            classify_trader(KNOWN_MEV_BOTS[0], 0.123456789, 100),
+            TraderType::Bot
+        );
+    }
+
+    #[test]
+    fn test_trader_type_serde() {
+        assert_eq!(serde_json::to_string(&TraderType::Bot).unwrap(), "\"bot\"");
+        assert_eq!(
+            serde_json::from_str::<TraderType>("\"\"").unwrap(),
+            TraderType::Unknown
+        );
+    }
+}
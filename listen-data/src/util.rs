@@ -1,7 +1,7 @@
 use anyhow::Result;
 use bb8_redis::{bb8, RedisConnectionManager};
 use solana_client::nonblocking::rpc_client::RpcClient;
-use std::{fs::File, io::BufWriter, sync::Arc};
+use std::{fs::File, io::BufWriter, sync::Arc, time::Duration};
 
 use crate::{
     db::{ClickhouseDb, Database},
@@ -105,15 +105,65 @@ pub fn must_get_env(key: &str) -> String {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct RedisPoolConfig {
+    pub max_size: u32,
+    pub min_idle: Option<u32>,
+    pub connection_timeout: Duration,
+    pub max_lifetime: Option<Duration>,
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for RedisPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 200,
+            min_idle: Some(20),
+            connection_timeout: Duration::from_secs(5),
+            max_lifetime: Some(Duration::from_secs(60 * 15)), // 15 minutes
+            idle_timeout: Some(Duration::from_secs(60 * 5)),  // 5 minutes
+        }
+    }
+}
+
+impl RedisPoolConfig {
+    /// REDIS_POOL_MAX_SIZE, REDIS_POOL_MIN_IDLE and
+    /// REDIS_POOL_CONNECTION_TIMEOUT_SECS override the defaults
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let get = |key: &str| {
+            std::env::var(key).ok().and_then(|v| v.parse::<u64>().ok())
+        };
+        Self {
+            max_size: get("REDIS_POOL_MAX_SIZE")
+                .map(|v| v as u32)
+                .unwrap_or(default.max_size),
+            min_idle: get("REDIS_POOL_MIN_IDLE")
+                .map(|v| Some(v as u32))
+                .unwrap_or(default.min_idle),
+            connection_timeout: get("REDIS_POOL_CONNECTION_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(default.connection_timeout),
+            ..default
+        }
+    }
+}
+
 pub async fn create_redis_pool(
     redis_url: &str,
+    config: &RedisPoolConfig,
 ) -> Result<bb8::Pool<RedisConnectionManager>> {
     let manager = RedisConnectionManager::new(redis_url)?;
     let pool = bb8::Pool::builder()
-        .max_size(200)
-        .min_idle(Some(20))
-        .max_lifetime(Some(std::time::Duration::from_secs(60 * 15))) // 15 minutes
-        .idle_timeout(Some(std::time::Duration::from_secs(60 * 5))) // 5 minutes
+        .max_size(config.max_size)
+        .min_idle(config.min_idle.map(|n| n.min(config.max_size)))
+        .connection_timeout(config.connection_timeout)
+        .max_lifetime(config.max_lifetime)
+        .idle_timeout(config.idle_timeout)
+        // pings connections on checkout, dropped ones get replaced with a
+        // fresh connection instead of failing the command
+        .test_on_check_out(true)
+        .retry_connection(true)
         .build(manager)
         .await?;
     Ok(pool)
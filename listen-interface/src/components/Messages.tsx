@@ -59,12 +59,13 @@ export const ToolMessage = ({ toolOutput }: { toolOutput: ToolOutput }) => {
   if (toolOutput.name === "swap") {
     try {
       // TODO standardize this output, not just string but { status: string, transactionHash: string }
+      // the signature may be followed by the priority fee paid
       return (
         <div className="bg-blue-900/20 text-blue-300 rounded-lg px-4 py-3 my-2 backdrop-blur-sm border border-opacity-20 border-blue-500 overflow-hidden">
           <div className="mb-2 overflow-hidden">
             <TransactionLink
               status={"Completed"}
-              transactionHash={JSON.parse(toolOutput.result)}
+              transactionHash={JSON.parse(toolOutput.result).split(" ")[0]}
               error={null}
             />
           </div>
@@ -32,9 +32,15 @@ export const ToolOutputSchema = z.object({
 
 export type ToolOutput = z.infer<typeof ToolOutputSchema>;
 
+// the stream carries the result as the JSON the tool returned
+export const StreamToolOutputSchema = z.object({
+  name: z.string(),
+  result: z.unknown(),
+});
+
 export const StreamResponseSchema = z.object({
   type: z.enum(["Message", "ToolCall", "Error"]),
-  content: z.union([z.string(), ToolOutputSchema]),
+  content: z.union([z.string(), StreamToolOutputSchema]),
 });
 export type StreamResponse = z.infer<typeof StreamResponseSchema>;
 
@@ -4,7 +4,12 @@ import { useCallback, useEffect, useRef, useState } from "react";
 import { v4 as uuidv4 } from "uuid";
 import { chatCache } from "./localStorage";
 import { systemPromptEvm, systemPromptSolana } from "./prompts";
-import { Chat, Message, StreamResponse, ToolOutputSchema } from "./types";
+import {
+  Chat,
+  Message,
+  StreamResponse,
+  StreamToolOutputSchema,
+} from "./types";
 import { useChatType } from "./useChatType";
 import { useDebounce } from "./useDebounce";
 import { useEvmPortfolio } from "./useEvmPortfolioAlchemy";
@@ -265,14 +270,15 @@ export function useChat() {
                 );
                 break;
               case "ToolCall": {
-                const toolOutput = ToolOutputSchema.parse(data.content);
+                const toolOutput = StreamToolOutputSchema.parse(data.content);
+                const result = JSON.stringify(toolOutput.result);
                 setChat((prev) => ({
                   ...prev!,
                   messages: [
                     ...prev!.messages,
                     {
                       id: crypto.randomUUID(),
-                      message: `Tool ${toolOutput.name}: ${toolOutput.result}`,
+                      message: `Tool ${toolOutput.name}: ${result}`,
                       direction: "incoming",
                       timestamp: new Date(),
                       isToolCall: true,
@@ -1,10 +1,12 @@
 #[cfg(feature = "solana")]
 use {
-    anyhow::Result, listen_kit::reasoning_loop::ReasoningLoop,
+    anyhow::Result, listen_kit::model::ModelProvider,
+    listen_kit::reasoning_loop::ReasoningLoop,
     listen_kit::signer::solana::LocalSolanaSigner,
     listen_kit::signer::SignerContext,
     listen_kit::solana::agent::create_solana_agent,
-    listen_kit::solana::util::env, std::sync::Arc,
+    listen_kit::solana::util::env, listen_kit::tool_allowlist::ToolAllowlist,
+    std::sync::Arc,
 };
 
 #[cfg(feature = "solana")]
@@ -13,7 +15,14 @@ async fn main() -> Result<()> {
     let signer = LocalSolanaSigner::new(env("SOLANA_PRIVATE_KEY"));
 
     SignerContext::with_signer(Arc::new(signer), async {
-        let trader_agent = Arc::new(create_solana_agent(None).await?);
+        let trader_agent = Arc::new(
+            create_solana_agent(
+                None,
+                &ModelProvider::default(),
+                &ToolAllowlist::all(),
+            )
+            .await?,
+        );
         let trader_agent = ReasoningLoop::new(trader_agent).with_stdout(true);
 
         trader_agent
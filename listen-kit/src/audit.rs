@@ -0,0 +1,412 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// params above this size are truncated before being stored
+pub const MAX_AUDIT_PARAMS_BYTES: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Ok,
+    Err,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub user_id: Option<String>,
+    pub wallet: Option<String>,
+    pub conversation_id: Option<String>,
+    pub tool: String,
+    pub params_json: String,
+    pub outcome: AuditOutcome,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    pub tx_signature: Option<String>,
+    /// pre-flight simulations of the transactions the tool tried to send
+    #[serde(default)]
+    pub simulations: Vec<SimulationRecord>,
+    /// failed signer requests that were retried or given up on
+    #[serde(default)]
+    pub signer_retries: Vec<SignerRetryRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimulationRecord {
+    pub units_consumed: Option<u64>,
+    pub logs: Vec<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignerRetryRecord {
+    pub method: String,
+    pub attempt: u32,
+    pub status: Option<u16>,
+    pub error: String,
+    pub gave_up: bool,
+}
+
+tokio::task_local! {
+    static SIMULATIONS: Arc<Mutex<Vec<SimulationRecord>>>;
+    static SIGNER_RETRIES: Arc<Mutex<Vec<SignerRetryRecord>>>;
+}
+
+/// attaches a simulation to the audit entry of the tool call running on
+/// this task, a no-op outside of `audit_tool_call`
+pub fn record_simulation(record: SimulationRecord) {
+    let _ = SIMULATIONS.try_with(|simulations| {
+        simulations.lock().unwrap().push(record);
+    });
+}
+
+/// same as `record_simulation`, for a retried signer request
+pub fn record_signer_retry(record: SignerRetryRecord) {
+    let _ = SIGNER_RETRIES.try_with(|retries| {
+        retries.lock().unwrap().push(record);
+    });
+}
+
+/// Who the tool calls are made on behalf of
+#[derive(Debug, Clone, Default)]
+pub struct AuditContext {
+    pub user_id: Option<String>,
+    pub wallet: Option<String>,
+    pub conversation_id: Option<String>,
+}
+
+#[async_trait::async_trait]
+pub trait AuditSink: Send + Sync {
+    async fn record(&self, entry: AuditEntry) -> Result<()>;
+}
+
+pub struct NoopAuditSink;
+
+#[async_trait::async_trait]
+impl AuditSink for NoopAuditSink {
+    async fn record(&self, _entry: AuditEntry) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub fn truncate_params(params: &str) -> String {
+    if params.len() <= MAX_AUDIT_PARAMS_BYTES {
+        return params.to_string();
+    }
+    let mut end = MAX_AUDIT_PARAMS_BYTES;
+    while !params.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!(
+        "{}...[truncated {} bytes]",
+        &params[..end],
+        params.len() - end
+    )
+}
+
+fn is_solana_signature(s: &str) -> bool {
+    (80..=90).contains(&s.len())
+        && s.chars().all(|c| {
+            c.is_ascii_alphanumeric() && !matches!(c, '0' | 'O' | 'I' | 'l')
+        })
+}
+
+fn is_evm_tx_hash(s: &str) -> bool {
+    s.len() == 66
+        && s.starts_with("0x")
+        && s[2..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// swap and transfer tools return the signature (or tx hash) either as a
+/// bare string or as a field of a json object
+pub fn extract_tx_signature(result: &str) -> Option<String> {
+    let value = serde_json::from_str::<serde_json::Value>(result)
+        .unwrap_or_else(|_| serde_json::Value::String(result.to_string()));
+    let candidate = match &value {
+        serde_json::Value::String(s) => Some(s.as_str()),
+        serde_json::Value::Object(map) => [
+            "signature",
+            "tx_signature",
+            "transaction_hash",
+            "tx_hash",
+            "hash",
+        ]
+        .iter()
+        .find_map(|key| map.get(*key).and_then(|v| v.as_str())),
+        _ => None,
+    }?;
+    let candidate = candidate.trim();
+    if is_solana_signature(candidate) || is_evm_tx_hash(candidate) {
+        Some(candidate.to_string())
+    } else {
+        None
+    }
+}
+
+/// runs the tool call and records its outcome, failing to write the audit
+/// entry is logged but never fails the tool call itself
+pub async fn audit_tool_call<F, Fut, E>(
+    sink: &dyn AuditSink,
+    context: &AuditContext,
+    tool: &str,
+    params: &str,
+    call: F,
+) -> Result<String, E>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<String, E>>,
+    E: Display,
+{
+    let start = Instant::now();
+    let simulations = Arc::new(Mutex::new(Vec::new()));
+    let signer_retries = Arc::new(Mutex::new(Vec::new()));
+    let result = SIMULATIONS
+        .scope(
+            simulations.clone(),
+            SIGNER_RETRIES.scope(signer_retries.clone(), call()),
+        )
+        .await;
+    let duration_ms = start.elapsed().as_millis() as u64;
+
+    let entry = AuditEntry {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        user_id: context.user_id.clone(),
+        wallet: context.wallet.clone(),
+        conversation_id: context.conversation_id.clone(),
+        tool: tool.to_string(),
+        params_json: truncate_params(params),
+        outcome: match &result {
+            Ok(_) => AuditOutcome::Ok,
+            Err(_) => AuditOutcome::Err,
+        },
+        error: result.as_ref().err().map(|e| e.to_string()),
+        duration_ms,
+        tx_signature: result
+            .as_ref()
+            .ok()
+            .and_then(|r| extract_tx_signature(r)),
+        simulations: std::mem::take(&mut *simulations.lock().unwrap()),
+        signer_retries: std::mem::take(&mut *signer_retries.lock().unwrap()),
+    };
+
+    if let Err(e) = sink.record(entry).await {
+        tracing::error!("Error: failed to write audit entry: {}", e);
+    }
+
+    result
+}
+
+#[cfg(feature = "http")]
+pub use redis_sink::RedisAuditSink;
+
+#[cfg(feature = "http")]
+mod redis_sink {
+    use super::{AuditEntry, AuditSink};
+    use anyhow::{anyhow, Result};
+
+    /// Appends entries to a per-user redis stream `audit:{user_id}`
+    pub struct RedisAuditSink {
+        client: redis::Client,
+    }
+
+    impl RedisAuditSink {
+        pub fn new(redis_url: &str) -> Result<Self> {
+            Ok(Self {
+                client: redis::Client::open(redis_url)?,
+            })
+        }
+
+        fn stream_key(user_id: &str) -> String {
+            format!("audit:{}", user_id)
+        }
+
+        /// newest first
+        pub async fn get_entries(
+            &self,
+            user_id: &str,
+            limit: usize,
+        ) -> Result<Vec<AuditEntry>> {
+            let mut conn =
+                self.client.get_multiplexed_async_connection().await?;
+            let reply: Vec<(String, Vec<String>)> = redis::cmd("XREVRANGE")
+                .arg(Self::stream_key(user_id))
+                .arg("+")
+                .arg("-")
+                .arg("COUNT")
+                .arg(limit)
+                .query_async(&mut conn)
+                .await?;
+
+            reply
+                .into_iter()
+                .map(|(_, fields)| {
+                    let entry = fields
+                        .chunks(2)
+                        .find(|kv| kv[0] == "entry")
+                        .and_then(|kv| kv.get(1))
+                        .ok_or_else(|| anyhow!("Malformed audit entry"))?;
+                    Ok(serde_json::from_str(entry)?)
+                })
+                .collect()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl AuditSink for RedisAuditSink {
+        async fn record(&self, entry: AuditEntry) -> Result<()> {
+            let user_id = entry.user_id.as_deref().unwrap_or("anonymous");
+            let mut conn =
+                self.client.get_multiplexed_async_connection().await?;
+            let _: String = redis::cmd("XADD")
+                .arg(Self::stream_key(user_id))
+                .arg("*")
+                .arg("entry")
+                .arg(serde_json::to_string(&entry)?)
+                .query_async(&mut conn)
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MemoryAuditSink {
+        entries: Mutex<Vec<AuditEntry>>,
+    }
+
+    #[async_trait::async_trait]
+    impl AuditSink for MemoryAuditSink {
+        async fn record(&self, entry: AuditEntry) -> Result<()> {
+            self.entries.lock().unwrap().push(entry);
+            Ok(())
+        }
+    }
+
+    const SIGNATURE: &str = "5j8nDKNNLbXcJqdZvM7h76m2tuzjMsbAWAo8vfSPwBMjnVaE47G6uXbG9NE6GFHth76K9qzJMBzNeM2xoHHkT3qZ";
+
+    fn context() -> AuditContext {
+        AuditContext {
+            user_id: Some("user".to_string()),
+            wallet: Some("wallet".to_string()),
+            conversation_id: Some("conversation".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audit_success_and_failure() {
+        let sink = MemoryAuditSink::default();
+
+        let result =
+            audit_tool_call(&sink, &context(), "swap", "{}", || async {
+                Ok::<_, anyhow::Error>(format!("\"{}\"", SIGNATURE))
+            })
+            .await;
+        assert!(result.is_ok());
+
+        let result = audit_tool_call(
+            &sink,
+            &context(),
+            "transfer_sol",
+            "{}",
+            || async {
+                Err::<String, _>(anyhow::anyhow!("insufficient funds"))
+            },
+        )
+        .await;
+        assert!(result.is_err());
+
+        let entries = sink.entries.lock().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].tool, "swap");
+        assert_eq!(entries[0].outcome, AuditOutcome::Ok);
+        assert_eq!(entries[0].tx_signature.as_deref(), Some(SIGNATURE));
+        assert_eq!(entries[0].wallet.as_deref(), Some("wallet"));
+        assert_eq!(entries[1].tool, "transfer_sol");
+        assert_eq!(entries[1].outcome, AuditOutcome::Err);
+        assert_eq!(entries[1].error.as_deref(), Some("insufficient funds"));
+        assert_eq!(entries[1].tx_signature, None);
+    }
+
+    #[tokio::test]
+    async fn test_audit_records_simulations() {
+        let sink = MemoryAuditSink::default();
+        let simulation = SimulationRecord {
+            units_consumed: Some(42_000),
+            logs: vec!["Program log: Instruction: Buy".to_string()],
+            error: None,
+        };
+
+        let retry = SignerRetryRecord {
+            method: "signAndSendTransaction".to_string(),
+            attempt: 1,
+            status: Some(429),
+            error: "429 Too Many Requests".to_string(),
+            gave_up: false,
+        };
+
+        let (expected, expected_retry) = (simulation.clone(), retry.clone());
+        audit_tool_call(&sink, &context(), "swap", "{}", || async move {
+            record_simulation(simulation);
+            record_signer_retry(retry);
+            Ok::<_, anyhow::Error>("ok".to_string())
+        })
+        .await
+        .unwrap();
+        // outside of a tool call there is nothing to attach to
+        record_simulation(expected.clone());
+        record_signer_retry(expected_retry.clone());
+
+        let entries = sink.entries.lock().unwrap();
+        assert_eq!(entries[0].simulations, vec![expected]);
+        assert_eq!(entries[0].signer_retries, vec![expected_retry]);
+    }
+
+    #[tokio::test]
+    async fn test_audit_truncates_large_params() {
+        let sink = MemoryAuditSink::default();
+        let params = format!("{{\"blob\":\"{}\"}}", "a".repeat(10_000));
+
+        audit_tool_call(&sink, &context(), "deploy", &params, || async {
+            Ok::<_, anyhow::Error>("ok".to_string())
+        })
+        .await
+        .unwrap();
+
+        let entries = sink.entries.lock().unwrap();
+        let stored = &entries[0].params_json;
+        assert!(stored.len() < params.len());
+        assert!(stored.starts_with("{\"blob\":\"aaa"));
+        assert!(stored.ends_with(&format!(
+            "...[truncated {} bytes]",
+            params.len() - MAX_AUDIT_PARAMS_BYTES
+        )));
+    }
+
+    #[test]
+    fn test_extract_tx_signature() {
+        assert_eq!(
+            extract_tx_signature(&format!(
+                "{{\"signature\":\"{}\"}}",
+                SIGNATURE
+            ))
+            .as_deref(),
+            Some(SIGNATURE)
+        );
+        let hash = format!("0x{}", "ab".repeat(32));
+        assert_eq!(
+            extract_tx_signature(&format!("\"{}\"", hash)).as_deref(),
+            Some(hash.as_str())
+        );
+        assert_eq!(extract_tx_signature("\"1000000\""), None);
+        assert_eq!(extract_tx_signature("{\"balance\":1}"), None);
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::{anyhow, Result};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "solana")]
+use crate::solana::confirmation::ConfirmationConfig;
+#[cfg(feature = "solana")]
+use crate::solana::jito::JitoConfig;
+#[cfg(feature = "solana")]
+use crate::solana::priority_fee::PriorityFeeConfig;
+#[cfg(feature = "solana")]
+use crate::solana::trade::MAX_SLIPPAGE_BPS;
+#[cfg(feature = "solana")]
+use crate::solana::trade_pump::{
+    DEFAULT_PUMP_BUY_SLIPPAGE_BPS, DEFAULT_PUMP_SELL_SLIPPAGE_BPS,
+};
+#[cfg(feature = "solana")]
+use solana_sdk::commitment_config::CommitmentLevel;
+#[cfg(feature = "solana")]
+use solana_sdk::pubkey::Pubkey;
+#[cfg(feature = "solana")]
+use std::str::FromStr;
+
+static CHAIN_CONFIG: OnceCell<ChainConfig> = OnceCell::new();
+
+/// Defaults of the tools per chain and venue, for whatever a tool call
+/// doesn't set itself. Missing fields keep the built-in defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainConfig {
+    #[cfg(feature = "solana")]
+    pub solana: SolanaConfig,
+}
+
+#[cfg(feature = "solana")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SolanaConfig {
+    /// what a sent transaction is confirmed at
+    pub commitment: CommitmentLevel,
+    /// how long to wait for that and how often to rebuild a transaction
+    /// whose blockhash expired
+    pub confirmation: ConfirmationConfig,
+    /// priority fee of every sent transaction, unless the tool call picks
+    /// a priority, overridden by the `PRIORITY_FEE` env var
+    pub priority_fee: PriorityFeeConfig,
+    /// tips and timeout of the transactions sent with `send_via_jito`
+    pub jito: JitoConfig,
+    /// `None` lets jupiter pick the slippage of every swap
+    pub jupiter_slippage_bps: Option<u16>,
+    pub pump_buy_slippage_bps: u16,
+    pub pump_sell_slippage_bps: u16,
+    /// vote account `stake_sol` delegates to when the tool call names no
+    /// validator
+    pub default_validator: Option<String>,
+}
+
+#[cfg(feature = "solana")]
+impl Default for SolanaConfig {
+    fn default() -> Self {
+        Self {
+            commitment: CommitmentLevel::Confirmed,
+            confirmation: ConfirmationConfig::default(),
+            priority_fee: PriorityFeeConfig::None,
+            jito: JitoConfig::default(),
+            jupiter_slippage_bps: None,
+            pump_buy_slippage_bps: DEFAULT_PUMP_BUY_SLIPPAGE_BPS,
+            pump_sell_slippage_bps: DEFAULT_PUMP_SELL_SLIPPAGE_BPS,
+            default_validator: None,
+        }
+    }
+}
+
+#[cfg(feature = "solana")]
+impl SolanaConfig {
+    /// `slippage_bps` of the tool call wins over the default
+    pub fn jupiter_slippage_bps(
+        &self,
+        slippage_bps: Option<u16>,
+    ) -> Option<u16> {
+        slippage_bps.or(self.jupiter_slippage_bps)
+    }
+
+    /// `slippage_bps` of the tool call wins over the default of the side
+    pub fn pump_slippage_bps(
+        &self,
+        is_buy: bool,
+        slippage_bps: Option<u16>,
+    ) -> u16 {
+        slippage_bps.unwrap_or(if is_buy {
+            self.pump_buy_slippage_bps
+        } else {
+            self.pump_sell_slippage_bps
+        })
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.priority_fee.validate()?;
+        self.jito.validate()?;
+        self.confirmation.validate()?;
+        let slippages = [
+            self.jupiter_slippage_bps,
+            Some(self.pump_buy_slippage_bps),
+            Some(self.pump_sell_slippage_bps),
+        ];
+        if slippages
+            .into_iter()
+            .flatten()
+            .any(|bps| bps > MAX_SLIPPAGE_BPS)
+        {
+            return Err(anyhow!(
+                "slippage defaults can be at most {} bps",
+                MAX_SLIPPAGE_BPS
+            ));
+        }
+        if let Some(validator) = &self.default_validator {
+            Pubkey::from_str(validator).map_err(|_| {
+                anyhow!(
+                    "default_validator {} is not a vote account",
+                    validator
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl ChainConfig {
+    /// `CHAIN_CONFIG` is the path of a JSON file, the built-in defaults
+    /// are used when it is unset
+    pub fn from_env() -> Result<Self> {
+        #[allow(unused_mut)]
+        let mut config = match std::env::var("CHAIN_CONFIG") {
+            Ok(path) => {
+                let json = std::fs::read_to_string(&path)
+                    .map_err(|e| anyhow!("Failed to read {}: {}", path, e))?;
+                Self::from_json(&json)?
+            }
+            Err(_) => Self::default(),
+        };
+        #[cfg(feature = "solana")]
+        if let Ok(priority_fee) = std::env::var("PRIORITY_FEE") {
+            config.solana.priority_fee = priority_fee.parse()?;
+        }
+        Ok(config)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let config: Self = serde_json::from_str(json)?;
+        #[cfg(feature = "solana")]
+        config.solana.validate()?;
+        Ok(config)
+    }
+
+    /// makes this the config the tools read, once at startup
+    pub fn install(self) -> Result<()> {
+        CHAIN_CONFIG
+            .set(self)
+            .map_err(|_| anyhow!("Chain config is already installed"))
+    }
+}
+
+/// the installed config, or the one from `ChainConfig::from_env` when none
+/// was, the defaults if that is invalid
+pub fn chain_config() -> &'static ChainConfig {
+    CHAIN_CONFIG.get_or_init(|| {
+        ChainConfig::from_env().unwrap_or_else(|e| {
+            tracing::warn!(%e, "invalid chain config, using the defaults");
+            ChainConfig::default()
+        })
+    })
+}
+
+#[cfg(all(test, feature = "solana"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pump_uses_configured_slippage() {
+        let config = ChainConfig::from_json(
+            r#"{ "solana": { "pump_buy_slippage_bps": 250 } }"#,
+        )
+        .unwrap();
+        let solana = &config.solana;
+        // the buy default replaces the hardcoded 1%, the rest is kept
+        assert_eq!(solana.pump_slippage_bps(true, None), 250);
+        assert_ne!(solana.pump_slippage_bps(true, None), 100);
+        assert_eq!(
+            solana.pump_slippage_bps(false, None),
+            DEFAULT_PUMP_SELL_SLIPPAGE_BPS
+        );
+        assert_eq!(solana.commitment, CommitmentLevel::Confirmed);
+        // the tool call still wins
+        assert_eq!(solana.pump_slippage_bps(true, Some(700)), 700);
+        assert_eq!(solana.jupiter_slippage_bps(None), None);
+        assert_eq!(solana.jupiter_slippage_bps(Some(30)), Some(30));
+        assert_eq!(solana.priority_fee, PriorityFeeConfig::None);
+    }
+
+    #[test]
+    fn test_priority_fee_config() {
+        let config = ChainConfig::from_json(
+            r#"{ "solana": { "priority_fee": { "mode": "auto" } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.solana.priority_fee,
+            PriorityFeeConfig::Auto { percentile: 50 }
+        );
+        let config = ChainConfig::from_json(
+            r#"{ "solana": { "priority_fee": {
+                "mode": "fixed", "micro_lamports": 10000 } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.solana.priority_fee,
+            PriorityFeeConfig::Fixed {
+                micro_lamports: 10_000
+            }
+        );
+        assert!(ChainConfig::from_json(
+            r#"{ "solana": { "priority_fee": {
+                "mode": "auto", "percentile": 120 } } }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_jito_config() {
+        let config = ChainConfig::from_json(
+            r#"{ "solana": { "jito": {
+                "tip_lamports": 50000, "fallback_to_rpc": false } } }"#,
+        )
+        .unwrap();
+        let jito = &config.solana.jito;
+        assert_eq!(jito.tip_lamports, Some(50_000));
+        assert!(!jito.fallback_to_rpc);
+        assert_eq!(jito.timeout_secs, JitoConfig::default().timeout_secs);
+        assert!(ChainConfig::from_json(
+            r#"{ "solana": { "jito": { "tip_lamports": 10 } } }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_confirmation_config() {
+        let config = ChainConfig::from_json(
+            r#"{ "solana": { "confirmation": { "max_rebuilds": 0 } } }"#,
+        )
+        .unwrap();
+        let confirmation = &config.solana.confirmation;
+        assert_eq!(confirmation.max_rebuilds, 0);
+        assert_eq!(
+            confirmation.timeout_secs,
+            ConfirmationConfig::default().timeout_secs
+        );
+        assert!(ChainConfig::from_json(
+            r#"{ "solana": { "confirmation": { "max_rebuilds": 50 } } }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_rejects_excessive_slippage() {
+        assert!(ChainConfig::from_json(
+            r#"{ "solana": { "jupiter_slippage_bps": 9000 } }"#
+        )
+        .is_err());
+        assert_eq!(
+            ChainConfig::from_json("{}").unwrap(),
+            ChainConfig::default()
+        );
+    }
+}
@@ -3,27 +3,52 @@ use rig::agent::Agent;
 use rig::providers::anthropic::completion::CompletionModel as AnthropicCompletionModel;
 
 use crate::{
-    common::{claude_agent_builder, PREAMBLE_COMMON},
-    cross_chain::tools::{ApproveToken, CheckApproval, GetQuote, Swap},
-    data::{FetchCandlesticks, FetchTopTokens},
+    common::PREAMBLE_COMMON,
+    cross_chain::tools::{
+        ApproveToken, CheckApproval, GetAddresses, GetQuote, Swap,
+    },
+    data::{
+        FetchCandlesticks, FetchTopTokens, GetMarketOverview,
+        GetPriceHistory, GetRecentTrades, GetSmartMoneyBuys,
+        GetTrendingTokens,
+    },
     dexscreener::tools::SearchOnDexScreener,
+    model::ModelProvider,
+    tool_allowlist::{AllowlistedTools, ToolAllowlist},
 };
 
 pub async fn create_cross_chain_agent(
     preamble: Option<String>,
+    model: &ModelProvider,
+    tools: &ToolAllowlist,
 ) -> Result<Agent<AnthropicCompletionModel>> {
     let preamble = preamble.unwrap_or(format!(
         "{} {}",
         "you are a cross-chain trading agent", PREAMBLE_COMMON,
     ));
-    let agent_builder = claude_agent_builder()
+    let agent_builder = model
+        .agent_builder()?
         .preamble(&preamble)
-        .tool(SearchOnDexScreener)
-        .tool(GetQuote)
-        .tool(Swap)
-        .tool(ApproveToken)
-        .tool(CheckApproval)
-        .tool(FetchCandlesticks)
-        .tool(FetchTopTokens);
+        .allowed_tool(GetAddresses, tools)
+        .allowed_tool(SearchOnDexScreener, tools)
+        .allowed_tool(GetQuote, tools)
+        .allowed_tool(Swap, tools)
+        .allowed_tool(ApproveToken, tools)
+        .allowed_tool(CheckApproval, tools)
+        .allowed_tool(FetchCandlesticks, tools)
+        .allowed_tool(GetPriceHistory, tools)
+        .allowed_tool(GetRecentTrades, tools)
+        .allowed_tool(FetchTopTokens, tools)
+        .allowed_tool(GetTrendingTokens, tools)
+        .allowed_tool(GetMarketOverview, tools)
+        .allowed_tool(GetSmartMoneyBuys, tools);
+    #[cfg(all(feature = "solana", feature = "evm"))]
+    let agent_builder = agent_builder
+        .allowed_tool(
+            crate::cross_chain::tools::DetectCrossChainArbitrage,
+            tools,
+        )
+        .allowed_tool(crate::cross_chain::tools::BridgeUsdcToEvm, tools)
+        .allowed_tool(crate::cross_chain::tools::BridgeUsdcFromEvm, tools);
     Ok(agent_builder.build())
 }
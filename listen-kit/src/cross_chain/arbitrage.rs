@@ -0,0 +1,121 @@
+use alloy::providers::Provider;
+use anyhow::{Context, Result};
+pub use arbitrage_costs::{ArbitrageCosts, DEFAULT_TRADE_SIZE_USD};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+
+use crate::evm::price::{fetch_evm_token_price, NATIVE_TOKEN_ADDRESS};
+use crate::evm::util::EvmProvider;
+use crate::solana::constants::WSOL;
+use crate::solana::fee_estimate::FeeOperation;
+use crate::solana::price::fetch_token_price;
+use crate::solana::priority_fee::PriorityFeeConfig;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArbitrageReport {
+    pub solana_price_usd: f64,
+    pub evm_price_usd: f64,
+    /// of the evm price over the solana one, positive when the token is
+    /// dearer on the evm chain
+    pub price_diff_pct: f64,
+    pub is_profitable_after_fees: bool,
+    /// of buying `trade_size_usd` where the token is cheaper, bridging it
+    /// and selling it on the other chain
+    pub estimated_profit_usd: f64,
+}
+
+pub fn arbitrage_report(
+    solana_price_usd: f64,
+    evm_price_usd: f64,
+    costs: &ArbitrageCosts,
+    trade_size_usd: f64,
+) -> Result<ArbitrageReport> {
+    let spread = arbitrage_costs::spread(
+        solana_price_usd,
+        evm_price_usd,
+        costs,
+        trade_size_usd,
+    )
+    .ok_or_else(|| {
+        anyhow::anyhow!(
+            "Can't compare prices of {} and {}",
+            solana_price_usd,
+            evm_price_usd
+        )
+    })?;
+    Ok(ArbitrageReport {
+        solana_price_usd,
+        evm_price_usd,
+        price_diff_pct: spread.price_diff_pct,
+        is_profitable_after_fees: spread.estimated_profit_usd > 0.,
+        estimated_profit_usd: spread.estimated_profit_usd,
+    })
+}
+
+/// Compares the price of `solana_mint` with its wormhole-bridged
+/// `evm_address` on `evm_chain_id`, the gas price is read from `provider`,
+/// which has to be of that chain
+pub async fn detect_cross_chain_arbitrage(
+    http: &Client,
+    rpc_client: &RpcClient,
+    provider: &EvmProvider,
+    priority_fee: &PriorityFeeConfig,
+    solana_mint: &str,
+    evm_chain_id: u64,
+    evm_address: &str,
+) -> Result<ArbitrageReport> {
+    let solana_price_usd =
+        fetch_token_price(solana_mint.to_string(), http).await?;
+    let evm_price_usd =
+        fetch_evm_token_price(http, evm_chain_id, evm_address).await?;
+    let costs = ArbitrageCosts::new(
+        provider
+            .get_gas_price()
+            .await
+            .context("Failed to get gas price")?,
+        fetch_evm_token_price(http, evm_chain_id, NATIVE_TOKEN_ADDRESS)
+            .await?,
+        FeeOperation::Swap.network_fee_lamports(
+            priority_fee.compute_unit_price_for(rpc_client, &[]).await?,
+        ),
+        fetch_token_price(WSOL.to_string(), http).await?,
+    );
+    arbitrage_report(
+        solana_price_usd,
+        evm_price_usd,
+        &costs,
+        DEFAULT_TRADE_SIZE_USD,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_COSTS: ArbitrageCosts = ArbitrageCosts {
+        bridge_usd: 0.,
+        gas_usd: 0.,
+    };
+
+    #[test]
+    fn test_arbitrage_report() {
+        let costs = ArbitrageCosts {
+            bridge_usd: 5.,
+            gas_usd: 3.,
+        };
+        // 2% dearer on the evm chain, $20 on $1000 less $8 of fees
+        let report = arbitrage_report(1., 1.02, &costs, 1_000.).unwrap();
+        assert!((report.price_diff_pct - 2.).abs() < 1e-9);
+        assert!((report.estimated_profit_usd - 12.).abs() < 1e-9);
+        assert!(report.is_profitable_after_fees);
+
+        // the other way around, the fees eat the 0.5%
+        let report = arbitrage_report(1.005, 1., &costs, 1_000.).unwrap();
+        assert!(report.price_diff_pct < 0.);
+        assert!((report.estimated_profit_usd - (-3.)).abs() < 1e-9);
+        assert!(!report.is_profitable_after_fees);
+
+        assert!(arbitrage_report(0., 1., &NO_COSTS, 1_000.).is_err());
+    }
+}
@@ -0,0 +1,747 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use alloy::network::TransactionBuilder;
+use alloy::primitives::{address, Address, Bytes, FixedBytes, U256};
+use alloy::rpc::types::TransactionRequest;
+use alloy::sol;
+use alloy::sol_types::SolCall;
+use anyhow::{anyhow, Context, Result};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::Transaction;
+use spl_associated_token_account::{
+    get_associated_token_address,
+    instruction::create_associated_token_account_idempotent,
+};
+
+use crate::evm::util::execute_evm_transaction;
+use crate::signer::SignerContext;
+use crate::solana::util::execute_solana_transaction_with_co_signers;
+
+pub const IRIS_API_URL: &str = "https://iris-api.circle.com";
+
+pub const MESSAGE_TRANSMITTER_PROGRAM: &str =
+    "CCTPmbSD7gX1bxKPAmg77w8oFzNFpaQiQUWD43TKaecd";
+pub const TOKEN_MESSENGER_MINTER_PROGRAM: &str =
+    "CCTPiPYPc6AsJuwueEnWgSgucamXDZwBd53dQ11YiKX3";
+pub const SOLANA_USDC_MINT: &str =
+    "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+/// the CCTP domain of solana, every chain has one besides its chain id
+pub const SOLANA_DOMAIN: u32 = 5;
+
+/// sha256("global:deposit_for_burn")[..8]
+const DEPOSIT_FOR_BURN_METHOD: [u8; 8] = [215, 60, 61, 46, 114, 55, 128, 176];
+/// sha256("global:receive_message")[..8]
+const RECEIVE_MESSAGE_METHOD: [u8; 8] = [38, 144, 127, 225, 31, 225, 238, 25];
+
+/// the header of a message and a burn message body
+const MIN_BURN_MESSAGE_LEN: usize = 116 + 132;
+
+/// each used nonces account covers this many nonces of a source domain
+const NONCES_PER_ACCOUNT: u64 = 6400;
+
+/// attestations of solana burns take seconds, the ones of evm burns wait
+/// for the block to be final, up to 20 minutes on ethereum
+pub const ATTESTATION_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const ATTESTATION_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// an evm chain CCTP bridges USDC to and from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CctpChain {
+    pub chain_id: u64,
+    pub domain: u32,
+    pub usdc: Address,
+    pub token_messenger: Address,
+    pub message_transmitter: Address,
+}
+
+pub const CCTP_CHAINS: [CctpChain; 3] = [
+    CctpChain {
+        chain_id: 1,
+        domain: 0,
+        usdc: address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+        token_messenger: address!("Bd3fa81B58Ba92a82136038B25aDec7066af3155"),
+        message_transmitter: address!(
+            "0a992d191DEeC32aFe36203Ad87D7d289a738F81"
+        ),
+    },
+    CctpChain {
+        chain_id: 42161,
+        domain: 3,
+        usdc: address!("af88d065e77c8cC2239327C5EDb3A432268e5831"),
+        token_messenger: address!("19330d10D9Cc8751218eaf51E8885D058642E08A"),
+        message_transmitter: address!(
+            "C30362313FBBA5cf9163F0bb16a0e01f01A896ca"
+        ),
+    },
+    CctpChain {
+        chain_id: 8453,
+        domain: 6,
+        usdc: address!("833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"),
+        token_messenger: address!("1682Ae6375C4E4A97e4B583BC394c861A46D8962"),
+        message_transmitter: address!(
+            "AD09780d193884d503182aD4588450C416D6F9D4"
+        ),
+    },
+];
+
+pub fn cctp_chain(chain_id: u64) -> Result<&'static CctpChain> {
+    CCTP_CHAINS
+        .iter()
+        .find(|chain| chain.chain_id == chain_id)
+        .ok_or_else(|| {
+            anyhow!(
+                "CCTP doesn't support chain {}, only ethereum (1), \
+                arbitrum (42161) and base (8453)",
+                chain_id
+            )
+        })
+}
+
+sol! {
+    interface ITokenMessenger {
+        function depositForBurn(uint256 amount, uint32 destinationDomain, bytes32 mintRecipient, address burnToken) external returns (uint64 nonce);
+    }
+
+    interface IMessageTransmitter {
+        function receiveMessage(bytes message, bytes attestation) external returns (bool success);
+    }
+}
+
+/// a burn on the source chain, attested by Circle and waiting to be minted
+/// on the destination chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CctpTransfer {
+    pub burn_transaction: String,
+    /// hex of the message and of Circle's attestation of it
+    pub message: String,
+    pub attestation: String,
+    /// the mint, when the destination signer was there to send it
+    pub receive_transaction: Option<String>,
+    /// the call to submit on the destination chain otherwise
+    pub receive_to: String,
+    pub receive_calldata: Option<String>,
+}
+
+/// the fields of a CCTP v1 message with a burn message body the mint needs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CctpMessage {
+    pub source_domain: u32,
+    pub destination_domain: u32,
+    pub nonce: u64,
+    /// the burnt token on the source chain, left padded
+    pub burn_token: [u8; 32],
+    pub mint_recipient: [u8; 32],
+}
+
+/// version(4) source_domain(4) destination_domain(4) nonce(8) sender(32)
+/// recipient(32) destination_caller(32), then the body, version(4)
+/// burn_token(32) mint_recipient(32) amount(32) message_sender(32)
+pub fn parse_message(message: &[u8]) -> Result<CctpMessage> {
+    if message.len() < MIN_BURN_MESSAGE_LEN {
+        return Err(anyhow!("CCTP message is {} bytes", message.len()));
+    }
+    let u32_at = |offset: usize| {
+        u32::from_be_bytes(message[offset..offset + 4].try_into().unwrap())
+    };
+    let bytes32_at = |offset: usize| -> [u8; 32] {
+        message[offset..offset + 32].try_into().unwrap()
+    };
+    Ok(CctpMessage {
+        source_domain: u32_at(4),
+        destination_domain: u32_at(8),
+        nonce: u64::from_be_bytes(message[12..20].try_into()?),
+        burn_token: bytes32_at(120),
+        mint_recipient: bytes32_at(152),
+    })
+}
+
+/// an evm address as the 32 bytes CCTP takes recipients in
+pub fn evm_mint_recipient(recipient: Address) -> Pubkey {
+    Pubkey::new_from_array(recipient.into_word().0)
+}
+
+fn program(id: &str) -> Pubkey {
+    Pubkey::from_str(id).unwrap()
+}
+
+fn pda(seeds: &[&[u8]], program_id: &str) -> Pubkey {
+    Pubkey::find_program_address(seeds, &program(program_id)).0
+}
+
+fn messenger_pda(seeds: &[&[u8]]) -> Pubkey {
+    pda(seeds, TOKEN_MESSENGER_MINTER_PROGRAM)
+}
+
+fn transmitter_pda(seeds: &[&[u8]]) -> Pubkey {
+    pda(seeds, MESSAGE_TRANSMITTER_PROGRAM)
+}
+
+/// the account marking nonces of `source_domain` as used, one per
+/// `NONCES_PER_ACCOUNT` nonces
+pub fn used_nonces_pda(source_domain: u32, nonce: u64) -> Pubkey {
+    let first_nonce =
+        nonce.saturating_sub(1) / NONCES_PER_ACCOUNT * NONCES_PER_ACCOUNT + 1;
+    // domains past 10 get a "-" between the domain and the nonce
+    let delimiter: &[u8] = if source_domain < 11 { b"" } else { b"-" };
+    transmitter_pda(&[
+        b"used_nonces",
+        source_domain.to_string().as_bytes(),
+        delimiter,
+        first_nonce.to_string().as_bytes(),
+    ])
+}
+
+/// burns `amount` of the owner's USDC for `mint_recipient` on
+/// `destination_domain`; `event_data` is a new keypair that has to sign,
+/// the program keeps the message in it
+pub fn deposit_for_burn_ix(
+    owner: &Pubkey,
+    event_data: &Pubkey,
+    amount: u64,
+    destination_domain: u32,
+    mint_recipient: &Pubkey,
+) -> Instruction {
+    let usdc = program(SOLANA_USDC_MINT);
+    let mut data = DEPOSIT_FOR_BURN_METHOD.to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.extend_from_slice(&destination_domain.to_le_bytes());
+    data.extend_from_slice(mint_recipient.as_ref());
+
+    Instruction {
+        program_id: program(TOKEN_MESSENGER_MINTER_PROGRAM),
+        accounts: vec![
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(*owner, true),
+            AccountMeta::new_readonly(
+                messenger_pda(&[b"sender_authority"]),
+                false,
+            ),
+            AccountMeta::new(
+                get_associated_token_address(owner, &usdc),
+                false,
+            ),
+            AccountMeta::new(
+                transmitter_pda(&[b"message_transmitter"]),
+                false,
+            ),
+            AccountMeta::new_readonly(
+                messenger_pda(&[b"token_messenger"]),
+                false,
+            ),
+            AccountMeta::new_readonly(
+                messenger_pda(&[
+                    b"remote_token_messenger",
+                    destination_domain.to_string().as_bytes(),
+                ]),
+                false,
+            ),
+            AccountMeta::new_readonly(
+                messenger_pda(&[b"token_minter"]),
+                false,
+            ),
+            AccountMeta::new(
+                messenger_pda(&[b"local_token", usdc.as_ref()]),
+                false,
+            ),
+            AccountMeta::new(usdc, false),
+            AccountMeta::new(*event_data, true),
+            AccountMeta::new_readonly(
+                program(MESSAGE_TRANSMITTER_PROGRAM),
+                false,
+            ),
+            AccountMeta::new_readonly(
+                program(TOKEN_MESSENGER_MINTER_PROGRAM),
+                false,
+            ),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(
+                solana_sdk::system_program::id(),
+                false,
+            ),
+            AccountMeta::new_readonly(
+                messenger_pda(&[b"__event_authority"]),
+                false,
+            ),
+            AccountMeta::new_readonly(
+                program(TOKEN_MESSENGER_MINTER_PROGRAM),
+                false,
+            ),
+        ],
+        data,
+    }
+}
+
+/// mints the USDC of an attested `message` to its recipient token account
+pub fn receive_message_ix(
+    payer: &Pubkey,
+    message: &[u8],
+    attestation: &[u8],
+) -> Result<Instruction> {
+    let parsed = parse_message(message)?;
+    if parsed.destination_domain != SOLANA_DOMAIN {
+        return Err(anyhow!(
+            "the message is for domain {}, not solana",
+            parsed.destination_domain
+        ));
+    }
+    let usdc = program(SOLANA_USDC_MINT);
+    let messenger = program(TOKEN_MESSENGER_MINTER_PROGRAM);
+    let source_domain = parsed.source_domain.to_string();
+
+    let mut data = RECEIVE_MESSAGE_METHOD.to_vec();
+    for bytes in [message, attestation] {
+        data.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        data.extend_from_slice(bytes);
+    }
+
+    Ok(Instruction {
+        program_id: program(MESSAGE_TRANSMITTER_PROGRAM),
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*payer, true),
+            AccountMeta::new_readonly(
+                transmitter_pda(&[
+                    b"message_transmitter_authority",
+                    messenger.as_ref(),
+                ]),
+                false,
+            ),
+            AccountMeta::new_readonly(
+                transmitter_pda(&[b"message_transmitter"]),
+                false,
+            ),
+            AccountMeta::new(
+                used_nonces_pda(parsed.source_domain, parsed.nonce),
+                false,
+            ),
+            AccountMeta::new_readonly(messenger, false),
+            AccountMeta::new_readonly(
+                solana_sdk::system_program::id(),
+                false,
+            ),
+            AccountMeta::new_readonly(
+                transmitter_pda(&[b"__event_authority"]),
+                false,
+            ),
+            AccountMeta::new_readonly(
+                program(MESSAGE_TRANSMITTER_PROGRAM),
+                false,
+            ),
+            // the accounts the token messenger minter handles the message
+            // with
+            AccountMeta::new_readonly(
+                messenger_pda(&[b"token_messenger"]),
+                false,
+            ),
+            AccountMeta::new_readonly(
+                messenger_pda(&[
+                    b"remote_token_messenger",
+                    source_domain.as_bytes(),
+                ]),
+                false,
+            ),
+            AccountMeta::new(messenger_pda(&[b"token_minter"]), false),
+            AccountMeta::new(
+                messenger_pda(&[b"local_token", usdc.as_ref()]),
+                false,
+            ),
+            AccountMeta::new_readonly(
+                messenger_pda(&[
+                    b"token_pair",
+                    source_domain.as_bytes(),
+                    &parsed.burn_token,
+                ]),
+                false,
+            ),
+            AccountMeta::new(
+                Pubkey::new_from_array(parsed.mint_recipient),
+                false,
+            ),
+            AccountMeta::new(
+                messenger_pda(&[b"custody", usdc.as_ref()]),
+                false,
+            ),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(
+                messenger_pda(&[b"__event_authority"]),
+                false,
+            ),
+            AccountMeta::new_readonly(messenger, false),
+        ],
+        data,
+    })
+}
+
+/// the signed message of a burn, hex encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attestation {
+    pub message: String,
+    pub attestation: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct IrisMessage {
+    message: String,
+    attestation: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct IrisMessages {
+    messages: Vec<IrisMessage>,
+}
+
+/// the attestation of the burn in `transaction`, `None` until Circle saw
+/// the burn final and signed it
+pub async fn fetch_attestation(
+    client: &Client,
+    api_base: &str,
+    source_domain: u32,
+    transaction: &str,
+) -> Result<Option<Attestation>> {
+    let response = client
+        .get(format!(
+            "{}/v1/messages/{}/{}",
+            api_base, source_domain, transaction
+        ))
+        .send()
+        .await?;
+    // the burn isn't indexed yet
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let messages = response
+        .error_for_status()?
+        .json::<IrisMessages>()
+        .await?
+        .messages;
+    Ok(messages
+        .into_iter()
+        .next()
+        .filter(|message| message.attestation.starts_with("0x"))
+        .map(|message| Attestation {
+            message: message.message,
+            attestation: message.attestation,
+        }))
+}
+
+pub async fn wait_for_attestation(
+    client: &Client,
+    api_base: &str,
+    source_domain: u32,
+    transaction: &str,
+    timeout: Duration,
+) -> Result<Attestation> {
+    let started = tokio::time::Instant::now();
+    loop {
+        match fetch_attestation(client, api_base, source_domain, transaction)
+            .await
+        {
+            Ok(Some(attestation)) => return Ok(attestation),
+            Ok(None) => {}
+            Err(e) => tracing::warn!(%transaction, %e, "attestation failed"),
+        }
+        if started.elapsed() >= timeout {
+            return Err(anyhow!(
+                "{} is burnt but Circle hasn't attested it after {} minutes, \
+                the USDC can still be minted once it does",
+                transaction,
+                timeout.as_secs() / 60
+            ));
+        }
+        tokio::time::sleep(ATTESTATION_POLL_INTERVAL).await;
+    }
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    Ok(hex::decode(hex.trim_start_matches("0x"))?)
+}
+
+pub fn receive_message_calldata(attestation: &Attestation) -> Result<Bytes> {
+    Ok(IMessageTransmitter::receiveMessageCall {
+        message: decode_hex(&attestation.message)?.into(),
+        attestation: decode_hex(&attestation.attestation)?.into(),
+    }
+    .abi_encode()
+    .into())
+}
+
+/// burns `amount` (in base units, 6 decimals) of the signer's USDC on
+/// solana, waits for the attestation and mints it to `recipient` on
+/// `destination_chain_id`; the mint is left to the user as calldata when
+/// the signer has no evm wallet
+pub async fn bridge_usdc_to_evm(
+    amount: u64,
+    destination_chain_id: u64,
+    recipient: Address,
+) -> Result<CctpTransfer> {
+    let chain = *cctp_chain(destination_chain_id)?;
+    // the message sent account is new, it signs the burn as well
+    let event_data = Arc::new(Keypair::new());
+    let event_data_account = event_data.pubkey();
+    let burn_transaction = execute_solana_transaction_with_co_signers(
+        move |owner| async move {
+            let ix = deposit_for_burn_ix(
+                &owner,
+                &event_data_account,
+                amount,
+                chain.domain,
+                &evm_mint_recipient(recipient),
+            );
+            Ok(Transaction::new_with_payer(&[ix], Some(&owner)).into())
+        },
+        vec![event_data],
+    )
+    .await?;
+
+    let attestation = wait_for_attestation(
+        &Client::new(),
+        IRIS_API_URL,
+        SOLANA_DOMAIN,
+        &burn_transaction,
+        ATTESTATION_TIMEOUT,
+    )
+    .await?;
+    let calldata = receive_message_calldata(&attestation)?;
+
+    let has_evm_signer = SignerContext::current()
+        .await
+        .addresses()
+        .contains_key("evm");
+    let receive_transaction = if has_evm_signer {
+        let input = calldata.clone();
+        Some(
+            execute_evm_transaction(move |owner| async move {
+                Ok(TransactionRequest::default()
+                    .with_from(owner)
+                    .with_to(chain.message_transmitter)
+                    .with_input(input)
+                    .with_chain_id(chain.chain_id))
+            })
+            .await
+            .context("the USDC is burnt but minting it failed")?,
+        )
+    } else {
+        None
+    };
+
+    Ok(CctpTransfer {
+        burn_transaction,
+        receive_to: chain.message_transmitter.to_string(),
+        receive_calldata: match receive_transaction {
+            Some(_) => None,
+            None => Some(calldata.to_string()),
+        },
+        receive_transaction,
+        message: attestation.message,
+        attestation: attestation.attestation,
+    })
+}
+
+/// burns `amount` (in base units, 6 decimals) of the signer's USDC on
+/// `source_chain_id`, waits for the attestation and mints it to the USDC
+/// account of `recipient` on solana
+pub async fn bridge_usdc_from_evm(
+    amount: u64,
+    source_chain_id: u64,
+    recipient: Pubkey,
+) -> Result<CctpTransfer> {
+    let chain = *cctp_chain(source_chain_id)?;
+    let recipient_account =
+        get_associated_token_address(&recipient, &program(SOLANA_USDC_MINT));
+    let burn_transaction = execute_evm_transaction(move |owner| async move {
+        let allowance = evm_approvals::get_allowance(
+            &chain.usdc.to_string(),
+            &owner.to_string(),
+            &chain.token_messenger.to_string(),
+            &chain.chain_id.to_string(),
+        )
+        .await?;
+        if allowance < amount as u128 {
+            return Err(anyhow!(
+                "Allowance not set, approve {} for {} first",
+                chain.usdc,
+                chain.token_messenger
+            ));
+        }
+        let call = ITokenMessenger::depositForBurnCall {
+            amount: U256::from(amount),
+            destinationDomain: SOLANA_DOMAIN,
+            mintRecipient: FixedBytes(recipient_account.to_bytes()),
+            burnToken: chain.usdc,
+        };
+        Ok(TransactionRequest::default()
+            .with_from(owner)
+            .with_to(chain.token_messenger)
+            .with_input(call.abi_encode())
+            .with_chain_id(chain.chain_id))
+    })
+    .await?;
+
+    let attestation = wait_for_attestation(
+        &Client::new(),
+        IRIS_API_URL,
+        chain.domain,
+        &burn_transaction,
+        ATTESTATION_TIMEOUT,
+    )
+    .await?;
+    let message = decode_hex(&attestation.message)?;
+    let signature = decode_hex(&attestation.attestation)?;
+    let receive_transaction =
+        execute_solana_transaction(move |owner| async move {
+            let ixs = [
+                create_associated_token_account_idempotent(
+                    &owner,
+                    &recipient,
+                    &program(SOLANA_USDC_MINT),
+                    &spl_token::id(),
+                ),
+                receive_message_ix(&owner, &message, &signature)?,
+            ];
+            Ok(Transaction::new_with_payer(&ixs, Some(&owner)).into())
+        })
+        .await
+        .context("the USDC is burnt but minting it failed")?;
+
+    Ok(CctpTransfer {
+        burn_transaction,
+        message: attestation.message,
+        attestation: attestation.attestation,
+        receive_transaction: Some(receive_transaction),
+        receive_to: MESSAGE_TRANSMITTER_PROGRAM.to_string(),
+        receive_calldata: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn burn_message(source_domain: u32, nonce: u64) -> Vec<u8> {
+        let mut message = Vec::new();
+        message.extend_from_slice(&0u32.to_be_bytes());
+        message.extend_from_slice(&source_domain.to_be_bytes());
+        message.extend_from_slice(&SOLANA_DOMAIN.to_be_bytes());
+        message.extend_from_slice(&nonce.to_be_bytes());
+        message.extend_from_slice(&[1; 32 * 3]);
+        // the burn message body
+        message.extend_from_slice(&0u32.to_be_bytes());
+        message.extend_from_slice(&[2; 32]);
+        message.extend_from_slice(&[3; 32]);
+        message.extend_from_slice(&U256::from(1_000_000).to_be_bytes::<32>());
+        message.extend_from_slice(&[4; 32]);
+        message
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let parsed = parse_message(&burn_message(6, 12_801)).unwrap();
+        assert_eq!(
+            parsed,
+            CctpMessage {
+                source_domain: 6,
+                destination_domain: SOLANA_DOMAIN,
+                nonce: 12_801,
+                burn_token: [2; 32],
+                mint_recipient: [3; 32],
+            }
+        );
+        assert!(parse_message(&[0; 100]).is_err());
+
+        // nonces 6401 to 12800 share an account, 12801 starts the next
+        assert_eq!(used_nonces_pda(6, 6401), used_nonces_pda(6, 12_800));
+        assert_ne!(used_nonces_pda(6, 12_800), used_nonces_pda(6, 12_801));
+
+        let ix = receive_message_ix(
+            &Pubkey::new_unique(),
+            &burn_message(6, 12_801),
+            &[5; 65],
+        )
+        .unwrap();
+        assert_eq!(ix.accounts[14].pubkey, Pubkey::new_from_array([3; 32]));
+        assert_eq!(ix.data[..8], RECEIVE_MESSAGE_METHOD);
+    }
+
+    #[test]
+    fn test_deposit_for_burn_ix() {
+        let owner = Pubkey::new_unique();
+        let event_data = Pubkey::new_unique();
+        let recipient = Address::repeat_byte(7);
+        let ix = deposit_for_burn_ix(
+            &owner,
+            &event_data,
+            1_500_000,
+            6,
+            &evm_mint_recipient(recipient),
+        );
+
+        assert_eq!(ix.data.len(), 8 + 8 + 4 + 32);
+        assert_eq!(ix.data[8..16], 1_500_000u64.to_le_bytes());
+        assert_eq!(ix.data[16..20], 6u32.to_le_bytes());
+        // the address is left padded to 32 bytes
+        assert_eq!(ix.data[20..32], [0; 12]);
+        assert_eq!(ix.data[32..], [7; 20]);
+        let signers: Vec<_> = ix
+            .accounts
+            .iter()
+            .filter(|account| account.is_signer)
+            .map(|account| account.pubkey)
+            .collect();
+        assert_eq!(signers, [owner, owner, event_data]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_attestation() {
+        let mut server = mockito::Server::new_async().await;
+        for (transaction, attestation) in
+            [("pending", "PENDING"), ("complete", "0x02")]
+        {
+            server
+                .mock(
+                    "GET",
+                    format!("/v1/messages/5/{}", transaction).as_str(),
+                )
+                .with_body(
+                    serde_json::json!({
+                        "messages": [{
+                            "message": "0x01",
+                            "attestation": attestation,
+                            "eventNonce": "1",
+                        }],
+                    })
+                    .to_string(),
+                )
+                .create_async()
+                .await;
+        }
+        server
+            .mock("GET", "/v1/messages/5/unknown")
+            .with_status(404)
+            .create_async()
+            .await;
+        let client = Client::new();
+
+        let pending = fetch_attestation(&client, &server.url(), 5, "pending");
+        assert_eq!(pending.await.unwrap(), None);
+        let unknown = fetch_attestation(&client, &server.url(), 5, "unknown");
+        assert_eq!(unknown.await.unwrap(), None);
+        let complete =
+            fetch_attestation(&client, &server.url(), 5, "complete")
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(complete.attestation, "0x02");
+        assert_eq!(
+            receive_message_calldata(&complete).unwrap()[..4],
+            IMessageTransmitter::receiveMessageCall::SELECTOR
+        );
+    }
+}
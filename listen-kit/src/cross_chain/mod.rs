@@ -1,2 +1,6 @@
 pub mod agent;
+#[cfg(all(feature = "solana", feature = "evm"))]
+pub mod arbitrage;
+#[cfg(all(feature = "solana", feature = "evm"))]
+pub mod cctp;
 pub mod tools;
@@ -1,6 +1,7 @@
 use anyhow::{anyhow, Result};
 use blockhash_cache::{inject_blockhash_into_encoded_tx, BLOCKHASH_CACHE};
 use rig_tool_macro::tool;
+use std::collections::HashMap;
 
 use crate::common::wrap_unsafe;
 use crate::signer::SignerContext;
@@ -156,6 +157,10 @@ pub async fn swap(
             from_token_address,
             amount,
             to_token_address,
+            None,
+            None,
+            None,
+            None,
         )
         .await;
     }
@@ -233,14 +238,14 @@ token_address is the ERC20 token contract address
 spender_address is the address that needs approval
 amount is the amount to check approval for (in token decimals)
 
-Returns 'true' if approved, 'false' if not approved
+Returns true if approved, false if not
 ")]
 pub async fn check_approval(
     token_address: String,
     spender_address: String,
     amount: String,
     from_chain_caip2: String,
-) -> Result<String> {
+) -> Result<bool> {
     let signer = SignerContext::current().await;
     let owner_address = signer.address();
 
@@ -255,7 +260,7 @@ pub async fn check_approval(
         .parse::<u128>()
         .map_err(|_| anyhow!("Invalid amount"))?;
 
-    Ok((allowance >= amount).to_string())
+    Ok(allowance >= amount)
 }
 
 #[tool(description = "
@@ -291,3 +296,179 @@ pub async fn approve_token(
 
     Ok("Approved".to_string())
 }
+
+#[tool(description = "
+Returns the addresses of the user's wallet keyed by chain, solana and evm.
+The evm address is the same on every evm chain, e.g. arbitrum and base
+
+Use it to tell the user where to send funds on each chain, chains without
+an address aren't supported by the wallet
+")]
+pub async fn get_addresses() -> Result<HashMap<String, String>> {
+    let addresses = SignerContext::current().await.addresses();
+    if addresses.is_empty() {
+        return Err(anyhow!("the signer has no addresses"));
+    }
+    Ok(addresses)
+}
+
+#[cfg(all(feature = "solana", feature = "evm"))]
+#[tool(description = "
+Checks whether a token bridged through wormhole trades at a different price
+on solana and on an evm chain, after the bridge and gas fees of buying it on
+the cheaper chain, bridging it and selling it on the other
+
+Params:
+solana_mint: string
+  public key of the token on solana
+evm_chain_id: number
+  chain id of the evm chain, e.g. 42161 for arbitrum or 8453 for base
+evm_address: string
+  address of the bridged token on that chain
+
+Return:
+solana_price_usd, evm_price_usd, price_diff_pct (positive when the token is
+dearer on the evm chain), is_profitable_after_fees and estimated_profit_usd
+of a $1000 trade
+")]
+pub async fn detect_cross_chain_arbitrage(
+    solana_mint: String,
+    evm_chain_id: u64,
+    evm_address: String,
+) -> Result<crate::cross_chain::arbitrage::ArbitrageReport> {
+    wrap_unsafe(move || async move {
+        crate::cross_chain::arbitrage::detect_cross_chain_arbitrage(
+            &reqwest::Client::new(),
+            &crate::solana::tools::create_rpc(),
+            &crate::evm::util::make_provider_for_chain(Some(evm_chain_id))?,
+            &crate::chain_config::chain_config().solana.priority_fee,
+            &solana_mint,
+            evm_chain_id,
+            &evm_address,
+        )
+        .await
+    })
+    .await
+}
+
+#[cfg(all(feature = "solana", feature = "evm"))]
+#[tool(description = "
+Bridges native USDC from solana to an evm chain through Circle CCTP: burns
+it on solana, waits for Circle's attestation (usually under a minute) and
+mints it to the recipient. The mint is sent from the user's evm wallet when
+there is one, otherwise the receive_calldata is returned for the user to
+submit to the receive_to contract themselves
+
+This function is dangerous, the USDC is burnt right away. ALWAYS tell the
+user the amount, the chain and the recipient and get them to confirm before
+calling it
+
+Params:
+amount: number
+  in base units, 6 decimals, e.g. 1000000 for 1 USDC
+destination_chain_id: number
+  1 for ethereum, 42161 for arbitrum or 8453 for base
+recipient_evm_address: string
+  the address the USDC is minted to
+
+Prefer it over swap for moving USDC, there is no bridge fee or wrapped token
+")]
+pub async fn bridge_usdc_to_evm(
+    amount: u64,
+    destination_chain_id: u64,
+    recipient_evm_address: String,
+) -> Result<crate::cross_chain::cctp::CctpTransfer> {
+    let recipient = recipient_evm_address.parse()?;
+    crate::cross_chain::cctp::bridge_usdc_to_evm(
+        amount,
+        destination_chain_id,
+        recipient,
+    )
+    .await
+}
+
+#[cfg(all(feature = "solana", feature = "evm"))]
+#[tool(description = "
+Bridges native USDC from an evm chain to solana through Circle CCTP: burns
+it on the evm chain, waits for Circle's attestation and mints it to the
+USDC account of the recipient on solana. The attestation waits for the burn
+to be final, which takes up to 20 minutes
+
+The token messenger of the chain needs a USDC allowance first, on a missing
+allowance approve the USDC for the token messenger named in the error with
+approve_token and call it again
+
+This function is dangerous, the USDC is burnt right away. ALWAYS tell the
+user the amount, the chain and the recipient and get them to confirm before
+calling it
+
+Params:
+amount: number
+  in base units, 6 decimals, e.g. 1000000 for 1 USDC
+source_chain_id: number
+  1 for ethereum, 42161 for arbitrum or 8453 for base
+recipient_solana_address: string, optional
+  the wallet the USDC is minted to, defaults to the user's wallet
+")]
+pub async fn bridge_usdc_from_evm(
+    amount: u64,
+    source_chain_id: u64,
+    recipient_solana_address: Option<String>,
+) -> Result<crate::cross_chain::cctp::CctpTransfer> {
+    let recipient = match recipient_solana_address {
+        Some(recipient) => recipient,
+        None => SignerContext::current().await.pubkey(),
+    };
+    crate::cross_chain::cctp::bridge_usdc_from_evm(
+        amount,
+        source_chain_id,
+        recipient.parse()?,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "http")]
+    #[tokio::test]
+    async fn test_get_addresses_of_multi_chain_signer() {
+        use crate::signer::privy::PrivySigner;
+        use privy::{auth::UserSession, config::PrivyConfig, Privy};
+        use std::sync::Arc;
+
+        let privy = Privy::new(PrivyConfig {
+            app_id: String::new(),
+            app_secret: String::new(),
+            verification_key: String::new(),
+        });
+        let signer = PrivySigner::new(
+            Arc::new(privy),
+            UserSession {
+                user_id: "user".to_string(),
+                session_id: "session".to_string(),
+                wallet_address: "0x0000000000000000000000000000000000000001"
+                    .to_string(),
+                pubkey: "11111111111111111111111111111112".to_string(),
+            },
+        );
+        let addresses =
+            SignerContext::with_signer(Arc::new(signer), get_addresses())
+                .await
+                .unwrap();
+        assert_eq!(
+            addresses,
+            HashMap::from([
+                (
+                    "solana".to_string(),
+                    "11111111111111111111111111111112".to_string()
+                ),
+                (
+                    "evm".to_string(),
+                    "0x0000000000000000000000000000000000000001".to_string()
+                ),
+            ])
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
 use rig_tool_macro::tool;
 use serde::{Deserialize, Serialize};
 
@@ -20,9 +21,107 @@ pub struct TopToken {
     pub market_cap: f64,
     pub volume_24h: f64,
     pub price_change_24h: f64,
+    #[serde(default)]
+    pub trade_count: u64,
+    #[serde(default)]
+    pub unique_traders: u64,
 }
 
-const API_BASE: &str = "https://api.listen-rs.com/v1/adapter";
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SmartMoneyActivity {
+    pub wallet: String,
+    pub mint: String,
+    pub action: String,
+    pub amount_usd: f64,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewToken {
+    pub name: String,
+    pub pubkey: String,
+    pub launched_at: u64,
+    pub market_cap: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MarketOverview {
+    pub total_tokens_tracked: u64,
+    pub total_volume_24h: f64,
+    pub total_swaps_24h: u64,
+    pub top_by_volume: Vec<TopToken>,
+    pub top_gainers: Vec<TopToken>,
+    pub top_losers: Vec<TopToken>,
+    pub new_tokens_1h: Vec<NewToken>,
+    pub graduations_24h: u64,
+    pub large_swaps_1h: u64,
+    pub computed_at: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenPriceChange {
+    pub mint: String,
+    pub name: String,
+    pub pct_change: f64,
+    pub volume_usd: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GainersLosersReport {
+    pub gainers: Vec<TokenPriceChange>,
+    pub losers: Vec<TokenPriceChange>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PriceHistory {
+    pub mint: String,
+    pub interval: String,
+    /// timestamp, open, high, low, close and volume (USD), oldest first
+    pub candles: Vec<(u64, f64, f64, f64, f64, f64)>,
+    /// from the open of the first candle to the close of the last
+    pub change_pct: Option<f64>,
+    /// older candles were left out to stay under the cap
+    pub truncated: bool,
+}
+
+/// a swap of the token as the indexer priced it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Trade {
+    pub timestamp: u64,
+    pub price: f64,
+    pub market_cap: f64,
+    /// in USD
+    pub swap_amount: f64,
+    pub is_buy: bool,
+    pub owner: String,
+    pub trader_type: String,
+}
+
+pub(crate) const API_BASE: &str = "https://api.listen-rs.com/v1/adapter";
+
+/// the Listen API, `LISTEN_DATA_API_URL` points the tools at another
+/// adapter, e.g. a local one
+pub(crate) static API_URL: Lazy<String> = Lazy::new(|| {
+    std::env::var("LISTEN_DATA_API_URL")
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| API_BASE.to_string())
+});
+
+/// the candles and trades are for the model context, a request for more is
+/// cut to the latest ones
+pub const MAX_PRICE_HISTORY_CANDLES: usize = 100;
+pub const MAX_RECENT_TRADES: usize = 50;
+pub const MAX_TRENDING_TOKENS: usize = 50;
+
+fn validate_interval(interval: &str) -> Result<()> {
+    match interval {
+        "15s" | "30s" | "1m" | "5m" | "15m" | "30m" | "1h" | "4h" | "1d" => {
+            Ok(())
+        }
+        _ => Err(anyhow!("Invalid interval: {}", interval)),
+    }
+}
 
 #[tool(description = "
 Fetch top tokens from the Listen API.
@@ -45,7 +144,6 @@ pub async fn fetch_top_tokens(
     timeframe: Option<String>,
     only_pumpfun_tokens: Option<String>,
 ) -> Result<Vec<TopToken>> {
-    let mut url = format!("{}/top-tokens", API_BASE);
     let mut query_params = vec![];
 
     if let Some(limit) = limit {
@@ -64,6 +162,14 @@ pub async fn fetch_top_tokens(
         query_params.push(format!("only_pumpfun_tokens={}", only_pumpfun));
     }
 
+    request_top_tokens(&API_URL, &query_params).await
+}
+
+async fn request_top_tokens(
+    api_base: &str,
+    query_params: &[String],
+) -> Result<Vec<TopToken>> {
+    let mut url = format!("{}/top-tokens", api_base);
     if !query_params.is_empty() {
         url = format!("{}?{}", url, query_params.join("&"));
     }
@@ -110,15 +216,11 @@ pub async fn fetch_candlesticks(
     interval: String,
     limit: Option<String>,
 ) -> Result<Vec<Candlestick>> {
-    // Validate interval
-    match interval.as_str() {
-        "15s" | "30s" | "1m" | "5m" | "15m" | "30m" | "1h" | "4h" | "1d" => {}
-        _ => return Err(anyhow!("Invalid interval: {}", interval)),
-    }
+    validate_interval(&interval)?;
 
     let mut url = format!(
         "{}/candlesticks?mint={}&interval={}",
-        API_BASE, mint, interval
+        *API_URL, mint, interval
     );
 
     if let Some(limit) = limit {
@@ -137,6 +239,259 @@ pub async fn fetch_candlesticks(
     Ok(candlesticks)
 }
 
+#[tool(description = "
+Fetch recent buys of \"smart money\" wallets from the Listen API, wallets
+that closed most of their positions of the past 30 days in profit.
+
+Parameters:
+- last_minutes (u64): How far back to look, in minutes
+
+Returns a list of buys with the wallet, token mint, USD amount and timestamp.
+")]
+pub async fn get_smart_money_buys(
+    last_minutes: u64,
+) -> Result<Vec<SmartMoneyActivity>> {
+    let url = format!(
+        "{}/smart-money?last_minutes={}&action=buy",
+        *API_URL, last_minutes
+    );
+
+    let response = reqwest::get(&url)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch smart money buys: {}", e))?;
+
+    let buys = response
+        .json::<Vec<SmartMoneyActivity>>()
+        .await
+        .map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+
+    Ok(buys)
+}
+
+#[tool(description = "
+Fetch an overview of the Solana token market from the Listen API, refreshed
+every minute.
+
+Returns the number of tokens tracked, the total 24h volume (USD) and swap
+count, the top 10 tokens by 24h volume, the top 10 gainers and losers by 24h
+price change, tokens launched in the last hour, pump.fun graduations in the
+last 24h and the count of swaps over $50k in the last hour.
+")]
+pub async fn get_market_overview() -> Result<MarketOverview> {
+    let url = format!("{}/market-overview", *API_URL);
+
+    let response = reqwest::get(&url)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch market overview: {}", e))?;
+
+    let overview = response
+        .json::<MarketOverview>()
+        .await
+        .map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+
+    Ok(overview)
+}
+
+#[tool(description = "
+Fetch the Solana tokens whose price rose and fell the most over a recent
+window from the Listen API, e.g. for \"what gained the most in the last
+hour?\". Tokens with fewer than 10 swaps in the window and tokens where a
+single wallet made most of the swaps (wash trading) are left out. Results
+are cached for a minute.
+
+Parameters:
+- time_window_minutes (u64): How far back to look, in minutes, at most 10080
+  (7 days)
+- limit (u32): Number of gainers and of losers to return, at most 50
+
+Returns the gainers and the losers, each with the mint, name, price change in
+percent and the USD volume over the window.
+")]
+pub async fn get_top_gainers_losers(
+    time_window_minutes: u64,
+    limit: u32,
+) -> Result<GainersLosersReport> {
+    let url = format!(
+        "{}/gainers-losers?window_minutes={}&limit={}",
+        *API_URL, time_window_minutes, limit
+    );
+
+    let response = reqwest::get(&url)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch gainers and losers: {}", e))?;
+    if !response.status().is_success() {
+        let error = response.text().await.unwrap_or_default();
+        return Err(anyhow!("Failed to fetch gainers and losers: {}", error));
+    }
+
+    let report = response
+        .json::<GainersLosersReport>()
+        .await
+        .map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+
+    Ok(report)
+}
+
+async fn fetch_price_history(
+    api_base: &str,
+    mint: &str,
+    interval: &str,
+    limit: Option<u32>,
+) -> Result<PriceHistory> {
+    validate_interval(interval)?;
+    let requested = limit.map_or(MAX_PRICE_HISTORY_CANDLES, |l| l as usize);
+    let limit = requested.min(MAX_PRICE_HISTORY_CANDLES);
+    let url = format!(
+        "{}/candlesticks?mint={}&interval={}&limit={}",
+        api_base, mint, interval, limit
+    );
+
+    let mut candles = reqwest::get(&url)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch price history: {}", e))?
+        .json::<Vec<Candlestick>>()
+        .await
+        .map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+    let truncated = requested > limit || candles.len() > limit;
+    candles.drain(..candles.len().saturating_sub(limit));
+
+    let change_pct = match (candles.first(), candles.last()) {
+        (Some(first), Some(last)) if first.open > 0. => {
+            Some((last.close / first.open - 1.) * 100.)
+        }
+        _ => None,
+    };
+    Ok(PriceHistory {
+        mint: mint.to_string(),
+        interval: interval.to_string(),
+        candles: candles
+            .into_iter()
+            .map(|c| (c.timestamp, c.open, c.high, c.low, c.close, c.volume))
+            .collect(),
+        change_pct,
+        truncated,
+    })
+}
+
+async fn fetch_recent_trades(
+    api_base: &str,
+    mint: &str,
+    limit: Option<u32>,
+) -> Result<Vec<Trade>> {
+    let limit = limit
+        .map_or(MAX_RECENT_TRADES, |l| l as usize)
+        .min(MAX_RECENT_TRADES);
+    let url = format!("{}/trades?mint={}&limit={}", api_base, mint, limit);
+
+    let mut trades = reqwest::get(&url)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch recent trades: {}", e))?
+        .json::<Vec<Trade>>()
+        .await
+        .map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+    trades.truncate(limit);
+
+    Ok(trades)
+}
+
+#[tool(description = "
+Fetch the price history of a token as OHLCV candles from the Listen API,
+e.g. for \"what has this token done in the last hour?\"
+
+Parameters:
+- mint (string): The token's mint/pubkey address
+- interval (string): The candle interval, one of 15s, 30s, 1m, 5m, 15m, 30m,
+  1h, 4h or 1d
+- limit (u32): Optional number of the latest candles to return, at most 100
+  (default: 100)
+
+Pick the interval so that 100 candles cover the period, e.g. 1m for the last
+hour or 15m for the last day.
+
+Returns the candles as [timestamp, open, high, low, close, volume_usd],
+oldest first, the price change in percent over them and whether older
+candles were left out.
+")]
+pub async fn get_price_history(
+    mint: String,
+    interval: String,
+    limit: Option<u32>,
+) -> Result<PriceHistory> {
+    fetch_price_history(&API_URL, &mint, &interval, limit).await
+}
+
+#[tool(description = "
+Fetch the latest swaps of a token from the Listen API, e.g. to see who is
+buying or selling it right now.
+
+Parameters:
+- mint (string): The token's mint/pubkey address
+- limit (u32): Optional number of swaps to return, at most 50 (default: 50)
+
+Returns the swaps newest first, each with the timestamp, price, market cap,
+USD amount, whether it was a buy, the wallet and whether the wallet looks
+like a human or a bot.
+")]
+pub async fn get_recent_trades(
+    mint: String,
+    limit: Option<u32>,
+) -> Result<Vec<Trade>> {
+    fetch_recent_trades(&API_URL, &mint, limit).await
+}
+
+fn trending_window_secs(window: &str) -> Result<u64> {
+    match window {
+        "5m" => Ok(5 * 60),
+        "15m" => Ok(15 * 60),
+        "1h" => Ok(60 * 60),
+        "6h" => Ok(6 * 60 * 60),
+        "24h" => Ok(24 * 60 * 60),
+        _ => Err(anyhow!("Invalid window: {}", window)),
+    }
+}
+
+async fn fetch_trending_tokens(
+    api_base: &str,
+    window: &str,
+    limit: u32,
+) -> Result<Vec<TopToken>> {
+    let window = trending_window_secs(window)?;
+    let limit = (limit as usize).clamp(1, MAX_TRENDING_TOKENS);
+    let query_params = [
+        format!("limit={}", limit),
+        format!("timeframe={}", window),
+        "only_pumpfun_tokens=false".to_string(),
+    ];
+
+    let mut tokens = request_top_tokens(api_base, &query_params).await?;
+    tokens.sort_by(|a, b| b.volume_24h.total_cmp(&a.volume_24h));
+    tokens.truncate(limit);
+
+    Ok(tokens)
+}
+
+#[tool(description = "
+Fetch the tokens trading the most right now from the Listen indexer, for
+questions like \"what's pumping right now?\"
+
+Parameters:
+- window (string): The window to rank the tokens over, one of 5m, 15m, 1h, 6h
+  or 24h
+- limit (u32): The number of tokens to return, at most 50
+
+Returns the tokens by volume over the window, highest first, each with the
+name, mint (pubkey), price, market cap, volume (USD, volume_24h), number of
+trades, number of unique traders and price change in percent
+(price_change_24h). Volume, trades and price change are over the window, not
+24h.
+")]
+pub async fn get_trending_tokens(
+    window: String,
+    limit: u32,
+) -> Result<Vec<TopToken>> {
+    fetch_trending_tokens(&API_URL, &window, limit).await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -164,4 +519,149 @@ mod tests {
         .await;
         println!("{:?}", candlesticks);
     }
+
+    #[tokio::test]
+    async fn test_price_history_is_capped() {
+        let mut server = mockito::Server::new_async().await;
+        // the adapter answering with more than it was asked for
+        let candles: Vec<_> = (0..1000u64)
+            .map(|i| Candlestick {
+                timestamp: i * 60,
+                open: 1.,
+                high: 2.5,
+                low: 0.5,
+                close: 2.,
+                volume: 10.,
+            })
+            .collect();
+        server
+            .mock("GET", "/candlesticks")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("interval".into(), "1m".into()),
+                mockito::Matcher::UrlEncoded("limit".into(), "100".into()),
+            ]))
+            .with_body(serde_json::to_string(&candles).unwrap())
+            .create_async()
+            .await;
+
+        let history =
+            fetch_price_history(&server.url(), "mint", "1m", Some(1000))
+                .await
+                .unwrap();
+        assert_eq!(history.candles.len(), MAX_PRICE_HISTORY_CANDLES);
+        assert!(history.truncated);
+        // the latest candles are kept
+        assert_eq!(history.candles.last().unwrap().0, 999 * 60);
+        assert_eq!(history.change_pct, Some(100.));
+
+        let json = serde_json::to_value(&history).unwrap();
+        assert_eq!(
+            json["candles"][0],
+            serde_json::json!([900 * 60, 1., 2.5, 0.5, 2., 10.])
+        );
+        assert_eq!(json["interval"], "1m");
+
+        assert!(fetch_price_history(&server.url(), "mint", "2m", None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_recent_trades_are_capped() {
+        let mut server = mockito::Server::new_async().await;
+        // price updates as the adapter returns them, with more fields than
+        // a trade keeps
+        let updates: Vec<_> = (0..200u64)
+            .map(|i| {
+                serde_json::json!({
+                    "name": "token",
+                    "pubkey": "mint",
+                    "price": 0.1,
+                    "market_cap": 100_000.,
+                    "timestamp": 1_000 - i,
+                    "slot": 1,
+                    "swap_amount": 25.,
+                    "owner": "wallet",
+                    "signature": "signature",
+                    "multi_hop": false,
+                    "is_buy": i % 2 == 0,
+                    "is_pump": true,
+                    "trader_type": "human",
+                })
+            })
+            .collect();
+        server
+            .mock("GET", "/trades")
+            .match_query(mockito::Matcher::Any)
+            .with_body(serde_json::to_string(&updates).unwrap())
+            .create_async()
+            .await;
+
+        let trades = fetch_recent_trades(&server.url(), "mint", Some(500))
+            .await
+            .unwrap();
+        assert_eq!(trades.len(), MAX_RECENT_TRADES);
+        assert_eq!(
+            serde_json::to_value(&trades[0]).unwrap(),
+            serde_json::json!({
+                "timestamp": 1_000,
+                "price": 0.1,
+                "market_cap": 100_000.,
+                "swap_amount": 25.,
+                "is_buy": true,
+                "owner": "wallet",
+                "trader_type": "human",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trending_tokens_by_volume() {
+        let mut server = mockito::Server::new_async().await;
+        let token = |mint: &str, volume: f64| {
+            serde_json::json!({
+                "name": mint.to_uppercase(),
+                "pubkey": mint,
+                "price": 0.01,
+                "market_cap": 1_000_000.,
+                "volume_24h": volume,
+                "price_change_24h": 12.5,
+                "trade_count": 120,
+                "unique_traders": 80,
+            })
+        };
+        server
+            .mock("GET", "/top-tokens")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded(
+                    "timeframe".into(),
+                    "3600".into(),
+                ),
+                mockito::Matcher::UrlEncoded("limit".into(), "2".into()),
+                mockito::Matcher::UrlEncoded(
+                    "only_pumpfun_tokens".into(),
+                    "false".into(),
+                ),
+            ]))
+            .with_body(
+                serde_json::json!([
+                    token("b", 500.),
+                    token("a", 9_000.),
+                    token("c", 50.),
+                ])
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let tokens =
+            fetch_trending_tokens(&server.url(), "1h", 2).await.unwrap();
+        let mints: Vec<_> =
+            tokens.iter().map(|t| t.pubkey.as_str()).collect();
+        assert_eq!(mints, ["a", "b"]);
+        assert_eq!(tokens[0].name, "A");
+        assert_eq!(tokens[0].unique_traders, 80);
+
+        assert!(fetch_trending_tokens(&server.url(), "2h", 2).await.is_err());
+    }
 }
@@ -0,0 +1,42 @@
+//! The `delegate` tool lets an agent hand a sub-task off to a child
+//! reasoning loop with its own preamble and a restricted tool set, e.g. a
+//! read-only research agent before executing a trade
+#![allow(non_upper_case_globals)]
+
+use anyhow::{anyhow, Result};
+use rig_tool_macro::tool;
+use serde::Deserialize;
+
+pub const DELEGATE_TOOL_NAME: &str = "delegate";
+
+/// root loop is depth 0, so this allows a child and a grandchild
+pub const MAX_DELEGATION_DEPTH: usize = 2;
+
+#[derive(Debug, Deserialize)]
+pub struct DelegateArgs {
+    pub agent: String,
+    pub task: String,
+}
+
+#[tool(description = "
+Delegates a task to a specialized sub-agent and returns its final answer.
+
+Params:
+agent: string
+  name of the sub-agent, e.g. research (read-only tools for looking up
+  tokens, prices, balances and charts)
+task: string
+  self-contained description of what the sub-agent should do, it does not
+  see the current conversation
+
+Use this to research a token before trading it.
+")]
+pub async fn delegate(agent: String, task: String) -> Result<String> {
+    // the reasoning loop intercepts this tool, it is only ever called
+    // directly if the agent runs outside of a loop
+    Err(anyhow!(
+        "delegating '{}' to {} requires a reasoning loop",
+        task,
+        agent
+    ))
+}
@@ -88,6 +88,34 @@ pub async fn search_ticker(ticker: String) -> Result<DexScreenerResponse> {
     Ok(dex_response)
 }
 
+/// looks up a single pair by its pool address
+pub async fn get_pair(
+    chain_id: &str,
+    pair_address: &str,
+) -> Result<Option<PairInfo>> {
+    let client = Client::new();
+    let url = format!(
+        "https://api.dexscreener.com/latest/dex/pairs/{}/{}",
+        chain_id, pair_address
+    );
+
+    let response = client.get(&url).send().await?;
+
+    if response.status().is_client_error() {
+        let res = response.text().await?;
+        tracing::error!("Error: {:?}", res);
+        return Err(anyhow::anyhow!("Error: {:?}", res));
+    }
+
+    let data: serde_json::Value = response.json().await?;
+
+    // pairs is null for unknown addresses
+    let pairs: Option<Vec<PairInfo>> =
+        serde_json::from_value(data["pairs"].clone())?;
+
+    Ok(pairs.and_then(|pairs| pairs.into_iter().next()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
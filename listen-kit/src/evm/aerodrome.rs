@@ -0,0 +1,167 @@
+use std::str::FromStr;
+
+use alloy::network::TransactionBuilder;
+use alloy::primitives::{address, Address, U256};
+use alloy::providers::Provider;
+use alloy::rpc::types::TransactionRequest;
+use alloy::sol;
+use alloy::sol_types::SolCall;
+use anyhow::{anyhow, Context, Result};
+
+use super::trade::check_allowance;
+use super::util::{EvmProvider, BASE_CHAIN_ID};
+
+/// the Aerodrome V2 router and pool factory on Base
+pub const AERODROME_ROUTER: Address =
+    address!("cF77a3Ba9A5CA399B7c97c74d54e5b1Beb874E43");
+pub const AERODROME_FACTORY: Address =
+    address!("420DD381b31aEf6683db6B902084cB0FFECe40Da");
+
+pub const DEFAULT_SLIPPAGE_BPS: u64 = 100;
+/// how long a signed swap stays valid
+const DEADLINE_SECS: i64 = 20 * 60;
+
+sol! {
+    #[sol(rpc)]
+    interface IAerodromeRouter {
+        struct Route {
+            address from;
+            address to;
+            bool stable;
+            address factory;
+        }
+
+        function getAmountsOut(uint256 amountIn, Route[] routes) external view returns (uint256[] amounts);
+        function swapExactTokensForTokens(uint256 amountIn, uint256 amountOutMin, Route[] routes, address to, uint256 deadline) external returns (uint256[] amounts);
+    }
+}
+
+/// the direct pool of the pair, stable or volatile
+pub fn route(
+    input: Address,
+    output: Address,
+    stable: bool,
+) -> Vec<IAerodromeRouter::Route> {
+    vec![IAerodromeRouter::Route {
+        from: input,
+        to: output,
+        stable,
+        factory: AERODROME_FACTORY,
+    }]
+}
+
+pub fn min_amount_out(amount_out: U256, slippage_bps: u64) -> U256 {
+    amount_out * U256::from(10_000 - slippage_bps.min(10_000))
+        / U256::from(10_000)
+}
+
+/// the output of swapping `amount_in` through `routes`, `None` when there
+/// is no such pool
+async fn quote(
+    provider: &EvmProvider,
+    amount_in: U256,
+    routes: Vec<IAerodromeRouter::Route>,
+) -> Option<U256> {
+    let amounts = IAerodromeRouter::new(AERODROME_ROUTER, provider)
+        .getAmountsOut(amount_in, routes)
+        .call()
+        .await
+        .ok()?
+        .amounts;
+    amounts.last().copied().filter(|out| !out.is_zero())
+}
+
+/// Swaps through the stable or volatile Aerodrome pool of the pair,
+/// whichever gives more
+pub async fn create_aerodrome_trade_tx(
+    input_token_address: String,
+    input_amount: String,
+    output_token_address: String,
+    provider: &EvmProvider,
+    owner: Address,
+    slippage_bps: u64,
+) -> Result<TransactionRequest> {
+    let input = Address::from_str(&input_token_address)?;
+    let output = Address::from_str(&output_token_address)?;
+    let amount_in = U256::from_str(&input_amount)?;
+
+    if !check_allowance(input, owner, AERODROME_ROUTER, provider)
+        .await
+        .context("Failed to check allowance")?
+    {
+        return Err(anyhow!("Allowance not set"));
+    }
+
+    let (volatile, stable) = tokio::join!(
+        quote(provider, amount_in, route(input, output, false)),
+        quote(provider, amount_in, route(input, output, true)),
+    );
+    let (amount_out, routes) = match (volatile, stable) {
+        (Some(volatile), Some(stable)) if stable > volatile => {
+            (stable, route(input, output, true))
+        }
+        (Some(volatile), _) => (volatile, route(input, output, false)),
+        (None, Some(stable)) => (stable, route(input, output, true)),
+        (None, None) => {
+            return Err(anyhow!(
+                "No Aerodrome pool for {} and {}",
+                input,
+                output
+            ))
+        }
+    };
+
+    let deadline = chrono::Utc::now().timestamp() + DEADLINE_SECS;
+    let call = IAerodromeRouter::swapExactTokensForTokensCall {
+        amountIn: amount_in,
+        amountOutMin: min_amount_out(amount_out, slippage_bps),
+        routes,
+        to: owner,
+        deadline: U256::from(deadline),
+    };
+    let gas_price = provider
+        .get_gas_price()
+        .await
+        .context("Failed to get gas price")?;
+
+    Ok(TransactionRequest::default()
+        .with_from(owner)
+        .with_to(AERODROME_ROUTER)
+        .with_input(call.abi_encode())
+        .with_chain_id(BASE_CHAIN_ID)
+        .with_gas_price(gas_price))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_min_amount_out() {
+        let out = U256::from(1_000_000);
+        assert_eq!(min_amount_out(out, 100), U256::from(990_000));
+        assert_eq!(min_amount_out(out, 0), out);
+        assert_eq!(min_amount_out(out, 20_000), U256::ZERO);
+    }
+
+    #[test]
+    fn test_swap_calldata() {
+        let (input, output) =
+            (Address::repeat_byte(1), Address::repeat_byte(2));
+        let call = IAerodromeRouter::swapExactTokensForTokensCall {
+            amountIn: U256::from(1),
+            amountOutMin: U256::ZERO,
+            routes: route(input, output, false),
+            to: Address::repeat_byte(3),
+            deadline: U256::from(60),
+        };
+        let decoded =
+            IAerodromeRouter::swapExactTokensForTokensCall::abi_decode(
+                &call.abi_encode(),
+                true,
+            )
+            .unwrap();
+        assert_eq!(decoded.routes[0].factory, AERODROME_FACTORY);
+        assert!(!decoded.routes[0].stable);
+    }
+}
@@ -3,27 +3,40 @@ use rig::agent::Agent;
 use rig::providers::anthropic::completion::CompletionModel as AnthropicCompletionModel;
 
 use super::tools::{
-    ApproveTokenForRouterSpend, GetErc20Balance, GetEthBalance, Trade,
-    TransferErc20, TransferEth, VerifySwapRouterHasAllowance, WalletAddress,
+    ApproveTokenForRouterSpend, GetErc20Balance, GetEthBalance,
+    GetEvmPortfolio, Trade, TransferErc20, TransferEth,
+    VerifySwapRouterHasAllowance, WalletAddress,
 };
-use crate::common::{claude_agent_builder, PREAMBLE_COMMON};
+use super::util::{default_chain_id, BASE_CHAIN_ID};
+use crate::common::PREAMBLE_COMMON;
+use crate::model::ModelProvider;
+use crate::tool_allowlist::{AllowlistedTools, ToolAllowlist};
 
 pub async fn create_evm_agent(
     preamble: Option<String>,
+    model: &ModelProvider,
+    tools: &ToolAllowlist,
 ) -> Result<Agent<AnthropicCompletionModel>> {
-    let preamble = preamble.unwrap_or(format!(
-        "{} {}",
-        "you are an ethereum trading agent", PREAMBLE_COMMON
-    ));
-    Ok(claude_agent_builder()
+    // `CREATE_EVM_AGENT_CHAIN=base` deploys an instance trading on Base
+    let role = match default_chain_id() {
+        Some(BASE_CHAIN_ID) => {
+            "you are a trading agent on Base, the tools default to it"
+        }
+        _ => "you are an ethereum trading agent",
+    };
+    let preamble =
+        preamble.unwrap_or(format!("{} {}", role, PREAMBLE_COMMON));
+    Ok(model
+        .agent_builder()?
         .preamble(&preamble)
-        .tool(Trade)
-        .tool(TransferEth)
-        .tool(TransferErc20)
-        .tool(WalletAddress)
-        .tool(GetEthBalance)
-        .tool(GetErc20Balance)
-        .tool(ApproveTokenForRouterSpend)
-        .tool(VerifySwapRouterHasAllowance)
+        .allowed_tool(Trade, tools)
+        .allowed_tool(TransferEth, tools)
+        .allowed_tool(TransferErc20, tools)
+        .allowed_tool(WalletAddress, tools)
+        .allowed_tool(GetEthBalance, tools)
+        .allowed_tool(GetErc20Balance, tools)
+        .allowed_tool(GetEvmPortfolio, tools)
+        .allowed_tool(ApproveTokenForRouterSpend, tools)
+        .allowed_tool(VerifySwapRouterHasAllowance, tools)
         .build())
 }
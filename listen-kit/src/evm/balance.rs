@@ -1,32 +1,125 @@
 use std::str::FromStr;
 
 use super::abi::IERC20;
-use alloy::primitives::Address;
+use super::price::{fetch_evm_token_price, NATIVE_TOKEN_ADDRESS};
+use alloy::primitives::utils::format_units;
+use alloy::primitives::{Address, U256};
 use alloy::providers::Provider;
 use anyhow::Result;
+use reqwest::Client;
+use serde::Serialize;
 
 use crate::evm::util::EvmProvider;
 
+/// one holding of an evm portfolio, `amount` in whole tokens
+#[derive(Debug, Serialize)]
+pub struct EvmPortfolioItem {
+    pub address: String,
+    pub amount: f64,
+    pub price: Option<f64>,
+    pub value_usd: Option<f64>,
+}
+
+/// a balance in base units as a string, `decimals` converts it to whole
+/// tokens
+#[derive(Debug, Serialize)]
+pub struct Balance {
+    pub amount: String,
+    pub decimals: u8,
+}
+
 pub async fn balance(
     provider: &EvmProvider,
     address: String,
-) -> Result<String> {
+) -> Result<Balance> {
     let balance = provider.get_balance(Address::from_str(&address)?).await?;
-    Ok(balance.to_string())
+    Ok(Balance {
+        amount: balance.to_string(),
+        decimals: 18,
+    })
 }
 
 pub async fn token_balance(
     owner: String,
     token_address: String,
     provider: &EvmProvider,
-) -> Result<String> {
-    let balance = IERC20::new(Address::from_str(&token_address)?, provider)
-        .balanceOf(Address::from_str(&owner)?)
-        .call()
-        .await?
-        ._0;
-
-    Ok(balance.to_string())
+) -> Result<Balance> {
+    let erc20 = IERC20::new(Address::from_str(&token_address)?, provider);
+    let balance =
+        erc20.balanceOf(Address::from_str(&owner)?).call().await?._0;
+    let decimals = erc20.decimals().call().await?._0;
+
+    Ok(Balance {
+        amount: balance.to_string(),
+        decimals,
+    })
+}
+
+async fn portfolio_item(
+    client: &Client,
+    chain_id: u64,
+    address: String,
+    balance: U256,
+    decimals: u8,
+) -> Result<EvmPortfolioItem> {
+    let amount = format_units(balance, decimals)?.parse::<f64>()?;
+    // a token without a defillama price is still held
+    let price = match fetch_evm_token_price(client, chain_id, &address).await
+    {
+        Ok(price) => Some(price),
+        Err(e) => {
+            tracing::warn!(%address, "no price: {}", e);
+            None
+        }
+    };
+    Ok(EvmPortfolioItem {
+        address,
+        amount,
+        price,
+        value_usd: price.map(|price| price * amount),
+    })
+}
+
+/// The native balance of `owner` and its non-zero balances of `tokens`,
+/// priced on `chain_id`. There is no index of the tokens an address holds
+/// on evm chains, so those to look at are passed in
+pub async fn portfolio(
+    provider: &EvmProvider,
+    client: &Client,
+    chain_id: u64,
+    owner: &str,
+    tokens: &[String],
+) -> Result<Vec<EvmPortfolioItem>> {
+    let owner = Address::from_str(owner)?;
+    let mut items = vec![
+        portfolio_item(
+            client,
+            chain_id,
+            NATIVE_TOKEN_ADDRESS.to_string(),
+            provider.get_balance(owner).await?,
+            18,
+        )
+        .await?,
+    ];
+    for token in tokens {
+        let erc20 = IERC20::new(Address::from_str(token)?, provider);
+        let balance = erc20.balanceOf(owner).call().await?._0;
+        if balance.is_zero() {
+            continue;
+        }
+        let decimals = erc20.decimals().call().await?._0;
+        items.push(
+            portfolio_item(
+                client,
+                chain_id,
+                token.clone(),
+                balance,
+                decimals,
+            )
+            .await?,
+        );
+    }
+    Ok(items)
 }
 
 #[cfg(test)]
@@ -43,6 +136,7 @@ mod tests {
         let balance = balance(&provider, signer.address().to_string())
             .await
             .unwrap();
-        assert_ne!(balance, "0");
+        assert_ne!(balance.amount, "0");
+        assert_eq!(balance.decimals, 18);
     }
 }
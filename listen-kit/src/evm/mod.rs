@@ -1,4 +1,5 @@
 pub mod abi;
+pub mod aerodrome;
 pub mod agent;
 pub mod balance;
 pub mod data;
@@ -1 +1,122 @@
-//
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// the native token of every evm chain, as defillama addresses it
+pub const NATIVE_TOKEN_ADDRESS: &str =
+    "0x0000000000000000000000000000000000000000";
+
+const DEFILLAMA_PRICES_URL: &str = "https://coins.llama.fi/prices/current";
+
+#[derive(Debug, Deserialize)]
+struct DefiLlamaPrices {
+    coins: HashMap<String, DefiLlamaPrice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DefiLlamaPrice {
+    price: f64,
+    decimals: Option<u8>,
+}
+
+/// the prefix defillama keys the tokens of `chain_id` with
+pub fn defillama_chain(chain_id: u64) -> Result<&'static str> {
+    match chain_id {
+        1 => Ok("ethereum"),
+        10 => Ok("optimism"),
+        56 => Ok("bsc"),
+        137 => Ok("polygon"),
+        8453 => Ok("base"),
+        42161 => Ok("arbitrum"),
+        _ => Err(anyhow!("Chain {} is not supported", chain_id)),
+    }
+}
+
+/// e.g. `base:0x833589fcd6edb6e08f4c7c32d4f71b54bda02913`
+pub fn defillama_coin_id(chain_id: u64, address: &str) -> Result<String> {
+    Ok(format!(
+        "{}:{}",
+        defillama_chain(chain_id)?,
+        address.to_lowercase()
+    ))
+}
+
+/// usd price of the token at `address` on `chain_id`, from defillama
+pub async fn fetch_evm_token_price(
+    client: &Client,
+    chain_id: u64,
+    address: &str,
+) -> Result<f64> {
+    Ok(fetch_defillama_price(client, chain_id, address)
+        .await?
+        .price)
+}
+
+/// usd price of one whole token at `address` on `chain_id` and its
+/// decimals, 18 for the native token
+pub async fn fetch_evm_token_price_and_decimals(
+    client: &Client,
+    chain_id: u64,
+    address: &str,
+) -> Result<(f64, u8)> {
+    let coin = fetch_defillama_price(client, chain_id, address).await?;
+    let decimals = match coin.decimals {
+        Some(decimals) => decimals,
+        None if address == NATIVE_TOKEN_ADDRESS => 18,
+        None => {
+            return Err(anyhow!(
+                "No decimals for {} on chain {}",
+                address,
+                chain_id
+            ))
+        }
+    };
+    Ok((coin.price, decimals))
+}
+
+async fn fetch_defillama_price(
+    client: &Client,
+    chain_id: u64,
+    address: &str,
+) -> Result<DefiLlamaPrice> {
+    let coin_id = defillama_coin_id(chain_id, address)?;
+    let url = format!("{}/{}", DEFILLAMA_PRICES_URL, coin_id);
+    let res = client
+        .get(url)
+        .header("accept", "application/json")
+        .send()
+        .await?;
+    let data = res.json::<DefiLlamaPrices>().await?;
+    tracing::debug!(?data, "fetch_evm_token_price");
+    // the keys keep the casing of the request, matched loosely anyway
+    data.coins
+        .into_iter()
+        .find(|(id, _)| id.eq_ignore_ascii_case(&coin_id))
+        .map(|(_, coin)| coin)
+        .ok_or_else(|| {
+            anyhow!("No price for {} on chain {}", address, chain_id)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defillama_coin_id() {
+        assert_eq!(
+            defillama_coin_id(
+                8453,
+                "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"
+            )
+            .unwrap(),
+            "base:0x833589fcd6edb6e08f4c7c32d4f71b54bda02913"
+        );
+        assert_eq!(
+            defillama_coin_id(1, NATIVE_TOKEN_ADDRESS).unwrap(),
+            format!("ethereum:{}", NATIVE_TOKEN_ADDRESS)
+        );
+        assert!(defillama_coin_id(999, NATIVE_TOKEN_ADDRESS).is_err());
+    }
+}
@@ -11,25 +11,44 @@ use uniswap_sdk_core::prelude::SWAP_ROUTER_02_ADDRESSES;
 use crate::common::wrap_unsafe;
 use crate::signer::SignerContext;
 
-use super::balance::{balance, token_balance};
+use super::aerodrome::{
+    create_aerodrome_trade_tx, AERODROME_ROUTER, DEFAULT_SLIPPAGE_BPS,
+};
+use super::balance::{
+    balance, portfolio, token_balance, Balance, EvmPortfolioItem,
+};
 use super::trade::{check_allowance, create_approve_tx, create_trade_tx};
 use super::transfer::{create_transfer_erc20_tx, create_transfer_eth_tx};
-use super::util::{execute_evm_transaction, make_provider};
+use super::util::{
+    execute_evm_transaction, make_provider_for_chain, BASE_CHAIN_ID,
+};
+
+/// swaps on Base go through Aerodrome, elsewhere through Uniswap
+fn router_address(chain_id: u64) -> Result<Address> {
+    if chain_id == BASE_CHAIN_ID {
+        return Ok(AERODROME_ROUTER);
+    }
+    Ok(*SWAP_ROUTER_02_ADDRESSES
+        .get(&chain_id)
+        .context("Router address not found")?)
+}
 
 #[tool(description = "
 Use this function to verify if a given token has swap router allowance
 
 On EVM, before swapping a token, this function has to be called to verify swap would be successful
+
+chain_id: number, optional
+  8453 for Base, the default chain of the agent otherwise
 ")]
 pub async fn verify_swap_router_has_allowance(
     token_address: String,
+    chain_id: Option<u64>,
 ) -> Result<bool> {
     let owner = SignerContext::current().await.address();
     wrap_unsafe(move || async move {
-        let provider = make_provider()?;
-        let router_address = *SWAP_ROUTER_02_ADDRESSES
-            .get(&provider.get_chain_id().await?)
-            .context("Router address not found")?;
+        let provider = make_provider_for_chain(chain_id)?;
+        let router_address = router_address(provider.get_chain_id().await?)?;
 
         check_allowance(
             Address::from_str(&token_address)?,
@@ -47,17 +66,19 @@ Use this function to approve a token for swap router spend
 
 If the verify_swap_router_has_allowance tool returns false, or the swap fails with 
 allowance error, call this function to approve the token for swap router spend
+
+chain_id: number, optional
+  8453 for Base, the default chain of the agent otherwise
 ")]
 pub async fn approve_token_for_router_spend(
     input_token_address: String,
+    chain_id: Option<u64>,
 ) -> Result<String> {
-    let provider = make_provider()?;
+    let provider = make_provider_for_chain(chain_id)?;
     let router_address = wrap_unsafe(move || async move {
-        let router_address = *SWAP_ROUTER_02_ADDRESSES
-            .get(&make_provider()?.get_chain_id().await?)
-            .context("Router address not found")?;
-
-        Ok(router_address)
+        router_address(
+            make_provider_for_chain(chain_id)?.get_chain_id().await?,
+        )
     })
     .await?;
 
@@ -74,14 +95,19 @@ pub async fn approve_token_for_router_spend(
 }
 
 #[tool(description = "
-Use this function to swap any tokens on EVM using Uniswap
+Use this function to swap any tokens on EVM, using Aerodrome on Base and
+Uniswap elsewhere
 
 The function supports tokens that are on the same chain
+
+chain_id: number, optional
+  8453 for Base, the default chain of the agent otherwise
 ")]
 pub async fn trade(
     input_token_address: String,
     input_amount: String,
     output_token_address: String,
+    chain_id: Option<u64>,
 ) -> Result<String> {
     let input_amount = if input_amount.contains('.') {
         parse_ether(&input_amount)?.to_string()
@@ -89,11 +115,23 @@ pub async fn trade(
         input_amount
     };
     execute_evm_transaction(move |owner| async move {
+        let provider = make_provider_for_chain(chain_id)?;
+        if provider.get_chain_id().await? == BASE_CHAIN_ID {
+            return create_aerodrome_trade_tx(
+                input_token_address,
+                input_amount,
+                output_token_address,
+                &provider,
+                owner,
+                DEFAULT_SLIPPAGE_BPS,
+            )
+            .await;
+        }
         create_trade_tx(
             input_token_address,
             input_amount,
             output_token_address,
-            &make_provider()?,
+            &provider,
             owner,
         )
         .await
@@ -114,8 +152,13 @@ pub async fn transfer_eth(
     amount: String,
 ) -> Result<String> {
     execute_evm_transaction(move |owner| async move {
-        create_transfer_eth_tx(recipient, amount, &make_provider()?, owner)
-            .await
+        create_transfer_eth_tx(
+            recipient,
+            amount,
+            &make_provider_for_chain(None)?,
+            owner,
+        )
+        .await
     })
     .await
 }
@@ -138,7 +181,7 @@ pub async fn transfer_erc20(
             token_address,
             recipient,
             amount,
-            &make_provider()?,
+            &make_provider_for_chain(None)?,
             owner,
         )
         .await
@@ -153,21 +196,79 @@ pub async fn wallet_address() -> Result<String> {
     Ok(SignerContext::current().await.address())
 }
 
-#[tool]
-pub async fn get_eth_balance(address: String) -> Result<String> {
-    wrap_unsafe(
-        move || async move { balance(&make_provider()?, address).await },
-    )
+#[tool(description = "
+Returns the ETH balance of an address in wei, with its 18 decimals
+
+chain_id: number, optional
+  8453 for Base, the default chain of the agent otherwise
+")]
+pub async fn get_eth_balance(
+    address: String,
+    chain_id: Option<u64>,
+) -> Result<Balance> {
+    wrap_unsafe(move || async move {
+        balance(&make_provider_for_chain(chain_id)?, address).await
+    })
     .await
 }
 
-#[tool]
+#[tool(description = "
+Returns the balance of an ERC20 token of an address in base units, with the
+decimals of the token
+
+chain_id: number, optional
+  8453 for Base, the default chain of the agent otherwise
+")]
 pub async fn get_erc20_balance(
     token_address: String,
     address: String,
-) -> Result<String> {
+    chain_id: Option<u64>,
+) -> Result<Balance> {
     wrap_unsafe(move || async move {
-        token_balance(address, token_address, &make_provider()?).await
+        token_balance(
+            address,
+            token_address,
+            &make_provider_for_chain(chain_id)?,
+        )
+        .await
+    })
+    .await
+}
+
+#[tool(description = "
+Returns the portfolio of the wallet on an evm chain: its ETH and its
+balances of the given tokens, in whole tokens with usd prices where
+DeFiLlama has them. Evm chains have no index of the tokens a wallet holds,
+so the tokens to look at have to be named
+
+token_addresses: string
+  comma separated ERC20 addresses, e.g. USDC and the tokens the user
+  traded, empty for the ETH alone. Tokens with no balance are left out
+chain_id: number, optional
+  8453 for Base, the default chain of the agent otherwise
+")]
+pub async fn get_evm_portfolio(
+    token_addresses: String,
+    chain_id: Option<u64>,
+) -> Result<Vec<EvmPortfolioItem>> {
+    let owner = SignerContext::current().await.address();
+    let tokens = token_addresses
+        .split(',')
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(String::from)
+        .collect::<Vec<_>>();
+    wrap_unsafe(move || async move {
+        let provider = make_provider_for_chain(chain_id)?;
+        let chain_id = provider.get_chain_id().await?;
+        portfolio(
+            &provider,
+            &reqwest::Client::new(),
+            chain_id,
+            &owner,
+            &tokens,
+        )
+        .await
     })
     .await
 }
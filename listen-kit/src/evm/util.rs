@@ -21,6 +21,39 @@ pub fn make_provider() -> Result<EvmProvider> {
     Ok(ProviderBuilder::new().on_http(rpc_url.parse()?))
 }
 
+pub const ETHEREUM_CHAIN_ID: u64 = 1;
+pub const BASE_CHAIN_ID: u64 = 8453;
+
+/// the chain of the evm agent when a tool call names none,
+/// `CREATE_EVM_AGENT_CHAIN`, e.g. `base` for an agent instance on Base
+pub fn default_chain_id() -> Option<u64> {
+    match std::env::var("CREATE_EVM_AGENT_CHAIN").ok()?.as_str() {
+        "base" => Some(BASE_CHAIN_ID),
+        chain => chain.parse().ok(),
+    }
+}
+
+/// the provider of `chain_id`, `BASE_RPC_URL` for Base and
+/// `ETHEREUM_RPC_URL` for Ethereum or without a chain. Other chains have no
+/// rpc configured and fail rather than reading Ethereum in their place
+pub fn make_provider_for_chain(chain_id: Option<u64>) -> Result<EvmProvider> {
+    match chain_id.or_else(default_chain_id) {
+        Some(BASE_CHAIN_ID) => {
+            let rpc_url = std::env::var("BASE_RPC_URL")
+                .map_err(|_| anyhow!("BASE_RPC_URL env var not set"))?;
+            Ok(ProviderBuilder::new().on_http(rpc_url.parse()?))
+        }
+        None | Some(ETHEREUM_CHAIN_ID) => make_provider(),
+        Some(chain_id) => Err(anyhow!(
+            "Chain {} is not configured, only Ethereum ({}) and Base ({}) \
+             are",
+            chain_id,
+            ETHEREUM_CHAIN_ID,
+            BASE_CHAIN_ID
+        )),
+    }
+}
+
 pub fn make_signer() -> Result<PrivateKeySigner> {
     Ok(PrivateKeySigner::from_str(&env("ETHEREUM_PRIVATE_KEY"))?)
 }
@@ -62,3 +95,14 @@ where
     .await
     .map_err(|e| anyhow!("{:#?}", e))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unconfigured_chain_has_no_provider() {
+        let err = make_provider_for_chain(Some(42161)).unwrap_err();
+        assert!(err.to_string().contains("not configured"), "{}", err);
+    }
+}
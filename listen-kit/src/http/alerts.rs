@@ -0,0 +1,538 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::webhooks::{
+    uuid_v4, DeliveryClient, PriceUpdate, PRICE_UPDATES_CHANNEL,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PriceCondition {
+    Above,
+    Below,
+}
+
+impl PriceCondition {
+    pub fn is_met(&self, price: f64, threshold: f64) -> bool {
+        match self {
+            Self::Above => price >= threshold,
+            Self::Below => price <= threshold,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlertRequest {
+    pub mint: String,
+    pub condition: PriceCondition,
+    /// USD price the condition compares against
+    pub price: f64,
+    pub webhook_url: String,
+    /// fires again on a later crossing once this long has passed, the
+    /// alert fires once and is disabled without it
+    #[serde(default)]
+    pub cooldown_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertStatus {
+    Active,
+    /// fired and done, alerts without a cooldown end up here
+    Triggered,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceAlert {
+    pub id: String,
+    pub user_id: String,
+    pub mint: String,
+    pub condition: PriceCondition,
+    pub price: f64,
+    pub webhook_url: String,
+    pub cooldown_secs: Option<u64>,
+    pub status: AlertStatus,
+    /// unix seconds
+    pub last_triggered_at: Option<u64>,
+    /// whether the last price seen met the condition, the alert fires
+    /// when it turns true, not for every update past the threshold
+    #[serde(default)]
+    pub condition_met: Option<bool>,
+    pub created_at: String,
+}
+
+impl PriceAlert {
+    pub fn new(user_id: String, request: AlertRequest) -> Self {
+        Self {
+            id: uuid_v4(),
+            user_id,
+            mint: request.mint,
+            condition: request.condition,
+            price: request.price,
+            webhook_url: request.webhook_url,
+            cooldown_secs: request.cooldown_secs,
+            status: AlertStatus::Active,
+            last_triggered_at: None,
+            condition_met: None,
+            created_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// whether `update` fires the alert at `now` (unix seconds), i.e. the
+    /// price crossed the threshold since the last update. The first update
+    /// only tells the side the price starts on
+    pub fn should_fire(&self, update: &PriceUpdate, now: u64) -> bool {
+        if self.status != AlertStatus::Active
+            || update.pubkey != self.mint
+            || self.condition_met != Some(false)
+            || !self.condition.is_met(update.price, self.price)
+        {
+            return false;
+        }
+        match (self.last_triggered_at, self.cooldown_secs) {
+            (Some(last), Some(cooldown)) => now >= last + cooldown,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AlertPayload<'a> {
+    alert_id: &'a str,
+    mint: &'a str,
+    condition: PriceCondition,
+    threshold: f64,
+    price: f64,
+    timestamp: u64,
+    signature: &'a str,
+}
+
+/// Alerts by id, and the active ones by mint for the price listener
+#[async_trait]
+pub trait AlertStore: Send + Sync {
+    async fn save(&self, alert: &PriceAlert) -> Result<()>;
+
+    async fn get(&self, id: &str) -> Result<Option<PriceAlert>>;
+
+    async fn delete(&self, id: &str) -> Result<()>;
+
+    async fn active_for_mint(&self, mint: &str) -> Result<Vec<PriceAlert>>;
+
+    /// Claims a firing of `alert` for `cooldown_secs`, or for good without
+    /// one. Only the first of the listeners racing for it gets true.
+    async fn claim(&self, alert: &PriceAlert) -> Result<bool>;
+}
+
+#[derive(Default)]
+pub struct MemoryAlertStore {
+    alerts: Mutex<HashMap<String, PriceAlert>>,
+    claims: Mutex<HashMap<String, u64>>,
+}
+
+#[async_trait]
+impl AlertStore for MemoryAlertStore {
+    async fn save(&self, alert: &PriceAlert) -> Result<()> {
+        self.alerts
+            .lock()
+            .unwrap()
+            .insert(alert.id.clone(), alert.clone());
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<PriceAlert>> {
+        Ok(self.alerts.lock().unwrap().get(id).cloned())
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        self.alerts.lock().unwrap().remove(id);
+        self.claims.lock().unwrap().remove(id);
+        Ok(())
+    }
+
+    async fn active_for_mint(&self, mint: &str) -> Result<Vec<PriceAlert>> {
+        Ok(self
+            .alerts
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|alert| {
+                alert.mint == mint && alert.status == AlertStatus::Active
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn claim(&self, alert: &PriceAlert) -> Result<bool> {
+        let now = chrono::Utc::now().timestamp() as u64;
+        let mut claims = self.claims.lock().unwrap();
+        if claims.get(&alert.id).is_some_and(|until| *until > now) {
+            return Ok(false);
+        }
+        let until = alert
+            .cooldown_secs
+            .map_or(u64::MAX, |cooldown| now + cooldown);
+        claims.insert(alert.id.clone(), until);
+        Ok(true)
+    }
+}
+
+/// Alerts as json, a set of the active alert ids per mint
+pub struct RedisAlertStore {
+    client: redis::Client,
+}
+
+impl RedisAlertStore {
+    pub fn new(redis_url: &str) -> Result<Self> {
+        Ok(Self {
+            client: redis::Client::open(redis_url)?,
+        })
+    }
+
+    fn alert_key(id: &str) -> String {
+        format!("alert:{}", id)
+    }
+
+    fn mint_key(mint: &str) -> String {
+        format!("alerts:mint:{}", mint)
+    }
+
+    fn claim_key(id: &str) -> String {
+        format!("alert:claim:{}", id)
+    }
+
+    async fn connection(&self) -> Result<redis::aio::MultiplexedConnection> {
+        Ok(self.client.get_multiplexed_async_connection().await?)
+    }
+}
+
+#[async_trait]
+impl AlertStore for RedisAlertStore {
+    async fn save(&self, alert: &PriceAlert) -> Result<()> {
+        let mut conn = self.connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .set(Self::alert_key(&alert.id), serde_json::to_string(alert)?)
+            .ignore();
+        if alert.status == AlertStatus::Active {
+            pipe.sadd(Self::mint_key(&alert.mint), &alert.id).ignore();
+        } else {
+            pipe.srem(Self::mint_key(&alert.mint), &alert.id).ignore();
+        }
+        let _: () = pipe.query_async(&mut conn).await?;
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<PriceAlert>> {
+        let mut conn = self.connection().await?;
+        let raw: Option<String> = conn.get(Self::alert_key(id)).await?;
+        match raw {
+            Some(raw) => Ok(Some(serde_json::from_str(&raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        let Some(alert) = self.get(id).await? else {
+            return Ok(());
+        };
+        let mut conn = self.connection().await?;
+        let _: () = redis::pipe()
+            .atomic()
+            .del(Self::alert_key(id))
+            .ignore()
+            .del(Self::claim_key(id))
+            .ignore()
+            .srem(Self::mint_key(&alert.mint), id)
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn active_for_mint(&self, mint: &str) -> Result<Vec<PriceAlert>> {
+        let mut conn = self.connection().await?;
+        let ids: Vec<String> = conn.smembers(Self::mint_key(mint)).await?;
+        let mut alerts = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(alert) = self.get(&id).await? {
+                if alert.status == AlertStatus::Active {
+                    alerts.push(alert);
+                }
+            }
+        }
+        Ok(alerts)
+    }
+
+    async fn claim(&self, alert: &PriceAlert) -> Result<bool> {
+        let mut conn = self.connection().await?;
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(Self::claim_key(&alert.id)).arg(1).arg("NX");
+        if let Some(cooldown) = alert.cooldown_secs {
+            cmd.arg("EX").arg(cooldown.max(1));
+        }
+        let claimed: Option<String> = cmd.query_async(&mut conn).await?;
+        Ok(claimed.is_some())
+    }
+}
+
+/// Background task firing the webhooks of the alerts a price update meets
+pub struct AlertMonitor {
+    store: Arc<dyn AlertStore>,
+    delivery: DeliveryClient,
+}
+
+impl AlertMonitor {
+    pub fn new(store: Arc<dyn AlertStore>) -> Result<Self> {
+        Ok(Self {
+            store,
+            delivery: DeliveryClient::new()?,
+        })
+    }
+
+    /// subscribes to the price updates channel of `redis_url`
+    pub async fn run(self, redis_url: &str) -> Result<()> {
+        let client = redis::Client::open(redis_url)?;
+        let mut pubsub = client.get_async_pubsub().await?;
+        pubsub.subscribe(PRICE_UPDATES_CHANNEL).await?;
+        let mut messages = pubsub.on_message();
+
+        while let Some(msg) = messages.next().await {
+            let payload: String = match msg.get_payload() {
+                Ok(payload) => payload,
+                Err(e) => {
+                    tracing::error!("Failed to get message payload: {}", e);
+                    continue;
+                }
+            };
+            let update: PriceUpdate = match serde_json::from_str(&payload) {
+                Ok(update) => update,
+                Err(e) => {
+                    tracing::error!("Failed to parse price update: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = self.handle_update(&update).await {
+                tracing::error!("Failed to check price alerts: {}", e);
+            }
+        }
+
+        Err(anyhow!("Price updates subscription closed"))
+    }
+
+    /// fires every alert on the mint of `update` whose threshold it
+    /// crossed, returns how many fired. The webhooks are delivered in the
+    /// background, a slow one mustn't hold up the next updates
+    pub async fn handle_update(&self, update: &PriceUpdate) -> Result<usize> {
+        let now = chrono::Utc::now().timestamp() as u64;
+        let mut fired = 0;
+        for mut alert in self.store.active_for_mint(&update.pubkey).await? {
+            let should_fire = alert.should_fire(update, now)
+                && self.store.claim(&alert).await?;
+            let met = alert.condition.is_met(update.price, alert.price);
+            if !should_fire && alert.condition_met == Some(met) {
+                continue;
+            }
+            alert.condition_met = Some(met);
+            if should_fire {
+                alert.last_triggered_at = Some(now);
+                if alert.cooldown_secs.is_none() {
+                    alert.status = AlertStatus::Triggered;
+                }
+            }
+            // saved before delivering, a slow webhook mustn't let the next
+            // update fire it again
+            self.store.save(&alert).await?;
+            if should_fire {
+                self.deliver(&alert, update)?;
+                fired += 1;
+            }
+        }
+        Ok(fired)
+    }
+
+    fn deliver(
+        &self,
+        alert: &PriceAlert,
+        update: &PriceUpdate,
+    ) -> Result<()> {
+        let body = serde_json::to_string(&AlertPayload {
+            alert_id: &alert.id,
+            mint: &alert.mint,
+            condition: alert.condition,
+            threshold: alert.price,
+            price: update.price,
+            timestamp: update.timestamp,
+            signature: &update.signature,
+        })?;
+        let (id, url) = (alert.id.clone(), alert.webhook_url.clone());
+        let delivery = self.delivery.clone();
+        tokio::spawn(async move {
+            if let Err(e) = delivery.post_with_retries(&url, &body, &[]).await
+            {
+                tracing::warn!("Alert {} delivery failed: {}", id, e);
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_update(mint: &str, price: f64) -> PriceUpdate {
+        PriceUpdate {
+            name: "TEST".to_string(),
+            pubkey: mint.to_string(),
+            price,
+            market_cap: 0.,
+            timestamp: 0,
+            slot: 0,
+            swap_amount: 100.0,
+            owner: "owner".to_string(),
+            signature: "sig".to_string(),
+            multi_hop: false,
+            is_buy: true,
+            is_pump: true,
+        }
+    }
+
+    fn make_alert(
+        condition: PriceCondition,
+        webhook_url: String,
+        cooldown_secs: Option<u64>,
+    ) -> PriceAlert {
+        PriceAlert::new(
+            "user".to_string(),
+            AlertRequest {
+                mint: "mint".to_string(),
+                condition,
+                price: 1.0,
+                webhook_url,
+                cooldown_secs,
+            },
+        )
+    }
+
+    #[test]
+    fn test_should_fire() {
+        let mut alert = make_alert(
+            PriceCondition::Below,
+            "https://example.com".to_string(),
+            Some(60),
+        );
+        // the side the price starts on is unknown
+        assert!(!alert.should_fire(&make_update("mint", 0.5), 0));
+        // already below
+        alert.condition_met = Some(true);
+        assert!(!alert.should_fire(&make_update("mint", 0.5), 0));
+
+        alert.condition_met = Some(false);
+        assert!(alert.should_fire(&make_update("mint", 0.5), 0));
+        assert!(!alert.should_fire(&make_update("mint", 1.5), 0));
+        assert!(!alert.should_fire(&make_update("other", 0.5), 0));
+        alert.last_triggered_at = Some(100);
+        assert!(!alert.should_fire(&make_update("mint", 0.5), 159));
+        assert!(alert.should_fire(&make_update("mint", 0.5), 160));
+    }
+
+    #[tokio::test]
+    async fn test_crossing_fires_webhook_once() {
+        let mut server = mockito::Server::new_async().await;
+        let store = Arc::new(MemoryAlertStore::default());
+        let alert = make_alert(
+            PriceCondition::Above,
+            format!("{}/alert", server.url()),
+            None,
+        );
+        let webhook = server
+            .mock("POST", "/alert")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "alert_id": alert.id.clone(),
+                "condition": "Above",
+                "price": 1.5,
+            })))
+            .expect(1)
+            .create_async()
+            .await;
+        store.save(&alert).await.unwrap();
+        let monitor = AlertMonitor {
+            store: store.clone(),
+            delivery: DeliveryClient::allowing_private(),
+        };
+
+        assert_eq!(
+            monitor
+                .handle_update(&make_update("mint", 0.5))
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            monitor
+                .handle_update(&make_update("mint", 1.5))
+                .await
+                .unwrap(),
+            1
+        );
+        // still above, the alert is spent
+        assert_eq!(
+            monitor
+                .handle_update(&make_update("mint", 2.0))
+                .await
+                .unwrap(),
+            0
+        );
+        // delivered in the background
+        for _ in 0..50 {
+            if webhook.matched_async().await {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        webhook.assert_async().await;
+        let alert = store.get(&alert.id).await.unwrap().unwrap();
+        assert_eq!(alert.status, AlertStatus::Triggered);
+    }
+
+    #[tokio::test]
+    async fn test_fires_on_crossings_only() {
+        let store = Arc::new(MemoryAlertStore::default());
+        // the url is never reached, nothing crosses
+        let alert = make_alert(
+            PriceCondition::Above,
+            "http://127.0.0.1:9/alert".to_string(),
+            Some(0),
+        );
+        store.save(&alert).await.unwrap();
+        let monitor = AlertMonitor::new(store.clone()).unwrap();
+
+        // created while the price is already above
+        for price in [1.5, 2.0, 1.2] {
+            assert_eq!(
+                monitor
+                    .handle_update(&make_update("mint", price))
+                    .await
+                    .unwrap(),
+                0
+            );
+        }
+        let alert = store.get(&alert.id).await.unwrap().unwrap();
+        assert_eq!(alert.condition_met, Some(true));
+        assert_eq!(alert.status, AlertStatus::Active);
+
+        assert_eq!(
+            monitor
+                .handle_update(&make_update("mint", 0.8))
+                .await
+                .unwrap(),
+            0
+        );
+        let alert = store.get(&alert.id).await.unwrap().unwrap();
+        assert_eq!(alert.condition_met, Some(false));
+    }
+}
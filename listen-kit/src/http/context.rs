@@ -0,0 +1,205 @@
+use futures::future::join_all;
+use privy::auth::UserSession;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// a provider taking longer than this is left out of the turn
+pub const CONTEXT_PROVIDER_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Supplies dynamic context for a turn, see
+/// `ReasoningLoop::with_ephemeral_context`; failures should be logged and
+/// return nothing, context is best effort
+#[async_trait::async_trait]
+pub trait ContextProvider: Send + Sync {
+    async fn provide(&self, session: &UserSession) -> Vec<String>;
+}
+
+/// runs all providers concurrently, leaving out the ones that time out
+pub async fn gather_context(
+    providers: &[Arc<dyn ContextProvider>],
+    session: &UserSession,
+    timeout: Duration,
+) -> Vec<String> {
+    join_all(providers.iter().map(|provider| async move {
+        match tokio::time::timeout(timeout, provider.provide(session)).await {
+            Ok(context) => context,
+            Err(_) => {
+                tracing::warn!("context provider timed out");
+                vec![]
+            }
+        }
+    }))
+    .await
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Live SOL price from the price feed the indexer writes to redis
+pub struct SolPriceContextProvider {
+    client: redis::Client,
+}
+
+impl SolPriceContextProvider {
+    pub fn new(redis_url: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: redis::Client::open(redis_url)?,
+        })
+    }
+
+    async fn sol_price(&self) -> anyhow::Result<Option<f64>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let price: Option<String> = redis::cmd("GET")
+            .arg("solana:price:So11111111111111111111111111111111111111112")
+            .query_async(&mut conn)
+            .await?;
+        Ok(match price {
+            Some(price) => serde_json::from_str::<serde_json::Value>(&price)?
+                ["price"]
+                .as_f64(),
+            None => None,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl ContextProvider for SolPriceContextProvider {
+    async fn provide(&self, _session: &UserSession) -> Vec<String> {
+        match self.sol_price().await {
+            Ok(Some(price)) => {
+                vec![format!("Current SOL price: ${:.2}", price)]
+            }
+            Ok(None) => vec![],
+            Err(e) => {
+                tracing::warn!("failed to get SOL price for context: {}", e);
+                vec![]
+            }
+        }
+    }
+}
+
+#[cfg(feature = "solana")]
+pub use portfolio::PortfolioContextProvider;
+
+/// the portfolio, and the SOL price if redis is configured
+pub fn default_context_providers(
+    redis_url: Option<&str>,
+) -> Vec<Arc<dyn ContextProvider>> {
+    let mut providers: Vec<Arc<dyn ContextProvider>> = vec![];
+    #[cfg(feature = "solana")]
+    providers.push(Arc::new(PortfolioContextProvider));
+    if let Some(redis_url) = redis_url {
+        match SolPriceContextProvider::new(redis_url) {
+            Ok(provider) => providers.push(Arc::new(provider)),
+            Err(e) => {
+                tracing::warn!("failed to create price provider: {}", e)
+            }
+        }
+    }
+    providers
+}
+
+#[cfg(feature = "solana")]
+mod portfolio {
+    use super::ContextProvider;
+    use crate::common::wrap_unsafe;
+    use crate::solana::balance::get_holdings;
+    use crate::solana::data::holdings_to_portfolio;
+    use crate::solana::tools::create_rpc;
+    use crate::tool_result::ToolResultLimits;
+    use privy::auth::UserSession;
+    use solana_sdk::pubkey::Pubkey;
+    use std::str::FromStr;
+
+    /// the snapshot is summarized like the `get_portfolio` tool result
+    const MAX_PORTFOLIO_CONTEXT_BYTES: usize = 4096;
+
+    /// SOL balance and token holdings of the user's solana wallet
+    pub struct PortfolioContextProvider;
+
+    impl PortfolioContextProvider {
+        async fn portfolio(pubkey: &str) -> anyhow::Result<String> {
+            let owner = Pubkey::from_str(pubkey)?;
+            let (lamports, holdings) = wrap_unsafe(move || async move {
+                let rpc = create_rpc();
+                Ok((
+                    rpc.get_balance(&owner).await?,
+                    get_holdings(&rpc, &owner).await?,
+                ))
+            })
+            .await?;
+            let portfolio = holdings_to_portfolio(holdings).await?;
+            let portfolio = ToolResultLimits::new(
+                MAX_PORTFOLIO_CONTEXT_BYTES,
+            )
+            .apply("get_portfolio", &serde_json::to_string(&portfolio)?);
+            Ok(format!(
+                "Current portfolio of {}: {} SOL, tokens: {}",
+                pubkey,
+                lamports as f64 / 1e9,
+                portfolio
+            ))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ContextProvider for PortfolioContextProvider {
+        async fn provide(&self, session: &UserSession) -> Vec<String> {
+            match Self::portfolio(&session.pubkey).await {
+                Ok(portfolio) => vec![portfolio],
+                Err(e) => {
+                    tracing::warn!(
+                        "failed to get portfolio for context: {}",
+                        e
+                    );
+                    vec![]
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StaticProvider(Vec<String>);
+
+    #[async_trait::async_trait]
+    impl ContextProvider for StaticProvider {
+        async fn provide(&self, _session: &UserSession) -> Vec<String> {
+            self.0.clone()
+        }
+    }
+
+    struct StuckProvider;
+
+    #[async_trait::async_trait]
+    impl ContextProvider for StuckProvider {
+        async fn provide(&self, _session: &UserSession) -> Vec<String> {
+            futures::future::pending().await
+        }
+    }
+
+    fn session() -> UserSession {
+        UserSession {
+            user_id: "user".to_string(),
+            session_id: "session".to_string(),
+            wallet_address: "0x".to_string(),
+            pubkey: "pubkey".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stuck_provider_is_omitted() {
+        let providers: Vec<Arc<dyn ContextProvider>> = vec![
+            Arc::new(StaticProvider(vec!["SOL price: $150".to_string()])),
+            Arc::new(StuckProvider),
+            Arc::new(StaticProvider(vec![])),
+        ];
+        let context =
+            gather_context(&providers, &session(), Duration::from_millis(50))
+                .await;
+        assert_eq!(context, vec!["SOL price: $150".to_string()]);
+    }
+}
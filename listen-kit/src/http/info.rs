@@ -0,0 +1,145 @@
+use actix_web::{get, web, Error, HttpResponse};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::state::AppState;
+use crate::model::CHAINS;
+
+/// Agents the server accepts in the `chain` field of a request: the ones
+/// compiled in, optionally narrowed down with `ENABLED_AGENTS`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Features {
+    pub agents: Vec<String>,
+}
+
+impl Features {
+    /// mirrors the `#[cfg(feature)]` gates of the agents in `routes.rs`
+    pub fn compiled() -> Self {
+        let mut agents = Vec::new();
+        if cfg!(feature = "solana") {
+            agents.push("solana".to_string());
+        }
+        if cfg!(feature = "evm") {
+            agents.push("evm".to_string());
+        }
+        agents.push("omni".to_string());
+        Self { agents }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    /// `ENABLED_AGENTS` is a comma separated subset of the compiled agents
+    pub fn from_vars(get: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let compiled = Self::compiled();
+        let Some(enabled) = get("ENABLED_AGENTS") else {
+            return Ok(compiled);
+        };
+        let mut agents = Vec::new();
+        for agent in enabled.split(',').map(str::trim) {
+            if agent.is_empty() || agents.iter().any(|a| a == agent) {
+                continue;
+            }
+            if !CHAINS.contains(&agent) {
+                return Err(anyhow!(
+                    "Unknown agent in ENABLED_AGENTS: {}",
+                    agent
+                ));
+            }
+            if !compiled.is_enabled(agent) {
+                return Err(anyhow!(
+                    "Agent {} in ENABLED_AGENTS is not compiled in",
+                    agent
+                ));
+            }
+            agents.push(agent.to_string());
+        }
+        Ok(Self { agents })
+    }
+
+    pub fn is_enabled(&self, agent: &str) -> bool {
+        self.agents.iter().any(|a| a == agent)
+    }
+
+    /// chains the enabled agents can transact on
+    pub fn chains(&self) -> Vec<&'static str> {
+        let mut chains = Vec::new();
+        for agent in &self.agents {
+            let agent_chains: &[&'static str] = match agent.as_str() {
+                "solana" => &["solana"],
+                "evm" => &["ethereum"],
+                // the lifi routes offered by the cross-chain tools
+                "omni" => &["solana", "arbitrum", "base"],
+                _ => &[],
+            };
+            for chain in agent_chains {
+                if !chains.contains(chain) {
+                    chains.push(*chain);
+                }
+            }
+        }
+        chains
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub name: String,
+    pub version: String,
+    pub agents: Vec<String>,
+    pub chains: Vec<String>,
+}
+
+impl ServerInfo {
+    pub fn new(features: &Features) -> Self {
+        Self {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            agents: features.agents.clone(),
+            chains: features
+                .chains()
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+#[get("/info")]
+async fn info(state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(ServerInfo::new(&state.features)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_info_lists_enabled_features() {
+        let info = ServerInfo::new(&Features::compiled());
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            info.agents.contains(&"solana".to_string()),
+            cfg!(feature = "solana")
+        );
+        assert_eq!(
+            info.agents.contains(&"evm".to_string()),
+            cfg!(feature = "evm")
+        );
+        assert!(info.chains.contains(&"solana".to_string()));
+
+        let features =
+            Features::from_vars(|_| Some("omni, solana".to_string()));
+        if cfg!(feature = "solana") {
+            let info = ServerInfo::new(&features.unwrap());
+            assert_eq!(info.agents, ["omni", "solana"]);
+            assert_eq!(info.chains, ["solana", "arbitrum", "base"]);
+        } else {
+            assert!(features.is_err());
+        }
+
+        let features = Features::from_vars(|_| Some("bitcoin".to_string()));
+        assert!(features.is_err());
+    }
+}
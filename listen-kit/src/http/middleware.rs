@@ -24,3 +24,19 @@ pub async fn verify_auth(req: &HttpRequest) -> Result<UserSession> {
         Err(e) => Err(anyhow::anyhow!("Authentication failed: {}", e)),
     }
 }
+
+/// `ADMIN_TOKEN` as a bearer token; admin routes are closed without it
+pub fn verify_admin(req: &HttpRequest) -> Result<()> {
+    let admin_token = std::env::var("ADMIN_TOKEN")
+        .map_err(|_| anyhow::anyhow!("Admin routes are disabled"))?;
+    let token = req
+        .headers()
+        .get("authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .ok_or_else(|| anyhow::anyhow!("Missing authorization header"))?;
+    if token != admin_token {
+        return Err(anyhow::anyhow!("Invalid admin token"));
+    }
+    Ok(())
+}
@@ -1,6 +1,10 @@
+pub mod alerts;
+pub mod context;
+pub mod info;
 pub mod middleware;
 pub mod routes;
 pub mod server;
 pub mod state;
+pub mod webhooks;
 
 pub use server::run_server;
@@ -1,25 +1,47 @@
-use super::middleware::verify_auth;
+use super::alerts::{AlertRequest, PriceAlert};
+use super::context::{gather_context, CONTEXT_PROVIDER_TIMEOUT};
+use super::middleware::{verify_admin, verify_auth};
 use super::state::AppState;
+use super::webhooks::{check_public_url, Webhook, WebhookRequest};
+use crate::audit::{AuditContext, AuditSink};
 use crate::common::spawn_with_signer;
 use crate::cross_chain::agent::create_cross_chain_agent;
 use crate::evm::agent::create_evm_agent;
-use crate::reasoning_loop::LoopResponse;
 use crate::reasoning_loop::ReasoningLoop;
+use crate::reasoning_loop::{LoopAgent, LoopResponse};
+use crate::replay::RecordingAgent;
+use crate::signer::delegation::{DelegationGrant, MAX_GRANT_SECS};
+use crate::signer::middleware::LayeredSigner;
 use crate::signer::privy::PrivySigner;
-use crate::signer::TransactionSigner;
-use crate::solana::agent::create_solana_agent;
+use crate::signer::spending_guard::SpendingLimits;
+use crate::signer::{SignerContext, TransactionSigner};
+use crate::solana::agent::{
+    create_solana_agent, create_solana_research_agent,
+};
+use crate::tool_result::structured_result;
+use crate::usage::UsageReporter;
 use actix_web::{
-    get, post, web, Error, HttpRequest, HttpResponse, Responder,
+    delete, get, post, put, web, Error, HttpRequest, HttpResponse, Responder,
 };
 use actix_web_lab::sse;
 use anyhow::Result;
 use futures::StreamExt;
+use rig::completion::AssistantContent;
 use rig::completion::Message;
-use rig::message::UserContent;
+use rig::message::{ToolResultContent, UserContent};
 use rig::OneOrMany;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::error::SendTimeoutError;
+
+/// events buffered for a client before sends wait on it to read
+pub const SSE_CHANNEL_CAPACITY: usize = 1024;
+
+/// how long a send waits on a client whose buffer is full before the
+/// stream is given up on
+pub const SSE_SEND_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Deserialize)]
 pub struct ChatRequest {
@@ -30,14 +52,139 @@ pub struct ChatRequest {
     chain: Option<String>,
     #[serde(default)]
     preamble: Option<String>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    conversation_id: Option<String>,
+    /// overrides the default token budget of the request
+    #[serde(default)]
+    max_tokens_total: Option<usize>,
+    /// leaves out the tools that transact, e.g. for an analyst preamble
+    #[serde(default)]
+    read_only: bool,
+}
+
+/// characters of prompt, preamble and chat history a request may send,
+/// roughly 50k tokens, `MAX_HISTORY_CHARS` overrides it
+pub const DEFAULT_MAX_HISTORY_CHARS: usize = 200_000;
+
+impl ChatRequest {
+    /// Drops the oldest turns of the chat history until it fits `max_chars`
+    /// along with the prompt and the preamble, which are never dropped.
+    /// Only whole turns go, a turn starting at a message of the user, so no
+    /// tool result is left without its call. Returns how many messages
+    /// were dropped
+    fn truncate_history(&mut self, max_chars: usize) -> usize {
+        let budget = max_chars.saturating_sub(
+            self.prompt.len() + self.preamble.as_ref().map_or(0, String::len),
+        );
+        let sizes: Vec<usize> =
+            self.chat_history.iter().map(message_chars).collect();
+        let mut remaining: usize = sizes.iter().sum();
+        if remaining <= budget {
+            return 0;
+        }
+
+        let mut start = self.chat_history.len();
+        for (i, message) in self.chat_history.iter().enumerate() {
+            if remaining <= budget && starts_turn(message) {
+                start = i;
+                break;
+            }
+            remaining -= sizes[i];
+        }
+        self.chat_history.drain(..start);
+        start
+    }
+}
+
+/// the size of a message as sent to the model, close enough to count
+/// tool calls, results and images alike
+fn message_chars(message: &Message) -> usize {
+    serde_json::to_string(message).map_or(0, |json| json.len())
+}
+
+fn starts_turn(message: &Message) -> bool {
+    match message {
+        Message::User { content } => content
+            .iter()
+            .all(|content| !matches!(content, UserContent::ToolResult(_))),
+        Message::Assistant { .. } => false,
+    }
 }
 
 #[derive(Serialize, Debug)]
 #[serde(tag = "type", content = "content")]
 pub enum StreamResponse {
     Message(String),
-    ToolCall { name: String, result: String },
+    ToolCall {
+        name: String,
+        /// the result as the tool serialized it, see `structured_result`
+        result: serde_json::Value,
+    },
+    Nested {
+        parent_id: String,
+        depth: usize,
+        content: Box<StreamResponse>,
+    },
     Error(String),
+    /// the signer waits on the user, e.g. "approve on your device"
+    SignerPrompt(String),
+    /// about the request rather than the answer, e.g. a truncated history
+    Notice(String),
+}
+
+impl From<LoopResponse> for StreamResponse {
+    fn from(response: LoopResponse) -> Self {
+        match response {
+            LoopResponse::Message(text) => StreamResponse::Message(text),
+            LoopResponse::ToolCall { name, result } => {
+                StreamResponse::ToolCall {
+                    name,
+                    result: structured_result(&result),
+                }
+            }
+            LoopResponse::Nested {
+                parent_id,
+                depth,
+                response,
+            } => StreamResponse::Nested {
+                parent_id,
+                depth,
+                content: Box::new((*response).into()),
+            },
+        }
+    }
+}
+
+/// Forwards the responses of a reasoning loop to the SSE stream of a
+/// client. While the client reads slower than the loop writes the buffers
+/// fill and the loop waits on them; once one send waits `send_timeout` the
+/// client is given up on and `responses` is dropped, which fails the next
+/// send of the loop and so ends it rather than stalling it forever
+pub(crate) async fn forward_responses(
+    mut responses: tokio::sync::mpsc::Receiver<LoopResponse>,
+    tx: tokio::sync::mpsc::Sender<sse::Event>,
+    send_timeout: Duration,
+) -> Result<()> {
+    while let Some(response) = responses.recv().await {
+        let event = sse::Event::Data(sse::Data::new(
+            serde_json::to_string(&StreamResponse::from(response)).unwrap(),
+        ));
+        match tx.send_timeout(event, send_timeout).await {
+            Ok(()) => {}
+            Err(SendTimeoutError::Closed(_)) => {
+                return Err(anyhow::anyhow!("the client disconnected"))
+            }
+            Err(SendTimeoutError::Timeout(_)) => {
+                return Err(anyhow::anyhow!(
+                    "the client read nothing for {:?}",
+                    send_timeout
+                ))
+            }
+        }
+    }
+    Ok(())
 }
 
 #[derive(Serialize)]
@@ -47,6 +194,16 @@ pub enum ServerError {
     ChainNotSupported,
 }
 
+impl std::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WalletError => write!(f, "WalletError"),
+            Self::PrivyError => write!(f, "PrivyError"),
+            Self::ChainNotSupported => write!(f, "ChainNotSupported"),
+        }
+    }
+}
+
 #[post("/stream")]
 async fn stream(
     req: HttpRequest,
@@ -79,7 +236,7 @@ async fn stream(
     // println!("Raw request body: {}", String::from_utf8_lossy(&bytes));
 
     // Deserialize into ChatRequest
-    let request: ChatRequest = match serde_json::from_slice(&bytes) {
+    let mut request: ChatRequest = match serde_json::from_slice(&bytes) {
         Ok(req) => req,
         Err(e) => {
             tracing::error!("Error: deserializing request: {}", e);
@@ -113,33 +270,89 @@ async fn stream(
         }
     };
 
-    let (tx, rx) = tokio::sync::mpsc::channel::<sse::Event>(1024);
+    let (tx, rx) =
+        tokio::sync::mpsc::channel::<sse::Event>(SSE_CHANNEL_CAPACITY);
+
+    if let Some(chain) = request.chain.as_deref() {
+        if !state.features.is_enabled(chain) {
+            tracing::error!("Error: disabled chain: {}", chain);
+            let error_event = sse::Event::Data(sse::Data::new(
+                serde_json::to_string(&StreamResponse::Error(format!(
+                    "Unsupported chain: {}",
+                    chain
+                )))
+                .unwrap(),
+            ));
+            let _ = tx.send(error_event).await;
+            return sse::Sse::from_infallible_receiver(rx);
+        }
+    }
+
+    let dropped = request.truncate_history(state.max_history_chars);
+    if dropped > 0 {
+        tracing::info!(dropped, "truncated the chat history");
+        let notice_event = sse::Event::Data(sse::Data::new(
+            serde_json::to_string(&StreamResponse::Notice(format!(
+                "The {} oldest messages were left out to fit the history \
+                 limit",
+                dropped
+            )))
+            .unwrap(),
+        ));
+        let _ = tx.send(notice_event).await;
+    }
 
     let preamble = request.preamble.clone();
+    let tools = if request.read_only {
+        state.tools.clone().read_only()
+    } else {
+        state.tools.clone()
+    };
+
+    let model = match state.models.resolve(
+        request.chain.as_deref().unwrap_or_default(),
+        request.model.as_deref(),
+    ) {
+        Ok(model) => model,
+        Err(e) => {
+            tracing::error!("Error: invalid model: {}", e);
+            let error_event = sse::Event::Data(sse::Data::new(
+                serde_json::to_string(&StreamResponse::Error(format!(
+                    "Invalid model: {}",
+                    e
+                )))
+                .unwrap(),
+            ));
+            let _ = tx.send(error_event).await;
+            return sse::Sse::from_infallible_receiver(rx);
+        }
+    };
 
     // Select the appropriate agent based on the chain parameter and preamble
     let agent = match request.chain.as_deref() {
         #[cfg(feature = "solana")]
-        Some("solana") => match create_solana_agent(preamble).await {
-            Ok(agent) => Arc::new(agent),
-            Err(e) => {
-                tracing::error!(
-                    "Error: failed to create Solana agent: {}",
-                    e
-                );
-                let error_event = sse::Event::Data(sse::Data::new(
-                    serde_json::to_string(&StreamResponse::Error(format!(
-                        "Failed to create Solana agent: {}",
+        Some("solana") => {
+            match create_solana_agent(preamble, &model, &tools).await {
+                Ok(agent) => Arc::new(agent),
+                Err(e) => {
+                    tracing::error!(
+                        "Error: failed to create Solana agent: {}",
                         e
-                    )))
-                    .unwrap(),
-                ));
-                let _ = tx.send(error_event).await;
-                return sse::Sse::from_infallible_receiver(rx);
+                    );
+                    let error_event = sse::Event::Data(sse::Data::new(
+                        serde_json::to_string(&StreamResponse::Error(
+                            format!("Failed to create Solana agent: {}", e),
+                        ))
+                        .unwrap(),
+                    ));
+                    let _ = tx.send(error_event).await;
+                    return sse::Sse::from_infallible_receiver(rx);
+                }
             }
-        },
+        }
         #[cfg(feature = "evm")]
-        Some("evm") => match create_evm_agent(preamble).await {
+        Some("evm") => match create_evm_agent(preamble, &model, &tools).await
+        {
             Ok(agent) => Arc::new(agent),
             Err(e) => {
                 tracing::error!("Error: failed to create EVM agent: {}", e);
@@ -154,24 +367,28 @@ async fn stream(
                 return sse::Sse::from_infallible_receiver(rx);
             }
         },
-        Some("omni") => match create_cross_chain_agent(preamble).await {
-            Ok(agent) => Arc::new(agent),
-            Err(e) => {
-                tracing::error!(
-                    "Error: failed to create cross-chain agent: {}",
-                    e
-                );
-                let error_event = sse::Event::Data(sse::Data::new(
-                    serde_json::to_string(&StreamResponse::Error(format!(
-                        "Failed to create cross-chain agent: {}",
+        Some("omni") => {
+            match create_cross_chain_agent(preamble, &model, &tools).await {
+                Ok(agent) => Arc::new(agent),
+                Err(e) => {
+                    tracing::error!(
+                        "Error: failed to create cross-chain agent: {}",
                         e
-                    )))
-                    .unwrap(),
-                ));
-                let _ = tx.send(error_event).await;
-                return sse::Sse::from_infallible_receiver(rx);
+                    );
+                    let error_event = sse::Event::Data(sse::Data::new(
+                        serde_json::to_string(&StreamResponse::Error(
+                            format!(
+                                "Failed to create cross-chain agent: {}",
+                                e
+                            ),
+                        ))
+                        .unwrap(),
+                    ));
+                    let _ = tx.send(error_event).await;
+                    return sse::Sse::from_infallible_receiver(rx);
+                }
             }
-        },
+        }
         Some(chain) => {
             tracing::error!("Error: unsupported chain: {}", chain);
             let error_event = sse::Event::Data(sse::Data::new(
@@ -197,62 +414,195 @@ async fn stream(
         }
     };
 
+    // sub-agents the chain agent can delegate to
+    let research_agent: Option<Arc<dyn LoopAgent>> = match request
+        .chain
+        .as_deref()
+    {
+        #[cfg(feature = "solana")]
+        Some("solana") => {
+            match create_solana_research_agent(&model, &tools).await {
+                Ok(agent) => Some(Arc::new(agent)),
+                Err(e) => {
+                    tracing::warn!("failed to create research agent: {}", e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
     let prompt = request.prompt.clone();
     let messages = request.chat_history.clone();
 
-    let signer: Arc<dyn TransactionSigner> =
-        Arc::new(PrivySigner::new(state.privy.clone(), user_session.clone()));
+    let audit_context = AuditContext {
+        user_id: Some(user_session.user_id.clone()),
+        wallet: Some(match request.chain.as_deref() {
+            Some("evm") => user_session.wallet_address.clone(),
+            _ => user_session.pubkey.clone(),
+        }),
+        conversation_id: request.conversation_id.clone(),
+    };
+    let audit_sink =
+        state.audit.clone().map(|audit| audit as Arc<dyn AuditSink>);
+    let usage_reporter = state
+        .usage
+        .clone()
+        .map(|usage| usage as Arc<dyn UsageReporter>);
+    let max_tokens_total =
+        request.max_tokens_total.or(state.max_tokens_total);
+
+    let signer: Arc<dyn TransactionSigner> = match &state.local_signer {
+        Some(local_signer) => local_signer.clone(),
+        // the policy is checked when signing, read-only chats never are
+        None => Arc::new(PrivySigner::with_policy(
+            state.privy.clone(),
+            user_session.clone(),
+            state.wallet_policy.clone(),
+        )),
+    };
+    // while the wallet has a grant, for a bot, every request only gets
+    // what the user granted, a client can't opt out of it
+    let signer: Arc<dyn TransactionSigner> = match &state.delegation {
+        Some(delegation) => match delegation.restrict(signer).await {
+            Ok(signer) => signer,
+            Err(e) => {
+                tracing::error!("Error: delegation: {}", e);
+                let error_event = sse::Event::Data(sse::Data::new(
+                    serde_json::to_string(&StreamResponse::Error(format!(
+                        "Delegation: {}",
+                        e
+                    )))
+                    .unwrap(),
+                ));
+                let _ = tx.send(error_event).await;
+                return sse::Sse::from_infallible_receiver(rx);
+            }
+        },
+        None => signer,
+    };
+    let signer: Arc<dyn TransactionSigner> = match &state.spending {
+        Some(spending) => Arc::new(spending.guard(signer)),
+        None => signer,
+    };
+    // outside the spending guard, which sees modified transactions as sent
+    let signer: Arc<dyn TransactionSigner> = Arc::new(LayeredSigner::new(
+        signer,
+        state.signer_middlewares.clone(),
+    ));
+
+    // sessions are recorded for replay if TRACE_DIR is set
+    let trace_path = std::env::var("TRACE_DIR").ok().map(|dir| {
+        std::path::Path::new(&dir).join(format!(
+            "{}-{}.jsonl",
+            request
+                .conversation_id
+                .clone()
+                .unwrap_or_else(|| user_session.user_id.clone()),
+            chrono::Utc::now().timestamp_millis()
+        ))
+    });
+
+    let context = gather_context(
+        &state.context_providers,
+        &user_session,
+        CONTEXT_PROVIDER_TIMEOUT,
+    )
+    .await;
 
     spawn_with_signer(signer, || async move {
-        let reasoning_loop = ReasoningLoop::new(agent).with_stdout(false);
+        let agent: Arc<dyn LoopAgent> = agent;
+        let agent = match trace_path {
+            Some(trace_path) => {
+                match RecordingAgent::new(agent.clone(), &trace_path) {
+                    Ok(recording) => Arc::new(recording),
+                    Err(e) => {
+                        tracing::warn!("failed to open trace: {}", e);
+                        agent
+                    }
+                }
+            }
+            None => agent,
+        };
+        let mut reasoning_loop = ReasoningLoop::new(agent)
+            .with_stdout(false)
+            .with_ephemeral_context(context);
+        if let Some(research_agent) = research_agent {
+            reasoning_loop =
+                reasoning_loop.with_delegate("research", research_agent);
+        }
+        if let Some(audit_sink) = audit_sink {
+            reasoning_loop =
+                reasoning_loop.with_audit(audit_sink, audit_context);
+        }
+        if let Some(usage_reporter) = usage_reporter {
+            reasoning_loop =
+                reasoning_loop.with_usage_reporter(usage_reporter);
+        }
+        if let Some(max_tokens_total) = max_tokens_total {
+            reasoning_loop =
+                reasoning_loop.with_max_tokens_total(max_tokens_total);
+        }
 
         // Create a channel for the reasoning loop to send responses
-        let (internal_tx, mut internal_rx) = tokio::sync::mpsc::channel(1024);
+        let (internal_tx, internal_rx) =
+            tokio::sync::mpsc::channel(SSE_CHANNEL_CAPACITY);
 
         // Create a separate task to handle sending responses
-        let tx_clone = tx.clone();
-        let send_task = tokio::spawn(async move {
-            while let Some(response) = internal_rx.recv().await {
-                let stream_response = match response {
-                    LoopResponse::Message(text) => {
-                        StreamResponse::Message(text)
-                    }
-                    LoopResponse::ToolCall { name, result } => {
-                        StreamResponse::ToolCall { name, result }
-                    }
-                };
+        let send_task = tokio::spawn(forward_responses(
+            internal_rx,
+            tx.clone(),
+            SSE_SEND_TIMEOUT,
+        ));
 
+        let (prompt_tx, mut prompt_rx) =
+            tokio::sync::mpsc::unbounded_channel::<String>();
+        let tx_clone = tx.clone();
+        let prompt_task = tokio::spawn(async move {
+            while let Some(prompt) = prompt_rx.recv().await {
+                let event = sse::Event::Data(sse::Data::new(
+                    serde_json::to_string(&StreamResponse::SignerPrompt(
+                        prompt,
+                    ))
+                    .unwrap(),
+                ));
                 if tx_clone
-                    .send(sse::Event::Data(sse::Data::new(
-                        serde_json::to_string(&stream_response).unwrap(),
-                    )))
+                    .send_timeout(event, SSE_SEND_TIMEOUT)
                     .await
                     .is_err()
                 {
-                    tracing::error!("Error: failed to send response");
                     break;
                 }
             }
         });
 
         // Run the reasoning loop in the current task (with signer context)
-        let loop_result = reasoning_loop
-            .stream(prompt, messages, Some(internal_tx))
-            .await;
+        let loop_result = SignerContext::with_prompts(
+            prompt_tx,
+            reasoning_loop.stream(prompt, messages, Some(internal_tx)),
+        )
+        .await;
 
-        // Wait for the send task to complete
-        let _ = send_task.await;
+        // Wait for the send tasks to complete
+        let forwarded = send_task.await?;
+        let _ = prompt_task.await;
 
-        // Check if the reasoning loop completed successfully
-        if let Err(e) = loop_result {
+        // a client that stopped reading has no room for the error, the
+        // stream just ends once what it holds is read
+        if let Err(e) = forwarded {
+            tracing::warn!("closing the stream: {}", e);
+        } else if let Err(e) = loop_result {
             tracing::error!("Error: reasoning loop failed: {}", e);
             let _ = tx
-                .send(sse::Event::Data(sse::Data::new(
-                    serde_json::to_string(&StreamResponse::Error(
-                        e.to_string(),
-                    ))
-                    .unwrap(),
-                )))
+                .send_timeout(
+                    sse::Event::Data(sse::Data::new(
+                        serde_json::to_string(&StreamResponse::Error(
+                            e.to_string(),
+                        ))
+                        .unwrap(),
+                    )),
+                    SSE_SEND_TIMEOUT,
+                )
                 .await;
         }
 
@@ -264,10 +614,15 @@ async fn stream(
 }
 
 #[get("/healthz")]
-async fn healthz() -> Result<HttpResponse, Error> {
+async fn healthz(state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    let privy_retries = &state.privy.retry_metrics;
     Ok(HttpResponse::Ok().json(json!({
         "status": "ok",
-        "timestamp": chrono::Utc::now().to_rfc3339()
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "privy": {
+            "retries": privy_retries.retries(),
+            "failures": privy_retries.failures(),
+        }
     })))
 }
 
@@ -287,53 +642,789 @@ async fn auth(req: HttpRequest) -> Result<HttpResponse, Error> {
     })))
 }
 
+#[post("/webhooks")]
+async fn create_webhook(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<WebhookRequest>,
+) -> Result<HttpResponse, Error> {
+    let user_session = match verify_auth(&req).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Ok(HttpResponse::Unauthorized()
+                .json(json!({ "error": e.to_string() })))
+        }
+    };
+
+    let store = match &state.webhooks {
+        Some(store) => store,
+        None => {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .json(json!({ "error": "Webhooks are not enabled" })))
+        }
+    };
+
+    let request = body.into_inner();
+    if let Err(e) = check_public_url(&request.url).await {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": format!("Invalid url: {}", e) })));
+    }
+    if request.secret.is_empty() || request.events.is_empty() {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "secret and events are required" })));
+    }
+
+    let webhook = Webhook::new(user_session.user_id, request);
+    if let Err(e) = store.save(&webhook).await {
+        tracing::error!("Error: failed to save webhook: {}", e);
+        return Ok(HttpResponse::InternalServerError()
+            .json(json!({ "error": "Failed to save webhook" })));
+    }
+
+    Ok(HttpResponse::Created().json(json!({
+        "id": webhook.id,
+        "status": webhook.status,
+    })))
+}
+
+#[post("/alerts")]
+async fn create_alert(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<AlertRequest>,
+) -> Result<HttpResponse, Error> {
+    let user_session = match verify_auth(&req).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Ok(HttpResponse::Unauthorized()
+                .json(json!({ "error": e.to_string() })))
+        }
+    };
+
+    let alerts = match &state.alerts {
+        Some(alerts) => alerts,
+        None => {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .json(json!({ "error": "Alerts are not enabled" })))
+        }
+    };
+
+    let request = body.into_inner();
+    if let Err(e) = check_public_url(&request.webhook_url).await {
+        return Ok(HttpResponse::BadRequest().json(
+            json!({ "error": format!("Invalid webhook_url: {}", e) }),
+        ));
+    }
+    if request.mint.parse::<solana_sdk::pubkey::Pubkey>().is_err() {
+        return Ok(HttpResponse::BadRequest().json(
+            json!({ "error": format!("Invalid mint: {}", request.mint) }),
+        ));
+    }
+    if !request.price.is_finite() || request.price <= 0. {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "price must be positive" })));
+    }
+
+    let alert = PriceAlert::new(user_session.user_id, request);
+    if let Err(e) = alerts.save(&alert).await {
+        tracing::error!("Error: failed to save alert: {}", e);
+        return Ok(HttpResponse::InternalServerError()
+            .json(json!({ "error": "Failed to save alert" })));
+    }
+
+    Ok(HttpResponse::Created().json(alert))
+}
+
+#[delete("/alerts/{id}")]
+async fn delete_alert(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let user_session = match verify_auth(&req).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Ok(HttpResponse::Unauthorized()
+                .json(json!({ "error": e.to_string() })))
+        }
+    };
+
+    let alerts = match &state.alerts {
+        Some(alerts) => alerts,
+        None => {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .json(json!({ "error": "Alerts are not enabled" })))
+        }
+    };
+
+    // someone else's alert is as good as missing
+    match alerts.get(&id).await {
+        Ok(Some(alert)) if alert.user_id == user_session.user_id => {}
+        Ok(_) => {
+            return Ok(HttpResponse::NotFound()
+                .json(json!({ "error": "No such alert" })))
+        }
+        Err(e) => {
+            tracing::error!("Error: failed to read alert: {}", e);
+            return Ok(HttpResponse::InternalServerError()
+                .json(json!({ "error": "Failed to read alert" })));
+        }
+    }
+
+    match alerts.delete(&id).await {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => {
+            tracing::error!("Error: failed to delete alert: {}", e);
+            Ok(HttpResponse::InternalServerError()
+                .json(json!({ "error": "Failed to delete alert" })))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    #[serde(default = "default_audit_limit")]
+    limit: usize,
+}
+
+fn default_audit_limit() -> usize {
+    100
+}
+
+#[get("/audit")]
+async fn get_audit(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    query: web::Query<AuditQuery>,
+) -> Result<HttpResponse, Error> {
+    let user_session = match verify_auth(&req).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Ok(HttpResponse::Unauthorized()
+                .json(json!({ "error": e.to_string() })))
+        }
+    };
+
+    let audit = match &state.audit {
+        Some(audit) => audit,
+        None => {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .json(json!({ "error": "Audit log is not enabled" })))
+        }
+    };
+
+    match audit
+        .get_entries(&user_session.user_id, query.limit.min(1000))
+        .await
+    {
+        Ok(entries) => Ok(HttpResponse::Ok().json(entries)),
+        Err(e) => {
+            tracing::error!("Error: failed to read audit log: {}", e);
+            Ok(HttpResponse::InternalServerError()
+                .json(json!({ "error": "Failed to read audit log" })))
+        }
+    }
+}
+
+#[get("/admin/spending-limits/{wallet}")]
+async fn get_spending_limits(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    wallet: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    if let Err(e) = verify_admin(&req) {
+        return Ok(HttpResponse::Unauthorized()
+            .json(json!({ "error": e.to_string() })));
+    }
+
+    let spending = match &state.spending {
+        Some(spending) => spending,
+        None => {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .json(json!({ "error": "Spending limits are not enabled" })))
+        }
+    };
+
+    match spending.store.get_limits(&wallet).await {
+        Ok(limits) => Ok(HttpResponse::Ok().json(limits.unwrap_or_default())),
+        Err(e) => {
+            tracing::error!("Error: failed to read spending limits: {}", e);
+            Ok(HttpResponse::InternalServerError()
+                .json(json!({ "error": "Failed to read spending limits" })))
+        }
+    }
+}
+
+#[put("/admin/spending-limits/{wallet}")]
+async fn set_spending_limits(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    wallet: web::Path<String>,
+    limits: web::Json<SpendingLimits>,
+) -> Result<HttpResponse, Error> {
+    if let Err(e) = verify_admin(&req) {
+        return Ok(HttpResponse::Unauthorized()
+            .json(json!({ "error": e.to_string() })));
+    }
+
+    let spending = match &state.spending {
+        Some(spending) => spending,
+        None => {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .json(json!({ "error": "Spending limits are not enabled" })))
+        }
+    };
+
+    match spending.store.set_limits(&wallet, &limits).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(limits.into_inner())),
+        Err(e) => {
+            tracing::error!("Error: failed to save spending limits: {}", e);
+            Ok(HttpResponse::InternalServerError()
+                .json(json!({ "error": "Failed to save spending limits" })))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DelegationRequest {
+    max_notional_usd: f64,
+    /// defaults to jupiter and pump.fun swaps
+    #[serde(default)]
+    allowed_programs: Option<Vec<String>>,
+    ttl_secs: u64,
+}
+
+#[post("/delegations")]
+async fn create_delegation(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<DelegationRequest>,
+) -> Result<HttpResponse, Error> {
+    let user_session = match verify_auth(&req).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Ok(HttpResponse::Unauthorized()
+                .json(json!({ "error": e.to_string() })))
+        }
+    };
+
+    let delegation = match &state.delegation {
+        Some(delegation) => delegation,
+        None => {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .json(json!({ "error": "Delegation is not enabled" })))
+        }
+    };
+
+    let request = body.into_inner();
+    if request.ttl_secs == 0 || request.ttl_secs > MAX_GRANT_SECS {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("ttl_secs must be 1 to {}", MAX_GRANT_SECS)
+        })));
+    }
+    if !request.max_notional_usd.is_finite() || request.max_notional_usd <= 0.
+    {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "max_notional_usd must be positive" })));
+    }
+    let allowed_programs = request
+        .allowed_programs
+        .unwrap_or_else(DelegationGrant::default_programs);
+    if let Some(program) = allowed_programs.iter().find(|program| {
+        program.parse::<solana_sdk::pubkey::Pubkey>().is_err()
+    }) {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Invalid program: {}", program)
+        })));
+    }
+
+    let grant = DelegationGrant {
+        wallet: user_session.pubkey,
+        max_notional_usd: request.max_notional_usd,
+        allowed_programs,
+        expires_at: chrono::Utc::now().timestamp() as u64 + request.ttl_secs,
+    };
+    match delegation.store.put(&grant).await {
+        Ok(()) => Ok(HttpResponse::Created().json(grant)),
+        Err(e) => {
+            tracing::error!("Error: failed to save delegation grant: {}", e);
+            Ok(HttpResponse::InternalServerError()
+                .json(json!({ "error": "Failed to save delegation grant" })))
+        }
+    }
+}
+
+#[get("/delegations")]
+async fn get_delegation(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let user_session = match verify_auth(&req).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Ok(HttpResponse::Unauthorized()
+                .json(json!({ "error": e.to_string() })))
+        }
+    };
+
+    let delegation = match &state.delegation {
+        Some(delegation) => delegation,
+        None => {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .json(json!({ "error": "Delegation is not enabled" })))
+        }
+    };
+
+    match delegation.store.get(&user_session.pubkey).await {
+        Ok(Some(grant)) => Ok(HttpResponse::Ok().json(grant)),
+        Ok(None) => Ok(HttpResponse::NotFound()
+            .json(json!({ "error": "No delegation grant" }))),
+        Err(e) => {
+            tracing::error!("Error: failed to read delegation grant: {}", e);
+            Ok(HttpResponse::InternalServerError()
+                .json(json!({ "error": "Failed to read delegation grant" })))
+        }
+    }
+}
+
+#[delete("/delegations")]
+async fn revoke_delegation(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let user_session = match verify_auth(&req).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Ok(HttpResponse::Unauthorized()
+                .json(json!({ "error": e.to_string() })))
+        }
+    };
+
+    let delegation = match &state.delegation {
+        Some(delegation) => delegation,
+        None => {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .json(json!({ "error": "Delegation is not enabled" })))
+        }
+    };
+
+    match delegation.store.revoke(&user_session.pubkey).await {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => {
+            tracing::error!(
+                "Error: failed to revoke delegation grant: {}",
+                e
+            );
+            Ok(HttpResponse::InternalServerError().json(
+                json!({ "error": "Failed to revoke delegation grant" }),
+            ))
+        }
+    }
+}
+
 fn deserialize_messages<'de, D>(
     deserializer: D,
 ) -> Result<Vec<Message>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
+    use serde::de::Error;
+
+    #[derive(Deserialize)]
+    struct RawToolCall {
+        #[serde(default)]
+        id: Option<String>,
+        name: String,
+        /// an object, or the object as a JSON string
+        #[serde(default)]
+        arguments: serde_json::Value,
+    }
+
     #[derive(Deserialize)]
     struct RawMessage {
         role: String,
+        #[serde(default)]
         content: serde_json::Value,
+        /// calls made by an assistant message
+        #[serde(default)]
+        tool_calls: Vec<RawToolCall>,
+        /// the call a tool message answers
+        #[serde(default)]
+        tool_call_id: Option<String>,
+        /// the tool of a tool message, pairs it with the call when the
+        /// history has no call ids
+        #[serde(default)]
+        name: Option<String>,
     }
 
     let raw_messages: Vec<RawMessage> = Vec::deserialize(deserializer)?;
 
-    raw_messages
-        .into_iter()
-        .map(|raw| {
-            let content = match raw.role.as_str() {
-                "user" => {
-                    let content = match raw.content {
-                        serde_json::Value::String(s) => {
-                            OneOrMany::one(UserContent::Text(s.into()))
-                        }
-                        _ => {
-                            return Err(serde::de::Error::custom(
-                                "Invalid user content format",
-                            ))
-                        }
-                    };
-                    Message::User { content }
+    let mut messages = Vec::new();
+    // (id, name) of the calls without a result yet
+    let mut pending_calls: Vec<(String, String)> = Vec::new();
+    for (i, raw) in raw_messages.into_iter().enumerate() {
+        match raw.role.as_str() {
+            "user" => {
+                let content = match raw.content {
+                    serde_json::Value::String(s) => {
+                        OneOrMany::one(UserContent::Text(s.into()))
+                    }
+                    _ => {
+                        return Err(D::Error::custom(
+                            "Invalid user content format",
+                        ))
+                    }
+                };
+                messages.push(Message::User { content });
+            }
+            "assistant" => {
+                let mut content = Vec::new();
+                match raw.content {
+                    serde_json::Value::String(s) if !s.is_empty() => {
+                        content.push(AssistantContent::text(s))
+                    }
+                    serde_json::Value::String(_)
+                    | serde_json::Value::Null => {}
+                    _ => {
+                        return Err(D::Error::custom(
+                            "Invalid assistant content format",
+                        ))
+                    }
                 }
-                "assistant" => {
-                    let content = match raw.content {
+                for (j, call) in raw.tool_calls.into_iter().enumerate() {
+                    let id = call
+                        .id
+                        .unwrap_or_else(|| format!("history_{}_{}", i, j));
+                    let arguments = match call.arguments {
                         serde_json::Value::String(s) => {
-                            OneOrMany::one(s.into())
-                        }
-                        _ => {
-                            return Err(serde::de::Error::custom(
-                                "Invalid assistant content format",
-                            ))
+                            serde_json::from_str(&s)
+                                .unwrap_or(serde_json::Value::String(s))
                         }
+                        serde_json::Value::Null => json!({}),
+                        arguments => arguments,
                     };
-                    Message::Assistant { content }
+                    pending_calls.push((id.clone(), call.name.clone()));
+                    content.push(AssistantContent::tool_call(
+                        id, call.name, arguments,
+                    ));
                 }
-                _ => return Err(serde::de::Error::custom("Invalid role")),
-            };
-            Ok(content)
-        })
-        .collect()
+                let content = OneOrMany::many(content).map_err(|_| {
+                    D::Error::custom("Empty assistant message")
+                })?;
+                messages.push(Message::Assistant { content });
+            }
+            "tool" => {
+                let result = match raw.content {
+                    serde_json::Value::String(s) => s,
+                    serde_json::Value::Null => {
+                        return Err(D::Error::custom(
+                            "Tool message without content",
+                        ))
+                    }
+                    result => result.to_string(),
+                };
+                let id = match (raw.tool_call_id, raw.name) {
+                    (Some(id), _) => {
+                        pending_calls.retain(|(pending, _)| *pending != id);
+                        id
+                    }
+                    (None, Some(name)) => {
+                        match pending_calls
+                            .iter()
+                            .position(|(_, n)| *n == name)
+                        {
+                            Some(position) => {
+                                pending_calls.remove(position).0
+                            }
+                            // the call isn't in the history, e.g. the UI only
+                            // keeps results, and a result needs its call
+                            None => {
+                                let id = format!("history_{}", i);
+                                messages.push(Message::Assistant {
+                                    content: OneOrMany::one(
+                                        AssistantContent::tool_call(
+                                            id.clone(),
+                                            name,
+                                            json!({}),
+                                        ),
+                                    ),
+                                });
+                                id
+                            }
+                        }
+                    }
+                    (None, None) => {
+                        return Err(D::Error::custom(
+                            "Tool message without tool_call_id or name",
+                        ))
+                    }
+                };
+                messages.push(Message::User {
+                    content: OneOrMany::one(UserContent::tool_result(
+                        id,
+                        OneOrMany::one(ToolResultContent::text(result)),
+                    )),
+                });
+            }
+            _ => return Err(D::Error::custom("Invalid role")),
+        }
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::streaming::{StreamingChoice, StreamingResult};
+
+    /// answers with more chunks than the buffers hold
+    struct ChattyAgent;
+
+    #[async_trait::async_trait]
+    impl LoopAgent for ChattyAgent {
+        async fn stream_chat(
+            &self,
+            _prompt: &str,
+            _chat_history: Vec<Message>,
+        ) -> Result<StreamingResult> {
+            let chunks = (0..100).map(|i| {
+                Ok(StreamingChoice::Message(format!("chunk {}", i)))
+            });
+            Ok(Box::pin(futures::stream::iter(chunks)))
+        }
+
+        async fn call_tool(
+            &self,
+            name: &str,
+            _args: String,
+        ) -> Result<String> {
+            Ok(format!("{} result", name))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stalled_client_ends_the_loop() {
+        // the client holds its end of the stream but never reads
+        let (tx, _rx) = tokio::sync::mpsc::channel::<sse::Event>(1);
+        let (internal_tx, internal_rx) = tokio::sync::mpsc::channel(1);
+        let send_task = tokio::spawn(forward_responses(
+            internal_rx,
+            tx,
+            Duration::from_millis(50),
+        ));
+        let reasoning_loop =
+            ReasoningLoop::new(Arc::new(ChattyAgent)).with_stdout(false);
+
+        let loop_result = tokio::time::timeout(
+            Duration::from_secs(5),
+            reasoning_loop.stream(
+                "talk".to_string(),
+                vec![],
+                Some(internal_tx),
+            ),
+        )
+        .await
+        .expect("the loop stalled on the client");
+        assert!(loop_result.is_err());
+
+        let forwarded =
+            tokio::time::timeout(Duration::from_secs(5), send_task)
+                .await
+                .unwrap()
+                .unwrap();
+        assert!(forwarded.unwrap_err().to_string().contains("read nothing"));
+    }
+
+    #[test]
+    fn test_tool_call_result_is_structured() {
+        let portfolio =
+            json!([{"symbol": "SOL", "price": 150.0, "amount": 2.0}]);
+        let event = StreamResponse::from(LoopResponse::Nested {
+            parent_id: "toolu_1".to_string(),
+            depth: 1,
+            response: Box::new(LoopResponse::ToolCall {
+                name: "get_portfolio".to_string(),
+                result: portfolio.to_string(),
+            }),
+        });
+        assert_eq!(
+            serde_json::to_value(&event).unwrap()["content"]["content"],
+            json!({
+                "type": "ToolCall",
+                "content": {"name": "get_portfolio", "result": portfolio},
+            })
+        );
+
+        // text results, e.g. errors, stay strings
+        let event = StreamResponse::from(LoopResponse::ToolCall {
+            name: "swap".to_string(),
+            result: "Error: insufficient funds".to_string(),
+        });
+        assert_eq!(
+            serde_json::to_value(&event).unwrap()["content"]["result"],
+            json!("Error: insufficient funds")
+        );
+    }
+
+    #[cfg(feature = "solana")]
+    #[test]
+    fn test_balance_result_is_structured() {
+        use crate::solana::balance::TokenBalance;
+
+        let balance = TokenBalance {
+            amount: "1500000".to_string(),
+            decimals: 6,
+            ui_amount: "1.5".to_string(),
+        };
+        let event = StreamResponse::from(LoopResponse::ToolCall {
+            name: "get_spl_token_balance".to_string(),
+            result: serde_json::to_string(&balance).unwrap(),
+        });
+        assert_eq!(
+            serde_json::to_value(&event).unwrap()["content"]["result"],
+            json!({"amount": "1500000", "decimals": 6, "ui_amount": "1.5"})
+        );
+    }
+
+    #[test]
+    fn test_chat_history_with_tool_calls() {
+        let request: ChatRequest = serde_json::from_value(json!({
+            "prompt": "and my portfolio?",
+            "chat_history": [
+                {"role": "user", "content": "what's my balance?"},
+                {
+                    "role": "assistant",
+                    "content": "checking",
+                    "tool_calls": [{
+                        "id": "toolu_1",
+                        "name": "get_sol_balance",
+                        "arguments": "{}"
+                    }]
+                },
+                {
+                    "role": "tool",
+                    "tool_call_id": "toolu_1",
+                    "name": "get_sol_balance",
+                    "content": "1000000000"
+                },
+                {"role": "assistant", "content": "you have 1 SOL"},
+                // results the UI kept without their call
+                {
+                    "role": "tool",
+                    "name": "get_portfolio",
+                    "content": [{"symbol": "SOL"}]
+                },
+            ],
+        }))
+        .unwrap();
+
+        // what the reasoning loop itself appends for the same turns
+        let tool_result = |id: &str, result: &str| Message::User {
+            content: OneOrMany::one(UserContent::tool_result(
+                id,
+                OneOrMany::one(ToolResultContent::text(result)),
+            )),
+        };
+        assert_eq!(
+            request.chat_history,
+            vec![
+                Message::User {
+                    content: OneOrMany::one(UserContent::text(
+                        "what's my balance?",
+                    )),
+                },
+                Message::Assistant {
+                    content: OneOrMany::many(vec![
+                        AssistantContent::text("checking"),
+                        AssistantContent::tool_call(
+                            "toolu_1",
+                            "get_sol_balance",
+                            json!({}),
+                        ),
+                    ])
+                    .unwrap(),
+                },
+                tool_result("toolu_1", "1000000000"),
+                Message::Assistant {
+                    content: OneOrMany::one(AssistantContent::text(
+                        "you have 1 SOL",
+                    )),
+                },
+                Message::Assistant {
+                    content: OneOrMany::one(AssistantContent::tool_call(
+                        "history_4",
+                        "get_portfolio",
+                        json!({}),
+                    )),
+                },
+                tool_result("history_4", r#"[{"symbol":"SOL"}]"#),
+            ]
+        );
+
+        let invalid = json!({
+            "prompt": "hi",
+            "chat_history": [{"role": "tool", "content": "orphan"}],
+        });
+        assert!(serde_json::from_value::<ChatRequest>(invalid).is_err());
+    }
+
+    #[test]
+    fn test_oversized_history_is_truncated() {
+        let turn = |i: usize| {
+            vec![
+                json!({"role": "user", "content": format!("question {}", i)}),
+                json!({
+                    "role": "assistant",
+                    "content": "",
+                    "tool_calls": [{
+                        "id": format!("toolu_{}", i),
+                        "name": "get_sol_balance",
+                        "arguments": "{}"
+                    }]
+                }),
+                json!({
+                    "role": "tool",
+                    "tool_call_id": format!("toolu_{}", i),
+                    "name": "get_sol_balance",
+                    "content": "x".repeat(1000)
+                }),
+                json!({
+                    "role": "assistant",
+                    "content": format!("answer {}", i)
+                }),
+            ]
+        };
+        let history: Vec<_> = (0..10).flat_map(turn).collect();
+        let mut request: ChatRequest = serde_json::from_value(json!({
+            "prompt": "and now?",
+            "chat_history": history,
+            "preamble": "you are a trader",
+        }))
+        .unwrap();
+        let full = request.chat_history.clone();
+        let turn_chars: usize = full[..4].iter().map(message_chars).sum();
+
+        // room for the preamble, the prompt and three turns
+        let max_chars = "you are a trader".len()
+            + "and now?".len()
+            + turn_chars * 3
+            + turn_chars / 2;
+        assert_eq!(request.truncate_history(max_chars), 28);
+        assert_eq!(request.chat_history, full[28..]);
+        assert_eq!(request.preamble.as_deref(), Some("you are a trader"));
+        assert!(starts_turn(&request.chat_history[0]));
+
+        // a history that fits stays as it is
+        assert_eq!(request.truncate_history(max_chars), 0);
+        assert_eq!(request.chat_history.len(), 12);
+
+        // the preamble counts against the limit but is never dropped
+        request.preamble = Some("p".repeat(max_chars));
+        assert_eq!(request.truncate_history(max_chars), 12);
+        assert!(request.chat_history.is_empty());
+        assert_eq!(
+            request.preamble.as_ref().map(String::len),
+            Some(max_chars)
+        );
+    }
 }
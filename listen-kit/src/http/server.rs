@@ -2,12 +2,263 @@ use actix_cors::Cors;
 use actix_web::middleware::{Compress, Logger};
 use actix_web::{web, App, HttpServer};
 use privy::Privy;
+use std::sync::Arc;
 
-use super::routes::{auth, healthz, stream};
+use super::alerts::{AlertMonitor, AlertStore, RedisAlertStore};
+use super::context::default_context_providers;
+use super::info::{info, Features};
+use super::routes::{
+    auth, create_alert, create_delegation, create_webhook, delete_alert,
+    get_audit, get_delegation, get_spending_limits, healthz,
+    revoke_delegation, set_spending_limits, stream,
+    DEFAULT_MAX_HISTORY_CHARS,
+};
 use super::state::AppState;
+use super::webhooks::{WebhookDelivery, WebhookStore};
+use crate::audit::RedisAuditSink;
+use crate::chain_config::ChainConfig;
+use crate::model::ModelConfig;
+use crate::signer::delegation::{DelegationPolicy, RedisGrantStore};
+#[cfg(feature = "ledger")]
+use crate::signer::ledger::LedgerSigner;
+use crate::signer::privy::{AuditRetryObserver, WalletPolicy};
+#[cfg(feature = "solana")]
+use crate::signer::solana::LocalSolanaSigner;
+use crate::signer::spending_guard::{
+    RedisOutflowPricer, RedisSpendingStore, SpendingPolicy,
+};
+use crate::signer::TransactionSigner;
+use crate::solana::tools::create_rpc;
+use crate::tool_allowlist::ToolAllowlist;
+use crate::usage::RedisUsageReporter;
 
 pub async fn run_server(privy: Privy) -> std::io::Result<()> {
-    let state = web::Data::new(AppState::new(privy));
+    // a misconfigured model provider should fail here, not on first request
+    let models = ModelConfig::from_env()
+        .and_then(|models| models.validate().map(|_| models))
+        .map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid model provider config: {}", e),
+            )
+        })?;
+
+    ChainConfig::from_env()
+        .and_then(|config| config.install())
+        .map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid chain config: {}", e),
+            )
+        })?;
+
+    let features = Features::from_env().map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid feature config: {}", e),
+        )
+    })?;
+
+    let tools = ToolAllowlist::from_env().map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid tool allowlist: {}", e),
+        )
+    })?;
+
+    // webhooks and the audit log are optional, they require REDIS_URL
+    let webhooks = match std::env::var("REDIS_URL") {
+        Ok(redis_url) => {
+            let store =
+                Arc::new(WebhookStore::new(&redis_url).await.map_err(
+                    |e| std::io::Error::new(std::io::ErrorKind::Other, e),
+                )?);
+            let delivery =
+                WebhookDelivery::new(store.clone()).map_err(|e| {
+                    std::io::Error::new(std::io::ErrorKind::Other, e)
+                })?;
+            let listener = store.clone();
+            tokio::spawn(async move {
+                if let Err(e) = listener.listen_for_price_updates().await {
+                    tracing::error!("Webhook price listener failed: {}", e);
+                }
+            });
+            tokio::spawn(delivery.run());
+            Some(store)
+        }
+        Err(_) => {
+            tracing::warn!("REDIS_URL not set, webhooks are disabled");
+            None
+        }
+    };
+
+    let alerts = match std::env::var("REDIS_URL") {
+        Ok(redis_url) => {
+            let store: Arc<dyn AlertStore> =
+                Arc::new(RedisAlertStore::new(&redis_url).map_err(|e| {
+                    std::io::Error::new(std::io::ErrorKind::Other, e)
+                })?);
+            let monitor = AlertMonitor::new(store.clone()).map_err(|e| {
+                std::io::Error::new(std::io::ErrorKind::Other, e)
+            })?;
+            tokio::spawn(async move {
+                if let Err(e) = monitor.run(&redis_url).await {
+                    tracing::error!("Price alert listener failed: {}", e);
+                }
+            });
+            Some(store)
+        }
+        Err(_) => None,
+    };
+
+    let audit = match std::env::var("REDIS_URL") {
+        Ok(redis_url) => {
+            Some(Arc::new(RedisAuditSink::new(&redis_url).map_err(|e| {
+                std::io::Error::new(std::io::ErrorKind::Other, e)
+            })?))
+        }
+        Err(_) => None,
+    };
+
+    let usage = match std::env::var("REDIS_URL") {
+        Ok(redis_url) => {
+            Some(Arc::new(RedisUsageReporter::new(&redis_url).map_err(
+                |e| std::io::Error::new(std::io::ErrorKind::Other, e),
+            )?))
+        }
+        Err(_) => None,
+    };
+
+    // empty values, as in .env.example, are unset
+    let max_tokens_total = match std::env::var("MAX_TOKENS_TOTAL") {
+        Ok(s) if !s.is_empty() => Some(s.parse::<usize>().map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid MAX_TOKENS_TOTAL: {}", e),
+            )
+        })?),
+        _ => None,
+    };
+
+    let max_history_chars = match std::env::var("MAX_HISTORY_CHARS") {
+        Ok(s) if !s.is_empty() => s.parse::<usize>().map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid MAX_HISTORY_CHARS: {}", e),
+            )
+        })?,
+        _ => DEFAULT_MAX_HISTORY_CHARS,
+    };
+
+    // self-hosted deployments sign every request with one local keypair or
+    // ledger instead of the user's privy wallet
+    let local_signer: Option<Arc<dyn TransactionSigner>> =
+        match std::env::var("SIGNER").as_deref() {
+            Ok("privy") | Err(_) => None,
+            #[cfg(feature = "solana")]
+            Ok("local") => Some(Arc::new(
+                LocalSolanaSigner::from_env_config().map_err(|e| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("Invalid local signer config: {}", e),
+                    )
+                })?,
+            )),
+            #[cfg(feature = "ledger")]
+            Ok("ledger") => Some(Arc::new(
+                LedgerSigner::from_env_config().await.map_err(|e| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("Invalid ledger signer config: {}", e),
+                    )
+                })?,
+            )),
+            Ok(signer) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Unsupported SIGNER: {}", signer),
+                ))
+            }
+        };
+
+    // which privy wallets may sign, every delegated one by default
+    let wallet_policy = match std::env::var("PRIVY_WALLET_POLICY") {
+        Ok(s) => s.parse::<WalletPolicy>().map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, e)
+        })?,
+        Err(_) => WalletPolicy::default(),
+    };
+
+    // spending limits are enforced whenever there is somewhere to keep them
+    let spending = match std::env::var("REDIS_URL") {
+        Ok(redis_url) => {
+            let to_io = |e: anyhow::Error| {
+                std::io::Error::new(std::io::ErrorKind::Other, e)
+            };
+            Some(SpendingPolicy {
+                store: Arc::new(
+                    RedisSpendingStore::new(&redis_url).map_err(to_io)?,
+                ),
+                pricer: Arc::new(
+                    RedisOutflowPricer::new(&redis_url, create_rpc())
+                        .map_err(to_io)?,
+                ),
+            })
+        }
+        Err(_) => {
+            tracing::warn!("REDIS_URL not set, spending limits are disabled");
+            None
+        }
+    };
+
+    // grants are signed with the secret, so bots can't be delegated without
+    // one
+    let delegation = match (
+        std::env::var("REDIS_URL"),
+        std::env::var("DELEGATION_SECRET"),
+    ) {
+        (Ok(redis_url), Ok(secret)) => {
+            let to_io = |e: anyhow::Error| {
+                std::io::Error::new(std::io::ErrorKind::Other, e)
+            };
+            Some(DelegationPolicy {
+                store: Arc::new(
+                    RedisGrantStore::new(&redis_url, secret)
+                        .map_err(to_io)?,
+                ),
+                pricer: Arc::new(
+                    RedisOutflowPricer::new(&redis_url, create_rpc())
+                        .map_err(to_io)?,
+                ),
+            })
+        }
+        _ => None,
+    };
+
+    let mut state = AppState::new(
+        privy.with_retry_observer(Arc::new(AuditRetryObserver)),
+        webhooks,
+        audit,
+        models,
+        usage,
+        max_tokens_total,
+        default_context_providers(std::env::var("REDIS_URL").ok().as_deref()),
+        local_signer,
+    )
+    .with_features(features)
+    .with_tools(tools)
+    .with_max_history_chars(max_history_chars)
+    .with_wallet_policy(wallet_policy);
+    if let Some(spending) = spending {
+        state = state.with_spending_policy(spending);
+    }
+    if let Some(delegation) = delegation {
+        state = state.with_delegation_policy(delegation);
+    }
+    if let Some(alerts) = alerts {
+        state = state.with_alert_store(alerts);
+    }
+    let state = web::Data::new(state);
 
     HttpServer::new(move || {
         App::new()
@@ -16,8 +267,18 @@ pub async fn run_server(privy: Privy) -> std::io::Result<()> {
             .wrap(Cors::permissive())
             .app_data(state.clone())
             .service(healthz)
+            .service(info)
             .service(stream)
             .service(auth)
+            .service(create_webhook)
+            .service(create_alert)
+            .service(delete_alert)
+            .service(get_audit)
+            .service(get_spending_limits)
+            .service(set_spending_limits)
+            .service(create_delegation)
+            .service(get_delegation)
+            .service(revoke_delegation)
     })
     .bind("0.0.0.0:6969")?
     .run()
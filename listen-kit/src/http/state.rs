@@ -1,14 +1,127 @@
+use super::alerts::AlertStore;
+use super::context::ContextProvider;
+use super::info::Features;
+use super::routes::DEFAULT_MAX_HISTORY_CHARS;
+use super::webhooks::WebhookStore;
+use crate::audit::RedisAuditSink;
+use crate::model::ModelConfig;
+use crate::signer::delegation::DelegationPolicy;
+use crate::signer::middleware::SignerMiddleware;
+use crate::signer::privy::WalletPolicy;
+use crate::signer::spending_guard::SpendingPolicy;
+use crate::signer::TransactionSigner;
+use crate::tool_allowlist::ToolAllowlist;
+use crate::usage::RedisUsageReporter;
 use privy::Privy;
 use std::sync::Arc;
 
 pub struct AppState {
     pub(crate) privy: Arc<Privy>,
+    pub(crate) webhooks: Option<Arc<WebhookStore>>,
+    pub(crate) audit: Option<Arc<RedisAuditSink>>,
+    pub(crate) models: ModelConfig,
+    pub(crate) usage: Option<Arc<RedisUsageReporter>>,
+    /// default token budget per request, `MAX_TOKENS_TOTAL`
+    pub(crate) max_tokens_total: Option<usize>,
+    /// characters of chat history per request, `MAX_HISTORY_CHARS`
+    pub(crate) max_history_chars: usize,
+    /// dynamic context injected into every turn
+    pub(crate) context_providers: Vec<Arc<dyn ContextProvider>>,
+    /// signs for every user instead of their privy wallet, `SIGNER=local`
+    pub(crate) local_signer: Option<Arc<dyn TransactionSigner>>,
+    /// per-wallet spending limits enforced before signing
+    pub(crate) spending: Option<SpendingPolicy>,
+    /// privy wallets the server signs with, `PRIVY_WALLET_POLICY`
+    pub(crate) wallet_policy: WalletPolicy,
+    /// grants bots sign under, `DELEGATION_SECRET`
+    pub(crate) delegation: Option<DelegationPolicy>,
+    /// wrapped around the signer of every request, outermost first
+    pub(crate) signer_middlewares: Vec<Arc<dyn SignerMiddleware>>,
+    /// agents requests may ask for, `ENABLED_AGENTS`
+    pub(crate) features: Features,
+    /// price alerts registered through `POST /alerts`
+    pub(crate) alerts: Option<Arc<dyn AlertStore>>,
+    /// tools the agents may register, `ALLOWED_TOOLS`
+    pub(crate) tools: ToolAllowlist,
 }
 
 impl AppState {
-    pub fn new(privy: Privy) -> Self {
+    pub fn new(
+        privy: Privy,
+        webhooks: Option<Arc<WebhookStore>>,
+        audit: Option<Arc<RedisAuditSink>>,
+        models: ModelConfig,
+        usage: Option<Arc<RedisUsageReporter>>,
+        max_tokens_total: Option<usize>,
+        context_providers: Vec<Arc<dyn ContextProvider>>,
+        local_signer: Option<Arc<dyn TransactionSigner>>,
+    ) -> Self {
         Self {
             privy: Arc::new(privy),
+            webhooks,
+            audit,
+            models,
+            usage,
+            max_tokens_total,
+            max_history_chars: DEFAULT_MAX_HISTORY_CHARS,
+            context_providers,
+            local_signer,
+            spending: None,
+            wallet_policy: WalletPolicy::default(),
+            delegation: None,
+            signer_middlewares: Vec::new(),
+            features: Features::compiled(),
+            alerts: None,
+            tools: ToolAllowlist::all(),
         }
     }
+
+    pub fn with_max_history_chars(
+        mut self,
+        max_history_chars: usize,
+    ) -> Self {
+        self.max_history_chars = max_history_chars;
+        self
+    }
+
+    pub fn with_spending_policy(mut self, spending: SpendingPolicy) -> Self {
+        self.spending = Some(spending);
+        self
+    }
+
+    pub fn with_wallet_policy(mut self, wallet_policy: WalletPolicy) -> Self {
+        self.wallet_policy = wallet_policy;
+        self
+    }
+
+    pub fn with_delegation_policy(
+        mut self,
+        delegation: DelegationPolicy,
+    ) -> Self {
+        self.delegation = Some(delegation);
+        self
+    }
+
+    pub fn with_signer_middleware(
+        mut self,
+        middleware: Arc<dyn SignerMiddleware>,
+    ) -> Self {
+        self.signer_middlewares.push(middleware);
+        self
+    }
+
+    pub fn with_features(mut self, features: Features) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn with_tools(mut self, tools: ToolAllowlist) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    pub fn with_alert_store(mut self, alerts: Arc<dyn AlertStore>) -> Self {
+        self.alerts = Some(alerts);
+        self
+    }
 }
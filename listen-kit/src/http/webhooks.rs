@@ -0,0 +1,683 @@
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use hmac::{Hmac, Mac};
+use redis::AsyncCommands;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const WEBHOOKS_SET_KEY: &str = "webhooks";
+pub(crate) const PRICE_UPDATES_CHANNEL: &str = "price_updates";
+const MAX_DELIVERY_RETRIES: u32 = 3;
+const MAX_CONSECUTIVE_FAILURES: u32 = 10;
+/// webhooks whose queues are drained at the same time
+const MAX_CONCURRENT_DELIVERIES: usize = 16;
+/// a delivery with its retries, a blackholed endpoint only holds up its
+/// own queue for this long a tick
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+/// how stale the webhooks matched against price updates can get, saves on
+/// other servers show up after at most this long
+const WEBHOOKS_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Price update as published by listen-data on the `price_updates` channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceUpdate {
+    pub name: String,
+    pub pubkey: String,
+    pub price: f64,
+    pub market_cap: f64,
+    pub timestamp: u64,
+    pub slot: u64,
+    pub swap_amount: f64,
+    pub owner: String,
+    pub signature: String,
+    pub multi_hop: bool,
+    pub is_buy: bool,
+    pub is_pump: bool,
+}
+
+impl PriceUpdate {
+    pub fn event_name(&self) -> &'static str {
+        if self.is_buy {
+            "buy"
+        } else {
+            "sell"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookRequest {
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub mint_filter: Option<Vec<String>>,
+    #[serde(default)]
+    pub min_market_cap_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookStatus {
+    Active,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: String,
+    pub user_id: String,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+    pub mint_filter: Option<Vec<String>>,
+    pub min_market_cap_usd: Option<f64>,
+    pub status: WebhookStatus,
+    pub consecutive_failures: u32,
+    pub created_at: String,
+}
+
+impl Webhook {
+    pub fn new(user_id: String, request: WebhookRequest) -> Self {
+        Self {
+            id: uuid_v4(),
+            user_id,
+            url: request.url,
+            secret: request.secret,
+            events: request.events,
+            mint_filter: request.mint_filter,
+            min_market_cap_usd: request.min_market_cap_usd,
+            status: WebhookStatus::Active,
+            consecutive_failures: 0,
+            created_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// "price_update" subscribes to every event, otherwise the event name
+    /// ("buy" / "sell") has to be listed explicitly
+    pub fn matches(&self, update: &PriceUpdate) -> bool {
+        if self.status != WebhookStatus::Active {
+            return false;
+        }
+        let event = update.event_name();
+        if !self
+            .events
+            .iter()
+            .any(|e| e == "price_update" || e == event)
+        {
+            return false;
+        }
+        if let Some(mints) = &self.mint_filter {
+            if !mints.iter().any(|m| m == &update.pubkey) {
+                return false;
+            }
+        }
+        if let Some(min_market_cap) = self.min_market_cap_usd {
+            if update.market_cap < min_market_cap {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    webhook_id: &'a str,
+    event: &'a str,
+    data: &'a PriceUpdate,
+}
+
+pub(crate) fn uuid_v4() -> String {
+    let bytes: [u8; 16] = rand::random();
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// hex-encoded HMAC-SHA256 of the body, sent as `X-Signature`
+pub fn sign_payload(body: &str, secret: &str) -> Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|e| anyhow!("Invalid webhook secret: {}", e))?;
+    mac.update(body.as_bytes());
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+/// whether `ip` is reachable from the internet, rather than an address
+/// of the server's own network
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // shared address space, carrier-grade NAT
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // unique local and link-local
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Checks a user given url is http(s) and only resolves to public
+/// addresses, so it can't be used to reach the services next to the
+/// server
+pub(crate) async fn check_public_url(url: &str) -> Result<()> {
+    let url = reqwest::Url::parse(url)
+        .map_err(|e| anyhow!("Invalid url {}: {}", url, e))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(anyhow!("url must be http(s)"));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("url has no host"))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| anyhow!("Failed to resolve {}: {}", host, e))?;
+    for addr in addrs {
+        if !is_public(addr.ip()) {
+            return Err(anyhow!("{} is not a public address", host));
+        }
+    }
+    Ok(())
+}
+
+/// drops the private addresses a host resolves to, a url checked at
+/// creation can point somewhere else by the time it's delivered to
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect::<Vec<SocketAddr>>();
+            if addrs.is_empty() {
+                return Err(format!(
+                    "{} has no public address",
+                    name.as_str()
+                )
+                .into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Posts the deliveries of webhooks and price alerts to their user given
+/// urls, never to a private or loopback address
+#[derive(Clone)]
+pub(crate) struct DeliveryClient {
+    http: reqwest::Client,
+    allow_private: bool,
+}
+
+impl DeliveryClient {
+    /// fails rather than fall back to a client that could reach private
+    /// addresses
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            // a redirect could point anywhere
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .redirect(reqwest::redirect::Policy::none())
+                .dns_resolver(Arc::new(PublicResolver))
+                .build()
+                .map_err(|e| {
+                    anyhow!("Failed to build delivery client: {}", e)
+                })?,
+            allow_private: false,
+        })
+    }
+
+    /// delivers to a local server
+    #[cfg(test)]
+    pub fn allowing_private() -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            allow_private: true,
+        }
+    }
+
+    /// posts the json `body` to `url`, retrying 200ms, 400ms and 800ms
+    /// after a failure
+    pub async fn post_with_retries(
+        &self,
+        url: &str,
+        body: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<()> {
+        if !self.allow_private {
+            check_public_url(url).await?;
+        }
+        let mut attempt = 0;
+        loop {
+            match self.post(url, body, headers).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= MAX_DELIVERY_RETRIES => return Err(e),
+                Err(e) => {
+                    let backoff =
+                        Duration::from_millis(200 * 2u64.pow(attempt));
+                    tracing::debug!(
+                        "Retrying delivery in {:?}: {}",
+                        backoff,
+                        e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    async fn post(
+        &self,
+        url: &str,
+        body: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<()> {
+        let mut request = self
+            .http
+            .post(url)
+            .header("Content-Type", "application/json")
+            .body(body.to_string());
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let res = request.send().await?;
+        if !res.status().is_success() {
+            return Err(anyhow!("Webhook returned status {}", res.status()));
+        }
+        Ok(())
+    }
+}
+
+struct CachedWebhooks {
+    webhooks: Arc<Vec<Webhook>>,
+    loaded_at: Instant,
+}
+
+pub struct WebhookStore {
+    client: redis::Client,
+    /// shared by every command, reconnects on its own
+    conn: redis::aio::ConnectionManager,
+    cache: tokio::sync::RwLock<Option<CachedWebhooks>>,
+}
+
+impl WebhookStore {
+    pub async fn new(redis_url: &str) -> Result<Self> {
+        let client = redis::Client::open(redis_url)?;
+        let conn = redis::aio::ConnectionManager::new(client.clone()).await?;
+        Ok(Self {
+            client,
+            conn,
+            cache: tokio::sync::RwLock::new(None),
+        })
+    }
+
+    fn webhook_key(id: &str) -> String {
+        format!("webhook:{}", id)
+    }
+
+    fn queue_key(id: &str) -> String {
+        format!("webhook_queue:{}", id)
+    }
+
+    fn connection(&self) -> redis::aio::ConnectionManager {
+        self.conn.clone()
+    }
+
+    /// the cached webhooks see the save right away
+    pub async fn save(&self, webhook: &Webhook) -> Result<()> {
+        let mut conn = self.connection();
+        let _: () = conn
+            .set(
+                Self::webhook_key(&webhook.id),
+                serde_json::to_string(webhook)?,
+            )
+            .await?;
+        let _: () = conn.sadd(WEBHOOKS_SET_KEY, &webhook.id).await?;
+
+        if let Some(cache) = self.cache.write().await.as_mut() {
+            let mut webhooks = cache.webhooks.as_ref().clone();
+            match webhooks.iter_mut().find(|w| w.id == webhook.id) {
+                Some(cached) => *cached = webhook.clone(),
+                None => webhooks.push(webhook.clone()),
+            }
+            cache.webhooks = Arc::new(webhooks);
+        }
+        Ok(())
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<Webhook>> {
+        let mut conn = self.connection();
+        let raw: Option<String> = conn.get(Self::webhook_key(id)).await?;
+        match raw {
+            Some(raw) => Ok(Some(serde_json::from_str(&raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn get_all(&self) -> Result<Vec<Webhook>> {
+        let mut conn = self.connection();
+        let ids: Vec<String> = conn.smembers(WEBHOOKS_SET_KEY).await?;
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let keys: Vec<String> =
+            ids.iter().map(|id| Self::webhook_key(id)).collect();
+        let raw: Vec<Option<String>> = conn.mget(keys).await?;
+        raw.into_iter()
+            .flatten()
+            .map(|raw| Ok(serde_json::from_str(&raw)?))
+            .collect()
+    }
+
+    /// `get_all`, reloaded once it is `WEBHOOKS_CACHE_TTL` old
+    pub async fn get_all_cached(&self) -> Result<Arc<Vec<Webhook>>> {
+        if let Some(cache) = self.cache.read().await.as_ref() {
+            if cache.loaded_at.elapsed() < WEBHOOKS_CACHE_TTL {
+                return Ok(cache.webhooks.clone());
+            }
+        }
+        let webhooks = Arc::new(self.get_all().await?);
+        *self.cache.write().await = Some(CachedWebhooks {
+            webhooks: webhooks.clone(),
+            loaded_at: Instant::now(),
+        });
+        Ok(webhooks)
+    }
+
+    pub async fn enqueue(&self, id: &str, body: &str) -> Result<()> {
+        let mut conn = self.connection();
+        let _: () = conn.rpush(Self::queue_key(id), body).await?;
+        Ok(())
+    }
+
+    pub async fn dequeue(&self, id: &str) -> Result<Option<String>> {
+        let mut conn = self.connection();
+        Ok(conn.lpop(Self::queue_key(id), None).await?)
+    }
+
+    /// subscribes to the price updates channel and buffers a delivery for
+    /// every active webhook that matches the update
+    pub async fn listen_for_price_updates(self: Arc<Self>) -> Result<()> {
+        let mut pubsub = self.client.get_async_pubsub().await?;
+        pubsub.subscribe(PRICE_UPDATES_CHANNEL).await?;
+        let mut messages = pubsub.on_message();
+
+        while let Some(msg) = messages.next().await {
+            let payload: String = match msg.get_payload() {
+                Ok(payload) => payload,
+                Err(e) => {
+                    tracing::error!("Failed to get message payload: {}", e);
+                    continue;
+                }
+            };
+            let update: PriceUpdate = match serde_json::from_str(&payload) {
+                Ok(update) => update,
+                Err(e) => {
+                    tracing::error!("Failed to parse price update: {}", e);
+                    continue;
+                }
+            };
+            let webhooks = match self.get_all_cached().await {
+                Ok(webhooks) => webhooks,
+                Err(e) => {
+                    tracing::error!("Failed to load webhooks: {}", e);
+                    continue;
+                }
+            };
+            for webhook in webhooks.iter().filter(|w| w.matches(&update)) {
+                let body = match serde_json::to_string(&WebhookPayload {
+                    webhook_id: &webhook.id,
+                    event: update.event_name(),
+                    data: &update,
+                }) {
+                    Ok(body) => body,
+                    Err(e) => {
+                        tracing::error!(
+                            "Failed to serialize delivery for {}: {}",
+                            webhook.id,
+                            e
+                        );
+                        continue;
+                    }
+                };
+                if let Err(e) = self.enqueue(&webhook.id, &body).await {
+                    tracing::error!(
+                        "Failed to enqueue delivery for {}: {}",
+                        webhook.id,
+                        e
+                    );
+                }
+            }
+        }
+
+        Err(anyhow!("Price updates subscription closed"))
+    }
+}
+
+/// Background task draining the `webhook_queue:{id}` lists
+pub struct WebhookDelivery {
+    store: Arc<WebhookStore>,
+    delivery: DeliveryClient,
+    poll_interval: Duration,
+}
+
+impl WebhookDelivery {
+    pub fn new(store: Arc<WebhookStore>) -> Result<Self> {
+        Ok(Self {
+            store,
+            delivery: DeliveryClient::new()?,
+            poll_interval: Duration::from_millis(500),
+        })
+    }
+
+    pub async fn run(self) {
+        loop {
+            if let Err(e) = self.process_queues().await {
+                tracing::error!("Webhook delivery error: {}", e);
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    /// the queues are drained concurrently, a failed delivery leaves the
+    /// rest of its queue to the next tick
+    async fn process_queues(&self) -> Result<()> {
+        let webhooks = self.store.get_all_cached().await?;
+        futures::stream::iter(
+            webhooks
+                .iter()
+                .filter(|webhook| webhook.status == WebhookStatus::Active)
+                .cloned(),
+        )
+        .map(|webhook| self.drain_queue(webhook))
+        .buffer_unordered(MAX_CONCURRENT_DELIVERIES)
+        .for_each(|result| async move {
+            if let Err(e) = result {
+                tracing::error!("Webhook delivery error: {}", e);
+            }
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn drain_queue(&self, mut webhook: Webhook) -> Result<()> {
+        while let Some(body) = self.store.dequeue(&webhook.id).await? {
+            let delivered = tokio::time::timeout(
+                DELIVERY_TIMEOUT,
+                self.deliver_with_retries(&webhook, &body),
+            )
+            .await
+            .unwrap_or_else(|_| {
+                Err(anyhow!("timed out after {:?}", DELIVERY_TIMEOUT))
+            });
+            match delivered {
+                Ok(()) => {
+                    if webhook.consecutive_failures > 0 {
+                        webhook.consecutive_failures = 0;
+                        self.store.save(&webhook).await?;
+                    }
+                }
+                Err(e) => {
+                    webhook.consecutive_failures += 1;
+                    tracing::warn!(
+                        "Webhook {} delivery failed ({} in a row): {}",
+                        webhook.id,
+                        webhook.consecutive_failures,
+                        e
+                    );
+                    if webhook.consecutive_failures
+                        >= MAX_CONSECUTIVE_FAILURES
+                    {
+                        tracing::error!(
+                            "Disabling webhook {} after {} failures",
+                            webhook.id,
+                            webhook.consecutive_failures
+                        );
+                        webhook.status = WebhookStatus::Failed;
+                    }
+                    self.store.save(&webhook).await?;
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn deliver_with_retries(
+        &self,
+        webhook: &Webhook,
+        body: &str,
+    ) -> Result<()> {
+        let signature = sign_payload(body, &webhook.secret)?;
+        self.delivery
+            .post_with_retries(
+                &webhook.url,
+                body,
+                &[("X-Signature", &signature)],
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_update(
+        pubkey: &str,
+        market_cap: f64,
+        is_buy: bool,
+    ) -> PriceUpdate {
+        PriceUpdate {
+            name: "TEST".to_string(),
+            pubkey: pubkey.to_string(),
+            price: 0.001,
+            market_cap,
+            timestamp: 0,
+            slot: 0,
+            swap_amount: 100.0,
+            owner: "owner".to_string(),
+            signature: "sig".to_string(),
+            multi_hop: false,
+            is_buy,
+            is_pump: true,
+        }
+    }
+
+    fn make_webhook(
+        events: Vec<&str>,
+        mint_filter: Option<Vec<&str>>,
+        min_market_cap_usd: Option<f64>,
+    ) -> Webhook {
+        Webhook::new(
+            "user".to_string(),
+            WebhookRequest {
+                url: "https://example.com/hook".to_string(),
+                secret: "secret".to_string(),
+                events: events.into_iter().map(String::from).collect(),
+                mint_filter: mint_filter
+                    .map(|m| m.into_iter().map(String::from).collect()),
+                min_market_cap_usd,
+            },
+        )
+    }
+
+    #[test]
+    fn test_sign_payload() {
+        // RFC 4231 test case 2
+        let signature =
+            sign_payload("what do ya want for nothing?", "Jefe").unwrap();
+        assert_eq!(
+            signature,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_webhook_matches() {
+        let webhook =
+            make_webhook(vec!["buy"], Some(vec!["mint1"]), Some(1000.0));
+        assert!(webhook.matches(&make_update("mint1", 5000.0, true)));
+        assert!(!webhook.matches(&make_update("mint1", 5000.0, false)));
+        assert!(!webhook.matches(&make_update("mint2", 5000.0, true)));
+        assert!(!webhook.matches(&make_update("mint1", 500.0, true)));
+
+        let mut webhook = make_webhook(vec!["price_update"], None, None);
+        assert!(webhook.matches(&make_update("mint2", 1.0, false)));
+        webhook.status = WebhookStatus::Failed;
+        assert!(!webhook.matches(&make_update("mint2", 1.0, false)));
+    }
+
+    #[tokio::test]
+    async fn test_check_public_url() {
+        assert!(check_public_url("https://1.1.1.1/hook").await.is_ok());
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://localhost/hook",
+            "http://10.0.0.5/hook",
+            "http://192.168.1.1/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/hook",
+            "http://[::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "ftp://1.1.1.1/hook",
+            "not a url",
+        ] {
+            assert!(check_public_url(url).await.is_err(), "{}", url);
+        }
+    }
+}
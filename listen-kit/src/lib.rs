@@ -7,12 +7,20 @@ pub mod solana;
 #[cfg(feature = "evm")]
 pub mod evm;
 
+pub mod audit;
+pub mod chain_config;
 pub mod common;
 pub mod cross_chain;
 pub mod data;
+pub mod delegate;
 pub mod dexscreener;
+pub mod model;
 pub mod reasoning_loop;
+pub mod replay;
 pub mod signer;
+pub mod tool_allowlist;
+pub mod tool_result;
+pub mod usage;
 
 #[ctor::ctor]
 fn init() {
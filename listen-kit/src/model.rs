@@ -0,0 +1,281 @@
+use anyhow::{anyhow, Result};
+use rig::agent::AgentBuilder;
+use rig::providers::anthropic::completion::CompletionModel as AnthropicCompletionModel;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const DEFAULT_MODEL: &str = rig::providers::anthropic::CLAUDE_3_5_SONNET;
+pub const CHAINS: [&str; 3] = ["solana", "evm", "omni"];
+
+/// The providers the agents can run on. The reasoning loop relies on
+/// streamed tool calls, which the rig version in use only implements for
+/// the Anthropic messages API, so every provider speaks it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderName {
+    Anthropic,
+    /// self-hosted endpoint speaking the Anthropic messages API
+    Local,
+}
+
+impl ProviderName {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "anthropic" => Ok(ProviderName::Anthropic),
+            "local" => Ok(ProviderName::Local),
+            _ => Err(anyhow!(
+                "Unknown model provider: {}, use anthropic or local",
+                s
+            )),
+        }
+    }
+
+    pub fn default_api_key_env(&self) -> &'static str {
+        match self {
+            ProviderName::Anthropic => "ANTHROPIC_API_KEY",
+            ProviderName::Local => "LOCAL_API_KEY",
+        }
+    }
+
+    pub fn default_api_base_env(&self) -> &'static str {
+        match self {
+            ProviderName::Anthropic => "ANTHROPIC_API_BASE",
+            ProviderName::Local => "LOCAL_API_BASE",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelProvider {
+    pub provider: ProviderName,
+    pub api_base: Option<String>,
+    pub api_key_env: String,
+    pub model: String,
+}
+
+impl Default for ModelProvider {
+    fn default() -> Self {
+        Self {
+            provider: ProviderName::Anthropic,
+            api_base: None,
+            api_key_env: ProviderName::Anthropic
+                .default_api_key_env()
+                .to_string(),
+            model: DEFAULT_MODEL.to_string(),
+        }
+    }
+}
+
+impl ModelProvider {
+    /// parses `provider:model`, e.g. `anthropic:claude-3-5-sonnet-latest`;
+    /// the api base is read from `<PROVIDER>_API_BASE` if set
+    pub fn parse(s: &str) -> Result<Self> {
+        let (provider, model) = s.split_once(':').ok_or_else(|| {
+            anyhow!("Invalid model provider '{}', expected provider:model", s)
+        })?;
+        let provider = ProviderName::parse(provider.trim())?;
+        Ok(Self {
+            provider,
+            api_base: std::env::var(provider.default_api_base_env()).ok(),
+            api_key_env: provider.default_api_key_env().to_string(),
+            model: model.trim().to_string(),
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.model.is_empty() {
+            return Err(anyhow!(
+                "Model name is empty for {:?}",
+                self.provider
+            ));
+        }
+        if self.provider == ProviderName::Local && self.api_base.is_none() {
+            return Err(anyhow!("Local provider requires an api base"));
+        }
+        match std::env::var(&self.api_key_env) {
+            Ok(key) if !key.is_empty() => Ok(()),
+            _ => Err(anyhow!(
+                "{} is not set for {:?} provider",
+                self.api_key_env,
+                self.provider
+            )),
+        }
+    }
+
+    pub fn completion_model(&self) -> Result<AnthropicCompletionModel> {
+        self.validate()?;
+        let api_key = std::env::var(&self.api_key_env)?;
+        let mut builder =
+            rig::providers::anthropic::ClientBuilder::new(&api_key);
+        if let Some(api_base) = &self.api_base {
+            builder = builder.base_url(api_base);
+        }
+        Ok(builder.build().completion_model(&self.model))
+    }
+
+    pub fn agent_builder(
+        &self,
+    ) -> Result<AgentBuilder<AnthropicCompletionModel>> {
+        Ok(AgentBuilder::new(self.completion_model()?).max_tokens(1024 * 4))
+    }
+}
+
+/// Default model provider plus per-chain overrides
+#[derive(Debug, Clone, Default)]
+pub struct ModelConfig {
+    pub default: ModelProvider,
+    pub per_chain: HashMap<String, ModelProvider>,
+}
+
+impl ModelConfig {
+    /// reads `MODEL_PROVIDER` and `MODEL_PROVIDER_<CHAIN>` (e.g.
+    /// `MODEL_PROVIDER_SOLANA`), each in the `provider:model` format
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    pub fn from_vars(get: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let default = match get("MODEL_PROVIDER") {
+            Some(s) => ModelProvider::parse(&s)?,
+            None => ModelProvider::default(),
+        };
+        let mut per_chain = HashMap::new();
+        for chain in CHAINS {
+            let key = format!("MODEL_PROVIDER_{}", chain.to_uppercase());
+            if let Some(s) = get(&key) {
+                per_chain
+                    .insert(chain.to_string(), ModelProvider::parse(&s)?);
+            }
+        }
+        Ok(Self { default, per_chain })
+    }
+
+    /// fails if any of the configured providers cannot be constructed,
+    /// meant to be called at startup
+    pub fn validate(&self) -> Result<()> {
+        self.default.validate()?;
+        for (chain, provider) in &self.per_chain {
+            provider.validate().map_err(|e| {
+                anyhow!("Invalid provider for {}: {}", chain, e)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// picks the chain default, the `model` override from the request can
+    /// either be a bare model name (keeps the chain's provider) or a full
+    /// `provider:model`
+    pub fn resolve(
+        &self,
+        chain: &str,
+        model_override: Option<&str>,
+    ) -> Result<ModelProvider> {
+        let base = self.per_chain.get(chain).unwrap_or(&self.default);
+        let provider = match model_override {
+            Some(model) if model.contains(':') => {
+                ModelProvider::parse(model)?
+            }
+            Some(model) => ModelProvider {
+                model: model.to_string(),
+                ..base.clone()
+            },
+            None => base.clone(),
+        };
+        Ok(provider)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let map: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| map.get(key).cloned()
+    }
+
+    #[test]
+    fn test_parse_model_provider() {
+        let provider =
+            ModelProvider::parse("anthropic:claude-3-7-sonnet-latest")
+                .unwrap();
+        assert_eq!(provider.provider, ProviderName::Anthropic);
+        assert_eq!(provider.model, "claude-3-7-sonnet-latest");
+        assert_eq!(provider.api_key_env, "ANTHROPIC_API_KEY");
+
+        let provider = ModelProvider::parse("local:llama").unwrap();
+        assert_eq!(provider.provider, ProviderName::Local);
+        assert_eq!(provider.api_key_env, "LOCAL_API_KEY");
+
+        assert!(ModelProvider::parse("claude-3-5-sonnet").is_err());
+        assert!(ModelProvider::parse("mistral:large").is_err());
+        // without streamed tool calls in rig they can't run the agents
+        assert!(ModelProvider::parse("openai:gpt-4o").is_err());
+        assert!(ModelProvider::parse("gemini:gemini-pro").is_err());
+    }
+
+    #[test]
+    fn test_per_chain_default_resolution() {
+        let config = ModelConfig::from_vars(vars(&[
+            ("MODEL_PROVIDER", "anthropic:default-model"),
+            ("MODEL_PROVIDER_SOLANA", "local:solana-model"),
+        ]))
+        .unwrap();
+
+        let solana = config.resolve("solana", None).unwrap();
+        assert_eq!(solana.provider, ProviderName::Local);
+        assert_eq!(solana.model, "solana-model");
+
+        let evm = config.resolve("evm", None).unwrap();
+        assert_eq!(evm.provider, ProviderName::Anthropic);
+        assert_eq!(evm.model, "default-model");
+
+        // bare model name keeps the chain's provider
+        let solana = config.resolve("solana", Some("other-model")).unwrap();
+        assert_eq!(solana.provider, ProviderName::Local);
+        assert_eq!(solana.model, "other-model");
+
+        let evm = config.resolve("evm", Some("local:evm-model")).unwrap();
+        assert_eq!(evm.provider, ProviderName::Local);
+        assert_eq!(evm.model, "evm-model");
+        assert!(config.resolve("evm", Some("openai:gpt-4o")).is_err());
+    }
+
+    #[test]
+    fn test_validate_fails_on_misconfigured_provider() {
+        let provider = ModelProvider {
+            api_key_env: "MODEL_TEST_MISSING_API_KEY".to_string(),
+            ..ModelProvider::default()
+        };
+        assert!(provider.validate().is_err());
+
+        std::env::set_var("MODEL_TEST_LOCAL_API_KEY", "key");
+        let provider = ModelProvider {
+            provider: ProviderName::Local,
+            api_base: None,
+            api_key_env: "MODEL_TEST_LOCAL_API_KEY".to_string(),
+            model: "local-model".to_string(),
+        };
+        assert!(provider.validate().is_err());
+    }
+
+    #[test]
+    fn test_agent_builder_receives_model() {
+        std::env::set_var("MODEL_TEST_ANTHROPIC_API_KEY", "key");
+        let config = ModelConfig {
+            default: ModelProvider {
+                api_key_env: "MODEL_TEST_ANTHROPIC_API_KEY".to_string(),
+                ..ModelProvider::default()
+            },
+            per_chain: HashMap::new(),
+        };
+        let provider = config
+            .resolve("solana", Some("claude-3-opus-latest"))
+            .unwrap();
+        let model = provider.completion_model().unwrap();
+        assert_eq!(model.model, "claude-3-opus-latest");
+    }
+}
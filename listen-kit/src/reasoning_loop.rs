@@ -1,31 +1,166 @@
-use anyhow::Result;
-use futures::StreamExt;
+use crate::audit::{audit_tool_call, AuditContext, AuditSink, NoopAuditSink};
+use crate::delegate::{
+    DelegateArgs, DELEGATE_TOOL_NAME, MAX_DELEGATION_DEPTH,
+};
+use crate::replay::{RecordingAgent, ReplayAgent, ReplayMismatch};
+use crate::tool_result::ToolResultLimits;
+use crate::usage::{
+    estimate_prompt_tokens, estimate_tokens, report_usage, NoopUsageReporter,
+    TokenBudget, UsageReporter,
+};
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use futures::{FutureExt, StreamExt};
 use rig::agent::Agent;
 use rig::completion::AssistantContent;
 use rig::completion::Message;
 use rig::message::{ToolResultContent, UserContent};
 use rig::providers::anthropic::completion::CompletionModel;
-use rig::streaming::{StreamingChat, StreamingChoice};
+use rig::streaming::{StreamingChat, StreamingChoice, StreamingResult};
 use rig::OneOrMany;
+use std::collections::HashMap;
 use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
 
+#[derive(Debug)]
 pub enum LoopResponse {
     Message(String),
-    ToolCall { name: String, result: String },
+    ToolCall {
+        name: String,
+        result: String,
+    },
+    /// event of a delegated child loop, `parent_id` is the id of the
+    /// delegate tool call that spawned it
+    Nested {
+        parent_id: String,
+        depth: usize,
+        response: Box<LoopResponse>,
+    },
+}
+
+/// What the reasoning loop needs from an agent, implemented for rig agents
+/// and by mocks in tests
+#[async_trait::async_trait]
+pub trait LoopAgent: Send + Sync {
+    async fn stream_chat(
+        &self,
+        prompt: &str,
+        chat_history: Vec<Message>,
+    ) -> Result<StreamingResult>;
+
+    async fn call_tool(&self, name: &str, args: String) -> Result<String>;
+}
+
+// concrete since rig's `StreamingChat` future isn't `Send` for generic models
+#[async_trait::async_trait]
+impl LoopAgent for Agent<CompletionModel> {
+    async fn stream_chat(
+        &self,
+        prompt: &str,
+        chat_history: Vec<Message>,
+    ) -> Result<StreamingResult> {
+        StreamingChat::stream_chat(self, prompt, chat_history)
+            .await
+            .map_err(|e| anyhow!(e))
+    }
+
+    async fn call_tool(&self, name: &str, args: String) -> Result<String> {
+        self.tools.call(name, args).await.map_err(|e| anyhow!(e))
+    }
 }
 
 pub struct ReasoningLoop {
-    agent: Arc<Agent<CompletionModel>>,
+    agent: Arc<dyn LoopAgent>,
     stdout: bool,
+    audit_sink: Arc<dyn AuditSink>,
+    audit_context: AuditContext,
+    delegates: Arc<HashMap<String, Arc<dyn LoopAgent>>>,
+    depth: usize,
+    cancel: CancellationToken,
+    /// remaining iterations, shared with delegated child loops
+    budget: Option<Arc<AtomicUsize>>,
+    /// prompt + completion tokens, shared with delegated child loops
+    token_budget: Option<Arc<TokenBudget>>,
+    usage_reporter: Arc<dyn UsageReporter>,
+    tool_result_limits: Arc<ToolResultLimits>,
+    /// prepended to every model call of this loop, never stored in the
+    /// chat history
+    ephemeral_context: Vec<String>,
+    max_parallel_tools: usize,
+}
+
+/// read-only tool calls of one model response running at once
+pub const DEFAULT_MAX_PARALLEL_TOOLS: usize = 4;
+
+/// tools that submit transactions, these never run concurrently with other
+/// calls so that e.g. a swap and a transfer can't race for the balance
+pub const TRANSACTION_TOOLS: &[&str] = &[
+    // solana
+    "swap",
+    "swap_exact_out",
+    "sell_all",
+    "create_nonce_account",
+    "close_nonce_account",
+    "create_limit_order",
+    "cancel_limit_order",
+    "create_dca",
+    "close_dca",
+    "transfer_sol",
+    "transfer_spl_token",
+    "burn_spl_token",
+    "transfer_cnft",
+    "stake_sol",
+    "unstake",
+    "wrap_sol",
+    "unwrap_sol",
+    "close_empty_token_accounts",
+    "kamino_deposit",
+    "kamino_borrow",
+    "deploy_pump_fun_token",
+    "buy_pump_fun_token",
+    "sell_pump_fun_token",
+    // evm
+    "trade",
+    "transfer_eth",
+    "transfer_erc20",
+    "approve_token_for_router_spend",
+    // cross chain
+    "approve_token",
+    "bridge_usdc_to_evm",
+    "bridge_usdc_from_evm",
+];
+
+/// delegated loops can trade too, so they count as transactional
+fn is_parallel_safe(tool: &str) -> bool {
+    tool != DELEGATE_TOOL_NAME && !TRANSACTION_TOOLS.contains(&tool)
+}
+
+struct PendingToolCall {
+    id: String,
+    name: String,
+    params: String,
 }
 
 impl ReasoningLoop {
-    pub fn new(agent: Arc<Agent<CompletionModel>>) -> Self {
+    pub fn new(agent: Arc<dyn LoopAgent>) -> Self {
         Self {
             agent,
             stdout: true,
+            audit_sink: Arc::new(NoopAuditSink),
+            audit_context: AuditContext::default(),
+            delegates: Arc::new(HashMap::new()),
+            depth: 0,
+            cancel: CancellationToken::new(),
+            budget: None,
+            token_budget: None,
+            usage_reporter: Arc::new(NoopUsageReporter),
+            tool_result_limits: Arc::new(ToolResultLimits::default()),
+            ephemeral_context: Vec::new(),
+            max_parallel_tools: DEFAULT_MAX_PARALLEL_TOOLS,
         }
     }
 
@@ -48,7 +183,21 @@ impl ReasoningLoop {
         let mut is_first_iteration = true;
 
         'outer: loop {
+            self.check_cancelled()?;
+            if let Some(budget) = &self.budget {
+                if budget
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                        n.checked_sub(1)
+                    })
+                    .is_err()
+                {
+                    return Err(anyhow!("iteration budget exhausted"));
+                }
+            }
+
             let mut current_response = String::new();
+            // every call of the response is run once the stream ends
+            let mut tool_calls = Vec::new();
 
             // Use the original prompt only for the first iteration
             let current_prompt = if is_first_iteration {
@@ -60,6 +209,15 @@ impl ReasoningLoop {
                 "Continue the conversation.".to_string()
             };
 
+            let current_prompt = self.with_context(current_prompt);
+
+            // stop before, not after, the call that would go over budget
+            let mut prompt_tokens =
+                estimate_prompt_tokens(&current_prompt, &current_messages);
+            if let Some(token_budget) = &self.token_budget {
+                token_budget.check(prompt_tokens)?;
+            }
+
             let mut stream = match agent
                 .stream_chat(&current_prompt, current_messages.clone())
                 .await
@@ -102,6 +260,16 @@ impl ReasoningLoop {
                         current_response.push_str(&text);
                     }
                     StreamingChoice::ToolCall(name, tool_id, params) => {
+                        // the prompt is only counted for the first record of
+                        // a model call
+                        self.record_usage(
+                            std::mem::take(&mut prompt_tokens),
+                            estimate_tokens(&current_response)
+                                + estimate_tokens(&name)
+                                + estimate_tokens(&params.to_string()),
+                        )
+                        .await;
+
                         // Add the assistant's response up to this point with the tool call
                         if !current_response.is_empty() {
                             current_messages.push(Message::Assistant {
@@ -125,56 +293,70 @@ impl ReasoningLoop {
                             ),
                         });
 
-                        // Call the tool and get result
-                        let result = self
-                            .agent
-                            .tools
-                            .call(&name, params.to_string())
-                            .await;
-
-                        if stdout {
-                            println!("Tool result: {:?}", result);
-                        }
+                        // no tools (swaps in particular) after cancellation
+                        self.check_cancelled()?;
 
-                        // Add the tool result as a user message
-                        current_messages.push(Message::User {
-                            content: OneOrMany::one(
-                                UserContent::tool_result(
-                                    tool_id,
-                                    OneOrMany::one(ToolResultContent::text(
-                                        match &result {
-                                            Ok(content) => {
-                                                content.to_string()
-                                            }
-                                            Err(err) => err.to_string(),
-                                        },
-                                    )),
-                                ),
-                            ),
+                        tool_calls.push(PendingToolCall {
+                            id: tool_id,
+                            name,
+                            params: params.to_string(),
                         });
+                    }
+                }
+            }
 
-                        if let Some(tx) = &tx {
-                            tx.send(LoopResponse::ToolCall {
-                                name,
-                                result: match &result {
-                                    Ok(content) => content.to_string(),
-                                    Err(err) => err.to_string(),
-                                },
-                            })
-                            .await
-                            .map_err(|e| {
-                                anyhow::anyhow!(
-                                    "failed to send tool call: {}",
-                                    e
-                                )
-                            })?;
-                        }
+            if !tool_calls.is_empty() {
+                if !current_response.is_empty() {
+                    self.record_usage(0, estimate_tokens(&current_response))
+                        .await;
+                    current_messages.push(Message::Assistant {
+                        content: OneOrMany::one(AssistantContent::text(
+                            std::mem::take(&mut current_response),
+                        )),
+                    });
+                }
+
+                let results = self.run_tools(&tool_calls, &tx).await?;
 
-                        continue 'outer;
+                for (call, result) in tool_calls.into_iter().zip(results) {
+                    if stdout {
+                        println!("Tool result: {:?}", result);
+                    }
+
+                    // Add the tool result as a user message, capped so
+                    // that large results don't blow up the context
+                    current_messages.push(Message::User {
+                        content: OneOrMany::one(UserContent::tool_result(
+                            call.id,
+                            OneOrMany::one(ToolResultContent::text(
+                                self.tool_result_limits
+                                    .apply(&call.name, &result),
+                            )),
+                        )),
+                    });
+
+                    // the UI gets the full result
+                    if let Some(tx) = &tx {
+                        tx.send(LoopResponse::ToolCall {
+                            name: call.name,
+                            result,
+                        })
+                        .await
+                        .map_err(|e| {
+                            anyhow::anyhow!("failed to send tool call: {}", e)
+                        })?;
                     }
                 }
+
+                continue 'outer;
             }
 
+            self.record_usage(
+                prompt_tokens,
+                estimate_tokens(&current_response),
+            )
+            .await;
+
             // Add any remaining response to messages
             if !current_response.is_empty() {
                 current_messages.push(Message::Assistant {
@@ -191,8 +373,764 @@ impl ReasoningLoop {
         Ok(current_messages)
     }
 
+    /// runs the tool calls of one model response and returns their results
+    /// in call order; consecutive read-only calls run concurrently, calls
+    /// that may move funds run one at a time in between
+    async fn run_tools(
+        &self,
+        calls: &[PendingToolCall],
+        tx: &Option<Sender<LoopResponse>>,
+    ) -> Result<Vec<String>> {
+        let mut results = Vec::with_capacity(calls.len());
+        let mut start = 0;
+        while start < calls.len() {
+            if !is_parallel_safe(&calls[start].name) {
+                results.push(self.run_tool(&calls[start], tx).await?);
+                start += 1;
+                continue;
+            }
+            let end = calls[start..]
+                .iter()
+                .position(|call| !is_parallel_safe(&call.name))
+                .map_or(calls.len(), |offset| start + offset);
+            let batch = futures::stream::iter(&calls[start..end])
+                .map(|call| self.run_tool(call, tx))
+                .buffered(self.max_parallel_tools.max(1))
+                .collect::<Vec<_>>()
+                .await;
+            for result in batch {
+                results.push(result?);
+            }
+            start = end;
+        }
+        Ok(results)
+    }
+
+    /// the result or error text of a tool call for the model, errors only
+    /// for what has to end the loop (cancellation, a diverging replay)
+    async fn run_tool(
+        &self,
+        call: &PendingToolCall,
+        tx: &Option<Sender<LoopResponse>>,
+    ) -> Result<String> {
+        self.check_cancelled()?;
+        let agent = &self.agent;
+        let result = audit_tool_call(
+            self.audit_sink.as_ref(),
+            &self.audit_context,
+            &call.name,
+            &call.params,
+            move || async move {
+                if call.name == DELEGATE_TOOL_NAME {
+                    self.delegate(&call.id, &call.params, tx.clone()).await
+                } else {
+                    agent.call_tool(&call.name, call.params.clone()).await
+                }
+            },
+        )
+        .await;
+
+        let result = match result {
+            Err(e) if e.is::<ReplayMismatch>() => return Err(e),
+            Ok(content) => content,
+            Err(err) => err.to_string(),
+        };
+        self.check_cancelled()?;
+        Ok(result)
+    }
+
+    /// caps how many read-only tool calls of one response run at once,
+    /// `DEFAULT_MAX_PARALLEL_TOOLS` by default, 1 runs them in sequence
+    pub fn with_max_parallel_tools(
+        mut self,
+        max_parallel_tools: usize,
+    ) -> Self {
+        self.max_parallel_tools = max_parallel_tools;
+        self
+    }
+
+    /// context for the model calls of this turn only, e.g. the current
+    /// portfolio or prices
+    pub fn with_ephemeral_context(mut self, context: Vec<String>) -> Self {
+        self.ephemeral_context = context;
+        self
+    }
+
+    fn with_context(&self, prompt: String) -> String {
+        if self.ephemeral_context.is_empty() {
+            return prompt;
+        }
+        format!(
+            "<context>\n{}\n</context>\n\n{}",
+            self.ephemeral_context.join("\n"),
+            prompt
+        )
+    }
+
+    /// appends every model chunk and tool result to the trace at `path`
+    pub fn with_recording(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        self.agent = Arc::new(RecordingAgent::new(self.agent, path)?);
+        Ok(self)
+    }
+
+    /// plays back a trace written by `with_recording` without calling the
+    /// provider or executing any tools
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(Arc::new(ReplayAgent::from_file(path)?)))
+    }
+
     pub fn with_stdout(mut self, enabled: bool) -> Self {
         self.stdout = enabled;
         self
     }
+
+    /// makes `agent` available to the `delegate` tool under `name`, the
+    /// parent agent has to be built with the `Delegate` tool
+    pub fn with_delegate(
+        mut self,
+        name: &str,
+        agent: Arc<dyn LoopAgent>,
+    ) -> Self {
+        Arc::make_mut(&mut self.delegates).insert(name.to_string(), agent);
+        self
+    }
+
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// caps the number of model calls, including those of delegated loops
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.budget = Some(Arc::new(AtomicUsize::new(max_iterations)));
+        self
+    }
+
+    pub fn remaining_iterations(&self) -> Option<usize> {
+        self.budget.as_ref().map(|b| b.load(Ordering::SeqCst))
+    }
+
+    /// caps the estimated prompt + completion tokens of all model calls,
+    /// including those of delegated loops
+    pub fn with_max_tokens_total(mut self, max_tokens_total: usize) -> Self {
+        self.token_budget =
+            Some(Arc::new(TokenBudget::new(max_tokens_total)));
+        self
+    }
+
+    pub fn tokens_used(&self) -> Option<usize> {
+        self.token_budget.as_ref().map(|b| b.used())
+    }
+
+    /// caps the size of the tool results fed back to the model, defaults
+    /// to `ToolResultLimits::default()`
+    pub fn with_tool_result_limits(
+        mut self,
+        limits: ToolResultLimits,
+    ) -> Self {
+        self.tool_result_limits = Arc::new(limits);
+        self
+    }
+
+    pub fn with_usage_reporter(
+        mut self,
+        reporter: Arc<dyn UsageReporter>,
+    ) -> Self {
+        self.usage_reporter = reporter;
+        self
+    }
+
+    async fn record_usage(
+        &self,
+        prompt_tokens: usize,
+        completion_tokens: usize,
+    ) {
+        if let Some(token_budget) = &self.token_budget {
+            token_budget.record(prompt_tokens + completion_tokens);
+        }
+        report_usage(
+            self.usage_reporter.as_ref(),
+            &self.audit_context,
+            prompt_tokens,
+            completion_tokens,
+        )
+        .await;
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            return Err(anyhow!("reasoning loop cancelled"));
+        }
+        Ok(())
+    }
+
+    /// runs a child loop to completion and returns its final answer; boxed
+    /// since it recurses through `stream`
+    fn delegate<'a>(
+        &'a self,
+        tool_id: &'a str,
+        params: &'a str,
+        tx: Option<Sender<LoopResponse>>,
+    ) -> BoxFuture<'a, Result<String>> {
+        async move {
+            if self.depth >= MAX_DELEGATION_DEPTH {
+                return Err(anyhow!(
+                    "maximum delegation depth of {} reached",
+                    MAX_DELEGATION_DEPTH
+                ));
+            }
+            let args: DelegateArgs = serde_json::from_str(params)?;
+            let agent = self
+                .delegates
+                .get(&args.agent)
+                .ok_or_else(|| anyhow!("unknown agent: {}", args.agent))?
+                .clone();
+
+            let child = ReasoningLoop {
+                agent,
+                stdout: self.stdout,
+                audit_sink: self.audit_sink.clone(),
+                audit_context: self.audit_context.clone(),
+                delegates: self.delegates.clone(),
+                depth: self.depth + 1,
+                cancel: self.cancel.child_token(),
+                budget: self.budget.clone(),
+                token_budget: self.token_budget.clone(),
+                usage_reporter: self.usage_reporter.clone(),
+                tool_result_limits: self.tool_result_limits.clone(),
+                // the task given by the parent carries what the child needs
+                ephemeral_context: Vec::new(),
+                max_parallel_tools: self.max_parallel_tools,
+            };
+
+            // forward the child's events to the parent channel, events of
+            // deeper loops are already wrapped
+            let (child_tx, forward) = match tx {
+                Some(tx) => {
+                    let (child_tx, mut child_rx) =
+                        tokio::sync::mpsc::channel(1024);
+                    let parent_id = tool_id.to_string();
+                    let depth = child.depth;
+                    let forward = tokio::spawn(async move {
+                        while let Some(response) = child_rx.recv().await {
+                            let response = match response {
+                                nested @ LoopResponse::Nested { .. } => {
+                                    nested
+                                }
+                                response => LoopResponse::Nested {
+                                    parent_id: parent_id.clone(),
+                                    depth,
+                                    response: Box::new(response),
+                                },
+                            };
+                            if tx.send(response).await.is_err() {
+                                break;
+                            }
+                        }
+                    });
+                    (Some(child_tx), Some(forward))
+                }
+                None => (None, None),
+            };
+
+            let result = child.stream(args.task, vec![], child_tx).await;
+            if let Some(forward) = forward {
+                let _ = forward.await;
+            }
+
+            Ok(final_answer(&result?))
+        }
+        .boxed()
+    }
+
+    pub fn with_audit(
+        mut self,
+        sink: Arc<dyn AuditSink>,
+        context: AuditContext,
+    ) -> Self {
+        self.audit_sink = sink;
+        self.audit_context = context;
+        self
+    }
+}
+
+/// text of the last assistant message
+fn final_answer(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .rev()
+        .find_map(|message| match message {
+            Message::Assistant { content } => Some(
+                content
+                    .iter()
+                    .filter_map(|c| match c {
+                        AssistantContent::Text(text) => {
+                            Some(text.text.clone())
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join(""),
+            ),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    /// replays scripted responses, one per `stream_chat`, and answers
+    /// "done" once the script runs out
+    #[derive(Default)]
+    struct MockAgent {
+        script: Mutex<VecDeque<Vec<StreamingChoice>>>,
+        stream_calls: AtomicUsize,
+        tool_calls: AtomicUsize,
+        cancel_on_stream: Option<CancellationToken>,
+        tool_result: Option<String>,
+        prompts: Mutex<Vec<String>>,
+        tool_delay: Option<std::time::Duration>,
+    }
+
+    impl MockAgent {
+        fn new(script: Vec<Vec<StreamingChoice>>) -> Self {
+            Self {
+                script: Mutex::new(script.into()),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl LoopAgent for MockAgent {
+        async fn stream_chat(
+            &self,
+            prompt: &str,
+            _chat_history: Vec<Message>,
+        ) -> Result<StreamingResult> {
+            self.stream_calls.fetch_add(1, Ordering::SeqCst);
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            if let Some(cancel) = &self.cancel_on_stream {
+                cancel.cancel();
+            }
+            let choices =
+                self.script.lock().unwrap().pop_front().unwrap_or_else(
+                    || vec![StreamingChoice::Message("done".to_string())],
+                );
+            Ok(Box::pin(futures::stream::iter(choices.into_iter().map(Ok))))
+        }
+
+        async fn call_tool(
+            &self,
+            name: &str,
+            _args: String,
+        ) -> Result<String> {
+            self.tool_calls.fetch_add(1, Ordering::SeqCst);
+            if let Some(delay) = self.tool_delay {
+                tokio::time::sleep(delay).await;
+            }
+            Ok(self
+                .tool_result
+                .clone()
+                .unwrap_or_else(|| format!("{} result", name)))
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeUsageReporter {
+        records: Mutex<Vec<crate::usage::UsageRecord>>,
+    }
+
+    #[async_trait::async_trait]
+    impl UsageReporter for FakeUsageReporter {
+        async fn report(
+            &self,
+            record: crate::usage::UsageRecord,
+        ) -> Result<()> {
+            self.records.lock().unwrap().push(record);
+            Ok(())
+        }
+    }
+
+    fn delegate_call(id: &str) -> Vec<StreamingChoice> {
+        vec![StreamingChoice::ToolCall(
+            DELEGATE_TOOL_NAME.to_string(),
+            id.to_string(),
+            json!({ "agent": "research", "task": "research the token" }),
+        )]
+    }
+
+    fn drain(
+        rx: &mut tokio::sync::mpsc::Receiver<LoopResponse>,
+    ) -> Vec<LoopResponse> {
+        let mut responses = Vec::new();
+        while let Ok(response) = rx.try_recv() {
+            responses.push(response);
+        }
+        responses
+    }
+
+    #[tokio::test]
+    async fn test_delegate_shares_budget() {
+        // parent delegates, child answers, parent answers: 3 iterations
+        let parent = Arc::new(MockAgent::new(vec![delegate_call("call_1")]));
+        let child = Arc::new(MockAgent::default());
+        let reasoning_loop = ReasoningLoop::new(parent)
+            .with_stdout(false)
+            .with_delegate("research", child)
+            .with_max_iterations(3);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1024);
+        let messages = reasoning_loop
+            .stream("trade it if safe".to_string(), vec![], Some(tx))
+            .await
+            .unwrap();
+        assert_eq!(reasoning_loop.remaining_iterations(), Some(0));
+        assert_eq!(final_answer(&messages), "done");
+
+        let responses = drain(&mut rx);
+        assert!(responses.iter().any(|r| matches!(
+            r,
+            LoopResponse::Nested { parent_id, depth: 1, response }
+                if parent_id == "call_1"
+                    && matches!(**response, LoopResponse::Message(ref m) if m == "done")
+        )));
+        assert!(responses.iter().any(|r| matches!(
+            r,
+            LoopResponse::ToolCall { name, result }
+                if name == DELEGATE_TOOL_NAME && result == "done"
+        )));
+
+        // the child's iteration leaves nothing for the parent's answer
+        let parent = Arc::new(MockAgent::new(vec![delegate_call("call_1")]));
+        let reasoning_loop = ReasoningLoop::new(parent)
+            .with_stdout(false)
+            .with_delegate("research", Arc::new(MockAgent::default()))
+            .with_max_iterations(2);
+        let (tx, _rx) = tokio::sync::mpsc::channel(1024);
+        let result = reasoning_loop
+            .stream("trade it if safe".to_string(), vec![], Some(tx))
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delegate_propagates_cancellation() {
+        let cancel = CancellationToken::new();
+        let parent = Arc::new(MockAgent::new(vec![delegate_call("call_1")]));
+        // cancels the parent while the child is streaming, right before
+        // the child would call a tool
+        let child = Arc::new(MockAgent {
+            script: Mutex::new(
+                vec![vec![StreamingChoice::ToolCall(
+                    "swap".to_string(),
+                    "call_2".to_string(),
+                    json!({}),
+                )]]
+                .into(),
+            ),
+            cancel_on_stream: Some(cancel.clone()),
+            ..Default::default()
+        });
+        let reasoning_loop = ReasoningLoop::new(parent.clone())
+            .with_stdout(false)
+            .with_delegate("research", child.clone())
+            .with_cancellation(cancel);
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(1024);
+        let result = reasoning_loop
+            .stream("trade it if safe".to_string(), vec![], Some(tx))
+            .await;
+        assert!(result.is_err());
+        assert_eq!(child.tool_calls.load(Ordering::SeqCst), 0);
+        assert_eq!(parent.tool_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_delegate_depth_cap() {
+        // the research agent keeps delegating to itself
+        let parent = Arc::new(MockAgent::new(vec![delegate_call("call_1")]));
+        let child = Arc::new(MockAgent::new(vec![
+            delegate_call("call_2"),
+            delegate_call("call_3"),
+        ]));
+        let reasoning_loop = ReasoningLoop::new(parent)
+            .with_stdout(false)
+            .with_delegate("research", child);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1024);
+        reasoning_loop
+            .stream("trade it if safe".to_string(), vec![], Some(tx))
+            .await
+            .unwrap();
+
+        let responses = drain(&mut rx);
+        assert!(responses.iter().any(|r| matches!(
+            r,
+            LoopResponse::Nested { parent_id, depth: 2, response }
+                if parent_id == "call_2"
+                    && matches!(
+                        **response,
+                        LoopResponse::ToolCall { ref result, .. }
+                            if result.contains("maximum delegation depth")
+                    )
+        )));
+        assert!(!responses
+            .iter()
+            .any(|r| matches!(r, LoopResponse::Nested { depth: 3, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_stops_before_over_budget_call() {
+        // the first call fits, the tool result grows the history past the
+        // budget for the second one
+        let agent =
+            Arc::new(MockAgent::new(vec![vec![StreamingChoice::ToolCall(
+                "swap".to_string(),
+                "call_1".to_string(),
+                json!({}),
+            )]]));
+        let reporter = Arc::new(FakeUsageReporter::default());
+        let reasoning_loop = ReasoningLoop::new(agent.clone())
+            .with_stdout(false)
+            .with_usage_reporter(reporter.clone())
+            .with_max_tokens_total(10);
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(1024);
+        let err = reasoning_loop
+            .stream("trade it".to_string(), vec![], Some(tx))
+            .await
+            .unwrap_err();
+
+        let exceeded = err
+            .downcast_ref::<crate::usage::TokenBudgetExceeded>()
+            .unwrap();
+        assert_eq!(exceeded.budget, 10);
+        assert_eq!(exceeded.used, 4);
+        assert!(exceeded.used + exceeded.next > exceeded.budget);
+        assert!(err.to_string().contains("used 4 of 10 tokens"));
+
+        assert_eq!(agent.stream_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(agent.tool_calls.load(Ordering::SeqCst), 1);
+        let records = reporter.records.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].prompt_tokens, 2);
+        assert_eq!(records[0].completion_tokens, 2);
+        assert_eq!(reasoning_loop.tokens_used(), Some(4));
+    }
+
+    fn tool_call(name: &str) -> Vec<StreamingChoice> {
+        vec![StreamingChoice::ToolCall(
+            name.to_string(),
+            "call_1".to_string(),
+            json!({}),
+        )]
+    }
+
+    /// the tool results as the model sees them
+    fn tool_results(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .flat_map(|message| match message {
+                Message::User { content } => {
+                    content.iter().cloned().collect()
+                }
+                _ => vec![],
+            })
+            .filter_map(|content| match content {
+                UserContent::ToolResult(result) => {
+                    match result.content.first() {
+                        ToolResultContent::Text(text) => Some(text.text),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_tool_result_truncated_for_model_only() {
+        let full = "x".repeat(1000);
+        let agent = Arc::new(MockAgent {
+            script: Mutex::new(vec![tool_call("search")].into()),
+            tool_result: Some(full.clone()),
+            ..Default::default()
+        });
+        let reasoning_loop = ReasoningLoop::new(agent)
+            .with_stdout(false)
+            .with_tool_result_limits(
+                ToolResultLimits::new(100_000).with_tool_limit("search", 64),
+            );
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1024);
+        let messages = reasoning_loop
+            .stream("search".to_string(), vec![], Some(tx))
+            .await
+            .unwrap();
+
+        let results = tool_results(&messages);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].starts_with(&"x".repeat(64)));
+        assert!(results[0].ends_with("[truncated: showing 64 of 1000 bytes]"));
+
+        // the SSE event carries the full payload
+        assert!(drain(&mut rx).iter().any(|r| matches!(
+            r,
+            LoopResponse::ToolCall { name, result }
+                if name == "search" && *result == full
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_portfolio_result_summarized() {
+        let portfolio = (0..300)
+            .map(|i| {
+                json!({
+                    "address": format!("dust{}", i),
+                    "price": 0.0001,
+                    "amount": 1.0,
+                })
+            })
+            .chain(std::iter::once(
+                json!({ "address": "sol", "price": 150.0, "amount": 2.0 }),
+            ))
+            .collect::<Vec<_>>();
+        let full = serde_json::to_string(&portfolio).unwrap();
+        let agent = Arc::new(MockAgent {
+            script: Mutex::new(vec![tool_call("get_portfolio")].into()),
+            tool_result: Some(full.clone()),
+            ..Default::default()
+        });
+        let reasoning_loop = ReasoningLoop::new(agent)
+            .with_stdout(false)
+            .with_tool_result_limits(
+                ToolResultLimits::default()
+                    .with_tool_limit("get_portfolio", 2048),
+            );
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1024);
+        let messages = reasoning_loop
+            .stream("portfolio".to_string(), vec![], Some(tx))
+            .await
+            .unwrap();
+
+        let results = tool_results(&messages);
+        assert!(results[0].len() <= 2048);
+        let summary: serde_json::Value =
+            serde_json::from_str(&results[0]).unwrap();
+        assert_eq!(summary["items"][0]["address"], "sol");
+        assert!(summary["omitted"].as_u64().unwrap() > 0);
+
+        assert!(drain(&mut rx).iter().any(|r| matches!(
+            r,
+            LoopResponse::ToolCall { result, .. } if *result == full
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_ephemeral_context_not_stored() {
+        let agent = Arc::new(MockAgent::new(vec![tool_call("swap")]));
+        let reasoning_loop = ReasoningLoop::new(agent.clone())
+            .with_stdout(false)
+            .with_ephemeral_context(vec!["SOL price: $150".to_string()]);
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(1024);
+        let messages = reasoning_loop
+            .stream("buy some".to_string(), vec![], Some(tx))
+            .await
+            .unwrap();
+
+        // every model call of the turn gets the context
+        let prompts = agent.prompts.lock().unwrap();
+        assert_eq!(prompts.len(), 2);
+        assert!(prompts.iter().all(|p| p.contains("SOL price: $150")));
+        assert!(prompts[0].ends_with("buy some"));
+
+        let history = serde_json::to_string(&messages).unwrap();
+        assert!(history.contains("buy some"));
+        assert!(!history.contains("SOL price"));
+    }
+
+    fn tool_calls(names: &[&str]) -> Vec<StreamingChoice> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                StreamingChoice::ToolCall(
+                    name.to_string(),
+                    format!("call_{}", i),
+                    json!({}),
+                )
+            })
+            .collect()
+    }
+
+    fn slow_agent(names: &[&str]) -> Arc<MockAgent> {
+        Arc::new(MockAgent {
+            script: Mutex::new(vec![tool_calls(names)].into()),
+            tool_delay: Some(std::time::Duration::from_millis(200)),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_read_tools_run_concurrently() {
+        let names = ["fetch_price_a", "fetch_price_b", "fetch_price_c"];
+        let agent = slow_agent(&names);
+        let reasoning_loop =
+            ReasoningLoop::new(agent.clone()).with_stdout(false);
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(1024);
+        let started = std::time::Instant::now();
+        let messages = reasoning_loop
+            .stream("prices".to_string(), vec![], Some(tx))
+            .await
+            .unwrap();
+
+        assert!(started.elapsed() < std::time::Duration::from_millis(600));
+        assert_eq!(agent.tool_calls.load(Ordering::SeqCst), 3);
+        // results come back in call order
+        assert_eq!(
+            tool_results(&messages),
+            names
+                .iter()
+                .map(|name| format!("{} result", name))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(final_answer(&messages), "done");
+    }
+
+    #[tokio::test]
+    async fn test_transaction_tools_run_sequentially() {
+        let agent = slow_agent(&["swap", "fetch_price", "transfer_sol"]);
+        let reasoning_loop =
+            ReasoningLoop::new(agent.clone()).with_stdout(false);
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(1024);
+        let started = std::time::Instant::now();
+        reasoning_loop
+            .stream("trade".to_string(), vec![], Some(tx))
+            .await
+            .unwrap();
+        assert!(started.elapsed() >= std::time::Duration::from_millis(600));
+
+        // a cap of one runs read tools in sequence too
+        let agent = slow_agent(&["fetch_price_a", "fetch_price_b"]);
+        let reasoning_loop = ReasoningLoop::new(agent)
+            .with_stdout(false)
+            .with_max_parallel_tools(1);
+        let (tx, _rx) = tokio::sync::mpsc::channel(1024);
+        let started = std::time::Instant::now();
+        reasoning_loop
+            .stream("prices".to_string(), vec![], Some(tx))
+            .await
+            .unwrap();
+        assert!(started.elapsed() >= std::time::Duration::from_millis(400));
+    }
 }
@@ -0,0 +1,485 @@
+//! Record/replay of reasoning loop sessions. `RecordingAgent` appends every
+//! model chunk and tool result to a JSON lines trace, `ReplayAgent` plays a
+//! trace back instead of calling the provider or executing tools, which
+//! reproduces the exact `LoopResponse` stream of the recorded session.
+//!
+//! Delegated child loops run their own agents, those have to be wrapped
+//! (and recorded to a separate trace) on their own
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use rig::completion::{CompletionError, Message};
+use rig::streaming::{StreamingChoice, StreamingResult};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::delegate::DELEGATE_TOOL_NAME;
+use crate::reasoning_loop::LoopAgent;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TraceChunk {
+    Message {
+        text: String,
+    },
+    ToolCall {
+        name: String,
+        call_id: String,
+        params: serde_json::Value,
+    },
+    Error {
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TraceEvent {
+    /// start of a model call, turns are counted from 0
+    ModelCall {
+        turn: usize,
+    },
+    ModelChunk {
+        turn: usize,
+        chunk: TraceChunk,
+    },
+    ToolResult {
+        turn: usize,
+        call_id: String,
+        name: String,
+        args: String,
+        result: Result<String, String>,
+    },
+}
+
+/// the live tool call differs from the recorded one, the loop aborts on
+/// this instead of feeding it back to the model
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "replay mismatch at turn {turn} (call {call_id}):\n- {expected}\n+ {actual}"
+)]
+pub struct ReplayMismatch {
+    pub turn: usize,
+    pub call_id: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl From<&StreamingChoice> for TraceChunk {
+    fn from(choice: &StreamingChoice) -> Self {
+        match choice {
+            StreamingChoice::Message(text) => {
+                TraceChunk::Message { text: text.clone() }
+            }
+            StreamingChoice::ToolCall(name, call_id, params) => {
+                TraceChunk::ToolCall {
+                    name: name.clone(),
+                    call_id: call_id.clone(),
+                    params: params.clone(),
+                }
+            }
+        }
+    }
+}
+
+impl From<TraceChunk> for Result<StreamingChoice, CompletionError> {
+    fn from(chunk: TraceChunk) -> Self {
+        match chunk {
+            TraceChunk::Message { text } => {
+                Ok(StreamingChoice::Message(text))
+            }
+            TraceChunk::ToolCall {
+                name,
+                call_id,
+                params,
+            } => Ok(StreamingChoice::ToolCall(name, call_id, params)),
+            // recorded through `Display`, which prefixes the variant
+            TraceChunk::Error { error } => {
+                Err(CompletionError::ProviderError(
+                    error
+                        .strip_prefix("ProviderError: ")
+                        .unwrap_or(&error)
+                        .to_string(),
+                ))
+            }
+        }
+    }
+}
+
+pub fn read_trace(path: impl AsRef<Path>) -> Result<Vec<TraceEvent>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+struct TraceWriter {
+    file: Mutex<File>,
+}
+
+impl TraceWriter {
+    fn append(&self, event: &TraceEvent) {
+        let line = match serde_json::to_string(event) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::error!("failed to serialize trace event: {}", e);
+                return;
+            }
+        };
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{}", line) {
+            tracing::error!("failed to write trace event: {}", e);
+        }
+    }
+}
+
+/// Passes through to `inner`, recording as it goes
+pub struct RecordingAgent {
+    inner: Arc<dyn LoopAgent>,
+    writer: Arc<TraceWriter>,
+    turn: AtomicUsize,
+    /// ids of the tool calls streamed by the model and not yet executed
+    pending_calls: Arc<Mutex<VecDeque<(usize, String)>>>,
+}
+
+impl RecordingAgent {
+    pub fn new(
+        inner: Arc<dyn LoopAgent>,
+        path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            inner,
+            writer: Arc::new(TraceWriter {
+                file: Mutex::new(file),
+            }),
+            turn: AtomicUsize::new(0),
+            pending_calls: Arc::new(Mutex::new(VecDeque::new())),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl LoopAgent for RecordingAgent {
+    async fn stream_chat(
+        &self,
+        prompt: &str,
+        chat_history: Vec<Message>,
+    ) -> Result<StreamingResult> {
+        let turn = self.turn.fetch_add(1, Ordering::SeqCst);
+        self.writer.append(&TraceEvent::ModelCall { turn });
+        let stream = self.inner.stream_chat(prompt, chat_history).await?;
+
+        // chunks are recorded as they are polled, the loop stops reading
+        // the stream at the first tool call
+        let writer = self.writer.clone();
+        let pending_calls = self.pending_calls.clone();
+        Ok(Box::pin(stream.inspect(move |chunk| {
+            let chunk = match chunk {
+                Ok(choice) => TraceChunk::from(choice),
+                Err(e) => TraceChunk::Error {
+                    error: e.to_string(),
+                },
+            };
+            // the loop runs delegations itself, they never reach call_tool
+            if let TraceChunk::ToolCall { call_id, name, .. } = &chunk {
+                if name == DELEGATE_TOOL_NAME {
+                    writer.append(&TraceEvent::ModelChunk { turn, chunk });
+                    return;
+                }
+                pending_calls
+                    .lock()
+                    .unwrap()
+                    .push_back((turn, call_id.clone()));
+            }
+            writer.append(&TraceEvent::ModelChunk { turn, chunk });
+        })))
+    }
+
+    async fn call_tool(&self, name: &str, args: String) -> Result<String> {
+        let (turn, call_id) = self
+            .pending_calls
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| {
+                (self.turn.load(Ordering::SeqCst), String::new())
+            });
+        let result = self.inner.call_tool(name, args.clone()).await;
+        self.writer.append(&TraceEvent::ToolResult {
+            turn,
+            call_id,
+            name: name.to_string(),
+            args,
+            result: result
+                .as_ref()
+                .map(String::clone)
+                .map_err(|e| e.to_string()),
+        });
+        result
+    }
+}
+
+struct RecordedToolResult {
+    turn: usize,
+    call_id: String,
+    name: String,
+    args: String,
+    result: Result<String, String>,
+}
+
+/// Plays back a trace, neither the provider nor the tools are called
+pub struct ReplayAgent {
+    turns: Mutex<VecDeque<Vec<TraceChunk>>>,
+    tool_results: Mutex<VecDeque<RecordedToolResult>>,
+}
+
+impl ReplayAgent {
+    pub fn new(events: Vec<TraceEvent>) -> Self {
+        let mut turns = VecDeque::new();
+        let mut tool_results = VecDeque::new();
+        for event in events {
+            match event {
+                TraceEvent::ModelCall { .. } => turns.push_back(Vec::new()),
+                TraceEvent::ModelChunk { chunk, .. } => {
+                    if let Some(turn) = turns.back_mut() {
+                        turn.push(chunk);
+                    }
+                }
+                TraceEvent::ToolResult {
+                    turn,
+                    call_id,
+                    name,
+                    args,
+                    result,
+                } => tool_results.push_back(RecordedToolResult {
+                    turn,
+                    call_id,
+                    name,
+                    args,
+                    result,
+                }),
+            }
+        }
+        Self {
+            turns: Mutex::new(turns),
+            tool_results: Mutex::new(tool_results),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(read_trace(path)?))
+    }
+}
+
+#[async_trait::async_trait]
+impl LoopAgent for ReplayAgent {
+    async fn stream_chat(
+        &self,
+        _prompt: &str,
+        _chat_history: Vec<Message>,
+    ) -> Result<StreamingResult> {
+        let chunks =
+            self.turns.lock().unwrap().pop_front().ok_or_else(|| {
+                anyhow!("replay trace has no more model calls")
+            })?;
+        Ok(Box::pin(futures::stream::iter(
+            chunks.into_iter().map(Into::into),
+        )))
+    }
+
+    async fn call_tool(&self, name: &str, args: String) -> Result<String> {
+        // concurrent calls are recorded in completion order, so any result
+        // of the same turn with matching name and args is taken first
+        let recorded = {
+            let mut tool_results = self.tool_results.lock().unwrap();
+            let turn = tool_results.front().map(|recorded| recorded.turn);
+            let position = tool_results
+                .iter()
+                .take_while(|recorded| Some(recorded.turn) == turn)
+                .position(|recorded| {
+                    recorded.name == name && recorded.args == args
+                })
+                .unwrap_or(0);
+            tool_results.remove(position).ok_or_else(|| {
+                anyhow!("replay trace has no more tool results ({})", name)
+            })?
+        };
+        if recorded.name != name || recorded.args != args {
+            return Err(ReplayMismatch {
+                turn: recorded.turn,
+                call_id: recorded.call_id,
+                expected: format!("{} {}", recorded.name, recorded.args),
+                actual: format!("{} {}", name, args),
+            }
+            .into());
+        }
+        recorded.result.map_err(|e| anyhow!(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reasoning_loop::{LoopResponse, ReasoningLoop};
+    use serde_json::json;
+
+    /// one scripted response per model call, tools echo their args
+    struct ScriptedAgent {
+        script: Mutex<VecDeque<Vec<StreamingChoice>>>,
+        tool_calls: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl LoopAgent for ScriptedAgent {
+        async fn stream_chat(
+            &self,
+            _prompt: &str,
+            _chat_history: Vec<Message>,
+        ) -> Result<StreamingResult> {
+            let choices = self.script.lock().unwrap().pop_front().unwrap();
+            Ok(Box::pin(futures::stream::iter(choices.into_iter().map(Ok))))
+        }
+
+        async fn call_tool(
+            &self,
+            name: &str,
+            args: String,
+        ) -> Result<String> {
+            self.tool_calls.fetch_add(1, Ordering::SeqCst);
+            if name == "swap" {
+                return Err(anyhow!("slippage exceeded"));
+            }
+            Ok(format!("{} {}", name, args))
+        }
+    }
+
+    fn session() -> ScriptedAgent {
+        ScriptedAgent {
+            script: Mutex::new(
+                vec![
+                    vec![
+                        StreamingChoice::Message("Checking ".to_string()),
+                        StreamingChoice::Message("the price".to_string()),
+                        StreamingChoice::ToolCall(
+                            "fetch_token_price".to_string(),
+                            "call_1".to_string(),
+                            json!({ "mint": "So11111111111111111111111111111111111111112" }),
+                        ),
+                    ],
+                    vec![StreamingChoice::ToolCall(
+                        "swap".to_string(),
+                        "call_2".to_string(),
+                        json!({ "amount": "1000" }),
+                    )],
+                    vec![StreamingChoice::Message(
+                        "The swap failed, not selling.".to_string(),
+                    )],
+                ]
+                .into(),
+            ),
+            tool_calls: AtomicUsize::new(0),
+        }
+    }
+
+    async fn run(
+        reasoning_loop: ReasoningLoop,
+    ) -> Result<(Vec<String>, Vec<Message>)> {
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<LoopResponse>(1024);
+        let messages = reasoning_loop
+            .with_stdout(false)
+            .stream("sell if down".to_string(), vec![], Some(tx))
+            .await?;
+        let mut responses = Vec::new();
+        while let Ok(response) = rx.try_recv() {
+            responses.push(format!("{:?}", response));
+        }
+        Ok((responses, messages))
+    }
+
+    fn trace_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "listen-trace-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay_session() {
+        let path = trace_path("session");
+        let agent = Arc::new(session());
+        let (recorded, recorded_messages) =
+            run(ReasoningLoop::new(agent.clone())
+                .with_recording(&path)
+                .unwrap())
+            .await
+            .unwrap();
+        assert_eq!(agent.tool_calls.load(Ordering::SeqCst), 2);
+
+        let trace = read_trace(&path).unwrap();
+        assert!(trace.contains(&TraceEvent::ToolResult {
+            turn: 1,
+            call_id: "call_2".to_string(),
+            name: "swap".to_string(),
+            args: json!({ "amount": "1000" }).to_string(),
+            result: Err("slippage exceeded".to_string()),
+        }));
+
+        let (replayed, replayed_messages) =
+            run(ReasoningLoop::replay(&path).unwrap()).await.unwrap();
+        assert_eq!(replayed, recorded);
+        assert_eq!(replayed_messages, recorded_messages);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_mismatch_fails_loudly() {
+        let path = trace_path("mismatch");
+        run(ReasoningLoop::new(Arc::new(session()))
+            .with_recording(&path)
+            .unwrap())
+        .await
+        .unwrap();
+
+        // the recorded tool result no longer matches the requested call
+        let trace = read_trace(&path)
+            .unwrap()
+            .into_iter()
+            .map(|event| match event {
+                TraceEvent::ToolResult {
+                    turn,
+                    call_id,
+                    name,
+                    result,
+                    ..
+                } if name == "swap" => TraceEvent::ToolResult {
+                    turn,
+                    call_id,
+                    name,
+                    args: json!({ "amount": "5" }).to_string(),
+                    result,
+                },
+                event => event,
+            })
+            .collect();
+
+        let err = run(ReasoningLoop::new(Arc::new(ReplayAgent::new(trace))))
+            .await
+            .unwrap_err();
+        let mismatch = err.downcast_ref::<ReplayMismatch>().unwrap();
+        assert_eq!(mismatch.call_id, "call_2");
+        assert!(err.to_string().contains(r#"- swap {"amount":"5"}"#));
+        assert!(err.to_string().contains(r#"+ swap {"amount":"1000"}"#));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,526 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::VersionedTransaction;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::spending_guard::{
+    account_keys, decode_outflows, value_outflows, OutflowPricer,
+    JUPITER_V6_PROGRAM, TOKEN_2022_PROGRAM,
+};
+use super::TransactionSigner;
+use crate::solana::constants::{
+    ASSOCIATED_TOKEN_PROGRAM, PUMP_FUN_PROGRAM, SYSTEM_PROGRAM_ID,
+    TOKEN_PROGRAM,
+};
+
+/// longest a grant can be issued for
+pub const MAX_GRANT_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// What a wallet lets an unattended bot sign on its behalf, until
+/// `expires_at`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DelegationGrant {
+    pub wallet: String,
+    pub max_notional_usd: f64,
+    /// programs every instruction has to belong to
+    pub allowed_programs: Vec<String>,
+    /// unix seconds
+    pub expires_at: u64,
+}
+
+impl DelegationGrant {
+    /// swaps on jupiter and pump.fun, with the token and account plumbing
+    /// they need
+    pub fn default_programs() -> Vec<String> {
+        [
+            JUPITER_V6_PROGRAM,
+            PUMP_FUN_PROGRAM,
+            TOKEN_PROGRAM,
+            TOKEN_2022_PROGRAM,
+            ASSOCIATED_TOKEN_PROGRAM,
+            SYSTEM_PROGRAM_ID,
+        ]
+        .iter()
+        .map(|program| program.to_string())
+        .chain([solana_sdk::compute_budget::id().to_string()])
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GrantViolation {
+    Expired {
+        expires_at: u64,
+    },
+    ProgramNotAllowed {
+        program_id: Pubkey,
+    },
+    OverLimit {
+        usd: f64,
+        limit: f64,
+    },
+    /// part of the transaction can't be valued against the limit
+    Unvalued,
+}
+
+impl fmt::Display for GrantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Expired { expires_at } => {
+                write!(f, "Delegation: the grant expired at {}", expires_at)
+            }
+            Self::ProgramNotAllowed { program_id } => write!(
+                f,
+                "Delegation: program {} is not allowed by the grant",
+                program_id
+            ),
+            Self::OverLimit { usd, limit } => write!(
+                f,
+                "Delegation: ${:.2} exceeds the ${:.2} per transaction limit \
+                 of the grant",
+                usd, limit
+            ),
+            Self::Unvalued => write!(
+                f,
+                "Delegation: the transaction can't be valued against the \
+                 limit of the grant"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GrantViolation {}
+
+/// the program of an instruction that the grant doesn't allow, if any
+pub fn check_programs(
+    grant: &DelegationGrant,
+    tx: &VersionedTransaction,
+) -> Result<(), GrantViolation> {
+    let keys = tx.message.static_account_keys();
+    for ix in tx.message.instructions() {
+        let program_id = keys[ix.program_id_index as usize];
+        if !grant
+            .allowed_programs
+            .iter()
+            .any(|allowed| *allowed == program_id.to_string())
+        {
+            return Err(GrantViolation::ProgramNotAllowed { program_id });
+        }
+    }
+    Ok(())
+}
+
+/// Signs only what the grant covers and nothing once it expired, the rest
+/// is refused before the wrapped signer sees it. EVM transactions are
+/// always refused.
+pub struct DelegatedSigner {
+    inner: Arc<dyn TransactionSigner>,
+    grant: DelegationGrant,
+    pricer: Arc<dyn OutflowPricer>,
+}
+
+impl DelegatedSigner {
+    pub fn new(
+        inner: Arc<dyn TransactionSigner>,
+        grant: DelegationGrant,
+        pricer: Arc<dyn OutflowPricer>,
+    ) -> Self {
+        Self {
+            inner,
+            grant,
+            pricer,
+        }
+    }
+
+    async fn check(&self, tx: &VersionedTransaction) -> Result<()> {
+        let now = chrono::Utc::now().timestamp() as u64;
+        if now >= self.grant.expires_at {
+            return Err(GrantViolation::Expired {
+                expires_at: self.grant.expires_at,
+            }
+            .into());
+        }
+        check_programs(&self.grant, tx)?;
+
+        let owner = Pubkey::from_str(&self.inner.pubkey())?;
+        let keys = account_keys(tx, self.pricer.as_ref()).await;
+        let outflows = decode_outflows(tx, &keys, &owner);
+        let (usd, unknown) =
+            value_outflows(&outflows, self.pricer.as_ref()).await;
+        if unknown {
+            return Err(GrantViolation::Unvalued.into());
+        }
+        if usd > self.grant.max_notional_usd {
+            return Err(GrantViolation::OverLimit {
+                usd,
+                limit: self.grant.max_notional_usd,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for DelegatedSigner {
+    fn address(&self) -> String {
+        self.inner.address()
+    }
+
+    fn pubkey(&self) -> String {
+        self.inner.pubkey()
+    }
+
+    fn addresses(&self) -> HashMap<String, String> {
+        self.inner.addresses()
+    }
+
+    fn needs_durable_nonce(&self) -> bool {
+        self.inner.needs_durable_nonce()
+    }
+
+    async fn sign_and_send_solana_transaction(
+        &self,
+        tx: &mut VersionedTransaction,
+    ) -> Result<String> {
+        if let Err(e) = self.check(tx).await {
+            tracing::warn!(wallet = self.grant.wallet, %e, "refused");
+            return Err(e);
+        }
+        self.inner.sign_and_send_solana_transaction(tx).await
+    }
+
+    async fn sign_solana_transaction(
+        &self,
+        tx: &mut VersionedTransaction,
+    ) -> Result<()> {
+        if let Err(e) = self.check(tx).await {
+            tracing::warn!(wallet = self.grant.wallet, %e, "refused");
+            return Err(e);
+        }
+        self.inner.sign_solana_transaction(tx).await
+    }
+
+    async fn sign_and_send_encoded_solana_transaction(
+        &self,
+        tx: String,
+    ) -> Result<String> {
+        let decoded: VersionedTransaction =
+            bincode::deserialize(&BASE64_STANDARD.decode(&tx)?)
+                .map_err(|e| anyhow!("Invalid encoded transaction: {}", e))?;
+        if let Err(e) = self.check(&decoded).await {
+            tracing::warn!(wallet = self.grant.wallet, %e, "refused");
+            return Err(e);
+        }
+        self.inner
+            .sign_and_send_encoded_solana_transaction(tx)
+            .await
+    }
+}
+
+/// Store and pricer shared by the delegated signers of every request
+#[cfg(feature = "http")]
+#[derive(Clone)]
+pub struct DelegationPolicy {
+    pub store: Arc<RedisGrantStore>,
+    pub pricer: Arc<dyn OutflowPricer>,
+}
+
+#[cfg(feature = "http")]
+impl DelegationPolicy {
+    /// `inner` restricted to the grant of its wallet, as it is if the
+    /// wallet has none. A grant that can't be read is an error rather than
+    /// no grant
+    pub async fn restrict(
+        &self,
+        inner: Arc<dyn TransactionSigner>,
+    ) -> Result<Arc<dyn TransactionSigner>> {
+        Ok(match self.store.get(&inner.pubkey()).await? {
+            Some(grant) => Arc::new(DelegatedSigner::new(
+                inner,
+                grant,
+                self.pricer.clone(),
+            )),
+            None => inner,
+        })
+    }
+}
+
+#[cfg(feature = "http")]
+pub use redis_store::RedisGrantStore;
+
+#[cfg(feature = "http")]
+mod redis_store {
+    use super::*;
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    fn grant_key(wallet: &str) -> String {
+        format!("delegation:grant:{}", wallet)
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct SignedGrant {
+        grant: DelegationGrant,
+        /// hex HMAC-SHA256 of the grant's json
+        signature: String,
+    }
+
+    /// Grants signed with a server secret, so one edited in redis is
+    /// rejected, and expiring together with the grant
+    pub struct RedisGrantStore {
+        client: redis::Client,
+        secret: String,
+    }
+
+    impl RedisGrantStore {
+        pub fn new(redis_url: &str, secret: String) -> Result<Self> {
+            Ok(Self {
+                client: redis::Client::open(redis_url)?,
+                secret,
+            })
+        }
+
+        fn mac(&self, grant: &DelegationGrant) -> Result<Hmac<Sha256>> {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+                    .map_err(|e| {
+                        anyhow!("Invalid delegation secret: {}", e)
+                    })?;
+            mac.update(serde_json::to_string(grant)?.as_bytes());
+            Ok(mac)
+        }
+
+        pub async fn put(&self, grant: &DelegationGrant) -> Result<()> {
+            let now = chrono::Utc::now().timestamp() as u64;
+            let ttl = grant.expires_at.saturating_sub(now);
+            if ttl == 0 {
+                return Err(anyhow!("The grant already expired"));
+            }
+            let signed = SignedGrant {
+                grant: grant.clone(),
+                signature: hex::encode(
+                    self.mac(grant)?.finalize().into_bytes(),
+                ),
+            };
+            let mut conn =
+                self.client.get_multiplexed_async_connection().await?;
+            let _: () = redis::cmd("SET")
+                .arg(grant_key(&grant.wallet))
+                .arg(serde_json::to_string(&signed)?)
+                .arg("EX")
+                .arg(ttl)
+                .query_async(&mut conn)
+                .await?;
+            Ok(())
+        }
+
+        pub async fn get(
+            &self,
+            wallet: &str,
+        ) -> Result<Option<DelegationGrant>> {
+            let mut conn =
+                self.client.get_multiplexed_async_connection().await?;
+            let signed: Option<String> = redis::cmd("GET")
+                .arg(grant_key(wallet))
+                .query_async(&mut conn)
+                .await?;
+            let Some(signed) = signed else {
+                return Ok(None);
+            };
+            let signed: SignedGrant = serde_json::from_str(&signed)?;
+            self.mac(&signed.grant)?
+                .verify_slice(&hex::decode(&signed.signature)?)
+                .map_err(|_| anyhow!("Invalid signature on the grant"))?;
+            if signed.grant.wallet != wallet {
+                return Err(anyhow!("The grant is for another wallet"));
+            }
+            Ok(Some(signed.grant))
+        }
+
+        pub async fn revoke(&self, wallet: &str) -> Result<()> {
+            let mut conn =
+                self.client.get_multiplexed_async_connection().await?;
+            let _: () = redis::cmd("DEL")
+                .arg(grant_key(wallet))
+                .query_async(&mut conn)
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::testing::{CountingSigner, FixedPricer};
+    use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+    use solana_sdk::message::{v0, VersionedMessage};
+    use solana_sdk::native_token::LAMPORTS_PER_SOL;
+    use solana_sdk::system_instruction;
+    use solana_sdk::transaction::Transaction;
+
+    fn delegated(expires_in: i64) -> (DelegatedSigner, Arc<CountingSigner>) {
+        delegated_with(expires_in, FixedPricer::default())
+    }
+
+    fn delegated_with(
+        expires_in: i64,
+        pricer: FixedPricer,
+    ) -> (DelegatedSigner, Arc<CountingSigner>) {
+        let inner = Arc::new(CountingSigner::new());
+        let grant = DelegationGrant {
+            wallet: inner.pubkey.to_string(),
+            // 2 SOL at the fixed price
+            max_notional_usd: 200.,
+            allowed_programs: DelegationGrant::default_programs(),
+            expires_at: (chrono::Utc::now().timestamp() + expires_in) as u64,
+        };
+        let signer =
+            DelegatedSigner::new(inner.clone(), grant, Arc::new(pricer));
+        (signer, inner)
+    }
+
+    fn transfer(from: &Pubkey, lamports: u64) -> VersionedTransaction {
+        Transaction::new_with_payer(
+            &[system_instruction::transfer(
+                from,
+                &Pubkey::new_unique(),
+                lamports,
+            )],
+            Some(from),
+        )
+        .into()
+    }
+
+    fn violation(err: anyhow::Error) -> GrantViolation {
+        err.downcast::<GrantViolation>().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_allowed_program() {
+        let (signer, inner) = delegated(3_600);
+        signer
+            .sign_and_send_solana_transaction(&mut transfer(
+                &inner.pubkey,
+                LAMPORTS_PER_SOL,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(inner.sent(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_program() {
+        let (signer, inner) = delegated(3_600);
+        let program_id = Pubkey::new_unique();
+        let mut tx = Transaction::new_with_payer(
+            &[Instruction::new_with_bytes(
+                program_id,
+                &[],
+                vec![AccountMeta::new(inner.pubkey, true)],
+            )],
+            Some(&inner.pubkey),
+        )
+        .into();
+        let err = signer
+            .sign_and_send_solana_transaction(&mut tx)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            violation(err),
+            GrantViolation::ProgramNotAllowed { program_id }
+        );
+        assert_eq!(inner.sent(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_over_limit_notional() {
+        let (signer, inner) = delegated(3_600);
+        let err = signer
+            .sign_and_send_solana_transaction(&mut transfer(
+                &inner.pubkey,
+                3 * LAMPORTS_PER_SOL,
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            violation(err),
+            GrantViolation::OverLimit {
+                usd: 300.,
+                limit: 200.
+            }
+        );
+        assert_eq!(inner.sent(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_expired_grant() {
+        let (signer, inner) = delegated(-1);
+        let err = signer
+            .sign_and_send_solana_transaction(&mut transfer(
+                &inner.pubkey,
+                LAMPORTS_PER_SOL,
+            ))
+            .await
+            .unwrap_err();
+        assert!(matches!(violation(err), GrantViolation::Expired { .. }));
+        assert_eq!(inner.sent(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_resolves_lookup_tables() {
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![Pubkey::new_unique()],
+        };
+        let pricer = FixedPricer {
+            tables: HashMap::from([(table.key, table.addresses.clone())]),
+            ..Default::default()
+        };
+        let (signer, inner) = delegated_with(3_600, pricer);
+        let message = v0::Message::try_compile(
+            &inner.pubkey,
+            &[system_instruction::transfer(
+                &inner.pubkey,
+                &table.addresses[0],
+                3 * LAMPORTS_PER_SOL,
+            )],
+            &[table],
+            Hash::default(),
+        )
+        .unwrap();
+        let mut tx = VersionedTransaction {
+            signatures: vec![Default::default()],
+            message: VersionedMessage::V0(message),
+        };
+        // the recipient comes from the table, the transfer is still valued
+        let err = signer
+            .sign_and_send_solana_transaction(&mut tx)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            violation(err),
+            GrantViolation::OverLimit {
+                usd: 300.,
+                limit: 200.
+            }
+        );
+
+        // a table that can't be loaded leaves it unvalued
+        let (signer, _) = delegated(3_600);
+        let err = signer
+            .sign_and_send_solana_transaction(&mut tx)
+            .await
+            .unwrap_err();
+        assert_eq!(violation(err), GrantViolation::Unvalued);
+    }
+}
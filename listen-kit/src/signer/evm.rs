@@ -2,6 +2,7 @@ use alloy::network::EthereumWallet;
 use alloy::signers::local::PrivateKeySigner;
 use anyhow::Result;
 use async_trait::async_trait;
+use std::collections::HashMap;
 use std::str::FromStr;
 
 use crate::evm::transaction::send_transaction;
@@ -29,6 +30,10 @@ impl TransactionSigner for LocalEvmSigner {
         self.wallet.default_signer().address().to_string()
     }
 
+    fn addresses(&self) -> HashMap<String, String> {
+        HashMap::from([("evm".to_string(), self.address())])
+    }
+
     async fn sign_and_send_evm_transaction(
         &self,
         tx: alloy::rpc::types::TransactionRequest,
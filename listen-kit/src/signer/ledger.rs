@@ -0,0 +1,583 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use ledger_transport::{APDUCommand, Exchange};
+use ledger_transport_hid::hidapi::HidApi;
+use ledger_transport_hid::TransportNativeHID;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+
+use super::solana::{prepare_transaction, send_transaction};
+use super::{SignerContext, TransactionSigner};
+
+/// the first account of the first wallet, what Phantom and Solflare derive
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/501'/0'/0'";
+
+/// how long the user gets to approve a transaction on the device
+pub const DEFAULT_SIGNING_TIMEOUT: Duration = Duration::from_secs(60);
+
+// APDUs of the Solana Ledger app
+const CLA: u8 = 0xe0;
+const INS_GET_APP_CONFIGURATION: u8 = 0x04;
+const INS_GET_PUBKEY: u8 = 0x05;
+const INS_SIGN_MESSAGE: u8 = 0x06;
+const P1_NON_CONFIRM: u8 = 0x00;
+const P1_CONFIRM: u8 = 0x01;
+const P2_EXTEND: u8 = 0x01;
+const P2_MORE: u8 = 0x02;
+/// the length byte of an APDU caps its payload
+const MAX_CHUNK_SIZE: usize = 255;
+
+const SW_OK: u16 = 0x9000;
+const SW_BLIND_SIGNING_DISABLED: u16 = 0x6808;
+const SW_USER_REJECTED: u16 = 0x6985;
+const SW_APP_NOT_OPEN: u16 = 0x6e00;
+
+const HARDENED: u32 = 0x8000_0000;
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum LedgerError {
+    #[error(
+        "The Ledger Solana app can't display this transaction, enable \
+         blind signing in the app's settings and try again"
+    )]
+    BlindSigningDisabled,
+    #[error("The transaction was rejected on the Ledger")]
+    Rejected,
+    #[error("Open the Solana app on the Ledger")]
+    AppNotOpen,
+    #[error("The transaction wasn't approved on the Ledger within {0:?}")]
+    Timeout(Duration),
+    #[error("Ledger returned status {0:#06x}")]
+    Status(u16),
+    #[error("Ledger transport error: {0}")]
+    Transport(String),
+}
+
+fn check_status(status: u16) -> Result<(), LedgerError> {
+    match status {
+        SW_OK => Ok(()),
+        SW_BLIND_SIGNING_DISABLED => Err(LedgerError::BlindSigningDisabled),
+        SW_USER_REJECTED => Err(LedgerError::Rejected),
+        SW_APP_NOT_OPEN => Err(LedgerError::AppNotOpen),
+        status => Err(LedgerError::Status(status)),
+    }
+}
+
+/// `m/44'/501'/<account>'/<change>'`, every index hardened as the Solana
+/// app requires
+pub fn parse_derivation_path(path: &str) -> Result<Vec<u32>> {
+    let indexes = path
+        .trim()
+        .trim_start_matches("m/")
+        .split('/')
+        .map(|index| {
+            let index = index
+                .strip_suffix('\'')
+                .or_else(|| index.strip_suffix('h'))
+                .ok_or_else(|| {
+                    anyhow!("{} has an unhardened index {}", path, index)
+                })?;
+            let index: u32 = index
+                .parse()
+                .map_err(|_| anyhow!("{} has an invalid index", path))?;
+            if index >= HARDENED {
+                return Err(anyhow!("{} has an invalid index", path));
+            }
+            Ok(index | HARDENED)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if indexes.len() < 2
+        || indexes.len() > 4
+        || indexes[..2] != [44 | HARDENED, 501 | HARDENED]
+    {
+        return Err(anyhow!("{} is not a Solana derivation path", path));
+    }
+    Ok(indexes)
+}
+
+fn serialize_derivation_path(path: &[u32]) -> Vec<u8> {
+    let mut serialized = vec![path.len() as u8];
+    for index in path {
+        serialized.extend_from_slice(&index.to_be_bytes());
+    }
+    serialized
+}
+
+/// `(p2, payload)` of the sign APDUs of `message`, the first one carrying
+/// the signer count and derivation path
+fn sign_message_chunks(path: &[u32], message: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut first = vec![1];
+    first.extend(serialize_derivation_path(path));
+    let (head, rest) =
+        message.split_at(message.len().min(MAX_CHUNK_SIZE - first.len()));
+    first.extend_from_slice(head);
+
+    let mut chunks = vec![(0, first)];
+    chunks.extend(
+        rest.chunks(MAX_CHUNK_SIZE)
+            .map(|chunk| (P2_EXTEND, chunk.to_vec())),
+    );
+    let last = chunks.len() - 1;
+    for (p2, _) in &mut chunks[..last] {
+        *p2 |= P2_MORE;
+    }
+    chunks
+}
+
+/// Signs on a Ledger running the Solana app, for self-hosted deployments;
+/// every transaction has to be approved on the device
+pub struct LedgerSigner<T = TransportNativeHID> {
+    transport: Arc<T>,
+    derivation_path: Vec<u32>,
+    pubkey: Pubkey,
+    timeout: Duration,
+    /// sends through this client instead of jito and the public RPC
+    rpc_client: Option<Arc<RpcClient>>,
+}
+
+impl LedgerSigner<TransportNativeHID> {
+    /// the connected Ledger with serial number `serial`, or the only one
+    pub fn open_device(serial: Option<&str>) -> Result<TransportNativeHID> {
+        let api = HidApi::new()
+            .map_err(|e| anyhow!("Failed to access USB devices: {}", e))?;
+        let devices: Vec<_> = TransportNativeHID::list_ledgers(&api)
+            .filter(|device| {
+                serial.is_none() || device.serial_number() == serial
+            })
+            .collect();
+        match devices.as_slice() {
+            [device] => TransportNativeHID::open_device(&api, device)
+                .map_err(|e| anyhow!("Failed to open the Ledger: {}", e)),
+            [] => {
+                Err(anyhow!("No Ledger found, is it connected and unlocked?"))
+            }
+            devices => Err(anyhow!(
+                "{} Ledgers found, pick one with LEDGER_SERIAL: {}",
+                devices.len(),
+                devices
+                    .iter()
+                    .filter_map(|device| device.serial_number())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    /// `LEDGER_SERIAL`, `LEDGER_DERIVATION_PATH` and `LEDGER_TIMEOUT_SECS`,
+    /// all optional
+    pub async fn from_env_config() -> Result<Self> {
+        let transport = Self::open_device(
+            std::env::var("LEDGER_SERIAL").ok().as_deref(),
+        )?;
+        let derivation_path = std::env::var("LEDGER_DERIVATION_PATH")
+            .unwrap_or_else(|_| DEFAULT_DERIVATION_PATH.to_string());
+        let mut signer = Self::connect(transport, &derivation_path).await?;
+        if let Ok(timeout) = std::env::var("LEDGER_TIMEOUT_SECS") {
+            let timeout = timeout.parse().map_err(|_| {
+                anyhow!("LEDGER_TIMEOUT_SECS is not a number")
+            })?;
+            signer = signer.with_timeout(Duration::from_secs(timeout));
+        }
+        if !signer.blind_signing_enabled().await? {
+            tracing::warn!(
+                "blind signing is disabled in the Ledger Solana app, swaps \
+                 will fail to sign until it is enabled"
+            );
+        }
+        Ok(signer)
+    }
+}
+
+impl<T> LedgerSigner<T>
+where
+    T: Exchange + Send + Sync + 'static,
+    T::Error: Display,
+{
+    /// reads the pubkey of `derivation_path` off the device
+    pub async fn connect(
+        transport: T,
+        derivation_path: &str,
+    ) -> Result<Self> {
+        let derivation_path = parse_derivation_path(derivation_path)?;
+        let mut signer = Self {
+            transport: Arc::new(transport),
+            derivation_path,
+            pubkey: Pubkey::default(),
+            timeout: DEFAULT_SIGNING_TIMEOUT,
+            rpc_client: None,
+        };
+        let pubkey = signer
+            .exchange(
+                INS_GET_PUBKEY,
+                P1_NON_CONFIRM,
+                0,
+                serialize_derivation_path(&signer.derivation_path),
+            )
+            .await?;
+        signer.pubkey = Pubkey::try_from(pubkey.as_slice())
+            .map_err(|_| anyhow!("Ledger returned an invalid pubkey"))?;
+        Ok(signer)
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_rpc_client(mut self, rpc_client: Arc<RpcClient>) -> Self {
+        self.rpc_client = Some(rpc_client);
+        self
+    }
+
+    /// whether the app signs transactions it can't display, most swaps
+    /// need it
+    pub async fn blind_signing_enabled(&self) -> Result<bool> {
+        let config = self
+            .exchange(INS_GET_APP_CONFIGURATION, 0, 0, Vec::new())
+            .await?;
+        Ok(config.first().is_some_and(|enabled| *enabled != 0))
+    }
+
+    /// the HID transport blocks until the device answers, so exchanges run
+    /// off the runtime
+    async fn exchange(
+        &self,
+        ins: u8,
+        p1: u8,
+        p2: u8,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>, LedgerError> {
+        let transport = self.transport.clone();
+        let command = APDUCommand {
+            cla: CLA,
+            ins,
+            p1,
+            p2,
+            data,
+        };
+        let handle = tokio::runtime::Handle::current();
+        let (status, data) = tokio::task::spawn_blocking(move || {
+            handle
+                .block_on(transport.exchange(&command))
+                .map(|answer| (answer.retcode(), answer.data().to_vec()))
+                .map_err(|e| LedgerError::Transport(e.to_string()))
+        })
+        .await
+        .map_err(|e| LedgerError::Transport(e.to_string()))??;
+        check_status(status)?;
+        Ok(data)
+    }
+
+    /// signs a serialized message once the user approves it on the device
+    pub async fn sign_message(
+        &self,
+        message: &[u8],
+    ) -> Result<Signature, LedgerError> {
+        SignerContext::prompt(format!(
+            "Approve the transaction on your Ledger ({})",
+            self.pubkey
+        ));
+        let sign = async {
+            let mut signature = Vec::new();
+            for (p2, payload) in
+                sign_message_chunks(&self.derivation_path, message)
+            {
+                signature = self
+                    .exchange(INS_SIGN_MESSAGE, P1_CONFIRM, p2, payload)
+                    .await?;
+            }
+            Signature::try_from(signature.as_slice()).map_err(|_| {
+                LedgerError::Transport("invalid signature".to_string())
+            })
+        };
+        // a timed out exchange keeps the device busy until the user
+        // answers it
+        tokio::time::timeout(self.timeout, sign)
+            .await
+            .map_err(|_| LedgerError::Timeout(self.timeout))?
+    }
+
+    /// sets the blockhash and signs at the signer's position among the
+    /// required signers
+    pub async fn sign_solana_transaction(
+        &self,
+        tx: &mut VersionedTransaction,
+    ) -> Result<()> {
+        let position =
+            prepare_transaction(self.rpc_client.as_ref(), tx, &self.pubkey)
+                .await?;
+        tx.signatures[position] =
+            self.sign_message(&tx.message.serialize()).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T> TransactionSigner for LedgerSigner<T>
+where
+    T: Exchange + Send + Sync + 'static,
+    T::Error: Display,
+{
+    fn pubkey(&self) -> String {
+        self.pubkey.to_string()
+    }
+
+    fn addresses(&self) -> HashMap<String, String> {
+        HashMap::from([("solana".to_string(), self.pubkey())])
+    }
+
+    /// the user might take longer to approve than a blockhash lives
+    fn needs_durable_nonce(&self) -> bool {
+        true
+    }
+
+    async fn sign_and_send_solana_transaction(
+        &self,
+        tx: &mut VersionedTransaction,
+    ) -> Result<String> {
+        self.sign_solana_transaction(tx).await?;
+        send_transaction(self.rpc_client.as_ref(), tx).await
+    }
+
+    async fn sign_solana_transaction(
+        &self,
+        tx: &mut VersionedTransaction,
+    ) -> Result<()> {
+        LedgerSigner::sign_solana_transaction(self, tx).await
+    }
+
+    /// base64 bincode, legacy transactions deserialize as versioned ones
+    async fn sign_and_send_encoded_solana_transaction(
+        &self,
+        tx: String,
+    ) -> Result<String> {
+        let mut tx: VersionedTransaction =
+            bincode::deserialize(&BASE64_STANDARD.decode(tx)?)?;
+        self.sign_and_send_solana_transaction(&mut tx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::ops::Deref;
+    use std::sync::Mutex;
+
+    use ledger_transport::APDUAnswer;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::message::{v0, VersionedMessage};
+    use solana_sdk::system_instruction;
+
+    use super::*;
+
+    /// answers with canned responses and records the commands it got
+    #[derive(Default)]
+    struct MockTransport {
+        commands: Mutex<Vec<(u8, u8, u8, Vec<u8>)>>,
+        responses: Mutex<VecDeque<Vec<u8>>>,
+        sign_delay: Duration,
+    }
+
+    impl MockTransport {
+        fn respond(self, data: &[u8], status: u16) -> Self {
+            let mut response = data.to_vec();
+            response.extend_from_slice(&status.to_be_bytes());
+            self.responses.lock().unwrap().push_back(response);
+            self
+        }
+    }
+
+    #[async_trait]
+    impl Exchange for MockTransport {
+        type Error = String;
+        type AnswerType = Vec<u8>;
+
+        async fn exchange<I>(
+            &self,
+            command: &APDUCommand<I>,
+        ) -> Result<APDUAnswer<Vec<u8>>, String>
+        where
+            I: Deref<Target = [u8]> + Send + Sync,
+        {
+            assert_eq!(command.cla, CLA);
+            if command.ins == INS_SIGN_MESSAGE {
+                // the HID transport blocks while the user decides
+                std::thread::sleep(self.sign_delay);
+            }
+            self.commands.lock().unwrap().push((
+                command.ins,
+                command.p1,
+                command.p2,
+                command.data.to_vec(),
+            ));
+            let response = self
+                .responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or("no response left")?;
+            APDUAnswer::from_answer(response).map_err(|e| e.to_string())
+        }
+    }
+
+    async fn connect(
+        transport: MockTransport,
+        pubkey: &Pubkey,
+    ) -> LedgerSigner<MockTransport> {
+        // the pubkey answer goes first
+        let transport = MockTransport {
+            responses: Mutex::new(
+                [[pubkey.to_bytes().as_slice(), &SW_OK.to_be_bytes()]
+                    .concat()]
+                .into_iter()
+                .chain(transport.responses.into_inner().unwrap())
+                .collect(),
+            ),
+            ..transport
+        };
+        LedgerSigner::connect(transport, DEFAULT_DERIVATION_PATH)
+            .await
+            .unwrap()
+            .with_rpc_client(Arc::new(RpcClient::new_mock(
+                "succeeds".to_string(),
+            )))
+    }
+
+    #[test]
+    fn test_parse_derivation_path() {
+        assert_eq!(
+            parse_derivation_path(DEFAULT_DERIVATION_PATH).unwrap(),
+            vec![44 | HARDENED, 501 | HARDENED, HARDENED, HARDENED]
+        );
+        assert_eq!(
+            parse_derivation_path("44h/501h/3h").unwrap(),
+            vec![44 | HARDENED, 501 | HARDENED, 3 | HARDENED]
+        );
+        assert!(parse_derivation_path("m/44'/501'/0/0").is_err());
+        assert!(parse_derivation_path("m/44'/60'/0'/0'").is_err());
+        assert!(parse_derivation_path("m/44'/501'/0'/0'/0'").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sign_v0_transaction_apdus() {
+        let payer = Pubkey::new_unique();
+        // enough accounts for the message to span several APDUs
+        let instructions: Vec<_> = (0..12)
+            .map(|_| {
+                system_instruction::transfer(&payer, &Pubkey::new_unique(), 1)
+            })
+            .collect();
+        let message = v0::Message::try_compile(
+            &payer,
+            &instructions,
+            &[],
+            Hash::default(),
+        )
+        .unwrap();
+        let mut tx = VersionedTransaction {
+            signatures: vec![Signature::default()],
+            message: VersionedMessage::V0(message),
+        };
+
+        let signature = Signature::from([7; 64]);
+        let transport = MockTransport::default()
+            .respond(&[], SW_OK)
+            .respond(&[], SW_OK)
+            .respond(signature.as_ref(), SW_OK);
+        let signer = connect(transport, &payer).await;
+        assert_eq!(signer.pubkey(), payer.to_string());
+
+        let (prompts, mut prompts_rx) =
+            tokio::sync::mpsc::unbounded_channel();
+        SignerContext::with_prompts(
+            prompts,
+            signer.sign_solana_transaction(&mut tx),
+        )
+        .await
+        .unwrap();
+        assert_eq!(tx.signatures, vec![signature]);
+        assert!(prompts_rx
+            .try_recv()
+            .unwrap()
+            .starts_with("Approve the transaction on your Ledger"));
+
+        let commands = signer.transport.commands.lock().unwrap();
+        let (ins, p1, p2, data) = &commands[0];
+        assert_eq!((*ins, *p1, *p2), (INS_GET_PUBKEY, P1_NON_CONFIRM, 0));
+        assert_eq!(data.len(), 17);
+
+        let sign_commands = &commands[1..];
+        assert_eq!(
+            sign_commands
+                .iter()
+                .map(|(ins, p1, p2, _)| (*ins, *p1, *p2))
+                .collect::<Vec<_>>(),
+            vec![
+                (INS_SIGN_MESSAGE, P1_CONFIRM, P2_MORE),
+                (INS_SIGN_MESSAGE, P1_CONFIRM, P2_EXTEND | P2_MORE),
+                (INS_SIGN_MESSAGE, P1_CONFIRM, P2_EXTEND),
+            ]
+        );
+        let first = &sign_commands[0].3;
+        assert_eq!(first.len(), MAX_CHUNK_SIZE);
+        // one signer, then the four indexes of the path
+        assert_eq!(&first[..2], &[1, 4]);
+        assert_eq!(&first[2..6], &(44 | HARDENED).to_be_bytes());
+        assert_eq!(&first[6..10], &(501 | HARDENED).to_be_bytes());
+        let message: Vec<u8> = std::iter::once(&first[18..])
+            .chain(sign_commands[1..].iter().map(|(_, _, _, data)| &data[..]))
+            .flatten()
+            .copied()
+            .collect();
+        // the versioned message with its 0x80 prefix, as signed
+        assert_eq!(message, tx.message.serialize());
+        assert_eq!(message[0], 0x80);
+    }
+
+    #[tokio::test]
+    async fn test_sign_errors() {
+        let pubkey = Pubkey::new_unique();
+        let transport = MockTransport::default()
+            .respond(&[], SW_BLIND_SIGNING_DISABLED)
+            .respond(&[], SW_USER_REJECTED);
+        let signer = connect(transport, &pubkey).await;
+        let err = signer.sign_message(&[0; 64]).await.unwrap_err();
+        assert_eq!(err, LedgerError::BlindSigningDisabled);
+        assert!(err.to_string().contains("enable blind signing"));
+        assert_eq!(
+            signer.sign_message(&[0; 64]).await.unwrap_err(),
+            LedgerError::Rejected
+        );
+
+        let transport = MockTransport {
+            sign_delay: Duration::from_millis(200),
+            ..Default::default()
+        }
+        .respond(&[], SW_OK);
+        let signer = connect(transport, &pubkey)
+            .await
+            .with_timeout(Duration::from_millis(20));
+        assert_eq!(
+            signer.sign_message(&[0; 64]).await.unwrap_err(),
+            LedgerError::Timeout(Duration::from_millis(20))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blind_signing_enabled() {
+        let transport = MockTransport::default()
+            .respond(&[1, 0, 1, 3, 17], SW_OK)
+            .respond(&[0, 0, 1, 3, 17], SW_OK);
+        let signer = connect(transport, &Pubkey::new_unique()).await;
+        assert!(signer.blind_signing_enabled().await.unwrap());
+        assert!(!signer.blind_signing_enabled().await.unwrap());
+    }
+}
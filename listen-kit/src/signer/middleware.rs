@@ -0,0 +1,424 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use solana_sdk::transaction::VersionedTransaction;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::TransactionSigner;
+
+/// What a middleware makes of a transaction about to be signed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    Allow,
+    /// nothing is signed, the reason is returned to the agent
+    Deny(String),
+    /// signs this one instead, e.g. with priority fee instructions added
+    Modify(VersionedTransaction),
+}
+
+/// How a transaction that went through the middlewares ended up
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendOutcome {
+    Sent,
+    Denied(String),
+    Failed(String),
+}
+
+/// Behaviour wrapped around a signer, Solana transactions only
+#[async_trait]
+pub trait SignerMiddleware: Send + Sync {
+    async fn before_sign(
+        &self,
+        _tx: &VersionedTransaction,
+    ) -> Result<Decision> {
+        Ok(Decision::Allow)
+    }
+
+    /// `signature` is set when the transaction was sent
+    async fn after_send(
+        &self,
+        _signature: Option<&str>,
+        _outcome: &SendOutcome,
+    ) {
+    }
+}
+
+/// Runs the `before_sign` of every middleware in order, then the inner
+/// signer, then the `after_send` of the middlewares that ran, in reverse.
+/// A denial stops the stack, later middlewares and the signer don't see
+/// the transaction. EVM transactions pass straight through.
+pub struct LayeredSigner {
+    inner: Arc<dyn TransactionSigner>,
+    middlewares: Vec<Arc<dyn SignerMiddleware>>,
+}
+
+impl LayeredSigner {
+    pub fn new(
+        inner: Arc<dyn TransactionSigner>,
+        middlewares: Vec<Arc<dyn SignerMiddleware>>,
+    ) -> Self {
+        Self { inner, middlewares }
+    }
+
+    /// adds `middleware` below the ones already stacked
+    pub fn layer(mut self, middleware: Arc<dyn SignerMiddleware>) -> Self {
+        self.middlewares.push(middleware);
+        self
+    }
+
+    /// number of middlewares that ran and the denial, if any
+    async fn run_before_sign(
+        &self,
+        tx: &mut VersionedTransaction,
+    ) -> Result<(usize, Option<String>)> {
+        for (i, middleware) in self.middlewares.iter().enumerate() {
+            match middleware.before_sign(tx).await? {
+                Decision::Allow => {}
+                Decision::Modify(modified) => *tx = modified,
+                Decision::Deny(reason) => return Ok((i + 1, Some(reason))),
+            }
+        }
+        Ok((self.middlewares.len(), None))
+    }
+
+    async fn run_after_send(
+        &self,
+        ran: usize,
+        signature: Option<&str>,
+        outcome: &SendOutcome,
+    ) {
+        for middleware in self.middlewares[..ran].iter().rev() {
+            middleware.after_send(signature, outcome).await;
+        }
+    }
+
+    async fn send_layered<F, Fut>(
+        &self,
+        tx: &mut VersionedTransaction,
+        send: F,
+    ) -> Result<String>
+    where
+        F: FnOnce(VersionedTransaction) -> Fut,
+        Fut: std::future::Future<Output = Result<String>>,
+    {
+        let (ran, denial) = self.run_before_sign(tx).await?;
+        if let Some(reason) = denial {
+            let outcome = SendOutcome::Denied(reason.clone());
+            self.run_after_send(ran, None, &outcome).await;
+            return Err(anyhow!("Transaction denied: {}", reason));
+        }
+        let result = send(tx.clone()).await;
+        match &result {
+            Ok(signature) => {
+                self.run_after_send(
+                    ran,
+                    Some(signature.as_str()),
+                    &SendOutcome::Sent,
+                )
+                .await
+            }
+            Err(e) => {
+                self.run_after_send(
+                    ran,
+                    None,
+                    &SendOutcome::Failed(e.to_string()),
+                )
+                .await
+            }
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for LayeredSigner {
+    fn address(&self) -> String {
+        self.inner.address()
+    }
+
+    fn pubkey(&self) -> String {
+        self.inner.pubkey()
+    }
+
+    fn addresses(&self) -> HashMap<String, String> {
+        self.inner.addresses()
+    }
+
+    fn needs_durable_nonce(&self) -> bool {
+        self.inner.needs_durable_nonce()
+    }
+
+    async fn sign_and_send_solana_transaction(
+        &self,
+        tx: &mut VersionedTransaction,
+    ) -> Result<String> {
+        if self.middlewares.is_empty() {
+            return self.inner.sign_and_send_solana_transaction(tx).await;
+        }
+        let inner = self.inner.clone();
+        let mut signed = None;
+        let signed_ref = &mut signed;
+        let result = self
+            .send_layered(tx, move |mut tx| async move {
+                let result =
+                    inner.sign_and_send_solana_transaction(&mut tx).await;
+                *signed_ref = Some(tx);
+                result
+            })
+            .await;
+        // the caller sees the transaction as the signer left it
+        if let Some(signed) = signed {
+            *tx = signed;
+        }
+        result
+    }
+
+    /// a transaction signed for the caller to send counts as sent for
+    /// `after_send`, with its signature
+    async fn sign_solana_transaction(
+        &self,
+        tx: &mut VersionedTransaction,
+    ) -> Result<()> {
+        if self.middlewares.is_empty() {
+            return self.inner.sign_solana_transaction(tx).await;
+        }
+        let inner = self.inner.clone();
+        let mut signed = None;
+        let signed_ref = &mut signed;
+        self.send_layered(tx, move |mut tx| async move {
+            inner.sign_solana_transaction(&mut tx).await?;
+            let signature = tx.signatures[0].to_string();
+            *signed_ref = Some(tx);
+            Ok(signature)
+        })
+        .await?;
+        if let Some(signed) = signed {
+            *tx = signed;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "evm")]
+    async fn sign_and_send_evm_transaction(
+        &self,
+        tx: alloy::rpc::types::TransactionRequest,
+    ) -> Result<String> {
+        self.inner.sign_and_send_evm_transaction(tx).await
+    }
+
+    async fn sign_and_send_encoded_solana_transaction(
+        &self,
+        tx: String,
+    ) -> Result<String> {
+        if self.middlewares.is_empty() {
+            return self
+                .inner
+                .sign_and_send_encoded_solana_transaction(tx)
+                .await;
+        }
+        let mut decoded: VersionedTransaction =
+            bincode::deserialize(&BASE64_STANDARD.decode(&tx)?)
+                .map_err(|e| anyhow!("Invalid encoded transaction: {}", e))?;
+        let inner = self.inner.clone();
+        self.send_layered(&mut decoded, |layered| async move {
+            let encoded =
+                BASE64_STANDARD.encode(bincode::serialize(&layered)?);
+            inner
+                .sign_and_send_encoded_solana_transaction(encoded)
+                .await
+        })
+        .await
+    }
+
+    async fn sign_and_send_json_evm_transaction(
+        &self,
+        tx: serde_json::Value,
+    ) -> Result<String> {
+        self.inner.sign_and_send_json_evm_transaction(tx).await
+    }
+
+    async fn sign_evm_typed_data(
+        &self,
+        typed_data: serde_json::Value,
+        chain_id: u64,
+    ) -> Result<String> {
+        self.inner.sign_evm_typed_data(typed_data, chain_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::transaction::Transaction;
+    use std::sync::Mutex;
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    fn blockhash(tx: &VersionedTransaction) -> Hash {
+        *tx.message.recent_blockhash()
+    }
+
+    struct MockSigner {
+        log: Log,
+    }
+
+    #[async_trait]
+    impl TransactionSigner for MockSigner {
+        fn pubkey(&self) -> String {
+            Pubkey::default().to_string()
+        }
+
+        async fn sign_and_send_solana_transaction(
+            &self,
+            tx: &mut VersionedTransaction,
+        ) -> Result<String> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("signer {}", blockhash(tx)));
+            Ok("signature".to_string())
+        }
+    }
+
+    struct MockMiddleware {
+        name: &'static str,
+        decision: Decision,
+        log: Log,
+    }
+
+    #[async_trait]
+    impl SignerMiddleware for MockMiddleware {
+        async fn before_sign(
+            &self,
+            tx: &VersionedTransaction,
+        ) -> Result<Decision> {
+            self.log.lock().unwrap().push(format!(
+                "{} before {}",
+                self.name,
+                blockhash(tx)
+            ));
+            Ok(self.decision.clone())
+        }
+
+        async fn after_send(
+            &self,
+            signature: Option<&str>,
+            outcome: &SendOutcome,
+        ) {
+            self.log.lock().unwrap().push(format!(
+                "{} after {:?} {:?}",
+                self.name, signature, outcome
+            ));
+        }
+    }
+
+    fn transaction(blockhash: Hash) -> VersionedTransaction {
+        let mut tx =
+            Transaction::new_with_payer(&[], Some(&Pubkey::default()));
+        tx.message.recent_blockhash = blockhash;
+        tx.into()
+    }
+
+    fn stack(decisions: [Decision; 2]) -> (LayeredSigner, Log) {
+        let log = Log::default();
+        let [a, b] = decisions;
+        let signer = LayeredSigner::new(
+            Arc::new(MockSigner { log: log.clone() }),
+            Vec::new(),
+        )
+        .layer(Arc::new(MockMiddleware {
+            name: "a",
+            decision: a,
+            log: log.clone(),
+        }))
+        .layer(Arc::new(MockMiddleware {
+            name: "b",
+            decision: b,
+            log: log.clone(),
+        }));
+        (signer, log)
+    }
+
+    fn take(log: &Log) -> Vec<String> {
+        std::mem::take(&mut *log.lock().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_middleware_order() {
+        let original = Hash::new_unique();
+        let (signer, log) = stack([Decision::Allow, Decision::Allow]);
+        let signature = signer
+            .sign_and_send_solana_transaction(&mut transaction(original))
+            .await
+            .unwrap();
+        assert_eq!(signature, "signature");
+        assert_eq!(
+            take(&log),
+            vec![
+                format!("a before {}", original),
+                format!("b before {}", original),
+                format!("signer {}", original),
+                "b after Some(\"signature\") Sent".to_string(),
+                "a after Some(\"signature\") Sent".to_string(),
+            ]
+        );
+
+        // without middlewares the signer is called as is
+        let log = Log::default();
+        let signer = LayeredSigner::new(
+            Arc::new(MockSigner { log: log.clone() }),
+            Vec::new(),
+        );
+        signer
+            .sign_and_send_solana_transaction(&mut transaction(original))
+            .await
+            .unwrap();
+        assert_eq!(take(&log), vec![format!("signer {}", original)]);
+    }
+
+    #[tokio::test]
+    async fn test_deny_short_circuits() {
+        let original = Hash::new_unique();
+        let (signer, log) = stack([
+            Decision::Deny("over limit".to_string()),
+            Decision::Allow,
+        ]);
+        let err = signer
+            .sign_and_send_solana_transaction(&mut transaction(original))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Transaction denied: over limit");
+        // neither b nor the signer saw it
+        assert_eq!(
+            take(&log),
+            vec![
+                format!("a before {}", original),
+                "a after None Denied(\"over limit\")".to_string(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_propagates() {
+        let (original, modified) = (Hash::new_unique(), Hash::new_unique());
+        let (signer, log) =
+            stack([Decision::Modify(transaction(modified)), Decision::Allow]);
+        let mut tx = transaction(original);
+        signer
+            .sign_and_send_solana_transaction(&mut tx)
+            .await
+            .unwrap();
+        assert_eq!(blockhash(&tx), modified);
+        assert_eq!(
+            take(&log)[..3],
+            [
+                format!("a before {}", original),
+                format!("b before {}", modified),
+                format!("signer {}", modified),
+            ]
+        );
+    }
+}
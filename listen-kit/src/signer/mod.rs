@@ -1,15 +1,27 @@
+#[cfg(feature = "solana")]
+pub mod delegation;
 #[cfg(feature = "evm")]
 pub mod evm;
+#[cfg(feature = "ledger")]
+pub mod ledger;
+#[cfg(feature = "solana")]
+pub mod middleware;
 #[cfg(feature = "http")]
 pub mod privy;
 #[cfg(feature = "solana")]
 pub mod solana;
+#[cfg(feature = "solana")]
+pub mod spending_guard;
+#[cfg(all(test, feature = "solana"))]
+pub(crate) mod testing;
 
+use std::collections::HashMap;
 use std::future::Future;
 use std::sync::Arc;
 
 use anyhow::Result;
 use async_trait::async_trait;
+use tokio::sync::mpsc::UnboundedSender;
 
 #[cfg(feature = "evm")]
 use self::evm::LocalEvmSigner;
@@ -18,6 +30,9 @@ use self::privy::PrivySigner;
 #[cfg(feature = "solana")]
 use self::solana::LocalSolanaSigner;
 
+/// the chain of evm transactions that don't name one
+pub const DEFAULT_EVM_CHAIN_ID: u64 = 42161;
+
 pub enum Transaction {
     #[cfg(feature = "solana")]
     Solana(solana_sdk::transaction::Transaction),
@@ -44,6 +59,19 @@ pub trait TransactionSigner: Send + Sync {
         unimplemented!()
     }
 
+    /// the address of the signer on every chain it signs for, keyed by
+    /// "solana" and "evm", one evm address serves every evm chain
+    fn addresses(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
+
+    /// signing can take longer than a blockhash stays valid, e.g. waiting
+    /// for approval on a hardware wallet, so Solana transactions get a
+    /// durable nonce
+    fn needs_durable_nonce(&self) -> bool {
+        false
+    }
+
     #[cfg(feature = "solana")]
     async fn sign_and_send_solana_transaction(
         &self,
@@ -54,6 +82,18 @@ pub trait TransactionSigner: Send + Sync {
         ))
     }
 
+    /// signs without sending, for transactions the caller lands itself,
+    /// e.g. in a Jito bundle
+    #[cfg(feature = "solana")]
+    async fn sign_solana_transaction(
+        &self,
+        _tx: &mut solana_sdk::transaction::VersionedTransaction,
+    ) -> Result<()> {
+        Err(anyhow::anyhow!(
+            "Signing without sending is not supported by this signer"
+        ))
+    }
+
     #[cfg(feature = "evm")]
     async fn sign_and_send_evm_transaction(
         &self,
@@ -81,10 +121,27 @@ pub trait TransactionSigner: Send + Sync {
             "EVM transactions not supported by this signer"
         ))
     }
+
+    /// EIP-712 signature of `typed_data` on `chain_id`
+    async fn sign_evm_typed_data(
+        &self,
+        _typed_data: serde_json::Value,
+        _chain_id: u64,
+    ) -> Result<String> {
+        Err(anyhow::anyhow!(
+            "EVM typed data signing not supported by this signer"
+        ))
+    }
 }
 
 tokio::task_local! {
     static CURRENT_SIGNER: Arc<dyn TransactionSigner>;
+    static SIGNER_PROMPTS: UnboundedSender<String>;
+}
+
+#[cfg(feature = "solana")]
+tokio::task_local! {
+    static CO_SIGNERS: Vec<Arc<solana_sdk::signature::Keypair>>;
 }
 
 pub struct SignerContext;
@@ -100,4 +157,59 @@ impl SignerContext {
     pub async fn current() -> Arc<dyn TransactionSigner> {
         CURRENT_SIGNER.get().clone()
     }
+
+    /// runs `f` with what the signer needs from the user, e.g. approving
+    /// on a hardware wallet, sent to `prompts`
+    pub async fn with_prompts<F: Future>(
+        prompts: UnboundedSender<String>,
+        f: F,
+    ) -> F::Output {
+        SIGNER_PROMPTS.scope(prompts, f).await
+    }
+
+    /// tells the user to act on the signer, a no-op when no one listens
+    pub fn prompt(message: impl Into<String>) {
+        let _ =
+            SIGNER_PROMPTS.try_with(|prompts| prompts.send(message.into()));
+    }
+
+    /// runs `f` with keypairs that sign Solana transactions next to the
+    /// signer, e.g. a new account, once the signer set the blockhash
+    #[cfg(feature = "solana")]
+    pub async fn with_co_signers<F: Future>(
+        co_signers: Vec<Arc<solana_sdk::signature::Keypair>>,
+        f: F,
+    ) -> F::Output {
+        CO_SIGNERS.scope(co_signers, f).await
+    }
+}
+
+/// signs `tx` with the co-signers of the context at their positions among
+/// the required signers, a no-op without co-signers. Any change to the
+/// message afterwards invalidates the signatures
+#[cfg(feature = "solana")]
+pub(crate) fn co_sign(
+    tx: &mut solana_sdk::transaction::VersionedTransaction,
+) -> Result<()> {
+    use solana_sdk::signer::Signer;
+
+    let Ok(co_signers) = CO_SIGNERS.try_with(Clone::clone) else {
+        return Ok(());
+    };
+    let num_signers = tx.message.header().num_required_signatures as usize;
+    tx.signatures.resize(num_signers, Default::default());
+    let message = tx.message.serialize();
+    for co_signer in co_signers {
+        let position = tx.message.static_account_keys()[..num_signers]
+            .iter()
+            .position(|key| *key == co_signer.pubkey())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{} is not a signer of the transaction",
+                    co_signer.pubkey()
+                )
+            })?;
+        tx.signatures[position] = co_signer.sign_message(&message);
+    }
+    Ok(())
 }
@@ -1,22 +1,181 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use once_cell::sync::Lazy;
 use serde::Serialize;
 
+#[cfg(feature = "solana")]
+use base64::prelude::{Engine, BASE64_STANDARD};
 #[cfg(feature = "solana")]
 use blockhash_cache::BLOCKHASH_CACHE;
+use privy::retry::{RetryEvent, RetryObserver};
+#[cfg(feature = "evm")]
+use privy::types::EvmTransaction;
+use privy::types::LinkedAccount;
 use privy::{auth::UserSession, caip2::Caip2, util::base64encode, Privy};
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use super::TransactionSigner;
+use crate::audit::{record_signer_retry, SignerRetryRecord};
 
 pub struct PrivySigner {
     privy: Arc<Privy>,
     session: UserSession,
+    policy: WalletPolicy,
 }
 
 impl PrivySigner {
     pub fn new(privy: Arc<Privy>, session: UserSession) -> Self {
-        Self { privy, session }
+        Self::with_policy(privy, session, WalletPolicy::Any)
+    }
+
+    /// the signer of `session`, refusing to sign unless `policy` lets its
+    /// wallets sign; a session that never signs is never checked
+    pub fn with_policy(
+        privy: Arc<Privy>,
+        session: UserSession,
+        policy: WalletPolicy,
+    ) -> Self {
+        Self {
+            privy,
+            session,
+            policy,
+        }
+    }
+
+    async fn check_policy(&self) -> Result<()> {
+        self.policy.check(&self.privy, &self.session).await
+    }
+}
+
+/// how long the delegated wallets of a user are trusted before privy is
+/// asked again, a revoked delegation can still sign for this long
+const DELEGATED_WALLETS_TTL: Duration = Duration::from_secs(60);
+
+static DELEGATED_WALLETS: Lazy<
+    Mutex<HashMap<String, (Instant, HashSet<String>)>>,
+> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// the wallets `user_id` has delegated to the server, from privy at most
+/// once per `DELEGATED_WALLETS_TTL`
+async fn delegated_wallets(
+    privy: &Privy,
+    user_id: &str,
+) -> Result<HashSet<String>> {
+    if let Some((fetched_at, wallets)) =
+        DELEGATED_WALLETS.lock().unwrap().get(user_id)
+    {
+        if fetched_at.elapsed() < DELEGATED_WALLETS_TTL {
+            return Ok(wallets.clone());
+        }
+    }
+    let wallets = privy
+        .get_user_by_id(user_id)
+        .await?
+        .linked_accounts
+        .iter()
+        .filter_map(|account| match account {
+            LinkedAccount::Wallet(wallet) if wallet.delegated => {
+                Some(normalize_address(&wallet.address))
+            }
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let mut cache = DELEGATED_WALLETS.lock().unwrap();
+    cache.retain(|_, (fetched_at, _)| {
+        fetched_at.elapsed() < DELEGATED_WALLETS_TTL
+    });
+    cache.insert(user_id.to_string(), (Instant::now(), wallets.clone()));
+    Ok(wallets)
+}
+
+/// which privy wallets the server signs with, `PRIVY_WALLET_POLICY`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum WalletPolicy {
+    /// every wallet privy authenticates, those were delegated at login
+    #[default]
+    Any,
+    /// wallets still delegated to the server, looked up again as the user
+    /// may have revoked the delegation since logging in
+    Delegated,
+    /// only these addresses, each wallet of a session has to be listed
+    Allowlist(HashSet<String>),
+}
+
+/// `any`, `delegated` or `allowlist:<address>,<address>,...`
+impl FromStr for WalletPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        match s.split_once(':') {
+            None if s == "any" => Ok(Self::Any),
+            None if s == "delegated" => Ok(Self::Delegated),
+            Some(("allowlist", addresses)) => Ok(Self::Allowlist(
+                addresses
+                    .split(',')
+                    .map(|address| address.trim())
+                    .filter(|address| !address.is_empty())
+                    .map(normalize_address)
+                    .collect(),
+            )),
+            _ => Err(anyhow::anyhow!("Invalid wallet policy: {}", s)),
+        }
+    }
+}
+
+/// evm addresses are compared regardless of their checksum casing
+fn normalize_address(address: &str) -> String {
+    if address.starts_with("0x") {
+        address.to_lowercase()
+    } else {
+        address.to_string()
+    }
+}
+
+impl WalletPolicy {
+    pub async fn check(
+        &self,
+        privy: &Privy,
+        session: &UserSession,
+    ) -> Result<()> {
+        let wallets = [&session.pubkey, &session.wallet_address]
+            .into_iter()
+            .filter(|address| !address.is_empty())
+            .map(|address| normalize_address(address))
+            .collect::<Vec<_>>();
+        let allowed = match self {
+            Self::Any => return Ok(()),
+            Self::Allowlist(allowlist) => allowlist.clone(),
+            Self::Delegated => {
+                delegated_wallets(privy, &session.user_id).await?
+            }
+        };
+        match wallets.iter().find(|wallet| !allowed.contains(*wallet)) {
+            Some(wallet) => Err(anyhow::anyhow!(
+                "wallet {} is not allowed to sign",
+                wallet
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// notes the retries privy makes in the audit entry of the tool call that
+/// is signing
+pub struct AuditRetryObserver;
+
+impl RetryObserver for AuditRetryObserver {
+    fn on_retry(&self, event: &RetryEvent) {
+        record_signer_retry(SignerRetryRecord {
+            method: event.method.clone(),
+            attempt: event.attempt,
+            status: event.status,
+            error: event.error.clone(),
+            gave_up: event.backoff.is_none(),
+        });
     }
 }
 
@@ -28,6 +187,47 @@ pub fn transaction_to_base64<T: Serialize>(
     Ok(base64encode(&serialized))
 }
 
+/// sets a recent blockhash unless the transaction uses a durable nonce and
+/// has the co-signers sign the final message, privy signs for the user
+#[cfg(feature = "solana")]
+async fn prepare_transaction(
+    tx: &mut solana_sdk::transaction::VersionedTransaction,
+) -> Result<()> {
+    // the blockhash of a durable transaction is its nonce
+    if !tx.uses_durable_nonce() {
+        tx.message
+            .set_recent_blockhash(BLOCKHASH_CACHE.get_blockhash().await?);
+    }
+    let num_signers = tx.message.header().num_required_signatures as usize;
+    tx.signatures = vec![Default::default(); num_signers];
+    super::co_sign(tx)
+}
+
+/// transactions without a chain id go to arbitrum, fees left out are
+/// estimated by privy
+#[cfg(feature = "evm")]
+pub fn to_privy_transaction(
+    tx: &alloy::rpc::types::TransactionRequest,
+    from: String,
+) -> EvmTransaction {
+    let hex = |n: u128| format!("0x{:x}", n);
+    let eip1559 =
+        tx.max_fee_per_gas.is_some() || tx.max_priority_fee_per_gas.is_some();
+    EvmTransaction {
+        from: Some(from),
+        to: tx.to.and_then(|to| to.to().map(|to| to.to_string())),
+        chain_id: tx.chain_id.unwrap_or(super::DEFAULT_EVM_CHAIN_ID),
+        value: tx.value.map(|value| format!("0x{:x}", value)),
+        data: tx.input.input().map(|data| data.to_string()),
+        nonce: tx.nonce,
+        gas_limit: tx.gas.map(|gas| hex(gas as u128)),
+        tx_type: tx.transaction_type.or(eip1559.then_some(2)),
+        gas_price: tx.gas_price.map(hex),
+        max_fee_per_gas: tx.max_fee_per_gas.map(hex),
+        max_priority_fee_per_gas: tx.max_priority_fee_per_gas.map(hex),
+    }
+}
+
 #[async_trait]
 impl TransactionSigner for PrivySigner {
     fn address(&self) -> String {
@@ -38,14 +238,25 @@ impl TransactionSigner for PrivySigner {
         self.session.pubkey.clone()
     }
 
+    /// users without a wallet on a chain have an empty address for it
+    fn addresses(&self) -> HashMap<String, String> {
+        [
+            ("solana", &self.session.pubkey),
+            ("evm", &self.session.wallet_address),
+        ]
+        .into_iter()
+        .filter(|(_, address)| !address.is_empty())
+        .map(|(chain, address)| (chain.to_string(), address.clone()))
+        .collect()
+    }
+
     #[cfg(feature = "solana")]
     async fn sign_and_send_solana_transaction(
         &self,
         tx: &mut solana_sdk::transaction::VersionedTransaction,
     ) -> Result<String> {
-        tx.message
-            .set_recent_blockhash(BLOCKHASH_CACHE.get_blockhash().await?);
-
+        self.check_policy().await?;
+        prepare_transaction(tx).await?;
         let encoded_tx = transaction_to_base64(tx)?;
 
         self.privy
@@ -63,34 +274,59 @@ impl TransactionSigner for PrivySigner {
             })
     }
 
+    /// through privy's sign-only endpoint, e.g. for a Jito bundle
+    #[cfg(feature = "solana")]
+    async fn sign_solana_transaction(
+        &self,
+        tx: &mut solana_sdk::transaction::VersionedTransaction,
+    ) -> Result<()> {
+        self.check_policy().await?;
+        prepare_transaction(tx).await?;
+        let encoded_tx = transaction_to_base64(tx)?;
+
+        let signed = self
+            .privy
+            .sign_solana_transaction(self.pubkey(), encoded_tx)
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!("Failed to sign solana transaction: {}", e)
+            })?;
+        let signed: solana_sdk::transaction::VersionedTransaction =
+            bincode::deserialize(&BASE64_STANDARD.decode(signed)?)?;
+        // only the signatures may change, anything else isn't what the
+        // caller asked to sign
+        if signed.message != tx.message {
+            return Err(anyhow::anyhow!(
+                "Privy returned a different transaction than it was asked \
+                 to sign"
+            ));
+        }
+        *tx = signed;
+        Ok(())
+    }
+
     #[cfg(feature = "evm")]
     async fn sign_and_send_evm_transaction(
         &self,
         tx: alloy::rpc::types::TransactionRequest,
     ) -> Result<String> {
-        let caip2 =
-            tx.chain_id.map_or(Caip2::ARBITRUM.to_string(), |chain_id| {
-                Caip2::from_chain_id(chain_id).to_string()
-            });
-        self.privy
-            .execute_evm_transaction(
+        self.check_policy().await?;
+        // privy rejections are kept as they are so callers can tell them
+        // apart from failed transactions
+        Ok(self
+            .privy
+            .send_evm_transaction(
                 self.address(),
-                serde_json::to_value(tx)?,
-                caip2,
+                &to_privy_transaction(&tx, self.address()),
             )
-            .await
-            .map_err(|e| {
-                anyhow::anyhow!(
-                    "Failed to sign and send evm transaction: {}",
-                    e
-                )
-            })
+            .await?)
     }
 
     async fn sign_and_send_encoded_solana_transaction(
         &self,
         encoded_transaction: String,
     ) -> Result<String> {
+        self.check_policy().await?;
         self.privy
             .execute_solana_transaction(
                 self.pubkey(),
@@ -110,6 +346,7 @@ impl TransactionSigner for PrivySigner {
         &self,
         tx: serde_json::Value,
     ) -> Result<String> {
+        self.check_policy().await?;
         let caip2 = match tx["chain_id"].as_u64() {
             Some(chain_id) => Caip2::from_chain_id(chain_id),
             None => {
@@ -128,4 +365,85 @@ impl TransactionSigner for PrivySigner {
                 )
             })
     }
+
+    async fn sign_evm_typed_data(
+        &self,
+        typed_data: serde_json::Value,
+        chain_id: u64,
+    ) -> Result<String> {
+        self.check_policy().await?;
+        Ok(self
+            .privy
+            .sign_typed_data(
+                self.address(),
+                typed_data,
+                Caip2::from_chain_id(chain_id).to_string(),
+            )
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use privy::config::PrivyConfig;
+
+    fn session() -> UserSession {
+        UserSession {
+            user_id: "user".to_string(),
+            session_id: "session".to_string(),
+            wallet_address: "0xAbC0000000000000000000000000000000000001"
+                .to_string(),
+            pubkey: "11111111111111111111111111111112".to_string(),
+        }
+    }
+
+    fn privy() -> Arc<Privy> {
+        Arc::new(Privy::new(PrivyConfig {
+            app_id: String::new(),
+            app_secret: String::new(),
+            verification_key: String::new(),
+        }))
+    }
+
+    #[test]
+    fn test_parse_wallet_policy() {
+        assert_eq!("any".parse::<WalletPolicy>().unwrap(), WalletPolicy::Any);
+        assert_eq!(
+            "delegated".parse::<WalletPolicy>().unwrap(),
+            WalletPolicy::Delegated
+        );
+        assert_eq!(
+            "allowlist:0xABC,So1ana, ".parse::<WalletPolicy>().unwrap(),
+            WalletPolicy::Allowlist(HashSet::from([
+                "0xabc".to_string(),
+                "So1ana".to_string(),
+            ]))
+        );
+        assert!("everyone".parse::<WalletPolicy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_allowlist_rejects_unlisted_wallet() {
+        let policy = WalletPolicy::Allowlist(HashSet::from([
+            "0xabc0000000000000000000000000000000000001".to_string(),
+        ]));
+        // the solana wallet of the session isn't listed, so signing fails
+        // before anything reaches privy
+        let signer = PrivySigner::with_policy(privy(), session(), policy);
+        let err = signer
+            .sign_evm_typed_data(serde_json::json!({}), 8453)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("11111111111111111111111111111112"));
+
+        let policy = format!(
+            "allowlist:0xABC0000000000000000000000000000000000001,{}",
+            session().pubkey
+        )
+        .parse::<WalletPolicy>()
+        .unwrap();
+        let signer = PrivySigner::with_policy(privy(), session(), policy);
+        assert!(signer.check_policy().await.is_ok());
+    }
 }
@@ -1,25 +1,151 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use solana_sdk::signature::Keypair;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair};
 use solana_sdk::signer::Signer;
+use solana_sdk::transaction::{Transaction, VersionedTransaction};
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::solana::transaction::send_tx;
+use crate::solana::transaction::broadcast_tx;
+use base64::prelude::{Engine, BASE64_STANDARD};
 use blockhash_cache::BLOCKHASH_CACHE;
 
-use super::TransactionSigner;
+use super::{co_sign, TransactionSigner};
 
 pub struct LocalSolanaSigner {
     keypair: Arc<Keypair>,
+    /// sends through this client instead of jito and the public RPC, e.g.
+    /// for a local validator
+    rpc_client: Option<Arc<RpcClient>>,
 }
 
 impl LocalSolanaSigner {
     pub fn new(private_key: String) -> Self {
         let keypair = Keypair::from_base58_string(&private_key);
+        Self::from_keypair(keypair)
+    }
+
+    pub fn from_keypair(keypair: Keypair) -> Self {
         Self {
             keypair: Arc::new(keypair),
+            rpc_client: None,
+        }
+    }
+
+    /// a keypair file as written by `solana-keygen`
+    pub fn from_file(path: &str) -> Result<Self> {
+        let keypair = read_keypair_file(path).map_err(|e| {
+            anyhow!("Failed to read keypair file {}: {}", path, e)
+        })?;
+        Ok(Self::from_keypair(keypair))
+    }
+
+    /// a base58 secret key in the env var `var`
+    pub fn from_env(var: &str) -> Result<Self> {
+        let secret =
+            std::env::var(var).map_err(|_| anyhow!("{} is not set", var))?;
+        let bytes = bs58::decode(secret.trim())
+            .into_vec()
+            .map_err(|e| anyhow!("{} is not valid base58: {}", var, e))?;
+        let keypair = Keypair::from_bytes(&bytes)
+            .map_err(|e| anyhow!("{} is not a valid keypair: {}", var, e))?;
+        Ok(Self::from_keypair(keypair))
+    }
+
+    /// `SOLANA_KEYPAIR_PATH` if set, otherwise `SOLANA_PRIVATE_KEY`
+    pub fn from_env_config() -> Result<Self> {
+        match std::env::var("SOLANA_KEYPAIR_PATH") {
+            Ok(path) => Self::from_file(&path),
+            Err(_) => Self::from_env("SOLANA_PRIVATE_KEY"),
         }
     }
+
+    pub fn with_rpc_client(mut self, rpc_client: Arc<RpcClient>) -> Self {
+        self.rpc_client = Some(rpc_client);
+        self
+    }
+
+    /// sets the blockhash and signs at the signer's position among the
+    /// required signers
+    pub async fn sign_solana_transaction(
+        &self,
+        tx: &mut VersionedTransaction,
+    ) -> Result<()> {
+        let position = prepare_transaction(
+            self.rpc_client.as_ref(),
+            tx,
+            &self.keypair.pubkey(),
+        )
+        .await?;
+        tx.signatures[position] =
+            self.keypair.sign_message(&tx.message.serialize());
+        Ok(())
+    }
+
+    pub async fn sign_legacy_transaction(
+        &self,
+        tx: Transaction,
+    ) -> Result<VersionedTransaction> {
+        let mut tx = VersionedTransaction::from(tx);
+        self.sign_solana_transaction(&mut tx).await?;
+        Ok(tx)
+    }
+
+    async fn send(&self, tx: &VersionedTransaction) -> Result<String> {
+        send_transaction(self.rpc_client.as_ref(), tx).await
+    }
+}
+
+/// sets a recent blockhash unless the transaction uses a durable nonce,
+/// clears the signatures, signs with the co-signers of the context and
+/// returns the position of `signer` among the required signers
+pub(super) async fn prepare_transaction(
+    rpc_client: Option<&Arc<RpcClient>>,
+    tx: &mut VersionedTransaction,
+    signer: &Pubkey,
+) -> Result<usize> {
+    if !tx.uses_durable_nonce() {
+        let recent_blockhash = match rpc_client {
+            Some(rpc_client) => rpc_client.get_latest_blockhash().await?,
+            None => BLOCKHASH_CACHE.get_blockhash().await?,
+        };
+        tx.message.set_recent_blockhash(recent_blockhash);
+    }
+
+    let num_signers = tx.message.header().num_required_signatures as usize;
+    let position = tx.message.static_account_keys()[..num_signers]
+        .iter()
+        .position(|key| key == signer)
+        .ok_or_else(|| {
+            anyhow!("{} is not a signer of the transaction", signer)
+        })?;
+    tx.signatures = vec![Default::default(); num_signers];
+    co_sign(tx)?;
+    Ok(position)
+}
+
+/// sends through `rpc_client` if set, otherwise through jito and the
+/// public RPC
+pub(super) async fn send_transaction(
+    rpc_client: Option<&Arc<RpcClient>>,
+    tx: &VersionedTransaction,
+) -> Result<String> {
+    match rpc_client {
+        Some(rpc_client) => Ok(rpc_client
+            .send_transaction_with_config(
+                tx,
+                RpcSendTransactionConfig {
+                    skip_preflight: true,
+                    ..RpcSendTransactionConfig::default()
+                },
+            )
+            .await?
+            .to_string()),
+        None => broadcast_tx(tx).await,
+    }
 }
 
 #[async_trait]
@@ -34,22 +160,149 @@ impl TransactionSigner for LocalSolanaSigner {
         self.keypair.pubkey().to_string()
     }
 
+    fn addresses(&self) -> HashMap<String, String> {
+        HashMap::from([("solana".to_string(), self.pubkey())])
+    }
+
     async fn sign_and_send_solana_transaction(
         &self,
-        tx: &mut solana_sdk::transaction::VersionedTransaction,
+        tx: &mut VersionedTransaction,
+    ) -> Result<String> {
+        self.sign_solana_transaction(tx).await?;
+        self.send(tx).await
+    }
+
+    async fn sign_solana_transaction(
+        &self,
+        tx: &mut VersionedTransaction,
+    ) -> Result<()> {
+        LocalSolanaSigner::sign_solana_transaction(self, tx).await
+    }
+
+    /// base64 bincode, legacy transactions deserialize as versioned ones
+    async fn sign_and_send_encoded_solana_transaction(
+        &self,
+        tx: String,
     ) -> Result<String> {
-        let recent_blockhash = BLOCKHASH_CACHE.get_blockhash().await?;
-        let mut message = tx.message.clone();
-        message.set_recent_blockhash(recent_blockhash);
+        let mut tx: VersionedTransaction =
+            bincode::deserialize(&BASE64_STANDARD.decode(tx)?)?;
+        self.sign_and_send_solana_transaction(&mut tx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::SignerContext;
+    use crate::solana::transfer::create_transfer_sol_tx;
+    use crate::solana::util::{
+        execute_solana_transaction_with_options, ExecuteOptions,
+    };
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::system_instruction;
+
+    fn mock_signer() -> (LocalSolanaSigner, Keypair) {
+        let keypair = Keypair::new();
+        let signer =
+            LocalSolanaSigner::from_keypair(keypair.insecure_clone())
+                .with_rpc_client(Arc::new(RpcClient::new_mock(
+                    "succeeds".to_string(),
+                )));
+        (signer, keypair)
+    }
+
+    #[tokio::test]
+    async fn test_sign_legacy_transaction() {
+        let (signer, keypair) = mock_signer();
+        let tx = Transaction::new_with_payer(
+            &[system_instruction::transfer(
+                &keypair.pubkey(),
+                &Pubkey::new_unique(),
+                1000,
+            )],
+            Some(&keypair.pubkey()),
+        );
+        let tx = signer.sign_legacy_transaction(tx).await.unwrap();
+        assert!(tx.verify_with_results().iter().all(|ok| *ok));
+    }
 
-        // Get the message data and sign it directly with the keypair
-        let message_bytes = message.serialize();
-        let signature = self.keypair.sign_message(&message_bytes);
+    #[tokio::test]
+    async fn test_co_signs_after_setting_the_blockhash() {
+        let (signer, keypair) = mock_signer();
+        let new_account = Arc::new(Keypair::new());
+        let tx = Transaction::new_with_payer(
+            &[system_instruction::create_account(
+                &keypair.pubkey(),
+                &new_account.pubkey(),
+                1_000_000,
+                0,
+                &Pubkey::new_unique(),
+            )],
+            Some(&keypair.pubkey()),
+        );
+
+        let tx = SignerContext::with_co_signers(
+            vec![new_account],
+            signer.sign_legacy_transaction(tx),
+        )
+        .await
+        .unwrap();
+        assert!(tx.verify_with_results().iter().all(|ok| *ok));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_foreign_transaction() {
+        let (signer, _) = mock_signer();
+        let other = Pubkey::new_unique();
+        let tx = Transaction::new_with_payer(
+            &[system_instruction::transfer(&other, &other, 1000)],
+            Some(&other),
+        );
+        assert!(signer.sign_legacy_transaction(tx).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transfer_sol_with_local_signer() {
+        let (signer, _) = mock_signer();
+        let signer: Arc<dyn TransactionSigner> = Arc::new(signer);
+        let to = Pubkey::new_unique();
+        let signature = SignerContext::with_signer(signer, async move {
+            execute_solana_transaction_with_options(
+                move |owner| async move {
+                    create_transfer_sol_tx(&to, 1000, &owner).await
+                },
+                // only the signer talks to the mock RPC, the pre-flight
+                // simulation would go to SOLANA_RPC_URL
+                ExecuteOptions {
+                    skip_simulation: true,
+                    ..Default::default()
+                },
+            )
+            .await
+        })
+        .await
+        .unwrap()
+        .signature;
+        // the mock RPC echoes the first signature of the sent transaction
+        assert_ne!(
+            signature,
+            solana_sdk::signature::Signature::default().to_string()
+        );
+    }
 
-        // Update transaction with the new message and signature
-        tx.message = message;
-        tx.signatures = vec![signature];
+    #[test]
+    fn test_from_env() {
+        let keypair = Keypair::new();
+        std::env::set_var(
+            "TEST_LOCAL_SIGNER_KEY",
+            keypair.to_base58_string(),
+        );
+        let signer =
+            LocalSolanaSigner::from_env("TEST_LOCAL_SIGNER_KEY").unwrap();
+        assert_eq!(signer.pubkey(), keypair.pubkey().to_string());
 
-        send_tx(tx).await
+        std::env::set_var("TEST_LOCAL_SIGNER_KEY", "not a key");
+        let signer = LocalSolanaSigner::from_env("TEST_LOCAL_SIGNER_KEY");
+        assert!(signer.is_err());
     }
 }
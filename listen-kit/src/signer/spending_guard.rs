@@ -0,0 +1,1619 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
+use solana_sdk::hash::hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction::SystemInstruction;
+use solana_sdk::transaction::VersionedTransaction;
+use spl_token::instruction::TokenInstruction;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use super::TransactionSigner;
+use crate::solana::constants::{
+    ASSOCIATED_TOKEN_PROGRAM, PUMP_BUY_METHOD, PUMP_FUN_PROGRAM,
+    PUMP_SELL_METHOD, SYSTEM_PROGRAM_ID, TOKEN_PROGRAM, WSOL,
+};
+
+pub const JUPITER_V6_PROGRAM: &str =
+    "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+pub const TOKEN_2022_PROGRAM: &str =
+    "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+pub const MEMO_PROGRAM: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+
+/// `transfer(address,uint256)` and `transferFrom(address,address,uint256)`
+const ERC20_TRANSFER: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+const ERC20_TRANSFER_FROM: [u8; 4] = [0x23, 0xb8, 0x72, 0xdd];
+
+/// in_amount, quoted_out_amount, slippage_bps and platform_fee_bps close
+/// every jupiter route instruction
+const JUPITER_ROUTE_TAIL: usize = 8 + 8 + 2 + 1;
+
+/// Spending ceilings of a wallet, in USD
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpendingLimits {
+    pub max_per_transaction_usd: f64,
+    pub max_per_window_usd: f64,
+    pub window_secs: u64,
+    /// transactions the guard can't value (unknown programs, unpriced
+    /// tokens) allowed per window
+    pub max_unknown_per_window: u32,
+}
+
+impl Default for SpendingLimits {
+    fn default() -> Self {
+        Self {
+            max_per_transaction_usd: 1_000.,
+            max_per_window_usd: 5_000.,
+            window_secs: 60 * 60,
+            max_unknown_per_window: 3,
+        }
+    }
+}
+
+/// What a single instruction can take out of the wallet
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outflow {
+    Lamports(u64),
+    Token {
+        mint: Pubkey,
+        amount: u64,
+    },
+    /// unchecked token transfers only name the source account
+    TokenAccount {
+        account: Pubkey,
+        amount: u64,
+    },
+    /// a token account closed into someone else's wallet takes its
+    /// lamports, rent and wrapped SOL, along; `wrapped` of them were moved
+    /// into it earlier in the same transaction
+    ClosedAccount {
+        account: Pubkey,
+        wrapped: u64,
+    },
+    Unknown {
+        program_id: Pubkey,
+    },
+}
+
+/// What an evm transaction can take out of the wallet, in base units
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvmOutflow {
+    Native(u128),
+    /// `token` is the contract address, lowercase
+    Erc20 {
+        token: String,
+        amount: u128,
+    },
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpendRecord {
+    /// unix seconds
+    pub timestamp: u64,
+    pub usd: f64,
+    /// the transaction couldn't be fully valued
+    pub unknown: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpendingLimitExceeded {
+    Transaction { usd: f64, limit: f64 },
+    Window { spent: f64, usd: f64, limit: f64 },
+    Unknown { count: u32, limit: u32 },
+}
+
+impl fmt::Display for SpendingLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transaction { usd, limit } => write!(
+                f,
+                "Spending limit: ${:.2} exceeds the ${:.2} per transaction \
+                 limit",
+                usd, limit
+            ),
+            Self::Window { spent, usd, limit } => write!(
+                f,
+                "Spending limit: ${:.2} on top of ${:.2} already spent \
+                 exceeds the ${:.2} limit of the window",
+                usd, spent, limit
+            ),
+            Self::Unknown { count, limit } => write!(
+                f,
+                "Spending limit: {} of {} transactions that can't be valued \
+                 already used in the window",
+                count, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SpendingLimitExceeded {}
+
+/// whether `spend` fits the limits given the spends of the window
+pub fn check_spend(
+    limits: &SpendingLimits,
+    history: &[SpendRecord],
+    spend: &SpendRecord,
+) -> Result<(), SpendingLimitExceeded> {
+    let since = spend.timestamp.saturating_sub(limits.window_secs);
+    let window = history.iter().filter(|record| record.timestamp > since);
+
+    if spend.usd > limits.max_per_transaction_usd {
+        return Err(SpendingLimitExceeded::Transaction {
+            usd: spend.usd,
+            limit: limits.max_per_transaction_usd,
+        });
+    }
+    let spent: f64 = window.clone().map(|record| record.usd).sum();
+    if spent + spend.usd > limits.max_per_window_usd {
+        return Err(SpendingLimitExceeded::Window {
+            spent,
+            usd: spend.usd,
+            limit: limits.max_per_window_usd,
+        });
+    }
+    if spend.unknown {
+        let count = window.filter(|record| record.unknown).count() as u32;
+        if count >= limits.max_unknown_per_window {
+            return Err(SpendingLimitExceeded::Unknown {
+                count,
+                limit: limits.max_unknown_per_window,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn anchor_discriminator(name: &str) -> [u8; 8] {
+    let hash = hash(format!("global:{}", name).as_bytes()).to_bytes();
+    hash[..8].try_into().expect("8 bytes")
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// The accounts of the transaction in the order instructions index them,
+/// the static ones followed by the writable and then the readonly ones of
+/// its address lookup tables. Only the static ones if a table can't be
+/// loaded, the instructions that use it then decode as unknown
+pub async fn account_keys(
+    tx: &VersionedTransaction,
+    pricer: &dyn OutflowPricer,
+) -> Vec<Pubkey> {
+    let mut keys = tx.message.static_account_keys().to_vec();
+    let Some(lookups) = tx.message.address_table_lookups() else {
+        return keys;
+    };
+    let mut writable = vec![];
+    let mut readonly = vec![];
+    for lookup in lookups {
+        let table = match pricer.lookup_table(&lookup.account_key).await {
+            Ok(Some(table)) => table,
+            Ok(None) => return keys,
+            Err(e) => {
+                tracing::warn!(
+                    table = %lookup.account_key,
+                    %e,
+                    "failed to load the lookup table"
+                );
+                return keys;
+            }
+        };
+        let resolve = |indexes: &[u8]| -> Option<Vec<Pubkey>> {
+            indexes
+                .iter()
+                .map(|i| table.get(*i as usize).copied())
+                .collect()
+        };
+        match (
+            resolve(&lookup.writable_indexes),
+            resolve(&lookup.readonly_indexes),
+        ) {
+            (Some(w), Some(r)) => {
+                writable.extend(w);
+                readonly.extend(r);
+            }
+            _ => return keys,
+        }
+    }
+    keys.extend(writable);
+    keys.extend(readonly);
+    keys
+}
+
+/// the outflows of every instruction of the transaction, with `keys` as
+/// given by `account_keys`; an instruction with an account that isn't
+/// among them is unknown
+pub fn decode_outflows(
+    tx: &VersionedTransaction,
+    keys: &[Pubkey],
+    owner: &Pubkey,
+) -> Vec<Outflow> {
+    let wsol = Pubkey::from_str(WSOL).expect("wsol");
+    // wrapping SOL into the owner's own account moves nothing out until
+    // the account is closed into another wallet
+    let wsol_account =
+        spl_associated_token_account::get_associated_token_address(
+            owner, &wsol,
+        );
+    let mut wrapped = 0;
+
+    let mut outflows = vec![];
+    for ix in tx.message.instructions() {
+        let program_id = keys[ix.program_id_index as usize];
+        let accounts: Option<Vec<Pubkey>> = ix
+            .accounts
+            .iter()
+            .map(|i| keys.get(*i as usize).copied())
+            .collect();
+        let decoded = accounts.and_then(|accounts| {
+            decode_instruction(
+                &program_id,
+                &accounts,
+                &ix.data,
+                owner,
+                &wsol_account,
+                &mut wrapped,
+            )
+        });
+        outflows.extend(
+            decoded.unwrap_or_else(|| vec![Outflow::Unknown { program_id }]),
+        );
+    }
+    outflows
+}
+
+/// `None` for instructions the guard doesn't understand, `wrapped` counts
+/// the lamports moved into `wsol_account` so far
+fn decode_instruction(
+    program_id: &Pubkey,
+    accounts: &[Pubkey],
+    data: &[u8],
+    owner: &Pubkey,
+    wsol_account: &Pubkey,
+    wrapped: &mut u64,
+) -> Option<Vec<Outflow>> {
+    let program = program_id.to_string();
+    match program.as_str() {
+        SYSTEM_PROGRAM_ID => {
+            let (lamports, to) = match bincode::deserialize(data).ok()? {
+                SystemInstruction::Transfer { lamports } => {
+                    (lamports, accounts.get(1)?)
+                }
+                SystemInstruction::TransferWithSeed { lamports, .. } => {
+                    (lamports, accounts.get(2)?)
+                }
+                SystemInstruction::CreateAccount { lamports, .. }
+                | SystemInstruction::CreateAccountWithSeed {
+                    lamports, ..
+                } => (lamports, accounts.get(1)?),
+                // prepended to durable nonce transactions, moves nothing
+                SystemInstruction::AdvanceNonceAccount => {
+                    return Some(vec![])
+                }
+                _ => return None,
+            };
+            if to == wsol_account {
+                *wrapped += lamports;
+                return Some(vec![]);
+            }
+            Some(vec![Outflow::Lamports(lamports)])
+        }
+        TOKEN_PROGRAM | TOKEN_2022_PROGRAM => {
+            match TokenInstruction::unpack(data).ok()? {
+                TokenInstruction::Transfer { amount } => {
+                    Some(vec![Outflow::TokenAccount {
+                        account: *accounts.first()?,
+                        amount,
+                    }])
+                }
+                TokenInstruction::TransferChecked { amount, .. } => {
+                    Some(vec![Outflow::Token {
+                        mint: *accounts.get(1)?,
+                        amount,
+                    }])
+                }
+                TokenInstruction::CloseAccount => {
+                    let (account, destination) =
+                        (accounts.first()?, accounts.get(1)?);
+                    if destination == owner {
+                        return Some(vec![]);
+                    }
+                    let wrapped = if account == wsol_account {
+                        std::mem::take(wrapped)
+                    } else {
+                        0
+                    };
+                    Some(vec![Outflow::ClosedAccount {
+                        account: *account,
+                        wrapped,
+                    }])
+                }
+                // rent of new accounts is paid by the system program
+                TokenInstruction::SyncNative
+                | TokenInstruction::InitializeAccount
+                | TokenInstruction::InitializeAccount2 { .. }
+                | TokenInstruction::InitializeAccount3 { .. } => Some(vec![]),
+                _ => None,
+            }
+        }
+        ASSOCIATED_TOKEN_PROGRAM | MEMO_PROGRAM => Some(vec![]),
+        PUMP_FUN_PROGRAM => {
+            let method: [u8; 8] = data.get(..8)?.try_into().ok()?;
+            if method == PUMP_BUY_METHOD {
+                // token amount, max SOL cost
+                Some(vec![Outflow::Lamports(read_u64(data, 16)?)])
+            } else if method == PUMP_SELL_METHOD {
+                Some(vec![Outflow::Token {
+                    mint: *accounts.get(2)?,
+                    amount: read_u64(data, 8)?,
+                }])
+            } else {
+                None
+            }
+        }
+        JUPITER_V6_PROGRAM => {
+            let method: [u8; 8] = data.get(..8)?.try_into().ok()?;
+            let in_amount =
+                read_u64(data, data.len().checked_sub(JUPITER_ROUTE_TAIL)?)?;
+            if method == anchor_discriminator("route") {
+                Some(vec![Outflow::TokenAccount {
+                    account: *accounts.get(2)?,
+                    amount: in_amount,
+                }])
+            } else if method == anchor_discriminator("shared_accounts_route")
+            {
+                Some(vec![Outflow::Token {
+                    mint: *accounts.get(7)?,
+                    amount: in_amount,
+                }])
+            } else {
+                None
+            }
+        }
+        _ if *program_id == solana_sdk::compute_budget::id() => Some(vec![]),
+        _ => None,
+    }
+}
+
+/// The native `value` of an evm transaction and the tokens its calldata
+/// moves. The token of an ERC20 transfer is the contract called, calls
+/// that do anything else, approvals and swaps included, are unknown
+pub fn decode_evm_outflows(
+    to: Option<&str>,
+    value: u128,
+    data: &[u8],
+) -> Vec<EvmOutflow> {
+    let mut outflows = vec![];
+    if value > 0 {
+        outflows.push(EvmOutflow::Native(value));
+    }
+    if data.is_empty() {
+        return outflows;
+    }
+    let amount_offset = match data.get(..4) {
+        Some(method) if method == ERC20_TRANSFER => Some(4 + 32),
+        Some(method) if method == ERC20_TRANSFER_FROM => Some(4 + 64),
+        _ => None,
+    };
+    let transfer = amount_offset.and_then(|offset| {
+        let word = data.get(offset..offset + 32)?;
+        // no balance needs more than 128 bits
+        if word[..16].iter().any(|byte| *byte != 0) {
+            return None;
+        }
+        Some(EvmOutflow::Erc20 {
+            token: to?.to_lowercase(),
+            amount: u128::from_be_bytes(word[16..].try_into().ok()?),
+        })
+    });
+    outflows.push(transfer.unwrap_or(EvmOutflow::Unknown));
+    outflows
+}
+
+/// a quantity of a json evm transaction, a hex or decimal string or a
+/// number
+fn json_quantity(value: &serde_json::Value) -> Option<u128> {
+    match value {
+        serde_json::Value::Number(n) => n.as_u64().map(u128::from),
+        serde_json::Value::String(s) => match s.strip_prefix("0x") {
+            Some(hex) if hex.is_empty() => Some(0),
+            Some(hex) => u128::from_str_radix(hex, 16).ok(),
+            None => s.parse().ok(),
+        },
+        _ => None,
+    }
+}
+
+/// `decode_evm_outflows` of a json evm transaction as the cross chain
+/// tools send it, and its chain id
+pub fn decode_json_evm_outflows(
+    tx: &serde_json::Value,
+) -> (Option<u64>, Vec<EvmOutflow>) {
+    let chain_id = tx["chain_id"].as_u64();
+    let value = match &tx["value"] {
+        serde_json::Value::Null => Some(0),
+        value => json_quantity(value),
+    };
+    let data = match tx["data"].as_str() {
+        Some(data) => hex::decode(data.trim_start_matches("0x")).ok(),
+        None => Some(vec![]),
+    };
+    let outflows = match (value, data) {
+        (Some(value), Some(data)) => {
+            decode_evm_outflows(tx["to"].as_str(), value, &data)
+        }
+        _ => vec![EvmOutflow::Unknown],
+    };
+    (chain_id, outflows)
+}
+
+/// `decode_evm_outflows` of a transaction request, and its chain id
+#[cfg(feature = "evm")]
+pub fn decode_evm_request_outflows(
+    tx: &alloy::rpc::types::TransactionRequest,
+) -> (Option<u64>, Vec<EvmOutflow>) {
+    let to = tx.to.and_then(|to| to.to().map(|to| to.to_string()));
+    let value = tx.value.unwrap_or_default().try_into().ok();
+    let data = tx.input.input().map(|data| data.to_vec());
+    let outflows = match value {
+        Some(value) => decode_evm_outflows(
+            to.as_deref(),
+            value,
+            &data.unwrap_or_default(),
+        ),
+        None => vec![EvmOutflow::Unknown],
+    };
+    (tx.chain_id, outflows)
+}
+
+/// USD price of one whole token
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenPrice {
+    pub price: f64,
+    pub decimals: u8,
+}
+
+#[async_trait]
+pub trait OutflowPricer: Send + Sync {
+    async fn token_price(&self, mint: &Pubkey) -> Result<Option<TokenPrice>>;
+
+    async fn token_account_mint(
+        &self,
+        account: &Pubkey,
+    ) -> Result<Option<Pubkey>>;
+
+    /// the balance of `account` before the transaction, 0 if there is none
+    async fn account_lamports(&self, account: &Pubkey) -> Result<u64>;
+
+    /// the addresses of an address lookup table, `None` if there is none
+    async fn lookup_table(
+        &self,
+        table: &Pubkey,
+    ) -> Result<Option<Vec<Pubkey>>>;
+
+    /// the price of the native token of `chain_id` when `token` is `None`,
+    /// of the ERC20 at `token` otherwise
+    async fn evm_token_price(
+        &self,
+        chain_id: u64,
+        token: Option<&str>,
+    ) -> Result<Option<TokenPrice>>;
+}
+
+/// USD value of the outflows, and whether any part of it couldn't be
+/// valued
+pub async fn value_outflows(
+    outflows: &[Outflow],
+    pricer: &dyn OutflowPricer,
+) -> (f64, bool) {
+    let mut usd = 0.;
+    let mut unknown = false;
+    for outflow in outflows {
+        let (mint, amount) = match outflow {
+            Outflow::Lamports(lamports) => {
+                (Pubkey::from_str(WSOL).expect("wsol"), *lamports)
+            }
+            Outflow::Token { mint, amount } => (*mint, *amount),
+            Outflow::ClosedAccount { account, wrapped } => {
+                match pricer.account_lamports(account).await {
+                    Ok(lamports) => (
+                        Pubkey::from_str(WSOL).expect("wsol"),
+                        lamports + wrapped,
+                    ),
+                    Err(_) => {
+                        unknown = true;
+                        continue;
+                    }
+                }
+            }
+            Outflow::TokenAccount { account, amount } => {
+                match pricer.token_account_mint(account).await {
+                    Ok(Some(mint)) => (mint, *amount),
+                    _ => {
+                        unknown = true;
+                        continue;
+                    }
+                }
+            }
+            Outflow::Unknown { .. } => {
+                unknown = true;
+                continue;
+            }
+        };
+        match pricer.token_price(&mint).await {
+            Ok(Some(price)) => {
+                usd += amount as f64 / 10f64.powi(price.decimals as i32)
+                    * price.price;
+            }
+            _ => unknown = true,
+        }
+    }
+    (usd, unknown)
+}
+
+/// `value_outflows` of an evm transaction on `chain_id`
+pub async fn value_evm_outflows(
+    chain_id: u64,
+    outflows: &[EvmOutflow],
+    pricer: &dyn OutflowPricer,
+) -> (f64, bool) {
+    let mut usd = 0.;
+    let mut unknown = false;
+    for outflow in outflows {
+        let (token, amount) = match outflow {
+            EvmOutflow::Native(wei) => (None, *wei),
+            EvmOutflow::Erc20 { token, amount } => {
+                (Some(token.as_str()), *amount)
+            }
+            EvmOutflow::Unknown => {
+                unknown = true;
+                continue;
+            }
+        };
+        match pricer.evm_token_price(chain_id, token).await {
+            Ok(Some(price)) => {
+                usd += amount as f64 / 10f64.powi(price.decimals as i32)
+                    * price.price;
+            }
+            _ => unknown = true,
+        }
+    }
+    (usd, unknown)
+}
+
+/// Limits and the spends of the rolling window, per wallet
+#[async_trait]
+pub trait SpendingStore: Send + Sync {
+    async fn get_limits(
+        &self,
+        wallet: &str,
+    ) -> Result<Option<SpendingLimits>>;
+
+    async fn set_limits(
+        &self,
+        wallet: &str,
+        limits: &SpendingLimits,
+    ) -> Result<()>;
+
+    /// spends after `since` (unix seconds)
+    async fn get_spends(
+        &self,
+        wallet: &str,
+        since: u64,
+    ) -> Result<Vec<SpendRecord>>;
+
+    /// records `spend` if it fits `limits` given the spends of its window,
+    /// fails with `SpendingLimitExceeded` otherwise. The check and the
+    /// record are one step, so concurrent spends of a wallet through any
+    /// guard sharing the store can't both fit
+    async fn admit_spend(
+        &self,
+        wallet: &str,
+        limits: &SpendingLimits,
+        spend: &SpendRecord,
+    ) -> Result<()>;
+}
+
+#[derive(Default)]
+pub struct MemorySpendingStore {
+    limits: Mutex<HashMap<String, SpendingLimits>>,
+    spends: Mutex<HashMap<String, Vec<SpendRecord>>>,
+}
+
+#[async_trait]
+impl SpendingStore for MemorySpendingStore {
+    async fn get_limits(
+        &self,
+        wallet: &str,
+    ) -> Result<Option<SpendingLimits>> {
+        Ok(self.limits.lock().unwrap().get(wallet).copied())
+    }
+
+    async fn set_limits(
+        &self,
+        wallet: &str,
+        limits: &SpendingLimits,
+    ) -> Result<()> {
+        self.limits
+            .lock()
+            .unwrap()
+            .insert(wallet.to_string(), *limits);
+        Ok(())
+    }
+
+    async fn get_spends(
+        &self,
+        wallet: &str,
+        since: u64,
+    ) -> Result<Vec<SpendRecord>> {
+        Ok(self
+            .spends
+            .lock()
+            .unwrap()
+            .get(wallet)
+            .map(|spends| {
+                spends
+                    .iter()
+                    .filter(|spend| spend.timestamp > since)
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn admit_spend(
+        &self,
+        wallet: &str,
+        limits: &SpendingLimits,
+        spend: &SpendRecord,
+    ) -> Result<()> {
+        let mut spends = self.spends.lock().unwrap();
+        let spends = spends.entry(wallet.to_string()).or_default();
+        check_spend(limits, spends, spend)?;
+        spends.push(*spend);
+        Ok(())
+    }
+}
+
+/// Rejects transactions that would take a wallet over its spending limits
+/// before the wrapped signer sees them. A spend is recorded once admitted,
+/// whether or not the transaction lands. EVM transactions are valued by
+/// their native value and ERC20 transfers, other contract calls count as
+/// unknown.
+pub struct SpendingGuard {
+    inner: Arc<dyn TransactionSigner>,
+    store: Arc<dyn SpendingStore>,
+    pricer: Arc<dyn OutflowPricer>,
+}
+
+impl SpendingGuard {
+    pub fn new(
+        inner: Arc<dyn TransactionSigner>,
+        store: Arc<dyn SpendingStore>,
+        pricer: Arc<dyn OutflowPricer>,
+    ) -> Self {
+        Self {
+            inner,
+            store,
+            pricer,
+        }
+    }
+
+    async fn admit(
+        &self,
+        wallet: &str,
+        usd: f64,
+        unknown: bool,
+    ) -> Result<()> {
+        let limits = self.store.get_limits(wallet).await?.unwrap_or_default();
+        let spend = SpendRecord {
+            timestamp: chrono::Utc::now().timestamp() as u64,
+            usd,
+            unknown,
+        };
+        let admitted = self.store.admit_spend(wallet, &limits, &spend).await;
+        if let Some(e) = admitted
+            .as_ref()
+            .err()
+            .and_then(|e| e.downcast_ref::<SpendingLimitExceeded>())
+        {
+            tracing::warn!(wallet, %e, "transaction rejected");
+        }
+        admitted
+    }
+
+    async fn admit_solana(&self, tx: &VersionedTransaction) -> Result<()> {
+        let wallet = self.inner.pubkey();
+        let keys = account_keys(tx, self.pricer.as_ref()).await;
+        let outflows =
+            decode_outflows(tx, &keys, &Pubkey::from_str(&wallet)?);
+        let (usd, unknown) =
+            value_outflows(&outflows, self.pricer.as_ref()).await;
+        self.admit(&wallet, usd, unknown).await
+    }
+
+    async fn admit_evm(
+        &self,
+        chain_id: Option<u64>,
+        outflows: &[EvmOutflow],
+    ) -> Result<()> {
+        let (usd, unknown) = value_evm_outflows(
+            chain_id.unwrap_or(super::DEFAULT_EVM_CHAIN_ID),
+            outflows,
+            self.pricer.as_ref(),
+        )
+        .await;
+        self.admit(&self.inner.address(), usd, unknown).await
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for SpendingGuard {
+    fn address(&self) -> String {
+        self.inner.address()
+    }
+
+    fn pubkey(&self) -> String {
+        self.inner.pubkey()
+    }
+
+    fn addresses(&self) -> HashMap<String, String> {
+        self.inner.addresses()
+    }
+
+    fn needs_durable_nonce(&self) -> bool {
+        self.inner.needs_durable_nonce()
+    }
+
+    async fn sign_and_send_solana_transaction(
+        &self,
+        tx: &mut VersionedTransaction,
+    ) -> Result<String> {
+        self.admit_solana(tx).await?;
+        self.inner.sign_and_send_solana_transaction(tx).await
+    }
+
+    async fn sign_solana_transaction(
+        &self,
+        tx: &mut VersionedTransaction,
+    ) -> Result<()> {
+        self.admit_solana(tx).await?;
+        self.inner.sign_solana_transaction(tx).await
+    }
+
+    #[cfg(feature = "evm")]
+    async fn sign_and_send_evm_transaction(
+        &self,
+        tx: alloy::rpc::types::TransactionRequest,
+    ) -> Result<String> {
+        let (chain_id, outflows) = decode_evm_request_outflows(&tx);
+        self.admit_evm(chain_id, &outflows).await?;
+        self.inner.sign_and_send_evm_transaction(tx).await
+    }
+
+    async fn sign_and_send_encoded_solana_transaction(
+        &self,
+        tx: String,
+    ) -> Result<String> {
+        let decoded: VersionedTransaction =
+            bincode::deserialize(&BASE64_STANDARD.decode(&tx)?)
+                .map_err(|e| anyhow!("Invalid encoded transaction: {}", e))?;
+        self.admit_solana(&decoded).await?;
+        self.inner
+            .sign_and_send_encoded_solana_transaction(tx)
+            .await
+    }
+
+    async fn sign_and_send_json_evm_transaction(
+        &self,
+        tx: serde_json::Value,
+    ) -> Result<String> {
+        let (chain_id, outflows) = decode_json_evm_outflows(&tx);
+        self.admit_evm(chain_id, &outflows).await?;
+        self.inner.sign_and_send_json_evm_transaction(tx).await
+    }
+}
+
+/// Store and pricer shared by the guards of every request
+#[derive(Clone)]
+pub struct SpendingPolicy {
+    pub store: Arc<dyn SpendingStore>,
+    pub pricer: Arc<dyn OutflowPricer>,
+}
+
+impl SpendingPolicy {
+    pub fn guard(&self, inner: Arc<dyn TransactionSigner>) -> SpendingGuard {
+        SpendingGuard::new(inner, self.store.clone(), self.pricer.clone())
+    }
+}
+
+#[cfg(feature = "http")]
+pub use redis_store::{RedisOutflowPricer, RedisSpendingStore};
+
+#[cfg(feature = "http")]
+mod redis_store {
+    use super::*;
+    use crate::solana::util::fetch_lookup_table;
+    use solana_client::nonblocking::rpc_client::RpcClient;
+
+    fn limits_key(wallet: &str) -> String {
+        format!("spending:limits:{}", wallet)
+    }
+
+    fn spends_key(wallet: &str) -> String {
+        format!("spending:window:{}", wallet)
+    }
+
+    /// bumped with every recorded spend of the wallet
+    fn version_key(wallet: &str) -> String {
+        format!("spending:version:{}", wallet)
+    }
+
+    /// the longest window anyone would configure
+    const EXPIRE_SECS: u64 = 7 * 24 * 60 * 60;
+
+    /// records the spend only if no other one was recorded since its window
+    /// was read at version ARGV[1], 0 if one was
+    const RECORD_IF_UNCHANGED: &str = r#"
+        if (redis.call('GET', KEYS[2]) or '0') ~= ARGV[1] then
+            return 0
+        end
+        redis.call('ZADD', KEYS[1], ARGV[2], ARGV[3])
+        redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', ARGV[4])
+        redis.call('EXPIRE', KEYS[1], ARGV[5])
+        redis.call('INCR', KEYS[2])
+        redis.call('EXPIRE', KEYS[2], ARGV[5])
+        return 1
+    "#;
+
+    /// Limits as json, spends in a sorted set scored by timestamp
+    pub struct RedisSpendingStore {
+        client: redis::Client,
+    }
+
+    impl RedisSpendingStore {
+        pub fn new(redis_url: &str) -> Result<Self> {
+            Ok(Self {
+                client: redis::Client::open(redis_url)?,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl SpendingStore for RedisSpendingStore {
+        async fn get_limits(
+            &self,
+            wallet: &str,
+        ) -> Result<Option<SpendingLimits>> {
+            let mut conn =
+                self.client.get_multiplexed_async_connection().await?;
+            let limits: Option<String> = redis::cmd("GET")
+                .arg(limits_key(wallet))
+                .query_async(&mut conn)
+                .await?;
+            Ok(match limits {
+                Some(limits) => Some(serde_json::from_str(&limits)?),
+                None => None,
+            })
+        }
+
+        async fn set_limits(
+            &self,
+            wallet: &str,
+            limits: &SpendingLimits,
+        ) -> Result<()> {
+            let mut conn =
+                self.client.get_multiplexed_async_connection().await?;
+            let _: () = redis::cmd("SET")
+                .arg(limits_key(wallet))
+                .arg(serde_json::to_string(limits)?)
+                .query_async(&mut conn)
+                .await?;
+            Ok(())
+        }
+
+        async fn get_spends(
+            &self,
+            wallet: &str,
+            since: u64,
+        ) -> Result<Vec<SpendRecord>> {
+            let mut conn =
+                self.client.get_multiplexed_async_connection().await?;
+            let spends: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+                .arg(spends_key(wallet))
+                .arg(format!("({}", since))
+                .arg("+inf")
+                .query_async(&mut conn)
+                .await?;
+            spends
+                .iter()
+                .map(|spend| Ok(serde_json::from_str(spend)?))
+                .collect()
+        }
+
+        /// the window is read with its version and the spend recorded only
+        /// if the version is still the same, a spend recorded in between
+        /// has the window read again
+        async fn admit_spend(
+            &self,
+            wallet: &str,
+            limits: &SpendingLimits,
+            spend: &SpendRecord,
+        ) -> Result<()> {
+            let mut conn =
+                self.client.get_multiplexed_async_connection().await?;
+            let (spends_key, version_key) =
+                (spends_key(wallet), version_key(wallet));
+            // members have to be unique for equal spends in the same second
+            let member = serde_json::to_string(&serde_json::json!({
+                "timestamp": spend.timestamp,
+                "usd": spend.usd,
+                "unknown": spend.unknown,
+                "nonce": rand::random::<u64>(),
+            }))?;
+            let script = redis::Script::new(RECORD_IF_UNCHANGED);
+            loop {
+                let (version, spends): (Option<String>, Vec<String>) =
+                    redis::pipe()
+                        .atomic()
+                        .cmd("GET")
+                        .arg(&version_key)
+                        .cmd("ZRANGEBYSCORE")
+                        .arg(&spends_key)
+                        .arg(format!(
+                            "({}",
+                            spend
+                                .timestamp
+                                .saturating_sub(limits.window_secs)
+                        ))
+                        .arg("+inf")
+                        .query_async(&mut conn)
+                        .await?;
+                let history = spends
+                    .iter()
+                    .map(|spend| Ok(serde_json::from_str(spend)?))
+                    .collect::<Result<Vec<SpendRecord>>>()?;
+                check_spend(limits, &history, spend)?;
+
+                let recorded: bool = script
+                    .key(&spends_key)
+                    .key(&version_key)
+                    .arg(version.unwrap_or_else(|| "0".to_string()))
+                    .arg(spend.timestamp)
+                    .arg(&member)
+                    .arg(spend.timestamp.saturating_sub(EXPIRE_SECS))
+                    .arg(EXPIRE_SECS)
+                    .invoke_async(&mut conn)
+                    .await?;
+                if recorded {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Prices from the indexer's redis price feed, token accounts through
+    /// the RPC
+    pub struct RedisOutflowPricer {
+        client: redis::Client,
+        rpc_client: RpcClient,
+    }
+
+    impl RedisOutflowPricer {
+        pub fn new(redis_url: &str, rpc_client: RpcClient) -> Result<Self> {
+            Ok(Self {
+                client: redis::Client::open(redis_url)?,
+                rpc_client,
+            })
+        }
+
+        async fn get_json(
+            &self,
+            key: String,
+        ) -> Result<Option<serde_json::Value>> {
+            let mut conn =
+                self.client.get_multiplexed_async_connection().await?;
+            let value: Option<String> =
+                redis::cmd("GET").arg(key).query_async(&mut conn).await?;
+            Ok(match value {
+                Some(value) => Some(serde_json::from_str(&value)?),
+                None => None,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl OutflowPricer for RedisOutflowPricer {
+        async fn token_price(
+            &self,
+            mint: &Pubkey,
+        ) -> Result<Option<TokenPrice>> {
+            let price = self
+                .get_json(format!("solana:price:{}", mint))
+                .await?
+                .and_then(|update| update["price"].as_f64());
+            let Some(price) = price else {
+                return Ok(None);
+            };
+            let decimals = if mint.to_string() == WSOL {
+                Some(9)
+            } else {
+                self.get_json(format!("solana:metadata:{}", mint))
+                    .await?
+                    .and_then(|metadata| metadata["spl"]["decimals"].as_u64())
+            };
+            let decimals = match decimals {
+                Some(decimals) => decimals as u8,
+                None => {
+                    let account = self.rpc_client.get_account(mint).await?;
+                    // decimals follow the mint authority and the supply
+                    *account
+                        .data
+                        .get(44)
+                        .ok_or_else(|| anyhow!("{} is not a mint", mint))?
+                }
+            };
+            Ok(Some(TokenPrice { price, decimals }))
+        }
+
+        async fn token_account_mint(
+            &self,
+            account: &Pubkey,
+        ) -> Result<Option<Pubkey>> {
+            let account = self.rpc_client.get_account(account).await?;
+            // the mint leads both token and token-2022 accounts
+            Ok(account
+                .data
+                .get(..32)
+                .map(|mint| Pubkey::try_from(mint).expect("32 bytes")))
+        }
+
+        async fn account_lamports(&self, account: &Pubkey) -> Result<u64> {
+            Ok(self.rpc_client.get_balance(account).await?)
+        }
+
+        async fn lookup_table(
+            &self,
+            table: &Pubkey,
+        ) -> Result<Option<Vec<Pubkey>>> {
+            fetch_lookup_table(&self.rpc_client, table).await
+        }
+
+        #[cfg(feature = "evm")]
+        async fn evm_token_price(
+            &self,
+            chain_id: u64,
+            token: Option<&str>,
+        ) -> Result<Option<TokenPrice>> {
+            use crate::evm::price::{
+                fetch_evm_token_price_and_decimals, NATIVE_TOKEN_ADDRESS,
+            };
+            let (price, decimals) = fetch_evm_token_price_and_decimals(
+                &reqwest::Client::new(),
+                chain_id,
+                token.unwrap_or(NATIVE_TOKEN_ADDRESS),
+            )
+            .await?;
+            Ok(Some(TokenPrice { price, decimals }))
+        }
+
+        /// evm transactions are only sent with the evm feature
+        #[cfg(not(feature = "evm"))]
+        async fn evm_token_price(
+            &self,
+            _chain_id: u64,
+            _token: Option<&str>,
+        ) -> Result<Option<TokenPrice>> {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::testing::{CountingSigner, FixedPricer};
+    use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+    use solana_sdk::compute_budget::ComputeBudgetInstruction;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+    use solana_sdk::message::{v0, VersionedMessage};
+    use solana_sdk::native_token::LAMPORTS_PER_SOL;
+    use solana_sdk::system_instruction;
+    use solana_sdk::transaction::Transaction;
+
+    fn make_tx(ixs: &[Instruction], payer: &Pubkey) -> VersionedTransaction {
+        Transaction::new_with_payer(ixs, Some(payer)).into()
+    }
+
+    #[test]
+    fn test_decode_transfers() {
+        let owner = Pubkey::new_unique();
+        let (to, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let source = Pubkey::new_unique();
+        let wsol_account =
+            spl_associated_token_account::get_associated_token_address(
+                &owner,
+                &Pubkey::from_str(WSOL).unwrap(),
+            );
+        let ixs = [
+            ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+            system_instruction::transfer(&owner, &to, 1_000),
+            // wrapping SOL is not an outflow
+            system_instruction::transfer(&owner, &wsol_account, 5_000),
+            spl_token::instruction::transfer(
+                &spl_token::id(),
+                &source,
+                &to,
+                &owner,
+                &[],
+                42,
+            )
+            .unwrap(),
+            spl_token::instruction::transfer_checked(
+                &spl_token::id(),
+                &source,
+                &mint,
+                &to,
+                &owner,
+                &[],
+                7,
+                6,
+            )
+            .unwrap(),
+            Instruction::new_with_bytes(
+                Pubkey::new_unique(),
+                &[1, 2, 3],
+                vec![AccountMeta::new(owner, true)],
+            ),
+        ];
+
+        let tx = make_tx(&ixs, &owner);
+        let outflows =
+            decode_outflows(&tx, tx.message.static_account_keys(), &owner);
+        assert_eq!(
+            outflows,
+            vec![
+                Outflow::Lamports(1_000),
+                Outflow::TokenAccount {
+                    account: source,
+                    amount: 42
+                },
+                Outflow::Token { mint, amount: 7 },
+                Outflow::Unknown {
+                    program_id: ixs[5].program_id
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_pump_buy() {
+        let owner = Pubkey::new_unique();
+        let mut data = PUMP_BUY_METHOD.to_vec();
+        data.extend_from_slice(&1_000_000u64.to_le_bytes());
+        data.extend_from_slice(&250_000_000u64.to_le_bytes());
+        let ix = Instruction::new_with_bytes(
+            Pubkey::from_str(PUMP_FUN_PROGRAM).unwrap(),
+            &data,
+            vec![AccountMeta::new(owner, true)],
+        );
+        let tx = make_tx(&[ix], &owner);
+        assert_eq!(
+            decode_outflows(&tx, tx.message.static_account_keys(), &owner),
+            vec![Outflow::Lamports(250_000_000)]
+        );
+    }
+
+    #[test]
+    fn test_decode_durable_nonce_transfer() {
+        let owner = Pubkey::new_unique();
+        let ixs = [
+            system_instruction::advance_nonce_account(
+                &Pubkey::new_unique(),
+                &owner,
+            ),
+            system_instruction::transfer(&owner, &Pubkey::new_unique(), 7),
+        ];
+        let tx = make_tx(&ixs, &owner);
+        assert_eq!(
+            decode_outflows(&tx, tx.message.static_account_keys(), &owner),
+            vec![Outflow::Lamports(7)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wrapping_and_closing_into_another_wallet_is_an_outflow() {
+        let signer = Arc::new(CountingSigner::new());
+        let owner = signer.pubkey;
+        let attacker = Pubkey::new_unique();
+        let wsol_account =
+            spl_associated_token_account::get_associated_token_address(
+                &owner,
+                &Pubkey::from_str(WSOL).unwrap(),
+            );
+        let close = |destination: &Pubkey| {
+            spl_token::instruction::close_account(
+                &spl_token::id(),
+                &wsol_account,
+                destination,
+                &owner,
+                &[],
+            )
+            .unwrap()
+        };
+        let ixs = [
+            system_instruction::transfer(
+                &owner,
+                &wsol_account,
+                20 * LAMPORTS_PER_SOL,
+            ),
+            spl_token::instruction::sync_native(
+                &spl_token::id(),
+                &wsol_account,
+            )
+            .unwrap(),
+            close(&attacker),
+        ];
+        let tx = make_tx(&ixs, &owner);
+        assert_eq!(
+            decode_outflows(&tx, tx.message.static_account_keys(), &owner),
+            vec![Outflow::ClosedAccount {
+                account: wsol_account,
+                wrapped: 20 * LAMPORTS_PER_SOL,
+            }]
+        );
+        // unwrapping back into the wallet still moves nothing out
+        let tx = make_tx(&[close(&owner)], &owner);
+        assert_eq!(
+            decode_outflows(&tx, tx.message.static_account_keys(), &owner),
+            vec![]
+        );
+
+        // the account already held a SOL besides what the transaction wraps
+        let pricer = FixedPricer {
+            lamports: HashMap::from([(wsol_account, LAMPORTS_PER_SOL)]),
+            ..Default::default()
+        };
+        let store = Arc::new(MemorySpendingStore::default());
+        let guard =
+            SpendingGuard::new(signer.clone(), store, Arc::new(pricer));
+        let mut tx = make_tx(&ixs, &owner);
+        let err = guard
+            .sign_and_send_solana_transaction(&mut tx)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SpendingLimitExceeded>(),
+            Some(&SpendingLimitExceeded::Transaction {
+                usd: 2_100.,
+                limit: 1_000.
+            })
+        );
+        assert_eq!(signer.sent(), 0);
+    }
+
+    fn spend(timestamp: u64, usd: f64, unknown: bool) -> SpendRecord {
+        SpendRecord {
+            timestamp,
+            usd,
+            unknown,
+        }
+    }
+
+    #[test]
+    fn test_rolling_window() {
+        let limits = SpendingLimits {
+            max_per_transaction_usd: 500.,
+            max_per_window_usd: 1_000.,
+            window_secs: 3_600,
+            max_unknown_per_window: 1,
+        };
+        let now = 1_700_000_000;
+        let history =
+            [spend(now - 3_000, 400., false), spend(now, 400., false)];
+
+        assert!(
+            check_spend(&limits, &history, &spend(now, 200., false)).is_ok()
+        );
+        assert_eq!(
+            check_spend(&limits, &history, &spend(now, 600., false)),
+            Err(SpendingLimitExceeded::Transaction {
+                usd: 600.,
+                limit: 500.
+            })
+        );
+        assert!(matches!(
+            check_spend(&limits, &history, &spend(now, 300., false)),
+            Err(SpendingLimitExceeded::Window { .. })
+        ));
+        // the first spend falls out of the window
+        assert!(check_spend(
+            &limits,
+            &history,
+            &spend(now + 700, 300., false)
+        )
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_instructions_use_the_unknown_budget() {
+        let signer = Arc::new(CountingSigner::new());
+        let store = Arc::new(MemorySpendingStore::default());
+        let wallet = signer.pubkey.to_string();
+        store
+            .set_limits(
+                &wallet,
+                &SpendingLimits {
+                    max_unknown_per_window: 2,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let guard = SpendingGuard::new(
+            signer.clone(),
+            store.clone(),
+            Arc::new(FixedPricer::default()),
+        );
+
+        let unknown_ix = Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[],
+            vec![AccountMeta::new(signer.pubkey, true)],
+        );
+        for _ in 0..2 {
+            let mut tx = make_tx(&[unknown_ix.clone()], &signer.pubkey);
+            guard
+                .sign_and_send_solana_transaction(&mut tx)
+                .await
+                .unwrap();
+        }
+        let mut tx = make_tx(&[unknown_ix], &signer.pubkey);
+        let err = guard
+            .sign_and_send_solana_transaction(&mut tx)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SpendingLimitExceeded>(),
+            Some(SpendingLimitExceeded::Unknown { count: 2, limit: 2 })
+        ));
+        assert_eq!(signer.sent(), 2);
+
+        // known transfers are valued and still go through
+        let mut tx = make_tx(
+            &[system_instruction::transfer(
+                &signer.pubkey,
+                &Pubkey::new_unique(),
+                LAMPORTS_PER_SOL,
+            )],
+            &signer.pubkey,
+        );
+        guard
+            .sign_and_send_solana_transaction(&mut tx)
+            .await
+            .unwrap();
+        let spends = store.get_spends(&wallet, 0).await.unwrap();
+        assert_eq!(spends.last().unwrap().usd, 100.);
+        assert!(!spends.last().unwrap().unknown);
+    }
+
+    #[tokio::test]
+    async fn test_guards_of_concurrent_requests_share_the_window() {
+        let signer = Arc::new(CountingSigner::new());
+        let store = Arc::new(MemorySpendingStore::default());
+        let wallet = signer.pubkey.to_string();
+        store
+            .set_limits(
+                &wallet,
+                &SpendingLimits {
+                    max_per_window_usd: 150.,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        // a guard per request, as the routes build them
+        let policy = SpendingPolicy {
+            store: store.clone(),
+            pricer: Arc::new(FixedPricer::default()),
+        };
+        let send = || {
+            let guard = policy.guard(signer.clone());
+            let mut tx = make_tx(
+                &[system_instruction::transfer(
+                    &signer.pubkey,
+                    &Pubkey::new_unique(),
+                    LAMPORTS_PER_SOL,
+                )],
+                &signer.pubkey,
+            );
+            async move { guard.sign_and_send_solana_transaction(&mut tx).await }
+        };
+
+        let (first, second) = tokio::join!(send(), send());
+        assert_eq!(first.is_ok() as usize + second.is_ok() as usize, 1);
+        assert_eq!(signer.sent(), 1);
+        assert_eq!(store.get_spends(&wallet, 0).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_values_transfers_through_lookup_tables() {
+        let signer = Arc::new(CountingSigner::new());
+        let store = Arc::new(MemorySpendingStore::default());
+        let to = Pubkey::new_unique();
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![to],
+        };
+        let pricer = FixedPricer {
+            tables: HashMap::from([(table.key, table.addresses.clone())]),
+            ..Default::default()
+        };
+        let guard = SpendingGuard::new(
+            signer.clone(),
+            store.clone(),
+            Arc::new(pricer),
+        );
+
+        let ixs = [system_instruction::transfer(
+            &signer.pubkey,
+            &to,
+            LAMPORTS_PER_SOL,
+        )];
+        let message = v0::Message::try_compile(
+            &signer.pubkey,
+            &ixs,
+            &[table],
+            Hash::default(),
+        )
+        .unwrap();
+        let mut tx = VersionedTransaction {
+            signatures: vec![Default::default()],
+            message: VersionedMessage::V0(message),
+        };
+        guard
+            .sign_and_send_solana_transaction(&mut tx)
+            .await
+            .unwrap();
+        let spends = store
+            .get_spends(&signer.pubkey.to_string(), 0)
+            .await
+            .unwrap();
+        assert_eq!(spends[0].usd, 100.);
+        assert!(!spends[0].unknown);
+    }
+
+    fn erc20_call(method: [u8; 4], words: &[[u8; 32]]) -> Vec<u8> {
+        let mut data = method.to_vec();
+        for word in words {
+            data.extend_from_slice(word);
+        }
+        data
+    }
+
+    fn amount_word(amount: u128) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        word[16..].copy_from_slice(&amount.to_be_bytes());
+        word
+    }
+
+    #[test]
+    fn test_decode_evm_outflows() {
+        let token = "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913";
+        assert_eq!(
+            decode_evm_outflows(Some(token), 5, &[]),
+            vec![EvmOutflow::Native(5)]
+        );
+        let transfer = erc20_call(ERC20_TRANSFER, &[[1; 32], amount_word(7)]);
+        assert_eq!(
+            decode_evm_outflows(Some(token), 0, &transfer),
+            vec![EvmOutflow::Erc20 {
+                token: token.to_lowercase(),
+                amount: 7
+            }]
+        );
+        let transfer_from = erc20_call(
+            ERC20_TRANSFER_FROM,
+            &[[1; 32], [2; 32], amount_word(9)],
+        );
+        assert_eq!(
+            decode_evm_outflows(Some(token), 0, &transfer_from),
+            vec![EvmOutflow::Erc20 {
+                token: token.to_lowercase(),
+                amount: 9
+            }]
+        );
+        // approve, a swap paying in ETH
+        let approve = erc20_call([0x09, 0x5e, 0xa7, 0xb3], &[[1; 32]]);
+        assert_eq!(
+            decode_evm_outflows(Some(token), 3, &approve),
+            vec![EvmOutflow::Native(3), EvmOutflow::Unknown]
+        );
+
+        let (chain_id, outflows) =
+            decode_json_evm_outflows(&serde_json::json!({
+                "chain_id": 8453,
+                "to": token,
+                "value": "0x0",
+                "data": format!("0x{}", hex::encode(&transfer)),
+            }));
+        assert_eq!(chain_id, Some(8453));
+        assert_eq!(
+            outflows,
+            vec![EvmOutflow::Erc20 {
+                token: token.to_lowercase(),
+                amount: 7
+            }]
+        );
+        let (_, outflows) = decode_json_evm_outflows(&serde_json::json!({
+            "to": token,
+            "value": "not a quantity",
+        }));
+        assert_eq!(outflows, vec![EvmOutflow::Unknown]);
+    }
+
+    #[tokio::test]
+    async fn test_evm_transfers_count_against_the_limits() {
+        let signer = Arc::new(CountingSigner::new());
+        let store = Arc::new(MemorySpendingStore::default());
+        let wallet = signer.pubkey.to_string();
+        store
+            .set_limits(
+                &wallet,
+                &SpendingLimits {
+                    max_per_transaction_usd: 150.,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let guard = SpendingGuard::new(
+            signer.clone(),
+            store.clone(),
+            Arc::new(FixedPricer::default()),
+        );
+        let token = "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913";
+
+        // 1 ETH at $100
+        guard
+            .sign_and_send_json_evm_transaction(serde_json::json!({
+                "chain_id": 1,
+                "to": token,
+                "value": "0xde0b6b3a7640000",
+            }))
+            .await
+            .unwrap();
+        let spends = store.get_spends(&wallet, 0).await.unwrap();
+        assert_eq!(spends[0].usd, 100.);
+        assert!(!spends[0].unknown);
+
+        // 2 tokens at $100 each are over the limit of a transaction
+        let transfer = erc20_call(
+            ERC20_TRANSFER,
+            &[[1; 32], amount_word(2_000_000_000_000_000_000)],
+        );
+        let err = guard
+            .sign_and_send_json_evm_transaction(serde_json::json!({
+                "chain_id": 1,
+                "to": token,
+                "data": format!("0x{}", hex::encode(&transfer)),
+            }))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SpendingLimitExceeded>(),
+            Some(SpendingLimitExceeded::Transaction { .. })
+        ));
+        assert_eq!(signer.sent(), 1);
+    }
+}
@@ -0,0 +1,110 @@
+//! test doubles for the signer wrappers and the code that sends through
+//! them
+
+use anyhow::Result;
+use async_trait::async_trait;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::VersionedTransaction;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::spending_guard::{OutflowPricer, TokenPrice};
+use super::TransactionSigner;
+
+/// counts what it was asked to send, sends nothing
+#[derive(Default)]
+pub struct CountingSigner {
+    pub pubkey: Pubkey,
+    pub sent: AtomicUsize,
+}
+
+impl CountingSigner {
+    pub fn new() -> Self {
+        Self {
+            pubkey: Pubkey::new_unique(),
+            ..Default::default()
+        }
+    }
+
+    pub fn sent(&self) -> usize {
+        self.sent.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for CountingSigner {
+    fn address(&self) -> String {
+        self.pubkey.to_string()
+    }
+
+    fn pubkey(&self) -> String {
+        self.pubkey.to_string()
+    }
+
+    async fn sign_and_send_solana_transaction(
+        &self,
+        _tx: &mut VersionedTransaction,
+    ) -> Result<String> {
+        self.sent.fetch_add(1, Ordering::SeqCst);
+        Ok("signature".to_string())
+    }
+
+    async fn sign_and_send_json_evm_transaction(
+        &self,
+        _tx: serde_json::Value,
+    ) -> Result<String> {
+        self.sent.fetch_add(1, Ordering::SeqCst);
+        Ok("hash".to_string())
+    }
+}
+
+/// $100 for a whole token of 9 decimals, whatever the mint, and for a
+/// whole evm token of 18. Token accounts are never found, balances and
+/// lookup tables are the ones in `lamports` and `tables`
+#[derive(Default)]
+pub struct FixedPricer {
+    pub tables: HashMap<Pubkey, Vec<Pubkey>>,
+    pub lamports: HashMap<Pubkey, u64>,
+}
+
+#[async_trait]
+impl OutflowPricer for FixedPricer {
+    async fn token_price(
+        &self,
+        _mint: &Pubkey,
+    ) -> Result<Option<TokenPrice>> {
+        Ok(Some(TokenPrice {
+            price: 100.,
+            decimals: 9,
+        }))
+    }
+
+    async fn token_account_mint(
+        &self,
+        _account: &Pubkey,
+    ) -> Result<Option<Pubkey>> {
+        Ok(None)
+    }
+
+    async fn account_lamports(&self, account: &Pubkey) -> Result<u64> {
+        Ok(self.lamports.get(account).copied().unwrap_or_default())
+    }
+
+    async fn lookup_table(
+        &self,
+        table: &Pubkey,
+    ) -> Result<Option<Vec<Pubkey>>> {
+        Ok(self.tables.get(table).cloned())
+    }
+
+    async fn evm_token_price(
+        &self,
+        _chain_id: u64,
+        _token: Option<&str>,
+    ) -> Result<Option<TokenPrice>> {
+        Ok(Some(TokenPrice {
+            price: 100.,
+            decimals: 18,
+        }))
+    }
+}
@@ -3,29 +3,167 @@ use rig::agent::Agent;
 use rig::providers::anthropic::completion::CompletionModel as AnthropicCompletionModel;
 
 use super::tools::{
-    DeployPumpFunToken, GetQuote, GetSolBalance, GetSplTokenBalance, Swap,
+    BurnSplToken, CancelLimitOrder, CheckTokenSafety, CloseDca,
+    CloseEmptyTokenAccounts, CloseNonceAccount, CompareRoutes, CreateDca,
+    CreateLimitOrder, CreateNonceAccount, DeployPumpFunToken,
+    EstimateTransactionFee, FetchNftFloorPrice, GetGovernanceProposals,
+    GetPoolInfo, GetQuote, GetSolBalance, GetSplTokenBalance,
+    GetTokenAuthorities, GetTopHolders, GetTransactionHistory, KaminoBorrow,
+    KaminoDeposit, KaminoGetObligation, KaminoGetReserveInfo, ListDcas,
+    ListLimitOrders, ListStakeAccounts, ResolveSnsDomain, SellAll,
+    SimulateSwap, StakeSol, Swap, SwapExactOut, Unstake, UnwrapSol, WrapSol,
+    TRANSACTION_OPTIONS,
 };
-use crate::common::{claude_agent_builder, PREAMBLE_COMMON};
-use crate::data::{FetchCandlesticks, FetchTopTokens};
+use crate::common::PREAMBLE_COMMON;
+use crate::data::{
+    FetchCandlesticks, FetchTopTokens, GetMarketOverview, GetPriceHistory,
+    GetRecentTrades, GetSmartMoneyBuys, GetTopGainersLosers,
+    GetTrendingTokens,
+};
+use crate::delegate::Delegate;
 use crate::dexscreener::tools::SearchOnDexScreener;
+use crate::model::ModelProvider;
+use crate::tool_allowlist::{AllowlistedTools, ToolAllowlist};
 
 pub async fn create_solana_agent(
     preamble: Option<String>,
+    model: &ModelProvider,
+    tools: &ToolAllowlist,
 ) -> Result<Agent<AnthropicCompletionModel>> {
     let preamble = preamble.unwrap_or(format!(
         "{} {}",
         "you are a solana trading agent that can also interact with pump.fun;",
         PREAMBLE_COMMON
     ));
-    Ok(claude_agent_builder()
+    // the tool descriptions refer to these, a custom preamble keeps them
+    let preamble = format!("{}\n{}", preamble, TRANSACTION_OPTIONS);
+    Ok(model
+        .agent_builder()?
         .preamble(&preamble)
-        .tool(GetQuote)
-        .tool(Swap)
-        .tool(GetSolBalance)
-        .tool(GetSplTokenBalance)
-        .tool(SearchOnDexScreener)
-        .tool(FetchCandlesticks)
-        .tool(FetchTopTokens)
-        .tool(DeployPumpFunToken)
+        .allowed_tool(GetQuote, tools)
+        .allowed_tool(CompareRoutes, tools)
+        .allowed_tool(SimulateSwap, tools)
+        .allowed_tool(EstimateTransactionFee, tools)
+        .allowed_tool(Swap, tools)
+        .allowed_tool(SwapExactOut, tools)
+        .allowed_tool(SellAll, tools)
+        .allowed_tool(CreateLimitOrder, tools)
+        .allowed_tool(CancelLimitOrder, tools)
+        .allowed_tool(ListLimitOrders, tools)
+        .allowed_tool(CreateDca, tools)
+        .allowed_tool(ListDcas, tools)
+        .allowed_tool(CloseDca, tools)
+        .allowed_tool(StakeSol, tools)
+        .allowed_tool(ListStakeAccounts, tools)
+        .allowed_tool(Unstake, tools)
+        .allowed_tool(KaminoGetReserveInfo, tools)
+        .allowed_tool(KaminoDeposit, tools)
+        .allowed_tool(KaminoBorrow, tools)
+        .allowed_tool(KaminoGetObligation, tools)
+        .allowed_tool(WrapSol, tools)
+        .allowed_tool(UnwrapSol, tools)
+        .allowed_tool(CloseEmptyTokenAccounts, tools)
+        .allowed_tool(BurnSplToken, tools)
+        .allowed_tool(GetSolBalance, tools)
+        .allowed_tool(GetSplTokenBalance, tools)
+        .allowed_tool(SearchOnDexScreener, tools)
+        .allowed_tool(FetchCandlesticks, tools)
+        .allowed_tool(GetPriceHistory, tools)
+        .allowed_tool(GetRecentTrades, tools)
+        .allowed_tool(FetchTopTokens, tools)
+        .allowed_tool(GetTrendingTokens, tools)
+        .allowed_tool(GetMarketOverview, tools)
+        .allowed_tool(GetTopGainersLosers, tools)
+        .allowed_tool(GetSmartMoneyBuys, tools)
+        .allowed_tool(DeployPumpFunToken, tools)
+        .allowed_tool(GetPoolInfo, tools)
+        .allowed_tool(GetTransactionHistory, tools)
+        .allowed_tool(GetTokenAuthorities, tools)
+        .allowed_tool(CheckTokenSafety, tools)
+        .allowed_tool(GetTopHolders, tools)
+        .allowed_tool(FetchNftFloorPrice, tools)
+        .allowed_tool(GetGovernanceProposals, tools)
+        .allowed_tool(ResolveSnsDomain, tools)
+        .allowed_tool(CreateNonceAccount, tools)
+        .allowed_tool(CloseNonceAccount, tools)
+        .allowed_tool(Delegate, tools)
         .build())
 }
+
+/// read-only sub-agent the trading agent can delegate research to
+pub async fn create_solana_research_agent(
+    model: &ModelProvider,
+    tools: &ToolAllowlist,
+) -> Result<Agent<AnthropicCompletionModel>> {
+    let preamble = format!(
+        "{} {}",
+        "you are a solana token research agent; you cannot trade, only look \
+        up tokens, prices, balances and charts and report back concisely \
+        whether the token looks safe to trade;",
+        PREAMBLE_COMMON
+    );
+    Ok(model
+        .agent_builder()?
+        .preamble(&preamble)
+        .allowed_tool(GetQuote, tools)
+        .allowed_tool(CompareRoutes, tools)
+        .allowed_tool(SimulateSwap, tools)
+        .allowed_tool(EstimateTransactionFee, tools)
+        .allowed_tool(GetSolBalance, tools)
+        .allowed_tool(GetSplTokenBalance, tools)
+        .allowed_tool(ListStakeAccounts, tools)
+        .allowed_tool(KaminoGetReserveInfo, tools)
+        .allowed_tool(KaminoGetObligation, tools)
+        .allowed_tool(SearchOnDexScreener, tools)
+        .allowed_tool(FetchCandlesticks, tools)
+        .allowed_tool(GetPriceHistory, tools)
+        .allowed_tool(GetRecentTrades, tools)
+        .allowed_tool(FetchTopTokens, tools)
+        .allowed_tool(GetTrendingTokens, tools)
+        .allowed_tool(GetMarketOverview, tools)
+        .allowed_tool(GetTopGainersLosers, tools)
+        .allowed_tool(GetSmartMoneyBuys, tools)
+        .allowed_tool(GetPoolInfo, tools)
+        .allowed_tool(GetTransactionHistory, tools)
+        .allowed_tool(GetTokenAuthorities, tools)
+        .allowed_tool(CheckTokenSafety, tools)
+        .allowed_tool(GetTopHolders, tools)
+        .allowed_tool(FetchNftFloorPrice, tools)
+        .allowed_tool(GetGovernanceProposals, tools)
+        .allowed_tool(ResolveSnsDomain, tools)
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_model() -> ModelProvider {
+        std::env::set_var("LISTEN_TEST_API_KEY", "test");
+        ModelProvider {
+            api_key_env: "LISTEN_TEST_API_KEY".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_only_agent_has_no_transaction_tools() {
+        let model = test_model();
+        let trader = create_solana_agent(None, &model, &ToolAllowlist::all())
+            .await
+            .unwrap();
+        assert!(trader.tools.contains("swap"));
+
+        let analyst = create_solana_agent(
+            Some("you are a read-only analyst".to_string()),
+            &model,
+            &ToolAllowlist::all().read_only(),
+        )
+        .await
+        .unwrap();
+        assert!(!analyst.tools.contains("transfer_sol"));
+        assert!(!analyst.tools.contains("swap"));
+        assert!(analyst.tools.contains("get_quote"));
+        assert!(analyst.tools.contains("get_sol_balance"));
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use spl_token::solana_program::program_pack::Pack;
+use spl_token::state::Mint;
+
+use crate::solana::constants::WSOL;
+use crate::solana::deploy_token::derive_metadata_account;
+use crate::solana::token_info::parse_metadata_symbol;
+
+pub const SOL_DECIMALS: u8 = 9;
+
+/// units that name the base units of a token rather than whole tokens
+const BASE_UNITS: [&str; 3] = ["lamport", "lamports", "raw"];
+
+/// An amount of a token as the model passes it, in base units:
+///
+/// - a bare integer is already in base units, e.g. "1500000000"
+/// - a decimal or a number with the token's `symbol` is in whole tokens,
+///   e.g. "1.5" or "1.5 SOL" of a token with 9 decimals is 1500000000
+/// - "lamports" or "raw" mark base units explicitly, e.g. "5000 lamports"
+///
+/// Any other unit, more fractional digits than the token has, signs,
+/// exponents, thousands separators and amounts that don't fit a u64 are
+/// rejected
+pub fn parse_amount(
+    input: &str,
+    decimals: u8,
+    symbol: Option<&str>,
+) -> Result<u64> {
+    let input = input.trim();
+    let (number, unit) = split_unit(input);
+    if number.is_empty() {
+        return Err(anyhow!("{:?} is not an amount", input));
+    }
+    if !unit.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(anyhow!("{:?} is not an amount", input));
+    }
+    let base_units = BASE_UNITS.contains(&unit.to_lowercase().as_str());
+    if !unit.is_empty()
+        && !base_units
+        && !symbol.is_some_and(|symbol| unit.eq_ignore_ascii_case(symbol))
+    {
+        return Err(match symbol {
+            Some(symbol) => anyhow!(
+                "{:?} is not in {}, give the amount in {}, lamports or raw",
+                input,
+                unit,
+                symbol
+            ),
+            None => anyhow!(
+                "{:?} is not in {}, give the amount without a unit, in \
+                 lamports or raw",
+                input,
+                unit
+            ),
+        });
+    }
+    let (whole, fraction) = match number.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (number, None),
+    };
+    if fraction.is_some_and(|fraction| fraction.contains('.')) {
+        return Err(anyhow!("{:?} has more than one decimal point", input));
+    }
+    let overflow = || anyhow!("{:?} is too large", input);
+
+    if fraction.is_none() && (unit.is_empty() || base_units) {
+        return whole.parse::<u64>().map_err(|_| overflow());
+    }
+    if base_units {
+        return Err(anyhow!(
+            "{:?} can't have a fraction of a {}",
+            input,
+            unit
+        ));
+    }
+    let fraction = fraction.unwrap_or_default();
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(anyhow!("{:?} is not an amount", input));
+    }
+    if fraction.len() > decimals as usize {
+        return Err(anyhow!(
+            "{:?} has more than {} decimals",
+            input,
+            decimals
+        ));
+    }
+    let scale = 10u64.checked_pow(decimals as u32).ok_or_else(overflow)?;
+    let whole = match whole {
+        "" => 0,
+        whole => whole.parse::<u64>().map_err(|_| overflow())?,
+    };
+    let fraction = match fraction {
+        "" => 0,
+        fraction => {
+            fraction.parse::<u64>().map_err(|_| overflow())?
+                * 10u64.pow((decimals as usize - fraction.len()) as u32)
+        }
+    };
+    whole
+        .checked_mul(scale)
+        .and_then(|whole| whole.checked_add(fraction))
+        .ok_or_else(overflow)
+}
+
+fn split_unit(input: &str) -> (&str, &str) {
+    let split = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    (&input[..split], input[split..].trim())
+}
+
+/// whether `input` is in base units, so `parse_amount` doesn't need the
+/// decimals of the token
+pub fn is_base_units(input: &str) -> bool {
+    let (number, unit) = split_unit(input.trim());
+    !number.contains('.')
+        && (unit.is_empty()
+            || BASE_UNITS.contains(&unit.to_lowercase().as_str()))
+}
+
+/// whether `input` names its token, so `parse_amount` needs the symbol
+pub fn names_symbol(input: &str) -> bool {
+    let (_, unit) = split_unit(input.trim());
+    !unit.is_empty() && !BASE_UNITS.contains(&unit.to_lowercase().as_str())
+}
+
+/// `parse_amount` of SOL, refusing bare integers. Where the model tends to
+/// mean whole SOL by them, e.g. "buy for 1", reading lamports would be a
+/// costly mistake either way, so it has to say which
+pub fn parse_explicit_sol_amount(input: &str) -> Result<u64> {
+    let (number, unit) = split_unit(input.trim());
+    if !number.contains('.') && unit.is_empty() {
+        return Err(anyhow!(
+            "{:?} could be SOL or lamports, add a unit or a decimal point, \
+             e.g. {:?} or \"{} lamports\"",
+            input,
+            format!("{} SOL", number),
+            number
+        ));
+    }
+    parse_amount(input, SOL_DECIMALS, Some("SOL"))
+}
+
+/// the symbol of the metaplex metadata of `mint`, none without one
+pub async fn mint_symbol(
+    rpc_client: &RpcClient,
+    mint: &Pubkey,
+) -> Result<Option<String>> {
+    let metadata = rpc_client
+        .get_account_with_commitment(
+            &derive_metadata_account(mint),
+            rpc_client.commitment(),
+        )
+        .await?
+        .value;
+    metadata
+        .map(|metadata| parse_metadata_symbol(&metadata.data))
+        .transpose()
+}
+
+/// `parse_amount` with the decimals of `mint`, only fetched for amounts
+/// that aren't in base units already
+pub async fn parse_mint_amount(
+    rpc_client: &RpcClient,
+    mint: &str,
+    input: &str,
+) -> Result<u64> {
+    if mint == WSOL {
+        return parse_amount(input, SOL_DECIMALS, Some("SOL"));
+    }
+    if is_base_units(input) {
+        return parse_amount(input, 0, None);
+    }
+    let mint = mint.parse::<Pubkey>()?;
+    let account = rpc_client.get_account(&mint).await?;
+    let data = account
+        .data
+        .get(..Mint::LEN)
+        .ok_or_else(|| anyhow!("{} is not a mint", mint))?;
+    let symbol = match names_symbol(input) {
+        true => mint_symbol(rpc_client, &mint).await?,
+        false => None,
+    };
+    parse_amount(
+        input,
+        Mint::unpack_from_slice(data)?.decimals,
+        symbol.as_deref(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOL: Option<&str> = Some("SOL");
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("1.5", 9, SOL).unwrap(), 1_500_000_000);
+        assert_eq!(parse_amount("1.5 SOL", 9, SOL).unwrap(), 1_500_000_000);
+        assert_eq!(
+            parse_amount(" 2usdc ", 6, Some("USDC")).unwrap(),
+            2_000_000
+        );
+        assert_eq!(parse_amount(".25", 6, None).unwrap(), 250_000);
+        assert_eq!(parse_amount("3.", 6, None).unwrap(), 3_000_000);
+        // bare integers and explicit base units are left as they are
+        assert_eq!(
+            parse_amount("1500000000", 9, SOL).unwrap(),
+            1_500_000_000
+        );
+        assert_eq!(parse_amount("5000 lamports", 9, SOL).unwrap(), 5000);
+        assert_eq!(parse_amount("5000 raw", 6, None).unwrap(), 5000);
+    }
+
+    #[test]
+    fn test_parse_amount_rejects() {
+        // u64::MAX lamports is about 18.4 billion SOL
+        assert!(parse_amount("18446744074 SOL", 9, SOL).is_err());
+        assert!(parse_amount("18446744073709551616", 9, SOL).is_err());
+        assert!(parse_amount("1.0000000001", 9, SOL).is_err());
+        assert!(parse_amount("1.5 lamports", 9, SOL).is_err());
+        for input in ["", "SOL", ".", "-1", "1e9", "1,5", "1.2.3", "1 $"] {
+            assert!(parse_amount(input, 9, SOL).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_amount_rejects_other_units() {
+        // a USDC amount given in SOL is not 2 USDC
+        assert!(parse_amount("2 SOL", 6, Some("USDC")).is_err());
+        assert!(parse_amount("2 USD", 6, Some("USDC")).is_err());
+        assert!(parse_amount("1.5 BONK", 9, SOL).is_err());
+        // without a known symbol only base units can be named
+        assert!(parse_amount("2 USDC", 6, None).is_err());
+        assert_eq!(parse_amount("2", 6, None).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_parse_explicit_sol_amount() {
+        assert_eq!(
+            parse_explicit_sol_amount("1 SOL").unwrap(),
+            1_000_000_000
+        );
+        assert_eq!(parse_explicit_sol_amount("0.5").unwrap(), 500_000_000);
+        assert_eq!(parse_explicit_sol_amount("1.").unwrap(), 1_000_000_000);
+        assert_eq!(parse_explicit_sol_amount("5000 lamports").unwrap(), 5000);
+        let err = parse_explicit_sol_amount("1").unwrap_err();
+        assert!(err.to_string().contains("add a unit"), "{}", err);
+    }
+
+    #[test]
+    fn test_names_symbol() {
+        assert!(names_symbol("2 USDC"));
+        assert!(!names_symbol("2"));
+        assert!(!names_symbol("2.5"));
+        assert!(!names_symbol("2 lamports"));
+    }
+
+    #[test]
+    fn test_is_base_units() {
+        assert!(is_base_units("1000"));
+        assert!(is_base_units("1000 raw"));
+        assert!(!is_base_units("1000 BONK"));
+        assert!(!is_base_units("1.5"));
+    }
+}
@@ -16,6 +16,15 @@ pub struct Holding {
     pub amount: u64,
 }
 
+/// the balance of a token account, `amount` in base units and `ui_amount`
+/// in whole tokens
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenBalance {
+    pub amount: String,
+    pub decimals: u8,
+    pub ui_amount: String,
+}
+
 pub fn parse_holding(ata: RpcKeyedAccount) -> Result<Holding> {
     if let UiAccountData::Json(ParsedAccount {
         program: _,
@@ -0,0 +1,105 @@
+use balance_diffs::{
+    summarize_balance_changes, SwapSummary, TokenBalance, WSOL_MINT,
+};
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{
+    UiTransactionStatusMeta, UiTransactionTokenBalance,
+};
+
+fn token_balances(
+    balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>,
+    owner: &str,
+) -> Vec<TokenBalance> {
+    let OptionSerializer::Some(balances) = balances else {
+        return Vec::new();
+    };
+    balances
+        .iter()
+        .filter(|balance| {
+            matches!(&balance.owner, OptionSerializer::Some(o) if o == owner)
+        })
+        .filter_map(|balance| {
+            Some(TokenBalance {
+                mint: balance.mint.clone(),
+                owner: owner.to_string(),
+                ui_amount: balance.ui_token_amount.ui_amount?,
+            })
+        })
+        .collect()
+}
+
+/// what a transaction of `owner` swapped, the way the indexer summarizes
+/// pool swaps; the owner is expected to be the fee payer, as it is for the
+/// transactions the agent sends, so native SOL (fee excluded) counts too
+pub fn summarize_transaction(
+    meta: &UiTransactionStatusMeta,
+    owner: &str,
+) -> SwapSummary {
+    let mut pre = token_balances(&meta.pre_token_balances, owner);
+    let mut post = token_balances(&meta.post_token_balances, owner);
+    if let (Some(pre_lamports), Some(post_lamports)) =
+        (meta.pre_balances.first(), meta.post_balances.first())
+    {
+        let native = |lamports: u64| TokenBalance {
+            mint: WSOL_MINT.to_string(),
+            owner: owner.to_string(),
+            ui_amount: lamports as f64 / LAMPORTS_PER_SOL as f64,
+        };
+        pre.push(native(*pre_lamports));
+        post.push(native(post_lamports + meta.fee));
+    }
+    summarize_balance_changes(&pre, &post)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWNER: &str = "6fp9frQ16W3kTRGiBVvpMS2NzoixE4Y1MWqYrW9SvTAj";
+    const BONK: &str = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263";
+
+    #[test]
+    fn test_summarize_transaction() {
+        // 0.5 SOL swapped for bonk, the pool's balances are left out
+        let token_balance = |owner: &str, amount: f64| {
+            serde_json::json!({
+                "accountIndex": 1,
+                "mint": BONK,
+                "owner": owner,
+                "uiTokenAmount": {
+                    "uiAmount": amount,
+                    "decimals": 5,
+                    "amount": ((amount * 1e5) as u64).to_string(),
+                    "uiAmountString": amount.to_string(),
+                },
+            })
+        };
+        let pool = "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1";
+        let meta: UiTransactionStatusMeta =
+            serde_json::from_value(serde_json::json!({
+                "err": null,
+                "status": {"Ok": null},
+                "fee": 5000,
+                "preBalances": [2_500_000_000u64, 0],
+                "postBalances": [1_999_995_000u64, 0],
+                "preTokenBalances": [token_balance(pool, 900_000.0)],
+                "postTokenBalances": [
+                    token_balance(pool, 700_000.0),
+                    token_balance(OWNER, 200_000.0),
+                ],
+            }))
+            .unwrap();
+
+        let summary = summarize_transaction(&meta, OWNER);
+        assert_eq!(summary.sold_mint.as_deref(), Some(WSOL_MINT));
+        assert_eq!(summary.sold_amount, 0.5);
+        assert_eq!(summary.bought_mint.as_deref(), Some(BONK));
+        assert_eq!(summary.bought_amount, 200_000.0);
+        assert_eq!(summary.sol_delta, -0.5);
+        assert_eq!(
+            summary.to_string(),
+            format!("sold 0.5 SOL for 200000 {}", BONK)
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token::state::Mint;
+
+use crate::solana::amount::{mint_symbol, names_symbol, parse_amount};
+
+/// `TokenInstruction::BurnChecked`, the same in both token programs
+const BURN_CHECKED: u8 = 15;
+
+/// what to burn, an amount as `parse_amount` reads it or the whole balance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BurnAmount {
+    Amount(String),
+    All,
+}
+
+/// the burn of some of the tokens of `mint` held by the owner
+#[derive(Debug, Clone, PartialEq)]
+pub struct BurnPlan {
+    pub instruction: Instruction,
+    pub amount: u64,
+    pub decimals: u8,
+    /// the balance left after the burn
+    pub remaining: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BurnReport {
+    pub burned: f64,
+    pub remaining_balance: f64,
+    pub transaction: String,
+}
+
+pub fn ui_amount(amount: u64, decimals: u8) -> f64 {
+    amount as f64 / 10f64.powi(decimals as i32)
+}
+
+/// `BurnChecked` of `amount` out of `account`, for either token program
+pub fn burn_checked_ix(
+    program_id: &Pubkey,
+    account: &Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    amount: u64,
+    decimals: u8,
+) -> Instruction {
+    let mut data = vec![BURN_CHECKED];
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(decimals);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*account, false),
+            AccountMeta::new(*mint, false),
+            AccountMeta::new_readonly(*owner, true),
+        ],
+        data,
+    }
+}
+
+/// Reads the balance of `owner` in `mint` as it is now and fails, with
+/// that balance, when `amount` is more than it
+pub async fn plan_burn(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    mint: &Pubkey,
+    amount: &BurnAmount,
+) -> Result<BurnPlan> {
+    let mint_account = rpc_client.get_account(mint).await?;
+    let decimals = mint_account
+        .data
+        .get(..Mint::LEN)
+        .ok_or_else(|| anyhow!("{} is not a mint", mint))
+        .and_then(|data| Ok(Mint::unpack_from_slice(data)?.decimals))?;
+    let program_id = mint_account.owner;
+    let account = get_associated_token_address_with_program_id(
+        owner,
+        mint,
+        &program_id,
+    );
+    let balance = rpc_client
+        .get_token_account_balance(&account)
+        .await
+        .map_err(|e| anyhow!("No balance of {} to burn: {}", mint, e))?
+        .amount
+        .parse::<u64>()?;
+
+    let amount = match amount {
+        BurnAmount::All => balance,
+        BurnAmount::Amount(amount) => {
+            let symbol = match names_symbol(amount) {
+                true => mint_symbol(rpc_client, mint).await?,
+                false => None,
+            };
+            parse_amount(amount, decimals, symbol.as_deref())?
+        }
+    };
+    if amount == 0 {
+        return Err(anyhow!("Nothing to burn, the balance is 0"));
+    }
+    if amount > balance {
+        return Err(anyhow!(
+            "Can't burn {} of {}, the balance is only {}",
+            ui_amount(amount, decimals),
+            mint,
+            ui_amount(balance, decimals)
+        ));
+    }
+    Ok(BurnPlan {
+        instruction: burn_checked_ix(
+            &program_id,
+            &account,
+            mint,
+            owner,
+            amount,
+            decimals,
+        ),
+        amount,
+        decimals,
+        remaining: balance - amount,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::prelude::{Engine, BASE64_STANDARD};
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use std::collections::HashMap;
+
+    /// a 6 decimals mint of which the owner holds 5 tokens
+    fn mock_rpc() -> RpcClient {
+        let mint = Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        };
+        let mut data = vec![0u8; Mint::LEN];
+        mint.pack_into_slice(&mut data);
+        RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([
+                (
+                    RpcRequest::GetAccountInfo,
+                    json!({
+                        "context": { "slot": 1 },
+                        "value": {
+                            "data": [BASE64_STANDARD.encode(data), "base64"],
+                            "executable": false,
+                            "lamports": 1_461_600,
+                            "owner": spl_token::id().to_string(),
+                            "rentEpoch": 0,
+                            "space": Mint::LEN,
+                        },
+                    }),
+                ),
+                (
+                    RpcRequest::GetTokenAccountBalance,
+                    json!({
+                        "context": { "slot": 1 },
+                        "value": {
+                            "amount": "5000000",
+                            "decimals": 6,
+                            "uiAmount": 5.0,
+                            "uiAmountString": "5",
+                        },
+                    }),
+                ),
+            ]),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_plan_burn_amount() {
+        let (owner, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let plan = plan_burn(
+            &mock_rpc(),
+            &owner,
+            &mint,
+            &BurnAmount::Amount("1.5".to_string()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(plan.amount, 1_500_000);
+        assert_eq!(plan.remaining, 3_500_000);
+        assert_eq!(
+            plan.instruction,
+            spl_token::instruction::burn_checked(
+                &spl_token::id(),
+                &get_associated_token_address_with_program_id(
+                    &owner,
+                    &mint,
+                    &spl_token::id()
+                ),
+                &mint,
+                &owner,
+                &[],
+                1_500_000,
+                6,
+            )
+            .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plan_burn_all() {
+        let plan = plan_burn(
+            &mock_rpc(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &BurnAmount::All,
+        )
+        .await
+        .unwrap();
+        assert_eq!(plan.amount, 5_000_000);
+        assert_eq!(plan.remaining, 0);
+    }
+
+    #[tokio::test]
+    async fn test_plan_burn_over_balance() {
+        let err = plan_burn(
+            &mock_rpc(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &BurnAmount::Amount("6 tokens".to_string()),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("the balance is only 5"), "{}", err);
+    }
+}
@@ -0,0 +1,390 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_account_decoder::parse_account_data::ParsedAccount;
+use solana_account_decoder::UiAccountData;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_request::TokenAccountsFilter;
+use solana_client::rpc_response::RpcKeyedAccount;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::native_token::lamports_to_sol;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::Transaction;
+use std::str::FromStr;
+
+use crate::signer::spending_guard::TOKEN_2022_PROGRAM;
+use crate::solana::constants::WSOL;
+
+/// `TokenInstruction::CloseAccount`, the same in both token programs
+const CLOSE_ACCOUNT: u8 = 9;
+
+/// room left in each transaction for the compute budget instructions the
+/// priority fee puts in front, the program and two short instructions
+const COMPUTE_BUDGET_HEADROOM: usize = 64;
+
+/// an empty token account of the wallet that can be closed
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmptyTokenAccount {
+    pub address: Pubkey,
+    pub mint: String,
+    pub program_id: Pubkey,
+    /// the rent closing it returns
+    pub lamports: u64,
+}
+
+/// an empty token account that can't be closed, and why
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkippedTokenAccount {
+    pub address: String,
+    pub mint: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmptyAccountCheck {
+    Closable(EmptyTokenAccount),
+    Skipped(SkippedTokenAccount),
+}
+
+/// what closing the empty token accounts of a wallet did, or would do
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CloseAccountsReport {
+    pub closed: usize,
+    pub reclaimed_sol: f64,
+    pub skipped: Vec<SkippedTokenAccount>,
+    /// nothing is signed in a dry run
+    pub dry_run: bool,
+    pub transactions: Vec<String>,
+}
+
+/// the empty accounts to close, batched into transactions
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloseAccountsPlan {
+    pub batches: Vec<Vec<Instruction>>,
+    pub closed: usize,
+    pub lamports: u64,
+    pub skipped: Vec<SkippedTokenAccount>,
+}
+
+impl CloseAccountsPlan {
+    pub fn report(
+        &self,
+        dry_run: bool,
+        transactions: Vec<String>,
+    ) -> CloseAccountsReport {
+        CloseAccountsReport {
+            closed: self.closed,
+            reclaimed_sol: lamports_to_sol(self.lamports),
+            skipped: self.skipped.clone(),
+            dry_run,
+            transactions,
+        }
+    }
+}
+
+fn parsed_u64(value: &Value) -> Option<u64> {
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+/// Sorts an account of `get_token_accounts_by_owner` of `program_id`:
+/// `None` for ones holding tokens and WSOL, which swaps reuse, skipped for
+/// empty ones the token program won't close for `owner`
+pub fn check_token_account(
+    keyed: &RpcKeyedAccount,
+    program_id: &Pubkey,
+    owner: &Pubkey,
+) -> Result<Option<EmptyAccountCheck>> {
+    let UiAccountData::Json(ParsedAccount { parsed, .. }) =
+        &keyed.account.data
+    else {
+        return Err(anyhow!("token account {} is not parsed", keyed.pubkey));
+    };
+    let info = &parsed["info"];
+    let mint = info["mint"].as_str().ok_or_else(|| {
+        anyhow!("token account {} has no mint", keyed.pubkey)
+    })?;
+    let amount = parsed_u64(&info["tokenAmount"]["amount"]);
+    if mint == WSOL || amount != Some(0) {
+        return Ok(None);
+    }
+
+    let close_authority = info["closeAuthority"].as_str();
+    let withheld_fees = info["extensions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|ext| ext["extension"] == "transferFeeAmount")
+        .and_then(|ext| parsed_u64(&ext["state"]["withheldAmount"]))
+        .unwrap_or(0);
+    let confidential = info["extensions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .any(|ext| ext["extension"] == "confidentialTransferAccount");
+    let reason = match close_authority {
+        _ if info["state"] == "frozen" => Some("frozen".to_string()),
+        Some(authority) if authority != owner.to_string() => {
+            Some(format!("only {} can close it", authority))
+        }
+        _ if withheld_fees > 0 => {
+            Some(format!("{} of withheld transfer fees", withheld_fees))
+        }
+        _ if confidential => {
+            Some("it has a confidential transfer balance".to_string())
+        }
+        _ => None,
+    };
+    if let Some(reason) = reason {
+        return Ok(Some(EmptyAccountCheck::Skipped(SkippedTokenAccount {
+            address: keyed.pubkey.clone(),
+            mint: mint.to_string(),
+            reason,
+        })));
+    }
+    Ok(Some(EmptyAccountCheck::Closable(EmptyTokenAccount {
+        address: Pubkey::from_str(&keyed.pubkey)?,
+        mint: mint.to_string(),
+        program_id: *program_id,
+        lamports: keyed.account.lamports,
+    })))
+}
+
+/// the empty token accounts of `owner` in both token programs
+pub async fn fetch_empty_token_accounts(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Vec<EmptyAccountCheck>> {
+    let mut checks = Vec::new();
+    for program_id in [spl_token::id(), Pubkey::from_str(TOKEN_2022_PROGRAM)?]
+    {
+        let accounts = rpc_client
+            .get_token_accounts_by_owner(
+                owner,
+                TokenAccountsFilter::ProgramId(program_id),
+            )
+            .await?;
+        for keyed in &accounts {
+            checks.extend(check_token_account(keyed, &program_id, owner)?);
+        }
+    }
+    Ok(checks)
+}
+
+/// returns the rent of `account` to its owner
+pub fn close_token_account_ix(
+    account: &EmptyTokenAccount,
+    owner: &Pubkey,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        account.program_id,
+        &[CLOSE_ACCOUNT],
+        vec![
+            AccountMeta::new(account.address, false),
+            AccountMeta::new(*owner, false),
+            AccountMeta::new_readonly(*owner, true),
+        ],
+    )
+}
+
+fn fits_in_packet(ixs: &[Instruction], owner: &Pubkey) -> bool {
+    let tx = Transaction::new_with_payer(ixs, Some(owner));
+    bincode::serialized_size(&tx).is_ok_and(|size| {
+        size as usize + COMPUTE_BUDGET_HEADROOM <= PACKET_DATA_SIZE
+    })
+}
+
+/// the closes of `accounts`, as many to a transaction as fit
+pub fn batch_close_ixs(
+    accounts: &[EmptyTokenAccount],
+    owner: &Pubkey,
+) -> Vec<Vec<Instruction>> {
+    let mut batches: Vec<Vec<Instruction>> = Vec::new();
+    for account in accounts {
+        let ix = close_token_account_ix(account, owner);
+        match batches.last_mut() {
+            Some(batch) => {
+                batch.push(ix);
+                if !fits_in_packet(batch, owner) {
+                    let ix = batch.pop().unwrap();
+                    batches.push(vec![ix]);
+                }
+            }
+            None => batches.push(vec![ix]),
+        }
+    }
+    batches
+}
+
+/// closes at most `max_accounts` of the closable accounts in `checks`
+pub fn plan_close_accounts(
+    checks: Vec<EmptyAccountCheck>,
+    owner: &Pubkey,
+    max_accounts: Option<usize>,
+) -> CloseAccountsPlan {
+    let (mut closable, mut skipped) = (Vec::new(), Vec::new());
+    for check in checks {
+        match check {
+            EmptyAccountCheck::Closable(account) => closable.push(account),
+            EmptyAccountCheck::Skipped(account) => skipped.push(account),
+        }
+    }
+    if let Some(max_accounts) = max_accounts {
+        closable.truncate(max_accounts);
+    }
+    CloseAccountsPlan {
+        batches: batch_close_ixs(&closable, owner),
+        closed: closable.len(),
+        lamports: closable.iter().map(|account| account.lamports).sum(),
+        skipped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const RENT: u64 = 2_039_280;
+
+    fn keyed_account(info: Value, program: &str) -> RpcKeyedAccount {
+        serde_json::from_value(json!({
+            "pubkey": Pubkey::new_unique().to_string(),
+            "account": {
+                "data": {
+                    "program": program,
+                    "parsed": { "type": "account", "info": info },
+                    "space": 165,
+                },
+                "executable": false,
+                "lamports": RENT,
+                "owner": spl_token::id().to_string(),
+                "rentEpoch": 0,
+                "space": 165,
+            }
+        }))
+        .unwrap()
+    }
+
+    fn token_info(mint: &str, amount: &str) -> Value {
+        json!({
+            "isNative": false,
+            "mint": mint,
+            "owner": Pubkey::new_unique().to_string(),
+            "state": "initialized",
+            "tokenAmount": {
+                "amount": amount,
+                "decimals": 6,
+                "uiAmount": 0.0,
+                "uiAmountString": "0",
+            },
+        })
+    }
+
+    fn empty_accounts(n: usize) -> Vec<EmptyTokenAccount> {
+        (0..n)
+            .map(|_| EmptyTokenAccount {
+                address: Pubkey::new_unique(),
+                mint: Pubkey::new_unique().to_string(),
+                program_id: spl_token::id(),
+                lamports: RENT,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_check_token_account() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique().to_string();
+        let check = |info: Value, program: &str| {
+            check_token_account(
+                &keyed_account(info, program),
+                &spl_token::id(),
+                &owner,
+            )
+            .unwrap()
+        };
+
+        assert!(matches!(
+            check(token_info(&mint, "0"), "spl-token"),
+            Some(EmptyAccountCheck::Closable(EmptyTokenAccount {
+                lamports: RENT,
+                ..
+            }))
+        ));
+        assert_eq!(check(token_info(&mint, "5"), "spl-token"), None);
+        assert_eq!(check(token_info(WSOL, "0"), "spl-token"), None);
+
+        let mut frozen = token_info(&mint, "0");
+        frozen["state"] = json!("frozen");
+        assert!(matches!(
+            check(frozen, "spl-token"),
+            Some(EmptyAccountCheck::Skipped(SkippedTokenAccount {
+                reason, ..
+            })) if reason == "frozen"
+        ));
+
+        let mut with_fees = token_info(&mint, "0");
+        with_fees["extensions"] = json!([{
+            "extension": "transferFeeAmount",
+            "state": { "withheldAmount": 12 },
+        }]);
+        assert!(matches!(
+            check(with_fees, "spl-token-2022"),
+            Some(EmptyAccountCheck::Skipped(SkippedTokenAccount {
+                reason, ..
+            })) if reason.contains("withheld transfer fees")
+        ));
+    }
+
+    #[test]
+    fn test_batch_close_ixs() {
+        let owner = Pubkey::new_unique();
+        let accounts = empty_accounts(60);
+
+        let batches = batch_close_ixs(&accounts, &owner);
+
+        // the first close takes 205 of the 1168 bytes left of the packet
+        // with the signature and header, each further one its account and a
+        // 7 byte instruction
+        assert_eq!(
+            batches.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![25, 25, 10]
+        );
+        for batch in &batches {
+            assert!(fits_in_packet(batch, &owner));
+        }
+        let first = &batches[0];
+        let mut one_more = first.clone();
+        one_more.push(close_token_account_ix(&accounts[first.len()], &owner));
+        assert!(!fits_in_packet(&one_more, &owner));
+
+        assert!(batch_close_ixs(&[], &owner).is_empty());
+    }
+
+    #[test]
+    fn test_plan_close_accounts_dry_run() {
+        let owner = Pubkey::new_unique();
+        let mut checks = empty_accounts(5)
+            .into_iter()
+            .map(EmptyAccountCheck::Closable)
+            .collect::<Vec<_>>();
+        let skipped = SkippedTokenAccount {
+            address: Pubkey::new_unique().to_string(),
+            mint: Pubkey::new_unique().to_string(),
+            reason: "frozen".to_string(),
+        };
+        checks.push(EmptyAccountCheck::Skipped(skipped.clone()));
+
+        let plan = plan_close_accounts(checks, &owner, Some(3));
+        let report = plan.report(true, vec![]);
+
+        assert_eq!(plan.batches.len(), 1);
+        assert_eq!(report.closed, 3);
+        assert_eq!(report.reclaimed_sol, lamports_to_sol(3 * RENT));
+        assert_eq!(report.skipped, vec![skipped]);
+        assert!(report.dry_run && report.transactions.is_empty());
+    }
+}
@@ -0,0 +1,383 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_program;
+use solana_sdk::transaction::{Transaction, VersionedTransaction};
+use std::str::FromStr;
+
+pub const BUBBLEGUM_PROGRAM_ID: &str =
+    "BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY";
+pub const SPL_NOOP_PROGRAM_ID: &str =
+    "noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV";
+pub const SPL_ACCOUNT_COMPRESSION_PROGRAM_ID: &str =
+    "cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK";
+/// the first 8 bytes of sha256 of "global:transfer"
+pub const BUBBLEGUM_TRANSFER_METHOD: [u8; 8] =
+    [0xa3, 0x34, 0xc8, 0xe7, 0x8c, 0x03, 0x45, 0xba];
+
+/// account_type(1) version(1) max_buffer_size(4) max_depth(4)
+/// authority(32) creation_slot(8) is_batch_initialized(1) padding(5)
+const MERKLE_TREE_HEADER_LEN: usize = 56;
+
+/// the leaf of a compressed nft as the DAS api reports it
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressedAsset {
+    pub owner: Pubkey,
+    /// the owner when nothing is delegated
+    pub delegate: Pubkey,
+    pub tree: Pubkey,
+    pub data_hash: [u8; 32],
+    pub creator_hash: [u8; 32],
+    pub leaf_id: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetProof {
+    pub root: [u8; 32],
+    /// from the leaf up, the nodes kept in the canopy of the tree included
+    pub proof: Vec<Pubkey>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AssetResponse {
+    compression: Compression,
+    ownership: Ownership,
+}
+
+#[derive(Debug, Deserialize)]
+struct Compression {
+    compressed: bool,
+    data_hash: String,
+    creator_hash: String,
+    leaf_id: u64,
+    tree: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Ownership {
+    owner: String,
+    delegate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AssetProofResponse {
+    root: String,
+    proof: Vec<String>,
+}
+
+fn decode_hash(hash: &str) -> Result<[u8; 32]> {
+    bs58::decode(hash)
+        .into_vec()?
+        .try_into()
+        .map_err(|_| anyhow!("{} is not a 32 byte hash", hash))
+}
+
+/// Digital Asset Standard api of an rpc that indexes compressed nfts,
+/// e.g. Helius
+#[derive(Debug, Clone)]
+pub struct DasClient {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl DasClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+        }
+    }
+
+    async fn call<T: for<'de> Deserialize<'de>>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<T> {
+        let body = self
+            .client
+            .post(&self.url)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await?
+            .json::<Value>()
+            .await?;
+        if let Some(error) = body.get("error") {
+            return Err(anyhow!("DAS {} failed: {}", method, error));
+        }
+        let result = body
+            .get("result")
+            .cloned()
+            .ok_or_else(|| anyhow!("DAS {} returned no result", method))?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    pub async fn compressed_asset(
+        &self,
+        asset_id: &str,
+    ) -> Result<CompressedAsset> {
+        let asset = self
+            .call::<AssetResponse>("getAsset", json!({ "id": asset_id }))
+            .await?;
+        if !asset.compression.compressed {
+            return Err(anyhow!(
+                "{} is not a compressed nft, transfer it with \
+                transfer_spl_token",
+                asset_id
+            ));
+        }
+        let owner = Pubkey::from_str(&asset.ownership.owner)?;
+        Ok(CompressedAsset {
+            owner,
+            delegate: match asset.ownership.delegate {
+                Some(delegate) => Pubkey::from_str(&delegate)?,
+                None => owner,
+            },
+            tree: Pubkey::from_str(&asset.compression.tree)?,
+            data_hash: decode_hash(&asset.compression.data_hash)?,
+            creator_hash: decode_hash(&asset.compression.creator_hash)?,
+            leaf_id: asset.compression.leaf_id,
+        })
+    }
+
+    pub async fn asset_proof(&self, asset_id: &str) -> Result<AssetProof> {
+        let proof = self
+            .call::<AssetProofResponse>(
+                "getAssetProof",
+                json!({ "id": asset_id }),
+            )
+            .await?;
+        Ok(AssetProof {
+            root: decode_hash(&proof.root)?,
+            proof: proof
+                .proof
+                .iter()
+                .map(|node| Ok(Pubkey::from_str(node)?))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+/// how many levels of the tree are kept on-chain in its canopy, the
+/// proof doesn't need to pass those
+pub fn canopy_depth(tree_data: &[u8]) -> Result<usize> {
+    let read_u32 = |offset: usize| -> Result<usize> {
+        Ok(u32::from_le_bytes(
+            tree_data
+                .get(offset..offset + 4)
+                .ok_or_else(|| anyhow!("merkle tree account too short"))?
+                .try_into()?,
+        ) as usize)
+    };
+    let (max_buffer_size, max_depth) = (read_u32(2)?, read_u32(6)?);
+    // sequence_number, active_index and buffer_size, then the change log
+    // of root, path, index and padding per buffered change, then the
+    // rightmost proof, leaf, index and padding
+    let path = 32 * max_depth + 40;
+    let tree_len = 24 + max_buffer_size * path + path;
+    let canopy_len = tree_data
+        .len()
+        .checked_sub(MERKLE_TREE_HEADER_LEN + tree_len)
+        .ok_or_else(|| anyhow!("merkle tree account too short"))?;
+    // a canopy of depth d holds 2^(d+1) - 2 nodes
+    Ok(((canopy_len / 32 + 2).ilog2() - 1) as usize)
+}
+
+/// the bubblegum transfer of `asset` to `new_owner`, signed by the owner
+pub fn transfer_cnft_ix(
+    asset: &CompressedAsset,
+    proof: &AssetProof,
+    canopy_depth: usize,
+    new_owner: &Pubkey,
+) -> Result<Instruction> {
+    let program_id = Pubkey::from_str(BUBBLEGUM_PROGRAM_ID)?;
+    let tree_authority =
+        Pubkey::find_program_address(&[asset.tree.as_ref()], &program_id).0;
+    let mut data = BUBBLEGUM_TRANSFER_METHOD.to_vec();
+    data.extend(proof.root);
+    data.extend(asset.data_hash);
+    data.extend(asset.creator_hash);
+    data.extend(asset.leaf_id.to_le_bytes());
+    data.extend((asset.leaf_id as u32).to_le_bytes());
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(tree_authority, false),
+        AccountMeta::new_readonly(asset.owner, true),
+        AccountMeta::new_readonly(asset.delegate, false),
+        AccountMeta::new_readonly(*new_owner, false),
+        AccountMeta::new(asset.tree, false),
+        AccountMeta::new_readonly(
+            Pubkey::from_str(SPL_NOOP_PROGRAM_ID)?,
+            false,
+        ),
+        AccountMeta::new_readonly(
+            Pubkey::from_str(SPL_ACCOUNT_COMPRESSION_PROGRAM_ID)?,
+            false,
+        ),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    let proof_len = proof.proof.len().saturating_sub(canopy_depth);
+    accounts.extend(
+        proof.proof[..proof_len]
+            .iter()
+            .map(|node| AccountMeta::new_readonly(*node, false)),
+    );
+    Ok(Instruction::new_with_bytes(program_id, &data, accounts))
+}
+
+pub async fn create_transfer_cnft_tx(
+    das: &DasClient,
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    asset_id: &str,
+    recipient: &Pubkey,
+) -> Result<VersionedTransaction> {
+    let asset = das.compressed_asset(asset_id).await?;
+    if asset.owner != *owner {
+        return Err(anyhow!(
+            "{} is owned by {}, not {}",
+            asset_id,
+            asset.owner,
+            owner
+        ));
+    }
+    let proof = das.asset_proof(asset_id).await?;
+    let tree = rpc_client.get_account(&asset.tree).await?;
+    let instruction = transfer_cnft_ix(
+        &asset,
+        &proof,
+        canopy_depth(&tree.data)?,
+        recipient,
+    )?;
+    Ok(Transaction::new_with_payer(&[instruction], Some(owner)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(byte: u8) -> String {
+        bs58::encode([byte; 32]).into_string()
+    }
+
+    fn tree_data(
+        max_depth: u32,
+        max_buffer_size: u32,
+        canopy: u32,
+    ) -> Vec<u8> {
+        let mut data = vec![1, 0];
+        data.extend(max_buffer_size.to_le_bytes());
+        data.extend(max_depth.to_le_bytes());
+        let path = 32 * max_depth + 40;
+        let len = MERKLE_TREE_HEADER_LEN as u32
+            + 24
+            + max_buffer_size * path
+            + path
+            + 32 * ((1 << (canopy + 1)) - 2);
+        data.resize(len as usize, 0);
+        data
+    }
+
+    #[test]
+    fn test_canopy_depth() {
+        assert_eq!(canopy_depth(&tree_data(14, 64, 0)).unwrap(), 0);
+        assert_eq!(canopy_depth(&tree_data(14, 64, 10)).unwrap(), 10);
+        assert_eq!(canopy_depth(&tree_data(20, 256, 14)).unwrap(), 14);
+        assert!(canopy_depth(&[1, 0]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transfer_cnft_ix() {
+        let (owner, tree, recipient) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let proof = (0..5).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/")
+            .match_body(mockito::Matcher::PartialJson(
+                json!({ "method": "getAsset" }),
+            ))
+            .with_body(
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "result": {
+                        "id": "asset",
+                        "compression": {
+                            "compressed": true,
+                            "data_hash": hash(1),
+                            "creator_hash": hash(2),
+                            "leaf_id": 7,
+                            "tree": tree.to_string(),
+                        },
+                        "ownership": {
+                            "owner": owner.to_string(),
+                            "delegate": null,
+                        },
+                    },
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/")
+            .match_body(mockito::Matcher::PartialJson(
+                json!({ "method": "getAssetProof" }),
+            ))
+            .with_body(
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "result": {
+                        "root": hash(3),
+                        "proof": proof
+                            .iter()
+                            .map(|node| node.to_string())
+                            .collect::<Vec<_>>(),
+                        "node_index": 16391,
+                        "tree_id": tree.to_string(),
+                    },
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let das = DasClient::new(&server.url());
+        let asset = das.compressed_asset("asset").await.unwrap();
+        assert_eq!(asset.delegate, owner);
+        let ix = transfer_cnft_ix(
+            &asset,
+            &das.asset_proof("asset").await.unwrap(),
+            3,
+            &recipient,
+        )
+        .unwrap();
+        let mut data = BUBBLEGUM_TRANSFER_METHOD.to_vec();
+        data.extend([3; 32]);
+        data.extend([1; 32]);
+        data.extend([2; 32]);
+        data.extend(7u64.to_le_bytes());
+        data.extend(7u32.to_le_bytes());
+        assert_eq!(ix.data, data);
+        // the top 3 levels of the proof are in the canopy
+        assert_eq!(ix.accounts.len(), 8 + 2);
+        assert_eq!(ix.accounts[1].pubkey, owner);
+        assert!(ix.accounts[1].is_signer);
+        assert_eq!(ix.accounts[3].pubkey, recipient);
+        assert!(ix.accounts[4].is_writable);
+        assert_eq!(ix.accounts[8].pubkey, proof[0]);
+        assert_eq!(ix.accounts[9].pubkey, proof[1]);
+    }
+}
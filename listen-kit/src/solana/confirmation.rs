@@ -0,0 +1,567 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use solana_sdk::hash::Hash;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::TransactionError;
+use solana_transaction_status::{
+    TransactionConfirmationStatus, TransactionStatus,
+};
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+
+const DEFAULT_CONFIRMATION_TIMEOUT_SECS: u64 = 90;
+const DEFAULT_POLL_INTERVAL_MS: u64 = 1_000;
+const DEFAULT_MAX_REBUILDS: u32 = 2;
+const DEFAULT_PRIORITY_FEE_BUMP_PCT: u16 = 25;
+/// a bump past this doesn't land anything a smaller one wouldn't
+pub const MAX_PRIORITY_FEE_BUMP_PCT: u16 = 1_000;
+/// more rebuilds than this mean the RPC or the fees are off, not bad luck
+pub const MAX_REBUILDS: u32 = 10;
+
+/// how sent transactions are confirmed, at the commitment of the chain
+/// config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfirmationConfig {
+    /// how long a sent transaction gets to confirm, about as long as a
+    /// blockhash stays valid
+    pub timeout_secs: u64,
+    pub poll_interval_ms: u64,
+    /// how many times a transaction whose blockhash expired before it landed
+    /// is built again with a fresh one, only for tools that can rebuild it
+    pub max_rebuilds: u32,
+    /// the transactions of tools that can't rebuild them are signed again
+    /// with a fresh blockhash instead, as often as `max_rebuilds`
+    pub resign_expired: bool,
+    /// how much more compute unit price each attempt after the first pays,
+    /// in percent of the one before, so retries land under congestion
+    pub priority_fee_bump_pct: u16,
+}
+
+impl Default for ConfirmationConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: DEFAULT_CONFIRMATION_TIMEOUT_SECS,
+            poll_interval_ms: DEFAULT_POLL_INTERVAL_MS,
+            max_rebuilds: DEFAULT_MAX_REBUILDS,
+            resign_expired: false,
+            priority_fee_bump_pct: DEFAULT_PRIORITY_FEE_BUMP_PCT,
+        }
+    }
+}
+
+impl ConfirmationConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.poll_interval_ms == 0 {
+            return Err(anyhow!("the confirmation poll interval can't be 0"));
+        }
+        if self.max_rebuilds > MAX_REBUILDS {
+            return Err(anyhow!(
+                "transactions can be rebuilt at most {} times",
+                MAX_REBUILDS
+            ));
+        }
+        if self.priority_fee_bump_pct > MAX_PRIORITY_FEE_BUMP_PCT {
+            return Err(anyhow!(
+                "the priority fee can be bumped by at most {}%",
+                MAX_PRIORITY_FEE_BUMP_PCT
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The transaction landed but failed, nothing it did was applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionFailed {
+    pub signature: String,
+    pub slot: u64,
+    pub error: TransactionError,
+}
+
+impl fmt::Display for TransactionFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Transaction {} failed in slot {}: {}",
+            self.signature, self.slot, self.error
+        )
+    }
+}
+
+impl std::error::Error for TransactionFailed {}
+
+/// Still unconfirmed when the timeout ran out while its blockhash (or
+/// durable nonce) is valid, so it may land later and is not rebuilt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfirmationTimeout {
+    pub signature: String,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for ConfirmationTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Transaction {} was not confirmed within {}s and may still land, \
+            check the signature before sending it again",
+            self.signature,
+            self.timeout.as_secs()
+        )
+    }
+}
+
+impl std::error::Error for ConfirmationTimeout {}
+
+/// None of the attempts landed before its blockhash expired, nothing was
+/// applied and it is safe to try again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockhashExpired {
+    /// of the last attempt
+    pub signature: String,
+    pub attempts: u32,
+}
+
+impl fmt::Display for BlockhashExpired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Transaction {} expired without landing after {} attempt(s), \
+            nothing was applied, it is safe to try again",
+            self.signature, self.attempts
+        )
+    }
+}
+
+impl std::error::Error for BlockhashExpired {}
+
+/// the calls confirmation needs, an `RpcClient` outside of tests
+#[async_trait::async_trait]
+pub trait ConfirmationRpc: Send + Sync {
+    async fn signature_status(
+        &self,
+        signature: &Signature,
+    ) -> Result<Option<TransactionStatus>>;
+
+    async fn is_blockhash_valid(
+        &self,
+        blockhash: &Hash,
+        commitment: CommitmentConfig,
+    ) -> Result<bool>;
+}
+
+#[async_trait::async_trait]
+impl ConfirmationRpc for RpcClient {
+    async fn signature_status(
+        &self,
+        signature: &Signature,
+    ) -> Result<Option<TransactionStatus>> {
+        Ok(self
+            .get_signature_statuses(&[*signature])
+            .await?
+            .value
+            .pop()
+            .flatten())
+    }
+
+    async fn is_blockhash_valid(
+        &self,
+        blockhash: &Hash,
+        commitment: CommitmentConfig,
+    ) -> Result<bool> {
+        Ok(
+            RpcClient::is_blockhash_valid(self, blockhash, commitment)
+                .await?,
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Confirmation {
+    Confirmed {
+        slot: u64,
+        status: TransactionConfirmationStatus,
+    },
+    /// the blockhash expired before the transaction landed, it never will
+    Expired,
+}
+
+fn landed(
+    signature: &Signature,
+    status: TransactionStatus,
+    commitment: CommitmentConfig,
+) -> Result<Option<Confirmation>> {
+    if let Some(error) = status.err.clone() {
+        return Err(TransactionFailed {
+            signature: signature.to_string(),
+            slot: status.slot,
+            error,
+        }
+        .into());
+    }
+    Ok(status.satisfies_commitment(commitment).then(|| {
+        Confirmation::Confirmed {
+            slot: status.slot,
+            status: status.confirmation_status(),
+        }
+    }))
+}
+
+/// waits for `signature` to reach `commitment`; `blockhash` is the recent
+/// blockhash the transaction was sent with, `None` for a durable nonce,
+/// which doesn't expire
+pub async fn confirm_transaction(
+    rpc: &impl ConfirmationRpc,
+    signature: &Signature,
+    blockhash: Option<&Hash>,
+    commitment: CommitmentLevel,
+    config: &ConfirmationConfig,
+) -> Result<Confirmation> {
+    let commitment = CommitmentConfig { commitment };
+    let timeout = Duration::from_secs(config.timeout_secs);
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = rpc.signature_status(signature).await? {
+            if let Some(confirmation) = landed(signature, status, commitment)?
+            {
+                return Ok(confirmation);
+            }
+        } else if let Some(blockhash) = blockhash {
+            // it could have landed right before the blockhash expired, the
+            // next round picks its status up then
+            if !rpc.is_blockhash_valid(blockhash, commitment).await?
+                && rpc.signature_status(signature).await?.is_none()
+            {
+                return Ok(Confirmation::Expired);
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err(ConfirmationTimeout {
+                signature: signature.to_string(),
+                timeout,
+            }
+            .into());
+        }
+        tokio::time::sleep(Duration::from_millis(config.poll_interval_ms))
+            .await;
+    }
+}
+
+/// a sent attempt of `land`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentAttempt<T> {
+    pub signature: Signature,
+    /// `None` for a durable nonce, see `confirm_transaction`
+    pub blockhash: Option<Hash>,
+    /// whatever the caller reports with the landed transaction
+    pub sent: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Landed<T> {
+    pub signature: Signature,
+    pub slot: u64,
+    pub status: TransactionConfirmationStatus,
+    /// 1 when the first attempt landed
+    pub attempts: u32,
+    pub sent: T,
+}
+
+/// builds, signs and sends with `attempt` until one confirms, up to
+/// `max_attempts` times. Only an expired blockhash leads to another attempt,
+/// as the earlier one can't land anymore; `attempt` has to build the
+/// transaction again from scratch, re-quoting swaps, rather than re-signing
+/// the same one
+pub async fn land<T, A, Fut>(
+    rpc: &impl ConfirmationRpc,
+    commitment: CommitmentLevel,
+    config: &ConfirmationConfig,
+    max_attempts: u32,
+    mut attempt: A,
+) -> Result<Landed<T>>
+where
+    A: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<SentAttempt<T>>>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let SentAttempt {
+            signature,
+            blockhash,
+            sent,
+        } = attempt(attempts).await?;
+        let confirmation = confirm_transaction(
+            rpc,
+            &signature,
+            blockhash.as_ref(),
+            commitment,
+            config,
+        )
+        .await?;
+        match confirmation {
+            Confirmation::Confirmed { slot, status } => {
+                return Ok(Landed {
+                    signature,
+                    slot,
+                    status,
+                    attempts,
+                    sent,
+                })
+            }
+            Confirmation::Expired if attempts < max_attempts => {
+                tracing::warn!(
+                    %signature,
+                    attempts,
+                    "blockhash expired before the transaction landed, \
+                    building it again"
+                );
+            }
+            Confirmation::Expired => {
+                return Err(BlockhashExpired {
+                    signature: signature.to_string(),
+                    attempts,
+                }
+                .into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    fn config() -> ConfirmationConfig {
+        ConfirmationConfig {
+            timeout_secs: 5,
+            poll_interval_ms: 1,
+            max_rebuilds: 2,
+            ..Default::default()
+        }
+    }
+
+    fn confirmed_status(slot: u64) -> TransactionStatus {
+        TransactionStatus {
+            slot,
+            confirmations: None,
+            status: Ok(()),
+            err: None,
+            confirmation_status: Some(
+                TransactionConfirmationStatus::Confirmed,
+            ),
+        }
+    }
+
+    /// statuses per signature and whether every blockhash is still valid
+    #[derive(Default)]
+    struct ScriptedRpc {
+        statuses: Mutex<HashMap<Signature, TransactionStatus>>,
+        blockhash_valid: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl ConfirmationRpc for ScriptedRpc {
+        async fn signature_status(
+            &self,
+            signature: &Signature,
+        ) -> Result<Option<TransactionStatus>> {
+            Ok(self.statuses.lock().unwrap().get(signature).cloned())
+        }
+
+        async fn is_blockhash_valid(
+            &self,
+            _blockhash: &Hash,
+            _commitment: CommitmentConfig,
+        ) -> Result<bool> {
+            Ok(self.blockhash_valid)
+        }
+    }
+
+    fn sent(signature: Signature) -> SentAttempt<()> {
+        SentAttempt {
+            signature,
+            blockhash: Some(Hash::new_unique()),
+            sent: (),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_first_try() {
+        let rpc = RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([(
+                RpcRequest::GetSignatureStatuses,
+                json!({
+                    "context": { "slot": 43 },
+                    "value": [{
+                        "slot": 42,
+                        "confirmations": 1,
+                        "status": { "Ok": null },
+                        "err": null,
+                        "confirmationStatus": "confirmed",
+                    }],
+                }),
+            )]),
+        );
+        let mut sends = 0;
+        let landed =
+            land(&rpc, CommitmentLevel::Confirmed, &config(), 3, |_| {
+                sends += 1;
+                async { Ok(sent(Signature::new_unique())) }
+            })
+            .await
+            .unwrap();
+        assert_eq!(sends, 1);
+        assert_eq!(landed.attempts, 1);
+        assert_eq!(landed.slot, 42);
+        assert_eq!(landed.status, TransactionConfirmationStatus::Confirmed);
+    }
+
+    #[tokio::test]
+    async fn test_expired_then_rebuilt() {
+        // the first attempt never lands, the rebuilt one does
+        let rebuilt = Signature::new_unique();
+        let rpc = ScriptedRpc {
+            statuses: Mutex::new(HashMap::from([(
+                rebuilt,
+                confirmed_status(7),
+            )])),
+            blockhash_valid: false,
+        };
+        let landed = land(
+            &rpc,
+            CommitmentLevel::Confirmed,
+            &config(),
+            3,
+            |attempt| async move {
+                Ok(sent(if attempt == 1 {
+                    Signature::new_unique()
+                } else {
+                    rebuilt
+                }))
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(landed.attempts, 2);
+        assert_eq!(landed.signature, rebuilt);
+        assert_eq!(landed.slot, 7);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let rpc = ScriptedRpc::default();
+        let mut sends = 0;
+        let err =
+            land(&rpc, CommitmentLevel::Confirmed, &config(), 3, |_| {
+                sends += 1;
+                async { Ok(sent(Signature::new_unique())) }
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(sends, 3);
+        let err = err.downcast::<BlockhashExpired>().unwrap();
+        assert_eq!(err.attempts, 3);
+        assert!(err.to_string().contains("safe to try again"));
+    }
+
+    #[tokio::test]
+    async fn test_valid_blockhash_times_out_without_rebuild() {
+        let rpc = ScriptedRpc {
+            blockhash_valid: true,
+            ..Default::default()
+        };
+        let config = ConfirmationConfig {
+            timeout_secs: 0,
+            ..config()
+        };
+        let mut sends = 0;
+        let err = land(&rpc, CommitmentLevel::Confirmed, &config, 3, |_| {
+            sends += 1;
+            async { Ok(sent(Signature::new_unique())) }
+        })
+        .await
+        .unwrap_err();
+        // it may still land, sending another one could apply it twice
+        assert_eq!(sends, 1);
+        assert!(err.downcast_ref::<ConfirmationTimeout>().is_some());
+    }
+
+    /// no status on the first look, confirmed from then on
+    struct LateRpc {
+        looks: Mutex<u32>,
+    }
+
+    #[async_trait::async_trait]
+    impl ConfirmationRpc for LateRpc {
+        async fn signature_status(
+            &self,
+            _signature: &Signature,
+        ) -> Result<Option<TransactionStatus>> {
+            let mut looks = self.looks.lock().unwrap();
+            *looks += 1;
+            Ok((*looks > 1).then(|| confirmed_status(3)))
+        }
+
+        async fn is_blockhash_valid(
+            &self,
+            _blockhash: &Hash,
+            _commitment: CommitmentConfig,
+        ) -> Result<bool> {
+            Ok(false)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_landed_as_blockhash_expired_is_not_resent() {
+        let rpc = LateRpc {
+            looks: Mutex::new(0),
+        };
+        let mut sends = 0;
+        let landed =
+            land(&rpc, CommitmentLevel::Confirmed, &config(), 3, |_| {
+                sends += 1;
+                async { Ok(sent(Signature::new_unique())) }
+            })
+            .await
+            .unwrap();
+        assert_eq!(sends, 1);
+        assert_eq!(landed.slot, 3);
+    }
+
+    #[tokio::test]
+    async fn test_failed_transaction_is_an_error() {
+        let signature = Signature::new_unique();
+        let rpc = ScriptedRpc {
+            statuses: Mutex::new(HashMap::from([(
+                signature,
+                TransactionStatus {
+                    err: Some(TransactionError::AccountInUse),
+                    status: Err(TransactionError::AccountInUse),
+                    ..confirmed_status(9)
+                },
+            )])),
+            blockhash_valid: true,
+        };
+        let err = confirm_transaction(
+            &rpc,
+            &signature,
+            None,
+            CommitmentLevel::Confirmed,
+            &config(),
+        )
+        .await
+        .unwrap_err();
+        let err = err.downcast::<TransactionFailed>().unwrap();
+        assert_eq!(err.slot, 9);
+    }
+}
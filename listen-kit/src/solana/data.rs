@@ -49,16 +49,16 @@ pub struct PriceResponse {
     data: std::collections::HashMap<String, Option<PriceData>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PortfolioItem {
-    address: String,
+    pub(crate) address: String,
     name: String,
-    symbol: String,
+    pub(crate) symbol: String,
     decimals: u8,
     #[serde(rename = "logoURI")]
     logo_uri: String,
-    price: f64,
-    amount: f64,
+    pub(crate) price: f64,
+    pub(crate) amount: f64,
     daily_volume: f64,
 }
 
@@ -0,0 +1,580 @@
+use anyhow::{anyhow, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcProgramAccountsConfig,
+};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_program;
+use solana_sdk::transaction::{Transaction, VersionedTransaction};
+use spl_associated_token_account::get_associated_token_address;
+use std::str::FromStr;
+
+use crate::solana::constants::WSOL;
+use crate::solana::wsol::{wrap_sol_ixs, wsol_address};
+
+pub const DCA_PROGRAM_ID: &str =
+    "DCA265Vj8a9CEuX1eb1LWJzR7H4C3kHnCjWiE8Bp2xYR";
+/// the dca program refuses positions of a single cycle
+pub const MIN_DCA_CYCLES: u32 = 2;
+/// the shortest cycle jupiter accepts
+pub const MIN_DCA_CYCLE_SECONDS: u64 = 60;
+
+/// anchor discriminators, the first 8 bytes of sha256 of
+/// "global:open_dca_v2", "global:close_dca" and "account:Dca"
+pub const OPEN_DCA_V2_METHOD: [u8; 8] =
+    [0x8e, 0x77, 0x2b, 0x6d, 0xa2, 0x34, 0x0b, 0xb1];
+pub const CLOSE_DCA_METHOD: [u8; 8] =
+    [0x16, 0x07, 0x21, 0x62, 0xa8, 0xb7, 0x22, 0xf3];
+const DCA_ACCOUNT: [u8; 8] = [0x52, 0x5d, 0x5a, 0x7f, 0x28, 0x65, 0x91, 0x9a];
+
+/// spends `amount_per_cycle` of `input_mint` on `output_mint` every
+/// `cycle_seconds`, `num_cycles` times; amounts are raw
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DcaSchedule {
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount_per_cycle: u64,
+    pub cycle_seconds: u64,
+    pub num_cycles: u32,
+}
+
+impl DcaSchedule {
+    pub fn validate(&self) -> Result<()> {
+        if self.input_mint == self.output_mint {
+            return Err(anyhow!("a dca needs two different mints"));
+        }
+        if self.num_cycles < MIN_DCA_CYCLES {
+            return Err(anyhow!(
+                "a dca needs at least {} cycles, got {}",
+                MIN_DCA_CYCLES,
+                self.num_cycles
+            ));
+        }
+        if self.cycle_seconds < MIN_DCA_CYCLE_SECONDS {
+            return Err(anyhow!(
+                "a dca cycle is at least {} seconds, got {}",
+                MIN_DCA_CYCLE_SECONDS,
+                self.cycle_seconds
+            ));
+        }
+        if self.amount_per_cycle == 0 {
+            return Err(anyhow!("amount_per_cycle has to be positive"));
+        }
+        self.total_amount().map(|_| ())
+    }
+
+    /// what is escrowed in the position up front
+    pub fn total_amount(&self) -> Result<u64> {
+        self.amount_per_cycle
+            .checked_mul(self.num_cycles as u64)
+            .ok_or_else(|| anyhow!("the dca total doesn't fit a u64"))
+    }
+
+    pub fn duration_seconds(&self) -> u64 {
+        // the first cycle runs right away
+        self.cycle_seconds * (self.num_cycles as u64 - 1)
+    }
+}
+
+#[derive(Debug, BorshSerialize)]
+struct OpenDcaV2Args {
+    application_idx: u64,
+    in_amount: u64,
+    in_amount_per_cycle: u64,
+    cycle_frequency: i64,
+    min_out_amount: Option<u64>,
+    max_out_amount: Option<u64>,
+    start_at: Option<i64>,
+}
+
+/// the `Dca` account after its discriminator
+#[derive(Debug, BorshDeserialize)]
+struct DcaAccount {
+    user: [u8; 32],
+    input_mint: [u8; 32],
+    output_mint: [u8; 32],
+    _idx: u64,
+    next_cycle_at: i64,
+    in_deposited: u64,
+    in_withdrawn: u64,
+    out_withdrawn: u64,
+    in_used: u64,
+    out_received: u64,
+    in_amount_per_cycle: u64,
+    cycle_frequency: i64,
+    _next_cycle_amount_left: u64,
+    _in_account: [u8; 32],
+    _out_account: [u8; 32],
+    _min_out_amount: u64,
+    _max_out_amount: u64,
+    _keeper_in_balance_before_borrow: u64,
+    _dca_out_balance_before_swap: u64,
+    created_at: i64,
+}
+
+/// an open dca position and how far it got, amounts are raw
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DcaPosition {
+    pub dca_account: String,
+    pub input_mint: String,
+    pub output_mint: String,
+    pub input_deposited: u64,
+    pub input_used: u64,
+    /// input that closing the position returns
+    pub input_remaining: u64,
+    pub output_received: u64,
+    /// output that closing the position returns
+    pub output_remaining: u64,
+    pub amount_per_cycle: u64,
+    pub cycle_seconds: u64,
+    pub cycles_filled: u64,
+    pub num_cycles: u64,
+    /// unix seconds
+    pub next_cycle_at: i64,
+    pub created_at: i64,
+}
+
+impl DcaPosition {
+    pub fn from_account_data(address: &Pubkey, data: &[u8]) -> Result<Self> {
+        if data.get(..8) != Some(&DCA_ACCOUNT[..]) {
+            return Err(anyhow!("{} is not a dca account", address));
+        }
+        let account = DcaAccount::deserialize(&mut &data[8..])?;
+        let (cycles_filled, num_cycles) = match account.in_amount_per_cycle {
+            0 => (0, 0),
+            per_cycle => (
+                account.in_used / per_cycle,
+                account.in_deposited.div_ceil(per_cycle),
+            ),
+        };
+        Ok(Self {
+            dca_account: address.to_string(),
+            input_mint: Pubkey::new_from_array(account.input_mint)
+                .to_string(),
+            output_mint: Pubkey::new_from_array(account.output_mint)
+                .to_string(),
+            input_deposited: account.in_deposited,
+            input_used: account.in_used,
+            input_remaining: account
+                .in_deposited
+                .saturating_sub(account.in_used)
+                .saturating_sub(account.in_withdrawn),
+            output_received: account.out_received,
+            output_remaining: account
+                .out_received
+                .saturating_sub(account.out_withdrawn),
+            amount_per_cycle: account.in_amount_per_cycle,
+            cycle_seconds: account.cycle_frequency as u64,
+            cycles_filled,
+            num_cycles,
+            next_cycle_at: account.next_cycle_at,
+            created_at: account.created_at,
+        })
+    }
+
+    pub fn user(data: &[u8]) -> Option<Pubkey> {
+        DcaAccount::deserialize(&mut data.get(8..)?)
+            .ok()
+            .map(|account| Pubkey::new_from_array(account.user))
+    }
+}
+
+fn program_id() -> Pubkey {
+    Pubkey::from_str(DCA_PROGRAM_ID).unwrap()
+}
+
+fn event_authority() -> Pubkey {
+    Pubkey::find_program_address(&[b"__event_authority"], &program_id()).0
+}
+
+/// `idx` tells apart the positions of a user between the same mints
+pub fn dca_address(
+    user: &Pubkey,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+    idx: u64,
+) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"dca",
+            user.as_ref(),
+            input_mint.as_ref(),
+            output_mint.as_ref(),
+            &idx.to_le_bytes(),
+        ],
+        &program_id(),
+    )
+    .0
+}
+
+/// moves the whole schedule from the token account of `user` into the
+/// escrow of a new position at `dca_address(.., idx)`
+pub fn open_dca_ix(
+    user: &Pubkey,
+    schedule: &DcaSchedule,
+    idx: u64,
+) -> Result<Instruction> {
+    schedule.validate()?;
+    let dca =
+        dca_address(user, &schedule.input_mint, &schedule.output_mint, idx);
+    let args = OpenDcaV2Args {
+        application_idx: idx,
+        in_amount: schedule.total_amount()?,
+        in_amount_per_cycle: schedule.amount_per_cycle,
+        cycle_frequency: schedule.cycle_seconds as i64,
+        min_out_amount: None,
+        max_out_amount: None,
+        start_at: None,
+    };
+    let mut data = OPEN_DCA_V2_METHOD.to_vec();
+    data.extend(borsh::to_vec(&args)?);
+    Ok(Instruction::new_with_bytes(
+        program_id(),
+        &data,
+        vec![
+            AccountMeta::new(dca, false),
+            AccountMeta::new_readonly(*user, true),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(schedule.input_mint, false),
+            AccountMeta::new_readonly(schedule.output_mint, false),
+            AccountMeta::new(
+                get_associated_token_address(user, &schedule.input_mint),
+                false,
+            ),
+            AccountMeta::new(
+                get_associated_token_address(&dca, &schedule.input_mint),
+                false,
+            ),
+            AccountMeta::new(
+                get_associated_token_address(&dca, &schedule.output_mint),
+                false,
+            ),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(
+                spl_associated_token_account::id(),
+                false,
+            ),
+            AccountMeta::new_readonly(event_authority(), false),
+            AccountMeta::new_readonly(program_id(), false),
+        ],
+    ))
+}
+
+/// closes the position, what is left of the input and the output it
+/// holds go back to the token accounts of `user`
+pub fn close_dca_ix(
+    user: &Pubkey,
+    dca: &Pubkey,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        program_id(),
+        &CLOSE_DCA_METHOD,
+        vec![
+            AccountMeta::new(*user, true),
+            AccountMeta::new(*dca, false),
+            AccountMeta::new_readonly(*input_mint, false),
+            AccountMeta::new_readonly(*output_mint, false),
+            AccountMeta::new(
+                get_associated_token_address(dca, input_mint),
+                false,
+            ),
+            AccountMeta::new(
+                get_associated_token_address(dca, output_mint),
+                false,
+            ),
+            AccountMeta::new(
+                get_associated_token_address(user, input_mint),
+                false,
+            ),
+            AccountMeta::new(
+                get_associated_token_address(user, output_mint),
+                false,
+            ),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(
+                spl_associated_token_account::id(),
+                false,
+            ),
+            AccountMeta::new_readonly(event_authority(), false),
+            AccountMeta::new_readonly(program_id(), false),
+        ],
+    )
+}
+
+/// the open dca transaction, SOL is wrapped into the token account the
+/// position is funded from
+pub fn create_open_dca_tx(
+    owner: &Pubkey,
+    schedule: &DcaSchedule,
+    idx: u64,
+) -> Result<VersionedTransaction> {
+    let open = open_dca_ix(owner, schedule, idx)?;
+    let mut instructions = Vec::new();
+    if schedule.input_mint.to_string() == WSOL {
+        instructions.extend(wrap_sol_ixs(owner, schedule.total_amount()?)?);
+    }
+    instructions.push(open);
+    Ok(Transaction::new_with_payer(&instructions, Some(owner)).into())
+}
+
+/// the close dca transaction, wrapped SOL it returns is unwrapped
+pub fn create_close_dca_tx(
+    owner: &Pubkey,
+    dca: &Pubkey,
+    position: &DcaPosition,
+) -> Result<VersionedTransaction> {
+    let input_mint = Pubkey::from_str(&position.input_mint)?;
+    let output_mint = Pubkey::from_str(&position.output_mint)?;
+    let mut instructions =
+        vec![close_dca_ix(owner, dca, &input_mint, &output_mint)];
+    if position.input_mint == WSOL || position.output_mint == WSOL {
+        instructions.push(spl_token::instruction::close_account(
+            &spl_token::id(),
+            &wsol_address(owner),
+            owner,
+            owner,
+            &[],
+        )?);
+    }
+    Ok(Transaction::new_with_payer(&instructions, Some(owner)).into())
+}
+
+pub async fn fetch_dca_position(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    dca: &Pubkey,
+) -> Result<DcaPosition> {
+    let account = rpc_client.get_account(dca).await?;
+    if account.owner != program_id() {
+        return Err(anyhow!("{} is not a dca account", dca));
+    }
+    if DcaPosition::user(&account.data) != Some(*owner) {
+        return Err(anyhow!("{} is not a dca of {}", dca, owner));
+    }
+    DcaPosition::from_account_data(dca, &account.data)
+}
+
+pub async fn fetch_dca_positions(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Vec<DcaPosition>> {
+    let accounts = rpc_client
+        .get_program_accounts_with_config(
+            &program_id(),
+            RpcProgramAccountsConfig {
+                filters: Some(vec![
+                    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                        0,
+                        DCA_ACCOUNT.to_vec(),
+                    )),
+                    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                        8,
+                        owner.to_bytes().to_vec(),
+                    )),
+                ]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await?;
+    let mut positions = accounts
+        .iter()
+        .map(|(address, account)| {
+            DcaPosition::from_account_data(address, &account.data)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    positions.sort_by_key(|position| position.created_at);
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+    fn schedule() -> DcaSchedule {
+        DcaSchedule {
+            input_mint: Pubkey::from_str(USDC).unwrap(),
+            output_mint: Pubkey::from_str(WSOL).unwrap(),
+            amount_per_cycle: 50_000_000,
+            cycle_seconds: 86_400,
+            num_cycles: 14,
+        }
+    }
+
+    #[test]
+    fn test_open_dca_ix() {
+        let user = Pubkey::new_unique();
+        let ix = open_dca_ix(&user, &schedule(), 1_700_000_000).unwrap();
+        // discriminator, idx, 14 * $50 in, $50 per cycle, daily, then
+        // no min out, max out or start
+        assert_eq!(
+            hex::encode(&ix.data),
+            "8e772b6da2340bb1\
+             00f1536500000000\
+             0027b92900000000\
+             80f0fa0200000000\
+             8051010000000000\
+             000000"
+        );
+        assert_eq!(ix.program_id, program_id());
+        assert_eq!(ix.accounts.len(), 13);
+        assert_eq!(
+            ix.accounts[0].pubkey,
+            dca_address(
+                &user,
+                &schedule().input_mint,
+                &schedule().output_mint,
+                1_700_000_000
+            )
+        );
+        assert!(ix.accounts[1].is_signer && ix.accounts[2].is_writable);
+    }
+
+    #[test]
+    fn test_discriminators() {
+        let discriminator = |name: &str| {
+            solana_sdk::hash::hash(name.as_bytes()).to_bytes()[..8].to_vec()
+        };
+        assert_eq!(
+            OPEN_DCA_V2_METHOD.to_vec(),
+            discriminator("global:open_dca_v2")
+        );
+        assert_eq!(
+            CLOSE_DCA_METHOD.to_vec(),
+            discriminator("global:close_dca")
+        );
+        assert_eq!(DCA_ACCOUNT.to_vec(), discriminator("account:Dca"));
+    }
+
+    #[test]
+    fn test_open_sol_dca_wraps_the_total() {
+        let user = Pubkey::new_unique();
+        let schedule = DcaSchedule {
+            input_mint: Pubkey::from_str(WSOL).unwrap(),
+            output_mint: Pubkey::from_str(USDC).unwrap(),
+            ..schedule()
+        };
+        let tx = create_open_dca_tx(&user, &schedule, 1).unwrap();
+        let expected = wrap_sol_ixs(&user, 700_000_000).unwrap();
+        let message = &tx.message;
+        // create the WSOL account, transfer, sync, then open
+        assert_eq!(message.instructions().len(), expected.len() + 1);
+        for (compiled, ix) in message.instructions().iter().zip(&expected) {
+            assert_eq!(compiled.data, ix.data);
+        }
+    }
+
+    #[test]
+    fn test_close_dca_ix() {
+        let (user, dca) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let ix = close_dca_ix(
+            &user,
+            &dca,
+            &schedule().input_mint,
+            &schedule().output_mint,
+        );
+        assert_eq!(hex::encode(&ix.data), "16072162a8b722f3");
+        assert_eq!(ix.accounts.len(), 13);
+        assert_eq!(ix.accounts[0].pubkey, user);
+        assert!(ix.accounts[0].is_signer);
+        assert_eq!(ix.accounts[1].pubkey, dca);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(schedule().validate().is_ok());
+        assert_eq!(schedule().total_amount().unwrap(), 700_000_000);
+        assert_eq!(schedule().duration_seconds(), 13 * 86_400);
+        for schedule in [
+            DcaSchedule {
+                cycle_seconds: 59,
+                ..schedule()
+            },
+            DcaSchedule {
+                num_cycles: 1,
+                ..schedule()
+            },
+            DcaSchedule {
+                amount_per_cycle: 0,
+                ..schedule()
+            },
+            DcaSchedule {
+                amount_per_cycle: u64::MAX,
+                ..schedule()
+            },
+            DcaSchedule {
+                output_mint: schedule().input_mint,
+                ..schedule()
+            },
+        ] {
+            assert!(schedule.validate().is_err(), "{:?}", schedule);
+        }
+    }
+
+    #[test]
+    fn test_from_account_data() {
+        let (user, dca) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = DCA_ACCOUNT.to_vec();
+        data.extend(user.to_bytes());
+        data.extend(schedule().input_mint.to_bytes());
+        data.extend(schedule().output_mint.to_bytes());
+        // idx, next_cycle_at, in_deposited, in_withdrawn, out_withdrawn,
+        // in_used, out_received, in_amount_per_cycle, cycle_frequency,
+        // next_cycle_amount_left
+        for field in [
+            1u64,
+            1_700_259_200,
+            700_000_000,
+            0,
+            100_000_000,
+            150_000_000,
+            1_000_000_000,
+            50_000_000,
+            86_400,
+            50_000_000,
+        ] {
+            data.extend(field.to_le_bytes());
+        }
+        data.extend([0; 64]);
+        data.extend([0; 32]);
+        data.extend(1_700_000_000i64.to_le_bytes());
+        data.push(255);
+
+        assert_eq!(DcaPosition::user(&data), Some(user));
+        assert_eq!(
+            DcaPosition::from_account_data(&dca, &data).unwrap(),
+            DcaPosition {
+                dca_account: dca.to_string(),
+                input_mint: USDC.to_string(),
+                output_mint: WSOL.to_string(),
+                input_deposited: 700_000_000,
+                input_used: 150_000_000,
+                input_remaining: 550_000_000,
+                output_received: 1_000_000_000,
+                output_remaining: 900_000_000,
+                amount_per_cycle: 50_000_000,
+                cycle_seconds: 86_400,
+                cycles_filled: 3,
+                num_cycles: 14,
+                next_cycle_at: 1_700_259_200,
+                created_at: 1_700_000_000,
+            }
+        );
+        data[0] = 0;
+        assert!(DcaPosition::from_account_data(&dca, &data).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use borsh::{BorshDeserialize, BorshSerialize};
 use log::debug;
 use rand::Rng;
@@ -15,8 +15,6 @@ use solana_sdk::{
 };
 use std::str::FromStr;
 
-use blockhash_cache::BLOCKHASH_CACHE;
-
 use crate::solana::{
     constants::{
         ASSOCIATED_TOKEN_PROGRAM, EVENT_AUTHORITY, PUMP_FUN_MINT_AUTHORITY,
@@ -49,9 +47,77 @@ pub struct DeployTokenParams {
     pub dev_buy: Option<u64>,
 }
 
+/// metaplex metadata limits, in bytes
+pub const MAX_NAME_LEN: usize = 32;
+pub const MAX_SYMBOL_LEN: usize = 10;
+
+impl DeployTokenParams {
+    /// checks the input up front, the pump.fun API fails late and without
+    /// saying what was wrong
+    pub fn validate(&self) -> Result<()> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("name cannot be empty"));
+        }
+        if name.len() > MAX_NAME_LEN {
+            return Err(anyhow!(
+                "name is {} bytes long, at most {} are allowed",
+                name.len(),
+                MAX_NAME_LEN
+            ));
+        }
+
+        let symbol = self.symbol.trim();
+        if symbol.is_empty() {
+            return Err(anyhow!("symbol cannot be empty"));
+        }
+        if symbol.len() > MAX_SYMBOL_LEN {
+            return Err(anyhow!(
+                "symbol is {} characters long, at most {} are allowed",
+                symbol.len(),
+                MAX_SYMBOL_LEN
+            ));
+        }
+        if !symbol.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(anyhow!(
+                "symbol {:?} can only contain letters A-Z and digits",
+                symbol
+            ));
+        }
+
+        if let Some(image_url) = &self.image_url {
+            let url = reqwest::Url::parse(image_url).map_err(|e| {
+                anyhow!("image_url {:?} is not a valid url: {}", image_url, e)
+            })?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(anyhow!(
+                    "image_url has to be an http(s) url from the internet, got {:?}",
+                    image_url
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// the dev buy is paid from the signer's SOL balance (lamports)
+pub fn check_dev_buy(dev_buy: u64, balance: u64) -> Result<()> {
+    if dev_buy > balance {
+        return Err(anyhow!(
+            "dev_buy of {} SOL exceeds the wallet balance of {} SOL",
+            dev_buy as f64 / 1e9,
+            balance as f64 / 1e9
+        ));
+    }
+    Ok(())
+}
+
+/// the keypair of `mint` has to sign the transaction as well
 pub async fn create_deploy_token_tx(
     params: DeployTokenParams,
     owner: &Pubkey,
+    mint: Pubkey,
 ) -> Result<VersionedTransaction> {
     let res = create_launch_tx(
         &IPFSMetaForm {
@@ -65,6 +131,7 @@ pub async fn create_deploy_token_tx(
         },
         params.image_url,
         owner,
+        mint,
         params.dev_buy,
     )
     .await?;
@@ -277,6 +344,7 @@ pub async fn create_launch_tx(
     ipfs_meta: &IPFSMetaForm,
     image_path: Option<String>,
     owner: &Pubkey,
+    mint: Pubkey,
     dev_buy: Option<u64>, // lamports
 ) -> Result<VersionedTransaction> {
     let mut ixs = vec![];
@@ -293,7 +361,6 @@ pub async fn create_launch_tx(
     let client = get_ipfs_client();
     let metadata_uri =
         push_meta_to_pump_ipfs(&client, ipfs_meta, image).await?;
-    let (mint, mint_signer) = generate_mint();
 
     ixs.push(_make_create_token_ix(
         ipfs_meta.name.clone(),
@@ -332,12 +399,7 @@ pub async fn create_launch_tx(
     // static tip of 50000 lamports for the launch
     ixs.push(transfer(owner, &get_jito_tip_pubkey(), 50000));
 
-    let mut create_tx = Transaction::new_with_payer(&ixs, Some(owner));
-
-    create_tx
-        .partial_sign(&[mint_signer], BLOCKHASH_CACHE.get_blockhash().await?);
-
-    Ok(create_tx.into())
+    Ok(Transaction::new_with_payer(&ixs, Some(owner)).into())
 }
 
 pub fn get_bc_and_abc(mint: Pubkey) -> (Pubkey, Pubkey) {
@@ -462,6 +524,96 @@ pub fn derive_metadata_account(mint: &Pubkey) -> Pubkey {
     .0
 }
 
+#[cfg(test)]
+mod validation_tests {
+    use super::*;
+
+    fn params() -> DeployTokenParams {
+        DeployTokenParams {
+            image_url: Some("https://example.com/image.png".to_string()),
+            name: "Listen Token".to_string(),
+            symbol: "LISTEN".to_string(),
+            description: "test".to_string(),
+            twitter: None,
+            telegram: None,
+            website: None,
+            dev_buy: None,
+        }
+    }
+
+    fn rejected(params: DeployTokenParams) -> String {
+        params.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_valid_params() {
+        params().validate().unwrap();
+        DeployTokenParams {
+            image_url: None,
+            ..params()
+        }
+        .validate()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_rejects_name() {
+        assert!(rejected(DeployTokenParams {
+            name: " ".to_string(),
+            ..params()
+        })
+        .contains("name cannot be empty"));
+        assert!(rejected(DeployTokenParams {
+            name: "a".repeat(MAX_NAME_LEN + 1),
+            ..params()
+        })
+        .contains("at most 32"));
+    }
+
+    #[test]
+    fn test_rejects_symbol() {
+        assert!(rejected(DeployTokenParams {
+            symbol: "".to_string(),
+            ..params()
+        })
+        .contains("symbol cannot be empty"));
+        assert!(rejected(DeployTokenParams {
+            symbol: "VERYLONGSYMBOL".to_string(),
+            ..params()
+        })
+        .contains("at most 10"));
+        assert!(rejected(DeployTokenParams {
+            symbol: "$LSTN".to_string(),
+            ..params()
+        })
+        .contains("letters A-Z and digits"));
+    }
+
+    #[test]
+    fn test_rejects_image_url() {
+        assert!(rejected(DeployTokenParams {
+            image_url: Some("/home/user/image.png".to_string()),
+            ..params()
+        })
+        .contains("not a valid url"));
+        assert!(rejected(DeployTokenParams {
+            image_url: Some("file:///home/user/image.png".to_string()),
+            ..params()
+        })
+        .contains("http(s) url"));
+    }
+
+    #[test]
+    fn test_rejects_dev_buy_over_balance() {
+        check_dev_buy(1_000_000_000, 1_000_000_000).unwrap();
+        let err = check_dev_buy(2_000_000_000, 500_000_000).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "dev_buy of 2 SOL exceeds the wallet balance of 0.5 SOL"
+        );
+    }
+}
+
 #[cfg(test)]
 mod launcher_tests {
     use solana_sdk::signer::EncodableKey;
@@ -469,6 +621,7 @@ mod launcher_tests {
     use super::*;
     use crate::solana::transaction::send_tx;
     use crate::solana::util::{env, init_logger, load_keypair_for_tests};
+    use blockhash_cache::BLOCKHASH_CACHE;
 
     #[tokio::test]
     #[ignore]
@@ -485,11 +638,15 @@ mod launcher_tests {
             website: None,
             dev_buy: None,
         };
-        let tx = create_deploy_token_tx(params, &keypair.pubkey())
+        let (mint, mint_signer) = generate_mint();
+        let tx = create_deploy_token_tx(params, &keypair.pubkey(), mint)
             .await
             .unwrap();
         let mut tx = tx.into_legacy_transaction().unwrap();
-        tx.sign(&[&keypair], BLOCKHASH_CACHE.get_blockhash().await.unwrap());
+        tx.sign(
+            &[&keypair, &mint_signer],
+            BLOCKHASH_CACHE.get_blockhash().await.unwrap(),
+        );
         let res = send_tx(&tx.into()).await.unwrap();
         tracing::info!(?res, "deploy_token");
     }
@@ -501,6 +658,7 @@ mod launcher_tests {
         init_logger().ok();
         let signer =
             Keypair::read_from_file(env("FUND_KEYPAIR_PATH")).unwrap();
+        let (mint, mint_signer) = generate_mint();
         let tx = create_launch_tx(
             &IPFSMetaForm {
                 name: "test".to_string(),
@@ -513,12 +671,16 @@ mod launcher_tests {
             },
             None,
             &signer.pubkey(),
+            mint,
             Some(50000),
         )
         .await
         .unwrap();
         let mut tx = tx.into_legacy_transaction().unwrap();
-        tx.sign(&[&signer], BLOCKHASH_CACHE.get_blockhash().await.unwrap());
+        tx.sign(
+            &[&signer, &mint_signer],
+            BLOCKHASH_CACHE.get_blockhash().await.unwrap(),
+        );
 
         send_tx(&tx.into()).await.unwrap();
     }
@@ -530,6 +692,7 @@ mod launcher_tests {
         init_logger().ok();
         let signer =
             Keypair::read_from_file(env("FUND_KEYPAIR_PATH")).unwrap();
+        let (mint, mint_signer) = generate_mint();
         let tx = create_launch_tx(
             &IPFSMetaForm {
                 name: "test".to_string(),
@@ -542,13 +705,17 @@ mod launcher_tests {
             },
             None,
             &signer.pubkey(),
+            mint,
             None,
         )
         .await
         .unwrap();
 
         let mut tx = tx.into_legacy_transaction().unwrap();
-        tx.sign(&[&signer], BLOCKHASH_CACHE.get_blockhash().await.unwrap());
+        tx.sign(
+            &[&signer, &mint_signer],
+            BLOCKHASH_CACHE.get_blockhash().await.unwrap(),
+        );
 
         send_tx(&tx.into()).await.unwrap();
     }
@@ -0,0 +1,287 @@
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::rent::Rent;
+use std::str::FromStr;
+
+use crate::solana::amount::SOL_DECIMALS;
+use crate::solana::constants::WSOL;
+use crate::solana::jup::{JupiterClient, QuoteResponse};
+use crate::solana::price::fetch_token_price;
+use crate::solana::priority_fee::{
+    PriorityFeeConfig, DEFAULT_AUTO_PERCENTILE,
+};
+use crate::solana::simulate::fetch_decimals;
+
+/// lamports each signature of a transaction pays
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// what typical transactions of each operation consume, the priority fee
+/// is paid on these
+const SWAP_COMPUTE_UNITS: u64 = 300_000;
+const TRANSFER_COMPUTE_UNITS: u64 = 30_000;
+const PUMP_DEPLOY_COMPUTE_UNITS: u64 = 250_000;
+
+/// sizes of the accounts a pump.fun deploy creates: the mint, the bonding
+/// curve as the program allocates it, its token account and the metadata
+const PUMP_DEPLOY_ACCOUNT_LENS: [usize; 4] = [82, 150, 165, 679];
+
+/// the kind of transaction a fee is estimated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeOperation {
+    Swap,
+    Transfer,
+    PumpDeploy,
+}
+
+/// Reads the operation out of a description like "swap 1 SOL for BONK",
+/// deploys first as "launch a token and buy some" is still a deploy
+impl FromStr for FeeOperation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let description = s.to_lowercase();
+        let mentions = |words: &[&str]| {
+            description
+                .split(|c: char| !c.is_ascii_alphanumeric() && c != '.')
+                .any(|word| words.contains(&word))
+        };
+        // pump.fun itself is named for buys and sells of its tokens too
+        if mentions(&["deploy", "launch"])
+            || ["create token", "create a token"]
+                .iter()
+                .any(|phrase| description.contains(phrase))
+        {
+            Ok(Self::PumpDeploy)
+        } else if mentions(&[
+            "swap", "buy", "sell", "trade", "exchange", "convert",
+        ]) {
+            Ok(Self::Swap)
+        } else if mentions(&["transfer", "send", "pay", "withdraw"]) {
+            Ok(Self::Transfer)
+        } else {
+            Err(anyhow!(
+                "Can't tell the operation of {:?}, expected a swap, a \
+                transfer or a pump.fun deploy",
+                s
+            ))
+        }
+    }
+}
+
+impl FeeOperation {
+    fn signatures(&self) -> u64 {
+        match self {
+            // the mint is a fresh keypair that signs too
+            Self::PumpDeploy => 2,
+            Self::Swap | Self::Transfer => 1,
+        }
+    }
+
+    fn compute_units(&self) -> u64 {
+        match self {
+            Self::Swap => SWAP_COMPUTE_UNITS,
+            Self::Transfer => TRANSFER_COMPUTE_UNITS,
+            Self::PumpDeploy => PUMP_DEPLOY_COMPUTE_UNITS,
+        }
+    }
+
+    /// base plus priority fee at `compute_unit_price` micro-lamports
+    pub fn network_fee_lamports(&self, compute_unit_price: u64) -> u64 {
+        let priority_fee = (compute_unit_price as u128
+            * self.compute_units() as u128)
+            .div_ceil(1_000_000) as u64;
+        self.signatures() * LAMPORTS_PER_SIGNATURE + priority_fee
+    }
+}
+
+/// what a transaction would cost, nothing is signed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeEstimate {
+    pub operation: FeeOperation,
+    /// base and priority fee
+    pub network_fee_sol: f64,
+    /// the jupiter platform fee of swaps
+    pub protocol_fee_sol: f64,
+    /// the rent of the accounts a deploy creates, not refunded
+    pub rent_sol: f64,
+    /// in percent, swaps only
+    pub price_impact_pct: Option<f64>,
+    pub total_fee_usd: f64,
+}
+
+fn ui_amount(amount: u64, decimals: u8) -> f64 {
+    amount as f64 / 10f64.powi(decimals as i32)
+}
+
+/// rent-exempt minimum of the accounts a pump.fun deploy creates
+pub fn pump_deploy_rent_lamports() -> u64 {
+    let rent = Rent::default();
+    PUMP_DEPLOY_ACCOUNT_LENS
+        .iter()
+        .map(|len| rent.minimum_balance(*len))
+        .sum()
+}
+
+/// the platform fee of `quote` in USD, its bps of the value swapped
+pub fn swap_protocol_fee_usd(
+    quote: &QuoteResponse,
+    input_decimals: u8,
+    input_price_usd: f64,
+) -> Result<f64> {
+    let fee_bps = match &quote.platform_fee {
+        Some(fee) => fee.fee_bps,
+        None => return Ok(0.),
+    };
+    let input_usd =
+        ui_amount(quote.in_amount.parse()?, input_decimals) * input_price_usd;
+    Ok(input_usd * fee_bps as f64 / 10_000.)
+}
+
+/// The fees of `operation` at the current priority fees and prices. Swaps
+/// need the swap, `(input_mint, amount, output_mint)`, to be quoted; with
+/// `PriorityFeeConfig::None` jupiter picks their fee, the median of recent
+/// fees stands in for it
+pub async fn estimate_transaction_fee(
+    jupiter: &JupiterClient,
+    rpc_client: &RpcClient,
+    http: &Client,
+    priority_fee: &PriorityFeeConfig,
+    operation: FeeOperation,
+    swap: Option<(&str, u64, &str)>,
+) -> Result<FeeEstimate> {
+    let priority_fee = match (operation, priority_fee) {
+        (FeeOperation::Swap, PriorityFeeConfig::None) => {
+            PriorityFeeConfig::Auto {
+                percentile: DEFAULT_AUTO_PERCENTILE,
+            }
+        }
+        (_, config) => config.clone(),
+    };
+    let compute_unit_price =
+        priority_fee.compute_unit_price_for(rpc_client, &[]).await?;
+    let network_fee_sol = ui_amount(
+        operation.network_fee_lamports(compute_unit_price),
+        SOL_DECIMALS,
+    );
+    let sol_price = fetch_token_price(WSOL.to_string(), http).await?;
+
+    let (mut protocol_fee_sol, mut rent_sol) = (0., 0.);
+    let mut price_impact_pct = None;
+    match operation {
+        FeeOperation::Swap => {
+            let (input_mint, amount, output_mint) =
+                swap.ok_or_else(|| {
+                    anyhow!("Estimating a swap needs its mints and amount")
+                })?;
+            let (input_decimals, _) = fetch_decimals(
+                rpc_client,
+                &input_mint.parse()?,
+                &output_mint.parse()?,
+            )
+            .await?;
+            let quote = jupiter
+                .fetch_quote_with_slippage(
+                    input_mint,
+                    output_mint,
+                    amount,
+                    None,
+                )
+                .await?;
+            let input_price =
+                fetch_token_price(input_mint.to_string(), http).await?;
+            protocol_fee_sol =
+                swap_protocol_fee_usd(&quote, input_decimals, input_price)?
+                    / sol_price;
+            price_impact_pct =
+                Some(quote.price_impact_pct.parse::<f64>()? * 100.);
+        }
+        FeeOperation::PumpDeploy => {
+            // pump.fun charges nothing to create, only the rent is paid
+            rent_sol = ui_amount(pump_deploy_rent_lamports(), SOL_DECIMALS);
+        }
+        FeeOperation::Transfer => {}
+    }
+
+    Ok(FeeEstimate {
+        operation,
+        network_fee_sol,
+        protocol_fee_sol,
+        rent_sol,
+        price_impact_pct,
+        total_fee_usd: (network_fee_sol + protocol_fee_sol + rent_sol)
+            * sol_price,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use solana_sdk::pubkey::Pubkey;
+
+    #[test]
+    fn test_fee_operation_from_description() {
+        for (description, operation) in [
+            ("swap 1 SOL for BONK", FeeOperation::Swap),
+            ("Sell all my WIF", FeeOperation::Swap),
+            ("send 5 USDC to toly.sol", FeeOperation::Transfer),
+            ("launch a token on pump.fun", FeeOperation::PumpDeploy),
+            (
+                "deploy a token and buy 1 SOL of it",
+                FeeOperation::PumpDeploy,
+            ),
+            ("create a token called FOO", FeeOperation::PumpDeploy),
+            ("buy 1 SOL of WIF on pump.fun", FeeOperation::Swap),
+            ("sell my pumpfun bags", FeeOperation::Swap),
+        ] {
+            assert_eq!(
+                description.parse::<FeeOperation>().unwrap(),
+                operation
+            );
+        }
+        assert!("stake 1 SOL".parse::<FeeOperation>().is_err());
+    }
+
+    #[test]
+    fn test_network_fee_lamports() {
+        assert_eq!(FeeOperation::Transfer.network_fee_lamports(0), 5_000);
+        // 300k units at 10k micro-lamports are 3000 lamports
+        assert_eq!(FeeOperation::Swap.network_fee_lamports(10_000), 8_000);
+        assert_eq!(FeeOperation::PumpDeploy.network_fee_lamports(1), 10_001);
+    }
+
+    #[test]
+    fn test_pump_deploy_rent() {
+        let rent = Rent::default();
+        assert!(pump_deploy_rent_lamports() > rent.minimum_balance(679));
+        assert!(pump_deploy_rent_lamports() < 20_000_000);
+    }
+
+    #[test]
+    fn test_swap_protocol_fee_usd() {
+        let mut quote: QuoteResponse = serde_json::from_value(json!({
+            "inputMint": WSOL,
+            "inAmount": "2000000000",
+            "outputMint": Pubkey::new_unique().to_string(),
+            "outAmount": "1000000",
+            "otherAmountThreshold": "995000",
+            "swapMode": "ExactIn",
+            "slippageBps": 50,
+            "platformFee": { "amount": "1000", "feeBps": 10 },
+            "priceImpactPct": "0.001",
+            "routePlan": [],
+            "contextSlot": 1,
+            "timeTaken": 0.01,
+        }))
+        .unwrap();
+        // 10 bps of 2 SOL at $150
+        let fee = swap_protocol_fee_usd(&quote, 9, 150.).unwrap();
+        assert!((fee - 0.3).abs() < 1e-9);
+
+        quote.platform_fee = None;
+        assert_eq!(swap_protocol_fee_usd(&quote, 9, 150.).unwrap(), 0.);
+    }
+}
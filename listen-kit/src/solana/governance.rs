@@ -0,0 +1,326 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcProgramAccountsConfig,
+};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+pub const SPL_GOVERNANCE_PROGRAM: &str =
+    "GovER5Lthms3bLBqWub97yVrMmEogzX7xNjdXpPPCVZw";
+
+/// `GovernanceAccountType` discriminators, the first byte of every account
+const GOVERNANCE_V2_ACCOUNT: u8 = 18;
+const PROPOSAL_V2_ACCOUNT: u8 = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalState {
+    Draft,
+    SigningOff,
+    Voting,
+    Succeeded,
+    Executing,
+    Completed,
+    Cancelled,
+    Defeated,
+    ExecutingWithErrors,
+    Vetoed,
+}
+
+impl TryFrom<u8> for ProposalState {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        Ok(match value {
+            0 => Self::Draft,
+            1 => Self::SigningOff,
+            2 => Self::Voting,
+            3 => Self::Succeeded,
+            4 => Self::Executing,
+            5 => Self::Completed,
+            6 => Self::Cancelled,
+            7 => Self::Defeated,
+            8 => Self::ExecutingWithErrors,
+            9 => Self::Vetoed,
+            _ => return Err(anyhow!("unknown proposal state {}", value)),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GovernanceProposal {
+    pub address: String,
+    pub name: String,
+    pub state: ProposalState,
+    /// vote weights are raw amounts of the governing token
+    pub yes_votes: u64,
+    pub no_votes: u64,
+    /// unix seconds, `None` until voting has started
+    pub voting_ends_at: Option<i64>,
+    pub description_link: String,
+    /// the token whose holders vote, community or council
+    pub governing_token_mint: String,
+}
+
+/// borsh reader over a fixed layout
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset + len)
+            .ok_or_else(|| anyhow!("proposal account data too short"))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn pubkey(&mut self) -> Result<Pubkey> {
+        Ok(Pubkey::try_from(self.take(32)?)?)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    /// an `Option` of a value `len` bytes long, read as u64 when present
+    fn option(&mut self, len: usize) -> Result<Option<u64>> {
+        if self.u8()? == 0 {
+            return Ok(None);
+        }
+        let mut bytes = [0u8; 8];
+        bytes[..len].copy_from_slice(self.take(len)?);
+        Ok(Some(u64::from_le_bytes(bytes)))
+    }
+}
+
+/// a `ProposalV2` account of spl-governance 3.x
+pub fn parse_proposal(
+    address: &Pubkey,
+    data: &[u8],
+) -> Result<GovernanceProposal> {
+    let mut reader = Reader { data, offset: 0 };
+    let account_type = reader.u8()?;
+    if account_type != PROPOSAL_V2_ACCOUNT {
+        return Err(anyhow!("not a proposal account: {}", account_type));
+    }
+    let _governance = reader.pubkey()?;
+    let governing_token_mint = reader.pubkey()?;
+    let state = ProposalState::try_from(reader.u8()?)?;
+    let _token_owner_record = reader.pubkey()?;
+    // signatories count, signed off count
+    reader.take(2)?;
+    // vote type, multi choice carries four more bytes
+    if reader.u8()? == 1 {
+        reader.take(4)?;
+    }
+
+    // yes votes are the option weights, a single option for yes/no votes
+    let mut yes_votes = 0u64;
+    for _ in 0..reader.u32()? {
+        let _label = reader.string()?;
+        yes_votes = yes_votes.saturating_add(reader.u64()?);
+        // vote result
+        reader.u8()?;
+        // transactions executed count, count and next index
+        reader.u16()?;
+        reader.u16()?;
+        reader.u16()?;
+    }
+    let no_votes = reader.option(8)?.unwrap_or_default();
+    // reserved
+    reader.u8()?;
+    let _abstain_vote_weight = reader.option(8)?;
+    let _start_voting_at = reader.option(8)?;
+    let _draft_at = reader.u64()?;
+    let _signing_off_at = reader.option(8)?;
+    let voting_at = reader.option(8)?.map(|at| at as i64);
+    let _voting_at_slot = reader.option(8)?;
+    let _voting_completed_at = reader.option(8)?;
+    let _executing_at = reader.option(8)?;
+    let _closed_at = reader.option(8)?;
+    let _execution_flags = reader.u8()?;
+    let _max_vote_weight = reader.option(8)?;
+    let max_voting_time = reader.option(4)?;
+    // vote threshold, every variant but `Disabled` carries a percentage
+    if reader.u8()? == 1 && reader.u8()? != 2 {
+        reader.u8()?;
+    }
+    reader.take(64)?;
+    let name = reader.string()?;
+    let description_link = reader.string()?;
+
+    Ok(GovernanceProposal {
+        address: address.to_string(),
+        name,
+        state,
+        yes_votes,
+        no_votes,
+        voting_ends_at: voting_at
+            .zip(max_voting_time)
+            .map(|(at, time)| at + time as i64),
+        description_link,
+        governing_token_mint: governing_token_mint.to_string(),
+    })
+}
+
+async fn get_accounts(
+    rpc_client: &RpcClient,
+    account_type: u8,
+    parent: &Pubkey,
+) -> Result<Vec<(Pubkey, solana_sdk::account::Account)>> {
+    // governances point to their realm and proposals to their governance
+    // right after the discriminator
+    Ok(rpc_client
+        .get_program_accounts_with_config(
+            &Pubkey::from_str(SPL_GOVERNANCE_PROGRAM)?,
+            RpcProgramAccountsConfig {
+                filters: Some(vec![
+                    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                        0,
+                        vec![account_type],
+                    )),
+                    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                        1,
+                        parent.to_bytes().to_vec(),
+                    )),
+                ]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await?)
+}
+
+/// proposals of every governance of the realm, the ones being voted on
+/// first, then the newest
+pub async fn get_governance_proposals(
+    rpc_client: &RpcClient,
+    realm: &str,
+) -> Result<Vec<GovernanceProposal>> {
+    let realm = Pubkey::from_str(realm)?;
+    let mut proposals = Vec::new();
+    for (governance, _) in
+        get_accounts(rpc_client, GOVERNANCE_V2_ACCOUNT, &realm).await?
+    {
+        for (address, account) in
+            get_accounts(rpc_client, PROPOSAL_V2_ACCOUNT, &governance).await?
+        {
+            match parse_proposal(&address, &account.data) {
+                Ok(proposal) => proposals.push(proposal),
+                Err(e) => {
+                    tracing::warn!(%address, ?e, "failed to parse proposal")
+                }
+            }
+        }
+    }
+    proposals.sort_by_key(|proposal| {
+        (
+            proposal.state != ProposalState::Voting,
+            std::cmp::Reverse(proposal.voting_ends_at),
+        )
+    });
+    Ok(proposals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(data: &mut Vec<u8>, s: &str) {
+        data.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        data.extend_from_slice(s.as_bytes());
+    }
+
+    fn some(data: &mut Vec<u8>, bytes: &[u8]) {
+        data.push(1);
+        data.extend_from_slice(bytes);
+    }
+
+    fn proposal_data(mint: &Pubkey) -> Vec<u8> {
+        let mut data = vec![PROPOSAL_V2_ACCOUNT];
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(mint.as_ref());
+        data.push(2); // voting
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(&[1, 1]);
+        data.push(0); // single choice
+        data.extend_from_slice(&1u32.to_le_bytes());
+        string(&mut data, "Approve");
+        data.extend_from_slice(&700u64.to_le_bytes());
+        data.push(0);
+        data.extend_from_slice(&[0; 6]);
+        some(&mut data, &300u64.to_le_bytes()); // deny
+        data.push(0); // reserved
+        data.push(0); // abstain
+        data.push(0); // start voting at
+        data.extend_from_slice(&1_700_000_000i64.to_le_bytes());
+        some(&mut data, &1_700_000_100i64.to_le_bytes());
+        some(&mut data, &1_700_000_200i64.to_le_bytes()); // voting at
+        some(&mut data, &250_000_000u64.to_le_bytes());
+        data.extend_from_slice(&[0, 0, 0]); // completed, executing, closed
+        data.push(0); // execution flags
+        some(&mut data, &1_000u64.to_le_bytes());
+        some(&mut data, &259_200u32.to_le_bytes()); // max voting time
+        some(&mut data, &[0, 60]); // yes vote percentage
+        data.extend_from_slice(&[0; 64]);
+        string(&mut data, "Raise the treasury allocation");
+        string(&mut data, "https://example.com/proposal");
+        data.extend_from_slice(&0u64.to_le_bytes()); // veto vote weight
+        data
+    }
+
+    #[test]
+    fn test_parse_proposal() {
+        let (address, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let proposal =
+            parse_proposal(&address, &proposal_data(&mint)).unwrap();
+        assert_eq!(
+            proposal,
+            GovernanceProposal {
+                address: address.to_string(),
+                name: "Raise the treasury allocation".to_string(),
+                state: ProposalState::Voting,
+                yes_votes: 700,
+                no_votes: 300,
+                voting_ends_at: Some(1_700_000_200 + 259_200),
+                description_link: "https://example.com/proposal".to_string(),
+                governing_token_mint: mint.to_string(),
+            }
+        );
+
+        let mut data = proposal_data(&mint);
+        data[0] = GOVERNANCE_V2_ACCOUNT;
+        assert!(parse_proposal(&address, &data).is_err());
+        assert!(
+            parse_proposal(&address, &proposal_data(&mint)[..100]).is_err()
+        );
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use balance_diffs::WSOL_MINT;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::{
+    UiTransactionEncoding, UiTransactionStatusMeta,
+};
+
+use crate::solana::balance_changes::summarize_transaction;
+
+/// signatures per page, more than this makes for as many RPC calls
+pub const MAX_HISTORY_LIMIT: u32 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionType {
+    Swap,
+    Transfer,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionSummary {
+    pub signature: String,
+    /// unix seconds, missing for transactions the RPC has no block time of
+    pub timestamp: Option<u64>,
+    #[serde(rename = "type")]
+    pub transaction_type: TransactionType,
+    /// name of the indexed token, the mint for transactions summarized
+    /// from the RPC
+    pub token_name: Option<String>,
+    /// only known for swaps the indexer priced
+    pub amount_usd: Option<f64>,
+    /// whether SOL was spent on the token, none for non-swaps and for
+    /// token to token swaps
+    pub is_buy: Option<bool>,
+}
+
+impl TransactionSummary {
+    fn other(signature: String, timestamp: Option<u64>) -> Self {
+        Self {
+            signature,
+            timestamp,
+            transaction_type: TransactionType::Other,
+            token_name: None,
+            amount_usd: None,
+            is_buy: None,
+        }
+    }
+}
+
+/// the fields of an indexed `PriceUpdate` the history needs
+#[derive(Debug, Clone, Deserialize)]
+struct IndexedSwap {
+    name: String,
+    timestamp: u64,
+    swap_amount: f64,
+    owner: String,
+    signature: String,
+    is_buy: bool,
+}
+
+impl From<IndexedSwap> for TransactionSummary {
+    fn from(swap: IndexedSwap) -> Self {
+        Self {
+            signature: swap.signature,
+            timestamp: Some(swap.timestamp),
+            transaction_type: TransactionType::Swap,
+            token_name: Some(swap.name),
+            amount_usd: Some(swap.swap_amount),
+            is_buy: Some(swap.is_buy),
+        }
+    }
+}
+
+/// the indexed swaps of `owner` among `signatures`, keyed by signature; a
+/// multi-hop swap is indexed per hop and the first hop is kept
+async fn fetch_indexed_swaps(
+    api_base: &str,
+    owner: &str,
+    signatures: &[String],
+) -> Result<HashMap<String, IndexedSwap>> {
+    if signatures.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let swaps = reqwest::Client::new()
+        .get(format!("{}/swaps", api_base))
+        .query(&[("signatures", signatures.join(","))])
+        .send()
+        .await
+        .map_err(|e| anyhow!("Failed to fetch indexed swaps: {}", e))?
+        .error_for_status()?
+        .json::<Vec<IndexedSwap>>()
+        .await
+        .map_err(|e| anyhow!("Failed to parse indexed swaps: {}", e))?;
+
+    let mut by_signature = HashMap::new();
+    for swap in swaps.into_iter().filter(|swap| swap.owner == owner) {
+        by_signature.entry(swap.signature.clone()).or_insert(swap);
+    }
+    Ok(by_signature)
+}
+
+fn token_name(mint: &str) -> String {
+    if mint == WSOL_MINT {
+        "SOL".to_string()
+    } else {
+        mint.to_string()
+    }
+}
+
+/// a transaction the indexer didn't price, told apart by the balances of
+/// `owner`: something went out and something else came in for a swap, a
+/// single balance moved for a transfer
+pub fn summarize_rpc_transaction(
+    signature: String,
+    timestamp: Option<u64>,
+    meta: &UiTransactionStatusMeta,
+    owner: &str,
+) -> TransactionSummary {
+    let summary = summarize_transaction(meta, owner);
+    let (transaction_type, token, is_buy) =
+        match (summary.sold_mint, summary.bought_mint) {
+            (Some(sold), Some(bought)) if sold == WSOL_MINT => {
+                (TransactionType::Swap, Some(bought), Some(true))
+            }
+            (Some(sold), Some(bought)) if bought == WSOL_MINT => {
+                (TransactionType::Swap, Some(sold), Some(false))
+            }
+            (Some(_), Some(bought)) => {
+                (TransactionType::Swap, Some(bought), None)
+            }
+            (Some(mint), None) | (None, Some(mint)) => {
+                (TransactionType::Transfer, Some(mint), None)
+            }
+            (None, None) => (TransactionType::Other, None, None),
+        };
+    TransactionSummary {
+        signature,
+        timestamp,
+        transaction_type,
+        token_name: token.as_deref().map(token_name),
+        amount_usd: None,
+        is_buy,
+    }
+}
+
+async fn fetch_rpc_summary(
+    rpc_client: &RpcClient,
+    signature: String,
+    timestamp: Option<u64>,
+    owner: &str,
+) -> TransactionSummary {
+    let transaction = match Signature::from_str(&signature) {
+        Ok(sig) => rpc_client
+            .get_transaction_with_config(
+                &sig,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Json),
+                    commitment: None,
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await
+            .map_err(|e| anyhow!(e)),
+        Err(e) => Err(anyhow!(e)),
+    };
+    match transaction {
+        Ok(transaction) => match transaction.transaction.meta {
+            Some(meta) => {
+                summarize_rpc_transaction(signature, timestamp, &meta, owner)
+            }
+            None => TransactionSummary::other(signature, timestamp),
+        },
+        Err(e) => {
+            tracing::warn!(?e, %signature, "failed to fetch transaction");
+            TransactionSummary::other(signature, timestamp)
+        }
+    }
+}
+
+/// the latest `limit` transactions of `owner`, newest first, older than
+/// `before` if given; the last signature is the cursor of the next page
+pub async fn get_transaction_history(
+    rpc_client: &RpcClient,
+    api_base: &str,
+    owner: &Pubkey,
+    limit: u32,
+    before: Option<String>,
+) -> Result<Vec<TransactionSummary>> {
+    let before = before.map(|sig| Signature::from_str(&sig)).transpose()?;
+    let signatures = rpc_client
+        .get_signatures_for_address_with_config(
+            owner,
+            GetConfirmedSignaturesForAddress2Config {
+                before,
+                until: None,
+                limit: Some(limit.clamp(1, MAX_HISTORY_LIMIT) as usize),
+                commitment: None,
+            },
+        )
+        .await?;
+
+    let owner = owner.to_string();
+    let keys: Vec<String> =
+        signatures.iter().map(|sig| sig.signature.clone()).collect();
+    let mut indexed = match fetch_indexed_swaps(api_base, &owner, &keys).await
+    {
+        Ok(indexed) => indexed,
+        Err(e) => {
+            tracing::warn!(?e, "indexed swaps unavailable, using the RPC");
+            HashMap::new()
+        }
+    };
+
+    let summaries = signatures.into_iter().map(|sig| {
+        let indexed = indexed.remove(&sig.signature);
+        let owner = &owner;
+        async move {
+            match indexed {
+                Some(swap) => swap.into(),
+                None => {
+                    let timestamp = sig.block_time.map(|t| t as u64);
+                    fetch_rpc_summary(
+                        rpc_client,
+                        sig.signature,
+                        timestamp,
+                        owner,
+                    )
+                    .await
+                }
+            }
+        }
+    });
+    Ok(join_all(summaries).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWNER: &str = "6fp9frQ16W3kTRGiBVvpMS2NzoixE4Y1MWqYrW9SvTAj";
+    const BONK: &str = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263";
+
+    fn meta(
+        lamports: (u64, u64),
+        bonk: (f64, f64),
+    ) -> UiTransactionStatusMeta {
+        let token_balance = |amount: f64| {
+            serde_json::json!([{
+                "accountIndex": 1,
+                "mint": BONK,
+                "owner": OWNER,
+                "uiTokenAmount": {
+                    "uiAmount": amount,
+                    "decimals": 5,
+                    "amount": ((amount * 1e5) as u64).to_string(),
+                    "uiAmountString": amount.to_string(),
+                },
+            }])
+        };
+        serde_json::from_value(serde_json::json!({
+            "err": null,
+            "status": {"Ok": null},
+            "fee": 5000,
+            "preBalances": [lamports.0],
+            "postBalances": [lamports.1],
+            "preTokenBalances": token_balance(bonk.0),
+            "postTokenBalances": token_balance(bonk.1),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_summarize_rpc_transaction() {
+        let buy = summarize_rpc_transaction(
+            "buy".to_string(),
+            Some(1),
+            &meta((1_000_000_000, 499_995_000), (0., 1_000.)),
+            OWNER,
+        );
+        assert_eq!(buy.transaction_type, TransactionType::Swap);
+        assert_eq!(buy.token_name.as_deref(), Some(BONK));
+        assert_eq!(buy.is_buy, Some(true));
+
+        let sell = summarize_rpc_transaction(
+            "sell".to_string(),
+            Some(1),
+            &meta((499_995_000, 999_990_000), (1_000., 0.)),
+            OWNER,
+        );
+        assert_eq!(sell.is_buy, Some(false));
+
+        let transfer = summarize_rpc_transaction(
+            "transfer".to_string(),
+            None,
+            &meta((1_000_000_000, 999_995_000), (1_000., 400.)),
+            OWNER,
+        );
+        assert_eq!(transfer.transaction_type, TransactionType::Transfer);
+        assert_eq!(transfer.is_buy, None);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_indexed_swaps() {
+        let mut server = mockito::Server::new_async().await;
+        let swap = |owner: &str, name: &str| {
+            serde_json::json!({
+                "name": name,
+                "pubkey": BONK,
+                "price": 0.00002,
+                "market_cap": 1.0,
+                "timestamp": 1_700_000_000,
+                "slot": 1,
+                "swap_amount": 120.5,
+                "owner": owner,
+                "signature": "sig",
+                "multi_hop": true,
+                "is_buy": true,
+                "is_pump": false,
+            })
+        };
+        let mock = server
+            .mock("GET", "/swaps")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "signatures".into(),
+                "sig,other".into(),
+            ))
+            .with_body(
+                serde_json::json!([
+                    swap("someone else", "WRONG"),
+                    swap(OWNER, "BONK"),
+                    swap(OWNER, "SECOND HOP"),
+                ])
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let swaps = fetch_indexed_swaps(
+            &server.url(),
+            OWNER,
+            &["sig".to_string(), "other".to_string()],
+        )
+        .await
+        .unwrap();
+        mock.assert_async().await;
+        assert_eq!(swaps.len(), 1);
+        let summary = TransactionSummary::from(swaps["sig"].clone());
+        assert_eq!(summary.token_name.as_deref(), Some("BONK"));
+        assert_eq!(summary.amount_usd, Some(120.5));
+        assert_eq!(summary.is_buy, Some(true));
+    }
+}
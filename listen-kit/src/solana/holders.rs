@@ -0,0 +1,304 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::solana::constants::PUMP_FUN_PROGRAM;
+use crate::solana::pool::{ORCA_WHIRLPOOL_PROGRAM, RAYDIUM_CLMM_PROGRAM};
+use crate::solana::safety::{token_account_owners, RAYDIUM_POOL_AUTHORITIES};
+
+/// `getTokenLargestAccounts` never returns more than 20 accounts
+pub const MAX_TOP_HOLDERS: usize = 20;
+
+const CONCENTRATION_HOLDERS: usize = 10;
+
+/// the owners of pool and curve vaults, matched against the wallet of a
+/// token account or the program that owns that wallet's account
+const KNOWN_VAULTS: [(&str, &str); 8] = [
+    (RAYDIUM_POOL_AUTHORITIES[0], "raydium amm v4"),
+    (RAYDIUM_POOL_AUTHORITIES[1], "raydium cpmm"),
+    (RAYDIUM_CLMM_PROGRAM, "raydium clmm"),
+    (ORCA_WHIRLPOOL_PROGRAM, "orca whirlpool"),
+    (PUMP_FUN_PROGRAM, "pump.fun bonding curve"),
+    (
+        "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YDpNpPMs",
+        "meteora dlmm",
+    ),
+    (
+        "Eo7WjKq67rjJQSZxS6z3YkapzY3eMj6Xy8X5EQVn5UaB",
+        "meteora pools",
+    ),
+    (
+        "24Uqj9JCLxUeoC3hGfh5W3s9FM9uCHDS2SG3LYwBpyTi",
+        "meteora vault",
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopHolder {
+    /// the token account
+    pub address: String,
+    /// the wallet of the token account, null if it couldn't be read
+    pub owner: Option<String>,
+    pub amount: f64,
+    /// share of the supply
+    pub percent: f64,
+    /// the pool or curve holding the tokens, null for regular holders
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopHoldersReport {
+    pub mint: String,
+    pub supply: f64,
+    pub holders: Vec<TopHolder>,
+    /// share of the supply in the 10 largest accounts
+    pub top10_percent: f64,
+    /// the same share with the labelled vaults left out
+    pub top10_percent_excluding_vaults: f64,
+}
+
+fn known_vault(address: &str) -> Option<&'static str> {
+    KNOWN_VAULTS
+        .iter()
+        .find(|(vault, _)| *vault == address)
+        .map(|(_, label)| *label)
+}
+
+/// the label of a wallet, known by its address or, for the PDAs of pools
+/// and curves, by the program owning its account
+fn label(
+    owner: Option<&Pubkey>,
+    owner_program: Option<&Pubkey>,
+) -> Option<&'static str> {
+    owner
+        .and_then(|owner| known_vault(&owner.to_string()))
+        .or_else(|| {
+            owner_program
+                .and_then(|program| known_vault(&program.to_string()))
+        })
+}
+
+/// the program owning each wallet account, `None` where there is no
+/// account or it couldn't be read
+async fn owner_programs(
+    rpc_client: &RpcClient,
+    owners: &[Option<Pubkey>],
+) -> Vec<Option<Pubkey>> {
+    let keys: Vec<_> = owners.iter().flatten().copied().collect();
+    if keys.is_empty() {
+        return vec![None; owners.len()];
+    }
+    let mut fetched = match rpc_client.get_multiple_accounts(&keys).await {
+        Ok(fetched) => fetched.into_iter(),
+        Err(e) => {
+            tracing::warn!(%e, "failed to fetch the accounts of the owners");
+            return vec![None; owners.len()];
+        }
+    };
+    owners
+        .iter()
+        .map(|owner| {
+            owner.and_then(|_| fetched.next().flatten().map(|a| a.owner))
+        })
+        .collect()
+}
+
+/// the largest holders of `mint`, at most `MAX_TOP_HOLDERS` of them
+pub async fn get_top_holders(
+    rpc_client: &RpcClient,
+    mint: &str,
+    limit: usize,
+) -> Result<TopHoldersReport> {
+    let mint = Pubkey::from_str(mint)?;
+    let (largest, supply) = tokio::try_join!(
+        rpc_client.get_token_largest_accounts(&mint),
+        rpc_client.get_token_supply(&mint),
+    )?;
+    let supply = supply.ui_amount.unwrap_or_default();
+    if supply <= 0. {
+        return Err(anyhow!("{} has no supply", mint));
+    }
+    let largest: Vec<_> = largest
+        .into_iter()
+        .take(limit.clamp(1, MAX_TOP_HOLDERS))
+        .collect();
+    let owners = token_account_owners(rpc_client, &largest).await;
+    let programs = owner_programs(rpc_client, &owners).await;
+
+    let holders: Vec<_> = largest
+        .into_iter()
+        .zip(owners.iter().zip(&programs))
+        .map(|(account, (owner, program))| {
+            let amount = account.amount.ui_amount.unwrap_or_default();
+            TopHolder {
+                address: account.address,
+                owner: owner.map(|owner| owner.to_string()),
+                amount,
+                percent: amount / supply * 100.,
+                label: label(owner.as_ref(), program.as_ref())
+                    .map(str::to_string),
+            }
+        })
+        .collect();
+    let top10 = holders.iter().take(CONCENTRATION_HOLDERS);
+    let top10_percent = top10.clone().map(|holder| holder.percent).sum();
+    let top10_percent_excluding_vaults = top10
+        .filter(|holder| holder.label.is_none())
+        .map(|holder| holder.percent)
+        .sum();
+
+    Ok(TopHoldersReport {
+        mint: mint.to_string(),
+        supply,
+        holders,
+        top10_percent,
+        top10_percent_excluding_vaults,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::prelude::{Engine, BASE64_STANDARD};
+    use serde_json::{json, Value};
+    use solana_client::rpc_request::RpcRequest;
+    use std::collections::HashMap;
+
+    /// `holders` are (wallet, program owning the wallet, ui amount) of a
+    /// supply of 1000 tokens
+    fn holders_rpc(holders: &[(Pubkey, Pubkey, f64)]) -> RpcClient {
+        let context = json!({ "slot": 1 });
+        // the mock answers every getMultipleAccounts the same way, so each
+        // account is both the token account, with the wallet in its data,
+        // and the wallet account, owned by its program
+        let accounts: Vec<Value> = holders
+            .iter()
+            .map(|(wallet, program, _)| {
+                let mut data = vec![0u8; 165];
+                data[32..64].copy_from_slice(wallet.as_ref());
+                json!({
+                    "data": [BASE64_STANDARD.encode(&data), "base64"],
+                    "executable": false,
+                    "lamports": 2_039_280,
+                    "owner": program.to_string(),
+                    "rentEpoch": 0,
+                    "space": data.len(),
+                })
+            })
+            .collect();
+        let balances: Vec<Value> = holders
+            .iter()
+            .map(|(_, _, ui_amount)| {
+                json!({
+                    "address": Pubkey::new_unique().to_string(),
+                    "amount": ((ui_amount * 1e6) as u64).to_string(),
+                    "decimals": 6,
+                    "uiAmount": ui_amount,
+                    "uiAmountString": ui_amount.to_string(),
+                })
+            })
+            .collect();
+
+        RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([
+                (
+                    RpcRequest::GetTokenLargestAccounts,
+                    json!({ "context": context, "value": balances }),
+                ),
+                (
+                    RpcRequest::GetTokenSupply,
+                    json!({
+                        "context": context,
+                        "value": {
+                            "amount": "1000000000",
+                            "decimals": 6,
+                            "uiAmount": 1000.0,
+                            "uiAmountString": "1000",
+                        },
+                    }),
+                ),
+                (
+                    RpcRequest::GetMultipleAccounts,
+                    json!({ "context": context, "value": accounts }),
+                ),
+            ]),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_top_holders_are_labelled() {
+        let system = solana_sdk::system_program::id();
+        let raydium = Pubkey::from_str(RAYDIUM_POOL_AUTHORITIES[0]).unwrap();
+        let pump = Pubkey::from_str(PUMP_FUN_PROGRAM).unwrap();
+        let whale = Pubkey::new_unique();
+        let holders = [
+            (Pubkey::new_unique(), pump, 500.),
+            (raydium, system, 200.),
+            (whale, system, 100.),
+            (Pubkey::new_unique(), system, 50.),
+        ];
+        let rpc_client = holders_rpc(&holders);
+
+        let report = get_top_holders(
+            &rpc_client,
+            &Pubkey::new_unique().to_string(),
+            20,
+        )
+        .await
+        .unwrap();
+        let labels: Vec<_> = report
+            .holders
+            .iter()
+            .map(|holder| holder.label.as_deref())
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                Some("pump.fun bonding curve"),
+                Some("raydium amm v4"),
+                None,
+                None
+            ]
+        );
+        assert_eq!(report.holders[2].owner, Some(whale.to_string()));
+        assert_eq!(report.supply, 1000.);
+        assert_eq!(report.holders[0].percent, 50.);
+        assert_eq!(report.holders[3].percent, 5.);
+        assert_eq!(report.top10_percent, 85.);
+        assert_eq!(report.top10_percent_excluding_vaults, 15.);
+    }
+
+    #[tokio::test]
+    async fn test_top_holders_limit() {
+        let system = solana_sdk::system_program::id();
+        let holders: Vec<_> = (0..12)
+            .map(|_| (Pubkey::new_unique(), system, 50.))
+            .collect();
+        let rpc_client = holders_rpc(&holders);
+
+        let report = get_top_holders(
+            &rpc_client,
+            &Pubkey::new_unique().to_string(),
+            3,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.holders.len(), 3);
+        assert_eq!(report.top10_percent, 15.);
+
+        // only the 10 largest count towards the concentration
+        let report = get_top_holders(
+            &rpc_client,
+            &Pubkey::new_unique().to_string(),
+            100,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.holders.len(), 12);
+        assert_eq!(report.top10_percent, 50.);
+    }
+}
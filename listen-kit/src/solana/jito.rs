@@ -0,0 +1,597 @@
+use anyhow::{anyhow, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::message::VersionedMessage;
+use solana_sdk::native_token::sol_to_lamports;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction;
+use solana_sdk::system_program;
+use solana_sdk::transaction::VersionedTransaction;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::solana::util::static_account_index;
+
+pub const JITO_BLOCK_ENGINE_URL: &str =
+    "https://mainnet.block-engine.jito.wtf";
+pub const JITO_TIP_FLOOR_URL: &str =
+    "https://bundles.jito.wtf/api/v1/bundles/tip_floor";
+
+/// the smallest tip the block engine takes a bundle with
+pub const MIN_JITO_TIP_LAMPORTS: u64 = 1_000;
+
+/// what a tip from the tip floor is capped at, 0.01 SOL
+pub const MAX_AUTO_JITO_TIP_LAMPORTS: u64 = 10_000_000;
+
+const DEFAULT_BUNDLE_TIMEOUT_SECS: u64 = 30;
+const BUNDLE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// how transactions sent with `send_via_jito` are tipped and landed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JitoConfig {
+    pub block_engine_url: String,
+    pub tip_floor_url: String,
+    /// `None` tips the median of the recently landed tips
+    pub tip_lamports: Option<u64>,
+    /// how long a bundle gets to land
+    pub timeout_secs: u64,
+    /// sends the transaction through the RPC when its bundle didn't land
+    /// in time, otherwise that is an error
+    pub fallback_to_rpc: bool,
+}
+
+impl Default for JitoConfig {
+    fn default() -> Self {
+        Self {
+            block_engine_url: JITO_BLOCK_ENGINE_URL.to_string(),
+            tip_floor_url: JITO_TIP_FLOOR_URL.to_string(),
+            tip_lamports: None,
+            timeout_secs: DEFAULT_BUNDLE_TIMEOUT_SECS,
+            fallback_to_rpc: true,
+        }
+    }
+}
+
+impl JitoConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self
+            .tip_lamports
+            .is_some_and(|tip| tip < MIN_JITO_TIP_LAMPORTS)
+        {
+            return Err(anyhow!(
+                "Jito tips have to be at least {} lamports",
+                MIN_JITO_TIP_LAMPORTS
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// where a bundle is at, `Invalid` until the block engine knows about it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleStatus {
+    Invalid,
+    Pending,
+    Failed,
+    Landed { slot: Option<u64> },
+}
+
+#[derive(Debug, Deserialize)]
+struct InflightBundleStatus {
+    status: String,
+    landed_slot: Option<u64>,
+}
+
+/// the recent tips of landed bundles, in SOL
+#[derive(Debug, Deserialize)]
+struct TipFloor {
+    ema_landed_tips_50th_percentile: f64,
+}
+
+/// How a transaction sent as a bundle landed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleResult {
+    pub signature: String,
+    pub bundle_id: String,
+    /// the bundle didn't land in time and the transaction went through
+    /// the RPC instead
+    pub fell_back_to_rpc: bool,
+}
+
+/// Sends bundles to the block engine and waits for them to land
+#[derive(Debug, Clone)]
+pub struct JitoClient {
+    client: reqwest::Client,
+    block_engine_url: String,
+    tip_floor_url: String,
+    timeout: Duration,
+    poll_interval: Duration,
+}
+
+impl JitoClient {
+    pub fn new(config: &JitoConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            block_engine_url: config
+                .block_engine_url
+                .trim_end_matches('/')
+                .to_string(),
+            tip_floor_url: config.tip_floor_url.clone(),
+            timeout: Duration::from_secs(config.timeout_secs),
+            poll_interval: BUNDLE_POLL_INTERVAL,
+        }
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    async fn call(
+        &self,
+        endpoint: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<Value> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/{}", self.block_engine_url, endpoint))
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.json::<Value>().await.map_err(|e| {
+            anyhow!("Failed to parse the {} response: {}", method, e)
+        })?;
+        if let Some(error) = body.get("error") {
+            return Err(anyhow!("Jito {} failed: {}", method, error));
+        }
+        if !status.is_success() {
+            return Err(anyhow!("Jito {} failed with {}", method, status));
+        }
+        body.get("result")
+            .cloned()
+            .ok_or_else(|| anyhow!("Jito {} returned no result", method))
+    }
+
+    /// the median tip of recently landed bundles, within the tip bounds
+    pub async fn tip_floor_lamports(&self) -> Result<u64> {
+        let floors = self
+            .client
+            .get(&self.tip_floor_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<TipFloor>>()
+            .await?;
+        let floor = floors
+            .first()
+            .ok_or_else(|| anyhow!("Jito returned no tip floor"))?;
+        Ok(sol_to_lamports(floor.ema_landed_tips_50th_percentile)
+            .clamp(MIN_JITO_TIP_LAMPORTS, MAX_AUTO_JITO_TIP_LAMPORTS))
+    }
+
+    /// the id of the bundle of the signed `txs`, landed in order or not
+    /// at all
+    pub async fn send_bundle(
+        &self,
+        txs: &[VersionedTransaction],
+    ) -> Result<String> {
+        let encoded = txs
+            .iter()
+            .map(|tx| Ok(BASE64_STANDARD.encode(bincode::serialize(tx)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let result = self
+            .call(
+                "bundles",
+                "sendBundle",
+                json!([encoded, { "encoding": "base64" }]),
+            )
+            .await?;
+        result
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Invalid bundle id: {}", result))
+    }
+
+    pub async fn bundle_status(
+        &self,
+        bundle_id: &str,
+    ) -> Result<BundleStatus> {
+        let result = self
+            .call(
+                "getInflightBundleStatuses",
+                "getInflightBundleStatuses",
+                json!([[bundle_id]]),
+            )
+            .await?;
+        let statuses: Vec<InflightBundleStatus> =
+            serde_json::from_value(result["value"].clone())?;
+        let status = statuses
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("No status for bundle {}", bundle_id))?;
+        Ok(match status.status.as_str() {
+            "Pending" => BundleStatus::Pending,
+            "Failed" => BundleStatus::Failed,
+            "Landed" => BundleStatus::Landed {
+                slot: status.landed_slot,
+            },
+            _ => BundleStatus::Invalid,
+        })
+    }
+
+    /// whether the bundle landed before the timeout, a failed bundle is
+    /// an error
+    pub async fn wait_for_bundle(&self, bundle_id: &str) -> Result<bool> {
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        loop {
+            match self.bundle_status(bundle_id).await? {
+                BundleStatus::Landed { slot } => {
+                    tracing::info!(bundle_id, ?slot, "bundle landed");
+                    return Ok(true);
+                }
+                BundleStatus::Failed => {
+                    return Err(anyhow!("Jito bundle {} failed", bundle_id))
+                }
+                BundleStatus::Invalid | BundleStatus::Pending => {}
+            }
+            if tokio::time::Instant::now() + self.poll_interval > deadline {
+                return Ok(false);
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    /// Sends the signed `tx` as a bundle of its own and waits for it to
+    /// land. When it doesn't in time, `fallback` sends the same
+    /// transaction, which can't land twice, or the timeout is an error
+    /// without one.
+    pub async fn send_transaction<F, Fut>(
+        &self,
+        tx: &VersionedTransaction,
+        fallback: Option<F>,
+    ) -> Result<BundleResult>
+    where
+        F: FnOnce(VersionedTransaction) -> Fut,
+        Fut: Future<Output = Result<String>>,
+    {
+        let signature = tx
+            .signatures
+            .first()
+            .ok_or_else(|| anyhow!("Transaction is not signed"))?
+            .to_string();
+        let bundle_id = self.send_bundle(std::slice::from_ref(tx)).await?;
+        if self.wait_for_bundle(&bundle_id).await? {
+            return Ok(BundleResult {
+                signature,
+                bundle_id,
+                fell_back_to_rpc: false,
+            });
+        }
+        let Some(fallback) = fallback else {
+            return Err(anyhow!(
+                "Jito bundle {} didn't land within {}s",
+                bundle_id,
+                self.timeout.as_secs()
+            ));
+        };
+        tracing::warn!(bundle_id, "bundle didn't land, sending through RPC");
+        Ok(BundleResult {
+            signature: fallback(tx.clone()).await?,
+            bundle_id,
+            fell_back_to_rpc: true,
+        })
+    }
+}
+
+/// Appends a transfer of `lamports` from the fee payer `owner` to
+/// `tip_account` to `tx`, which pays the block engine for the bundle.
+/// Any signatures are invalidated.
+pub fn append_tip(
+    tx: &mut VersionedTransaction,
+    owner: &Pubkey,
+    tip_account: &Pubkey,
+    lamports: u64,
+) -> Result<()> {
+    if tx.message.static_account_keys().first() != Some(owner) {
+        return Err(anyhow!("{} doesn't pay for the transaction", owner));
+    }
+    let tip_index = static_account_index(&mut tx.message, tip_account, true)?;
+    let program_id_index =
+        static_account_index(&mut tx.message, &system_program::id(), false)?;
+    let tip = CompiledInstruction {
+        program_id_index,
+        accounts: vec![0, tip_index],
+        data: system_instruction::transfer(owner, tip_account, lamports).data,
+    };
+    match &mut tx.message {
+        VersionedMessage::Legacy(message) => message.instructions.push(tip),
+        VersionedMessage::V0(message) => message.instructions.push(tip),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solana::transaction::get_jito_tip_pubkey;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::message::v0;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::transaction::Transaction;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn signed_transfer(keypair: &Keypair) -> VersionedTransaction {
+        let mut tx: VersionedTransaction = Transaction::new_with_payer(
+            &[system_instruction::transfer(
+                &keypair.pubkey(),
+                &Pubkey::new_unique(),
+                1,
+            )],
+            Some(&keypair.pubkey()),
+        )
+        .into();
+        append_tip(&mut tx, &keypair.pubkey(), &get_jito_tip_pubkey(), 5000)
+            .unwrap();
+        tx.signatures[0] = keypair.sign_message(&tx.message.serialize());
+        tx
+    }
+
+    fn client(server: &mockito::Server, timeout_secs: u64) -> JitoClient {
+        JitoClient::new(&JitoConfig {
+            block_engine_url: server.url(),
+            tip_floor_url: format!("{}/tip_floor", server.url()),
+            timeout_secs,
+            ..Default::default()
+        })
+        .with_poll_interval(Duration::from_millis(10))
+    }
+
+    fn rpc_result(result: Value) -> String {
+        json!({ "jsonrpc": "2.0", "id": 1, "result": result }).to_string()
+    }
+
+    fn inflight_status(status: &str) -> String {
+        rpc_result(json!({
+            "context": { "slot": 1 },
+            "value": [{
+                "bundle_id": "bundle",
+                "status": status,
+                "landed_slot": (status == "Landed").then_some(2),
+            }]
+        }))
+    }
+
+    #[test]
+    fn test_append_tip() {
+        let owner = Pubkey::new_unique();
+        let tip_account = get_jito_tip_pubkey();
+        let program = Pubkey::new_unique();
+        // the program reads an account loaded from a lookup table, which
+        // sits after the static ones
+        let message = v0::Message {
+            header: solana_sdk::message::MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            },
+            account_keys: vec![owner, program],
+            recent_blockhash: Hash::default(),
+            instructions: vec![CompiledInstruction::new_from_raw_parts(
+                1,
+                vec![],
+                vec![0, 2],
+            )],
+            address_table_lookups: vec![
+                solana_sdk::message::v0::MessageAddressTableLookup {
+                    account_key: Pubkey::new_unique(),
+                    writable_indexes: vec![],
+                    readonly_indexes: vec![0],
+                },
+            ],
+        };
+        let mut tx = VersionedTransaction {
+            signatures: vec![Default::default()],
+            message: VersionedMessage::V0(message),
+        };
+
+        append_tip(&mut tx, &owner, &tip_account, 5000).unwrap();
+
+        let keys = tx.message.static_account_keys();
+        assert_eq!(
+            keys,
+            &[owner, tip_account, program, system_program::id()]
+        );
+        assert!(tx.message.is_maybe_writable(1, None));
+        let ixs = tx.message.instructions();
+        // the program and the loaded account moved up with the tip account
+        assert_eq!(ixs[0].program_id_index, 2);
+        assert_eq!(ixs[0].accounts, vec![0, 4]);
+        assert_eq!(ixs[1].program_id_index, 3);
+        assert_eq!(ixs[1].accounts, vec![0, 1]);
+        assert_eq!(
+            bincode::deserialize::<system_instruction::SystemInstruction>(
+                &ixs[1].data
+            )
+            .unwrap(),
+            system_instruction::SystemInstruction::Transfer {
+                lamports: 5000
+            }
+        );
+    }
+
+    #[test]
+    fn test_append_tip_needs_fee_payer() {
+        let keypair = Keypair::new();
+        let mut tx = signed_transfer(&keypair);
+        assert!(append_tip(
+            &mut tx,
+            &Pubkey::new_unique(),
+            &get_jito_tip_pubkey(),
+            5000
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bundle_payload() {
+        let keypair = Keypair::new();
+        let tx = signed_transfer(&keypair);
+        let encoded =
+            BASE64_STANDARD.encode(bincode::serialize(&tx).unwrap());
+
+        let mut server = mockito::Server::new_async().await;
+        let send = server
+            .mock("POST", "/api/v1/bundles")
+            .match_body(mockito::Matcher::Json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "sendBundle",
+                "params": [[encoded], { "encoding": "base64" }],
+            })))
+            .with_body(rpc_result(json!("bundle")))
+            .expect(1)
+            .create_async()
+            .await;
+        let status = server
+            .mock("POST", "/api/v1/getInflightBundleStatuses")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "method": "getInflightBundleStatuses",
+                "params": [["bundle"]],
+            })))
+            .with_body(inflight_status("Landed"))
+            .expect(1)
+            .create_async()
+            .await;
+
+        let result = client(&server, 5)
+            .send_transaction(
+                &tx,
+                None::<fn(_) -> std::future::Ready<Result<String>>>,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            result,
+            BundleResult {
+                signature: tx.signatures[0].to_string(),
+                bundle_id: "bundle".to_string(),
+                fell_back_to_rpc: false,
+            }
+        );
+        send.assert_async().await;
+        status.assert_async().await;
+
+        // the bundled transaction tips a tip account
+        let bundled: VersionedTransaction =
+            bincode::deserialize(&BASE64_STANDARD.decode(encoded).unwrap())
+                .unwrap();
+        let tip = bundled.message.instructions().last().unwrap();
+        let keys = bundled.message.static_account_keys();
+        assert_eq!(keys[tip.program_id_index as usize], system_program::id());
+        assert_eq!(keys[tip.accounts[0] as usize], keypair.pubkey());
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_rpc_after_timeout() {
+        let keypair = Keypair::new();
+        let tx = signed_transfer(&keypair);
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/api/v1/bundles")
+            .with_body(rpc_result(json!("bundle")))
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/api/v1/getInflightBundleStatuses")
+            .with_body(inflight_status("Pending"))
+            .create_async()
+            .await;
+
+        let sent = AtomicUsize::new(0);
+        let result = client(&server, 0)
+            .send_transaction(
+                &tx,
+                Some(|fallback: VersionedTransaction| {
+                    sent.fetch_add(1, Ordering::SeqCst);
+                    async move { Ok(fallback.signatures[0].to_string()) }
+                }),
+            )
+            .await
+            .unwrap();
+        assert!(result.fell_back_to_rpc);
+        assert_eq!(result.signature, tx.signatures[0].to_string());
+        assert_eq!(sent.load(Ordering::SeqCst), 1);
+
+        // without a fallback the timeout is reported
+        let err = client(&server, 0)
+            .send_transaction(
+                &tx,
+                None::<fn(_) -> std::future::Ready<Result<String>>>,
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("didn't land"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_bundle_is_not_retried() {
+        let keypair = Keypair::new();
+        let tx = signed_transfer(&keypair);
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/api/v1/bundles")
+            .with_body(rpc_result(json!("bundle")))
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/api/v1/getInflightBundleStatuses")
+            .with_body(inflight_status("Failed"))
+            .create_async()
+            .await;
+
+        let sent = AtomicUsize::new(0);
+        let err = client(&server, 5)
+            .send_transaction(
+                &tx,
+                Some(|fallback: VersionedTransaction| {
+                    sent.fetch_add(1, Ordering::SeqCst);
+                    async move { Ok(fallback.signatures[0].to_string()) }
+                }),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("failed"));
+        assert_eq!(sent.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_tip_floor() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/tip_floor")
+            .with_body(
+                json!([{
+                    "time": "2025-01-01T00:00:00Z",
+                    "landed_tips_50th_percentile": 0.00002,
+                    "ema_landed_tips_50th_percentile": 0.00003,
+                }])
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        assert_eq!(
+            client(&server, 5).tip_floor_lamports().await.unwrap(),
+            30_000
+        );
+    }
+}
@@ -1,12 +1,21 @@
+use std::collections::HashSet;
+use std::fmt;
 use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::transaction::VersionedTransaction;
 
+use super::tools::create_rpc;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PlatformFee {
     pub amount: String,
@@ -148,38 +157,484 @@ pub struct AccountMeta {
     pub is_writable: bool,
 }
 
-pub struct Jupiter;
+pub const JUPITER_API_URL: &str = "https://quote-api.jup.ag/v6";
+pub const DEFAULT_JUPITER_MAX_RETRIES: u32 = 3;
+/// jupiter's referral program, owner of the referral fee token accounts
+pub const JUPITER_REFERRAL_PROGRAM_ID: &str =
+    "REFER4ZgmyYx9c6He5XfaTMiGfdLwRnkV4RPp9t9iF3";
+/// referral token accounts known to be initialized, they stay so
+static FEE_ACCOUNTS: Lazy<Mutex<HashSet<Pubkey>>> =
+    Lazy::new(Default::default);
+/// first backoff without a `Retry-After`, doubled on every retry
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Jupiter kept answering 429 after all retries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimited {
+    pub attempts: u32,
+    /// the last `Retry-After` jupiter sent
+    pub retry_after: Option<Duration>,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Jupiter rate limit hit after {} attempts, try again later",
+            self.attempts
+        )
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Whether the quoted amount is what goes in or what comes out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapMode {
+    ExactIn,
+    ExactOut,
+}
+
+impl fmt::Display for SwapMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ExactIn => write!(f, "ExactIn"),
+            Self::ExactOut => write!(f, "ExactOut"),
+        }
+    }
+}
+
+/// jupiter found no ExactOut route between the mints, fewer venues quote
+/// exact outputs than exact inputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExactOutUnsupported {
+    pub input_mint: String,
+    pub output_mint: String,
+}
+
+impl fmt::Display for ExactOutUnsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "No exact-out route from {} to {}, swap an exact input amount \
+            instead",
+            self.input_mint, self.output_mint
+        )
+    }
+}
+
+impl std::error::Error for ExactOutUnsupported {}
+
+/// the `errorCode`s of quotes jupiter has no route for
+const NO_ROUTE_ERRORS: [&str; 2] =
+    ["COULD_NOT_FIND_ANY_ROUTE", "NO_ROUTES_FOUND"];
+
+/// A referral account whose token accounts collect a platform fee on swaps
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JupiterReferral {
+    pub account: Pubkey,
+    pub fee_bps: u16,
+}
+
+impl JupiterReferral {
+    /// `JUPITER_REFERRAL_ACCOUNT`, with the fee from
+    /// `JUPITER_PLATFORM_FEE_BPS`, none unless both are set and valid
+    pub fn from_env() -> Option<Self> {
+        let account = std::env::var("JUPITER_REFERRAL_ACCOUNT").ok()?;
+        let account = match Pubkey::from_str(account.trim()) {
+            Ok(account) => account,
+            Err(e) => {
+                tracing::warn!(
+                    "invalid JUPITER_REFERRAL_ACCOUNT {}: {}",
+                    account,
+                    e
+                );
+                return None;
+            }
+        };
+        // a fee is only taken when it is set on purpose
+        let fee_bps = std::env::var("JUPITER_PLATFORM_FEE_BPS").ok();
+        let fee_bps = match fee_bps.as_deref().map(|bps| bps.trim().parse()) {
+            Some(Ok(fee_bps)) => fee_bps,
+            Some(Err(e)) => {
+                tracing::warn!("invalid JUPITER_PLATFORM_FEE_BPS: {}", e);
+                return None;
+            }
+            None => {
+                tracing::warn!(
+                    "JUPITER_REFERRAL_ACCOUNT is set without \
+                     JUPITER_PLATFORM_FEE_BPS, no platform fee is taken"
+                );
+                return None;
+            }
+        };
+        Some(Self { account, fee_bps })
+    }
+
+    /// the referral token account of `mint`, it has to be initialized for
+    /// jupiter to take the fee
+    pub fn fee_account(&self, mint: &Pubkey) -> Pubkey {
+        let program_id = Pubkey::from_str(JUPITER_REFERRAL_PROGRAM_ID)
+            .expect("valid referral program id");
+        Pubkey::find_program_address(
+            &[b"referral_ata", self.account.as_ref(), mint.as_ref()],
+            &program_id,
+        )
+        .0
+    }
+
+    /// fees are taken in the output for exact inputs and in the input for
+    /// exact outputs
+    pub fn fee_mint<'a>(
+        input_mint: &'a str,
+        output_mint: &'a str,
+        mode: SwapMode,
+    ) -> &'a str {
+        match mode {
+            SwapMode::ExactIn => output_mint,
+            SwapMode::ExactOut => input_mint,
+        }
+    }
+
+    /// the referral token account the fee of `quote` goes to
+    pub fn fee_account_for_quote(
+        &self,
+        quote: &QuoteResponse,
+    ) -> Result<Pubkey> {
+        let mode = if quote.swap_mode == SwapMode::ExactOut.to_string() {
+            SwapMode::ExactOut
+        } else {
+            SwapMode::ExactIn
+        };
+        let mint =
+            Self::fee_mint(&quote.input_mint, &quote.output_mint, mode);
+        Ok(self.fee_account(&Pubkey::from_str(mint)?))
+    }
+}
+
+/// `Retry-After` in seconds, the HTTP-date form is not used by jupiter
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Quote and swap requests that back off and retry on 429s
+#[derive(Debug, Clone)]
+pub struct JupiterClient {
+    client: reqwest::Client,
+    base_url: String,
+    max_retries: u32,
+    referral: Option<JupiterReferral>,
+    /// the only dexes quotes may route through, see `routes::Venue`
+    dexes: Option<Vec<String>>,
+    /// where the referral token accounts are looked up, `SOLANA_RPC_URL`
+    /// by default
+    rpc_url: Option<String>,
+}
+
+impl Default for JupiterClient {
+    fn default() -> Self {
+        Self::new(JUPITER_API_URL)
+    }
+}
+
+impl JupiterClient {
+    /// retries default to `JUPITER_MAX_RETRIES` if set, a referral fee is
+    /// taken when `JUPITER_REFERRAL_ACCOUNT` is
+    pub fn new(base_url: &str) -> Self {
+        let max_retries = std::env::var("JUPITER_MAX_RETRIES")
+            .ok()
+            .and_then(|retries| retries.parse().ok())
+            .unwrap_or(DEFAULT_JUPITER_MAX_RETRIES);
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            max_retries,
+            referral: JupiterReferral::from_env(),
+            dexes: None,
+            rpc_url: None,
+        }
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_referral(
+        mut self,
+        referral: Option<JupiterReferral>,
+    ) -> Self {
+        self.referral = referral;
+        self
+    }
+
+    pub fn with_rpc_url(mut self, rpc_url: &str) -> Self {
+        self.rpc_url = Some(rpc_url.to_string());
+        self
+    }
+
+    /// The fee of the referral if the token account it goes to is
+    /// initialized, jupiter fails the swap otherwise. Not knowing counts as
+    /// not initialized, the swap goes through without a fee then
+    async fn platform_fee_bps(
+        &self,
+        input_mint: &str,
+        output_mint: &str,
+        mode: SwapMode,
+    ) -> Option<u16> {
+        let referral = self.referral.as_ref()?;
+        let mint = JupiterReferral::fee_mint(input_mint, output_mint, mode);
+        let fee_account = referral.fee_account(&Pubkey::from_str(mint).ok()?);
+        if FEE_ACCOUNTS.lock().unwrap().contains(&fee_account) {
+            return Some(referral.fee_bps);
+        }
+
+        let rpc_client = match &self.rpc_url {
+            Some(rpc_url) => RpcClient::new(rpc_url.clone()),
+            None => create_rpc(),
+        };
+        match rpc_client
+            .get_account_with_commitment(
+                &fee_account,
+                CommitmentConfig::confirmed(),
+            )
+            .await
+        {
+            Ok(response) if response.value.is_some() => {
+                FEE_ACCOUNTS.lock().unwrap().insert(fee_account);
+                Some(referral.fee_bps)
+            }
+            Ok(_) => {
+                tracing::info!(%fee_account, mint, "no referral account");
+                None
+            }
+            Err(e) => {
+                tracing::warn!(
+                    %fee_account,
+                    %e,
+                    "failed to look up the referral token account"
+                );
+                None
+            }
+        }
+    }
+
+    /// restricts the routes to the dexes by their jupiter labels, e.g.
+    /// "Raydium CLMM"
+    pub fn with_dexes(mut self, dexes: Option<&[&str]>) -> Self {
+        self.dexes = dexes
+            .map(|dexes| dexes.iter().map(|dex| dex.to_string()).collect());
+        self
+    }
+
+    async fn send(
+        &self,
+        request: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let response = request().send().await?;
+            if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                return Ok(response);
+            }
+            let retry_after = retry_after(&response);
+            if attempt > self.max_retries {
+                return Err(RateLimited {
+                    attempts: attempt,
+                    retry_after,
+                }
+                .into());
+            }
+            let backoff = retry_after
+                .unwrap_or(BASE_BACKOFF * 2u32.pow(attempt - 1))
+                .min(MAX_BACKOFF);
+            tracing::warn!(
+                attempt,
+                ?backoff,
+                "jupiter rate limit hit, backing off"
+            );
+            tokio::time::sleep(backoff).await;
+        }
+    }
 
-impl Jupiter {
     pub async fn fetch_quote(
+        &self,
         input_mint: &str,
         output_mint: &str,
         amount: u64,
     ) -> Result<QuoteResponse> {
-        let url = format!(
-            "https://quote-api.jup.ag/v6/quote?inputMint={}&outputMint={}&amount={}",
-            input_mint, output_mint, amount,
+        self.fetch_quote_with_slippage(input_mint, output_mint, amount, None)
+            .await
+    }
+
+    /// `slippage_bps` sets the tolerance of the quote, jupiter's default
+    /// otherwise
+    pub async fn fetch_quote_with_slippage(
+        &self,
+        input_mint: &str,
+        output_mint: &str,
+        amount: u64,
+        slippage_bps: Option<u16>,
+    ) -> Result<QuoteResponse> {
+        self.fetch_quote_with_mode(
+            input_mint,
+            output_mint,
+            amount,
+            slippage_bps,
+            SwapMode::ExactIn,
+        )
+        .await
+    }
+
+    /// `amount` is the output for `SwapMode::ExactOut`, a missing route is
+    /// an `ExactOutUnsupported` then
+    pub async fn fetch_quote_with_mode(
+        &self,
+        input_mint: &str,
+        output_mint: &str,
+        amount: u64,
+        slippage_bps: Option<u16>,
+        mode: SwapMode,
+    ) -> Result<QuoteResponse> {
+        let mut url = format!(
+            "{}/quote?inputMint={}&outputMint={}&amount={}",
+            self.base_url, input_mint, output_mint, amount,
         );
+        if let Some(slippage_bps) = slippage_bps {
+            url.push_str(&format!("&slippageBps={}", slippage_bps));
+        }
+        if mode == SwapMode::ExactOut {
+            url.push_str(&format!("&swapMode={}", mode));
+        }
+        if let Some(fee_bps) =
+            self.platform_fee_bps(input_mint, output_mint, mode).await
+        {
+            url.push_str(&format!("&platformFeeBps={}", fee_bps));
+        }
+        if let Some(dexes) = &self.dexes {
+            let mut with_dexes = reqwest::Url::parse(&url)?;
+            with_dexes
+                .query_pairs_mut()
+                .append_pair("dexes", &dexes.join(","));
+            url = with_dexes.to_string();
+        }
 
-        let response =
-            reqwest::get(&url).await?.json::<QuoteResponse>().await?;
-        Ok(response)
+        let response = self.send(|| self.client.get(&url)).await?;
+        if !response.status().is_success() {
+            let error = response.text().await.map_err(|e| anyhow!(e))?;
+            let no_route = serde_json::from_str::<serde_json::Value>(&error)
+                .ok()
+                .and_then(|error| {
+                    error["errorCode"]
+                        .as_str()
+                        .map(|code| NO_ROUTE_ERRORS.contains(&code))
+                })
+                .unwrap_or(false);
+            if mode == SwapMode::ExactOut && no_route {
+                return Err(ExactOutUnsupported {
+                    input_mint: input_mint.to_string(),
+                    output_mint: output_mint.to_string(),
+                }
+                .into());
+            }
+            return Err(anyhow!(error));
+        }
+        Ok(response.json::<QuoteResponse>().await?)
     }
 
+    /// A quote for exactly `out_amount` of `output_mint`, refused when the
+    /// most it could take with slippage is over `max_in_amount`
+    pub async fn fetch_exact_out_quote(
+        &self,
+        input_mint: &str,
+        output_mint: &str,
+        out_amount: u64,
+        max_in_amount: u64,
+        slippage_bps: Option<u16>,
+    ) -> Result<QuoteResponse> {
+        let quote = self
+            .fetch_quote_with_mode(
+                input_mint,
+                output_mint,
+                out_amount,
+                slippage_bps,
+                SwapMode::ExactOut,
+            )
+            .await?;
+        let in_amount: u64 = quote.in_amount.parse()?;
+        // for exact outputs the threshold is the most that can go in
+        let most_in: u64 = quote.other_amount_threshold.parse()?;
+        if most_in > max_in_amount {
+            return Err(anyhow!(
+                "Getting {} of {} takes {} of {} ({} with slippage), more \
+                than the max_in_amount of {}",
+                out_amount,
+                output_mint,
+                in_amount,
+                input_mint,
+                most_in,
+                max_in_amount
+            ));
+        }
+        Ok(quote)
+    }
+
+    /// jupiter picks the slippage at the time of the swap
     pub async fn swap(
+        &self,
+        quote_response: QuoteResponse,
+        owner: &Pubkey,
+    ) -> Result<VersionedTransaction> {
+        self.send_swap(quote_response, owner, true).await
+    }
+
+    /// keeps the slippage of the quote, for quotes fetched with one
+    pub async fn swap_with_fixed_slippage(
+        &self,
         quote_response: QuoteResponse,
         owner: &Pubkey,
     ) -> Result<VersionedTransaction> {
-        let swap_request = serde_json::json!({
+        self.send_swap(quote_response, owner, false).await
+    }
+
+    async fn send_swap(
+        &self,
+        quote_response: QuoteResponse,
+        owner: &Pubkey,
+        dynamic_slippage: bool,
+    ) -> Result<VersionedTransaction> {
+        // quotes fetched without a platform fee are swapped without one
+        let fee_account = match (&self.referral, &quote_response.platform_fee)
+        {
+            (Some(referral), Some(_)) => {
+                Some(referral.fee_account_for_quote(&quote_response)?)
+            }
+            _ => None,
+        };
+        let mut swap_request = serde_json::json!({
             "userPublicKey": owner.to_string(),
             "quoteResponse": quote_response,
-            "dynamicSlippage": true,
+            "dynamicSlippage": dynamic_slippage,
+            // SOL is wrapped for the swap and unwrapped in the same
+            // transaction
+            "wrapAndUnwrapSol": true,
         });
-        let client = reqwest::Client::new();
-        let raw_res = client
-            .post("https://quote-api.jup.ag/v6/swap")
-            .json(&swap_request)
-            .send()
+        if let Some(fee_account) = fee_account {
+            swap_request["feeAccount"] = fee_account.to_string().into();
+        }
+        let url = format!("{}/swap", self.base_url);
+        let raw_res = self
+            .send(|| self.client.post(&url).json(&swap_request))
             .await?;
         if !raw_res.status().is_success() {
             let error = raw_res.text().await.map_err(|e| anyhow!(e))?;
@@ -199,6 +654,70 @@ impl Jupiter {
 
         Ok(tx)
     }
+}
+
+pub struct Jupiter;
+
+impl Jupiter {
+    pub async fn fetch_quote(
+        input_mint: &str,
+        output_mint: &str,
+        amount: u64,
+    ) -> Result<QuoteResponse> {
+        JupiterClient::default()
+            .fetch_quote(input_mint, output_mint, amount)
+            .await
+    }
+
+    pub async fn fetch_quote_with_slippage(
+        input_mint: &str,
+        output_mint: &str,
+        amount: u64,
+        slippage_bps: Option<u16>,
+    ) -> Result<QuoteResponse> {
+        JupiterClient::default()
+            .fetch_quote_with_slippage(
+                input_mint,
+                output_mint,
+                amount,
+                slippage_bps,
+            )
+            .await
+    }
+
+    pub async fn fetch_exact_out_quote(
+        input_mint: &str,
+        output_mint: &str,
+        out_amount: u64,
+        max_in_amount: u64,
+        slippage_bps: Option<u16>,
+    ) -> Result<QuoteResponse> {
+        JupiterClient::default()
+            .fetch_exact_out_quote(
+                input_mint,
+                output_mint,
+                out_amount,
+                max_in_amount,
+                slippage_bps,
+            )
+            .await
+    }
+
+    pub async fn swap(
+        quote_response: QuoteResponse,
+        owner: &Pubkey,
+    ) -> Result<VersionedTransaction> {
+        JupiterClient::default().swap(quote_response, owner).await
+    }
+
+    pub async fn swap_with_fixed_slippage(
+        quote_response: QuoteResponse,
+        owner: &Pubkey,
+    ) -> Result<VersionedTransaction> {
+        JupiterClient::default()
+            .swap_with_fixed_slippage(quote_response, owner)
+            .await
+    }
 
     fn _convert_instruction_data(
         ix_data: InstructionData,
@@ -244,6 +763,372 @@ mod tests {
     const TEST_ADDRESS_SOL: &str =
         "6fp9frQ16W3kTRGiBVvpMS2NzoixE4Y1MWqYrW9SvTAj";
 
+    const QUOTE: &str = r#"{
+        "inputMint": "So11111111111111111111111111111111111111112",
+        "inAmount": "1000",
+        "outputMint": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+        "outAmount": "2000",
+        "otherAmountThreshold": "1990",
+        "swapMode": "ExactIn",
+        "slippageBps": 50,
+        "platformFee": null,
+        "priceImpactPct": "0",
+        "routePlan": [],
+        "contextSlot": 1,
+        "timeTaken": 0.01
+    }"#;
+
+    #[tokio::test]
+    async fn test_quote_retries_after_rate_limit() {
+        let mut server = mockito::Server::new_async().await;
+        let rate_limited = server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::Any)
+            .with_status(429)
+            .with_header("retry-after", "0")
+            .expect(1)
+            .create_async()
+            .await;
+        let quote = server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::Any)
+            .with_body(QUOTE)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = JupiterClient::new(&server.url()).with_max_retries(2);
+        let response = client
+            .fetch_quote(
+                "So11111111111111111111111111111111111111112",
+                "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                1000,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.out_amount, "2000");
+        rate_limited.assert_async().await;
+        quote.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_slippage_reaches_quote_and_swap() {
+        let mut server = mockito::Server::new_async().await;
+        let quote = server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "slippageBps".into(),
+                "300".into(),
+            ))
+            .with_body(QUOTE)
+            .expect(1)
+            .create_async()
+            .await;
+        // jupiter would override the slippage of the quote otherwise
+        let swap = server
+            .mock("POST", "/swap")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "dynamicSlippage": false,
+            })))
+            .with_status(500)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = JupiterClient::new(&server.url()).with_max_retries(0);
+        let response = client
+            .fetch_quote_with_slippage(
+                "So11111111111111111111111111111111111111112",
+                "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                1000,
+                Some(300),
+            )
+            .await
+            .unwrap();
+        assert!(client
+            .swap_with_fixed_slippage(response, &Pubkey::new_unique())
+            .await
+            .is_err());
+        quote.assert_async().await;
+        swap.assert_async().await;
+    }
+
+    /// answers `getAccountInfo` for the referral token account, with an
+    /// account if `exists`
+    async fn mock_fee_account(
+        server: &mut mockito::Server,
+        fee_account: &Pubkey,
+        exists: bool,
+    ) -> mockito::Mock {
+        let value = exists.then(|| {
+            serde_json::json!({
+                "data": ["", "base64"],
+                "executable": false,
+                "lamports": 2_039_280,
+                "owner": spl_token::id().to_string(),
+                "rentEpoch": 0,
+                "space": 0,
+            })
+        });
+        server
+            .mock("POST", "/")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "method": "getAccountInfo",
+                "params": [fee_account.to_string()],
+            })))
+            .with_body(
+                serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "result": {"context": {"slot": 1}, "value": value},
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_referral_fee_reaches_quote_and_swap() {
+        let referral = JupiterReferral {
+            account: Pubkey::new_unique(),
+            fee_bps: 20,
+        };
+        let output_mint =
+            Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v")
+                .unwrap();
+        let mut server = mockito::Server::new_async().await;
+        let rpc = mock_fee_account(
+            &mut server,
+            &referral.fee_account(&output_mint),
+            true,
+        )
+        .await;
+        let quote = server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "platformFeeBps".into(),
+                "20".into(),
+            ))
+            .with_body(QUOTE.replace(
+                r#""platformFee": null"#,
+                r#""platformFee": {"amount": "4", "feeBps": 20}"#,
+            ))
+            .expect(1)
+            .create_async()
+            .await;
+        let swap = server
+            .mock("POST", "/swap")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "feeAccount": referral.fee_account(&output_mint).to_string(),
+            })))
+            .with_status(500)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = JupiterClient::new(&server.url())
+            .with_max_retries(0)
+            .with_referral(Some(referral.clone()))
+            .with_rpc_url(&server.url());
+        let response = client
+            .fetch_quote(
+                "So11111111111111111111111111111111111111112",
+                &output_mint.to_string(),
+                1000,
+            )
+            .await
+            .unwrap();
+        assert!(client.swap(response, &Pubkey::new_unique()).await.is_err());
+        rpc.assert_async().await;
+        quote.assert_async().await;
+        swap.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_no_fee_without_referral_token_account() {
+        let referral = JupiterReferral {
+            account: Pubkey::new_unique(),
+            fee_bps: 20,
+        };
+        let output_mint =
+            Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v")
+                .unwrap();
+        let mut server = mockito::Server::new_async().await;
+        let rpc = mock_fee_account(
+            &mut server,
+            &referral.fee_account(&output_mint),
+            false,
+        )
+        .await;
+        let with_fee = server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::Regex("platformFeeBps".into()))
+            .expect(0)
+            .create_async()
+            .await;
+        let quote = server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::Any)
+            .with_body(QUOTE)
+            .expect(1)
+            .create_async()
+            .await;
+        let swap = server
+            .mock("POST", "/swap")
+            .match_body(mockito::Matcher::Regex("feeAccount".into()))
+            .expect(0)
+            .create_async()
+            .await;
+
+        let client = JupiterClient::new(&server.url())
+            .with_max_retries(0)
+            .with_referral(Some(referral))
+            .with_rpc_url(&server.url());
+        let response = client
+            .fetch_quote(
+                "So11111111111111111111111111111111111111112",
+                &output_mint.to_string(),
+                1000,
+            )
+            .await
+            .unwrap();
+        assert!(response.platform_fee.is_none());
+        // the swap goes out without a fee account, the mock has no route
+        // for it
+        assert!(client.swap(response, &Pubkey::new_unique()).await.is_err());
+        rpc.assert_async().await;
+        with_fee.assert_async().await;
+        quote.assert_async().await;
+        swap.assert_async().await;
+    }
+
+    const EXACT_OUT_QUOTE: &str = r#"{
+        "inputMint": "So11111111111111111111111111111111111111112",
+        "inAmount": "1000",
+        "outputMint": "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263",
+        "outAmount": "100000000000",
+        "otherAmountThreshold": "1005",
+        "swapMode": "ExactOut",
+        "slippageBps": 50,
+        "platformFee": null,
+        "priceImpactPct": "0",
+        "routePlan": [],
+        "contextSlot": 1,
+        "timeTaken": 0.01
+    }"#;
+
+    async fn exact_out_quote_server() -> mockito::ServerGuard {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded(
+                    "swapMode".into(),
+                    "ExactOut".into(),
+                ),
+                mockito::Matcher::UrlEncoded(
+                    "amount".into(),
+                    "100000000000".into(),
+                ),
+            ]))
+            .with_body(EXACT_OUT_QUOTE)
+            .create_async()
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn test_exact_out_quote_within_budget() {
+        let server = exact_out_quote_server().await;
+        let client = JupiterClient::new(&server.url()).with_max_retries(0);
+        let quote = client
+            .fetch_exact_out_quote(
+                "So11111111111111111111111111111111111111112",
+                "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263",
+                100_000_000_000,
+                1005,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(quote.in_amount, "1000");
+        assert_eq!(quote.swap_mode, "ExactOut");
+    }
+
+    #[tokio::test]
+    async fn test_exact_out_quote_over_budget() {
+        let server = exact_out_quote_server().await;
+        let client = JupiterClient::new(&server.url()).with_max_retries(0);
+        // the quoted 1000 fits, the 1005 it may take with slippage doesn't
+        let err = client
+            .fetch_exact_out_quote(
+                "So11111111111111111111111111111111111111112",
+                "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263",
+                100_000_000_000,
+                1000,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("max_in_amount of 1000"));
+    }
+
+    #[tokio::test]
+    async fn test_exact_out_without_route() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::Any)
+            .with_status(400)
+            .with_body(
+                r#"{"error":"Could not find any route",
+                "errorCode":"COULD_NOT_FIND_ANY_ROUTE"}"#,
+            )
+            .create_async()
+            .await;
+        let client = JupiterClient::new(&server.url()).with_max_retries(0);
+        let err = client
+            .fetch_exact_out_quote("a", "b", 1, 1, None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ExactOutUnsupported>(),
+            Some(&ExactOutUnsupported {
+                input_mint: "a".to_string(),
+                output_mint: "b".to_string(),
+            })
+        );
+        // exact-in quotes keep jupiter's error
+        let err = client.fetch_quote("a", "b", 1).await.unwrap_err();
+        assert!(err.downcast_ref::<ExactOutUnsupported>().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_after_retries() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::Any)
+            .with_status(429)
+            .with_header("retry-after", "0")
+            .expect(3)
+            .create_async()
+            .await;
+
+        let client = JupiterClient::new(&server.url()).with_max_retries(2);
+        let err = client.fetch_quote("a", "b", 1).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RateLimited>(),
+            Some(&RateLimited {
+                attempts: 3,
+                retry_after: Some(Duration::ZERO)
+            })
+        );
+        mock.assert_async().await;
+    }
+
     #[tokio::test]
     async fn test_e2e_versioned_with_local_signer() {
         let signer = make_test_signer();
@@ -0,0 +1,1015 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcProgramAccountsConfig,
+};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::{Transaction, VersionedTransaction};
+use solana_sdk::{system_program, sysvar};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use std::str::FromStr;
+
+use crate::solana::wsol::{wrap_sol_ixs, wsol_address};
+
+pub const KAMINO_LENDING_PROGRAM_ID: &str =
+    "KLend2g3cP87fffoy8q1mQqGKjrxjC8boSyAYavgmjD";
+pub const KAMINO_FARMS_PROGRAM_ID: &str =
+    "FarmsPZpWu9i7Kky8tPN37rs2TpmMrAZrC7S7vJa91Hr";
+pub const KAMINO_MAIN_MARKET: &str =
+    "7u3HeHxYDLhnCoErrtycNokbQYbWGzLs6JSDqGAv5PfF";
+
+/// anchor discriminators, the first 8 bytes of sha256 of "global:<name>"
+/// of each instruction and "account:Reserve" and "account:Obligation"
+const INIT_USER_METADATA: [u8; 8] =
+    [0x75, 0xa9, 0xb0, 0x45, 0xc5, 0x17, 0x0f, 0xa2];
+const INIT_OBLIGATION: [u8; 8] =
+    [0xfb, 0x0a, 0xe7, 0x4c, 0x1b, 0x0b, 0x9f, 0x60];
+const INIT_OBLIGATION_FARMS_FOR_RESERVE: [u8; 8] =
+    [0x88, 0x3f, 0x0f, 0xba, 0xd3, 0x98, 0xa8, 0xa4];
+const REFRESH_RESERVE: [u8; 8] =
+    [0x02, 0xda, 0x8a, 0xeb, 0x4f, 0xc9, 0x19, 0x66];
+const REFRESH_OBLIGATION: [u8; 8] =
+    [0x21, 0x84, 0x93, 0xe4, 0x97, 0xc0, 0x48, 0x59];
+const DEPOSIT_V2: [u8; 8] = [0xd8, 0xe0, 0xbf, 0x1b, 0xcc, 0x97, 0x66, 0xaf];
+const BORROW_V2: [u8; 8] = [0xa1, 0x80, 0x8f, 0xf5, 0xab, 0xc7, 0xc2, 0x06];
+const RESERVE_ACCOUNT: [u8; 8] =
+    [0x2b, 0xf2, 0xcc, 0xca, 0x1a, 0xf7, 0x3b, 0x7f];
+const OBLIGATION_ACCOUNT: [u8; 8] =
+    [0xa8, 0xce, 0x8d, 0x6a, 0x58, 0x4c, 0xac, 0xa7];
+
+pub const RESERVE_LEN: usize = 8624;
+/// where the fields of `Reserve` the tools read start, after the
+/// discriminator, version(8) and last_update(16)
+const RESERVE_LENDING_MARKET: usize = 32;
+const RESERVE_FARM_COLLATERAL: usize = 64;
+const RESERVE_FARM_DEBT: usize = 96;
+/// `ReserveLiquidity`: mint(32) supply_vault(32) fee_vault(32)
+/// available_amount(8) borrowed_amount_sf(16) market_price_sf(16)
+/// market_price_last_updated_ts(8) mint_decimals(8), token_program at 408
+const RESERVE_LIQUIDITY: usize = 128;
+const RESERVE_LIQUIDITY_TOKEN_PROGRAM: usize = 408;
+/// `ReserveCollateral`: mint(32) mint_total_supply(8) supply_vault(32)
+const RESERVE_COLLATERAL: usize = 2560;
+/// `ReserveConfig`: status(1) asset_tier(1) host_fixed_interest_rate_bps(2)
+/// reserved(10) protocol_take_rate_pct(1) ...
+const RESERVE_CONFIG: usize = 4856;
+const RESERVE_BORROW_RATE_CURVE: usize = 4920;
+const BORROW_RATE_CURVE_POINTS: usize = 11;
+/// the oracles of `TokenInfo`: scope price feed, switchboard price and
+/// twap aggregators and the pyth price
+const RESERVE_SCOPE_PRICES: usize = 5112;
+const RESERVE_SWITCHBOARD_PRICE: usize = 5160;
+const RESERVE_SWITCHBOARD_TWAP: usize = 5192;
+const RESERVE_PYTH_PRICE: usize = 5224;
+
+/// `Obligation`: tag(8) last_update(16) lending_market(32) owner(32), then
+/// 8 deposits of 136 bytes and 5 borrows of 200
+const OBLIGATION_LENDING_MARKET: usize = 32;
+const OBLIGATION_DEPOSITS: usize = 96;
+const OBLIGATION_DEPOSIT_LEN: usize = 136;
+const OBLIGATION_DEPOSITS_COUNT: usize = 8;
+const OBLIGATION_DEPOSITED_VALUE: usize = 1192;
+const OBLIGATION_BORROWS: usize = 1208;
+const OBLIGATION_BORROW_LEN: usize = 200;
+const OBLIGATION_BORROWS_COUNT: usize = 5;
+/// borrow_factor_adjusted_debt_value_sf, then borrowed_assets_market_value,
+/// allowed_borrow_value and unhealthy_borrow_value
+const OBLIGATION_DEBT_VALUE: usize = 2208;
+const OBLIGATION_UNHEALTHY_BORROW_VALUE: usize = 2256;
+
+/// klend accrues interest every slot, at its 2 slots a second
+const SLOTS_PER_YEAR: f64 = 63_072_000.;
+/// `_sf` fields are fixed point with 60 fractional bits
+const SCALE: f64 = (1u64 << 60) as f64;
+
+/// the farm the obligation is staked in for rewards, per side
+const FARM_MODE_COLLATERAL: u8 = 0;
+const FARM_MODE_DEBT: u8 = 1;
+
+fn program_id() -> Pubkey {
+    Pubkey::from_str(KAMINO_LENDING_PROGRAM_ID).unwrap()
+}
+
+fn farms_program_id() -> Pubkey {
+    Pubkey::from_str(KAMINO_FARMS_PROGRAM_ID).unwrap()
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N]> {
+    data.get(offset..offset + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| anyhow!("kamino account too short"))
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Result<Pubkey> {
+    Ok(Pubkey::new_from_array(read(data, offset)?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    Ok(u64::from_le_bytes(read(data, offset)?))
+}
+
+fn read_sf(data: &[u8], offset: usize) -> Result<f64> {
+    Ok(u128::from_le_bytes(read(data, offset)?) as f64 / SCALE)
+}
+
+/// an optional account of klend, the program itself stands in for none
+fn optional(account: &Pubkey) -> Pubkey {
+    if *account == Pubkey::default() {
+        program_id()
+    } else {
+        *account
+    }
+}
+
+fn apy(apr: f64) -> f64 {
+    (1. + apr / SLOTS_PER_YEAR).powf(SLOTS_PER_YEAR) - 1.
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CurvePoint {
+    pub utilization_bps: u32,
+    pub borrow_rate_bps: u32,
+}
+
+/// the fields of a klend `Reserve` the tools need
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reserve {
+    pub address: Pubkey,
+    pub lending_market: Pubkey,
+    pub farm_collateral: Pubkey,
+    pub farm_debt: Pubkey,
+    pub liquidity_mint: Pubkey,
+    pub liquidity_supply_vault: Pubkey,
+    pub liquidity_fee_vault: Pubkey,
+    pub liquidity_token_program: Pubkey,
+    pub available_amount: u64,
+    /// raw, with the interest accrued
+    pub borrowed_amount: f64,
+    pub mint_decimals: u8,
+    pub collateral_mint: Pubkey,
+    pub collateral_supply_vault: Pubkey,
+    pub host_fixed_interest_rate_bps: u16,
+    pub protocol_take_rate_pct: u8,
+    pub borrow_rate_curve: Vec<CurvePoint>,
+    pub scope_prices: Pubkey,
+    pub switchboard_price: Pubkey,
+    pub switchboard_twap: Pubkey,
+    pub pyth_price: Pubkey,
+}
+
+/// the rates of a reserve, in percent, and what can still be borrowed in
+/// whole tokens
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KaminoReserveInfo {
+    pub reserve: String,
+    pub mint: String,
+    pub supply_apy: f64,
+    pub borrow_apy: f64,
+    pub utilization_rate: f64,
+    pub available_liquidity: f64,
+}
+
+impl Reserve {
+    pub fn from_account_data(address: Pubkey, data: &[u8]) -> Result<Self> {
+        if data.get(..8) != Some(&RESERVE_ACCOUNT[..]) {
+            return Err(anyhow!("{} is not a kamino reserve", address));
+        }
+        let liquidity = RESERVE_LIQUIDITY;
+        let borrow_rate_curve = (0..BORROW_RATE_CURVE_POINTS)
+            .map(|i| {
+                let offset = RESERVE_BORROW_RATE_CURVE + i * 8;
+                Ok(CurvePoint {
+                    utilization_bps: u32::from_le_bytes(read(data, offset)?),
+                    borrow_rate_bps: u32::from_le_bytes(read(
+                        data,
+                        offset + 4,
+                    )?),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            address,
+            lending_market: read_pubkey(data, RESERVE_LENDING_MARKET)?,
+            farm_collateral: read_pubkey(data, RESERVE_FARM_COLLATERAL)?,
+            farm_debt: read_pubkey(data, RESERVE_FARM_DEBT)?,
+            liquidity_mint: read_pubkey(data, liquidity)?,
+            liquidity_supply_vault: read_pubkey(data, liquidity + 32)?,
+            liquidity_fee_vault: read_pubkey(data, liquidity + 64)?,
+            liquidity_token_program: read_pubkey(
+                data,
+                RESERVE_LIQUIDITY_TOKEN_PROGRAM,
+            )?,
+            available_amount: read_u64(data, liquidity + 96)?,
+            borrowed_amount: read_sf(data, liquidity + 104)?,
+            mint_decimals: read_u64(data, liquidity + 144)? as u8,
+            collateral_mint: read_pubkey(data, RESERVE_COLLATERAL)?,
+            collateral_supply_vault: read_pubkey(
+                data,
+                RESERVE_COLLATERAL + 40,
+            )?,
+            host_fixed_interest_rate_bps: u16::from_le_bytes(read(
+                data,
+                RESERVE_CONFIG + 2,
+            )?),
+            protocol_take_rate_pct: read::<1>(data, RESERVE_CONFIG + 14)?[0],
+            borrow_rate_curve,
+            scope_prices: read_pubkey(data, RESERVE_SCOPE_PRICES)?,
+            switchboard_price: read_pubkey(data, RESERVE_SWITCHBOARD_PRICE)?,
+            switchboard_twap: read_pubkey(data, RESERVE_SWITCHBOARD_TWAP)?,
+            pyth_price: read_pubkey(data, RESERVE_PYTH_PRICE)?,
+        })
+    }
+
+    /// the share of the supply that is borrowed
+    pub fn utilization_rate(&self) -> f64 {
+        let supply = self.available_amount as f64 + self.borrowed_amount;
+        if supply > 0. {
+            self.borrowed_amount / supply
+        } else {
+            0.
+        }
+    }
+
+    /// the curve at the utilization, linear between its points, plus the
+    /// fixed host rate
+    pub fn borrow_apr(&self) -> f64 {
+        let utilization_bps = self.utilization_rate() * 10_000.;
+        let rate_bps = self
+            .borrow_rate_curve
+            .windows(2)
+            .find(|points| {
+                utilization_bps <= points[1].utilization_bps as f64
+            })
+            .map(|points| {
+                let (start, end) = (points[0], points[1]);
+                let span =
+                    (end.utilization_bps - start.utilization_bps) as f64;
+                let progress = if span > 0. {
+                    (utilization_bps - start.utilization_bps as f64) / span
+                } else {
+                    1.
+                };
+                start.borrow_rate_bps as f64
+                    + progress
+                        * (end.borrow_rate_bps as f64
+                            - start.borrow_rate_bps as f64)
+            })
+            .or_else(|| {
+                self.borrow_rate_curve
+                    .last()
+                    .map(|point| point.borrow_rate_bps as f64)
+            })
+            .unwrap_or(0.);
+        (rate_bps + self.host_fixed_interest_rate_bps as f64) / 10_000.
+    }
+
+    /// what depositors earn of the interest, less the protocol take
+    pub fn supply_apr(&self) -> f64 {
+        self.borrow_apr()
+            * self.utilization_rate()
+            * (1. - self.protocol_take_rate_pct as f64 / 100.)
+    }
+
+    pub fn info(&self) -> KaminoReserveInfo {
+        KaminoReserveInfo {
+            reserve: self.address.to_string(),
+            mint: self.liquidity_mint.to_string(),
+            supply_apy: apy(self.supply_apr()) * 100.,
+            borrow_apy: apy(self.borrow_apr()) * 100.,
+            utilization_rate: self.utilization_rate() * 100.,
+            available_liquidity: self.available_amount as f64
+                / 10f64.powi(self.mint_decimals as i32),
+        }
+    }
+
+    /// updates the price and interest of the reserve, every instruction
+    /// that reads it needs this first in the transaction
+    pub fn refresh_ix(&self) -> Instruction {
+        Instruction::new_with_bytes(
+            program_id(),
+            &REFRESH_RESERVE,
+            vec![
+                AccountMeta::new(self.address, false),
+                AccountMeta::new_readonly(self.lending_market, false),
+                AccountMeta::new_readonly(optional(&self.pyth_price), false),
+                AccountMeta::new_readonly(
+                    optional(&self.switchboard_price),
+                    false,
+                ),
+                AccountMeta::new_readonly(
+                    optional(&self.switchboard_twap),
+                    false,
+                ),
+                AccountMeta::new_readonly(
+                    optional(&self.scope_prices),
+                    false,
+                ),
+            ],
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObligationDeposit {
+    pub reserve: String,
+    /// raw collateral tokens of the reserve
+    pub deposited_amount: u64,
+    pub market_value_usd: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObligationBorrow {
+    pub reserve: String,
+    /// raw, with the interest accrued
+    pub borrowed_amount: f64,
+    pub market_value_usd: f64,
+}
+
+/// a position in a market, values are as of its last refresh
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KaminoObligation {
+    pub obligation: String,
+    pub lending_market: String,
+    pub deposits: Vec<ObligationDeposit>,
+    pub borrows: Vec<ObligationBorrow>,
+    pub deposited_value_usd: f64,
+    /// adjusted by the borrow factors of the reserves
+    pub debt_value_usd: f64,
+    /// the debt the obligation can carry before it is liquidated over the
+    /// debt it carries, none without debt
+    pub health_factor: Option<f64>,
+}
+
+impl KaminoObligation {
+    pub fn from_account_data(address: Pubkey, data: &[u8]) -> Result<Self> {
+        if data.get(..8) != Some(&OBLIGATION_ACCOUNT[..]) {
+            return Err(anyhow!("{} is not a kamino obligation", address));
+        }
+        let mut deposits = Vec::new();
+        for i in 0..OBLIGATION_DEPOSITS_COUNT {
+            let offset = OBLIGATION_DEPOSITS + i * OBLIGATION_DEPOSIT_LEN;
+            let reserve = read_pubkey(data, offset)?;
+            if reserve != Pubkey::default() {
+                deposits.push(ObligationDeposit {
+                    reserve: reserve.to_string(),
+                    deposited_amount: read_u64(data, offset + 32)?,
+                    market_value_usd: read_sf(data, offset + 40)?,
+                });
+            }
+        }
+        let mut borrows = Vec::new();
+        for i in 0..OBLIGATION_BORROWS_COUNT {
+            // borrow_reserve(32) cumulative_borrow_rate_bsf(48) padding(8)
+            // borrowed_amount_sf(16) market_value_sf(16)
+            let offset = OBLIGATION_BORROWS + i * OBLIGATION_BORROW_LEN;
+            let reserve = read_pubkey(data, offset)?;
+            if reserve != Pubkey::default() {
+                borrows.push(ObligationBorrow {
+                    reserve: reserve.to_string(),
+                    borrowed_amount: read_sf(data, offset + 88)?,
+                    market_value_usd: read_sf(data, offset + 104)?,
+                });
+            }
+        }
+        let debt_value_usd = read_sf(data, OBLIGATION_DEBT_VALUE)?;
+        let unhealthy_borrow_value =
+            read_sf(data, OBLIGATION_UNHEALTHY_BORROW_VALUE)?;
+        Ok(Self {
+            obligation: address.to_string(),
+            lending_market: read_pubkey(data, OBLIGATION_LENDING_MARKET)?
+                .to_string(),
+            deposits,
+            borrows,
+            deposited_value_usd: read_sf(data, OBLIGATION_DEPOSITED_VALUE)?,
+            debt_value_usd,
+            health_factor: (debt_value_usd > 0.)
+                .then(|| unhealthy_borrow_value / debt_value_usd),
+        })
+    }
+
+    fn reserves(list: &[String]) -> Result<Vec<Pubkey>> {
+        list.iter()
+            .map(|reserve| Ok(Pubkey::from_str(reserve)?))
+            .collect()
+    }
+
+    pub fn deposit_reserves(&self) -> Result<Vec<Pubkey>> {
+        Self::reserves(
+            &self
+                .deposits
+                .iter()
+                .map(|deposit| deposit.reserve.clone())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    pub fn borrow_reserves(&self) -> Result<Vec<Pubkey>> {
+        Self::reserves(
+            &self
+                .borrows
+                .iter()
+                .map(|borrow| borrow.reserve.clone())
+                .collect::<Vec<_>>(),
+        )
+    }
+}
+
+pub fn lending_market_authority(market: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"lma", market.as_ref()], &program_id()).0
+}
+
+pub fn user_metadata_address(owner: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"user_meta", owner.as_ref()],
+        &program_id(),
+    )
+    .0
+}
+
+/// the plain obligation of `owner` in `market`, tag and id 0 and no seed
+/// accounts, the one the kamino app opens
+pub fn obligation_address(owner: &Pubkey, market: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            &[0],
+            &[0],
+            owner.as_ref(),
+            market.as_ref(),
+            Pubkey::default().as_ref(),
+            Pubkey::default().as_ref(),
+        ],
+        &program_id(),
+    )
+    .0
+}
+
+pub fn obligation_farm_address(farm: &Pubkey, obligation: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"user", farm.as_ref(), obligation.as_ref()],
+        &farms_program_id(),
+    )
+    .0
+}
+
+pub fn init_user_metadata_ix(owner: &Pubkey) -> Instruction {
+    let mut data = INIT_USER_METADATA.to_vec();
+    // no lookup table
+    data.extend_from_slice(Pubkey::default().as_ref());
+    Instruction::new_with_bytes(
+        program_id(),
+        &data,
+        vec![
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(*owner, true),
+            AccountMeta::new(user_metadata_address(owner), false),
+            AccountMeta::new_readonly(program_id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn init_obligation_ix(owner: &Pubkey, market: &Pubkey) -> Instruction {
+    let mut data = INIT_OBLIGATION.to_vec();
+    // tag and id
+    data.extend_from_slice(&[0, 0]);
+    Instruction::new_with_bytes(
+        program_id(),
+        &data,
+        vec![
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(*owner, true),
+            AccountMeta::new(obligation_address(owner, market), false),
+            AccountMeta::new_readonly(*market, false),
+            AccountMeta::new_readonly(Pubkey::default(), false),
+            AccountMeta::new_readonly(Pubkey::default(), false),
+            AccountMeta::new_readonly(user_metadata_address(owner), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn init_obligation_farm_ix(
+    owner: &Pubkey,
+    obligation: &Pubkey,
+    reserve: &Reserve,
+    farm: &Pubkey,
+    mode: u8,
+) -> Instruction {
+    let mut data = INIT_OBLIGATION_FARMS_FOR_RESERVE.to_vec();
+    data.push(mode);
+    Instruction::new_with_bytes(
+        program_id(),
+        &data,
+        vec![
+            AccountMeta::new(*owner, true),
+            AccountMeta::new_readonly(*owner, false),
+            AccountMeta::new(*obligation, false),
+            AccountMeta::new_readonly(
+                lending_market_authority(&reserve.lending_market),
+                false,
+            ),
+            AccountMeta::new(reserve.address, false),
+            AccountMeta::new(*farm, false),
+            AccountMeta::new(
+                obligation_farm_address(farm, obligation),
+                false,
+            ),
+            AccountMeta::new_readonly(reserve.lending_market, false),
+            AccountMeta::new_readonly(farms_program_id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+/// updates the values of the obligation from its reserves, which have to
+/// be refreshed before it, deposits first
+pub fn refresh_obligation_ix(
+    market: &Pubkey,
+    obligation: &Pubkey,
+    deposit_reserves: &[Pubkey],
+    borrow_reserves: &[Pubkey],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*market, false),
+        AccountMeta::new(*obligation, false),
+    ];
+    accounts.extend(
+        deposit_reserves
+            .iter()
+            .chain(borrow_reserves)
+            .map(|reserve| AccountMeta::new_readonly(*reserve, false)),
+    );
+    Instruction::new_with_bytes(program_id(), &REFRESH_OBLIGATION, accounts)
+}
+
+/// the farm accounts that close the v2 instructions, the program standing
+/// in for both when the reserve has no farm
+fn farm_accounts(obligation: &Pubkey, farm: &Pubkey) -> Vec<AccountMeta> {
+    let (user_state, farm_state) = if *farm == Pubkey::default() {
+        (
+            AccountMeta::new_readonly(program_id(), false),
+            AccountMeta::new_readonly(program_id(), false),
+        )
+    } else {
+        (
+            AccountMeta::new(
+                obligation_farm_address(farm, obligation),
+                false,
+            ),
+            AccountMeta::new(*farm, false),
+        )
+    };
+    vec![
+        user_state,
+        farm_state,
+        AccountMeta::new_readonly(farms_program_id(), false),
+    ]
+}
+
+/// deposits `amount` raw of the liquidity of `reserve` from the token
+/// account of `owner` as collateral of the obligation
+pub fn deposit_ix(
+    owner: &Pubkey,
+    obligation: &Pubkey,
+    reserve: &Reserve,
+    amount: u64,
+) -> Instruction {
+    let mut data = DEPOSIT_V2.to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+    let mut accounts = vec![
+        AccountMeta::new(*owner, true),
+        AccountMeta::new(*obligation, false),
+        AccountMeta::new_readonly(reserve.lending_market, false),
+        AccountMeta::new_readonly(
+            lending_market_authority(&reserve.lending_market),
+            false,
+        ),
+        AccountMeta::new(reserve.address, false),
+        AccountMeta::new_readonly(reserve.liquidity_mint, false),
+        AccountMeta::new(reserve.liquidity_supply_vault, false),
+        AccountMeta::new(reserve.collateral_mint, false),
+        AccountMeta::new(reserve.collateral_supply_vault, false),
+        AccountMeta::new(
+            get_associated_token_address_with_program_id(
+                owner,
+                &reserve.liquidity_mint,
+                &reserve.liquidity_token_program,
+            ),
+            false,
+        ),
+        // no collateral account of the user, it stays in the obligation
+        AccountMeta::new_readonly(program_id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(reserve.liquidity_token_program, false),
+        AccountMeta::new_readonly(sysvar::instructions::id(), false),
+    ];
+    accounts.extend(farm_accounts(obligation, &reserve.farm_collateral));
+    Instruction::new_with_bytes(program_id(), &data, accounts)
+}
+
+/// borrows `amount` raw of the liquidity of `reserve` against the
+/// obligation into the token account of `owner`
+pub fn borrow_ix(
+    owner: &Pubkey,
+    obligation: &Pubkey,
+    reserve: &Reserve,
+    amount: u64,
+) -> Instruction {
+    let mut data = BORROW_V2.to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new(*obligation, false),
+        AccountMeta::new_readonly(reserve.lending_market, false),
+        AccountMeta::new_readonly(
+            lending_market_authority(&reserve.lending_market),
+            false,
+        ),
+        AccountMeta::new(reserve.address, false),
+        AccountMeta::new_readonly(reserve.liquidity_mint, false),
+        AccountMeta::new(reserve.liquidity_supply_vault, false),
+        AccountMeta::new(reserve.liquidity_fee_vault, false),
+        AccountMeta::new(
+            get_associated_token_address_with_program_id(
+                owner,
+                &reserve.liquidity_mint,
+                &reserve.liquidity_token_program,
+            ),
+            false,
+        ),
+        // no referrer
+        AccountMeta::new_readonly(program_id(), false),
+        AccountMeta::new_readonly(reserve.liquidity_token_program, false),
+        AccountMeta::new_readonly(sysvar::instructions::id(), false),
+    ];
+    accounts.extend(farm_accounts(obligation, &reserve.farm_debt));
+    Instruction::new_with_bytes(program_id(), &data, accounts)
+}
+
+/// the reserve of `mint` in `market`
+pub async fn fetch_reserve(
+    rpc_client: &RpcClient,
+    market: &Pubkey,
+    mint: &Pubkey,
+) -> Result<Reserve> {
+    let accounts = rpc_client
+        .get_program_accounts_with_config(
+            &program_id(),
+            RpcProgramAccountsConfig {
+                filters: Some(vec![
+                    RpcFilterType::DataSize(RESERVE_LEN as u64),
+                    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                        RESERVE_LENDING_MARKET,
+                        market.to_bytes().to_vec(),
+                    )),
+                    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                        RESERVE_LIQUIDITY,
+                        mint.to_bytes().to_vec(),
+                    )),
+                ]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await?;
+    let (address, account) = accounts.first().ok_or_else(|| {
+        anyhow!("market {} has no reserve of {}", market, mint)
+    })?;
+    Reserve::from_account_data(*address, &account.data)
+}
+
+async fn fetch_reserves(
+    rpc_client: &RpcClient,
+    addresses: &[Pubkey],
+) -> Result<Vec<Reserve>> {
+    rpc_client
+        .get_multiple_accounts(addresses)
+        .await?
+        .into_iter()
+        .zip(addresses)
+        .map(|(account, address)| {
+            let account = account
+                .ok_or_else(|| anyhow!("reserve {} not found", address))?;
+            Reserve::from_account_data(*address, &account.data)
+        })
+        .collect()
+}
+
+async fn account_exists(
+    rpc_client: &RpcClient,
+    address: &Pubkey,
+) -> Result<bool> {
+    Ok(rpc_client
+        .get_account_with_commitment(address, rpc_client.commitment())
+        .await?
+        .value
+        .is_some())
+}
+
+/// the obligation of `owner` in `market`, `None` before the first deposit
+pub async fn fetch_obligation(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    market: &Pubkey,
+) -> Result<Option<KaminoObligation>> {
+    let address = obligation_address(owner, market);
+    rpc_client
+        .get_account_with_commitment(&address, rpc_client.commitment())
+        .await?
+        .value
+        .map(|account| {
+            KaminoObligation::from_account_data(address, &account.data)
+        })
+        .transpose()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KaminoAction {
+    Deposit,
+    Borrow,
+}
+
+/// Deposits into or borrows from the reserve of `mint` in `market`. The
+/// obligation, and the user metadata it needs, are opened on the first
+/// deposit; every reserve the obligation touches is refreshed before it.
+/// SOL is wrapped for deposits and unwrapped after borrows
+pub async fn create_kamino_tx(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    market: &Pubkey,
+    mint: &Pubkey,
+    amount: u64,
+    action: KaminoAction,
+) -> Result<VersionedTransaction> {
+    let reserve = fetch_reserve(rpc_client, market, mint).await?;
+    let obligation = fetch_obligation(rpc_client, owner, market).await?;
+    let obligation_address = obligation_address(owner, market);
+    let is_sol = *mint == spl_token::native_mint::id();
+
+    let mut instructions = Vec::new();
+    let (deposit_reserves, borrow_reserves) = match &obligation {
+        Some(obligation) => (
+            obligation.deposit_reserves()?,
+            obligation.borrow_reserves()?,
+        ),
+        None if action == KaminoAction::Borrow => {
+            return Err(anyhow!(
+                "nothing is deposited in market {} to borrow against",
+                market
+            ))
+        }
+        None => {
+            if !account_exists(rpc_client, &user_metadata_address(owner))
+                .await?
+            {
+                instructions.push(init_user_metadata_ix(owner));
+            }
+            instructions.push(init_obligation_ix(owner, market));
+            (vec![], vec![])
+        }
+    };
+
+    let (farm, mode) = match action {
+        KaminoAction::Deposit => {
+            (reserve.farm_collateral, FARM_MODE_COLLATERAL)
+        }
+        KaminoAction::Borrow => (reserve.farm_debt, FARM_MODE_DEBT),
+    };
+    if farm != Pubkey::default()
+        && !account_exists(
+            rpc_client,
+            &obligation_farm_address(&farm, &obligation_address),
+        )
+        .await?
+    {
+        instructions.push(init_obligation_farm_ix(
+            owner,
+            &obligation_address,
+            &reserve,
+            &farm,
+            mode,
+        ));
+    }
+
+    match action {
+        KaminoAction::Deposit if is_sol => {
+            instructions.extend(wrap_sol_ixs(owner, amount)?)
+        }
+        KaminoAction::Deposit => {}
+        KaminoAction::Borrow => {
+            instructions.push(create_associated_token_account_idempotent(
+                owner,
+                owner,
+                mint,
+                &reserve.liquidity_token_program,
+            ))
+        }
+    }
+
+    let mut refreshed = deposit_reserves
+        .iter()
+        .chain(&borrow_reserves)
+        .filter(|address| **address != reserve.address)
+        .copied()
+        .collect::<Vec<_>>();
+    refreshed.sort();
+    refreshed.dedup();
+    for other in fetch_reserves(rpc_client, &refreshed).await? {
+        instructions.push(other.refresh_ix());
+    }
+    instructions.push(reserve.refresh_ix());
+    instructions.push(refresh_obligation_ix(
+        market,
+        &obligation_address,
+        &deposit_reserves,
+        &borrow_reserves,
+    ));
+
+    match action {
+        KaminoAction::Deposit => instructions.push(deposit_ix(
+            owner,
+            &obligation_address,
+            &reserve,
+            amount,
+        )),
+        KaminoAction::Borrow => {
+            instructions.push(borrow_ix(
+                owner,
+                &obligation_address,
+                &reserve,
+                amount,
+            ));
+            if is_sol {
+                instructions.push(spl_token::instruction::close_account(
+                    &spl_token::id(),
+                    &wsol_address(owner),
+                    owner,
+                    owner,
+                    &[],
+                )?);
+            }
+        }
+    }
+    Ok(Transaction::new_with_payer(&instructions, Some(owner)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn sf(value: f64) -> [u8; 16] {
+        ((value * SCALE) as u128).to_le_bytes()
+    }
+
+    /// 600 of 1000 tokens borrowed, a curve of 0% to 10% at 80%
+    /// utilization and 10% to 100% above, 20% protocol take
+    fn reserve_data(market: &Pubkey, mint: &Pubkey) -> Vec<u8> {
+        let mut data = vec![0u8; RESERVE_LEN];
+        write(&mut data, 0, &RESERVE_ACCOUNT);
+        write(&mut data, RESERVE_LENDING_MARKET, market.as_ref());
+        write(&mut data, RESERVE_LIQUIDITY, mint.as_ref());
+        write(
+            &mut data,
+            RESERVE_LIQUIDITY + 96,
+            &400_000_000u64.to_le_bytes(),
+        );
+        write(&mut data, RESERVE_LIQUIDITY + 104, &sf(600_000_000.));
+        write(&mut data, RESERVE_LIQUIDITY + 144, &6u64.to_le_bytes());
+        write(
+            &mut data,
+            RESERVE_LIQUIDITY_TOKEN_PROGRAM,
+            spl_token::id().as_ref(),
+        );
+        write(&mut data, RESERVE_CONFIG + 14, &[20]);
+        let mut curve = vec![(0u32, 0u32), (8_000, 1_000), (10_000, 10_000)];
+        curve.resize(BORROW_RATE_CURVE_POINTS, (10_000, 10_000));
+        for (i, (utilization, rate)) in curve.into_iter().enumerate() {
+            let offset = RESERVE_BORROW_RATE_CURVE + i * 8;
+            write(&mut data, offset, &utilization.to_le_bytes());
+            write(&mut data, offset + 4, &rate.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_reserve_info() {
+        let (market, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let reserve = Reserve::from_account_data(
+            Pubkey::new_unique(),
+            &reserve_data(&market, &mint),
+        )
+        .unwrap();
+        assert_eq!(reserve.lending_market, market);
+        assert_eq!(reserve.liquidity_mint, mint);
+
+        // 60% utilization is 3/4 of the way to the 10% kink
+        assert!((reserve.utilization_rate() - 0.6).abs() < 1e-9);
+        assert!((reserve.borrow_apr() - 0.075).abs() < 1e-9);
+        // 7.5% of the 60% borrowed, less the 20% take
+        assert!((reserve.supply_apr() - 0.036).abs() < 1e-9);
+
+        let info = reserve.info();
+        assert!((info.borrow_apy - 7.788).abs() < 1e-3);
+        assert!((info.utilization_rate - 60.).abs() < 1e-9);
+        assert_eq!(info.available_liquidity, 400.);
+    }
+
+    #[test]
+    fn test_obligation_from_account_data() {
+        let (reserve, market) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = vec![0u8; 3344];
+        write(&mut data, 0, &OBLIGATION_ACCOUNT);
+        write(&mut data, OBLIGATION_LENDING_MARKET, market.as_ref());
+        write(&mut data, OBLIGATION_DEPOSITS, reserve.as_ref());
+        write(&mut data, OBLIGATION_DEPOSITS + 32, &5_000u64.to_le_bytes());
+        write(&mut data, OBLIGATION_DEPOSITS + 40, &sf(1_000.));
+        write(&mut data, OBLIGATION_DEPOSITED_VALUE, &sf(1_000.));
+        write(&mut data, OBLIGATION_DEBT_VALUE, &sf(400.));
+        write(&mut data, OBLIGATION_UNHEALTHY_BORROW_VALUE, &sf(800.));
+
+        let address = Pubkey::new_unique();
+        let obligation =
+            KaminoObligation::from_account_data(address, &data).unwrap();
+
+        assert_eq!(obligation.lending_market, market.to_string());
+        assert_eq!(
+            obligation.deposits,
+            vec![ObligationDeposit {
+                reserve: reserve.to_string(),
+                deposited_amount: 5_000,
+                market_value_usd: 1_000.,
+            }]
+        );
+        assert!(obligation.borrows.is_empty());
+        assert_eq!(obligation.health_factor, Some(2.));
+
+        write(&mut data, OBLIGATION_DEBT_VALUE, &sf(0.));
+        let obligation =
+            KaminoObligation::from_account_data(address, &data).unwrap();
+        assert_eq!(obligation.health_factor, None);
+    }
+
+    #[test]
+    fn test_deposit_ix() {
+        let (market, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let reserve = Reserve::from_account_data(
+            Pubkey::new_unique(),
+            &reserve_data(&market, &mint),
+        )
+        .unwrap();
+        let owner = Pubkey::new_unique();
+        let obligation = obligation_address(&owner, &market);
+
+        let ix = deposit_ix(&owner, &obligation, &reserve, 1_000_000);
+
+        assert_eq!(ix.program_id, program_id());
+        assert_eq!(&ix.data[..8], &DEPOSIT_V2);
+        assert_eq!(ix.data[8..], 1_000_000u64.to_le_bytes());
+        // the 14 deposit accounts and the 3 farm ones
+        assert_eq!(ix.accounts.len(), 17);
+        assert_eq!(ix.accounts[0], AccountMeta::new(owner, true));
+        assert_eq!(ix.accounts[4], AccountMeta::new(reserve.address, false));
+        // the reserve has no farm
+        assert_eq!(ix.accounts[14].pubkey, program_id());
+        assert_eq!(ix.accounts[16].pubkey, farms_program_id());
+    }
+
+    #[test]
+    fn test_refresh_obligation_ix() {
+        let (market, obligation) =
+            (Pubkey::new_unique(), Pubkey::new_unique());
+        let deposits = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let borrows = [Pubkey::new_unique()];
+
+        let ix =
+            refresh_obligation_ix(&market, &obligation, &deposits, &borrows);
+
+        let accounts = ix
+            .accounts
+            .iter()
+            .map(|meta| meta.pubkey)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            accounts,
+            vec![market, obligation, deposits[0], deposits[1], borrows[0]]
+        );
+        assert_eq!(ix.data, REFRESH_OBLIGATION);
+    }
+}
@@ -0,0 +1,795 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::VersionedTransaction;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+pub const JUPITER_LIMIT_ORDER_API_URL: &str = "https://api.jup.ag/limit/v2";
+pub const LIMIT_ORDER_PROGRAM_ID: Pubkey =
+    pubkey!("j1o2qRpjcyUwEvwtcfhEQefh773ZgjxcVRry7LDqg5X");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitOrderStatus {
+    Open,
+    PartiallyFilled,
+    /// closed after its output was received in full
+    Filled,
+    /// past its expiry, whatever wasn't filled can be cancelled
+    Expired,
+    Cancelled,
+}
+
+/// an order and how much of it was filled, amounts are raw
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LimitOrder {
+    pub order_id: String,
+    pub input_mint: String,
+    pub output_mint: String,
+    /// what the order was created with
+    pub input_amount: u64,
+    pub min_output_amount: u64,
+    /// what is left to sell
+    pub remaining_input_amount: u64,
+    /// 0 to 100
+    pub filled_percent: f64,
+    pub status: LimitOrderStatus,
+    /// unix seconds, none for orders that don't expire
+    pub expires_at: Option<i64>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OpenOrderAccount {
+    input_mint: String,
+    output_mint: String,
+    making_amount: String,
+    ori_making_amount: String,
+    ori_taking_amount: String,
+    expired_at: Option<String>,
+    created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OpenOrder {
+    public_key: String,
+    account: OpenOrderAccount,
+}
+
+impl LimitOrder {
+    fn from_open(order: OpenOrder, now: i64) -> Result<Self> {
+        let account = order.account;
+        let input_amount: u64 = account.ori_making_amount.parse()?;
+        let remaining_input_amount: u64 = account.making_amount.parse()?;
+        let filled_percent = if input_amount > 0 {
+            input_amount.saturating_sub(remaining_input_amount) as f64
+                / input_amount as f64
+                * 100.
+        } else {
+            0.
+        };
+        let expires_at = account
+            .expired_at
+            .map(|expired_at| expired_at.parse())
+            .transpose()?;
+        // expired orders stay open until they are cancelled
+        let status = match expires_at {
+            Some(expires_at) if expires_at <= now => {
+                LimitOrderStatus::Expired
+            }
+            _ if remaining_input_amount < input_amount => {
+                LimitOrderStatus::PartiallyFilled
+            }
+            _ => LimitOrderStatus::Open,
+        };
+        Ok(Self {
+            order_id: order.public_key,
+            input_mint: account.input_mint,
+            output_mint: account.output_mint,
+            input_amount,
+            min_output_amount: account.ori_taking_amount.parse()?,
+            remaining_input_amount,
+            filled_percent,
+            status,
+            expires_at,
+            created_at: account.created_at,
+        })
+    }
+
+    /// a tracked order the api no longer lists as open
+    fn closed(tracked: &TrackedOrder, now: i64) -> Self {
+        let status = if tracked.cancelled {
+            LimitOrderStatus::Cancelled
+        } else if tracked.expires_at.is_some_and(|at| at <= now) {
+            LimitOrderStatus::Expired
+        } else {
+            LimitOrderStatus::Filled
+        };
+        let filled = status == LimitOrderStatus::Filled;
+        Self {
+            order_id: tracked.order_id.clone(),
+            input_mint: tracked.input_mint.clone(),
+            output_mint: tracked.output_mint.clone(),
+            input_amount: tracked.input_amount,
+            min_output_amount: tracked.min_output_amount,
+            remaining_input_amount: if filled {
+                0
+            } else {
+                tracked.input_amount
+            },
+            filled_percent: if filled { 100. } else { 0. },
+            status,
+            expires_at: tracked.expires_at,
+            created_at: tracked.created_at.clone(),
+        }
+    }
+}
+
+/// an order created through `create_limit_order`, kept so that orders
+/// which are no longer open still show up as filled, expired or cancelled
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackedOrder {
+    pub order_id: String,
+    pub input_mint: String,
+    pub output_mint: String,
+    pub input_amount: u64,
+    pub min_output_amount: u64,
+    pub expires_at: Option<i64>,
+    pub created_at: String,
+    pub cancelled: bool,
+}
+
+/// The orders each wallet created, by its address
+#[async_trait]
+pub trait OrderStore: Send + Sync {
+    async fn track(&self, wallet: &Pubkey, order: TrackedOrder)
+        -> Result<()>;
+
+    async fn mark_cancelled(
+        &self,
+        wallet: &Pubkey,
+        order_id: &str,
+    ) -> Result<()>;
+
+    async fn orders(&self, wallet: &Pubkey) -> Result<Vec<TrackedOrder>>;
+}
+
+/// orders of this process only, for running without redis
+#[derive(Debug, Default)]
+pub struct MemoryOrderStore {
+    orders: Mutex<HashMap<String, Vec<TrackedOrder>>>,
+}
+
+#[async_trait]
+impl OrderStore for MemoryOrderStore {
+    async fn track(
+        &self,
+        wallet: &Pubkey,
+        order: TrackedOrder,
+    ) -> Result<()> {
+        self.orders
+            .lock()
+            .unwrap()
+            .entry(wallet.to_string())
+            .or_default()
+            .push(order);
+        Ok(())
+    }
+
+    async fn mark_cancelled(
+        &self,
+        wallet: &Pubkey,
+        order_id: &str,
+    ) -> Result<()> {
+        if let Some(orders) =
+            self.orders.lock().unwrap().get_mut(&wallet.to_string())
+        {
+            for order in orders.iter_mut().filter(|o| o.order_id == order_id)
+            {
+                order.cancelled = true;
+            }
+        }
+        Ok(())
+    }
+
+    async fn orders(&self, wallet: &Pubkey) -> Result<Vec<TrackedOrder>> {
+        Ok(self
+            .orders
+            .lock()
+            .unwrap()
+            .get(&wallet.to_string())
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(feature = "http")]
+pub use redis_store::RedisOrderStore;
+
+#[cfg(feature = "http")]
+mod redis_store {
+    use super::*;
+    use redis::AsyncCommands;
+
+    /// A hash per wallet of its orders as json, by order id, so they
+    /// survive restarts and are shared by every server
+    pub struct RedisOrderStore {
+        client: redis::Client,
+    }
+
+    impl RedisOrderStore {
+        pub fn new(redis_url: &str) -> Result<Self> {
+            Ok(Self {
+                client: redis::Client::open(redis_url)?,
+            })
+        }
+
+        fn wallet_key(wallet: &Pubkey) -> String {
+            format!("limit_orders:{}", wallet)
+        }
+
+        async fn connection(
+            &self,
+        ) -> Result<redis::aio::MultiplexedConnection> {
+            Ok(self.client.get_multiplexed_async_connection().await?)
+        }
+    }
+
+    #[async_trait]
+    impl OrderStore for RedisOrderStore {
+        async fn track(
+            &self,
+            wallet: &Pubkey,
+            order: TrackedOrder,
+        ) -> Result<()> {
+            let mut conn = self.connection().await?;
+            let _: () = conn
+                .hset(
+                    Self::wallet_key(wallet),
+                    &order.order_id,
+                    serde_json::to_string(&order)?,
+                )
+                .await?;
+            Ok(())
+        }
+
+        async fn mark_cancelled(
+            &self,
+            wallet: &Pubkey,
+            order_id: &str,
+        ) -> Result<()> {
+            let mut conn = self.connection().await?;
+            let raw: Option<String> =
+                conn.hget(Self::wallet_key(wallet), order_id).await?;
+            let Some(raw) = raw else {
+                return Ok(());
+            };
+            let mut order: TrackedOrder = serde_json::from_str(&raw)?;
+            order.cancelled = true;
+            let _: () = conn
+                .hset(
+                    Self::wallet_key(wallet),
+                    order_id,
+                    serde_json::to_string(&order)?,
+                )
+                .await?;
+            Ok(())
+        }
+
+        async fn orders(&self, wallet: &Pubkey) -> Result<Vec<TrackedOrder>> {
+            let mut conn = self.connection().await?;
+            let raw: Vec<String> =
+                conn.hvals(Self::wallet_key(wallet)).await?;
+            let mut orders = raw
+                .iter()
+                .map(|raw| serde_json::from_str(raw))
+                .collect::<Result<Vec<TrackedOrder>, _>>()?;
+            orders.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+            Ok(orders)
+        }
+    }
+}
+
+/// in redis when `REDIS_URL` is set, in memory otherwise
+pub static ORDER_STORE: Lazy<Arc<dyn OrderStore>> = Lazy::new(|| {
+    #[cfg(feature = "http")]
+    if let Ok(redis_url) = std::env::var("REDIS_URL") {
+        match RedisOrderStore::new(&redis_url) {
+            Ok(store) => return Arc::new(store),
+            Err(e) => {
+                tracing::warn!("Limit orders are kept in memory: {}", e)
+            }
+        }
+    }
+    Arc::new(MemoryOrderStore::default())
+});
+
+/// the least raw output of `input_amount` at `target_price`, the units of
+/// the output one whole input token has to fetch. The price is taken as
+/// the shortest decimal that reads back as it, e.g. 0.29 is 29/100 rather
+/// than the binary fraction below it, and the rest is integer math
+pub fn min_output_amount(
+    input_amount: u64,
+    target_price: f64,
+    input_decimals: u8,
+    output_decimals: u8,
+) -> Result<u64> {
+    if !target_price.is_finite() || target_price <= 0. {
+        return Err(anyhow!("target_price has to be positive"));
+    }
+    let out_of_range = || {
+        anyhow!(
+            "{} at a price of {} is out of range",
+            input_amount,
+            target_price
+        )
+    };
+    // `Display` of a float never uses an exponent
+    let price = target_price.to_string();
+    let (whole, fraction) = price.split_once('.').unwrap_or((&price, ""));
+    let mantissa: u128 = format!("{}{}", whole, fraction)
+        .parse()
+        .map_err(|_| out_of_range())?;
+    let scale = fraction.len() as u32 + input_decimals as u32;
+    let output = (input_amount as u128)
+        .checked_mul(mantissa)
+        .and_then(|n| {
+            n.checked_mul(10u128.checked_pow(output_decimals as u32)?)
+        })
+        .ok_or_else(out_of_range)?
+        .checked_div(10u128.checked_pow(scale).ok_or_else(out_of_range)?)
+        .unwrap_or_default();
+    match u64::try_from(output) {
+        Ok(output) if output > 0 => Ok(output),
+        _ => Err(out_of_range()),
+    }
+}
+
+/// the cancel transaction comes from the api, it is only signed if it
+/// calls the limit order program on `order_id` of `maker`
+pub fn verify_cancel_transaction(
+    tx: &VersionedTransaction,
+    maker: &Pubkey,
+    order_id: &str,
+) -> Result<()> {
+    let order = Pubkey::from_str(order_id)?;
+    let keys = tx.message.static_account_keys();
+    if keys.first() != Some(maker) {
+        return Err(anyhow!("cancel transaction isn't paid by {}", maker));
+    }
+    let cancels_order = tx.message.instructions().iter().any(|ix| {
+        keys.get(ix.program_id_index as usize)
+            == Some(&LIMIT_ORDER_PROGRAM_ID)
+            && ix
+                .accounts
+                .iter()
+                .any(|&index| keys.get(index as usize) == Some(&order))
+    });
+    if !cancels_order {
+        return Err(anyhow!(
+            "cancel transaction doesn't cancel order {}",
+            order_id
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateOrderResponse {
+    order: String,
+    tx: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CancelOrderResponse {
+    tx: String,
+}
+
+/// the base64 unsigned transactions the jupiter apis return
+pub(crate) fn decode_transaction(tx: &str) -> Result<VersionedTransaction> {
+    Ok(bincode::deserialize(&BASE64_STANDARD.decode(tx)?)?)
+}
+
+/// Orders of the jupiter limit order program, the api returns unsigned
+/// transactions for the maker to sign
+#[derive(Debug, Clone)]
+pub struct LimitOrderClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl Default for LimitOrderClient {
+    fn default() -> Self {
+        Self::new(JUPITER_LIMIT_ORDER_API_URL)
+    }
+}
+
+impl LimitOrderClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    async fn post<T: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        body: serde_json::Value,
+    ) -> Result<T> {
+        let response = self
+            .client
+            .post(format!("{}/{}", self.base_url, path))
+            .json(&body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let error = response.text().await?;
+            return Err(anyhow!("limit order {} failed: {}", path, error));
+        }
+        Ok(response.json::<T>().await?)
+    }
+
+    /// sells `input_amount` of `input_mint` once it fetches at least
+    /// `min_output_amount` of `output_mint`; returns the order id and the
+    /// transaction creating it
+    pub async fn create_order(
+        &self,
+        maker: &Pubkey,
+        input_mint: &str,
+        output_mint: &str,
+        input_amount: u64,
+        min_output_amount: u64,
+        expires_at: Option<u64>,
+    ) -> Result<(String, VersionedTransaction)> {
+        let mut params = serde_json::json!({
+            "makingAmount": input_amount.to_string(),
+            "takingAmount": min_output_amount.to_string(),
+        });
+        if let Some(expires_at) = expires_at {
+            params["expiredAt"] = expires_at.to_string().into();
+        }
+        let response: CreateOrderResponse = self
+            .post(
+                "createOrder",
+                serde_json::json!({
+                    "inputMint": input_mint,
+                    "outputMint": output_mint,
+                    "maker": maker.to_string(),
+                    "payer": maker.to_string(),
+                    "params": params,
+                    "computeUnitPrice": "auto",
+                }),
+            )
+            .await?;
+        Ok((response.order, decode_transaction(&response.tx)?))
+    }
+
+    /// returns what is left of the order to the maker
+    pub async fn cancel_order(
+        &self,
+        maker: &Pubkey,
+        order_id: &str,
+    ) -> Result<VersionedTransaction> {
+        let response: CancelOrderResponse = self
+            .post(
+                "cancelOrder",
+                serde_json::json!({
+                    "maker": maker.to_string(),
+                    "orders": [order_id],
+                    "computeUnitPrice": "auto",
+                }),
+            )
+            .await?;
+        let tx = decode_transaction(&response.tx)?;
+        verify_cancel_transaction(&tx, maker, order_id)?;
+        Ok(tx)
+    }
+
+    pub async fn open_orders(
+        &self,
+        wallet: &Pubkey,
+        now: i64,
+    ) -> Result<Vec<LimitOrder>> {
+        let response = self
+            .client
+            .get(format!("{}/openOrders", self.base_url))
+            .query(&[("wallet", wallet.to_string())])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<OpenOrder>>()
+            .await?;
+        response
+            .into_iter()
+            .map(|order| LimitOrder::from_open(order, now))
+            .collect()
+    }
+
+    /// the open orders of the wallet, followed by the orders it created
+    /// through `store` that closed since
+    pub async fn list_orders(
+        &self,
+        wallet: &Pubkey,
+        store: &dyn OrderStore,
+        now: i64,
+    ) -> Result<Vec<LimitOrder>> {
+        let mut orders = self.open_orders(wallet, now).await?;
+        let closed = store
+            .orders(wallet)
+            .await?
+            .into_iter()
+            .filter(|tracked| {
+                !orders.iter().any(|open| open.order_id == tracked.order_id)
+            })
+            .map(|tracked| LimitOrder::closed(&tracked, now))
+            .collect::<Vec<_>>();
+        orders.extend(closed);
+        Ok(orders)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+    use solana_sdk::message::{v0, VersionedMessage};
+    use solana_sdk::system_instruction;
+
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    const NOW: i64 = 1_800_000_000;
+
+    fn unsigned_tx(maker: &Pubkey, ix: Instruction) -> VersionedTransaction {
+        let message = v0::Message::try_compile(
+            maker,
+            &[ix],
+            &[],
+            solana_sdk::hash::Hash::new_unique(),
+        )
+        .unwrap();
+        VersionedTransaction {
+            signatures: vec![Default::default()],
+            message: VersionedMessage::V0(message),
+        }
+    }
+
+    fn encode(tx: &VersionedTransaction) -> String {
+        BASE64_STANDARD.encode(bincode::serialize(tx).unwrap())
+    }
+
+    fn cancel_ix(maker: &Pubkey, order: &Pubkey) -> Instruction {
+        Instruction::new_with_bytes(
+            LIMIT_ORDER_PROGRAM_ID,
+            &[0u8; 8],
+            vec![
+                AccountMeta::new(*maker, true),
+                AccountMeta::new(*order, false),
+            ],
+        )
+    }
+
+    fn open_order(
+        order: &str,
+        remaining: &str,
+        expired_at: Option<i64>,
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "publicKey": order,
+            "account": {
+                "inputMint": SOL,
+                "outputMint": USDC,
+                "makingAmount": remaining,
+                "takingAmount": "50000000",
+                "oriMakingAmount": "1000000000",
+                "oriTakingAmount": "200000000",
+                "expiredAt": expired_at.map(|at| at.to_string()),
+                "createdAt": "2025-01-01T00:00:00Z",
+                "updatedAt": "2025-01-02T00:00:00Z",
+                "feeBps": 10,
+            },
+        })
+    }
+
+    fn tracked(order_id: &str, expires_at: Option<i64>) -> TrackedOrder {
+        TrackedOrder {
+            order_id: order_id.to_string(),
+            input_mint: SOL.to_string(),
+            output_mint: USDC.to_string(),
+            input_amount: 1_000_000_000,
+            min_output_amount: 200_000_000,
+            expires_at,
+            created_at: "2025-01-01T00:00:00Z".to_string(),
+            cancelled: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_order() {
+        let maker = Pubkey::new_unique();
+        let tx = unsigned_tx(
+            &maker,
+            system_instruction::transfer(&maker, &Pubkey::new_unique(), 1),
+        );
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/createOrder")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "inputMint": SOL,
+                "outputMint": USDC,
+                "maker": maker.to_string(),
+                "params": {
+                    "makingAmount": "1000000000",
+                    "takingAmount": "200000000",
+                    "expiredAt": "1900000000",
+                },
+            })))
+            .with_body(
+                serde_json::json!({ "order": "order1", "tx": encode(&tx) })
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let (order_id, created) = LimitOrderClient::new(&server.url())
+            .create_order(
+                &maker,
+                SOL,
+                USDC,
+                1_000_000_000,
+                200_000_000,
+                Some(1_900_000_000),
+            )
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(order_id, "order1");
+        assert_eq!(created, tx);
+    }
+
+    #[tokio::test]
+    async fn test_list_orders() {
+        let wallet = Pubkey::new_unique();
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/openOrders")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "wallet".into(),
+                wallet.to_string(),
+            ))
+            .with_body(
+                serde_json::json!([
+                    open_order("partial", "250000000", None),
+                    open_order("expired", "1000000000", Some(NOW - 60)),
+                ])
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let store = MemoryOrderStore::default();
+        for order in ["partial", "filled", "cancelled"] {
+            store.track(&wallet, tracked(order, None)).await.unwrap();
+        }
+        store.mark_cancelled(&wallet, "cancelled").await.unwrap();
+
+        let orders = LimitOrderClient::new(&server.url())
+            .list_orders(&wallet, &store, NOW)
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(
+            orders
+                .iter()
+                .map(|order| (
+                    order.order_id.as_str(),
+                    order.status,
+                    order.remaining_input_amount
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("partial", LimitOrderStatus::PartiallyFilled, 250_000_000),
+                ("expired", LimitOrderStatus::Expired, 1_000_000_000),
+                ("filled", LimitOrderStatus::Filled, 0),
+                ("cancelled", LimitOrderStatus::Cancelled, 1_000_000_000),
+            ]
+        );
+        assert_eq!(orders[0].filled_percent, 75.);
+        assert_eq!(orders[0].min_output_amount, 200_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_order() {
+        let maker = Pubkey::new_unique();
+        let order = Pubkey::new_unique();
+        let tx = unsigned_tx(&maker, cancel_ix(&maker, &order));
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/cancelOrder")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "maker": maker.to_string(),
+                "orders": [order.to_string()],
+            })))
+            .with_body(serde_json::json!({ "tx": encode(&tx) }).to_string())
+            .create_async()
+            .await;
+
+        let cancel = LimitOrderClient::new(&server.url())
+            .cancel_order(&maker, &order.to_string())
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        let keys = cancel.message.static_account_keys();
+        let ix = &cancel.message.instructions()[0];
+        assert_eq!(
+            keys[ix.program_id_index as usize],
+            LIMIT_ORDER_PROGRAM_ID
+        );
+        assert_eq!(
+            ix.accounts
+                .iter()
+                .map(|&index| keys[index as usize])
+                .collect::<Vec<_>>(),
+            vec![maker, order]
+        );
+    }
+
+    #[test]
+    fn test_verify_cancel_transaction() {
+        let maker = Pubkey::new_unique();
+        let order = Pubkey::new_unique();
+        let other = Pubkey::new_unique().to_string();
+        let tx = unsigned_tx(&maker, cancel_ix(&maker, &order));
+        assert!(verify_cancel_transaction(&tx, &maker, &order.to_string())
+            .is_ok());
+        // another order, another payer or another program
+        assert!(verify_cancel_transaction(&tx, &maker, &other).is_err());
+        assert!(verify_cancel_transaction(
+            &tx,
+            &Pubkey::new_unique(),
+            &order.to_string()
+        )
+        .is_err());
+        let transfer = unsigned_tx(
+            &maker,
+            system_instruction::transfer(&maker, &order, 1),
+        );
+        assert!(verify_cancel_transaction(
+            &transfer,
+            &maker,
+            &order.to_string()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_min_output_amount() {
+        // 1 SOL at 150 USDC
+        assert_eq!(
+            min_output_amount(1_000_000_000, 150., 9, 6).unwrap(),
+            150_000_000
+        );
+        // buying a token at 0.002 USDC is 500 tokens per USDC
+        assert_eq!(
+            min_output_amount(100_000_000, 500., 6, 6).unwrap(),
+            50_000_000_000
+        );
+        // 0.29 is below 29/100 as a float, 100 of it isn't 28
+        assert_eq!(min_output_amount(100, 0.29, 0, 0).unwrap(), 29);
+        assert_eq!(
+            min_output_amount(3_000_000, 0.1, 6, 9).unwrap(),
+            300_000_000
+        );
+        assert!(min_output_amount(1_000_000, 0., 6, 6).is_err());
+        assert!(min_output_amount(u64::MAX, 1e30, 0, 9).is_err());
+        assert!(min_output_amount(1, 1e-9, 6, 6).is_err());
+    }
+}
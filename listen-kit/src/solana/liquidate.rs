@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+use crate::solana::constants::WSOL;
+use crate::solana::data::PortfolioItem;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SellAllReport {
+    /// each sold mint with the result of its swap
+    pub successful: Vec<String>,
+    /// the mints that failed to sell with the error of the swap
+    pub failed: Vec<(String, String)>,
+    /// the worth of the sold positions at the portfolio prices, the amount
+    /// received differs by the slippage and fees
+    pub total_received_usd: f64,
+}
+
+/// the positions `sell_all` swaps: everything but SOL, the target itself,
+/// the `keep_mints` and empty balances
+pub fn positions_to_sell(
+    portfolio: Vec<PortfolioItem>,
+    keep_mints: &[String],
+    target_mint: &str,
+) -> Vec<PortfolioItem> {
+    portfolio
+        .into_iter()
+        .filter(|position| {
+            position.address != WSOL
+                && position.address != target_mint
+                && position.amount > 0.
+                && !keep_mints.contains(&position.address)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+    fn position(address: &str, amount: f64) -> PortfolioItem {
+        serde_json::from_value(serde_json::json!({
+            "address": address,
+            "name": address,
+            "symbol": address,
+            "decimals": 6,
+            "logoURI": "",
+            "price": 1.0,
+            "amount": amount,
+            "daily_volume": 0.0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_positions_to_sell() {
+        let portfolio = vec![
+            position(WSOL, 2.0),
+            position(USDC, 10.0),
+            position("bonk", 1_000.0),
+            position("wif", 5.0),
+            position("dust", 0.0),
+        ];
+        let sold: Vec<String> =
+            positions_to_sell(portfolio, &["wif".to_string()], USDC)
+                .into_iter()
+                .map(|position| position.address)
+                .collect();
+        assert_eq!(sold, vec!["bonk".to_string()]);
+    }
+}
@@ -1,15 +1,43 @@
 pub mod agent;
+pub mod amount;
 pub mod balance;
+pub mod balance_changes;
+pub mod burn;
+pub mod close_accounts;
+pub mod cnft;
+pub mod confirmation;
 pub mod constants;
 pub mod data;
+pub mod dca;
 pub mod deploy_token;
+pub mod fee_estimate;
+pub mod governance;
+pub mod history;
+pub mod holders;
+pub mod jito;
 pub mod jup;
+pub mod kamino;
+pub mod limit_order;
+pub mod liquidate;
+pub mod nft;
+pub mod nonce;
+pub mod pool;
 pub mod price;
+pub mod priority_fee;
 pub mod pump;
+pub mod reserve;
+pub mod routes;
+pub mod safety;
 pub mod scan;
+pub mod simulate;
+pub mod simulation;
+pub mod sns;
+pub mod stake;
+pub mod token_info;
 pub mod tools;
 pub mod trade;
 pub mod trade_pump;
 pub mod transaction;
 pub mod transfer;
 pub mod util;
+pub mod wsol;
@@ -0,0 +1,249 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::native_token::lamports_to_sol;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const MAGIC_EDEN_API_URL: &str = "https://api-mainnet.magiceden.dev/v2";
+pub const NFT_FLOOR_CACHE_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NftFloorReport {
+    /// the magic eden symbol of the collection
+    pub collection: String,
+    pub floor_price_sol: f64,
+    pub floor_price_usd: f64,
+    /// none when magic eden doesn't report it
+    pub volume_24h_sol: Option<f64>,
+    pub listed_count: u64,
+    pub holder_count: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CollectionStats {
+    /// lamports
+    floor_price: Option<f64>,
+    #[serde(default)]
+    listed_count: u64,
+    /// lamports
+    volume24hr: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HolderStats {
+    unique_holders: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenCollection {
+    collection: Option<String>,
+}
+
+/// Collection stats from magic eden, each collection is refetched at most
+/// once per `NFT_FLOOR_CACHE_TTL`
+#[derive(Debug)]
+pub struct MagicEdenClient {
+    client: reqwest::Client,
+    base_url: String,
+    cache: Mutex<HashMap<String, (Instant, NftFloorReport)>>,
+}
+
+impl Default for MagicEdenClient {
+    fn default() -> Self {
+        Self::new(MAGIC_EDEN_API_URL)
+    }
+}
+
+impl MagicEdenClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get<T: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+    ) -> Result<T> {
+        let response = self
+            .client
+            .get(format!("{}/{}", self.base_url, path))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let error = response.text().await?;
+            return Err(anyhow!("magic eden {} failed: {}", path, error));
+        }
+        Ok(response.json::<T>().await?)
+    }
+
+    /// the symbol of a collection given as a symbol, or as the address of
+    /// one of its nfts or of its verified collection nft
+    pub async fn resolve_symbol(&self, collection: &str) -> Result<String> {
+        let collection = collection.trim();
+        if Pubkey::from_str(collection).is_err() {
+            return Ok(collection.to_string());
+        }
+        self.get::<TokenCollection>(&format!("tokens/{}", collection))
+            .await?
+            .collection
+            .ok_or_else(|| {
+                anyhow!("{} is not in a magic eden collection", collection)
+            })
+    }
+
+    /// `sol_price` is only awaited when the report isn't cached
+    pub async fn floor_report(
+        &self,
+        collection: &str,
+        sol_price: impl Future<Output = Result<f64>>,
+    ) -> Result<NftFloorReport> {
+        let key = collection.trim().to_string();
+        let cached = self
+            .cache
+            .lock()
+            .unwrap()
+            .get(&key)
+            .filter(|(fetched_at, _)| {
+                fetched_at.elapsed() < NFT_FLOOR_CACHE_TTL
+            })
+            .map(|(_, report)| report.clone());
+        if let Some(report) = cached {
+            return Ok(report);
+        }
+
+        let symbol = self.resolve_symbol(&key).await?;
+        let stats = self
+            .get::<CollectionStats>(&format!("collections/{}/stats", symbol))
+            .await?;
+        let floor_price = stats
+            .floor_price
+            .ok_or_else(|| anyhow!("{} has no listings", symbol))?;
+        // holders are nice to have, the floor is what was asked for
+        let holder_count = match self
+            .get::<HolderStats>(&format!(
+                "collections/{}/holder_stats",
+                symbol
+            ))
+            .await
+        {
+            Ok(holders) => holders.unique_holders,
+            Err(e) => {
+                tracing::warn!(%symbol, %e, "no holder stats");
+                None
+            }
+        };
+        let floor_price_sol = lamports_to_sol(floor_price as u64);
+        let report = NftFloorReport {
+            collection: symbol,
+            floor_price_sol,
+            floor_price_usd: floor_price_sol * sol_price.await?,
+            volume_24h_sol: stats
+                .volume24hr
+                .map(|volume| lamports_to_sol(volume as u64)),
+            listed_count: stats.listed_count,
+            holder_count,
+        };
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(key, (Instant::now(), report.clone()));
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn sol_price() -> Result<f64> {
+        Ok(150.)
+    }
+
+    #[tokio::test]
+    async fn test_floor_report_by_address() {
+        let nft = Pubkey::new_unique().to_string();
+        let mut server = mockito::Server::new_async().await;
+        let token = server
+            .mock("GET", format!("/tokens/{}", nft).as_str())
+            .with_body(r#"{"mintAddress":"x","collection":"okay_bears"}"#)
+            .create_async()
+            .await;
+        let stats = server
+            .mock("GET", "/collections/okay_bears/stats")
+            .with_body(
+                serde_json::json!({
+                    "symbol": "okay_bears",
+                    "floorPrice": 12_500_000_000u64,
+                    "listedCount": 321,
+                    "volume24hr": 400_000_000_000u64,
+                    "volumeAll": 1e15,
+                })
+                .to_string(),
+            )
+            // the second report is cached
+            .expect(1)
+            .create_async()
+            .await;
+        let holders = server
+            .mock("GET", "/collections/okay_bears/holder_stats")
+            .with_body(r#"{"totalSupply":10000,"uniqueHolders":5000}"#)
+            .create_async()
+            .await;
+
+        let client = MagicEdenClient::new(&server.url());
+        let report = client.floor_report(&nft, sol_price()).await.unwrap();
+        let cached = client.floor_report(&nft, sol_price()).await.unwrap();
+        token.assert_async().await;
+        stats.assert_async().await;
+        holders.assert_async().await;
+        assert_eq!(report, cached);
+        assert_eq!(
+            report,
+            NftFloorReport {
+                collection: "okay_bears".to_string(),
+                floor_price_sol: 12.5,
+                floor_price_usd: 1875.,
+                volume_24h_sol: Some(400.),
+                listed_count: 321,
+                holder_count: Some(5000),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_floor_report_by_symbol() {
+        let mut server = mockito::Server::new_async().await;
+        let stats = server
+            .mock("GET", "/collections/mad_lads/stats")
+            .with_body(
+                r#"{"symbol":"mad_lads","floorPrice":80000000000,
+                "listedCount":100}"#,
+            )
+            .create_async()
+            .await;
+        // holders are left out when magic eden fails on them
+        server
+            .mock("GET", "/collections/mad_lads/holder_stats")
+            .with_status(500)
+            .create_async()
+            .await;
+
+        let report = MagicEdenClient::new(&server.url())
+            .floor_report("mad_lads", sol_price())
+            .await
+            .unwrap();
+        stats.assert_async().await;
+        assert_eq!(report.floor_price_sol, 80.);
+        assert_eq!(report.volume_24h_sol, None);
+        assert_eq!(report.holder_count, None);
+    }
+}
@@ -0,0 +1,373 @@
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::message::{v0, Message, VersionedMessage};
+use solana_sdk::nonce::state::{State, Versions};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction;
+use solana_sdk::system_program;
+use solana_sdk::transaction::{Transaction, VersionedTransaction};
+
+use crate::solana::util::fetch_lookup_table;
+
+/// seed of the nonce account derived from each wallet
+pub const NONCE_SEED: &str = "listen-nonce";
+
+/// the nonce account of `owner`, which is also its authority
+pub fn nonce_account_address(owner: &Pubkey) -> Result<Pubkey> {
+    Ok(Pubkey::create_with_seed(
+        owner,
+        NONCE_SEED,
+        &system_program::id(),
+    )?)
+}
+
+/// funds the nonce account of `owner` with its rent-exempt minimum, which
+/// `create_close_nonce_account_tx` gives back
+pub async fn create_nonce_account_tx(
+    owner: &Pubkey,
+    rpc_client: &RpcClient,
+) -> Result<VersionedTransaction> {
+    let lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(State::size())
+        .await?;
+    let instructions = system_instruction::create_nonce_account_with_seed(
+        owner,
+        &nonce_account_address(owner)?,
+        owner,
+        NONCE_SEED,
+        owner,
+        lamports,
+    );
+    Ok(Transaction::new_with_payer(&instructions, Some(owner)).into())
+}
+
+/// withdraws the whole balance of the nonce account back to `owner`, which
+/// closes it
+pub async fn create_close_nonce_account_tx(
+    owner: &Pubkey,
+    rpc_client: &RpcClient,
+) -> Result<VersionedTransaction> {
+    let nonce_account = nonce_account_address(owner)?;
+    let lamports = rpc_client.get_balance(&nonce_account).await?;
+    if lamports == 0 {
+        return Err(anyhow!("{} has no nonce account", owner));
+    }
+    let instruction = system_instruction::withdraw_nonce_account(
+        &nonce_account,
+        owner,
+        owner,
+        lamports,
+    );
+    Ok(Transaction::new_with_payer(&[instruction], Some(owner)).into())
+}
+
+/// the blockhash stored in the nonce account of `owner`, `None` if the
+/// account doesn't exist yet
+pub async fn fetch_nonce(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Option<Hash>> {
+    let nonce_account = nonce_account_address(owner)?;
+    let Some(account) = rpc_client
+        .get_account_with_commitment(
+            &nonce_account,
+            CommitmentConfig::confirmed(),
+        )
+        .await?
+        .value
+    else {
+        return Ok(None);
+    };
+    if account.owner != system_program::id() {
+        return Err(anyhow!("{} is not a nonce account", nonce_account));
+    }
+    let versions: Versions = bincode::deserialize(&account.data)
+        .map_err(|e| anyhow!("Invalid nonce account: {}", e))?;
+    match versions.state() {
+        State::Initialized(data) if data.authority == *owner => {
+            Ok(Some(data.blockhash()))
+        }
+        State::Initialized(data) => Err(anyhow!(
+            "{} is owned by {}, not {}",
+            nonce_account,
+            data.authority,
+            owner
+        )),
+        State::Uninitialized => Ok(None),
+    }
+}
+
+/// the address lookup tables `tx` loads accounts from, e.g. those of a
+/// jupiter swap, for `make_durable` to compile it with again
+pub async fn fetch_lookup_tables(
+    rpc_client: &RpcClient,
+    tx: &VersionedTransaction,
+) -> Result<Vec<AddressLookupTableAccount>> {
+    let mut tables = vec![];
+    for lookup in tx.message.address_table_lookups().unwrap_or_default() {
+        let addresses = fetch_lookup_table(rpc_client, &lookup.account_key)
+            .await?
+            .ok_or_else(|| {
+                anyhow!("No lookup table {}", lookup.account_key)
+            })?;
+        tables.push(AddressLookupTableAccount {
+            key: lookup.account_key,
+            addresses,
+        });
+    }
+    Ok(tables)
+}
+
+/// the accounts `tx` indexes, its static ones followed by the writable and
+/// then the readonly ones it loads from `tables`
+fn account_keys(
+    tx: &VersionedTransaction,
+    tables: &[AddressLookupTableAccount],
+) -> Result<Vec<Pubkey>> {
+    let mut keys = tx.message.static_account_keys().to_vec();
+    let lookups = tx.message.address_table_lookups().unwrap_or_default();
+    let mut writable = vec![];
+    let mut readonly = vec![];
+    for lookup in lookups {
+        let table = tables
+            .iter()
+            .find(|table| table.key == lookup.account_key)
+            .ok_or_else(|| {
+                anyhow!("No lookup table {}", lookup.account_key)
+            })?;
+        let resolve = |indexes: &[u8]| -> Result<Vec<Pubkey>> {
+            indexes
+                .iter()
+                .map(|&i| {
+                    table.addresses.get(i as usize).copied().ok_or_else(
+                        || anyhow!("{} has no address {}", table.key, i),
+                    )
+                })
+                .collect()
+        };
+        writable.extend(resolve(&lookup.writable_indexes)?);
+        readonly.extend(resolve(&lookup.readonly_indexes)?);
+    }
+    keys.extend(writable);
+    keys.extend(readonly);
+    Ok(keys)
+}
+
+/// `tx` with the advance instruction of the nonce account of `owner`
+/// prepended and `nonce` as its blockhash, so it stays valid until the
+/// nonce is advanced rather than for ~150 slots. A transaction using
+/// address lookup tables is compiled again with `tables`, see
+/// `fetch_lookup_tables`
+pub fn make_durable(
+    tx: &VersionedTransaction,
+    owner: &Pubkey,
+    nonce: Hash,
+    tables: &[AddressLookupTableAccount],
+) -> Result<VersionedTransaction> {
+    if tx.uses_durable_nonce() {
+        return Err(anyhow!("Transaction already uses a durable nonce"));
+    }
+    let message = &tx.message;
+    let keys = account_keys(tx, tables)?;
+    let nonce_account = nonce_account_address(owner)?;
+    // e.g. closing it, which fails once the nonce was advanced in the same
+    // transaction
+    if keys.contains(&nonce_account) {
+        return Err(anyhow!(
+            "Transaction uses the nonce account, it can't also advance it"
+        ));
+    }
+    let mut instructions = message
+        .instructions()
+        .iter()
+        .map(|ix| Instruction {
+            program_id: keys[ix.program_id_index as usize],
+            accounts: ix
+                .accounts
+                .iter()
+                .map(|&i| AccountMeta {
+                    pubkey: keys[i as usize],
+                    is_signer: message.is_signer(i as usize),
+                    is_writable: message.is_maybe_writable(i as usize, None),
+                })
+                .collect(),
+            data: ix.data.clone(),
+        })
+        .collect::<Vec<_>>();
+    instructions.insert(
+        0,
+        system_instruction::advance_nonce_account(&nonce_account, owner),
+    );
+
+    let payer = keys.first().ok_or_else(|| anyhow!("No fee payer"))?;
+    let message = match message {
+        VersionedMessage::Legacy(_) => {
+            let mut message = Message::new(&instructions, Some(payer));
+            message.recent_blockhash = nonce;
+            VersionedMessage::Legacy(message)
+        }
+        VersionedMessage::V0(_) => VersionedMessage::V0(
+            v0::Message::try_compile(payer, &instructions, tables, nonce)?,
+        ),
+    };
+    Ok(VersionedTransaction {
+        signatures: vec![
+            Default::default();
+            message.header().num_required_signatures as usize
+        ],
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::solana::LocalSolanaSigner;
+    use crate::solana::transfer::create_transfer_sol_tx;
+    use base64::prelude::{Engine, BASE64_STANDARD};
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::nonce::state::{Data, DurableNonce};
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::system_instruction::SystemInstruction;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn system_instruction_at(
+        tx: &VersionedTransaction,
+        index: usize,
+    ) -> SystemInstruction {
+        let ix = &tx.message.instructions()[index];
+        assert_eq!(
+            tx.message.static_account_keys()[ix.program_id_index as usize],
+            system_program::id()
+        );
+        bincode::deserialize(&ix.data).unwrap()
+    }
+
+    /// an RPC serving a nonce account of `authority` and its nonce
+    fn nonce_rpc(authority: &Pubkey) -> (RpcClient, Hash) {
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let nonce = *durable_nonce.as_hash();
+        let state = Versions::new(State::Initialized(Data::new(
+            *authority,
+            durable_nonce,
+            5000,
+        )));
+        let data =
+            BASE64_STANDARD.encode(bincode::serialize(&state).unwrap());
+        let rpc_client = RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([(
+                RpcRequest::GetAccountInfo,
+                json!({
+                    "context": { "slot": 1 },
+                    "value": {
+                        "data": [data, "base64"],
+                        "executable": false,
+                        "lamports": 1_447_680,
+                        "owner": system_program::id().to_string(),
+                        "rentEpoch": 0,
+                        "space": State::size(),
+                    }
+                }),
+            )]),
+        );
+        (rpc_client, nonce)
+    }
+
+    #[tokio::test]
+    async fn test_durable_transfer() {
+        let owner = Keypair::new();
+        let to = Pubkey::new_unique();
+        let (rpc_client, nonce) = nonce_rpc(&owner.pubkey());
+        let fetched =
+            fetch_nonce(&rpc_client, &owner.pubkey()).await.unwrap();
+        assert_eq!(fetched, Some(nonce));
+
+        let tx = create_transfer_sol_tx(&to, 1, &owner.pubkey())
+            .await
+            .unwrap();
+        let mut tx = make_durable(&tx, &owner.pubkey(), nonce, &[]).unwrap();
+
+        // advance first, then the transfer, with the nonce as blockhash
+        assert!(tx.uses_durable_nonce());
+        assert_eq!(tx.message.instructions().len(), 2);
+        assert_eq!(
+            system_instruction_at(&tx, 0),
+            SystemInstruction::AdvanceNonceAccount
+        );
+        assert_eq!(
+            system_instruction_at(&tx, 1),
+            SystemInstruction::Transfer { lamports: 1 }
+        );
+        assert_eq!(*tx.message.recent_blockhash(), nonce);
+        let nonce_account = nonce_account_address(&owner.pubkey()).unwrap();
+        let advance = &tx.message.instructions()[0];
+        assert_eq!(
+            tx.message.static_account_keys()[advance.accounts[0] as usize],
+            nonce_account
+        );
+        assert!(make_durable(&tx, &owner.pubkey(), nonce, &[]).is_err());
+
+        // signing keeps the nonce instead of fetching a recent blockhash
+        let signer = LocalSolanaSigner::from_keypair(owner.insecure_clone())
+            .with_rpc_client(Arc::new(RpcClient::new_mock(
+                "succeeds".to_string(),
+            )));
+        signer.sign_solana_transaction(&mut tx).await.unwrap();
+        assert_eq!(*tx.message.recent_blockhash(), nonce);
+        assert_eq!(tx.verify_with_results(), vec![true]);
+    }
+
+    #[test]
+    fn test_durable_with_lookup_table() {
+        let owner = Pubkey::new_unique();
+        let (to, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![other, to],
+        };
+        let ixs = [system_instruction::transfer(&owner, &to, 1)];
+        let message = v0::Message::try_compile(
+            &owner,
+            &ixs,
+            &[table.clone()],
+            Hash::default(),
+        )
+        .unwrap();
+        let tx = VersionedTransaction {
+            signatures: vec![Default::default()],
+            message: VersionedMessage::V0(message),
+        };
+        assert!(make_durable(&tx, &owner, Hash::new_unique(), &[]).is_err());
+
+        let nonce = Hash::new_unique();
+        let durable =
+            make_durable(&tx, &owner, nonce, &[table.clone()]).unwrap();
+        assert!(durable.uses_durable_nonce());
+        assert_eq!(
+            system_instruction_at(&durable, 1),
+            SystemInstruction::Transfer { lamports: 1 }
+        );
+        // the recipient is still loaded from the table
+        let lookups = durable.message.address_table_lookups().unwrap();
+        assert_eq!(lookups.len(), 1);
+        assert_eq!(lookups[0].account_key, table.key);
+        let keys = account_keys(&durable, &[table]).unwrap();
+        let transfer = &durable.message.instructions()[1];
+        assert_eq!(keys[transfer.accounts[1] as usize], to);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_nonce_of_other_authority() {
+        let owner = Pubkey::new_unique();
+        let (rpc_client, _) = nonce_rpc(&Pubkey::new_unique());
+        assert!(fetch_nonce(&rpc_client, &owner).await.is_err());
+    }
+}
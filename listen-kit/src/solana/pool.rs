@@ -0,0 +1,257 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+use crate::dexscreener::get_pair;
+
+pub const RAYDIUM_CLMM_PROGRAM: &str =
+    "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+pub const ORCA_WHIRLPOOL_PROGRAM: &str =
+    "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+
+/// fee rates of both programs are expressed in hundredths of a bip
+const FEE_RATE_DENOMINATOR: f64 = 1_000_000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolType {
+    RaydiumClmm,
+    OrcaWhirlpool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolInfo {
+    pub pool_type: PoolType,
+    pub base_mint: String,
+    pub quote_mint: String,
+    pub base_reserve: f64,
+    pub quote_reserve: f64,
+    /// price of the base token in the quote token
+    pub current_price: f64,
+    /// fraction, e.g. 0.0025 for 0.25%
+    pub fee_rate: f64,
+    pub tick_spacing: u16,
+    pub tick_current: i32,
+    pub total_volume_24h: Option<f64>,
+}
+
+/// the subset of the on-chain pool state that is common to both programs
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConcentratedPoolState {
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub vault_a: Pubkey,
+    pub vault_b: Pubkey,
+    pub sqrt_price_x64: u128,
+    pub tick_spacing: u16,
+    pub tick_current: i32,
+    /// raw fee rate, for raydium this lives in the amm config account
+    pub fee_rate: Option<u32>,
+    pub amm_config: Option<Pubkey>,
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Result<Pubkey> {
+    let bytes: [u8; 32] = data
+        .get(offset..offset + 32)
+        .ok_or_else(|| anyhow!("account data too short"))?
+        .try_into()?;
+    Ok(Pubkey::new_from_array(bytes))
+}
+
+fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N]> {
+    Ok(data
+        .get(offset..offset + N)
+        .ok_or_else(|| anyhow!("account data too short"))?
+        .try_into()?)
+}
+
+/// Raydium CLMM `PoolState`, anchor account layout:
+/// discriminator(8) bump(1) amm_config(32) owner(32) token_mint_0(32)
+/// token_mint_1(32) token_vault_0(32) token_vault_1(32) observation_key(32)
+/// mint_decimals_0(1) mint_decimals_1(1) tick_spacing(2) liquidity(16)
+/// sqrt_price_x64(16) tick_current(4)
+pub fn parse_raydium_clmm_pool(data: &[u8]) -> Result<ConcentratedPoolState> {
+    Ok(ConcentratedPoolState {
+        amm_config: Some(read_pubkey(data, 9)?),
+        mint_a: read_pubkey(data, 73)?,
+        mint_b: read_pubkey(data, 105)?,
+        vault_a: read_pubkey(data, 137)?,
+        vault_b: read_pubkey(data, 169)?,
+        tick_spacing: u16::from_le_bytes(read_bytes(data, 235)?),
+        sqrt_price_x64: u128::from_le_bytes(read_bytes(data, 253)?),
+        tick_current: i32::from_le_bytes(read_bytes(data, 269)?),
+        fee_rate: None,
+    })
+}
+
+/// Raydium CLMM `AmmConfig`: discriminator(8) bump(1) index(2) owner(32)
+/// protocol_fee_rate(4) trade_fee_rate(4)
+pub fn parse_raydium_clmm_fee_rate(data: &[u8]) -> Result<u32> {
+    Ok(u32::from_le_bytes(read_bytes(data, 47)?))
+}
+
+/// Orca `Whirlpool`, anchor account layout:
+/// discriminator(8) whirlpools_config(32) whirlpool_bump(1)
+/// tick_spacing(2) tick_spacing_seed(2) fee_rate(2) protocol_fee_rate(2)
+/// liquidity(16) sqrt_price(16) tick_current_index(4)
+/// protocol_fee_owed_a(8) protocol_fee_owed_b(8) token_mint_a(32)
+/// token_vault_a(32) fee_growth_global_a(16) token_mint_b(32)
+/// token_vault_b(32)
+pub fn parse_whirlpool(data: &[u8]) -> Result<ConcentratedPoolState> {
+    Ok(ConcentratedPoolState {
+        amm_config: None,
+        tick_spacing: u16::from_le_bytes(read_bytes(data, 41)?),
+        fee_rate: Some(u16::from_le_bytes(read_bytes(data, 45)?) as u32),
+        sqrt_price_x64: u128::from_le_bytes(read_bytes(data, 65)?),
+        tick_current: i32::from_le_bytes(read_bytes(data, 81)?),
+        mint_a: read_pubkey(data, 101)?,
+        vault_a: read_pubkey(data, 133)?,
+        mint_b: read_pubkey(data, 181)?,
+        vault_b: read_pubkey(data, 213)?,
+    })
+}
+
+/// price of token a in token b, adjusted for decimals
+pub fn sqrt_price_x64_to_price(
+    sqrt_price_x64: u128,
+    decimals_a: u8,
+    decimals_b: u8,
+) -> f64 {
+    let sqrt_price = sqrt_price_x64 as f64 / 2f64.powi(64);
+    sqrt_price
+        * sqrt_price
+        * 10f64.powi(decimals_a as i32 - decimals_b as i32)
+}
+
+pub async fn get_pool_info(
+    rpc_client: &RpcClient,
+    pool_address: &str,
+) -> Result<PoolInfo> {
+    let pool = Pubkey::from_str(pool_address)?;
+    let account = rpc_client.get_account(&pool).await?;
+
+    let (pool_type, mut state) = match account.owner.to_string().as_str() {
+        RAYDIUM_CLMM_PROGRAM => (
+            PoolType::RaydiumClmm,
+            parse_raydium_clmm_pool(&account.data)?,
+        ),
+        ORCA_WHIRLPOOL_PROGRAM => {
+            (PoolType::OrcaWhirlpool, parse_whirlpool(&account.data)?)
+        }
+        owner => {
+            return Err(anyhow!(
+                "Unsupported pool program {} (supported: Raydium CLMM, Orca Whirlpool)",
+                owner
+            ))
+        }
+    };
+
+    if let Some(amm_config) = state.amm_config {
+        let config = rpc_client.get_account(&amm_config).await?;
+        state.fee_rate = Some(parse_raydium_clmm_fee_rate(&config.data)?);
+    }
+
+    // vault balances come with the mint decimals, which neither of the
+    // pool states carry for both tokens
+    let (reserve_a, reserve_b) = tokio::try_join!(
+        rpc_client.get_token_account_balance(&state.vault_a),
+        rpc_client.get_token_account_balance(&state.vault_b),
+    )?;
+
+    let total_volume_24h = match get_pair("solana", pool_address).await {
+        Ok(pair) => pair.and_then(|p| p.volume).and_then(|v| v.h24),
+        Err(e) => {
+            tracing::warn!(?e, "failed to fetch pool volume");
+            None
+        }
+    };
+
+    Ok(PoolInfo {
+        pool_type,
+        base_mint: state.mint_a.to_string(),
+        quote_mint: state.mint_b.to_string(),
+        base_reserve: reserve_a.ui_amount.unwrap_or_default(),
+        quote_reserve: reserve_b.ui_amount.unwrap_or_default(),
+        current_price: sqrt_price_x64_to_price(
+            state.sqrt_price_x64,
+            reserve_a.decimals,
+            reserve_b.decimals,
+        ),
+        fee_rate: state.fee_rate.unwrap_or_default() as f64
+            / FEE_RATE_DENOMINATOR,
+        tick_spacing: state.tick_spacing,
+        tick_current: state.tick_current,
+        total_volume_24h,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    #[test]
+    fn test_parse_raydium_clmm_pool() {
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = vec![0u8; 1544];
+        write(&mut data, 73, mint_a.as_ref());
+        write(&mut data, 105, mint_b.as_ref());
+        write(&mut data, 235, &60u16.to_le_bytes());
+        write(&mut data, 253, &(1u128 << 64).to_le_bytes());
+        write(&mut data, 269, &(-120i32).to_le_bytes());
+
+        let state = parse_raydium_clmm_pool(&data).unwrap();
+        assert_eq!(state.mint_a, mint_a);
+        assert_eq!(state.mint_b, mint_b);
+        assert_eq!(state.tick_spacing, 60);
+        assert_eq!(state.tick_current, -120);
+        assert_eq!(state.sqrt_price_x64, 1u128 << 64);
+
+        assert!(parse_raydium_clmm_pool(&data[..100]).is_err());
+    }
+
+    #[test]
+    fn test_parse_whirlpool() {
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = vec![0u8; 653];
+        write(&mut data, 41, &64u16.to_le_bytes());
+        write(&mut data, 45, &3000u16.to_le_bytes());
+        write(&mut data, 81, &42i32.to_le_bytes());
+        write(&mut data, 101, mint_a.as_ref());
+        write(&mut data, 181, mint_b.as_ref());
+
+        let state = parse_whirlpool(&data).unwrap();
+        assert_eq!(state.mint_a, mint_a);
+        assert_eq!(state.mint_b, mint_b);
+        assert_eq!(state.tick_spacing, 64);
+        assert_eq!(state.fee_rate, Some(3000));
+        assert_eq!(state.tick_current, 42);
+    }
+
+    #[test]
+    fn test_sqrt_price_x64_to_price() {
+        // sqrt price of 2 with SOL (9) priced in USDC (6)
+        let price = sqrt_price_x64_to_price(2u128 << 64, 9, 6);
+        assert!((price - 4000.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_get_pool_info_whirlpool() {
+        // SOL/USDC whirlpool
+        let rpc_client =
+            RpcClient::new("https://api.mainnet-beta.solana.com".to_string());
+        let info = get_pool_info(
+            &rpc_client,
+            "Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE",
+        )
+        .await
+        .unwrap();
+        assert_eq!(info.pool_type, PoolType::OrcaWhirlpool);
+        assert!(info.current_price > 0.0);
+    }
+}
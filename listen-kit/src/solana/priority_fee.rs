@@ -0,0 +1,617 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::compute_budget::{self, ComputeBudgetInstruction};
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::message::VersionedMessage;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::VersionedTransaction;
+use std::str::FromStr;
+
+use crate::solana::util::static_account_index;
+
+/// the most compute units a transaction can request
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// what each instruction gets when the transaction doesn't set a limit
+const DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT: u32 = 200_000;
+
+/// headroom for the instructions added after the simulation, the compute
+/// budget ones and the advance of a durable nonce
+const UNSIMULATED_UNITS: u64 = 1_000;
+
+/// `ComputeBudgetInstruction` variants, borsh encodes them as the first byte
+const SET_COMPUTE_UNIT_LIMIT: u8 = 2;
+const SET_COMPUTE_UNIT_PRICE: u8 = 3;
+
+/// percentile of `PriorityFeeConfig::Auto` when the config leaves it out
+pub const DEFAULT_AUTO_PERCENTILE: u8 = 50;
+
+/// micro-lamports per compute unit a retry escalates from when the
+/// transaction set no price
+pub const MIN_ESCALATED_PRICE: u64 = 10_000;
+
+/// how the compute unit price of sent transactions is picked
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum PriorityFeeConfig {
+    /// transactions are sent as built, e.g. with the fee jupiter picked
+    #[default]
+    None,
+    /// the percentile of the fees recently paid to write the accounts of
+    /// the transaction
+    Auto {
+        #[serde(default = "default_auto_percentile")]
+        percentile: u8,
+    },
+    /// micro-lamports per compute unit
+    Fixed { micro_lamports: u64 },
+}
+
+fn default_auto_percentile() -> u8 {
+    DEFAULT_AUTO_PERCENTILE
+}
+
+/// the `priority` of a tool call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriorityLevel {
+    Low,
+    Medium,
+    High,
+    Turbo,
+}
+
+impl PriorityLevel {
+    pub fn percentile(&self) -> u8 {
+        match self {
+            Self::Low => 25,
+            Self::Medium => 50,
+            Self::High => 75,
+            Self::Turbo => 95,
+        }
+    }
+}
+
+impl FromStr for PriorityLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "low" => Ok(Self::Low),
+            "medium" => Ok(Self::Medium),
+            "high" => Ok(Self::High),
+            "turbo" => Ok(Self::Turbo),
+            _ => Err(anyhow!(
+                "Invalid priority {}, expected low, medium, high or turbo",
+                s
+            )),
+        }
+    }
+}
+
+impl From<PriorityLevel> for PriorityFeeConfig {
+    fn from(level: PriorityLevel) -> Self {
+        Self::Auto {
+            percentile: level.percentile(),
+        }
+    }
+}
+
+/// `none`, `auto`, `auto:<percentile>`, `fixed:<micro-lamports>` or a
+/// priority level, the format of the `PRIORITY_FEE` env var
+impl FromStr for PriorityFeeConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().to_lowercase();
+        let config = match s.split_once(':') {
+            None if s == "none" => Self::None,
+            None if s == "auto" => Self::Auto {
+                percentile: DEFAULT_AUTO_PERCENTILE,
+            },
+            None => s.parse::<PriorityLevel>()?.into(),
+            Some(("auto", percentile)) => Self::Auto {
+                percentile: percentile.parse().map_err(|_| {
+                    anyhow!("Invalid priority fee percentile: {}", percentile)
+                })?,
+            },
+            Some(("fixed", micro_lamports)) => Self::Fixed {
+                micro_lamports: micro_lamports.parse().map_err(|_| {
+                    anyhow!("Invalid priority fee: {}", micro_lamports)
+                })?,
+            },
+            _ => return Err(anyhow!("Invalid priority fee config: {}", s)),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl PriorityFeeConfig {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Self::Auto { percentile } if *percentile > 100 => Err(anyhow!(
+                "Priority fee percentile can be at most 100, got {}",
+                percentile
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// micro-lamports per compute unit for `tx`, 0 for none
+    pub async fn compute_unit_price(
+        &self,
+        rpc_client: &RpcClient,
+        tx: &VersionedTransaction,
+    ) -> Result<u64> {
+        self.compute_unit_price_for(
+            rpc_client,
+            &writable_accounts(&tx.message),
+        )
+        .await
+    }
+
+    /// micro-lamports per compute unit for a transaction writing
+    /// `accounts`, with none the fees recently paid across the cluster
+    pub async fn compute_unit_price_for(
+        &self,
+        rpc_client: &RpcClient,
+        accounts: &[Pubkey],
+    ) -> Result<u64> {
+        match self {
+            Self::None => Ok(0),
+            Self::Fixed { micro_lamports } => Ok(*micro_lamports),
+            Self::Auto { percentile } => {
+                let fees = rpc_client
+                    .get_recent_prioritization_fees(accounts)
+                    .await?
+                    .into_iter()
+                    .map(|fee| fee.prioritization_fee)
+                    .collect();
+                Ok(percentile_fee(fees, *percentile))
+            }
+        }
+    }
+}
+
+/// `priority` of a tool call, `None` keeps the default of the chain config
+pub fn parse_priority(
+    priority: Option<String>,
+) -> Result<Option<PriorityFeeConfig>> {
+    priority
+        .map(|p| p.parse::<PriorityLevel>().map(PriorityFeeConfig::from))
+        .transpose()
+}
+
+/// nearest-rank percentile of `fees`, 0 when there are none
+pub fn percentile_fee(mut fees: Vec<u64>, percentile: u8) -> u64 {
+    if fees.is_empty() {
+        return 0;
+    }
+    fees.sort_unstable();
+    let rank = (fees.len() * percentile.min(100) as usize).div_ceil(100);
+    fees[rank.saturating_sub(1)]
+}
+
+/// the limit of a transaction that consumed `units_consumed` in its
+/// simulation, with 10% to spare for state that changes until it lands
+pub fn compute_unit_limit(units_consumed: u64) -> u32 {
+    let units = units_consumed + units_consumed / 10 + UNSIMULATED_UNITS;
+    units.min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+}
+
+fn writable_accounts(message: &VersionedMessage) -> Vec<Pubkey> {
+    message
+        .static_account_keys()
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| message.is_maybe_writable(*i, None))
+        .map(|(_, key)| *key)
+        .collect()
+}
+
+/// Puts the compute unit price and limit in front of the instructions of
+/// `tx`, replacing the ones it already sets, behind the advance of a
+/// durable nonce which has to come first. Any signatures are invalidated.
+pub fn set_compute_budget(
+    tx: &mut VersionedTransaction,
+    price: Option<u64>,
+    limit: Option<u32>,
+) -> Result<()> {
+    if price.is_none() && limit.is_none() {
+        return Ok(());
+    }
+    let first = usize::from(tx.uses_durable_nonce());
+    let program_id_index =
+        static_account_index(&mut tx.message, &compute_budget::id(), false)?;
+    let budget_ixs = limit
+        .map(ComputeBudgetInstruction::set_compute_unit_limit)
+        .into_iter()
+        .chain(price.map(ComputeBudgetInstruction::set_compute_unit_price))
+        .map(|ix| CompiledInstruction {
+            program_id_index,
+            accounts: vec![],
+            data: ix.data,
+        })
+        .collect::<Vec<_>>();
+
+    let instructions = match &mut tx.message {
+        VersionedMessage::Legacy(message) => &mut message.instructions,
+        VersionedMessage::V0(message) => &mut message.instructions,
+    };
+    instructions.retain(|ix| {
+        ix.program_id_index != program_id_index
+            || match ix.data.first() {
+                Some(&SET_COMPUTE_UNIT_LIMIT) => limit.is_none(),
+                Some(&SET_COMPUTE_UNIT_PRICE) => price.is_none(),
+                _ => true,
+            }
+    });
+    instructions.splice(first..first, budget_ixs);
+    Ok(())
+}
+
+/// the priority fee in lamports `message` pays, its compute unit price
+/// times its compute unit limit
+pub fn priority_fee_lamports(message: &VersionedMessage) -> u64 {
+    let keys = message.static_account_keys();
+    let (mut price, mut limit, mut other_ixs) = (0, None, 0);
+    for ix in message.instructions() {
+        let is_budget = keys
+            .get(ix.program_id_index as usize)
+            .is_some_and(compute_budget::check_id);
+        match ix.data.split_first() {
+            Some((&SET_COMPUTE_UNIT_PRICE, data)) if is_budget => {
+                price = data
+                    .get(..8)
+                    .map_or(0, |b| u64::from_le_bytes(b.try_into().unwrap()));
+            }
+            Some((&SET_COMPUTE_UNIT_LIMIT, data)) if is_budget => {
+                limit = data
+                    .get(..4)
+                    .map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+            }
+            _ if is_budget => {}
+            _ => other_ixs += 1,
+        }
+    }
+    let limit = limit
+        .unwrap_or(other_ixs * DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT)
+        .min(MAX_COMPUTE_UNIT_LIMIT);
+    // the price is in micro-lamports, rounded up like the runtime does
+    (price as u128 * limit as u128).div_ceil(1_000_000) as u64
+}
+
+/// Sets the compute budget of `tx` for `config`, with a limit from the
+/// simulation when there was one. Returns the priority fee it pays, which
+/// for `PriorityFeeConfig::None` is whatever the builder of `tx` set.
+pub async fn apply_priority_fee(
+    rpc_client: &RpcClient,
+    tx: &mut VersionedTransaction,
+    config: &PriorityFeeConfig,
+    units_consumed: Option<u64>,
+) -> Result<u64> {
+    if *config != PriorityFeeConfig::None {
+        let price = config.compute_unit_price(rpc_client, tx).await?;
+        set_compute_budget(
+            tx,
+            (price > 0).then_some(price),
+            units_consumed.map(compute_unit_limit),
+        )?;
+    }
+    Ok(priority_fee_lamports(&tx.message))
+}
+
+/// the compute unit price `message` sets, 0 for none
+pub fn compute_unit_price_of(message: &VersionedMessage) -> u64 {
+    let keys = message.static_account_keys();
+    message
+        .instructions()
+        .iter()
+        .filter(|ix| {
+            keys.get(ix.program_id_index as usize)
+                .is_some_and(compute_budget::check_id)
+        })
+        .find_map(|ix| match ix.data.split_first() {
+            Some((&SET_COMPUTE_UNIT_PRICE, data)) => data
+                .get(..8)
+                .map(|b| u64::from_le_bytes(b.try_into().unwrap())),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+/// `price` bumped by `bump_pct` for each of `retries`, from at least
+/// `MIN_ESCALATED_PRICE`
+pub fn escalated_price(price: u64, bump_pct: u16, retries: u32) -> u64 {
+    (0..retries).fold(price.max(MIN_ESCALATED_PRICE), |price, _| {
+        price.saturating_mul(100 + bump_pct as u64) / 100
+    })
+}
+
+/// Raises the compute unit price of `tx` for its `retries`th retry, keeping
+/// its limit. Returns the priority fee it pays then; nothing changes
+/// without a bump
+pub fn escalate_priority_fee(
+    tx: &mut VersionedTransaction,
+    bump_pct: u16,
+    retries: u32,
+) -> Result<u64> {
+    if bump_pct > 0 && retries > 0 {
+        let price = escalated_price(
+            compute_unit_price_of(&tx.message),
+            bump_pct,
+            retries,
+        );
+        set_compute_budget(tx, Some(price), None)?;
+    }
+    Ok(priority_fee_lamports(&tx.message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::message::v0;
+    use solana_sdk::system_instruction;
+    use solana_sdk::system_program;
+    use solana_sdk::transaction::Transaction;
+    use std::collections::HashMap;
+
+    fn transfer_tx(payer: &Pubkey, to: &Pubkey) -> VersionedTransaction {
+        Transaction::new_with_payer(
+            &[system_instruction::transfer(payer, to, 1)],
+            Some(payer),
+        )
+        .into()
+    }
+
+    /// the program and accounts of every instruction of `message`
+    fn resolved(message: &VersionedMessage) -> Vec<(Pubkey, Vec<usize>)> {
+        let keys = message.static_account_keys();
+        message
+            .instructions()
+            .iter()
+            .map(|ix| {
+                (
+                    keys[ix.program_id_index as usize],
+                    ix.accounts.iter().map(|&a| a as usize).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_percentile_fee() {
+        let fees = vec![70, 0, 10, 1_000, 20, 30, 0, 40, 50, 60];
+        assert_eq!(percentile_fee(fees.clone(), 0), 0);
+        assert_eq!(percentile_fee(fees.clone(), 25), 10);
+        assert_eq!(percentile_fee(fees.clone(), 50), 30);
+        assert_eq!(percentile_fee(fees.clone(), 75), 60);
+        assert_eq!(percentile_fee(fees.clone(), 95), 1_000);
+        assert_eq!(percentile_fee(fees, 100), 1_000);
+        assert_eq!(percentile_fee(vec![5], 25), 5);
+        assert_eq!(percentile_fee(vec![], 50), 0);
+    }
+
+    #[test]
+    fn test_parse_priority() {
+        assert_eq!(parse_priority(None).unwrap(), None);
+        assert_eq!(
+            parse_priority(Some("Turbo".to_string())).unwrap(),
+            Some(PriorityFeeConfig::Auto { percentile: 95 })
+        );
+        assert!(parse_priority(Some("urgent".to_string())).is_err());
+
+        assert_eq!(
+            "none".parse::<PriorityFeeConfig>().unwrap(),
+            PriorityFeeConfig::None
+        );
+        assert_eq!(
+            "auto".parse::<PriorityFeeConfig>().unwrap(),
+            PriorityFeeConfig::Auto { percentile: 50 }
+        );
+        assert_eq!(
+            "auto:90".parse::<PriorityFeeConfig>().unwrap(),
+            PriorityFeeConfig::Auto { percentile: 90 }
+        );
+        assert_eq!(
+            "fixed:5000".parse::<PriorityFeeConfig>().unwrap(),
+            PriorityFeeConfig::Fixed {
+                micro_lamports: 5_000
+            }
+        );
+        assert_eq!(
+            "low".parse::<PriorityFeeConfig>().unwrap(),
+            PriorityFeeConfig::Auto { percentile: 25 }
+        );
+        assert!("auto:101".parse::<PriorityFeeConfig>().is_err());
+        assert!("fixed:lots".parse::<PriorityFeeConfig>().is_err());
+    }
+
+    #[test]
+    fn test_compute_unit_limit() {
+        assert_eq!(compute_unit_limit(10_000), 12_000);
+        assert_eq!(compute_unit_limit(2_000_000), MAX_COMPUTE_UNIT_LIMIT);
+    }
+
+    #[test]
+    fn test_prepends_compute_budget() {
+        let (payer, to) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut tx = transfer_tx(&payer, &to);
+        let transfer = resolved(&tx.message).remove(0);
+
+        set_compute_budget(&mut tx, Some(10_000), Some(50_000)).unwrap();
+
+        let ixs = resolved(&tx.message);
+        assert_eq!(ixs.len(), 3);
+        assert!(compute_budget::check_id(&ixs[0].0));
+        assert!(compute_budget::check_id(&ixs[1].0));
+        assert_eq!(
+            tx.message.instructions()[0].data,
+            ComputeBudgetInstruction::set_compute_unit_limit(50_000).data
+        );
+        assert_eq!(
+            tx.message.instructions()[1].data,
+            ComputeBudgetInstruction::set_compute_unit_price(10_000).data
+        );
+        assert_eq!(ixs[2], transfer);
+        // the program is readonly, the transfer accounts keep their locks
+        let keys = tx.message.static_account_keys();
+        let program = keys.len() - 1;
+        assert!(!tx.message.is_maybe_writable(program, None));
+        assert!(tx.message.is_maybe_writable(0, None));
+        assert_eq!(priority_fee_lamports(&tx.message), 500);
+    }
+
+    #[test]
+    fn test_replaces_compute_budget() {
+        let payer = Pubkey::new_unique();
+        let mut ixs = crate::solana::util::make_compute_budget_ixs(69, 69);
+        ixs.push(system_instruction::transfer(
+            &payer,
+            &Pubkey::new_unique(),
+            1,
+        ));
+        let mut tx: VersionedTransaction =
+            Transaction::new_with_payer(&ixs, Some(&payer)).into();
+        let keys = tx.message.static_account_keys().len();
+
+        // only the price, the limit of the builder stays
+        set_compute_budget(&mut tx, Some(1_000_000), None).unwrap();
+
+        assert_eq!(tx.message.static_account_keys().len(), keys);
+        assert_eq!(tx.message.instructions().len(), 3);
+        assert_eq!(
+            tx.message.instructions()[0].data,
+            ComputeBudgetInstruction::set_compute_unit_price(1_000_000).data
+        );
+        assert_eq!(priority_fee_lamports(&tx.message), 69);
+    }
+
+    #[test]
+    fn test_shifts_lookup_table_accounts() {
+        let (payer, to) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![to],
+        };
+        let message = v0::Message::try_compile(
+            &payer,
+            &[system_instruction::transfer(&payer, &to, 1)],
+            &[table],
+            Hash::default(),
+        )
+        .unwrap();
+        let mut tx = VersionedTransaction {
+            signatures: vec![Default::default()],
+            message: VersionedMessage::V0(message),
+        };
+        // `to` is loaded, right after the static keys
+        let statics = tx.message.static_account_keys().len();
+        assert_eq!(resolved(&tx.message)[0].1, vec![0, statics]);
+
+        set_compute_budget(&mut tx, Some(1), Some(1_000)).unwrap();
+
+        let ixs = resolved(&tx.message);
+        assert_eq!(tx.message.static_account_keys().len(), statics + 1);
+        assert_eq!(ixs[2].0, system_program::id());
+        assert_eq!(ixs[2].1, vec![0, statics + 1]);
+    }
+
+    #[test]
+    fn test_keeps_advance_nonce_first() {
+        let (payer, nonce) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut tx: VersionedTransaction = Transaction::new_with_payer(
+            &[
+                system_instruction::advance_nonce_account(&nonce, &payer),
+                system_instruction::transfer(&payer, &nonce, 1),
+            ],
+            Some(&payer),
+        )
+        .into();
+
+        set_compute_budget(&mut tx, Some(1), Some(1_000)).unwrap();
+
+        assert!(tx.uses_durable_nonce());
+        let ixs = resolved(&tx.message);
+        assert_eq!(ixs[0].0, system_program::id());
+        assert!(compute_budget::check_id(&ixs[1].0));
+        assert!(compute_budget::check_id(&ixs[2].0));
+    }
+
+    #[tokio::test]
+    async fn test_apply_auto_priority_fee() {
+        let rpc_client = RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([(
+                RpcRequest::GetRecentPrioritizationFees,
+                json!([
+                    { "slot": 1, "prioritizationFee": 0 },
+                    { "slot": 2, "prioritizationFee": 2_000 },
+                    { "slot": 3, "prioritizationFee": 1_000 },
+                    { "slot": 4, "prioritizationFee": 4_000 },
+                ]),
+            )]),
+        );
+        let payer = Pubkey::new_unique();
+
+        let mut tx = transfer_tx(&payer, &Pubkey::new_unique());
+        let fee = apply_priority_fee(
+            &rpc_client,
+            &mut tx,
+            &PriorityLevel::High.into(),
+            Some(150),
+        )
+        .await
+        .unwrap();
+        // 75th percentile of the recent fees, for 150 * 1.1 + 1000 units
+        assert_eq!(
+            tx.message.instructions()[1].data,
+            ComputeBudgetInstruction::set_compute_unit_price(2_000).data
+        );
+        assert_eq!(fee, 3);
+
+        // without a config the transaction is left as built
+        let mut tx = transfer_tx(&payer, &Pubkey::new_unique());
+        let before = tx.clone();
+        let fee = apply_priority_fee(
+            &rpc_client,
+            &mut tx,
+            &PriorityFeeConfig::None,
+            Some(150),
+        )
+        .await
+        .unwrap();
+        assert_eq!(tx, before);
+        assert_eq!(fee, 0);
+    }
+
+    #[test]
+    fn test_escalate_priority_fee() {
+        let payer = Pubkey::new_unique();
+        let mut tx = transfer_tx(&payer, &Pubkey::new_unique());
+        set_compute_budget(&mut tx, Some(20_000), Some(100_000)).unwrap();
+
+        // the first attempt is sent as priced
+        let before = tx.clone();
+        escalate_priority_fee(&mut tx, 50, 0).unwrap();
+        assert_eq!(tx, before);
+
+        let fee = escalate_priority_fee(&mut tx, 50, 2).unwrap();
+        assert_eq!(compute_unit_price_of(&tx.message), 45_000);
+        // the limit is kept, 45k micro-lamports for 100k units
+        assert_eq!(fee, 4_500);
+
+        // unpriced transactions start from the floor
+        let mut tx = transfer_tx(&payer, &Pubkey::new_unique());
+        escalate_priority_fee(&mut tx, 25, 1).unwrap();
+        assert_eq!(
+            compute_unit_price_of(&tx.message),
+            MIN_ESCALATED_PRICE * 125 / 100
+        );
+    }
+}
@@ -265,6 +265,29 @@ pub fn get_pump_token_amount(
     Ok(final_amount_out as u64)
 }
 
+/// pump.fun takes 1% of the SOL of every trade
+pub const PUMP_FEE_BPS: u64 = 100;
+
+/// lamports received for selling `token_amount` into the bonding curve,
+/// after the pump.fun fee
+pub fn get_pump_sol_amount(
+    virtual_sol_reserves: u64,
+    virtual_token_reserves: u64,
+    token_amount: u64,
+) -> Result<u64> {
+    let new_virtual_token_reserve = (virtual_token_reserves as u128)
+        .checked_add(token_amount as u128)
+        .ok_or_else(|| anyhow!("Overflow in new virtual token reserve"))?;
+    let sol_out = (token_amount as u128)
+        .checked_mul(virtual_sol_reserves as u128)
+        .ok_or_else(|| anyhow!("Overflow in SOL out calculation"))?
+        .checked_div(new_virtual_token_reserve)
+        .ok_or_else(|| anyhow!("Division by zero in SOL out calculation"))?;
+    let fee = sol_out * PUMP_FEE_BPS as u128 / 10_000;
+
+    Ok((sol_out - fee) as u64)
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct PumpBuyRequest {
     #[serde(
@@ -415,7 +438,9 @@ pub async fn sell_pump_token(
 
     let mut ixs = vec![];
     let mut compute_budget_ixs = make_compute_budget_ixs(69_000, 69_000);
-    let sell_ix = make_pump_sell_ix(owner, pump_accounts, token_amount, ata)?;
+    // max slippage, careful if not using frontrun protection
+    let sell_ix =
+        make_pump_sell_ix(owner, pump_accounts, token_amount, 0, ata)?;
     ixs.append(&mut compute_budget_ixs);
     ixs.push(sell_ix);
     ixs.push(transfer(&owner, &get_jito_tip_pubkey(), 30_000));
@@ -445,10 +470,13 @@ pub async fn sell_pump_token(
 /// #10 - Token Program
 /// #11 - Event Authority
 /// #12 - Program: Pump.fun Program
+/// the sell fails on-chain if it would yield less than `min_sol_output`
+/// lamports, 0 accepts any price
 pub fn make_pump_sell_ix(
     owner: Pubkey,
     pump_accounts: PumpAccounts,
     token_amount: u64,
+    min_sol_output: u64,
     ata: Pubkey,
 ) -> Result<Instruction> {
     let accounts: [AccountMeta; 12] = [
@@ -475,11 +503,10 @@ pub fn make_pump_sell_ix(
         AccountMeta::new_readonly(Pubkey::from_str(PUMP_FUN_PROGRAM)?, false),
     ];
 
-    // max slippage, careful if not using frontrun protection
     let data = PumpFunSwapInstructionData {
         method_id: PUMP_SELL_METHOD,
         token_amount,
-        lamports: 0,
+        lamports: min_sol_output,
     };
 
     Ok(Instruction::new_with_borsh(
@@ -0,0 +1,71 @@
+use anyhow::{anyhow, Result};
+use solana_sdk::native_token::{lamports_to_sol, sol_to_lamports};
+
+/// `amount` of the swap tool that stands for the whole balance
+pub const MAX_AMOUNT: &str = "max";
+
+/// 0.01 SOL, enough for the fees and rent of a few more transactions
+pub const DEFAULT_SOL_RESERVE: u64 = 10_000_000;
+
+pub fn is_max_amount(amount: &str) -> bool {
+    amount.trim().eq_ignore_ascii_case(MAX_AMOUNT)
+}
+
+/// lamports left in the wallet on "max" SOL spends, `SOL_RESERVE` in SOL
+pub fn sol_reserve() -> u64 {
+    parse_sol_reserve(std::env::var("SOL_RESERVE").ok().as_deref())
+}
+
+fn parse_sol_reserve(value: Option<&str>) -> u64 {
+    value
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .filter(|sol| sol.is_finite() && *sol >= 0.0)
+        .map(sol_to_lamports)
+        .unwrap_or(DEFAULT_SOL_RESERVE)
+}
+
+/// what a "max" spend of a `balance` lamports wallet comes down to
+pub fn max_spendable_sol(balance: u64, reserve: u64) -> Result<u64> {
+    match balance.checked_sub(reserve) {
+        Some(spendable) if spendable > 0 => Ok(spendable),
+        _ => Err(anyhow!(
+            "balance of {} SOL doesn't exceed the reserve of {} SOL kept for fees",
+            lamports_to_sol(balance),
+            lamports_to_sol(reserve)
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_leaves_reserve() {
+        let balance = 1_234_567_890;
+        let spendable =
+            max_spendable_sol(balance, DEFAULT_SOL_RESERVE).unwrap();
+        assert_eq!(balance - spendable, DEFAULT_SOL_RESERVE);
+
+        let err = max_spendable_sol(DEFAULT_SOL_RESERVE, DEFAULT_SOL_RESERVE)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "balance of 0.01 SOL doesn't exceed the reserve of 0.01 SOL kept for fees"
+        );
+        assert!(max_spendable_sol(0, DEFAULT_SOL_RESERVE).is_err());
+    }
+
+    #[test]
+    fn test_sol_reserve_config() {
+        assert_eq!(parse_sol_reserve(None), DEFAULT_SOL_RESERVE);
+        assert_eq!(parse_sol_reserve(Some("0.05")), 50_000_000);
+        assert_eq!(parse_sol_reserve(Some("0")), 0);
+        assert_eq!(parse_sol_reserve(Some("-1")), DEFAULT_SOL_RESERVE);
+        assert_eq!(parse_sol_reserve(Some("lots")), DEFAULT_SOL_RESERVE);
+
+        assert!(is_max_amount("max"));
+        assert!(is_max_amount(" MAX "));
+        assert!(!is_max_amount("1000"));
+    }
+}
@@ -0,0 +1,214 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+
+use crate::solana::jup::JupiterClient;
+
+/// the quotes need a slippage, the output they compare doesn't depend on it
+pub const DEFAULT_COMPARE_SLIPPAGE_BPS: u16 = 50;
+
+/// the jupiter labels of the raydium pools
+const RAYDIUM_DEXES: [&str; 3] = ["Raydium", "Raydium CLMM", "Raydium CP"];
+
+/// where a swap is routed
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Venue {
+    /// the best route across every dex jupiter aggregates
+    #[default]
+    Jupiter,
+    /// raydium pools only
+    Raydium,
+}
+
+/// every venue, in the order they are compared
+const VENUES: [Venue; 2] = [Venue::Jupiter, Venue::Raydium];
+
+impl Venue {
+    /// the dexes jupiter has to route through to swap on this venue
+    pub fn jupiter_dexes(&self) -> Option<&'static [&'static str]> {
+        match self {
+            Venue::Jupiter => None,
+            Venue::Raydium => Some(&RAYDIUM_DEXES),
+        }
+    }
+}
+
+impl fmt::Display for Venue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Venue::Jupiter => write!(f, "jupiter"),
+            Venue::Raydium => write!(f, "raydium"),
+        }
+    }
+}
+
+impl FromStr for Venue {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "jupiter" => Ok(Venue::Jupiter),
+            "raydium" => Ok(Venue::Raydium),
+            _ => Err(anyhow!(
+                "unknown venue {}, use jupiter or raydium",
+                s.trim()
+            )),
+        }
+    }
+}
+
+/// the quote of a venue for the same input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteQuote {
+    pub venue: Venue,
+    pub out_amount: u64,
+    /// in percent
+    pub price_impact: f64,
+}
+
+/// best first, the most output for the input
+pub fn sort_routes(mut routes: Vec<RouteQuote>) -> Vec<RouteQuote> {
+    routes.sort_by(|a, b| b.out_amount.cmp(&a.out_amount));
+    routes
+}
+
+/// The quotes of every venue with a route for `amount` of `input_mint`,
+/// best first; fails only when none has one. Each venue is quoted through
+/// jupiter restricted to its dexes, as its swaps are sent, so the route
+/// picked is the one that executes
+pub async fn compare_routes(
+    jupiter: &JupiterClient,
+    input_mint: &str,
+    output_mint: &str,
+    amount: u64,
+    slippage_bps: u16,
+) -> Result<Vec<RouteQuote>> {
+    let quotes = join_all(VENUES.map(|venue| async move {
+        let quote = jupiter
+            .clone()
+            .with_dexes(venue.jupiter_dexes())
+            .fetch_quote_with_slippage(
+                input_mint,
+                output_mint,
+                amount,
+                Some(slippage_bps),
+            )
+            .await?;
+        Ok::<_, anyhow::Error>(RouteQuote {
+            venue,
+            out_amount: quote.out_amount.parse()?,
+            // jupiter reports it as a fraction
+            price_impact: quote.price_impact_pct.parse::<f64>()? * 100.,
+        })
+    }))
+    .await;
+
+    let mut routes = Vec::new();
+    let mut errors = Vec::new();
+    for quote in quotes {
+        match quote {
+            Ok(quote) => routes.push(quote),
+            Err(e) => {
+                tracing::warn!(%input_mint, %output_mint, %e, "no route");
+                errors.push(e.to_string());
+            }
+        }
+    }
+    if routes.is_empty() {
+        return Err(anyhow!("no venue has a route: {}", errors.join("; ")));
+    }
+    Ok(sort_routes(routes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+    fn quote_body(out_amount: &str, price_impact_pct: &str) -> String {
+        serde_json::json!({
+            "inputMint": SOL,
+            "inAmount": "1000000000",
+            "outputMint": USDC,
+            "outAmount": out_amount,
+            "otherAmountThreshold": out_amount,
+            "swapMode": "ExactIn",
+            "slippageBps": 50,
+            "platformFee": null,
+            "priceImpactPct": price_impact_pct,
+            "routePlan": [],
+            "contextSlot": 1,
+            "timeTaken": 0.01,
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_compare_routes_best_first() {
+        let mut server = mockito::Server::new_async().await;
+        let jupiter_mock = server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::Any)
+            .match_request(|request| {
+                !request.path_and_query().contains("dexes")
+            })
+            .with_body(quote_body("150000000", "0.002"))
+            .expect(1)
+            .create_async()
+            .await;
+        // the raydium quote is the route a raydium swap executes
+        let raydium_mock = server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("inputMint".into(), SOL.into()),
+                mockito::Matcher::UrlEncoded(
+                    "dexes".into(),
+                    RAYDIUM_DEXES.join(","),
+                ),
+            ]))
+            .with_body(quote_body("150300000", "0.0005"))
+            .expect(1)
+            .create_async()
+            .await;
+
+        let routes = compare_routes(
+            &JupiterClient::new(&server.url()).with_referral(None),
+            SOL,
+            USDC,
+            1_000_000_000,
+            50,
+        )
+        .await
+        .unwrap();
+        jupiter_mock.assert_async().await;
+        raydium_mock.assert_async().await;
+        assert_eq!(
+            routes
+                .iter()
+                .map(|route| (route.venue, route.out_amount))
+                .collect::<Vec<_>>(),
+            vec![
+                (Venue::Raydium, 150_300_000),
+                (Venue::Jupiter, 150_000_000)
+            ]
+        );
+        // both in percent
+        assert!((routes[0].price_impact - 0.05).abs() < 1e-9);
+        assert!((routes[1].price_impact - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_venue_from_str() {
+        assert_eq!(" Raydium ".parse::<Venue>().unwrap(), Venue::Raydium);
+        assert_eq!(Venue::Jupiter.jupiter_dexes(), None);
+        assert!("orca".parse::<Venue>().is_err());
+    }
+}
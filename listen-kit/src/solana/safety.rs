@@ -0,0 +1,609 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_response::RpcTokenAccountBalance;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::solana::pump::mint_to_pump_accounts;
+use crate::solana::token_info::{get_created_slot, parse_mint_authorities};
+
+pub const RAYDIUM_POOLS_API_URL: &str = "https://api-v3.raydium.io";
+
+/// authorities holding the reserves of raydium AMM v4 and CPMM pools
+pub(crate) const RAYDIUM_POOL_AUTHORITIES: [&str; 2] = [
+    "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1",
+    "GpMZbSM2GgvTKHJirzeGfMFoaZ8UR2X7F4v8vHTvxFbL",
+];
+
+const SLOT_SECS: f64 = 0.4;
+const TOP_HOLDERS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlagStatus {
+    Pass,
+    Warn,
+    Fail,
+    /// the signal couldn't be fetched
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SafetyFlag {
+    pub status: FlagStatus,
+    pub detail: String,
+}
+
+impl SafetyFlag {
+    fn new(status: FlagStatus, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            detail: detail.into(),
+        }
+    }
+
+    fn unknown(e: impl std::fmt::Display) -> Self {
+        Self::new(FlagStatus::Unknown, e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenSafety {
+    pub mint: String,
+    /// an active mint authority can inflate the supply at will
+    pub mint_authority: SafetyFlag,
+    /// an active freeze authority can block holders from selling
+    pub freeze_authority: SafetyFlag,
+    /// share of the supply in the 10 largest accounts, pools left out
+    pub top_holders: SafetyFlag,
+    /// whether the largest account is the bonding curve or a pool
+    pub largest_holder: SafetyFlag,
+    /// share of the LP tokens of the deepest raydium pool that is burnt
+    pub lp_burned: SafetyFlag,
+    pub token_age: SafetyFlag,
+    /// 0 (no red flags) to 100, unknown signals count for a quarter
+    pub risk_score: u8,
+    pub risk_level: RiskLevel,
+}
+
+impl TokenSafety {
+    /// weight of each flag in the score, they add up to 100
+    fn weighted_flags(&self) -> [(&SafetyFlag, f64); 6] {
+        [
+            (&self.mint_authority, 25.),
+            (&self.freeze_authority, 25.),
+            (&self.top_holders, 20.),
+            (&self.largest_holder, 10.),
+            (&self.lp_burned, 10.),
+            (&self.token_age, 10.),
+        ]
+    }
+
+    fn score(&mut self) {
+        let score: f64 = self
+            .weighted_flags()
+            .iter()
+            .map(|(flag, weight)| {
+                weight
+                    * match flag.status {
+                        FlagStatus::Pass => 0.,
+                        FlagStatus::Warn => 0.5,
+                        FlagStatus::Fail => 1.,
+                        FlagStatus::Unknown => 0.25,
+                    }
+            })
+            .sum();
+        self.risk_score = score.round() as u8;
+        self.risk_level = match self.risk_score {
+            0..=24 => RiskLevel::Low,
+            25..=49 => RiskLevel::Medium,
+            _ => RiskLevel::High,
+        };
+    }
+}
+
+fn authority_flag(authority: Option<String>, power: &str) -> SafetyFlag {
+    match authority {
+        Some(authority) => SafetyFlag::new(
+            FlagStatus::Fail,
+            format!("{} can {}", authority, power),
+        ),
+        None => SafetyFlag::new(FlagStatus::Pass, "renounced"),
+    }
+}
+
+async fn check_authorities(
+    rpc_client: &RpcClient,
+    mint: &Pubkey,
+) -> (SafetyFlag, SafetyFlag) {
+    let authorities = match rpc_client.get_account(mint).await {
+        Ok(account) => parse_mint_authorities(&account.data),
+        Err(e) => Err(e.into()),
+    };
+    match authorities {
+        Ok((mint_authority, freeze_authority)) => (
+            authority_flag(mint_authority, "mint more tokens"),
+            authority_flag(freeze_authority, "freeze holders"),
+        ),
+        Err(e) => {
+            tracing::warn!(%mint, %e, "failed to fetch mint authorities");
+            (SafetyFlag::unknown(&e), SafetyFlag::unknown(&e))
+        }
+    }
+}
+
+/// what the account is known as, if it belongs to the bonding curve or a
+/// pool rather than a holder
+fn known_pool(
+    mint: &Pubkey,
+    account: &str,
+    owner: Option<&Pubkey>,
+) -> Option<&'static str> {
+    let pump = mint_to_pump_accounts(mint);
+    if account == pump.associated_bonding_curve.to_string()
+        || owner == Some(&pump.bonding_curve)
+    {
+        return Some("the pump.fun bonding curve");
+    }
+    owner
+        .filter(|owner| {
+            RAYDIUM_POOL_AUTHORITIES.contains(&owner.to_string().as_str())
+        })
+        .map(|_| "a raydium pool")
+}
+
+/// the wallet of each token account, `None` where it couldn't be read
+pub(crate) async fn token_account_owners(
+    rpc_client: &RpcClient,
+    accounts: &[RpcTokenAccountBalance],
+) -> Vec<Option<Pubkey>> {
+    let keys = accounts
+        .iter()
+        .map(|account| Pubkey::from_str(&account.address))
+        .collect::<Result<Vec<_>, _>>();
+    let fetched = match keys {
+        Ok(keys) => rpc_client.get_multiple_accounts(&keys).await,
+        Err(_) => return vec![None; accounts.len()],
+    };
+    match fetched {
+        Ok(fetched) => fetched
+            .into_iter()
+            .map(|account| {
+                // the owner follows the mint in the token account layout
+                let owner = account?.data.get(32..64)?.try_into().ok()?;
+                Some(Pubkey::new_from_array(owner))
+            })
+            .collect(),
+        Err(e) => {
+            tracing::warn!(%e, "failed to fetch the owners of the holders");
+            vec![None; accounts.len()]
+        }
+    }
+}
+
+async fn check_holders(
+    rpc_client: &RpcClient,
+    mint: &Pubkey,
+) -> (SafetyFlag, SafetyFlag) {
+    let (largest, supply) = match tokio::try_join!(
+        rpc_client.get_token_largest_accounts(mint),
+        rpc_client.get_token_supply(mint),
+    ) {
+        Ok(holders) => holders,
+        Err(e) => {
+            tracing::warn!(%mint, %e, "failed to fetch the largest holders");
+            return (SafetyFlag::unknown(&e), SafetyFlag::unknown(&e));
+        }
+    };
+    let supply = supply.ui_amount.unwrap_or_default();
+    if supply <= 0. || largest.is_empty() {
+        let flag = SafetyFlag::new(FlagStatus::Unknown, "no supply");
+        return (flag.clone(), flag);
+    }
+    let largest: Vec<_> = largest.into_iter().take(TOP_HOLDERS).collect();
+    let owners = token_account_owners(rpc_client, &largest).await;
+    let share = |account: &RpcTokenAccountBalance| {
+        account.amount.ui_amount.unwrap_or_default() / supply * 100.
+    };
+
+    let pools: Vec<_> = largest
+        .iter()
+        .zip(&owners)
+        .map(|(account, owner)| {
+            known_pool(mint, &account.address, owner.as_ref())
+        })
+        .collect();
+    let concentration: f64 = largest
+        .iter()
+        .zip(&pools)
+        .filter(|(_, pool)| pool.is_none())
+        .map(|(account, _)| share(account))
+        .sum();
+    let top_holders = SafetyFlag::new(
+        match concentration {
+            c if c > 50. => FlagStatus::Fail,
+            c if c > 25. => FlagStatus::Warn,
+            _ => FlagStatus::Pass,
+        },
+        format!("the top holders own {:.1}% of the supply", concentration),
+    );
+
+    let largest_share = share(&largest[0]);
+    let largest_holder = match pools[0] {
+        Some(pool) => SafetyFlag::new(
+            FlagStatus::Pass,
+            format!("{} with {:.1}%", pool, largest_share),
+        ),
+        None => SafetyFlag::new(
+            match largest_share {
+                s if s > 20. => FlagStatus::Fail,
+                s if s > 10. => FlagStatus::Warn,
+                _ => FlagStatus::Pass,
+            },
+            format!("a holder with {:.1}%", largest_share),
+        ),
+    };
+    (top_holders, largest_holder)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RaydiumPool {
+    burn_percent: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RaydiumPoolPage {
+    data: Vec<RaydiumPool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RaydiumPoolsResponse {
+    success: bool,
+    data: Option<RaydiumPoolPage>,
+}
+
+/// the burnt share of the LP tokens of the deepest standard raydium pool
+async fn fetch_lp_burn_percent(
+    api_base: &str,
+    mint: &Pubkey,
+) -> Result<Option<f64>> {
+    let response = reqwest::Client::new()
+        .get(format!("{}/pools/info/mint", api_base))
+        .query(&[
+            ("mint1", mint.to_string().as_str()),
+            ("poolType", "standard"),
+            ("poolSortField", "liquidity"),
+            ("sortType", "desc"),
+            ("pageSize", "1"),
+            ("page", "1"),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<RaydiumPoolsResponse>()
+        .await?;
+    if !response.success {
+        return Err(anyhow!("raydium pool lookup failed"));
+    }
+    Ok(response
+        .data
+        .and_then(|page| page.data.into_iter().next())
+        .map(|pool| pool.burn_percent))
+}
+
+async fn check_lp_burn(api_base: &str, mint: &Pubkey) -> SafetyFlag {
+    match fetch_lp_burn_percent(api_base, mint).await {
+        Ok(Some(burnt)) => SafetyFlag::new(
+            match burnt {
+                b if b >= 90. => FlagStatus::Pass,
+                b if b >= 50. => FlagStatus::Warn,
+                _ => FlagStatus::Fail,
+            },
+            format!("{:.0}% of the LP tokens are burnt", burnt),
+        ),
+        Ok(None) => {
+            SafetyFlag::new(FlagStatus::Unknown, "no raydium pool found")
+        }
+        Err(e) => {
+            tracing::warn!(%mint, %e, "failed to fetch the LP burn");
+            SafetyFlag::unknown(e)
+        }
+    }
+}
+
+async fn check_age(rpc_client: &RpcClient, mint: &Pubkey) -> SafetyFlag {
+    let slots = tokio::try_join!(
+        async { get_created_slot(rpc_client, mint).await },
+        async { rpc_client.get_slot().await.map_err(anyhow::Error::from) },
+    );
+    match slots {
+        Ok((Some(created), current)) => {
+            let hours =
+                current.saturating_sub(created) as f64 * SLOT_SECS / 3600.;
+            SafetyFlag::new(
+                match hours {
+                    h if h < 1. => FlagStatus::Fail,
+                    h if h < 24. => FlagStatus::Warn,
+                    _ => FlagStatus::Pass,
+                },
+                format!("created about {:.1} hours ago", hours),
+            )
+        }
+        Ok((None, _)) => SafetyFlag::new(
+            FlagStatus::Unknown,
+            "the first transaction of the mint wasn't found",
+        ),
+        Err(e) => {
+            tracing::warn!(%mint, %e, "failed to fetch the token age");
+            SafetyFlag::unknown(e)
+        }
+    }
+}
+
+/// every signal is fetched on its own and is unknown when its request
+/// fails, only an invalid mint is an error
+pub async fn check_token_safety(
+    rpc_client: &RpcClient,
+    raydium_api: &str,
+    mint: &str,
+) -> Result<TokenSafety> {
+    let mint = Pubkey::from_str(mint)?;
+    let (
+        (mint_authority, freeze_authority),
+        (top_holders, largest_holder),
+        lp_burned,
+        token_age,
+    ) = tokio::join!(
+        check_authorities(rpc_client, &mint),
+        check_holders(rpc_client, &mint),
+        check_lp_burn(raydium_api, &mint),
+        check_age(rpc_client, &mint),
+    );
+    let mut safety = TokenSafety {
+        mint: mint.to_string(),
+        mint_authority,
+        freeze_authority,
+        top_holders,
+        largest_holder,
+        lp_burned,
+        token_age,
+        risk_score: 0,
+        risk_level: RiskLevel::Low,
+    };
+    safety.score();
+    Ok(safety)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::prelude::{Engine, BASE64_STANDARD};
+    use serde_json::{json, Value};
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::signature::Signature;
+    use spl_token::solana_program::program_pack::Pack;
+    use spl_token::state::Mint;
+    use std::collections::HashMap;
+
+    const CURRENT_SLOT: u64 = 300_000_000;
+
+    fn account(data: &[u8], owner: &Pubkey) -> Value {
+        json!({
+            "data": [BASE64_STANDARD.encode(data), "base64"],
+            "executable": false,
+            "lamports": 1_461_600,
+            "owner": owner.to_string(),
+            "rentEpoch": 0,
+            "space": data.len(),
+        })
+    }
+
+    fn token_account(owner: &Pubkey) -> Value {
+        let mut data = vec![0u8; 165];
+        data[32..64].copy_from_slice(owner.as_ref());
+        account(&data, &spl_token::id())
+    }
+
+    fn balance(address: &Pubkey, ui_amount: f64) -> Value {
+        json!({
+            "address": address.to_string(),
+            "amount": ((ui_amount * 1e6) as u64).to_string(),
+            "decimals": 6,
+            "uiAmount": ui_amount,
+            "uiAmountString": ui_amount.to_string(),
+        })
+    }
+
+    /// `holders` are (token account, its owner, ui amount) of a supply of
+    /// 1000 tokens, created `age_slots` ago
+    fn token_rpc(
+        authority: Option<Pubkey>,
+        holders: &[(Pubkey, Pubkey, f64)],
+        age_slots: u64,
+    ) -> RpcClient {
+        let mint = Mint {
+            mint_authority: authority.into(),
+            supply: 1_000_000_000,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: authority.into(),
+        };
+        let mut data = vec![0u8; Mint::LEN];
+        mint.pack_into_slice(&mut data);
+        let context = json!({ "slot": CURRENT_SLOT });
+
+        RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([
+                (
+                    RpcRequest::GetAccountInfo,
+                    json!({
+                        "context": context,
+                        "value": account(&data, &spl_token::id()),
+                    }),
+                ),
+                (
+                    RpcRequest::GetTokenLargestAccounts,
+                    json!({
+                        "context": context,
+                        "value": holders
+                            .iter()
+                            .map(|(address, _, amount)| {
+                                balance(address, *amount)
+                            })
+                            .collect::<Vec<_>>(),
+                    }),
+                ),
+                (
+                    RpcRequest::GetTokenSupply,
+                    json!({
+                        "context": context,
+                        "value": {
+                            "amount": "1000000000",
+                            "decimals": 6,
+                            "uiAmount": 1000.0,
+                            "uiAmountString": "1000",
+                        },
+                    }),
+                ),
+                (
+                    RpcRequest::GetMultipleAccounts,
+                    json!({
+                        "context": context,
+                        "value": holders
+                            .iter()
+                            .map(|(_, owner, _)| token_account(owner))
+                            .collect::<Vec<_>>(),
+                    }),
+                ),
+                (
+                    RpcRequest::GetSignaturesForAddress,
+                    json!([{
+                        "signature": Signature::default().to_string(),
+                        "slot": CURRENT_SLOT - age_slots,
+                        "err": null,
+                        "memo": null,
+                        "blockTime": null,
+                        "confirmationStatus": "finalized",
+                    }]),
+                ),
+                (RpcRequest::GetSlot, json!(CURRENT_SLOT)),
+            ]),
+        )
+    }
+
+    async fn raydium_server(
+        burn_percent: Option<f64>,
+    ) -> mockito::ServerGuard {
+        let mut server = mockito::Server::new_async().await;
+        let pools = match burn_percent {
+            Some(burn_percent) => json!([{ "burnPercent": burn_percent }]),
+            None => json!([]),
+        };
+        server
+            .mock("GET", "/pools/info/mint")
+            .match_query(mockito::Matcher::Any)
+            .with_body(
+                json!({
+                    "id": "id",
+                    "success": true,
+                    "data": {
+                        "count": 1,
+                        "data": pools,
+                        "hasNextPage": false,
+                    },
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn test_clean_token() {
+        let mint = Pubkey::new_unique();
+        let raydium = Pubkey::from_str(RAYDIUM_POOL_AUTHORITIES[0]).unwrap();
+        let holders = [
+            (Pubkey::new_unique(), raydium, 400.),
+            (Pubkey::new_unique(), Pubkey::new_unique(), 50.),
+            (Pubkey::new_unique(), Pubkey::new_unique(), 30.),
+        ];
+        // about a week old
+        let rpc_client = token_rpc(None, &holders, 1_500_000);
+        let server = raydium_server(Some(100.)).await;
+
+        let safety =
+            check_token_safety(&rpc_client, &server.url(), &mint.to_string())
+                .await
+                .unwrap();
+        assert_eq!(safety.mint_authority.status, FlagStatus::Pass);
+        assert_eq!(safety.freeze_authority.status, FlagStatus::Pass);
+        // the pool is left out of the concentration
+        assert_eq!(safety.top_holders.status, FlagStatus::Pass);
+        assert_eq!(
+            safety.top_holders.detail,
+            "the top holders own 8.0% of the supply"
+        );
+        assert_eq!(safety.largest_holder.status, FlagStatus::Pass);
+        assert_eq!(safety.lp_burned.status, FlagStatus::Pass);
+        assert_eq!(safety.token_age.status, FlagStatus::Pass);
+        assert_eq!(safety.risk_score, 0);
+        assert_eq!(safety.risk_level, RiskLevel::Low);
+    }
+
+    #[tokio::test]
+    async fn test_honeypot_token() {
+        let mint = Pubkey::new_unique();
+        let dev = Pubkey::new_unique();
+        let holders = [
+            (Pubkey::new_unique(), dev, 700.),
+            (Pubkey::new_unique(), Pubkey::new_unique(), 100.),
+        ];
+        // 10 minutes old
+        let rpc_client = token_rpc(Some(dev), &holders, 1_500);
+        let server = raydium_server(Some(0.)).await;
+
+        let safety =
+            check_token_safety(&rpc_client, &server.url(), &mint.to_string())
+                .await
+                .unwrap();
+        assert_eq!(safety.mint_authority.status, FlagStatus::Fail);
+        assert_eq!(safety.freeze_authority.status, FlagStatus::Fail);
+        assert_eq!(safety.top_holders.status, FlagStatus::Fail);
+        assert_eq!(safety.largest_holder.status, FlagStatus::Fail);
+        assert_eq!(safety.lp_burned.status, FlagStatus::Fail);
+        assert_eq!(safety.token_age.status, FlagStatus::Fail);
+        assert_eq!(safety.risk_score, 100);
+        assert_eq!(safety.risk_level, RiskLevel::High);
+    }
+
+    #[tokio::test]
+    async fn test_failing_rpc_is_unknown() {
+        let rpc_client = RpcClient::new_mock("fails".to_string());
+        let server = raydium_server(None).await;
+        let safety = check_token_safety(
+            &rpc_client,
+            &server.url(),
+            &Pubkey::new_unique().to_string(),
+        )
+        .await
+        .unwrap();
+        for (flag, _) in safety.weighted_flags() {
+            assert_eq!(flag.status, FlagStatus::Unknown, "{:?}", flag);
+        }
+        assert_eq!(safety.risk_score, 25);
+        assert_eq!(safety.risk_level, RiskLevel::Medium);
+    }
+}
@@ -0,0 +1,434 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use spl_token::solana_program::program_pack::Pack;
+use spl_token::state::Mint;
+use std::str::FromStr;
+
+use crate::solana::constants::WSOL;
+use crate::solana::jup::{JupiterClient, QuoteResponse};
+use crate::solana::pump::{
+    get_bonding_curve, get_pump_sol_amount, get_pump_token_amount,
+    mint_to_pump_accounts, BondingCurveLayout,
+};
+
+pub const PUMP_FUN_ROUTE: &str = "pump.fun";
+
+/// what a swap would yield right now, nothing is signed; amounts are in UI
+/// units
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SwapSimulation {
+    pub input_amount: f64,
+    pub expected_out: f64,
+    /// the least the swap yields at `slippage_bps`
+    pub minimum_received: f64,
+    /// of one input token in output tokens
+    pub price: f64,
+    /// in percent
+    pub price_impact: f64,
+    /// the labels of the hops, "pump.fun" for the bonding curve
+    pub route: Vec<String>,
+    pub slippage_bps: u16,
+}
+
+fn ui_amount(amount: u64, decimals: u8) -> f64 {
+    amount as f64 / 10f64.powi(decimals as i32)
+}
+
+/// of one input token in output tokens, an error for no input
+fn price(input_amount: f64, expected_out: f64) -> Result<f64> {
+    if input_amount == 0. {
+        return Err(anyhow!("the quote is for no input"));
+    }
+    Ok(expected_out / input_amount)
+}
+
+fn apply_slippage(amount: u64, slippage_bps: u16) -> u64 {
+    (amount as u128 * (10_000 - slippage_bps as u128) / 10_000) as u64
+}
+
+/// the decimals of both mints, in one request
+pub(crate) async fn fetch_decimals(
+    rpc_client: &RpcClient,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+) -> Result<(u8, u8)> {
+    let accounts = rpc_client
+        .get_multiple_accounts(&[*input_mint, *output_mint])
+        .await?;
+    let decimals = accounts
+        .into_iter()
+        .zip([input_mint, output_mint])
+        .map(|(account, mint)| {
+            let account =
+                account.ok_or_else(|| anyhow!("mint {} not found", mint))?;
+            let data = account
+                .data
+                .get(..Mint::LEN)
+                .ok_or_else(|| anyhow!("{} is not a mint", mint))?;
+            Ok(Mint::unpack_from_slice(data)?.decimals)
+        })
+        .collect::<Result<Vec<u8>>>()?;
+    match decimals[..] {
+        [input, output] => Ok((input, output)),
+        _ => Err(anyhow!("mint accounts missing")),
+    }
+}
+
+fn simulate_quote(
+    quote: &QuoteResponse,
+    slippage_bps: u16,
+    (input_decimals, output_decimals): (u8, u8),
+) -> Result<SwapSimulation> {
+    let input_amount = ui_amount(quote.in_amount.parse()?, input_decimals);
+    let expected_out = ui_amount(quote.out_amount.parse()?, output_decimals);
+    Ok(SwapSimulation {
+        input_amount,
+        expected_out,
+        price: price(input_amount, expected_out)?,
+        minimum_received: ui_amount(
+            quote.other_amount_threshold.parse()?,
+            output_decimals,
+        ),
+        price_impact: quote.price_impact_pct.parse::<f64>()? * 100.,
+        route: quote
+            .route_plan
+            .iter()
+            .map(|hop| {
+                hop.swap_info
+                    .label
+                    .clone()
+                    .unwrap_or_else(|| hop.swap_info.amm_key.clone())
+            })
+            .collect(),
+        slippage_bps,
+    })
+}
+
+/// buys with lamports or sells tokens into the bonding curve, the impact is
+/// against the spot price of the curve
+pub fn simulate_bonding_curve(
+    bonding_curve: &BondingCurveLayout,
+    buy: bool,
+    amount: u64,
+    slippage_bps: u16,
+    (input_decimals, output_decimals): (u8, u8),
+) -> Result<SwapSimulation> {
+    if bonding_curve.complete {
+        return Err(anyhow!(
+            "bonding curve is complete, the token trades on raydium now"
+        ));
+    }
+    let (sol, tokens) = (
+        bonding_curve.virtual_sol_reserves as f64,
+        bonding_curve.virtual_token_reserves as f64,
+    );
+    let (out, spot_out) = if buy {
+        (
+            get_pump_token_amount(
+                bonding_curve.virtual_sol_reserves,
+                bonding_curve.virtual_token_reserves,
+                Some(bonding_curve.real_token_reserves),
+                amount,
+            )?,
+            amount as f64 * tokens / sol,
+        )
+    } else {
+        (
+            get_pump_sol_amount(
+                bonding_curve.virtual_sol_reserves,
+                bonding_curve.virtual_token_reserves,
+                amount,
+            )?,
+            amount as f64 * sol / tokens,
+        )
+    };
+    let price_impact = if spot_out > 0. {
+        ((spot_out - out as f64) / spot_out * 100.).max(0.)
+    } else {
+        0.
+    };
+    let input_amount = ui_amount(amount, input_decimals);
+    let expected_out = ui_amount(out, output_decimals);
+    Ok(SwapSimulation {
+        input_amount,
+        expected_out,
+        price: price(input_amount, expected_out)?,
+        minimum_received: ui_amount(
+            apply_slippage(out, slippage_bps),
+            output_decimals,
+        ),
+        price_impact,
+        route: vec![PUMP_FUN_ROUTE.to_string()],
+        slippage_bps,
+    })
+}
+
+/// quotes the swap on jupiter, tokens jupiter has no route for that trade
+/// against SOL are simulated on their pump.fun bonding curve
+pub async fn simulate_swap(
+    jupiter: &JupiterClient,
+    rpc_client: &RpcClient,
+    input_mint: &str,
+    amount: u64,
+    output_mint: &str,
+    slippage_bps: u16,
+) -> Result<SwapSimulation> {
+    let decimals = fetch_decimals(
+        rpc_client,
+        &Pubkey::from_str(input_mint)?,
+        &Pubkey::from_str(output_mint)?,
+    )
+    .await?;
+    let jupiter_error = match jupiter
+        .fetch_quote_with_slippage(
+            input_mint,
+            output_mint,
+            amount,
+            Some(slippage_bps),
+        )
+        .await
+    {
+        Ok(quote) => return simulate_quote(&quote, slippage_bps, decimals),
+        Err(e) => e,
+    };
+    let (buy, pump_mint) = match (input_mint, output_mint) {
+        (WSOL, mint) => (true, mint),
+        (mint, WSOL) => (false, mint),
+        _ => return Err(jupiter_error),
+    };
+    let pump_accounts = mint_to_pump_accounts(&Pubkey::from_str(pump_mint)?);
+    let bonding_curve =
+        get_bonding_curve(rpc_client, pump_accounts.bonding_curve)
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "{}, and there is no pump.fun bonding curve: {}",
+                    jupiter_error,
+                    e
+                )
+            })?;
+    simulate_bonding_curve(
+        &bonding_curve,
+        buy,
+        amount,
+        slippage_bps,
+        decimals,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::prelude::{Engine, BASE64_STANDARD};
+    use serde_json::{json, Value};
+    use solana_client::rpc_request::RpcRequest;
+    use std::collections::HashMap;
+
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+    fn account(data: &[u8], owner: &Pubkey) -> Value {
+        json!({
+            "data": [BASE64_STANDARD.encode(data), "base64"],
+            "executable": false,
+            "lamports": 1_461_600,
+            "owner": owner.to_string(),
+            "rentEpoch": 0,
+            "space": data.len(),
+        })
+    }
+
+    fn mint(decimals: u8) -> Value {
+        let mint = Mint {
+            decimals,
+            is_initialized: true,
+            ..Default::default()
+        };
+        let mut data = vec![0u8; Mint::LEN];
+        mint.pack_into_slice(&mut data);
+        account(&data, &spl_token::id())
+    }
+
+    /// `bonding_curve` is served for the account info of the curve
+    fn mock_rpc(
+        decimals: (u8, u8),
+        bonding_curve: Option<&BondingCurveLayout>,
+    ) -> RpcClient {
+        let context = json!({ "slot": 1 });
+        let mut mocks = HashMap::from([(
+            RpcRequest::GetMultipleAccounts,
+            json!({
+                "context": context,
+                "value": [mint(decimals.0), mint(decimals.1)],
+            }),
+        )]);
+        if let Some(curve) = bonding_curve {
+            let mut data = Vec::with_capacity(49);
+            for field in [
+                curve.blob1,
+                curve.virtual_token_reserves,
+                curve.virtual_sol_reserves,
+                curve.real_token_reserves,
+                curve.real_sol_reserves,
+                curve.blob4,
+            ] {
+                data.extend_from_slice(&field.to_le_bytes());
+            }
+            data.push(curve.complete as u8);
+            mocks.insert(
+                RpcRequest::GetAccountInfo,
+                json!({
+                    "context": context,
+                    "value": account(&data, &Pubkey::new_unique()),
+                }),
+            );
+        }
+        RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks)
+    }
+
+    fn bonding_curve() -> BondingCurveLayout {
+        BondingCurveLayout {
+            blob1: 0,
+            virtual_token_reserves: 1_000_000_000_000_000,
+            virtual_sol_reserves: 30_000_000_000,
+            real_token_reserves: 800_000_000_000_000,
+            real_sol_reserves: 0,
+            blob4: 0,
+            complete: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simulate_swap_jupiter() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "slippageBps".into(),
+                "100".into(),
+            ))
+            .with_body(
+                json!({
+                    "inputMint": WSOL,
+                    "inAmount": "2000000000",
+                    "outputMint": USDC,
+                    "outAmount": "300000000",
+                    "otherAmountThreshold": "297000000",
+                    "swapMode": "ExactIn",
+                    "slippageBps": 100,
+                    "platformFee": null,
+                    "priceImpactPct": "0.0015",
+                    "routePlan": [{
+                        "swapInfo": {
+                            "ammKey": Pubkey::new_unique().to_string(),
+                            "label": "Whirlpool",
+                            "inputMint": WSOL,
+                            "outputMint": USDC,
+                            "inAmount": "2000000000",
+                            "outAmount": "300000000",
+                            "feeAmount": "0",
+                            "feeMint": WSOL,
+                        },
+                        "percent": 100,
+                    }],
+                    "contextSlot": 1,
+                    "timeTaken": 0.01,
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let simulation = simulate_swap(
+            &JupiterClient::new(&server.url()).with_referral(None),
+            &mock_rpc((9, 6), None),
+            WSOL,
+            2_000_000_000,
+            USDC,
+            100,
+        )
+        .await
+        .unwrap();
+        mock.assert_async().await;
+        assert_eq!(simulation.input_amount, 2.);
+        assert_eq!(simulation.expected_out, 300.);
+        assert_eq!(simulation.minimum_received, 297.);
+        // 150 USDC per SOL
+        assert_eq!(simulation.price, 150.);
+        assert!((simulation.price_impact - 0.15).abs() < 1e-9);
+        assert_eq!(simulation.route, vec!["Whirlpool".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_simulate_swap_bonding_curve() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::Any)
+            .with_status(400)
+            .with_body(r#"{"error":"Could not find any route"}"#)
+            .create_async()
+            .await;
+        let curve = bonding_curve();
+        let pump_mint = Pubkey::new_unique().to_string();
+
+        let simulation = simulate_swap(
+            &JupiterClient::new(&server.url()).with_referral(None),
+            &mock_rpc((9, 6), Some(&curve)),
+            WSOL,
+            1_000_000_000,
+            &pump_mint,
+            500,
+        )
+        .await
+        .unwrap();
+        mock.assert_async().await;
+        let expected = get_pump_token_amount(
+            curve.virtual_sol_reserves,
+            curve.virtual_token_reserves,
+            Some(curve.real_token_reserves),
+            1_000_000_000,
+        )
+        .unwrap();
+        assert_eq!(simulation.expected_out, ui_amount(expected, 6));
+        assert_eq!(
+            simulation.minimum_received,
+            ui_amount(expected * 95 / 100, 6)
+        );
+        // 1 SOL into 30 SOL of virtual reserves moves the price ~3.2%
+        assert!((simulation.price_impact - 3.2258).abs() < 1e-3);
+        assert_eq!(simulation.route, vec![PUMP_FUN_ROUTE.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_simulate_swap_no_route() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::Any)
+            .with_status(400)
+            .with_body(r#"{"error":"Could not find any route"}"#)
+            .create_async()
+            .await;
+
+        // neither side is SOL, there is no bonding curve to fall back to
+        assert!(simulate_swap(
+            &JupiterClient::new(&server.url()).with_referral(None),
+            &mock_rpc((6, 6), None),
+            USDC,
+            1_000_000,
+            &Pubkey::new_unique().to_string(),
+            50,
+        )
+        .await
+        .is_err());
+    }
+
+    #[test]
+    fn test_simulate_bonding_curve_complete() {
+        let mut curve = bonding_curve();
+        curve.complete = true;
+        assert!(simulate_bonding_curve(&curve, true, 1, 50, (9, 6)).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::transaction::{TransactionError, VersionedTransaction};
+use std::fmt;
+
+use crate::audit::{record_simulation, SimulationRecord};
+
+/// jupiter `SlippageToleranceExceeded`
+const JUPITER_SLIPPAGE_ERROR: u32 = 6001;
+/// pump.fun `TooMuchSolRequired` and `TooLittleSolReceived`
+const PUMP_SLIPPAGE_ERRORS: [u32; 2] = [6002, 6003];
+/// anchor `AccountNotInitialized`
+const ANCHOR_ACCOUNT_NOT_INITIALIZED: u32 = 3012;
+
+/// best-effort reason a simulation failed, for the user rather than the
+/// program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulationFailureCause {
+    InsufficientFunds,
+    Slippage,
+    AccountNotFound,
+    Unknown,
+}
+
+impl fmt::Display for SimulationFailureCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::InsufficientFunds => {
+                "insufficient funds for the amount and fees"
+            }
+            Self::Slippage => "price moved beyond the slippage tolerance",
+            Self::AccountNotFound => {
+                "an account the transaction needs does not exist"
+            }
+            Self::Unknown => "the transaction would fail on-chain",
+        })
+    }
+}
+
+/// a transaction that was not sent because its simulation failed
+#[derive(Debug, Clone)]
+pub struct SimulationError {
+    pub cause: SimulationFailureCause,
+    pub error: TransactionError,
+    pub logs: Vec<String>,
+    pub units_consumed: Option<u64>,
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Transaction simulation failed, not sent: {} ({})",
+            self.cause, self.error
+        )?;
+        if !self.logs.is_empty() {
+            write!(f, "\nProgram logs:\n{}", self.logs.join("\n"))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+pub fn classify_failure(
+    error: &TransactionError,
+    logs: &[String],
+) -> SimulationFailureCause {
+    match error {
+        TransactionError::InsufficientFundsForFee
+        | TransactionError::InsufficientFundsForRent { .. }
+        | TransactionError::InstructionError(
+            _,
+            InstructionError::InsufficientFunds,
+        ) => return SimulationFailureCause::InsufficientFunds,
+        TransactionError::AccountNotFound
+        | TransactionError::ProgramAccountNotFound
+        | TransactionError::InstructionError(
+            _,
+            InstructionError::UninitializedAccount,
+        ) => return SimulationFailureCause::AccountNotFound,
+        TransactionError::InstructionError(
+            _,
+            InstructionError::Custom(code),
+        ) => {
+            if *code == JUPITER_SLIPPAGE_ERROR
+                || PUMP_SLIPPAGE_ERRORS.contains(code)
+            {
+                return SimulationFailureCause::Slippage;
+            }
+            if *code == ANCHOR_ACCOUNT_NOT_INITIALIZED {
+                return SimulationFailureCause::AccountNotFound;
+            }
+        }
+        _ => {}
+    }
+
+    // the system and token programs only say it in the logs
+    let logs = logs.join("\n").to_lowercase();
+    if logs.contains("insufficient lamports")
+        || logs.contains("insufficient funds")
+    {
+        SimulationFailureCause::InsufficientFunds
+    } else if logs.contains("slippage") {
+        SimulationFailureCause::Slippage
+    } else if logs.contains("accountnotinitialized")
+        || logs.contains("account not found")
+    {
+        SimulationFailureCause::AccountNotFound
+    } else {
+        SimulationFailureCause::Unknown
+    }
+}
+
+/// simulates the transaction against the latest blockhash, signatures are
+/// not verified so this works before signing too; the outcome is recorded
+/// to the audit entry of the running tool call. Returns the compute units
+/// the transaction consumed, if the RPC reports them
+pub async fn simulate_transaction(
+    rpc_client: &RpcClient,
+    tx: &VersionedTransaction,
+) -> Result<Option<u64>> {
+    let simulation = rpc_client
+        .simulate_transaction_with_config(
+            tx,
+            RpcSimulateTransactionConfig {
+                sig_verify: false,
+                replace_recent_blockhash: true,
+                ..RpcSimulateTransactionConfig::default()
+            },
+        )
+        .await?
+        .value;
+    let logs = simulation.logs.unwrap_or_default();
+
+    record_simulation(SimulationRecord {
+        units_consumed: simulation.units_consumed,
+        logs: logs.clone(),
+        error: simulation.err.as_ref().map(|e| e.to_string()),
+    });
+
+    match simulation.err {
+        Some(error) => Err(SimulationError {
+            cause: classify_failure(&error, &logs),
+            error,
+            logs,
+            units_consumed: simulation.units_consumed,
+        }
+        .into()),
+        None => Ok(simulation.units_consumed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(code: u32) -> TransactionError {
+        TransactionError::InstructionError(2, InstructionError::Custom(code))
+    }
+
+    #[test]
+    fn test_classify_failure() {
+        assert_eq!(
+            classify_failure(&TransactionError::InsufficientFundsForFee, &[]),
+            SimulationFailureCause::InsufficientFunds
+        );
+        assert_eq!(
+            classify_failure(&custom(6001), &[]),
+            SimulationFailureCause::Slippage
+        );
+        assert_eq!(
+            classify_failure(&custom(6003), &[]),
+            SimulationFailureCause::Slippage
+        );
+        assert_eq!(
+            classify_failure(&TransactionError::AccountNotFound, &[]),
+            SimulationFailureCause::AccountNotFound
+        );
+        assert_eq!(
+            classify_failure(
+                &custom(1),
+                &["Transfer: insufficient lamports 10, need 20".to_string()]
+            ),
+            SimulationFailureCause::InsufficientFunds
+        );
+        assert_eq!(
+            classify_failure(&custom(42), &[]),
+            SimulationFailureCause::Unknown
+        );
+    }
+}
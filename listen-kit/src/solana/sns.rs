@@ -0,0 +1,177 @@
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::hashv;
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+/// the Solana Name Service program
+pub const NAME_PROGRAM_ID: Pubkey =
+    pubkey!("namesLPneVptA9Z5rqUDD9tMTWEJwofgaYwp8cawRkX");
+
+/// parent of every `.sol` domain
+pub const SOL_TLD_AUTHORITY: Pubkey =
+    pubkey!("58PwtjSDuFHuUkYjH9BYnnQKHfwo9reZhC2zMJv9JPkx");
+
+const HASH_PREFIX: &str = "SPL Name Service";
+
+/// parent, owner and class, followed by the record data
+const REGISTRY_HEADER_LEN: usize = 96;
+
+fn name_account(name: &str, parent: &Pubkey) -> Pubkey {
+    let hashed = hashv(&[HASH_PREFIX.as_bytes(), name.as_bytes()]);
+    Pubkey::find_program_address(
+        &[hashed.as_ref(), Pubkey::default().as_ref(), parent.as_ref()],
+        &NAME_PROGRAM_ID,
+    )
+    .0
+}
+
+/// the name account of `domain`, e.g. `bonfida.sol` or `dex.bonfida.sol`
+pub fn domain_key(domain: &str) -> Result<Pubkey> {
+    let name = domain.strip_suffix(".sol").unwrap_or(domain);
+    let labels = name.split('.').collect::<Vec<_>>();
+    if labels.iter().any(|label| label.is_empty()) {
+        return Err(anyhow!("Invalid .sol domain: {}", domain));
+    }
+    match labels[..] {
+        [domain] => Ok(name_account(domain, &SOL_TLD_AUTHORITY)),
+        // subdomains are hashed with a leading zero byte
+        [sub, domain] => Ok(name_account(
+            &format!("\0{}", sub),
+            &name_account(domain, &SOL_TLD_AUTHORITY),
+        )),
+        _ => Err(anyhow!("Invalid .sol domain: {}", domain)),
+    }
+}
+
+/// the wallet owning `domain`
+pub async fn resolve_domain(
+    rpc_client: &RpcClient,
+    domain: &str,
+) -> Result<Pubkey> {
+    let key = domain_key(domain)?;
+    let account = rpc_client
+        .get_account_with_commitment(&key, CommitmentConfig::confirmed())
+        .await?
+        .value
+        .ok_or_else(|| anyhow!("{} is not registered", domain))?;
+    if account.owner != NAME_PROGRAM_ID
+        || account.data.len() < REGISTRY_HEADER_LEN
+    {
+        return Err(anyhow!("{} is not a name account", key));
+    }
+    let owner = Pubkey::try_from(&account.data[32..64])?;
+    if owner == Pubkey::default() {
+        return Err(anyhow!("{} has no owner", domain));
+    }
+    // e.g. a tokenized domain, held in escrow by the tokenizer, funds sent
+    // there can't be recovered by the holder of the domain
+    if !owner.is_on_curve() {
+        return Err(anyhow!(
+            "{} is owned by a program account ({}), not a wallet",
+            domain,
+            owner
+        ));
+    }
+    Ok(owner)
+}
+
+/// `to` as an address, resolving it first if it is a `.sol` domain
+pub async fn resolve_recipient(
+    rpc_client: &RpcClient,
+    to: &str,
+) -> Result<Pubkey> {
+    if to.ends_with(".sol") {
+        return resolve_domain(rpc_client, to)
+            .await
+            .map_err(|e| anyhow!("Failed to resolve {}: {}", to, e));
+    }
+    Pubkey::from_str(to).map_err(|_| {
+        anyhow!("{} is neither a Solana address nor a .sol domain", to)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::prelude::{Engine, BASE64_STANDARD};
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use std::collections::HashMap;
+
+    /// an RPC serving a name account owned by `owner`
+    fn resolver_rpc(owner: &Pubkey) -> RpcClient {
+        let mut data = vec![0; REGISTRY_HEADER_LEN];
+        data[..32].copy_from_slice(SOL_TLD_AUTHORITY.as_ref());
+        data[32..64].copy_from_slice(owner.as_ref());
+        RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([(
+                RpcRequest::GetAccountInfo,
+                json!({
+                    "context": { "slot": 1 },
+                    "value": {
+                        "data": [BASE64_STANDARD.encode(data), "base64"],
+                        "executable": false,
+                        "lamports": 1_000_000,
+                        "owner": NAME_PROGRAM_ID.to_string(),
+                        "rentEpoch": 0,
+                        "space": REGISTRY_HEADER_LEN,
+                    }
+                }),
+            )]),
+        )
+    }
+
+    #[test]
+    fn test_domain_key() {
+        assert_eq!(
+            domain_key("bonfida.sol").unwrap(),
+            pubkey!("Crf8hzfthWGbGbLTVCiqRqV5MVnbpHB1L9KQMd6gsinb")
+        );
+        assert_eq!(
+            domain_key("bonfida").unwrap(),
+            domain_key("bonfida.sol").unwrap()
+        );
+        assert_ne!(
+            domain_key("dex.bonfida.sol").unwrap(),
+            domain_key("bonfida.sol").unwrap()
+        );
+        assert!(domain_key(".sol").is_err());
+        assert!(domain_key("a.b.c.sol").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_domain() {
+        let wallet = Keypair::new().pubkey();
+        let rpc_client = resolver_rpc(&wallet);
+        assert_eq!(
+            resolve_domain(&rpc_client, "bonfida.sol").await.unwrap(),
+            wallet
+        );
+        assert_eq!(
+            resolve_recipient(&rpc_client, "bonfida.sol").await.unwrap(),
+            wallet
+        );
+        // plain addresses don't hit the resolver
+        assert_eq!(
+            resolve_recipient(&rpc_client, &wallet.to_string())
+                .await
+                .unwrap(),
+            wallet
+        );
+        assert!(resolve_recipient(&rpc_client, "bonfida").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_domain_owned_by_program() {
+        let (escrow, _) =
+            Pubkey::find_program_address(&[b"nft_record"], &NAME_PROGRAM_ID);
+        let rpc_client = resolver_rpc(&escrow);
+        assert!(resolve_domain(&rpc_client, "bonfida.sol").await.is_err());
+    }
+}
@@ -0,0 +1,629 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcProgramAccountsConfig,
+};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::epoch_info::EpochInfo;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::native_token::lamports_to_sol;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::stake::instruction as stake_instruction;
+use solana_sdk::stake::state::{Authorized, Lockup, StakeStateV2};
+use solana_sdk::{stake, system_program, sysvar};
+use spl_associated_token_account::{
+    get_associated_token_address,
+    instruction::create_associated_token_account_idempotent,
+};
+use std::str::FromStr;
+
+pub const STAKE_POOL_PROGRAM_ID: &str =
+    "SPoo1Ku8WFXoNDMHPsrGSTSG1Y47rzgn41SLUNakuHy";
+pub const JITO_STAKE_POOL: &str =
+    "Jito4APyf642JPZPx3hGc6WWJ8zPKtRbRs4P815Awbb";
+pub const JITOSOL_MINT: &str = "J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn";
+
+/// `StakePoolInstruction` variants, borsh encodes them as the first byte
+const DEPOSIT_SOL: u8 = 14;
+const WITHDRAW_SOL: u8 = 16;
+/// where the withdrawer of a stake account starts: the state enum(4)
+/// rent_exempt_reserve(8) staker(32)
+const STAKE_WITHDRAWER_OFFSET: usize = 44;
+/// the target slot time, epochs run a little longer in practice
+const SLOT_MILLIS: u64 = 400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StakeState {
+    /// created but never delegated
+    Initialized,
+    Activating,
+    Active,
+    Deactivating,
+    /// withdrawable
+    Inactive,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StakeAccount {
+    pub stake_account: String,
+    pub state: StakeState,
+    pub lamports: u64,
+    /// rewards included, none when undelegated
+    pub delegated_lamports: Option<u64>,
+    pub validator_vote_account: Option<String>,
+    /// none when it earned nothing last epoch
+    pub last_epoch_reward_lamports: Option<u64>,
+}
+
+/// the instructions of a stake tool and what they do, epoch timing
+/// included
+#[derive(Debug, Clone)]
+pub struct StakePlan {
+    pub instructions: Vec<Instruction>,
+    pub summary: String,
+}
+
+/// the state of a stake account in `epoch`, stake activates and
+/// deactivates at the end of the epoch it was (de)delegated in
+pub fn stake_state(state: &StakeStateV2, epoch: u64) -> Result<StakeState> {
+    match state {
+        StakeStateV2::Initialized(_) => Ok(StakeState::Initialized),
+        StakeStateV2::Stake(_, stake, _) => {
+            let delegation = &stake.delegation;
+            Ok(if delegation.deactivation_epoch != u64::MAX {
+                if delegation.deactivation_epoch < epoch {
+                    StakeState::Inactive
+                } else {
+                    StakeState::Deactivating
+                }
+            } else if delegation.activation_epoch < epoch {
+                StakeState::Active
+            } else {
+                StakeState::Activating
+            })
+        }
+        _ => Err(anyhow!("not an initialized stake account")),
+    }
+}
+
+/// e.g. "epoch 700 ends in about 1h 20m"
+pub fn describe_epoch(epoch_info: &EpochInfo) -> String {
+    let slots_left = epoch_info
+        .slots_in_epoch
+        .saturating_sub(epoch_info.slot_index);
+    let minutes = slots_left * SLOT_MILLIS / 60_000;
+    format!(
+        "epoch {} ends in about {}h {}m",
+        epoch_info.epoch,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+pub fn stake_account_address(owner: &Pubkey, seed: &str) -> Result<Pubkey> {
+    Ok(Pubkey::create_with_seed(
+        owner,
+        seed,
+        &stake::program::id(),
+    )?)
+}
+
+/// creates a stake account derived from `owner` and `seed`, so that only
+/// `owner` signs, and delegates `lamports` of it to `vote_account`
+pub async fn plan_stake(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    vote_account: &Pubkey,
+    lamports: u64,
+    seed: &str,
+) -> Result<StakePlan> {
+    let rent = rpc_client
+        .get_minimum_balance_for_rent_exemption(StakeStateV2::size_of())
+        .await?;
+    if lamports <= rent {
+        return Err(anyhow!(
+            "staking needs more than the {} SOL a stake account keeps for \
+            rent",
+            lamports_to_sol(rent)
+        ));
+    }
+    let epoch_info = rpc_client.get_epoch_info().await?;
+    let stake_account = stake_account_address(owner, seed)?;
+    Ok(StakePlan {
+        instructions:
+            stake_instruction::create_account_with_seed_and_delegate_stake(
+                owner,
+                &stake_account,
+                owner,
+                seed,
+                vote_account,
+                &Authorized::auto(owner),
+                &Lockup::default(),
+                lamports,
+            ),
+        summary: format!(
+            "staked {} SOL with {} in stake account {}, it activates and \
+            starts earning when {}",
+            lamports_to_sol(lamports),
+            vote_account,
+            stake_account,
+            describe_epoch(&epoch_info)
+        ),
+    })
+}
+
+/// deactivates a delegated stake account, or withdraws the whole balance
+/// of one that is no longer delegated, which closes it
+pub async fn plan_unstake(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    stake_account: &Pubkey,
+) -> Result<StakePlan> {
+    let account = rpc_client.get_account(stake_account).await?;
+    if account.owner != stake::program::id() {
+        return Err(anyhow!("{} is not a stake account", stake_account));
+    }
+    let state: StakeStateV2 = bincode::deserialize(&account.data)?;
+    let withdrawer = state
+        .authorized()
+        .ok_or_else(|| anyhow!("{} is not initialized", stake_account))?
+        .withdrawer;
+    if withdrawer != *owner {
+        return Err(anyhow!(
+            "{} can only be unstaked by {}",
+            stake_account,
+            withdrawer
+        ));
+    }
+    let epoch_info = rpc_client.get_epoch_info().await?;
+    match stake_state(&state, epoch_info.epoch)? {
+        StakeState::Activating | StakeState::Active => Ok(StakePlan {
+            instructions: vec![stake_instruction::deactivate_stake(
+                stake_account,
+                owner,
+            )],
+            summary: format!(
+                "deactivating stake account {}, it stops earning and can \
+                be withdrawn with unstake once {}",
+                stake_account,
+                describe_epoch(&epoch_info)
+            ),
+        }),
+        StakeState::Deactivating => Err(anyhow!(
+            "{} is still deactivating, unstake it again to withdraw once {}",
+            stake_account,
+            describe_epoch(&epoch_info)
+        )),
+        StakeState::Initialized | StakeState::Inactive => Ok(StakePlan {
+            instructions: vec![stake_instruction::withdraw(
+                stake_account,
+                owner,
+                owner,
+                account.lamports,
+                None,
+            )],
+            summary: format!(
+                "withdrew {} SOL from stake account {} to the wallet",
+                lamports_to_sol(account.lamports),
+                stake_account
+            ),
+        }),
+    }
+}
+
+/// the stake accounts `owner` can withdraw from, oldest delegation first
+pub async fn list_stake_accounts(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Vec<StakeAccount>> {
+    let accounts = rpc_client
+        .get_program_accounts_with_config(
+            &stake::program::id(),
+            RpcProgramAccountsConfig {
+                filters: Some(vec![RpcFilterType::Memcmp(
+                    Memcmp::new_raw_bytes(
+                        STAKE_WITHDRAWER_OFFSET,
+                        owner.to_bytes().to_vec(),
+                    ),
+                )]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await?;
+    if accounts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let epoch = rpc_client.get_epoch_info().await?.epoch;
+    let addresses = accounts
+        .iter()
+        .map(|(address, _)| *address)
+        .collect::<Vec<_>>();
+    // rewards are nice to have, the rpc may not keep the last epoch's
+    let rewards = rpc_client
+        .get_inflation_reward(&addresses, None)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!(%e, "no stake rewards");
+            vec![None; addresses.len()]
+        });
+    let mut stake_accounts = Vec::new();
+    for ((address, account), reward) in accounts.iter().zip(rewards) {
+        let state: StakeStateV2 = bincode::deserialize(&account.data)?;
+        let delegation = state.delegation();
+        stake_accounts.push((
+            delegation.map_or(u64::MAX, |d| d.activation_epoch),
+            StakeAccount {
+                stake_account: address.to_string(),
+                state: stake_state(&state, epoch)?,
+                lamports: account.lamports,
+                delegated_lamports: delegation.map(|d| d.stake),
+                validator_vote_account: delegation
+                    .map(|d| d.voter_pubkey.to_string()),
+                last_epoch_reward_lamports: reward.map(|r| r.amount),
+            },
+        ));
+    }
+    stake_accounts.sort_by_key(|(activation_epoch, _)| *activation_epoch);
+    Ok(stake_accounts
+        .into_iter()
+        .map(|(_, account)| account)
+        .collect())
+}
+
+/// the accounts of an spl stake pool that deposits and withdrawals go
+/// through
+#[derive(Debug, Clone, PartialEq)]
+pub struct StakePool {
+    pub address: Pubkey,
+    pub reserve_stake: Pubkey,
+    pub pool_mint: Pubkey,
+    pub manager_fee_account: Pubkey,
+}
+
+impl StakePool {
+    /// `StakePool` layout: account_type(1) manager(32) staker(32)
+    /// stake_deposit_authority(32) stake_withdraw_bump_seed(1)
+    /// validator_list(32) reserve_stake(32) pool_mint(32)
+    /// manager_fee_account(32)
+    pub fn from_account_data(address: Pubkey, data: &[u8]) -> Result<Self> {
+        let pubkey = |offset: usize| -> Result<Pubkey> {
+            Ok(Pubkey::try_from(
+                data.get(offset..offset + 32)
+                    .ok_or_else(|| anyhow!("stake pool account too short"))?,
+            )?)
+        };
+        Ok(Self {
+            address,
+            reserve_stake: pubkey(130)?,
+            pool_mint: pubkey(162)?,
+            manager_fee_account: pubkey(194)?,
+        })
+    }
+
+    pub async fn fetch(
+        rpc_client: &RpcClient,
+        address: &str,
+    ) -> Result<Self> {
+        let address = Pubkey::from_str(address)?;
+        let account = rpc_client.get_account(&address).await?;
+        Self::from_account_data(address, &account.data)
+    }
+
+    fn program_id() -> Pubkey {
+        Pubkey::from_str(STAKE_POOL_PROGRAM_ID).unwrap()
+    }
+
+    fn withdraw_authority(&self) -> Pubkey {
+        Pubkey::find_program_address(
+            &[self.address.as_ref(), b"withdraw"],
+            &Self::program_id(),
+        )
+        .0
+    }
+
+    /// stakes `lamports` of `owner` into the pool for its liquid staking
+    /// token
+    pub fn deposit_sol_ixs(
+        &self,
+        owner: &Pubkey,
+        lamports: u64,
+    ) -> Vec<Instruction> {
+        let pool_tokens =
+            get_associated_token_address(owner, &self.pool_mint);
+        let mut data = vec![DEPOSIT_SOL];
+        data.extend(lamports.to_le_bytes());
+        vec![
+            create_associated_token_account_idempotent(
+                owner,
+                owner,
+                &self.pool_mint,
+                &spl_token::id(),
+            ),
+            Instruction::new_with_bytes(
+                Self::program_id(),
+                &data,
+                vec![
+                    AccountMeta::new(self.address, false),
+                    AccountMeta::new_readonly(
+                        self.withdraw_authority(),
+                        false,
+                    ),
+                    AccountMeta::new(self.reserve_stake, false),
+                    AccountMeta::new(*owner, true),
+                    AccountMeta::new(pool_tokens, false),
+                    AccountMeta::new(self.manager_fee_account, false),
+                    // no referrer, the fee goes back to the depositor
+                    AccountMeta::new(pool_tokens, false),
+                    AccountMeta::new(self.pool_mint, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(spl_token::id(), false),
+                ],
+            ),
+        ]
+    }
+
+    /// burns `pool_tokens` of `owner` for SOL from the reserve of the pool
+    pub fn withdraw_sol_ix(
+        &self,
+        owner: &Pubkey,
+        pool_tokens: u64,
+    ) -> Instruction {
+        let mut data = vec![WITHDRAW_SOL];
+        data.extend(pool_tokens.to_le_bytes());
+        Instruction::new_with_bytes(
+            Self::program_id(),
+            &data,
+            vec![
+                AccountMeta::new(self.address, false),
+                AccountMeta::new_readonly(self.withdraw_authority(), false),
+                AccountMeta::new_readonly(*owner, true),
+                AccountMeta::new(
+                    get_associated_token_address(owner, &self.pool_mint),
+                    false,
+                ),
+                AccountMeta::new(self.reserve_stake, false),
+                AccountMeta::new(*owner, false),
+                AccountMeta::new(self.manager_fee_account, false),
+                AccountMeta::new(self.pool_mint, false),
+                AccountMeta::new_readonly(sysvar::clock::id(), false),
+                AccountMeta::new_readonly(sysvar::stake_history::id(), false),
+                AccountMeta::new_readonly(stake::program::id(), false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ],
+        )
+    }
+}
+
+/// stakes `lamports` into the jito stake pool for jitoSOL
+pub async fn plan_liquid_stake(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    lamports: u64,
+) -> Result<StakePlan> {
+    let pool = StakePool::fetch(rpc_client, JITO_STAKE_POOL).await?;
+    Ok(StakePlan {
+        instructions: pool.deposit_sol_ixs(owner, lamports),
+        summary: format!(
+            "staked {} SOL for jitoSOL ({}), it earns from now on and can be \
+            unstaked or swapped back any time",
+            lamports_to_sol(lamports),
+            JITOSOL_MINT
+        ),
+    })
+}
+
+/// redeems the whole jitoSOL balance of `owner` for SOL
+pub async fn plan_liquid_unstake(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<StakePlan> {
+    let pool = StakePool::fetch(rpc_client, JITO_STAKE_POOL).await?;
+    let balance = rpc_client
+        .get_token_account_balance(&get_associated_token_address(
+            owner,
+            &pool.pool_mint,
+        ))
+        .await
+        .map_err(|_| anyhow!("{} holds no jitoSOL", owner))?;
+    let pool_tokens = balance.amount.parse::<u64>()?;
+    if pool_tokens == 0 {
+        return Err(anyhow!("{} holds no jitoSOL", owner));
+    }
+    Ok(StakePlan {
+        instructions: vec![pool.withdraw_sol_ix(owner, pool_tokens)],
+        summary: format!(
+            "unstaked {} jitoSOL for SOL from the pool reserve, if the \
+            reserve runs short swap jitoSOL to SOL instead",
+            balance.ui_amount_string
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::prelude::{Engine, BASE64_STANDARD};
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::stake::stake_flags::StakeFlags;
+    use solana_sdk::stake::state::{Delegation, Meta, Stake};
+    use std::collections::HashMap;
+
+    fn epoch_info() -> serde_json::Value {
+        // 216000 of 432000 slots left is 24 hours
+        json!({
+            "absoluteSlot": 302_616_000u64,
+            "blockHeight": 280_000_000u64,
+            "epoch": 700,
+            "slotIndex": 216_000,
+            "slotsInEpoch": 432_000,
+            "transactionCount": null,
+        })
+    }
+
+    fn stake_rpc(
+        owner: &Pubkey,
+        activation_epoch: u64,
+        deactivation_epoch: u64,
+    ) -> RpcClient {
+        let state = StakeStateV2::Stake(
+            Meta {
+                rent_exempt_reserve: 2_282_880,
+                authorized: Authorized::auto(owner),
+                lockup: Lockup::default(),
+            },
+            Stake {
+                delegation: Delegation {
+                    voter_pubkey: Pubkey::new_unique(),
+                    stake: 1_000_000_000,
+                    activation_epoch,
+                    deactivation_epoch,
+                    ..Default::default()
+                },
+                credits_observed: 0,
+            },
+            StakeFlags::empty(),
+        );
+        let mut data = bincode::serialize(&state).unwrap();
+        data.resize(StakeStateV2::size_of(), 0);
+        RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([
+                (RpcRequest::GetEpochInfo, epoch_info()),
+                (
+                    RpcRequest::GetAccountInfo,
+                    json!({
+                        "context": { "slot": 1 },
+                        "value": {
+                            "data": [BASE64_STANDARD.encode(&data), "base64"],
+                            "executable": false,
+                            "lamports": 1_002_282_880u64,
+                            "owner": stake::program::id().to_string(),
+                            "rentEpoch": 0,
+                            "space": data.len(),
+                        }
+                    }),
+                ),
+                (
+                    RpcRequest::GetMinimumBalanceForRentExemption,
+                    json!(2_282_880),
+                ),
+            ]),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_plan_stake() {
+        let (owner, vote) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let rpc_client = stake_rpc(&owner, 0, u64::MAX);
+        let plan =
+            plan_stake(&rpc_client, &owner, &vote, 1_000_000_000, "stake:1")
+                .await
+                .unwrap();
+        let stake_account = stake_account_address(&owner, "stake:1").unwrap();
+        let [create, initialize, delegate] = &plan.instructions[..] else {
+            panic!("expected create, initialize and delegate");
+        };
+        assert_eq!(create.program_id, system_program::id());
+        assert_eq!(create.accounts[1].pubkey, stake_account);
+        assert_eq!(initialize.program_id, stake::program::id());
+        // `StakeInstruction::DelegateStake`, a u32 variant index
+        assert_eq!(delegate.data, [2, 0, 0, 0]);
+        assert_eq!(delegate.accounts[0].pubkey, stake_account);
+        assert_eq!(delegate.accounts[1].pubkey, vote);
+        assert_eq!(delegate.accounts[5].pubkey, owner);
+        assert!(delegate.accounts[5].is_signer);
+        assert!(plan.summary.contains("epoch 700 ends in about 24h 0m"));
+
+        // the account would only hold its rent
+        assert!(plan_stake(&rpc_client, &owner, &vote, 2_282_880, "stake:2")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_plan_unstake() {
+        let (owner, stake_account) =
+            (Pubkey::new_unique(), Pubkey::new_unique());
+
+        // active, so it is deactivated
+        let plan = plan_unstake(
+            &stake_rpc(&owner, 650, u64::MAX),
+            &owner,
+            &stake_account,
+        )
+        .await
+        .unwrap();
+        let [deactivate] = &plan.instructions[..] else {
+            panic!("expected a deactivation");
+        };
+        assert_eq!(deactivate.program_id, stake::program::id());
+        // `StakeInstruction::Deactivate`
+        assert_eq!(deactivate.data, [5, 0, 0, 0]);
+        assert_eq!(deactivate.accounts[0].pubkey, stake_account);
+        assert_eq!(deactivate.accounts[2].pubkey, owner);
+        assert!(plan.summary.contains("epoch 700 ends in about 24h 0m"));
+
+        // deactivated this epoch, not withdrawable until the next
+        assert!(plan_unstake(
+            &stake_rpc(&owner, 650, 700),
+            &owner,
+            &stake_account
+        )
+        .await
+        .is_err());
+
+        // deactivated last epoch, so the whole balance is withdrawn
+        let plan = plan_unstake(
+            &stake_rpc(&owner, 650, 699),
+            &owner,
+            &stake_account,
+        )
+        .await
+        .unwrap();
+        let [withdraw] = &plan.instructions[..] else {
+            panic!("expected a withdrawal");
+        };
+        // `StakeInstruction::Withdraw(lamports)`
+        let mut data = vec![4, 0, 0, 0];
+        data.extend(1_002_282_880u64.to_le_bytes());
+        assert_eq!(withdraw.data, data);
+
+        // only the withdrawer can unstake
+        let other = Pubkey::new_unique();
+        assert!(plan_unstake(
+            &stake_rpc(&other, 650, u64::MAX),
+            &owner,
+            &stake_account
+        )
+        .await
+        .is_err());
+    }
+
+    #[test]
+    fn test_deposit_sol_ixs() {
+        let owner = Pubkey::new_unique();
+        let pool = StakePool {
+            address: Pubkey::from_str(JITO_STAKE_POOL).unwrap(),
+            reserve_stake: Pubkey::new_unique(),
+            pool_mint: Pubkey::from_str(JITOSOL_MINT).unwrap(),
+            manager_fee_account: Pubkey::new_unique(),
+        };
+        let [_, deposit] = &pool.deposit_sol_ixs(&owner, 1_000_000_000)[..]
+        else {
+            panic!("expected the ata and the deposit");
+        };
+        let mut data = vec![DEPOSIT_SOL];
+        data.extend(1_000_000_000u64.to_le_bytes());
+        assert_eq!(deposit.data, data);
+        assert_eq!(deposit.accounts[3].pubkey, owner);
+        assert!(deposit.accounts[3].is_signer);
+    }
+}
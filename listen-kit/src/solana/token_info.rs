@@ -0,0 +1,264 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use spl_token::solana_program::program_pack::Pack;
+use spl_token::state::Mint;
+use std::str::FromStr;
+
+use crate::solana::deploy_token::derive_metadata_account;
+
+/// signatures are paged from the newest, tokens with a longer history than
+/// this many pages get no creation slot
+const MAX_SIGNATURE_PAGES: usize = 5;
+const SIGNATURES_PER_PAGE: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenAuthorities {
+    /// an active mint authority can inflate the supply at will
+    pub mint_authority: Option<String>,
+    /// an active freeze authority can block holders from selling
+    pub freeze_authority: Option<String>,
+    /// whether the metaplex metadata can still be changed, false if the
+    /// token has no metadata account
+    pub is_mutable: bool,
+    /// slot of the earliest transaction touching the mint
+    pub created_slot: Option<u64>,
+}
+
+/// mint and freeze authority of a mint account, token-2022 mints share the
+/// base layout and carry their extensions after it
+pub fn parse_mint_authorities(
+    data: &[u8],
+) -> Result<(Option<String>, Option<String>)> {
+    let mint = Mint::unpack_from_slice(
+        data.get(..Mint::LEN)
+            .ok_or_else(|| anyhow!("mint account data too short"))?,
+    )?;
+    Ok((
+        Option::from(mint.mint_authority).map(|a: Pubkey| a.to_string()),
+        Option::from(mint.freeze_authority).map(|a: Pubkey| a.to_string()),
+    ))
+}
+
+fn skip_string(data: &[u8], offset: usize) -> Result<usize> {
+    let len = u32::from_le_bytes(
+        data.get(offset..offset + 4)
+            .ok_or_else(|| anyhow!("metadata account data too short"))?
+            .try_into()?,
+    );
+    Ok(offset + 4 + len as usize)
+}
+
+fn read_string(data: &[u8], offset: usize) -> Result<&str> {
+    let end = skip_string(data, offset)?;
+    let bytes = data
+        .get(offset + 4..end)
+        .ok_or_else(|| anyhow!("metadata account data too short"))?;
+    Ok(std::str::from_utf8(bytes)?)
+}
+
+/// the symbol of a metaplex metadata account, without the padding
+pub fn parse_metadata_symbol(data: &[u8]) -> Result<String> {
+    // key, update authority, mint, name
+    let offset = skip_string(data, 1 + 32 + 32)?;
+    Ok(read_string(data, offset)?
+        .trim_end_matches('\0')
+        .trim()
+        .to_string())
+}
+
+/// `is_mutable` of a metaplex metadata account, which follows the
+/// variable length name, symbol, uri and creators
+pub fn parse_metadata_is_mutable(data: &[u8]) -> Result<bool> {
+    // key, update authority, mint
+    let mut offset = 1 + 32 + 32;
+    for _ in 0..3 {
+        offset = skip_string(data, offset)?;
+    }
+    // seller fee basis points
+    offset += 2;
+    let too_short = || anyhow!("metadata account data too short");
+    if *data.get(offset).ok_or_else(too_short)? == 1 {
+        let creators = u32::from_le_bytes(
+            data.get(offset + 1..offset + 5)
+                .ok_or_else(too_short)?
+                .try_into()?,
+        );
+        // address, verified, share
+        offset += 4 + creators as usize * 34;
+    }
+    // creators option, primary sale happened
+    offset += 2;
+    Ok(*data.get(offset).ok_or_else(too_short)? == 1)
+}
+
+pub(crate) async fn get_created_slot(
+    rpc_client: &RpcClient,
+    mint: &Pubkey,
+) -> Result<Option<u64>> {
+    let mut before: Option<Signature> = None;
+    for _ in 0..MAX_SIGNATURE_PAGES {
+        let signatures = rpc_client
+            .get_signatures_for_address_with_config(
+                mint,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until: None,
+                    limit: Some(SIGNATURES_PER_PAGE),
+                    commitment: None,
+                },
+            )
+            .await?;
+        let Some(oldest) = signatures.last() else {
+            return Ok(None);
+        };
+        if signatures.len() < SIGNATURES_PER_PAGE {
+            return Ok(Some(oldest.slot));
+        }
+        before = Some(Signature::from_str(&oldest.signature)?);
+    }
+    Ok(None)
+}
+
+pub async fn get_token_authorities(
+    rpc_client: &RpcClient,
+    mint: &str,
+) -> Result<TokenAuthorities> {
+    let mint = Pubkey::from_str(mint)?;
+    let account = rpc_client.get_account(&mint).await?;
+    let (mint_authority, freeze_authority) =
+        parse_mint_authorities(&account.data)?;
+
+    let is_mutable = match rpc_client
+        .get_account_with_commitment(
+            &derive_metadata_account(&mint),
+            rpc_client.commitment(),
+        )
+        .await?
+        .value
+    {
+        Some(metadata) => parse_metadata_is_mutable(&metadata.data)?,
+        None => false,
+    };
+
+    let created_slot = match get_created_slot(rpc_client, &mint).await {
+        Ok(slot) => slot,
+        Err(e) => {
+            tracing::warn!(?e, "failed to fetch token creation slot");
+            None
+        }
+    };
+
+    Ok(TokenAuthorities {
+        mint_authority,
+        freeze_authority,
+        is_mutable,
+        created_slot,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mint_data(
+        mint_authority: Option<Pubkey>,
+        freeze_authority: Option<Pubkey>,
+    ) -> Vec<u8> {
+        let mint = Mint {
+            mint_authority: mint_authority.into(),
+            supply: 1_000_000_000,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: freeze_authority.into(),
+        };
+        let mut data = vec![0u8; Mint::LEN];
+        mint.pack_into_slice(&mut data);
+        data
+    }
+
+    #[test]
+    fn test_parse_mint_authorities() {
+        let (mint_authority, freeze_authority) =
+            (Pubkey::new_unique(), Pubkey::new_unique());
+        let data = mint_data(Some(mint_authority), Some(freeze_authority));
+        assert_eq!(
+            parse_mint_authorities(&data).unwrap(),
+            (
+                Some(mint_authority.to_string()),
+                Some(freeze_authority.to_string())
+            )
+        );
+
+        // renounced, with trailing token-2022 extension data
+        let mut data = mint_data(None, None);
+        data.extend_from_slice(&[0u8; 83]);
+        assert_eq!(parse_mint_authorities(&data).unwrap(), (None, None));
+
+        assert!(parse_mint_authorities(&data[..40]).is_err());
+    }
+
+    fn metadata_data(creators: usize, is_mutable: bool) -> Vec<u8> {
+        let mut data = vec![4u8];
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        for s in ["name", "SYM", "https://example.com/meta.json"] {
+            data.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            data.extend_from_slice(s.as_bytes());
+        }
+        data.extend_from_slice(&500u16.to_le_bytes());
+        if creators > 0 {
+            data.push(1);
+            data.extend_from_slice(&(creators as u32).to_le_bytes());
+            data.extend_from_slice(&vec![0u8; creators * 34]);
+        } else {
+            data.push(0);
+        }
+        data.push(1);
+        data.push(is_mutable as u8);
+        data
+    }
+
+    #[test]
+    fn test_parse_metadata_is_mutable() {
+        let is_mutable = |creators, mutable| {
+            parse_metadata_is_mutable(&metadata_data(creators, mutable))
+                .unwrap()
+        };
+        assert!(is_mutable(0, true));
+        assert!(!is_mutable(2, false));
+        assert!(is_mutable(2, true));
+        assert!(parse_metadata_is_mutable(&[4u8; 40]).is_err());
+    }
+
+    #[test]
+    fn test_parse_metadata_symbol() {
+        let data = metadata_data(0, true);
+        assert_eq!(parse_metadata_symbol(&data).unwrap(), "SYM");
+        // older metadata pads the symbol to 10 bytes with NULs
+        let mut data = vec![4u8; 65];
+        for s in ["name", "SYM\0\0\0\0\0\0\0"] {
+            data.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            data.extend_from_slice(s.as_bytes());
+        }
+        assert_eq!(parse_metadata_symbol(&data).unwrap(), "SYM");
+        assert!(parse_metadata_symbol(&data[..70]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_token_authorities_usdc() {
+        let rpc_client =
+            RpcClient::new("https://api.mainnet-beta.solana.com".to_string());
+        let authorities = get_token_authorities(
+            &rpc_client,
+            "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+        )
+        .await
+        .unwrap();
+        assert!(authorities.mint_authority.is_some());
+        assert!(authorities.freeze_authority.is_some());
+    }
+}
@@ -8,19 +8,73 @@ use once_cell::sync::Lazy;
 use reqwest::Client;
 use rig_tool_macro::tool;
 use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_sdk::native_token::sol_to_lamports;
+use solana_sdk::native_token::lamports_to_sol;
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::Transaction;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
+use crate::chain_config::chain_config;
 use crate::common::wrap_unsafe;
+use crate::data::API_URL;
 use crate::solana::data::PortfolioItem;
 
+use super::amount::{
+    parse_amount, parse_explicit_sol_amount, parse_mint_amount, SOL_DECIMALS,
+};
+use super::balance::TokenBalance;
+use super::burn::{plan_burn, ui_amount, BurnAmount, BurnReport};
+use super::close_accounts::{
+    fetch_empty_token_accounts, plan_close_accounts, CloseAccountsReport,
+};
+use super::cnft::{create_transfer_cnft_tx, DasClient};
+use super::constants::WSOL;
 use super::data::holdings_to_portfolio;
-use super::deploy_token::create_deploy_token_tx;
-use super::trade::create_jupiter_swap_transaction;
+use super::dca::{
+    create_close_dca_tx, create_open_dca_tx, dca_address, fetch_dca_position,
+    fetch_dca_positions, DcaPosition, DcaSchedule,
+};
+use super::deploy_token::{create_deploy_token_tx, generate_mint};
+use super::fee_estimate::{self, FeeEstimate, FeeOperation};
+use super::governance::GovernanceProposal;
+use super::history::TransactionSummary;
+use super::holders::{self, TopHoldersReport};
+use super::jup::{JupiterClient, QuoteResponse};
+use super::kamino::{
+    self, create_kamino_tx, KaminoAction, KaminoObligation,
+    KaminoReserveInfo, KAMINO_MAIN_MARKET,
+};
+use super::limit_order::{
+    min_output_amount, LimitOrder, LimitOrderClient, TrackedOrder,
+    ORDER_STORE,
+};
+use super::liquidate::{positions_to_sell, SellAllReport};
+use super::nft::{MagicEdenClient, NftFloorReport};
+use super::nonce::{
+    create_close_nonce_account_tx, create_nonce_account_tx, fetch_nonce,
+    nonce_account_address,
+};
+use super::pool::PoolInfo;
+use super::priority_fee::parse_priority;
+use super::reserve::{is_max_amount, max_spendable_sol, sol_reserve};
+use super::routes::{self, RouteQuote, Venue, DEFAULT_COMPARE_SLIPPAGE_BPS};
+use super::safety::{TokenSafety, RAYDIUM_POOLS_API_URL};
+use super::simulate::{self, fetch_decimals, SwapSimulation};
+use super::sns::{resolve_domain, resolve_recipient};
+use super::stake::{self, StakeAccount, StakePlan};
+use super::token_info::TokenAuthorities;
+use super::trade::{
+    create_jupiter_swap_transaction,
+    create_jupiter_swap_transaction_from_quote, default_slippage_bps,
+    quote_jupiter_exact_out, validate_slippage_bps, SMALL_SWAP_SLIPPAGE_BPS,
+};
 use super::trade_pump::{create_buy_pump_fun_tx, create_sell_pump_fun_tx};
 use super::transfer::{create_transfer_sol_tx, create_transfer_spl_tx};
-use super::util::execute_solana_transaction;
+use super::util::{
+    execute_solana_transaction, execute_solana_transaction_with_co_signers,
+    execute_solana_transaction_with_options, nothing_landed, ExecuteOptions,
+};
+use super::wsol::{plan_unwrap_sol, wrap_sol_ixs};
 use crate::signer::SignerContext;
 
 static SOLANA_RPC_URL: Lazy<String> = Lazy::new(|| {
@@ -28,10 +82,24 @@ static SOLANA_RPC_URL: Lazy<String> = Lazy::new(|| {
         .unwrap_or_else(|_| "https://api.mainnet-beta.solana.com".to_string())
 });
 
-fn create_rpc() -> RpcClient {
+pub(crate) fn create_rpc() -> RpcClient {
     RpcClient::new(SOLANA_RPC_URL.to_string())
 }
 
+/// the `priority` and `send_via_jito` params of the tools sending
+/// transactions, the agent preamble documents them once for all of them
+pub const TRANSACTION_OPTIONS: &str = "
+Transaction options of the tools that send transactions:
+priority: string, optional
+  low, medium, high or turbo, how much priority fee to pay for faster
+  inclusion, from the 25th to the 95th percentile of recent fees. Leave it
+  out for the default, use high or turbo when the user wants speed
+send_via_jito: bool, optional
+  tips a Jito validator and sends the transaction as a bundle, out of the
+  public mempool, for snipes and exits the user wants protected from MEV.
+  Leave it out otherwise, the tip is paid on top of the fees
+";
+
 #[tool(description = "
 Fetches a quote from Jupiter API.
 
@@ -44,163 +112,1260 @@ input_amount: string
 output_mint: string
   public key of the token to swap to
 
-Might throw for pump.fun tokens that have not migrated to Raydium. This is
-different from the Swap tool, works for any Solana token
+Might throw for pump.fun tokens that have not migrated to Raydium. This is
+different from the Swap tool, works for any Solana token
+")]
+pub async fn get_quote(
+    input_mint: String,
+    input_amount: u64,
+    output_mint: String,
+) -> Result<QuoteResponse> {
+    crate::solana::jup::Jupiter::fetch_quote(
+        &input_mint,
+        &output_mint,
+        input_amount,
+    )
+    .await
+    .map_err(|e| anyhow!("{:#?}", e))
+}
+
+#[tool(description = "
+Compares the quotes of Jupiter and Raydium for the same swap, best first, so
+the best venue can be picked for a large swap
+
+Params:
+input_mint: string
+  public key of the token to swap from
+amount: string
+  amount of the input_mint to swap accounting for decimals, or in whole
+  tokens with a decimal point or its symbol, e.g. 1.5 SOL, or max for the
+  whole balance, for SOL a small reserve is kept for fees
+output_mint: string
+  public key of the token to swap to
+
+Venues without a route are left out. Pass the venue of the best quote to
+swap, after showing the comparison to the user
+
+Return:
+the venue, the out_amount accounting for decimals and the price_impact in
+percent of every quote
+")]
+pub async fn compare_routes(
+    input_mint: String,
+    output_mint: String,
+    amount: String,
+) -> Result<Vec<RouteQuote>> {
+    let amount = resolve_swap_amount(&input_mint, &amount).await?;
+    let slippage_bps = chain_config()
+        .solana
+        .jupiter_slippage_bps(None)
+        .unwrap_or(DEFAULT_COMPARE_SLIPPAGE_BPS);
+    routes::compare_routes(
+        &JupiterClient::default(),
+        &input_mint,
+        &output_mint,
+        amount,
+        slippage_bps,
+    )
+    .await
+}
+
+#[tool(description = "
+Simulates a swap without signing anything, e.g. to answer how many BONK 1 SOL
+would get right now and at what price impact
+
+Params:
+input_mint: string
+  public key of the token to swap from
+amount: string
+  amount of the input_mint accounting for decimals, or in whole tokens with
+  a decimal point or its symbol, e.g. 1.5 or 1.5 SOL
+output_mint: string
+  public key of the token to swap to
+slippage_bps: optional u16
+  the tolerance of the minimum received, the default of swap if empty
+
+Tokens that only trade on pump.fun are simulated on their bonding curve
+
+Return:
+the input_amount, expected_out and minimum_received in UI units, the price
+of one input token in output tokens, the price_impact in percent and the
+route as the labels of its hops, ready to relay as they are
+")]
+pub async fn simulate_swap(
+    input_mint: String,
+    amount: String,
+    output_mint: String,
+    slippage_bps: Option<u16>,
+) -> Result<SwapSimulation> {
+    validate_slippage_bps(slippage_bps)?;
+    let amount = parse_token_amount(&input_mint, &amount).await?;
+    let slippage_bps = match slippage_bps {
+        Some(bps) => bps,
+        None => default_slippage_bps(&input_mint, amount)
+            .await
+            .unwrap_or(SMALL_SWAP_SLIPPAGE_BPS),
+    };
+    wrap_unsafe(move || async move {
+        simulate::simulate_swap(
+            &JupiterClient::default(),
+            &create_rpc(),
+            &input_mint,
+            amount,
+            &output_mint,
+            slippage_bps,
+        )
+        .await
+    })
+    .await
+}
+
+#[tool(description = "
+Estimates the fees of a transaction before it is made: the network fee, the
+protocol fee and, for deploys, the rent of the new accounts. Nothing is
+signed
+
+Params:
+instructions_description: string
+  what the transaction does in words, e.g. swap 1 SOL for BONK, send 5 USDC
+  or deploy a token on pump.fun
+input_mint: string, optional
+  for swaps, public key of the token to swap from
+amount: string, optional
+  for swaps, amount of the input_mint accounting for decimals, or in whole
+  tokens with a decimal point or its symbol, e.g. 1.5 or 1.5 SOL
+output_mint: string, optional
+  for swaps, public key of the token to swap to
+
+Return:
+the operation, network_fee_sol, protocol_fee_sol, rent_sol, the
+price_impact_pct of swaps in percent and total_fee_usd
+")]
+pub async fn estimate_transaction_fee(
+    instructions_description: String,
+    input_mint: Option<String>,
+    amount: Option<String>,
+    output_mint: Option<String>,
+) -> Result<FeeEstimate> {
+    let operation = instructions_description.parse::<FeeOperation>()?;
+    let swap = match (operation, input_mint, amount, output_mint) {
+        (
+            FeeOperation::Swap,
+            Some(input_mint),
+            Some(amount),
+            Some(output_mint),
+        ) => {
+            let amount = parse_token_amount(&input_mint, &amount).await?;
+            Some((input_mint, amount, output_mint))
+        }
+        (FeeOperation::Swap, ..) => {
+            return Err(anyhow!(
+                "Pass input_mint, amount and output_mint to estimate a swap"
+            ))
+        }
+        _ => None,
+    };
+    wrap_unsafe(move || async move {
+        fee_estimate::estimate_transaction_fee(
+            &JupiterClient::default(),
+            &create_rpc(),
+            &Client::new(),
+            &chain_config().solana.priority_fee,
+            operation,
+            swap.as_ref().map(|(input, amount, output)| {
+                (input.as_str(), *amount, output.as_str())
+            }),
+        )
+        .await
+    })
+    .await
+}
+
+/// amounts of `mint` as `parse_amount` reads them, with the decimals of
+/// the mint when the amount isn't raw
+async fn parse_token_amount(mint: &str, amount: &str) -> Result<u64> {
+    let (mint, amount) = (mint.to_string(), amount.to_string());
+    wrap_unsafe(move || async move {
+        parse_mint_amount(&create_rpc(), &mint, &amount).await
+    })
+    .await
+}
+
+/// amounts as `parse_token_amount` reads them, "max" as the whole balance
+/// of `input_mint` less the SOL reserve
+async fn resolve_swap_amount(input_mint: &str, amount: &str) -> Result<u64> {
+    if !is_max_amount(amount) {
+        return parse_token_amount(input_mint, amount).await;
+    }
+    if input_mint == WSOL {
+        return max_spendable_sol(get_sol_balance().await?, sol_reserve());
+    }
+    let balance = get_spl_token_balance(input_mint.to_string()).await?;
+    Ok(balance.amount.parse::<u64>()?)
+}
+
+#[tool(description = "
+Performs a swap, choosing the best method. 
+
+Params:
+input_mint: string
+  public key of the token to swap from
+amount: string 
+  amount of the input_mint to swap accounting for decimals, 
+  e.g. 1000000 6 decimals, or 1000000000000000000 9 decimals,
+  or in whole tokens with a decimal point or its symbol, e.g. 1.5 SOL,
+  or max to swap the whole balance, for SOL a small reserve is kept
+  for fees
+output_mint: string
+  public key of the token to swap to
+slippage_bps: number, optional
+  the most the price may move against the swap before it fails, in basis
+  points, 100 = 1%, at most 5000. Leave it out for 50, or 300 for swaps
+  worth over $1000. Volatile tokens may need 300-1000, ask the user before
+  going above 1000
+priority: string, optional, see the transaction options
+send_via_jito: bool, optional, see the transaction options
+venue: string, optional
+  jupiter or raydium, a venue compare_routes found the best for a large
+  swap. Leave it out to route across every dex through Jupiter
+
+Works for any Solana token, regardless of whether it's on PumpFun, Raydium,
+Meteora etc. Will try Jupiter first, and if that fails, will attempt to use 
+Pump.fun directly for applicable tokens.
+
+Return:
+transaction signature as a string, followed by the priority fee and Jito
+tip paid
+")]
+pub async fn swap(
+    input_mint: String,
+    amount: String,
+    output_mint: String,
+    slippage_bps: Option<u16>,
+    priority: Option<String>,
+    send_via_jito: Option<bool>,
+    venue: Option<String>,
+) -> Result<String> {
+    validate_slippage_bps(slippage_bps)?;
+    let venue = venue
+        .map(|venue| venue.parse::<Venue>())
+        .transpose()?
+        .unwrap_or_default();
+    let options = ExecuteOptions {
+        priority: parse_priority(priority)?,
+        send_via_jito: send_via_jito.unwrap_or(false),
+        ..Default::default()
+    };
+    let _options = options.clone();
+    let config = &chain_config().solana;
+    let amount = resolve_swap_amount(&input_mint, &amount).await?;
+    let jupiter_slippage_bps = match slippage_bps {
+        Some(bps) => Some(bps),
+        None => default_slippage_bps(&input_mint, amount).await,
+    };
+    let _input_mint = input_mint.clone();
+    let _output_mint = output_mint.clone();
+
+    let jupiter_result = execute_solana_transaction_with_options(
+        move |owner| {
+            let (input_mint, output_mint) =
+                (input_mint.clone(), output_mint.clone());
+            async move {
+                create_jupiter_swap_transaction(
+                    input_mint,
+                    amount,
+                    output_mint,
+                    jupiter_slippage_bps,
+                    venue,
+                    &owner,
+                )
+                .await
+            }
+        },
+        options,
+    )
+    .await;
+
+    // If Jupiter swap succeeds, return the result
+    match jupiter_result {
+        Ok(executed) => return Ok(executed.to_string()),
+        // it may have landed, swapping again on pump.fun could swap twice
+        Err(e) if !nothing_landed(&e) => return Err(e),
+        Err(e) => {
+            let jupiter_error = e.to_string();
+            // the price moved past the slippage, pump.fun would fail the
+            // same way
+            if jupiter_error.contains("0x1771") {
+                return Err(anyhow!(
+                    "{}\nthe price moved more than the slippage allows \
+                    (0x1771), retry with a higher slippage_bps than {} \
+                    after checking with the user",
+                    jupiter_error,
+                    jupiter_slippage_bps
+                        .map(|bps| bps.to_string())
+                        .unwrap_or_else(|| "the default".to_string())
+                ));
+            }
+            let amount_u64 = amount;
+
+            // Try to buy using Pump.fun, with the slippage of the chain
+            // config when none was given
+            let pump_res = execute_solana_transaction_with_options(
+                move |owner| {
+                    let (_input_mint, _output_mint) =
+                        (_input_mint.clone(), _output_mint.clone());
+                    async move {
+                        if _input_mint.to_lowercase()
+                            == "so11111111111111111111111111111111111111112"
+                        {
+                            create_buy_pump_fun_tx(
+                                _output_mint,
+                                amount_u64,
+                                config.pump_slippage_bps(true, slippage_bps),
+                                &create_rpc(),
+                                &owner,
+                            )
+                            .await
+                        } else {
+                            create_sell_pump_fun_tx(
+                                _input_mint,
+                                amount_u64,
+                                config.pump_slippage_bps(false, slippage_bps),
+                                &create_rpc(),
+                                &owner,
+                            )
+                            .await
+                        }
+                    }
+                },
+                _options,
+            )
+            .await;
+
+            match pump_res {
+                Ok(executed) => return Ok(executed.to_string()),
+                Err(e) => {
+                    return Err(anyhow!(
+                        "jupiter error: {}\n pump.fun error: {}",
+                        jupiter_error,
+                        e.to_string()
+                    ));
+                }
+            }
+        }
+    }
+}
+
+#[tool(description = "
+Swaps for an exact output amount, e.g. buy exactly 1000000 BONK, through
+Jupiter. Use swap instead when the user gives the amount to spend.
+
+Params:
+output_mint: string
+  public key of the token to receive
+out_amount: string
+  exact amount of the output_mint to receive accounting for decimals, or in
+  whole tokens with a decimal point or unit, e.g. 2.5 or 2.5 BONK
+input_mint: string
+  public key of the token to pay with
+max_in_amount: string
+  the most of the input_mint to spend, read like out_amount, or max for
+  the whole balance, for SOL a small reserve is kept for fees. The swap is
+  refused when it could cost more, slippage included
+slippage_bps: number, optional
+  the most the price may move against the swap before it fails, in basis
+  points, 100 = 1%, at most 5000. Leave it out for the default of the venue
+priority: string, optional, see the transaction options
+send_via_jito: bool, optional, see the transaction options
+
+Fewer tokens have exact-out routes, when there is none use swap with an
+exact input amount instead.
+
+Return:
+the amount received and the input amount quoted for it, then the
+transaction signature, followed by the priority fee and Jito tip paid
+")]
+pub async fn swap_exact_out(
+    output_mint: String,
+    out_amount: String,
+    input_mint: String,
+    max_in_amount: String,
+    slippage_bps: Option<u16>,
+    priority: Option<String>,
+    send_via_jito: Option<bool>,
+) -> Result<String> {
+    let options = ExecuteOptions {
+        priority: parse_priority(priority)?,
+        send_via_jito: send_via_jito.unwrap_or(false),
+        ..Default::default()
+    };
+    let out_amount = parse_token_amount(&output_mint, &out_amount).await?;
+    let max_in_amount =
+        resolve_swap_amount(&input_mint, &max_in_amount).await?;
+    let slippage_bps =
+        chain_config().solana.jupiter_slippage_bps(slippage_bps);
+    let quote = quote_jupiter_exact_out(
+        &input_mint,
+        out_amount,
+        &output_mint,
+        max_in_amount,
+        slippage_bps,
+    )
+    .await?;
+    // the first attempt swaps with the quote checked above, a rebuild after
+    // the blockhash expired quotes again
+    let in_amount = Arc::new(Mutex::new(quote.in_amount.clone()));
+    let quote = Mutex::new(Some(quote));
+
+    let _in_amount = in_amount.clone();
+    let _input_mint = input_mint.clone();
+    let _output_mint = output_mint.clone();
+    let executed = execute_solana_transaction_with_options(
+        move |owner| {
+            let quote = quote.lock().unwrap().take();
+            let in_amount = _in_amount.clone();
+            let (input_mint, output_mint) =
+                (_input_mint.clone(), _output_mint.clone());
+            async move {
+                let quote = match quote {
+                    Some(quote) => quote,
+                    None => {
+                        quote_jupiter_exact_out(
+                            &input_mint,
+                            out_amount,
+                            &output_mint,
+                            max_in_amount,
+                            slippage_bps,
+                        )
+                        .await?
+                    }
+                };
+                *in_amount.lock().unwrap() = quote.in_amount.clone();
+                create_jupiter_swap_transaction_from_quote(
+                    quote,
+                    slippage_bps,
+                    &owner,
+                )
+                .await
+            }
+        },
+        options,
+    )
+    .await?;
+    let in_amount = in_amount.lock().unwrap().clone();
+    Ok(format!(
+        "received {} of {} for {} of {}: {}",
+        out_amount, output_mint, in_amount, input_mint, executed
+    ))
+}
+
+#[tool(description = "
+Transfers SOL from the current signer to the given address
+
+This function is dangerous, as it can lead to loss of funds if the address is incorrect
+
+ALWAYS double check the to address with the user before calling this function
+
+to is an address or a .sol domain, domains are resolved to their owner
+
+amount is denoted in lamports, 1 SOL = 10^9 lamports, or in SOL with a
+decimal point or unit, e.g. 1.5 or 1.5 SOL
+
+priority: string, optional, see the transaction options
+
+Returns the transaction signature, followed by the priority fee paid
+")]
+pub async fn transfer_sol(
+    to: String,
+    amount: String,
+    priority: Option<String>,
+) -> Result<String> {
+    let amount = parse_amount(&amount, SOL_DECIMALS, Some("SOL"))?;
+    let options = ExecuteOptions {
+        priority: parse_priority(priority)?,
+        ..Default::default()
+    };
+    let to = wrap_unsafe(move || async move {
+        resolve_recipient(&create_rpc(), &to).await
+    })
+    .await?;
+    execute_solana_transaction_with_options(
+        move |owner| async move {
+            create_transfer_sol_tx(&to, amount, &owner).await
+        },
+        options,
+    )
+    .await
+    .map(|executed| executed.to_string())
+}
+
+/// param amount is token amount, accounting for decimals
+/// e.g. 1 Fartcoin = 1 * 10^6 (6 decimals)
+#[tool(description = "
+Transfers SPL token from the current signer to the given address
+
+This function is dangerous, as it can lead to loss of funds if the address is incorrect
+
+ALWAYS double check the to address with the user before calling this function
+
+amount is denoted in the token amount, accounting for decimals, if you are unsure
+about the decimals, use get_spl_token_balance to get the amount and decimals.
+Whole tokens with a decimal point or unit work too, e.g. 2.5 or 2.5 USDC
+
+to is an address or a .sol domain, domains are resolved to their owner
+
+priority: string, optional, see the transaction options
+
+Returns the transaction signature, followed by the priority fee paid
+")]
+pub async fn transfer_spl_token(
+    to: String,
+    amount: String,
+    mint: String,
+    priority: Option<String>,
+) -> Result<String> {
+    let amount = parse_token_amount(&mint, &amount).await?;
+    let options = ExecuteOptions {
+        priority: parse_priority(priority)?,
+        ..Default::default()
+    };
+    let to = wrap_unsafe(move || async move {
+        resolve_recipient(&create_rpc(), &to).await
+    })
+    .await?;
+    execute_solana_transaction_with_options(
+        move |owner| {
+            let mint = mint.clone();
+            async move {
+                create_transfer_spl_tx(
+                    &to,
+                    amount,
+                    &Pubkey::from_str(&mint)?,
+                    &owner,
+                    &create_rpc(),
+                )
+                .await
+            }
+        },
+        options,
+    )
+    .await
+    .map(|executed| executed.to_string())
+}
+
+#[tool(description = "
+Burns SPL tokens of the current signer, e.g. the rest of a rugged token or
+part of the supply of the user's own deployment
+
+This function is dangerous, burned tokens are gone for good. ALWAYS tell the
+user the token and the amount that will be burned and get them to confirm
+before calling it
+
+Params:
+mint: string
+  public key of the token to burn
+amount: string, optional
+  in whole tokens with a decimal point or unit, e.g. 2.5 or 2.5 BONK, or in
+  base units as a bare integer; not needed with burn_all
+burn_all: bool
+  burn the whole balance as it is when the transaction is built, prefer it
+  over passing the balance as the amount
+
+Burning more than the balance fails before anything is signed
+
+Return:
+burned and remaining_balance in whole tokens and the transaction
+")]
+pub async fn burn_spl_token(
+    mint: String,
+    amount: Option<String>,
+    burn_all: bool,
+) -> Result<BurnReport> {
+    let amount = match (burn_all, amount) {
+        (true, _) => BurnAmount::All,
+        (false, Some(amount)) => BurnAmount::Amount(amount),
+        (false, None) => {
+            return Err(anyhow!("Pass the amount to burn or set burn_all"))
+        }
+    };
+    let mint = Pubkey::from_str(&mint)?;
+    // the balance is read when the transaction is built, so burn_all gets
+    // everything even if it changed since the user confirmed
+    let planned = Arc::new(Mutex::new(None));
+    let _planned = planned.clone();
+    let transaction = execute_solana_transaction(move |owner| async move {
+        let plan = plan_burn(&create_rpc(), &owner, &mint, &amount).await?;
+        let tx = Transaction::new_with_payer(
+            &[plan.instruction.clone()],
+            Some(&owner),
+        );
+        *_planned.lock().unwrap() = Some(plan);
+        Ok(tx.into())
+    })
+    .await?;
+    let plan = planned
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or_else(|| anyhow!("burn was not planned"))?;
+    Ok(BurnReport {
+        burned: ui_amount(plan.amount, plan.decimals),
+        remaining_balance: ui_amount(plan.remaining, plan.decimals),
+        transaction,
+    })
+}
+
+#[tool(description = "
+Transfers a compressed NFT (cNFT) of the current signer by its asset_id to
+the recipient, an address or a .sol domain. cNFTs can't be moved with
+transfer_spl_token
+
+ALWAYS double check the recipient with the user before calling this function
+
+The transfer proves the NFT against its Merkle tree with a proof from the
+DAS api. It may fail if the tree changed in the meantime or its canopy is
+stale, retrying fetches a fresh proof
+
+Returns the transaction signature
+")]
+pub async fn transfer_cnft(
+    asset_id: String,
+    recipient: String,
+) -> Result<String> {
+    let recipient = wrap_unsafe(move || async move {
+        resolve_recipient(&create_rpc(), &recipient).await
+    })
+    .await?;
+    execute_solana_transaction(move |owner| async move {
+        create_transfer_cnft_tx(
+            &DasClient::new(&SOLANA_RPC_URL),
+            &create_rpc(),
+            &owner,
+            &asset_id,
+            &recipient,
+        )
+        .await
+    })
+    .await
+}
+
+#[tool(description = "
+Resolves a .sol domain (Solana Name Service), e.g. bonfida.sol, to the address
+of the wallet that owns it
+
+Use it whenever the user refers to someone by a .sol name, never guess the
+address. Fails if the domain isn't registered or isn't owned by a wallet
+")]
+pub async fn resolve_sns_domain(domain: String) -> Result<String> {
+    wrap_unsafe(move || async move {
+        resolve_domain(&create_rpc(), &domain).await
+    })
+    .await
+    .map(|owner| owner.to_string())
+}
+
+#[tool(description = "
+Creates the durable nonce account of the current signer, transactions that
+need a long confirmation window, e.g. on a hardware wallet, use it instead of
+a recent blockhash that expires after about a minute
+
+Costs the rent-exempt minimum of the account (~0.0015 SOL), which
+close_nonce_account returns. Returns the address of the nonce account
+")]
+pub async fn create_nonce_account() -> Result<String> {
+    let signer = SignerContext::current().await;
+    let owner = Pubkey::from_str(&signer.pubkey())?;
+    let address = nonce_account_address(&owner)?;
+    let exists = wrap_unsafe(move || async move {
+        fetch_nonce(&create_rpc(), &owner).await
+    })
+    .await?
+    .is_some();
+    if !exists {
+        execute_solana_transaction(move |owner| async move {
+            create_nonce_account_tx(&owner, &create_rpc()).await
+        })
+        .await?;
+    }
+    Ok(address.to_string())
+}
+
+#[tool(description = "
+Closes the durable nonce account of the current signer and returns its rent
+to the signer
+")]
+pub async fn close_nonce_account() -> Result<String> {
+    execute_solana_transaction(move |owner| async move {
+        create_close_nonce_account_tx(&owner, &create_rpc()).await
+    })
+    .await
+}
+
+#[tool(description = "
+Places a Jupiter limit order that sells in_amount of input_mint once one whole
+input_mint token fetches target_price of output_mint, e.g. buying a token
+when it dips to 0.002 USDC is input_mint USDC at a target_price of 500
+
+in_amount is raw, including the decimals of input_mint. The order expires
+after expiry_minutes, leave it empty for an order that stays open until it is
+filled or cancelled. The in_amount is held by the order until then. Returns
+the order id
+")]
+pub async fn create_limit_order(
+    input_mint: String,
+    output_mint: String,
+    in_amount: u64,
+    target_price: f64,
+    expiry_minutes: Option<u64>,
+) -> Result<String> {
+    let (_input_mint, _output_mint) =
+        (input_mint.clone(), output_mint.clone());
+    let (input_decimals, output_decimals) = wrap_unsafe(move || async move {
+        fetch_decimals(
+            &create_rpc(),
+            &Pubkey::from_str(&_input_mint)?,
+            &Pubkey::from_str(&_output_mint)?,
+        )
+        .await
+    })
+    .await?;
+    let min_output_amount = min_output_amount(
+        in_amount,
+        target_price,
+        input_decimals,
+        output_decimals,
+    )?;
+    let now = chrono::Utc::now().timestamp();
+    let expires_at = expiry_minutes.map(|minutes| now + minutes as i64 * 60);
+
+    let created = Arc::new(Mutex::new(None));
+    let _created = created.clone();
+    let executed = execute_solana_transaction(move |owner| async move {
+        let (order_id, tx) = LimitOrderClient::default()
+            .create_order(
+                &owner,
+                &input_mint,
+                &output_mint,
+                in_amount,
+                min_output_amount,
+                expires_at.map(|at| at as u64),
+            )
+            .await?;
+        *_created.lock().unwrap() = Some((
+            owner,
+            TrackedOrder {
+                order_id,
+                input_mint,
+                output_mint,
+                input_amount: in_amount,
+                min_output_amount,
+                expires_at,
+                created_at: chrono::Utc::now().to_rfc3339(),
+                cancelled: false,
+            },
+        ));
+        Ok(tx)
+    })
+    .await?;
+    let (owner, order) = created
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or_else(|| anyhow!("limit order was not created"))?;
+    let order_id = order.order_id.clone();
+    // the order exists either way, it only drops out of the closed ones
+    if let Err(e) = ORDER_STORE.track(&owner, order).await {
+        tracing::warn!("Failed to track limit order {}: {}", order_id, e);
+    }
+    Ok(format!("order {}: {}", order_id, executed))
+}
+
+#[tool(description = "
+Cancels an open Jupiter limit order of the current signer, the part of the
+input that wasn't filled yet goes back to the wallet. Expired orders have to
+be cancelled too for their input to come back
+")]
+pub async fn cancel_limit_order(order_id: String) -> Result<String> {
+    let signer = SignerContext::current().await;
+    let owner = Pubkey::from_str(&signer.pubkey())?;
+    let _order_id = order_id.clone();
+    let executed = execute_solana_transaction(move |owner| async move {
+        LimitOrderClient::default()
+            .cancel_order(&owner, &_order_id)
+            .await
+    })
+    .await?;
+    if let Err(e) = ORDER_STORE.mark_cancelled(&owner, &order_id).await {
+        tracing::warn!("Failed to mark {} cancelled: {}", order_id, e);
+    }
+    Ok(executed)
+}
+
+#[tool(description = "
+Lists the Jupiter limit orders of the current signer: the open ones with how
+much of each was filled, and the ones placed with create_limit_order that
+were filled, expired or cancelled since. The amounts are raw
+")]
+pub async fn list_limit_orders() -> Result<Vec<LimitOrder>> {
+    let signer = SignerContext::current().await;
+    let owner = Pubkey::from_str(&signer.pubkey())?;
+    LimitOrderClient::default()
+        .list_orders(
+            &owner,
+            ORDER_STORE.as_ref(),
+            chrono::Utc::now().timestamp(),
+        )
+        .await
+}
+
+#[tool(description = "
+Creates a Jupiter DCA that buys output_mint with amount_per_cycle of
+input_mint every cycle_seconds, num_cycles times, e.g. $50 of SOL every day
+for two weeks. The cycles are filled on-chain by Jupiter, prefer it over
+scheduling swaps
+
+Params:
+input_mint: string
+  public key of the token to spend
+output_mint: string
+  public key of the token to buy
+amount_per_cycle: string
+  amount of the input_mint to spend per cycle accounting for decimals, or
+  in whole tokens with a decimal point or unit, e.g. 50 USDC
+cycle_seconds: u64
+  seconds between cycles, at least 60, e.g. 86400 for daily
+num_cycles: u32
+  how many cycles to buy in, at least 2
+
+The total of every cycle is escrowed in the DCA up front. Confirm the total
+and schedule with the user before calling this
+
+Return:
+the total committed, the schedule and the dca_account, then the
+transaction signature
+")]
+pub async fn create_dca(
+    input_mint: String,
+    output_mint: String,
+    amount_per_cycle: String,
+    cycle_seconds: u64,
+    num_cycles: u32,
+) -> Result<String> {
+    let schedule = DcaSchedule {
+        input_mint: Pubkey::from_str(&input_mint)?,
+        output_mint: Pubkey::from_str(&output_mint)?,
+        amount_per_cycle: parse_token_amount(&input_mint, &amount_per_cycle)
+            .await?,
+        cycle_seconds,
+        num_cycles,
+    };
+    schedule.validate()?;
+    let signer = SignerContext::current().await;
+    let owner = Pubkey::from_str(&signer.pubkey())?;
+    // a new position per second keeps repeated dcas apart
+    let idx = chrono::Utc::now().timestamp() as u64;
+    let _schedule = schedule.clone();
+    let executed = execute_solana_transaction(move |owner| async move {
+        create_open_dca_tx(&owner, &_schedule, idx)
+    })
+    .await?;
+    let dca_account =
+        dca_address(&owner, &schedule.input_mint, &schedule.output_mint, idx);
+    Ok(format!(
+        "committed {} of {} (raw) to buy {}: {} every {} seconds for {} \
+        cycles, the last one in {} seconds, dca_account {}: {}",
+        schedule.total_amount()?,
+        input_mint,
+        output_mint,
+        schedule.amount_per_cycle,
+        schedule.cycle_seconds,
+        schedule.num_cycles,
+        schedule.duration_seconds(),
+        dca_account,
+        executed
+    ))
+}
+
+#[tool(description = "
+Lists the open Jupiter DCAs of the current signer with how many of their
+cycles were filled, what they received and what closing them returns, the
+amounts are raw
+")]
+pub async fn list_dcas() -> Result<Vec<DcaPosition>> {
+    let signer = SignerContext::current().await;
+    let owner = Pubkey::from_str(&signer.pubkey())?;
+    wrap_unsafe(move || async move {
+        fetch_dca_positions(&create_rpc(), &owner).await
+    })
+    .await
+}
+
+#[tool(description = "
+Closes a Jupiter DCA of the current signer by its dca_account, the input it
+didn't spend and the output it holds go back to the wallet
+
+Return:
+the raw amounts returned, then the transaction signature
+")]
+pub async fn close_dca(dca_account: String) -> Result<String> {
+    let signer = SignerContext::current().await;
+    let owner = Pubkey::from_str(&signer.pubkey())?;
+    let dca = Pubkey::from_str(&dca_account)?;
+    let position = wrap_unsafe(move || async move {
+        fetch_dca_position(&create_rpc(), &owner, &dca).await
+    })
+    .await?;
+    let _position = position.clone();
+    let executed = execute_solana_transaction(move |owner| async move {
+        create_close_dca_tx(&owner, &dca, &_position)
+    })
+    .await?;
+    Ok(format!(
+        "returned {} of {} and {} of {}: {}",
+        position.input_remaining,
+        position.input_mint,
+        position.output_remaining,
+        position.output_mint,
+        executed
+    ))
+}
+
+#[tool(description = "
+Stakes SOL of the current signer, natively with a validator or for the
+jitoSOL liquid staking token
+
+Params:
+amount: string
+  SOL to stake, e.g. 1.5 or 1.5 SOL, a number without a decimal point or
+  unit is in lamports
+validator_vote_account: string, optional
+  vote account of the validator to delegate to, the configured default if
+  empty. Ignored when liquid
+liquid: bool, optional
+  true deposits into the Jito stake pool for jitoSOL, which can be swapped
+  or unstaked any time, instead of creating a stake account
+
+Native stake activates at the end of the current epoch and has to be
+deactivated for an epoch before it can be withdrawn, tell the user
+
+Return:
+what was staked and when it starts earning, then the transaction signature
+")]
+pub async fn stake_sol(
+    amount: String,
+    validator_vote_account: Option<String>,
+    liquid: Option<bool>,
+) -> Result<String> {
+    let lamports = parse_amount(&amount, SOL_DECIMALS, Some("SOL"))?;
+    let signer = SignerContext::current().await;
+    let owner = Pubkey::from_str(&signer.pubkey())?;
+    let plan = if liquid.unwrap_or(false) {
+        wrap_unsafe(move || async move {
+            stake::plan_liquid_stake(&create_rpc(), &owner, lamports).await
+        })
+        .await?
+    } else {
+        let vote_account = validator_vote_account
+            .or_else(|| chain_config().solana.default_validator.clone())
+            .ok_or_else(|| {
+                anyhow!(
+                    "no validator_vote_account and no default validator, \
+                    ask the user for one or stake liquid"
+                )
+            })?;
+        let vote_account = Pubkey::from_str(&vote_account)?;
+        // a new stake account per second keeps repeated stakes apart
+        let seed = format!("stake:{}", chrono::Utc::now().timestamp());
+        wrap_unsafe(move || async move {
+            stake::plan_stake(
+                &create_rpc(),
+                &owner,
+                &vote_account,
+                lamports,
+                &seed,
+            )
+            .await
+        })
+        .await?
+    };
+    execute_stake_plan(plan).await
+}
+
+#[tool(description = "
+Lists the native stake accounts of the current signer with their state
+(initialized, activating, active, deactivating or inactive), balance,
+validator and the reward of the last epoch, amounts in lamports. Inactive
+accounts can be withdrawn with unstake
+")]
+pub async fn list_stake_accounts() -> Result<Vec<StakeAccount>> {
+    let signer = SignerContext::current().await;
+    let owner = Pubkey::from_str(&signer.pubkey())?;
+    wrap_unsafe(move || async move {
+        stake::list_stake_accounts(&create_rpc(), &owner).await
+    })
+    .await
+}
+
+#[tool(description = "
+Unstakes a stake account of the current signer, or jitoSOL
+
+Params:
+stake_account: string
+  a stake account from list_stake_accounts, or jitoSOL to redeem the whole
+  jitoSOL balance for SOL
+
+An active stake account is deactivated, which takes until the end of the
+epoch, call unstake again after that to withdraw the SOL. An inactive one is
+withdrawn to the wallet right away
+
+Return:
+what was done and when the SOL is withdrawable, then the transaction
+signature
+")]
+pub async fn unstake(stake_account: String) -> Result<String> {
+    let signer = SignerContext::current().await;
+    let owner = Pubkey::from_str(&signer.pubkey())?;
+    let plan = if stake_account.eq_ignore_ascii_case("jitosol")
+        || stake_account == stake::JITOSOL_MINT
+    {
+        wrap_unsafe(move || async move {
+            stake::plan_liquid_unstake(&create_rpc(), &owner).await
+        })
+        .await?
+    } else {
+        let stake_account = Pubkey::from_str(&stake_account)?;
+        wrap_unsafe(move || async move {
+            stake::plan_unstake(&create_rpc(), &owner, &stake_account).await
+        })
+        .await?
+    };
+    execute_stake_plan(plan).await
+}
+
+async fn execute_stake_plan(plan: StakePlan) -> Result<String> {
+    let instructions = plan.instructions;
+    let executed = execute_solana_transaction(move |owner| async move {
+        Ok(Transaction::new_with_payer(&instructions, Some(&owner)).into())
+    })
+    .await?;
+    Ok(format!("{}: {}", plan.summary, executed))
+}
+
+#[tool(description = "
+Wraps SOL into the WSOL token account of the wallet, creating it when
+missing
+
+Params:
+amount: string
+  lamports, or SOL with a decimal point or unit, e.g. 0.5 or 0.5 SOL
 ")]
-pub async fn get_quote(
-    input_mint: String,
-    input_amount: u64,
-    output_mint: String,
-) -> Result<String> {
-    let quote = crate::solana::jup::Jupiter::fetch_quote(
-        &input_mint,
-        &output_mint,
-        input_amount,
-    )
+pub async fn wrap_sol(amount: String) -> Result<String> {
+    let lamports = parse_amount(&amount, SOL_DECIMALS, Some("SOL"))?;
+    execute_solana_transaction(move |owner| async move {
+        let instructions = wrap_sol_ixs(&owner, lamports)?;
+        Ok(Transaction::new_with_payer(&instructions, Some(&owner)).into())
+    })
     .await
-    .map_err(|e| anyhow!("{:#?}", e))?;
+}
 
-    Ok(serde_json::to_string(&quote)?)
+#[tool(description = "
+Unwraps all of the WSOL of the wallet back into SOL by closing its WSOL
+token account, e.g. one left behind by a failed swap. The rent of the
+account is returned too
+")]
+pub async fn unwrap_sol() -> Result<String> {
+    let owner = Pubkey::from_str(&SignerContext::current().await.pubkey())?;
+    let plan = wrap_unsafe(move || async move {
+        plan_unwrap_sol(&create_rpc(), &owner).await
+    })
+    .await?;
+    let Some(plan) = plan else {
+        return Ok("Nothing to unwrap, the wallet has no WSOL account".into());
+    };
+    let instructions = plan.instructions;
+    let executed = execute_solana_transaction(move |owner| async move {
+        Ok(Transaction::new_with_payer(&instructions, Some(&owner)).into())
+    })
+    .await?;
+    Ok(format!(
+        "Unwrapped {} SOL: {}",
+        lamports_to_sol(plan.lamports),
+        executed
+    ))
 }
 
 #[tool(description = "
-Performs a swap, choosing the best method. 
+Closes the empty token accounts of the wallet to reclaim their rent, about
+0.002 SOL each. WSOL and accounts that can't be closed, e.g. frozen ones or
+token-2022 ones with withheld fees, are left and reported as skipped. Many
+accounts take several transactions
 
 Params:
-input_mint: string
-  public key of the token to swap from
-amount: string 
-  amount of the input_mint to swap accounting for decimals, 
-  e.g. 1000000 6 decimals, or 1000000000000000000 9 decimals
-output_mint: string
-  public key of the token to swap to
-
-Works for any Solana token, regardless of whether it's on PumpFun, Raydium,
-Meteora etc. Will try Jupiter first, and if that fails, will attempt to use 
-Pump.fun directly for applicable tokens.
+max_accounts: number, optional
+  the most accounts to close, all of them by default
+dry_run: bool, optional
+  only report what would be closed and reclaimed, nothing is signed; run
+  this first to confirm with the user
 
 Return:
-transaction signature as a string
+the number closed, reclaimed_sol, the skipped accounts with the reason and
+the transactions sent
 ")]
-pub async fn swap(
-    input_mint: String,
-    amount: String,
-    output_mint: String,
-) -> Result<String> {
-    let _input_mint = input_mint.clone();
-    let _amount = amount.clone();
-    let _output_mint = output_mint.clone();
+pub async fn close_empty_token_accounts(
+    max_accounts: Option<u32>,
+    dry_run: Option<bool>,
+) -> Result<CloseAccountsReport> {
+    let owner = Pubkey::from_str(&SignerContext::current().await.pubkey())?;
+    let checks = wrap_unsafe(move || async move {
+        fetch_empty_token_accounts(&create_rpc(), &owner).await
+    })
+    .await?;
+    let plan = plan_close_accounts(
+        checks,
+        &owner,
+        max_accounts.map(|max| max as usize),
+    );
+    if dry_run.unwrap_or(false) {
+        return Ok(plan.report(true, vec![]));
+    }
 
-    let jupiter_result =
-        execute_solana_transaction(move |owner| async move {
-            create_jupiter_swap_transaction(
-                input_mint.clone(),
-                amount.parse::<u64>()?,
-                output_mint.clone(),
-                &owner,
-            )
-            .await
-            // there would be a slippage error here
+    let mut transactions = Vec::new();
+    for batch in plan.batches.clone() {
+        let executed = execute_solana_transaction(move |owner| async move {
+            Ok(Transaction::new_with_payer(&batch, Some(&owner)).into())
         })
-        .await;
-
-    // If Jupiter swap succeeds, return the result
-    match jupiter_result {
-        Ok(signature) => return Ok(signature),
-        Err(e) => {
-            let jupiter_error = e.to_string();
-            if e.to_string().contains("0x1771") {
-                return Err(e);
-            }
-            // Parse the amount from lamports to SOL
-            let amount_u64 = _amount.parse::<u64>()?;
-            let sol_amount = amount_u64 as f64 / 1_000_000_000.0; // Convert lamports to SOL
-
-            // Try to buy using Pump.fun with a default slippage of 100 bps (1%)
-            let pump_res =
-                execute_solana_transaction(move |owner| async move {
-                    if _input_mint.to_lowercase()
-                        == "so11111111111111111111111111111111111111112"
-                    {
-                        create_buy_pump_fun_tx(
-                            _output_mint,
-                            sol_to_lamports(sol_amount),
-                            100, // 1% slippage
-                            &create_rpc(),
-                            &owner,
-                        )
-                        .await
-                    } else {
-                        create_sell_pump_fun_tx(
-                            _input_mint,
-                            amount_u64,
-                            &owner,
-                        )
-                        .await
-                    }
-                })
-                .await;
-
-            match pump_res {
-                Ok(signature) => return Ok(signature),
-                Err(e) => {
-                    return Err(anyhow!(
-                        "jupiter error: {}\n pump.fun error: {}",
-                        jupiter_error,
-                        e.to_string()
-                    ));
-                }
-            }
-        }
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "failed after {} of {} transactions: {}",
+                transactions.len(),
+                plan.batches.len(),
+                e
+            )
+        })?;
+        transactions.push(executed);
     }
+    Ok(plan.report(false, transactions))
 }
 
 #[tool(description = "
-Transfers SOL from the current signer to the given address
+Fetches the rates of the Kamino lending reserve of a token
 
-This function is dangerous, as it can lead to loss of funds if the address is incorrect
-
-ALWAYS double check the to address with the user before calling this function
+Params:
+market: string
+  public key of the Kamino lending market, the main market is
+  7u3HeHxYDLhnCoErrtycNokbQYbWGzLs6JSDqGAv5PfF
+mint: string
+  public key of the token
 
-amount is denoted in lamports, 1 SOL = 10^9 lamports
+Return:
+supply_apy, borrow_apy and utilization_rate in percent and the
+available_liquidity that can be borrowed in whole tokens
 ")]
-pub async fn transfer_sol(to: String, amount: u64) -> Result<String> {
-    execute_solana_transaction(move |owner| async move {
-        create_transfer_sol_tx(&Pubkey::from_str(&to)?, amount, &owner).await
+pub async fn kamino_get_reserve_info(
+    market: String,
+    mint: String,
+) -> Result<KaminoReserveInfo> {
+    let market = Pubkey::from_str(&market)?;
+    let mint = Pubkey::from_str(&mint)?;
+    wrap_unsafe(move || async move {
+        let reserve =
+            kamino::fetch_reserve(&create_rpc(), &market, &mint).await?;
+        Ok(reserve.info())
     })
     .await
 }
 
-/// param amount is token amount, accounting for decimals
-/// e.g. 1 Fartcoin = 1 * 10^6 (6 decimals)
 #[tool(description = "
-Transfers SPL token from the current signer to the given address
+Deposits a token into Kamino lending as collateral, opening the position of
+the wallet in the market on the first deposit
 
-This function is dangerous, as it can lead to loss of funds if the address is incorrect
+Params:
+market: string
+  public key of the Kamino lending market
+mint: string
+  public key of the token to deposit
+amount: string
+  amount of the mint accounting for decimals, or in whole tokens with a
+  decimal point or its symbol, e.g. 1.5 or 1.5 SOL
+")]
+pub async fn kamino_deposit(
+    market: String,
+    mint: String,
+    amount: String,
+) -> Result<String> {
+    execute_kamino(market, mint, amount, KaminoAction::Deposit).await
+}
 
-ALWAYS double check the to address with the user before calling this function
+#[tool(description = "
+Borrows a token from Kamino lending against the collateral deposited in the
+market. Check the health factor with kamino_get_obligation first
 
-amount is denoted in the token amount, accounting for decimals, if you are unsure
-about the decimals, use get_spl_token_balance to get the amount and decimals
+Params:
+market: string
+  public key of the Kamino lending market
+mint: string
+  public key of the token to borrow
+amount: string
+  amount of the mint accounting for decimals, or in whole tokens with a
+  decimal point or its symbol, e.g. 1.5 or 1.5 SOL
 ")]
-pub async fn transfer_spl_token(
-    to: String,
-    amount: u64,
+pub async fn kamino_borrow(
+    market: String,
+    mint: String,
+    amount: String,
+) -> Result<String> {
+    execute_kamino(market, mint, amount, KaminoAction::Borrow).await
+}
+
+async fn execute_kamino(
+    market: String,
     mint: String,
+    amount: String,
+    action: KaminoAction,
 ) -> Result<String> {
+    let amount = parse_token_amount(&mint, &amount).await?;
+    let market = Pubkey::from_str(&market)?;
+    let mint = Pubkey::from_str(&mint)?;
     execute_solana_transaction(move |owner| async move {
-        create_transfer_spl_tx(
-            &Pubkey::from_str(&to)?,
-            amount,
-            &Pubkey::from_str(&mint)?,
-            &owner,
+        create_kamino_tx(
             &create_rpc(),
+            &owner,
+            &market,
+            &mint,
+            amount,
+            action,
         )
         .await
     })
     .await
 }
 
+#[tool(description = "
+Shows the Kamino lending position of the wallet: its deposits, borrows and
+health factor, below 1 it gets liquidated. Values are as of the last time
+the position was touched
+
+Params:
+market: string, optional
+  public key of the Kamino lending market, the main market by default
+")]
+pub async fn kamino_get_obligation(
+    market: Option<String>,
+) -> Result<KaminoObligation> {
+    let owner = Pubkey::from_str(&SignerContext::current().await.pubkey())?;
+    let market =
+        Pubkey::from_str(market.as_deref().unwrap_or(KAMINO_MAIN_MARKET))?;
+    wrap_unsafe(move || async move {
+        kamino::fetch_obligation(&create_rpc(), &owner, &market)
+            .await?
+            .ok_or_else(|| {
+                anyhow!("the wallet has no position in market {}", market)
+            })
+    })
+    .await
+}
+
 #[tool]
 pub async fn get_public_key() -> Result<String> {
     Ok(SignerContext::current().await.pubkey())
@@ -221,10 +1386,10 @@ pub async fn get_sol_balance() -> Result<u64> {
 }
 
 #[tool(description = "
-get_token_balance returns the amount as String and the decimals as u8
-in order to convert to UI amount: amount / 10^decimals
+Returns the balance of a token of the wallet: the amount in base units as a
+string, the decimals and the amount in whole tokens (amount / 10^decimals)
 ")]
-pub async fn get_spl_token_balance(mint: String) -> Result<(String, u8)> {
+pub async fn get_spl_token_balance(mint: String) -> Result<TokenBalance> {
     let signer = SignerContext::current().await;
     let owner = Pubkey::from_str(&signer.pubkey())?;
     let mint = Pubkey::from_str(&mint)?;
@@ -240,7 +1405,11 @@ pub async fn get_spl_token_balance(mint: String) -> Result<(String, u8)> {
     .await
     .map_err(|e| anyhow!("{:#?}", e))?;
 
-    Ok((balance.amount, balance.decimals))
+    Ok(TokenBalance {
+        amount: balance.amount,
+        decimals: balance.decimals,
+        ui_amount: balance.ui_amount_string,
+    })
 }
 
 #[tool(description = "
@@ -252,7 +1421,10 @@ if the user doesnt provide those they can be left as empty strings
 The image_url cannot be a local path, it has to be an image url from the
 internet, ask user to paste in
 
-dev_buy is denoted in lamports - 1 solana is 10^9 lamports
+The name can be at most 32 bytes, the symbol at most 10 letters or digits
+
+dev_buy is denoted in lamports - 1 solana is 10^9 lamports, it cannot be
+more than the SOL balance of the wallet
 ")]
 #[allow(clippy::too_many_arguments)]
 pub async fn deploy_pump_fun_token(
@@ -265,22 +1437,32 @@ pub async fn deploy_pump_fun_token(
     image_url: String,
     description: String,
 ) -> Result<String> {
-    execute_solana_transaction(move |owner| async move {
-        create_deploy_token_tx(
-            crate::solana::deploy_token::DeployTokenParams {
-                name,
-                symbol,
-                twitter: Some(twitter),
-                website: Some(website),
-                dev_buy: Some(dev_buy),
-                telegram: Some(telegram),
-                image_url: Some(image_url),
-                description,
-            },
-            &owner,
-        )
-        .await
-    })
+    let params = crate::solana::deploy_token::DeployTokenParams {
+        name,
+        symbol,
+        twitter: Some(twitter),
+        website: Some(website),
+        dev_buy: Some(dev_buy),
+        telegram: Some(telegram),
+        // an empty image_url gets a generated image
+        image_url: Some(image_url).filter(|url| !url.is_empty()),
+        description,
+    };
+    params.validate()?;
+    if dev_buy > 0 {
+        crate::solana::deploy_token::check_dev_buy(
+            dev_buy,
+            get_sol_balance().await?,
+        )?;
+    }
+
+    let (mint, mint_signer) = generate_mint();
+    execute_solana_transaction_with_co_signers(
+        move |owner| async move {
+            create_deploy_token_tx(params, &owner, mint).await
+        },
+        vec![Arc::new(mint_signer)],
+    )
     .await
 }
 
@@ -299,23 +1481,52 @@ regular swap function and if it fails with the token being not found, only then
 directly on pump
 
 Also, if the user specifically requests to buy on pump.fun, use this method
+
+amount is the SOL to spend, with a unit or a decimal point, e.g. 0.5, 1.0,
+1 SOL or 5000000 lamports. A bare number like 1 is rejected as ambiguous
+
+skip_simulation sends the transaction without simulating it first, only set
+it to true when the user asks for a snipe where speed matters more than the
+fee of a failed transaction, otherwise always false
+
+priority: string, optional, see the transaction options
+send_via_jito: bool, optional, see the transaction options
+
+Returns the transaction signature, followed by the priority fee and Jito tip
+paid
 ")]
 pub async fn buy_pump_fun_token(
     mint: String,
-    sol_amount: f64,
+    amount: String,
     slippage_bps: u16,
+    skip_simulation: bool,
+    priority: Option<String>,
+    send_via_jito: Option<bool>,
 ) -> Result<String> {
-    execute_solana_transaction(move |owner| async move {
-        create_buy_pump_fun_tx(
-            mint,
-            sol_to_lamports(sol_amount),
-            slippage_bps,
-            &create_rpc(),
-            &owner,
-        )
-        .await
-    })
+    let options = ExecuteOptions {
+        skip_simulation,
+        priority: parse_priority(priority)?,
+        send_via_jito: send_via_jito.unwrap_or(false),
+    };
+    let lamports = parse_explicit_sol_amount(&amount)?;
+    execute_solana_transaction_with_options(
+        move |owner| {
+            let mint = mint.clone();
+            async move {
+                create_buy_pump_fun_tx(
+                    mint,
+                    lamports,
+                    slippage_bps,
+                    &create_rpc(),
+                    &owner,
+                )
+                .await
+            }
+        },
+        options,
+    )
     .await
+    .map(|executed| executed.to_string())
 }
 
 #[tool(description = "
@@ -326,15 +1537,46 @@ regular swap function and if it fails with the token being not found, only then
 directly on pump
 
 Also, if the user specifically requests to sell on pump.fun, use this method
+
+slippage_bps is the most the price may drop below the current bonding curve
+price before the sell is rejected, use 500 (5%) unless the user asks otherwise
+
+priority: string, optional, see the transaction options
+send_via_jito: bool, optional, see the transaction options
+
+Returns the transaction signature, followed by the priority fee and Jito tip
+paid
 ")]
 pub async fn sell_pump_fun_token(
     mint: String,
     token_amount: u64,
+    slippage_bps: u16,
+    priority: Option<String>,
+    send_via_jito: Option<bool>,
 ) -> Result<String> {
-    execute_solana_transaction(move |owner| async move {
-        create_sell_pump_fun_tx(mint, token_amount, &owner).await
-    })
+    let options = ExecuteOptions {
+        priority: parse_priority(priority)?,
+        send_via_jito: send_via_jito.unwrap_or(false),
+        ..Default::default()
+    };
+    execute_solana_transaction_with_options(
+        move |owner| {
+            let mint = mint.clone();
+            async move {
+                create_sell_pump_fun_tx(
+                    mint,
+                    token_amount,
+                    slippage_bps,
+                    &create_rpc(),
+                    &owner,
+                )
+                .await
+            }
+        },
+        options,
+    )
     .await
+    .map(|executed| executed.to_string())
 }
 
 #[tool(description = "
@@ -355,3 +1597,251 @@ pub async fn get_portfolio() -> Result<Vec<PortfolioItem>> {
 
     holdings_to_portfolio(holdings).await
 }
+
+#[tool(description = "
+Sells every position of the portfolio for the target token, e.g. to exit
+everything into SOL or USDC at once
+
+This function is dangerous, it sells the whole portfolio. ALWAYS list the
+positions that will be sold and get the user to confirm before calling it
+
+Params:
+keep_mints: array of strings, optional
+  public keys of the tokens to keep, SOL is always kept
+target_mint: string
+  public key of the token to sell into, e.g.
+  So11111111111111111111111111111111111111112 for SOL or
+  EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v for USDC
+
+The positions are swapped one after the other, a failed swap doesn't stop
+the rest
+
+Return:
+the sold mints with their transaction, the ones that failed with the error
+and the usd worth of what was sold at the portfolio prices
+")]
+pub async fn sell_all(
+    keep_mints: Option<Vec<String>>,
+    target_mint: String,
+) -> Result<SellAllReport> {
+    let portfolio = get_portfolio().await?;
+    let positions = positions_to_sell(
+        portfolio,
+        &keep_mints.unwrap_or_default(),
+        &target_mint,
+    );
+
+    let mut report = SellAllReport::default();
+    for position in positions {
+        let sold = swap(
+            position.address.clone(),
+            "max".to_string(),
+            target_mint.clone(),
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+        match sold {
+            Ok(executed) => {
+                report.total_received_usd += position.amount * position.price;
+                report.successful.push(format!(
+                    "{} ({}): {}",
+                    position.symbol, position.address, executed
+                ));
+            }
+            Err(e) => {
+                tracing::warn!(mint = %position.address, %e, "sell failed");
+                report.failed.push((position.address, e.to_string()));
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[tool(description = "
+Returns the recent transactions of the user, newest first, each with its
+signature, timestamp, type (swap, transfer or other), token name, usd amount
+and whether it was a buy
+
+Params:
+limit: number
+  how many transactions to return, at most 50
+before_signature: optional string
+  the last signature of the previous page, to scroll further back
+
+Swaps the indexer priced come with the token name and usd amount, the rest
+are summarized from their balance changes with the mint as the token name
+")]
+pub async fn get_transaction_history(
+    limit: u32,
+    before_signature: Option<String>,
+) -> Result<Vec<TransactionSummary>> {
+    let owner = Pubkey::from_str(&SignerContext::current().await.pubkey())?;
+    wrap_unsafe(move || async move {
+        crate::solana::history::get_transaction_history(
+            &create_rpc(),
+            &API_URL,
+            &owner,
+            limit,
+            before_signature,
+        )
+        .await
+    })
+    .await
+}
+
+#[tool(description = "
+Returns the on-chain state of a concentrated liquidity pool: reserves,
+current price (base token in quote token), fee rate, tick spacing, current
+tick and 24h volume.
+
+Params:
+pool_address: string
+  address of a Raydium CLMM or Orca Whirlpool pool, the type is detected
+  from the account owner
+
+Useful for estimating slippage before a large swap
+")]
+pub async fn get_pool_info(pool_address: String) -> Result<PoolInfo> {
+    wrap_unsafe(move || async move {
+        crate::solana::pool::get_pool_info(&create_rpc(), &pool_address).await
+    })
+    .await
+}
+
+#[tool(description = "
+Checks a token for rug pull and honeypot signals before buying: mint and
+freeze authority, top 10 holder concentration, whether the largest holder is
+the bonding curve or a pool, LP burn of its Raydium pool and token age
+
+Params:
+mint: string
+  the token mint address
+
+Each flag is pass, warn, fail or unknown when it couldn't be fetched, with
+a detail. The risk_score goes from 0 to 100, with a low, medium or high
+risk_level. Use it before recommending or executing a buy of an unfamiliar
+token and tell the user about every failed flag
+")]
+pub async fn check_token_safety(mint: String) -> Result<TokenSafety> {
+    wrap_unsafe(move || async move {
+        crate::solana::safety::check_token_safety(
+            &create_rpc(),
+            RAYDIUM_POOLS_API_URL,
+            &mint,
+        )
+        .await
+    })
+    .await
+}
+
+#[tool(description = "
+Returns the largest holders of a token with their share of the supply. Each
+holder has the wallet owning the token account and a label when it is a known
+pool or bonding curve vault (raydium, pump.fun, meteora, orca) rather than a
+regular holder
+
+Params:
+mint: string
+  the token mint address
+limit: number, optional
+  how many holders to return, defaults to 10; the RPC returns at most 20
+
+Return:
+top10_percent is the share of the 10 largest accounts and
+top10_percent_excluding_vaults the same share without the labelled vaults,
+use the latter to judge how concentrated the token is
+")]
+pub async fn get_top_holders(
+    mint: String,
+    limit: Option<u32>,
+) -> Result<TopHoldersReport> {
+    wrap_unsafe(move || async move {
+        holders::get_top_holders(
+            &create_rpc(),
+            &mint,
+            limit.map_or(10, |limit| limit as usize),
+        )
+        .await
+    })
+    .await
+}
+
+#[tool(description = "
+Returns the mint authority, freeze authority, whether the metadata is still
+mutable and the slot the token was created in (null for tokens with a long
+history).
+
+Params:
+mint: string
+  the token mint address
+
+An active mint authority means the supply can be inflated and an active
+freeze authority means holders can be blocked from selling, always point
+these out to the user as red flags before buying
+")]
+pub async fn get_token_authorities(mint: String) -> Result<TokenAuthorities> {
+    wrap_unsafe(move || async move {
+        crate::solana::token_info::get_token_authorities(&create_rpc(), &mint)
+            .await
+    })
+    .await
+}
+
+#[tool(description = "
+Returns the proposals of a Solana DAO on SPL Governance (Realms): name,
+state, yes and no vote weights (raw amounts of the governing token), when
+voting ends (unix seconds) and a link to the description.
+
+Params:
+realm_address: string
+  address of the DAO's realm account
+
+Proposals being voted on come first. To recommend a vote, read the
+description link and weigh it against the user's holdings of the
+governing_token_mint (e.g. from get_spl_token_balance); only users holding
+that token can vote
+")]
+pub async fn get_governance_proposals(
+    realm_address: String,
+) -> Result<Vec<GovernanceProposal>> {
+    wrap_unsafe(move || async move {
+        crate::solana::governance::get_governance_proposals(
+            &create_rpc(),
+            &realm_address,
+        )
+        .await
+    })
+    .await
+}
+
+static MAGIC_EDEN: Lazy<MagicEdenClient> =
+    Lazy::new(MagicEdenClient::default);
+
+#[tool(description = "
+Returns the floor of an NFT collection from Magic Eden: floor_price_sol,
+floor_price_usd, volume_24h_sol, listed_count and holder_count, the last two
+may be null when Magic Eden doesn't report them
+
+Params:
+collection_address: string
+  the Magic Eden symbol of the collection, e.g. okay_bears, or the address of
+  one of its NFTs or of its verified collection NFT
+
+The stats of a collection are cached for a minute
+")]
+pub async fn fetch_nft_floor_price(
+    collection_address: String,
+) -> Result<NftFloorReport> {
+    MAGIC_EDEN
+        .floor_report(
+            &collection_address,
+            crate::solana::price::fetch_token_price(
+                WSOL.to_string(),
+                &Client::new(),
+            ),
+        )
+        .await
+}
@@ -1,23 +1,217 @@
-use crate::solana::jup::Jupiter;
+use crate::chain_config::chain_config;
+use crate::solana::constants::WSOL;
+use crate::solana::jup::{Jupiter, JupiterClient, QuoteResponse, SwapMode};
+use crate::solana::routes::Venue;
 use anyhow::{anyhow, Result};
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::message::VersionedMessage;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::transaction::VersionedTransaction;
+use spl_associated_token_account::get_associated_token_address;
+use std::str::FromStr;
 
+/// `TokenInstruction::CloseAccount`
+const CLOSE_ACCOUNT: u8 = 9;
+
+/// swaps giving up more than half of their output to price moves are
+/// refused rather than sent
+pub const MAX_SLIPPAGE_BPS: u16 = 5_000;
+
+/// the slippage of swaps without one, unless the chain config has a default
+pub const SMALL_SWAP_SLIPPAGE_BPS: u16 = 50;
+/// larger swaps move the price more between the quote and landing
+pub const LARGE_SWAP_SLIPPAGE_BPS: u16 = 300;
+pub const LARGE_SWAP_USD: f64 = 1_000.;
+
+const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+const USDC_DECIMALS: i32 = 6;
+
+pub fn validate_slippage_bps(slippage_bps: Option<u16>) -> Result<()> {
+    match slippage_bps {
+        Some(slippage_bps) if slippage_bps > MAX_SLIPPAGE_BPS => {
+            Err(anyhow!(
+                "slippage_bps can be at most {}, got {}",
+                MAX_SLIPPAGE_BPS,
+                slippage_bps
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn size_slippage_bps(swap_usd: f64) -> u16 {
+    if swap_usd > LARGE_SWAP_USD {
+        LARGE_SWAP_SLIPPAGE_BPS
+    } else {
+        SMALL_SWAP_SLIPPAGE_BPS
+    }
+}
+
+/// what `amount` of `input_mint` is worth, by a quote for USDC
+pub async fn swap_value_usd(
+    jupiter: &JupiterClient,
+    input_mint: &str,
+    amount: u64,
+) -> Result<f64> {
+    let usdc = if input_mint == USDC_MINT {
+        amount
+    } else {
+        jupiter
+            .fetch_quote(input_mint, USDC_MINT, amount)
+            .await?
+            .out_amount
+            .parse()?
+    };
+    Ok(usdc as f64 / 10f64.powi(USDC_DECIMALS))
+}
+
+/// the slippage of a swap the tool call gave none for: the chain config
+/// default, or one by the size of the swap. `None` lets jupiter pick when
+/// the swap can't be valued
+pub async fn default_slippage_bps(
+    input_mint: &str,
+    amount: u64,
+) -> Option<u16> {
+    if let Some(bps) = chain_config().solana.jupiter_slippage_bps {
+        return Some(bps);
+    }
+    match swap_value_usd(&JupiterClient::default(), input_mint, amount).await
+    {
+        Ok(swap_usd) => Some(size_slippage_bps(swap_usd)),
+        Err(e) => {
+            tracing::warn!(%input_mint, %e, "can't value the swap");
+            None
+        }
+    }
+}
+
+/// `slippage_bps` fixes the slippage of the swap, jupiter picks one
+/// dynamically when it is `None`; the route stays on the dexes of `venue`
 pub async fn create_jupiter_swap_transaction(
     input_mint: String,
     input_amount: u64,
     output_mint: String,
+    slippage_bps: Option<u16>,
+    venue: Venue,
     owner: &Pubkey,
 ) -> Result<VersionedTransaction> {
-    let quote = Jupiter::fetch_quote(&input_mint, &output_mint, input_amount)
+    validate_slippage_bps(slippage_bps)?;
+    let quote = JupiterClient::default()
+        .with_dexes(venue.jupiter_dexes())
+        .fetch_quote_with_slippage(
+            &input_mint,
+            &output_mint,
+            input_amount,
+            slippage_bps,
+        )
         .await
         .map_err(|e| anyhow!("Failed to fetch quote: {}", e.to_string()))?;
-
-    let tx = Jupiter::swap(quote, owner)
+    create_jupiter_swap_transaction_from_quote(quote, slippage_bps, owner)
         .await
-        .map_err(|e| anyhow!("Failed to swap: {}", e.to_string()))?;
+}
+
+/// `out_amount` of `output_mint` for whatever `input_mint` it takes, as
+/// long as that stays within `max_in_amount` with slippage. A pair without
+/// exact-out routes fails with `ExactOutUnsupported`.
+pub async fn quote_jupiter_exact_out(
+    input_mint: &str,
+    out_amount: u64,
+    output_mint: &str,
+    max_in_amount: u64,
+    slippage_bps: Option<u16>,
+) -> Result<QuoteResponse> {
+    validate_slippage_bps(slippage_bps)?;
+    Jupiter::fetch_exact_out_quote(
+        input_mint,
+        output_mint,
+        out_amount,
+        max_in_amount,
+        slippage_bps,
+    )
+    .await
+}
+
+/// whether the swap of `quote` keeps its slippage rather than jupiter
+/// picking one. An exact-out quote always does, the most it can take was
+/// checked against the max_in_amount with the slippage of the quote and a
+/// higher dynamic slippage would let the swap spend past it
+fn keeps_quote_slippage(
+    quote: &QuoteResponse,
+    slippage_bps: Option<u16>,
+) -> bool {
+    slippage_bps.is_some()
+        || quote.swap_mode == SwapMode::ExactOut.to_string()
+}
+
+/// the swap of a quote, `slippage_bps` as the quote was fetched with
+pub async fn create_jupiter_swap_transaction_from_quote(
+    quote: QuoteResponse,
+    slippage_bps: Option<u16>,
+    owner: &Pubkey,
+) -> Result<VersionedTransaction> {
+    let tx = match keeps_quote_slippage(&quote, slippage_bps) {
+        true => Jupiter::swap_with_fixed_slippage(quote, owner).await,
+        false => Jupiter::swap(quote, owner).await,
+    }
+    .map_err(|e| anyhow!("Failed to swap: {}", e.to_string()))?;
+
+    let mut tx = tx.into();
+    if ensure_wsol_unwrap(&mut tx, owner)? {
+        tracing::warn!(%owner, "swap left WSOL wrapped, added an unwrap");
+    }
+    Ok(tx)
+}
 
-    Ok(tx.into())
+/// Closes the WSOL account of `owner` at the end of `tx` if the
+/// transaction uses it without doing so, so that SOL wrapped for a swap
+/// can't stay behind in it. Returns whether an unwrap was added.
+///
+/// A failing transaction reverts the wrap along with everything else, it
+/// is a route that wraps and never unwraps that would strand the WSOL.
+pub fn ensure_wsol_unwrap(
+    tx: &mut VersionedTransaction,
+    owner: &Pubkey,
+) -> Result<bool> {
+    let wsol_account =
+        get_associated_token_address(owner, &Pubkey::from_str(WSOL)?);
+    let message = &tx.message;
+    let keys = message.static_account_keys();
+    let index_of = |key: &Pubkey| keys.iter().position(|k| k == key);
+    let Some(account_index) = index_of(&wsol_account) else {
+        return Ok(false);
+    };
+    // closing needs the account writable and the owner signing, which the
+    // fee payer of a swap does
+    if !message.is_maybe_writable(account_index, None)
+        || keys.first() != Some(owner)
+    {
+        return Ok(false);
+    }
+    let Some(token_program_index) = index_of(&spl_token::id()) else {
+        return Ok(false);
+    };
+    let closes = message.instructions().iter().any(|ix| {
+        ix.program_id_index as usize == token_program_index
+            && ix.data.first() == Some(&CLOSE_ACCOUNT)
+            && ix.accounts.first() == Some(&(account_index as u8))
+    });
+    if closes {
+        return Ok(false);
+    }
+
+    let unwrap = CompiledInstruction {
+        program_id_index: token_program_index as u8,
+        // the account, where its lamports go, and its owner
+        accounts: vec![account_index as u8, 0, 0],
+        data: vec![CLOSE_ACCOUNT],
+    };
+    match &mut tx.message {
+        VersionedMessage::Legacy(message) => {
+            message.instructions.push(unwrap)
+        }
+        VersionedMessage::V0(message) => message.instructions.push(unwrap),
+    }
+    Ok(true)
 }
 
 #[cfg(test)]
@@ -25,8 +219,93 @@ mod tests {
     use crate::solana::{constants, util::load_keypair_for_tests};
 
     use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+    use solana_sdk::message::v0;
     use solana_sdk::native_token::sol_to_lamports;
     use solana_sdk::signer::Signer;
+    use solana_sdk::system_instruction;
+    use solana_sdk::transaction::Transaction;
+    use spl_associated_token_account::instruction as ata_instruction;
+
+    #[test]
+    fn test_size_slippage_bps() {
+        assert_eq!(size_slippage_bps(20.), SMALL_SWAP_SLIPPAGE_BPS);
+        assert_eq!(size_slippage_bps(1_000.), SMALL_SWAP_SLIPPAGE_BPS);
+        assert_eq!(size_slippage_bps(1_500.), LARGE_SWAP_SLIPPAGE_BPS);
+    }
+
+    #[tokio::test]
+    async fn test_swap_value_usd() {
+        let mut server = mockito::Server::new_async().await;
+        let quote = server
+            .mock("GET", "/quote")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "outputMint".into(),
+                USDC_MINT.into(),
+            ))
+            .with_body(
+                serde_json::json!({
+                    "inputMint": WSOL,
+                    "inAmount": "10000000000",
+                    "outputMint": USDC_MINT,
+                    "outAmount": "1500000000",
+                    "otherAmountThreshold": "1492500000",
+                    "swapMode": "ExactIn",
+                    "slippageBps": 50,
+                    "platformFee": null,
+                    "priceImpactPct": "0",
+                    "routePlan": [],
+                    "contextSlot": 1,
+                    "timeTaken": 0.01,
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+        let jupiter = JupiterClient::new(&server.url()).with_referral(None);
+
+        let swap_usd = swap_value_usd(&jupiter, WSOL, sol_to_lamports(10.))
+            .await
+            .unwrap();
+        assert_eq!(swap_usd, 1_500.);
+        assert_eq!(size_slippage_bps(swap_usd), LARGE_SWAP_SLIPPAGE_BPS);
+        // USDC is valued without a quote
+        assert_eq!(
+            swap_value_usd(&jupiter, USDC_MINT, 25_000_000)
+                .await
+                .unwrap(),
+            25.
+        );
+        quote.assert_async().await;
+    }
+
+    #[test]
+    fn test_exact_out_keeps_quote_slippage() {
+        let quote = |swap_mode: &str| {
+            serde_json::from_value::<QuoteResponse>(serde_json::json!({
+                "inputMint": WSOL,
+                "inAmount": "1000000",
+                "outputMint": USDC_MINT,
+                "outAmount": "150000",
+                "otherAmountThreshold": "1005000",
+                "swapMode": swap_mode,
+                "slippageBps": 50,
+                "platformFee": null,
+                "priceImpactPct": "0",
+                "routePlan": [],
+                "contextSlot": 1,
+                "timeTaken": 0.01,
+            }))
+            .unwrap()
+        };
+        assert!(!keeps_quote_slippage(&quote("ExactIn"), None));
+        assert!(keeps_quote_slippage(&quote("ExactIn"), Some(100)));
+        // the threshold checked against max_in_amount holds without a
+        // slippage given too
+        assert!(keeps_quote_slippage(&quote("ExactOut"), None));
+    }
 
     #[tokio::test]
     async fn test_jupiter_swap() {
@@ -35,6 +314,8 @@ mod tests {
             constants::WSOL.to_string(),
             sol_to_lamports(0.001),
             "FUAfBo2jgks6gB4Z4LfZkqSZgzNucisEHqnNebaRxM1P".to_string(),
+            None,
+            Venue::Jupiter,
             &keypair.pubkey(),
         )
         .await;
@@ -42,4 +323,118 @@ mod tests {
 
         assert!(result.is_ok());
     }
+
+    /// what a route that wraps SOL into the WSOL account compiles to,
+    /// without or with the closing unwrap
+    fn wrapping_swap(owner: &Pubkey, unwrap: bool) -> VersionedTransaction {
+        let wsol = Pubkey::from_str(WSOL).unwrap();
+        let wsol_account = get_associated_token_address(owner, &wsol);
+        let mut ixs = vec![
+            ata_instruction::create_associated_token_account_idempotent(
+                owner,
+                owner,
+                &wsol,
+                &spl_token::id(),
+            ),
+            system_instruction::transfer(owner, &wsol_account, 1_000_000),
+            spl_token::instruction::sync_native(
+                &spl_token::id(),
+                &wsol_account,
+            )
+            .unwrap(),
+            // the swap itself, spending the WSOL
+            Instruction::new_with_bytes(
+                Pubkey::new_unique(),
+                &[1],
+                vec![
+                    AccountMeta::new(wsol_account, false),
+                    AccountMeta::new_readonly(*owner, true),
+                ],
+            ),
+        ];
+        if unwrap {
+            ixs.push(
+                spl_token::instruction::close_account(
+                    &spl_token::id(),
+                    &wsol_account,
+                    owner,
+                    owner,
+                    &[],
+                )
+                .unwrap(),
+            );
+        }
+        let message =
+            v0::Message::try_compile(owner, &ixs, &[], Hash::default())
+                .unwrap();
+        VersionedTransaction {
+            signatures: vec![Default::default()],
+            message: VersionedMessage::V0(message),
+        }
+    }
+
+    #[test]
+    fn test_adds_wsol_unwrap() {
+        let owner = Pubkey::new_unique();
+        let wsol_account = get_associated_token_address(
+            &owner,
+            &Pubkey::from_str(WSOL).unwrap(),
+        );
+        let mut tx = wrapping_swap(&owner, false);
+
+        assert!(ensure_wsol_unwrap(&mut tx, &owner).unwrap());
+
+        let keys = tx.message.static_account_keys();
+        let last = tx.message.instructions().last().unwrap();
+        assert_eq!(keys[last.program_id_index as usize], spl_token::id());
+        let accounts = last
+            .accounts
+            .iter()
+            .map(|&i| keys[i as usize])
+            .collect::<Vec<_>>();
+        assert_eq!(accounts, vec![wsol_account, owner, owner]);
+        assert_eq!(
+            last.data,
+            spl_token::instruction::close_account(
+                &spl_token::id(),
+                &wsol_account,
+                &owner,
+                &owner,
+                &[],
+            )
+            .unwrap()
+            .data
+        );
+
+        // only once
+        assert!(!ensure_wsol_unwrap(&mut tx, &owner).unwrap());
+    }
+
+    #[test]
+    fn test_keeps_existing_wsol_unwrap() {
+        let owner = Pubkey::new_unique();
+        let mut tx = wrapping_swap(&owner, true);
+        let before = tx.clone();
+        assert!(!ensure_wsol_unwrap(&mut tx, &owner).unwrap());
+        assert_eq!(tx, before);
+
+        // swaps that don't touch WSOL are left alone
+        let mut tx: VersionedTransaction = Transaction::new_with_payer(
+            &[system_instruction::transfer(
+                &owner,
+                &Pubkey::new_unique(),
+                1,
+            )],
+            Some(&owner),
+        )
+        .into();
+        assert!(!ensure_wsol_unwrap(&mut tx, &owner).unwrap());
+    }
+
+    #[test]
+    fn test_validate_slippage_bps() {
+        assert!(validate_slippage_bps(None).is_ok());
+        assert!(validate_slippage_bps(Some(MAX_SLIPPAGE_BPS)).is_ok());
+        assert!(validate_slippage_bps(Some(MAX_SLIPPAGE_BPS + 1)).is_err());
+    }
 }
@@ -1,18 +1,49 @@
 use crate::solana::pump::{
-    _make_buy_ixs, get_bonding_curve, get_pump_token_amount,
-    make_pump_sell_ix, mint_to_pump_accounts,
+    _make_buy_ixs, get_bonding_curve, get_pump_sol_amount,
+    get_pump_token_amount, make_pump_sell_ix, mint_to_pump_accounts,
+    BondingCurveLayout,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::transaction::{Transaction, VersionedTransaction};
 use std::str::FromStr;
 
+/// used when neither the caller nor the chain config pick a slippage
+pub const DEFAULT_PUMP_BUY_SLIPPAGE_BPS: u16 = 100;
+pub const DEFAULT_PUMP_SELL_SLIPPAGE_BPS: u16 = 500;
+
 fn apply_slippage(amount: u64, slippage_bps: u16) -> u64 {
     let slippage = amount * slippage_bps as u64 / 10_000;
     amount - slippage
 }
 
+/// the least lamports a sell of `token_amount` may yield, the expected
+/// output at the current reserves less `slippage_bps`
+pub fn pump_sell_min_sol_output(
+    bonding_curve: &BondingCurveLayout,
+    token_amount: u64,
+    slippage_bps: u16,
+) -> Result<u64> {
+    if bonding_curve.complete {
+        return Err(anyhow!(
+            "bonding curve is complete, the token trades on raydium now"
+        ));
+    }
+    if slippage_bps > 10_000 {
+        return Err(anyhow!("slippage_bps can be at most 10000"));
+    }
+    let expected = get_pump_sol_amount(
+        bonding_curve.virtual_sol_reserves,
+        bonding_curve.virtual_token_reserves,
+        token_amount,
+    )?;
+    if expected == 0 {
+        return Err(anyhow!("selling {} tokens yields no SOL", token_amount));
+    }
+    Ok(apply_slippage(expected, slippage_bps))
+}
+
 pub async fn create_buy_pump_fun_tx(
     mint: String,
     sol_amount: u64,
@@ -49,17 +80,30 @@ pub async fn create_buy_pump_fun_tx(
 pub async fn create_sell_pump_fun_tx(
     mint: String,
     token_amount: u64,
+    slippage_bps: u16,
+    rpc_client: &RpcClient,
     owner: &Pubkey,
 ) -> Result<VersionedTransaction> {
     let mint = Pubkey::from_str(&mint)?;
     let pump_accounts = mint_to_pump_accounts(&mint);
 
+    let bonding_curve =
+        get_bonding_curve(rpc_client, pump_accounts.bonding_curve).await?;
+    let min_sol_output =
+        pump_sell_min_sol_output(&bonding_curve, token_amount, slippage_bps)?;
+
     let ata = spl_associated_token_account::get_associated_token_address(
         owner,
         &pump_accounts.mint,
     );
 
-    let ix = make_pump_sell_ix(*owner, pump_accounts, token_amount, ata)?;
+    let ix = make_pump_sell_ix(
+        *owner,
+        pump_accounts,
+        token_amount,
+        min_sol_output,
+        ata,
+    )?;
 
     let tx = Transaction::new_with_payer([ix].as_slice(), Some(owner));
 
@@ -93,9 +137,12 @@ mod tests {
     #[tokio::test]
     async fn test_sell_pump_fun() {
         let signer = make_test_signer();
+        let rpc_client = make_rpc_client();
         let mut tx = create_sell_pump_fun_tx(
             "76VCegXJdjqHXBdQyeVV3Swt3JgXrBoQpXcvRQsYpump".to_string(),
             (1. * 1e6) as u64,
+            DEFAULT_PUMP_SELL_SLIPPAGE_BPS,
+            &rpc_client,
             &Pubkey::from_str(&signer.pubkey()).unwrap(),
         )
         .await
@@ -103,4 +150,38 @@ mod tests {
         let result = signer.sign_and_send_solana_transaction(&mut tx).await;
         assert!(result.is_ok(), "{:?}", result);
     }
+
+    #[test]
+    fn test_pump_sell_min_sol_output() {
+        // captured from prod
+        let mut bonding_curve = BondingCurveLayout {
+            blob1: 6966180631402821399,
+            virtual_token_reserves: 1_072_964_268_463_317,
+            virtual_sol_reserves: 30_000_999_057,
+            real_token_reserves: 793_064_268_463_317,
+            real_sol_reserves: 999_057,
+            blob4: 1_000_000_000_000_000,
+            complete: false,
+        };
+        let token_amount = 17_852_389_307;
+
+        // 17852389307 * 30000999057 / (1072964268463317 + 17852389307)
+        // = 499_159 lamports, less the 1% fee
+        let expected = 499_159 - 4_991;
+        assert_eq!(
+            pump_sell_min_sol_output(&bonding_curve, token_amount, 0)
+                .unwrap(),
+            expected
+        );
+        assert_eq!(
+            pump_sell_min_sol_output(&bonding_curve, token_amount, 500)
+                .unwrap(),
+            expected - expected * 500 / 10_000
+        );
+        assert!(pump_sell_min_sol_output(&bonding_curve, 0, 500).is_err());
+
+        bonding_curve.complete = true;
+        assert!(pump_sell_min_sol_output(&bonding_curve, token_amount, 500)
+            .is_err());
+    }
 }
@@ -6,7 +6,6 @@ use serde::Deserialize;
 use serde_json::json;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_client::rpc_config::RpcSendTransactionConfig;
-use solana_client::rpc_config::RpcSimulateTransactionConfig;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::transaction::VersionedTransaction;
 use solana_transaction_status::{
@@ -16,6 +15,7 @@ use std::cell::RefCell;
 use std::str::FromStr;
 use tracing::info;
 
+use crate::solana::simulation::simulate_transaction;
 use crate::solana::util::env;
 
 #[derive(Debug, Deserialize)]
@@ -78,23 +78,14 @@ pub async fn send_tx_fallback(tx: &VersionedTransaction) -> Result<String> {
 
 pub async fn send_tx(tx: &VersionedTransaction) -> Result<String> {
     if std::env::var("SKIP_SIMULATION").is_err() {
-        let simres = RpcClient::new(env("SOLANA_RPC_URL"))
-            .simulate_transaction_with_config(
-                tx,
-                RpcSimulateTransactionConfig {
-                    replace_recent_blockhash: true,
-                    ..RpcSimulateTransactionConfig::default()
-                },
-            )
+        simulate_transaction(&RpcClient::new(env("SOLANA_RPC_URL")), tx)
             .await?;
-        if simres.value.err.is_some() {
-            return Err(anyhow!(
-                "Transaction simulation failed: {:?}",
-                simres
-            ));
-        }
     }
+    broadcast_tx(tx).await
+}
 
+/// sends through jito, falling back to the RPC, without simulating first
+pub async fn broadcast_tx(tx: &VersionedTransaction) -> Result<String> {
     let signature = send_jito_tx(tx).await;
     if let Ok(signature) = &signature {
         tracing::info!(?signature, "send_jito_tx");
@@ -1,26 +1,45 @@
 use anyhow::{anyhow, Result};
 use chrono::Local;
 use env_logger::Builder;
+use futures::future::{BoxFuture, FutureExt};
 use log::LevelFilter;
 use serde::Deserialize;
 use solana_account_decoder::parse_account_data::ParsedAccount;
 use solana_account_decoder::UiAccountData;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_client::rpc_response::RpcKeyedAccount;
+use solana_sdk::address_lookup_table::state::AddressLookupTable;
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::hash::Hash;
 use solana_sdk::instruction::Instruction;
+use solana_sdk::message::VersionedMessage;
+use solana_sdk::native_token::lamports_to_sol;
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::signature::Keypair;
+use solana_sdk::signature::{Keypair, Signature};
 use solana_sdk::transaction::VersionedTransaction;
+use solana_transaction_status::TransactionConfirmationStatus;
+use std::fmt;
 use std::future::Future;
 use std::io::Write;
 use std::str::FromStr;
 use std::sync::Arc;
 
+use crate::chain_config::chain_config;
 use crate::common::wrap_unsafe;
 use crate::signer::solana::LocalSolanaSigner;
 use crate::signer::{SignerContext, TransactionSigner};
+use crate::solana::confirmation::{
+    land, BlockhashExpired, SentAttempt, TransactionFailed,
+};
+use crate::solana::jito::{append_tip, BundleResult, JitoClient, JitoConfig};
+use crate::solana::nonce::{fetch_lookup_tables, fetch_nonce, make_durable};
+use crate::solana::priority_fee::{
+    apply_priority_fee, escalate_priority_fee, PriorityFeeConfig,
+};
+use crate::solana::simulation::{simulate_transaction, SimulationError};
+use crate::solana::tools::create_rpc;
+use crate::solana::transaction::{get_jito_tip_pubkey, send_tx_fallback};
 
 pub fn env(var: &str) -> String {
     std::env::var(var).unwrap_or_else(|_| panic!("{} env var not set", var))
@@ -141,7 +160,9 @@ pub async fn verify_transaction(
     let confirmation = rpc_client
         .confirm_transaction_with_commitment(
             &signature.parse().unwrap(),
-            CommitmentConfig::confirmed(),
+            CommitmentConfig {
+                commitment: chain_config().solana.commitment,
+            },
         )
         .await;
 
@@ -160,23 +181,874 @@ pub fn parse_pubkey(s: &str) -> Result<Pubkey> {
     }
 }
 
+/// Index of `key` among the static accounts of `message`, adding it as an
+/// unsigned account if it isn't one yet. The accounts after it, including
+/// the ones loaded from lookup tables, shift up by one. Any signatures are
+/// invalidated.
+pub fn static_account_index(
+    message: &mut VersionedMessage,
+    key: &Pubkey,
+    writable: bool,
+) -> Result<u8> {
+    let keys = message.static_account_keys();
+    if let Some(index) = keys.iter().position(|k| k == key) {
+        if writable && !message.is_maybe_writable(index, None) {
+            return Err(anyhow!("{} is readonly in the transaction", key));
+        }
+        return Ok(index as u8);
+    }
+    let (header, account_keys, instructions) = match message {
+        VersionedMessage::Legacy(message) => (
+            &mut message.header,
+            &mut message.account_keys,
+            &mut message.instructions,
+        ),
+        VersionedMessage::V0(message) => (
+            &mut message.header,
+            &mut message.account_keys,
+            &mut message.instructions,
+        ),
+    };
+    // writable unsigned accounts come before the readonly unsigned ones
+    let index = if writable {
+        account_keys.len() - header.num_readonly_unsigned_accounts as usize
+    } else {
+        account_keys.len()
+    };
+    let index = u8::try_from(index)
+        .ok()
+        .filter(|_| account_keys.len() < u8::MAX as usize)
+        .ok_or_else(|| anyhow!("Transaction has too many accounts"))?;
+    account_keys.insert(index as usize, *key);
+    if !writable {
+        header.num_readonly_unsigned_accounts += 1;
+    }
+    for ix in instructions {
+        for account in
+            std::iter::once(&mut ix.program_id_index).chain(&mut ix.accounts)
+        {
+            if *account >= index {
+                *account += 1;
+            }
+        }
+    }
+    Ok(index)
+}
+
+/// a confirmed transaction and what it paid to land
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutedTransaction {
+    pub signature: String,
+    pub priority_fee_lamports: u64,
+    pub jito_tip_lamports: u64,
+    /// its Jito bundle didn't land in time and it was sent through the RPC
+    pub fell_back_to_rpc: bool,
+    pub slot: u64,
+    pub confirmation_status: TransactionConfirmationStatus,
+    /// more than 1 when the blockhash of the earlier ones expired and the
+    /// transaction was built again
+    pub attempts: u32,
+}
+
+fn confirmation_status_name(status: &TransactionConfirmationStatus) -> &str {
+    match status {
+        TransactionConfirmationStatus::Processed => "processed",
+        TransactionConfirmationStatus::Confirmed => "confirmed",
+        TransactionConfirmationStatus::Finalized => "finalized",
+    }
+}
+
+/// the signature as its own word, clients link the first one, followed by
+/// where it was confirmed, the fees and tip it paid, a fallback to the RPC
+/// and the rebuilds it took
+impl fmt::Display for ExecutedTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} in slot {}",
+            self.signature,
+            confirmation_status_name(&self.confirmation_status),
+            self.slot
+        )?;
+        let paid = [
+            ("priority fee", self.priority_fee_lamports),
+            ("Jito tip", self.jito_tip_lamports),
+        ]
+        .into_iter()
+        .filter(|(_, lamports)| *lamports > 0)
+        .map(|(name, lamports)| {
+            format!("{}: {} SOL", name, lamports_to_sol(lamports))
+        })
+        .collect::<Vec<_>>();
+        if !paid.is_empty() {
+            write!(f, " ({})", paid.join(", "))?;
+        }
+        if self.fell_back_to_rpc {
+            write!(
+                f,
+                ", sent through the RPC after the Jito bundle didn't land"
+            )?;
+        }
+        if self.attempts > 1 {
+            write!(
+                f,
+                ", on attempt {} after the earlier blockhash expired",
+                self.attempts
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The transaction couldn't be built, e.g. there was no route for a swap,
+/// so nothing was sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildFailed {
+    pub error: String,
+}
+
+impl fmt::Display for BuildFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to build the transaction: {}", self.error)
+    }
+}
+
+impl std::error::Error for BuildFailed {}
+
+/// whether `e` means the transaction wasn't applied and never will be, so
+/// another way of doing the same can be tried without doing it twice. Any
+/// other error, e.g. the RPC failing while confirming, may hide a
+/// transaction that landed
+pub fn nothing_landed(e: &anyhow::Error) -> bool {
+    e.is::<BuildFailed>()
+        || e.is::<SimulationError>()
+        || e.is::<TransactionFailed>()
+        || e.is::<BlockhashExpired>()
+}
+
+/// how `execute_solana_transaction_with_options` lands a transaction
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExecuteOptions {
+    /// sends without the pre-flight simulation, for snipes where the extra
+    /// round trip costs more than a failed transaction
+    pub skip_simulation: bool,
+    /// replaces the priority fee of the chain config
+    pub priority: Option<PriorityFeeConfig>,
+    /// tips the transaction and sends it as a Jito bundle instead of
+    /// through the public mempool, see `JitoConfig`
+    pub send_via_jito: bool,
+}
+
+/// confirms the transaction, see `ConfirmationConfig`; it is not built
+/// again when its blockhash expires, only signed again with
+/// `ConfirmationConfig::resign_expired`
 pub async fn execute_solana_transaction<F, Fut>(
     tx_creator: F,
 ) -> Result<String>
 where
     F: FnOnce(Pubkey) -> Fut + Send + 'static,
     Fut: Future<Output = Result<VersionedTransaction>> + Send + 'static,
+{
+    execute_solana_transaction_with(tx_creator, false).await
+}
+
+/// `skip_simulation` sends without the pre-flight simulation, for snipes
+/// where the extra round trip costs more than a failed transaction
+pub async fn execute_solana_transaction_with<F, Fut>(
+    tx_creator: F,
+    skip_simulation: bool,
+) -> Result<String>
+where
+    F: FnOnce(Pubkey) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<VersionedTransaction>> + Send + 'static,
+{
+    let options = ExecuteOptions {
+        skip_simulation,
+        ..Default::default()
+    };
+    Ok(execute(run_once(tx_creator, 0), options, false, vec![])
+        .await?
+        .signature)
+}
+
+/// for transactions that other keypairs sign too, e.g. a new account.
+/// They sign after the signer set the blockhash, once the priority fee and
+/// the tip are in, a signature from `tx_creator` wouldn't hold
+pub async fn execute_solana_transaction_with_co_signers<F, Fut>(
+    tx_creator: F,
+    co_signers: Vec<Arc<Keypair>>,
+) -> Result<String>
+where
+    F: FnOnce(Pubkey) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<VersionedTransaction>> + Send + 'static,
+{
+    let tx_creator = run_once(tx_creator, co_signers.len());
+    Ok(
+        execute(tx_creator, ExecuteOptions::default(), false, co_signers)
+            .await?
+            .signature,
+    )
+}
+
+/// a transaction whose blockhash expired before it landed is built again
+/// with `tx_creator`, up to `ConfirmationConfig::max_rebuilds` times; it has
+/// to fetch what the transaction depends on again, e.g. a fresh quote for
+/// a swap, rather than reuse it
+pub async fn execute_solana_transaction_with_options<F, Fut>(
+    tx_creator: F,
+    options: ExecuteOptions,
+) -> Result<ExecutedTransaction>
+where
+    F: Fn(Pubkey) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<VersionedTransaction>> + Send + 'static,
+{
+    execute(tx_creator, options, true, vec![]).await
+}
+
+/// `tx_creator` as a creator that builds the transaction once, for the
+/// callers that don't allow rebuilds. Later attempts, with
+/// `ConfirmationConfig::resign_expired`, get the same transaction to sign
+/// again with a fresh blockhash, unless it needs more signatures than the
+/// signer and its `co_signers`
+fn run_once<F, Fut>(
+    tx_creator: F,
+    co_signers: usize,
+) -> impl Fn(Pubkey) -> BoxFuture<'static, Result<VersionedTransaction>>
+       + Send
+       + Sync
+       + 'static
+where
+    F: FnOnce(Pubkey) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<VersionedTransaction>> + Send + 'static,
+{
+    let tx_creator = std::sync::Mutex::new(Some(tx_creator));
+    let built = Arc::new(std::sync::Mutex::new(None));
+    move |owner| {
+        if let Some(tx_creator) = tx_creator.lock().unwrap().take() {
+            let built = built.clone();
+            return async move {
+                let tx: VersionedTransaction = tx_creator(owner).await?;
+                *built.lock().unwrap() = Some(tx.clone());
+                Ok(tx)
+            }
+            .boxed();
+        }
+        let tx = built.lock().unwrap().clone();
+        futures::future::ready(match tx {
+            Some(tx)
+                if tx.message.header().num_required_signatures as usize
+                    <= 1 + co_signers =>
+            {
+                Ok(tx)
+            }
+            _ => Err(anyhow!("the transaction can't be built again")),
+        })
+        .boxed()
+    }
+}
+
+/// what an attempt paid to land
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Paid {
+    priority_fee_lamports: u64,
+    jito_tip_lamports: u64,
+    fell_back_to_rpc: bool,
+}
+
+async fn execute<F, Fut>(
+    tx_creator: F,
+    options: ExecuteOptions,
+    rebuildable: bool,
+    co_signers: Vec<Arc<Keypair>>,
+) -> Result<ExecutedTransaction>
+where
+    F: Fn(Pubkey) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<VersionedTransaction>> + Send + 'static,
 {
     let signer = SignerContext::current().await;
     let owner = Pubkey::from_str(&signer.pubkey())?;
+    let tx_creator = Arc::new(tx_creator);
+    let rpc_client = create_rpc();
+    let config = &chain_config().solana;
+    // signers slow enough to outlive a blockhash, e.g. a ledger waiting for
+    // the user, sign with the durable nonce of the wallet, see
+    // `TransactionSigner::needs_durable_nonce`. A durable nonce doesn't
+    // expire, so there is nothing to sign again
+    let long_lived = signer.needs_durable_nonce();
+    let max_attempts = if rebuildable
+        || (config.confirmation.resign_expired && !long_lived)
+    {
+        1 + config.confirmation.max_rebuilds
+    } else {
+        1
+    };
 
+    let landed = land(
+        &rpc_client,
+        config.commitment,
+        &config.confirmation,
+        max_attempts,
+        |attempt| {
+            send_attempt(
+                signer.clone(),
+                &rpc_client,
+                owner,
+                tx_creator.clone(),
+                long_lived,
+                &options,
+                &co_signers,
+                attempt,
+            )
+        },
+    )
+    .await?;
+    Ok(ExecutedTransaction {
+        signature: landed.signature.to_string(),
+        priority_fee_lamports: landed.sent.priority_fee_lamports,
+        jito_tip_lamports: landed.sent.jito_tip_lamports,
+        fell_back_to_rpc: landed.sent.fell_back_to_rpc,
+        slot: landed.slot,
+        confirmation_status: landed.status,
+        attempts: landed.attempts,
+    })
+}
+
+/// builds, prices, signs and sends the transaction once, the `attempt`th
+/// time; retries pay more, see `ConfirmationConfig::priority_fee_bump_pct`
+async fn send_attempt<F, Fut>(
+    signer: Arc<dyn TransactionSigner>,
+    rpc_client: &RpcClient,
+    owner: Pubkey,
+    tx_creator: Arc<F>,
+    long_lived: bool,
+    options: &ExecuteOptions,
+    co_signers: &[Arc<Keypair>],
+    attempt: u32,
+) -> Result<SentAttempt<Paid>>
+where
+    F: Fn(Pubkey) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<VersionedTransaction>> + Send + 'static,
+{
     let mut tx = wrap_unsafe(move || async move { tx_creator(owner).await })
         .await
-        .map_err(|e| anyhow!("{:#?}", e))?;
+        .map_err(|e| BuildFailed {
+            error: format!("{:#?}", e),
+        })?;
+
+    // the compute unit limit comes from the simulation, so it runs on the
+    // transaction as built
+    let units_consumed = if options.skip_simulation {
+        None
+    } else {
+        simulate_transaction(rpc_client, &tx).await?
+    };
+    let priority = options
+        .priority
+        .clone()
+        .unwrap_or_else(|| chain_config().solana.priority_fee.clone());
+    apply_priority_fee(rpc_client, &mut tx, &priority, units_consumed)
+        .await?;
+    let priority_fee_lamports = escalate_priority_fee(
+        &mut tx,
+        chain_config().solana.confirmation.priority_fee_bump_pct,
+        attempt - 1,
+    )?;
+    let jito = &chain_config().solana.jito;
+    let jito_tip_lamports = if options.send_via_jito {
+        let tip = match jito.tip_lamports {
+            Some(tip) => tip,
+            None => JitoClient::new(jito).tip_floor_lamports().await?,
+        };
+        append_tip(&mut tx, &owner, &get_jito_tip_pubkey(), tip)?;
+        tip
+    } else {
+        0
+    };
+
+    // after the compute budget, the advance nonce instruction goes in front
+    let (tx, durable) = if long_lived {
+        with_durable_nonce(rpc_client, &owner, tx).await?
+    } else {
+        (tx, false)
+    };
+    // signers put in a fresh blockhash, expiry is told by the one signed,
+    // the co-signers sign the message as it is then
+    let co_signers = co_signers.to_vec();
+    let (signature, signed_blockhash, fell_back_to_rpc) =
+        if options.send_via_jito {
+            let (bundle, blockhash) =
+                sign_and_bundle(signer, tx, jito.clone(), co_signers).await?;
+            (bundle.signature, blockhash, bundle.fell_back_to_rpc)
+        } else {
+            let (signature, blockhash) =
+                sign_and_send(signer, tx, co_signers).await?;
+            (signature, blockhash, false)
+        };
+    // a durable nonce doesn't expire
+    let blockhash = (!durable).then_some(signed_blockhash);
+    Ok(SentAttempt {
+        signature: Signature::from_str(&signature)?,
+        blockhash,
+        sent: Paid {
+            priority_fee_lamports,
+            jito_tip_lamports,
+            fell_back_to_rpc,
+        },
+    })
+}
+
+/// falls back to a recent blockhash when the wallet has no nonce account or
+/// the transaction can't take one, returns whether the nonce was used
+async fn with_durable_nonce(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    tx: VersionedTransaction,
+) -> Result<(VersionedTransaction, bool)> {
+    match fetch_nonce(rpc_client, owner).await? {
+        Some(nonce) => match fetch_lookup_tables(rpc_client, &tx)
+            .await
+            .and_then(|tables| make_durable(&tx, owner, nonce, &tables))
+        {
+            Ok(durable) => Ok((durable, true)),
+            Err(e) => {
+                tracing::warn!(%owner, %e, "using a recent blockhash");
+                Ok((tx, false))
+            }
+        },
+        None => {
+            tracing::warn!(
+                %owner,
+                "no nonce account, using a recent blockhash"
+            );
+            Ok((tx, false))
+        }
+    }
+}
+
+/// the addresses of the address lookup table `table`, `None` if there is
+/// no such account
+pub async fn fetch_lookup_table(
+    rpc_client: &RpcClient,
+    table: &Pubkey,
+) -> Result<Option<Vec<Pubkey>>> {
+    let account = rpc_client
+        .get_account_with_commitment(table, rpc_client.commitment())
+        .await?
+        .value;
+    let Some(account) = account else {
+        return Ok(None);
+    };
+    let lookup_table = AddressLookupTable::deserialize(&account.data)
+        .map_err(|e| anyhow!("{} is not a lookup table: {}", table, e))?;
+    Ok(Some(lookup_table.addresses.to_vec()))
+}
+
+/// a failing simulation returns a `SimulationError` and nothing is sent
+pub async fn simulate_and_send(
+    signer: Arc<dyn TransactionSigner>,
+    rpc_client: &RpcClient,
+    tx: VersionedTransaction,
+    skip_simulation: bool,
+) -> Result<String> {
+    if !skip_simulation {
+        simulate_transaction(rpc_client, &tx).await?;
+    }
+    Ok(sign_and_send(signer, tx, vec![]).await?.0)
+}
+
+/// the signature and the blockhash the transaction was signed with
+async fn sign_and_send(
+    signer: Arc<dyn TransactionSigner>,
+    mut tx: VersionedTransaction,
+    co_signers: Vec<Arc<Keypair>>,
+) -> Result<(String, Hash)> {
+    wrap_unsafe(move || async move {
+        let signature = SignerContext::with_co_signers(
+            co_signers,
+            signer.sign_and_send_solana_transaction(&mut tx),
+        )
+        .await?;
+        Ok((signature, *tx.message.recent_blockhash()))
+    })
+    .await
+    .map_err(|e| anyhow!("{:#?}", e))
+}
 
+/// the fallback sends the signed transaction through the RPC, it can't
+/// land twice
+async fn sign_and_bundle(
+    signer: Arc<dyn TransactionSigner>,
+    mut tx: VersionedTransaction,
+    config: JitoConfig,
+    co_signers: Vec<Arc<Keypair>>,
+) -> Result<(BundleResult, Hash)> {
     wrap_unsafe(move || async move {
-        signer.sign_and_send_solana_transaction(&mut tx).await
+        SignerContext::with_co_signers(
+            co_signers,
+            signer.sign_solana_transaction(&mut tx),
+        )
+        .await?;
+        let fallback =
+            config.fallback_to_rpc.then_some(
+                |tx: VersionedTransaction| async move {
+                    send_tx_fallback(&tx).await
+                },
+            );
+        let bundle = JitoClient::new(&config)
+            .send_transaction(&tx, fallback)
+            .await?;
+        Ok((bundle, *tx.message.recent_blockhash()))
     })
     .await
     .map_err(|e| anyhow!("{:#?}", e))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::testing::CountingSigner;
+    use crate::solana::confirmation::{
+        ConfirmationConfig, ConfirmationRpc, ConfirmationTimeout,
+    };
+    use crate::solana::priority_fee::{
+        compute_unit_price_of, set_compute_budget,
+    };
+    use crate::solana::simulation::{
+        SimulationError, SimulationFailureCause,
+    };
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::commitment_config::CommitmentLevel;
+    use solana_sdk::instruction::InstructionError;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::system_instruction;
+    use solana_sdk::transaction::{Transaction, TransactionError};
+    use solana_transaction_status::TransactionStatus;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn transfer_tx(owner: &Pubkey) -> VersionedTransaction {
+        Transaction::new_with_payer(
+            &[system_instruction::transfer(
+                owner,
+                &Pubkey::new_unique(),
+                1,
+            )],
+            Some(owner),
+        )
+        .into()
+    }
+
+    #[tokio::test]
+    async fn test_failed_simulation_is_not_sent() {
+        let signer = Arc::new(CountingSigner::new());
+        let error = TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(1),
+        );
+        let rpc_client = RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([(
+                RpcRequest::SimulateTransaction,
+                json!({
+                    "context": { "slot": 1 },
+                    "value": {
+                        "err": error,
+                        "logs": [
+                            "Transfer: insufficient lamports 0, need 1"
+                        ],
+                        "unitsConsumed": 150,
+                    }
+                }),
+            )]),
+        );
+
+        let err = simulate_and_send(
+            signer.clone(),
+            &rpc_client,
+            transfer_tx(&signer.pubkey),
+            false,
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(signer.sent(), 0);
+        let err = err.downcast::<SimulationError>().unwrap();
+        assert_eq!(err.cause, SimulationFailureCause::InsufficientFunds);
+        assert_eq!(err.units_consumed, Some(150));
+        assert!(err.to_string().contains("insufficient lamports"));
+    }
+
+    #[tokio::test]
+    async fn test_passed_simulation_is_sent() {
+        let signer = Arc::new(CountingSigner::new());
+        let rpc_client = RpcClient::new_mock("succeeds".to_string());
+
+        let signature = simulate_and_send(
+            signer.clone(),
+            &rpc_client,
+            transfer_tx(&signer.pubkey),
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(signature, "signature");
+        assert_eq!(signer.sent(), 1);
+    }
+
+    #[test]
+    fn test_executed_transaction_display() {
+        let mut executed = ExecutedTransaction {
+            signature: "signature".to_string(),
+            priority_fee_lamports: 0,
+            jito_tip_lamports: 0,
+            fell_back_to_rpc: false,
+            slot: 42,
+            confirmation_status: TransactionConfirmationStatus::Confirmed,
+            attempts: 1,
+        };
+        assert_eq!(executed.to_string(), "signature confirmed in slot 42");
+        executed.priority_fee_lamports = 25_000;
+        assert_eq!(
+            executed.to_string(),
+            "signature confirmed in slot 42 (priority fee: 0.000025 SOL)"
+        );
+        executed.jito_tip_lamports = 10_000;
+        executed.fell_back_to_rpc = true;
+        executed.attempts = 2;
+        assert_eq!(
+            executed.to_string(),
+            "signature confirmed in slot 42 (priority fee: 0.000025 SOL, \
+             Jito tip: 0.00001 SOL), sent through the RPC after the Jito \
+             bundle didn't land, on attempt 2 after the earlier blockhash \
+             expired"
+        );
+        // the interface links the first word
+        assert_eq!(executed.to_string().split(' ').next(), Some("signature"));
+    }
+
+    #[test]
+    fn test_nothing_landed() {
+        let failed: anyhow::Error = TransactionFailed {
+            signature: "signature".to_string(),
+            slot: 1,
+            error: TransactionError::AccountNotFound,
+        }
+        .into();
+        assert!(nothing_landed(&failed));
+        let expired: anyhow::Error = BlockhashExpired {
+            signature: "signature".to_string(),
+            attempts: 1,
+        }
+        .into();
+        assert!(nothing_landed(&expired));
+        let not_built: anyhow::Error = BuildFailed {
+            error: "no route".to_string(),
+        }
+        .into();
+        assert!(nothing_landed(&not_built));
+
+        // the transaction may have landed behind these
+        assert!(!nothing_landed(&anyhow!("getSignatureStatuses failed")));
+        let timeout: anyhow::Error = ConfirmationTimeout {
+            signature: "signature".to_string(),
+            timeout: std::time::Duration::from_secs(1),
+        }
+        .into();
+        assert!(!nothing_landed(&timeout));
+    }
+
+    /// signs with a blockhash of its own, like the local and Privy signers
+    struct RehashingSigner {
+        pubkey: Pubkey,
+        blockhash: Hash,
+    }
+
+    #[async_trait::async_trait]
+    impl TransactionSigner for RehashingSigner {
+        fn pubkey(&self) -> String {
+            self.pubkey.to_string()
+        }
+
+        async fn sign_and_send_solana_transaction(
+            &self,
+            tx: &mut VersionedTransaction,
+        ) -> Result<String> {
+            tx.message.set_recent_blockhash(self.blockhash);
+            Ok("signature".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expiry_is_told_by_the_signed_blockhash() {
+        let signer = Arc::new(RehashingSigner {
+            pubkey: Pubkey::new_unique(),
+            blockhash: Hash::new_unique(),
+        });
+        let tx = transfer_tx(&signer.pubkey);
+        assert_ne!(*tx.message.recent_blockhash(), signer.blockhash);
+
+        let (_, blockhash) =
+            sign_and_send(signer.clone(), tx, vec![]).await.unwrap();
+        assert_eq!(blockhash, signer.blockhash);
+    }
+
+    /// the first signature never lands before its blockhash expires, any
+    /// other one is confirmed
+    struct ExpiringRpc {
+        first: Signature,
+    }
+
+    #[async_trait::async_trait]
+    impl ConfirmationRpc for ExpiringRpc {
+        async fn signature_status(
+            &self,
+            signature: &Signature,
+        ) -> Result<Option<TransactionStatus>> {
+            Ok((*signature != self.first).then(|| TransactionStatus {
+                slot: 5,
+                confirmations: None,
+                status: Ok(()),
+                err: None,
+                confirmation_status: Some(
+                    TransactionConfirmationStatus::Confirmed,
+                ),
+            }))
+        }
+
+        async fn is_blockhash_valid(
+            &self,
+            _blockhash: &Hash,
+            _commitment: CommitmentConfig,
+        ) -> Result<bool> {
+            Ok(false)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expired_transaction_is_resigned_with_a_higher_fee() {
+        let owner = Pubkey::new_unique();
+        let builds = Arc::new(AtomicUsize::new(0));
+        let _builds = builds.clone();
+        let creator = run_once(
+            move |owner| async move {
+                _builds.fetch_add(1, Ordering::SeqCst);
+                let mut tx = transfer_tx(&owner);
+                set_compute_budget(&mut tx, Some(20_000), None)?;
+                Ok(tx)
+            },
+            0,
+        );
+        let rpc = ExpiringRpc {
+            first: Signature::new_unique(),
+        };
+        let config = ConfirmationConfig {
+            timeout_secs: 5,
+            poll_interval_ms: 1,
+            ..Default::default()
+        };
+        let prices = std::sync::Mutex::new(Vec::new());
+        let (prices_ref, first) = (&prices, rpc.first);
+
+        let landed =
+            land(&rpc, CommitmentLevel::Confirmed, &config, 3, |attempt| {
+                let tx = creator(owner);
+                async move {
+                    let mut tx = tx.await?;
+                    escalate_priority_fee(&mut tx, 50, attempt - 1)?;
+                    prices_ref
+                        .lock()
+                        .unwrap()
+                        .push(compute_unit_price_of(&tx.message));
+                    Ok(SentAttempt {
+                        signature: if attempt == 1 {
+                            first
+                        } else {
+                            Signature::new_unique()
+                        },
+                        blockhash: Some(Hash::new_unique()),
+                        sent: (),
+                    })
+                }
+            })
+            .await
+            .unwrap();
+
+        // built once, signed again after the expiry, for 50% more
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+        assert_eq!(landed.attempts, 2);
+        assert_eq!(landed.slot, 5);
+        assert_eq!(*prices.lock().unwrap(), vec![20_000, 30_000]);
+    }
+
+    #[tokio::test]
+    async fn test_co_signed_transaction_is_not_resigned() {
+        let owner = Pubkey::new_unique();
+        let creator = run_once(
+            move |owner| async move {
+                let co_signer = Pubkey::new_unique();
+                Ok(Transaction::new_with_payer(
+                    &[system_instruction::transfer(&co_signer, &owner, 1)],
+                    Some(&owner),
+                )
+                .into())
+            },
+            0,
+        );
+        assert!(creator(owner).await.is_ok());
+        let err = creator(owner).await.unwrap_err();
+        assert!(err.to_string().contains("can't be built again"));
+
+        // unless the co-signer signs again too
+        let creator = run_once(
+            move |owner| async move {
+                let co_signer = Pubkey::new_unique();
+                Ok(Transaction::new_with_payer(
+                    &[system_instruction::transfer(&co_signer, &owner, 1)],
+                    Some(&owner),
+                )
+                .into())
+            },
+            1,
+        );
+        assert!(creator(owner).await.is_ok());
+        assert!(creator(owner).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_co_signature_holds_after_fee_and_tip() {
+        let payer = Keypair::new();
+        let new_account = Arc::new(Keypair::new());
+        let signer = Arc::new(
+            LocalSolanaSigner::from_keypair(payer.insecure_clone())
+                .with_rpc_client(Arc::new(RpcClient::new_mock(
+                    "succeeds".to_string(),
+                ))),
+        );
+        let mut tx: VersionedTransaction = Transaction::new_with_payer(
+            &[system_instruction::create_account(
+                &payer.pubkey(),
+                &new_account.pubkey(),
+                1_000_000,
+                0,
+                &Pubkey::new_unique(),
+            )],
+            Some(&payer.pubkey()),
+        )
+        .into();
+        // what `send_attempt` does to the message after it was built
+        set_compute_budget(&mut tx, Some(20_000), Some(100_000)).unwrap();
+        escalate_priority_fee(&mut tx, 50, 1).unwrap();
+        append_tip(&mut tx, &payer.pubkey(), &get_jito_tip_pubkey(), 1_000)
+            .unwrap();
+
+        SignerContext::with_co_signers(
+            vec![new_account],
+            signer.sign_solana_transaction(&mut tx),
+        )
+        .await
+        .unwrap();
+        assert!(tx.verify_with_results().iter().all(|ok| *ok));
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction;
+use spl_associated_token_account::{
+    get_associated_token_address,
+    instruction::create_associated_token_account_idempotent,
+};
+
+/// the WSOL account of `owner`, where swaps wrap SOL into
+pub fn wsol_address(owner: &Pubkey) -> Pubkey {
+    get_associated_token_address(owner, &spl_token::native_mint::id())
+}
+
+/// Moves `lamports` into the WSOL account of `owner`, creating it when
+/// missing, and syncs its token balance to them
+pub fn wrap_sol_ixs(
+    owner: &Pubkey,
+    lamports: u64,
+) -> Result<Vec<Instruction>> {
+    let wsol_account = wsol_address(owner);
+    Ok(vec![
+        create_associated_token_account_idempotent(
+            owner,
+            owner,
+            &spl_token::native_mint::id(),
+            &spl_token::id(),
+        ),
+        system_instruction::transfer(owner, &wsol_account, lamports),
+        spl_token::instruction::sync_native(&spl_token::id(), &wsol_account)?,
+    ])
+}
+
+/// closing the WSOL account and the lamports it returns to the owner, the
+/// wrapped SOL and the rent
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnwrapPlan {
+    pub instructions: Vec<Instruction>,
+    pub lamports: u64,
+}
+
+/// The unwrap of the WSOL account of `owner`, `None` when there is no
+/// account and so nothing to unwrap
+pub async fn plan_unwrap_sol(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Option<UnwrapPlan>> {
+    let wsol_account = wsol_address(owner);
+    let account = rpc_client
+        .get_account_with_commitment(&wsol_account, rpc_client.commitment())
+        .await?
+        .value;
+    let Some(account) = account else {
+        return Ok(None);
+    };
+    Ok(Some(UnwrapPlan {
+        instructions: vec![spl_token::instruction::close_account(
+            &spl_token::id(),
+            &wsol_account,
+            owner,
+            owner,
+            &[],
+        )?],
+        lamports: account.lamports,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use solana_client::rpc_request::RpcRequest;
+    use std::collections::HashMap;
+
+    fn mock_rpc(account: Value) -> RpcClient {
+        RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([(
+                RpcRequest::GetAccountInfo,
+                json!({ "context": { "slot": 1 }, "value": account }),
+            )]),
+        )
+    }
+
+    #[test]
+    fn test_wrap_sol_ixs() {
+        let owner = Pubkey::new_unique();
+        let wsol_account = wsol_address(&owner);
+        let ixs = wrap_sol_ixs(&owner, 1_000_000).unwrap();
+
+        let programs = ixs.iter().map(|ix| ix.program_id).collect::<Vec<_>>();
+        assert_eq!(
+            programs,
+            vec![
+                spl_associated_token_account::id(),
+                solana_sdk::system_program::id(),
+                spl_token::id(),
+            ]
+        );
+        assert_eq!(
+            ixs[1],
+            system_instruction::transfer(&owner, &wsol_account, 1_000_000)
+        );
+        assert_eq!(
+            ixs[2],
+            spl_token::instruction::sync_native(
+                &spl_token::id(),
+                &wsol_account
+            )
+            .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plan_unwrap_sol() {
+        let owner = Pubkey::new_unique();
+        let rpc = mock_rpc(json!({
+            "data": ["", "base64"],
+            "executable": false,
+            "lamports": 1_002_039_280u64,
+            "owner": spl_token::id().to_string(),
+            "rentEpoch": 0,
+            "space": 165,
+        }));
+
+        let plan = plan_unwrap_sol(&rpc, &owner).await.unwrap().unwrap();
+
+        assert_eq!(plan.lamports, 1_002_039_280);
+        assert_eq!(
+            plan.instructions,
+            vec![spl_token::instruction::close_account(
+                &spl_token::id(),
+                &wsol_address(&owner),
+                &owner,
+                &owner,
+                &[],
+            )
+            .unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plan_unwrap_sol_without_account() {
+        let rpc = mock_rpc(Value::Null);
+        let plan = plan_unwrap_sol(&rpc, &Pubkey::new_unique()).await;
+        assert_eq!(plan.unwrap(), None);
+    }
+}
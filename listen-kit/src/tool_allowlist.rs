@@ -0,0 +1,144 @@
+//! The tools a deployment lets its agents register. A tool that isn't
+//! registered can't be called, whatever the prompt asks for
+use anyhow::{anyhow, Result};
+use rig::agent::AgentBuilder;
+use rig::completion::CompletionModel;
+use rig::tool::Tool;
+use std::collections::HashSet;
+
+use crate::reasoning_loop::TRANSACTION_TOOLS;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolAllowlist {
+    /// the only tool names allowed, every tool of the agent if none
+    only: Option<HashSet<String>>,
+    /// leaves out the tools that submit transactions, see
+    /// `TRANSACTION_TOOLS`
+    read_only: bool,
+}
+
+impl ToolAllowlist {
+    /// every tool of the agent
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn only<I, S>(tools: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            only: Some(tools.into_iter().map(Into::into).collect()),
+            read_only: false,
+        }
+    }
+
+    /// narrows the allowlist down to the tools that don't transact, e.g.
+    /// for an analyst that only quotes and looks up prices
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    /// `ALLOWED_TOOLS` is a comma separated list of tool names,
+    /// `READ_ONLY_TOOLS=true` leaves out the tools that transact
+    pub fn from_vars(get: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut allowlist = match get("ALLOWED_TOOLS") {
+            Some(tools) => {
+                let tools = tools
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tool| !tool.is_empty())
+                    .collect::<Vec<_>>();
+                if tools.is_empty() {
+                    return Err(anyhow!("ALLOWED_TOOLS has no tools"));
+                }
+                Self::only(tools)
+            }
+            None => Self::all(),
+        };
+        if let Some(read_only) = get("READ_ONLY_TOOLS") {
+            if read_only.parse::<bool>().map_err(|_| {
+                anyhow!("READ_ONLY_TOOLS must be true or false")
+            })? {
+                allowlist = allowlist.read_only();
+            }
+        }
+        Ok(allowlist)
+    }
+
+    pub fn allows(&self, tool: &str) -> bool {
+        if self.read_only && TRANSACTION_TOOLS.contains(&tool) {
+            return false;
+        }
+        self.only.as_ref().map_or(true, |only| only.contains(tool))
+    }
+}
+
+/// `tool` of the agent builder for the tools the allowlist allows
+pub trait AllowlistedTools: Sized {
+    fn allowed_tool<T: Tool + 'static>(
+        self,
+        tool: T,
+        allowlist: &ToolAllowlist,
+    ) -> Self;
+}
+
+impl<M: CompletionModel> AllowlistedTools for AgentBuilder<M> {
+    fn allowed_tool<T: Tool + 'static>(
+        self,
+        tool: T,
+        allowlist: &ToolAllowlist,
+    ) -> Self {
+        if allowlist.allows(T::NAME) {
+            self.tool(tool)
+        } else {
+            self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn from_vars(vars: &[(&str, &str)]) -> Result<ToolAllowlist> {
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        ToolAllowlist::from_vars(|key| vars.get(key).map(|v| v.to_string()))
+    }
+
+    #[test]
+    fn test_allows() {
+        assert!(ToolAllowlist::all().allows("transfer_sol"));
+        let read_only = ToolAllowlist::all().read_only();
+        assert!(!read_only.allows("transfer_sol"));
+        assert!(!read_only.allows("swap"));
+        assert!(read_only.allows("get_quote"));
+
+        let only = ToolAllowlist::only(["get_quote", "swap"]).read_only();
+        assert!(only.allows("get_quote"));
+        assert!(!only.allows("swap"));
+        assert!(!only.allows("get_sol_balance"));
+    }
+
+    #[test]
+    fn test_from_vars() {
+        assert_eq!(from_vars(&[]).unwrap(), ToolAllowlist::all());
+        assert_eq!(
+            from_vars(&[
+                ("ALLOWED_TOOLS", "get_quote, swap,"),
+                ("READ_ONLY_TOOLS", "true")
+            ])
+            .unwrap(),
+            ToolAllowlist::only(["get_quote", "swap"]).read_only()
+        );
+        assert!(from_vars(&[("ALLOWED_TOOLS", " , ")]).is_err());
+        assert!(from_vars(&[("READ_ONLY_TOOLS", "yes")]).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// results above this size are summarized or truncated before they are
+/// fed back to the model, the UI still gets them in full
+pub const DEFAULT_MAX_TOOL_RESULT_BYTES: usize = 16 * 1024;
+
+/// Turns a result that is over `max_bytes` into a shorter one, `None` if
+/// the result doesn't have the shape the summarizer understands
+pub type ToolResultSummarizer =
+    Arc<dyn Fn(&str, usize) -> Option<String> + Send + Sync>;
+
+/// Size caps of the tool results going back into the chat history
+#[derive(Clone)]
+pub struct ToolResultLimits {
+    max_bytes: usize,
+    per_tool: HashMap<String, usize>,
+    summarizers: HashMap<String, ToolResultSummarizer>,
+}
+
+impl Default for ToolResultLimits {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_TOOL_RESULT_BYTES).with_summarizer(
+            "get_portfolio",
+            Arc::new(summarize_by_usd_value),
+        )
+    }
+}
+
+impl ToolResultLimits {
+    /// without any summarizers, results over `max_bytes` are truncated
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            per_tool: HashMap::new(),
+            summarizers: HashMap::new(),
+        }
+    }
+
+    pub fn with_tool_limit(mut self, tool: &str, max_bytes: usize) -> Self {
+        self.per_tool.insert(tool.to_string(), max_bytes);
+        self
+    }
+
+    pub fn with_summarizer(
+        mut self,
+        tool: &str,
+        summarizer: ToolResultSummarizer,
+    ) -> Self {
+        self.summarizers.insert(tool.to_string(), summarizer);
+        self
+    }
+
+    pub fn limit_for(&self, tool: &str) -> usize {
+        self.per_tool.get(tool).copied().unwrap_or(self.max_bytes)
+    }
+
+    /// what the model gets to see of `result`
+    pub fn apply(&self, tool: &str, result: &str) -> String {
+        let max_bytes = self.limit_for(tool);
+        if result.len() <= max_bytes {
+            return result.to_string();
+        }
+        if let Some(summary) = self
+            .summarizers
+            .get(tool)
+            .and_then(|summarize| summarize(result, max_bytes))
+        {
+            if summary.len() <= max_bytes {
+                return summary;
+            }
+        }
+        truncate_result(result, max_bytes)
+    }
+}
+
+/// the value a tool serialized its result to, results that aren't JSON,
+/// like errors, as a string
+pub fn structured_result(result: &str) -> Value {
+    serde_json::from_str(result)
+        .unwrap_or_else(|_| Value::String(result.to_string()))
+}
+
+/// cuts `result` at a char boundary and appends a marker saying so
+pub fn truncate_result(result: &str, max_bytes: usize) -> String {
+    let mut end = max_bytes.min(result.len());
+    while !result.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!(
+        "{}\n[truncated: showing {} of {} bytes]",
+        &result[..end],
+        end,
+        result.len()
+    )
+}
+
+fn usd_value(item: &Value) -> f64 {
+    let price = item.get("price").and_then(Value::as_f64).unwrap_or(0.);
+    let amount = item.get("amount").and_then(Value::as_f64).unwrap_or(0.);
+    price * amount
+}
+
+/// For lists of holdings with `price` and `amount`: keeps the items with
+/// the highest USD value that fit and counts the rest, e.g.
+/// `{"items": [...], "omitted": 280, "omitted_usd_value": 1.2}`
+pub fn summarize_by_usd_value(
+    result: &str,
+    max_bytes: usize,
+) -> Option<String> {
+    let Ok(Value::Array(mut items)) = serde_json::from_str(result) else {
+        return None;
+    };
+    items.sort_by(|a, b| usd_value(b).total_cmp(&usd_value(a)));
+
+    let summary = |kept: &[Value], omitted: &[Value]| {
+        serde_json::json!({
+            "items": kept,
+            "omitted": omitted.len(),
+            "omitted_usd_value": omitted.iter().map(usd_value).sum::<f64>(),
+        })
+        .to_string()
+    };
+
+    // estimate the largest prefix that fits from the item sizes, then back
+    // off in case the omitted totals serialize longer than estimated
+    let mut size = summary(&[], &items).len();
+    let mut kept = 0;
+    for item in &items {
+        size += item.to_string().len() + 1;
+        if size > max_bytes {
+            break;
+        }
+        kept += 1;
+    }
+    loop {
+        let result = summary(&items[..kept], &items[kept..]);
+        if result.len() <= max_bytes || kept == 0 {
+            return Some(result);
+        }
+        kept -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_truncate_result() {
+        let limits = ToolResultLimits::new(10);
+        assert_eq!(limits.apply("any", "short"), "short");
+
+        let truncated = limits.apply("any", &"é".repeat(20));
+        assert!(truncated.starts_with(&"é".repeat(5)));
+        assert!(truncated.ends_with("[truncated: showing 10 of 40 bytes]"));
+
+        let limits = limits.with_tool_limit("big", 100);
+        assert_eq!(limits.apply("big", &"a".repeat(50)), "a".repeat(50));
+    }
+
+    #[test]
+    fn test_summarize_portfolio() {
+        let portfolio = (0..300)
+            .map(|i| {
+                json!({
+                    "address": format!("mint{}", i),
+                    "symbol": format!("T{}", i),
+                    "price": 0.0001,
+                    "amount": i as f64,
+                })
+            })
+            .chain(std::iter::once(json!({
+                "address": "whale",
+                "symbol": "SOL",
+                "price": 150.0,
+                "amount": 10.0,
+            })))
+            .collect::<Vec<_>>();
+        let result = serde_json::to_string(&portfolio).unwrap();
+
+        let limits = ToolResultLimits::default()
+            .with_tool_limit("get_portfolio", 1024);
+        let summary: Value =
+            serde_json::from_str(&limits.apply("get_portfolio", &result))
+                .unwrap();
+        let items = summary["items"].as_array().unwrap();
+        assert!(!items.is_empty());
+        assert_eq!(items[0]["address"], "whale");
+        assert_eq!(items[1]["address"], "mint299");
+        assert_eq!(
+            items.len() + summary["omitted"].as_u64().unwrap() as usize,
+            301
+        );
+    }
+
+    #[test]
+    fn test_summarizer_falls_back_to_truncation() {
+        let limits = ToolResultLimits::new(16).with_summarizer(
+            "get_portfolio",
+            Arc::new(summarize_by_usd_value),
+        );
+        let result = limits.apply("get_portfolio", &"not a list".repeat(10));
+        assert!(result.contains("[truncated: showing 16 of 100 bytes]"));
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::Result;
+use rig::completion::Message;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::audit::AuditContext;
+
+/// streamed responses carry no usage metadata, so token counts are
+/// estimated at roughly 4 characters per token
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// One model call, prompt tokens include the whole chat history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: String,
+    pub user_id: Option<String>,
+    pub conversation_id: Option<String>,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+}
+
+#[async_trait::async_trait]
+pub trait UsageReporter: Send + Sync {
+    async fn report(&self, record: UsageRecord) -> Result<()>;
+}
+
+pub struct NoopUsageReporter;
+
+#[async_trait::async_trait]
+impl UsageReporter for NoopUsageReporter {
+    async fn report(&self, _record: UsageRecord) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// size of a model call sending `prompt` on top of `history`
+pub fn estimate_prompt_tokens(prompt: &str, history: &[Message]) -> usize {
+    estimate_tokens(prompt)
+        + history
+            .iter()
+            .map(|message| {
+                serde_json::to_string(message)
+                    .map(|s| estimate_tokens(&s))
+                    .unwrap_or_default()
+            })
+            .sum::<usize>()
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+#[error(
+    "token budget exceeded: used {used} of {budget} tokens, next model call needs ~{next}"
+)]
+pub struct TokenBudgetExceeded {
+    pub used: usize,
+    pub budget: usize,
+    pub next: usize,
+}
+
+/// Cumulative prompt + completion tokens of a single loop invocation,
+/// shared with delegated child loops
+#[derive(Debug)]
+pub struct TokenBudget {
+    max_tokens_total: usize,
+    used: AtomicUsize,
+}
+
+impl TokenBudget {
+    pub fn new(max_tokens_total: usize) -> Self {
+        Self {
+            max_tokens_total,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    pub fn max_tokens_total(&self) -> usize {
+        self.max_tokens_total
+    }
+
+    /// fails if a call of `next` tokens would not fit the remaining budget
+    pub fn check(&self, next: usize) -> Result<(), TokenBudgetExceeded> {
+        let used = self.used();
+        if used + next > self.max_tokens_total {
+            return Err(TokenBudgetExceeded {
+                used,
+                budget: self.max_tokens_total,
+                next,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn record(&self, tokens: usize) {
+        self.used.fetch_add(tokens, Ordering::SeqCst);
+    }
+}
+
+/// reports a finished model call, failures are logged and not propagated
+pub async fn report_usage(
+    reporter: &dyn UsageReporter,
+    ctx: &AuditContext,
+    prompt_tokens: usize,
+    completion_tokens: usize,
+) {
+    let record = UsageRecord {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        user_id: ctx.user_id.clone(),
+        conversation_id: ctx.conversation_id.clone(),
+        prompt_tokens,
+        completion_tokens,
+    };
+    if let Err(e) = reporter.report(record).await {
+        tracing::warn!("failed to report usage: {}", e);
+    }
+}
+
+#[cfg(feature = "http")]
+pub use redis_reporter::RedisUsageReporter;
+
+#[cfg(feature = "http")]
+mod redis_reporter {
+    use super::{UsageRecord, UsageReporter};
+    use anyhow::Result;
+
+    /// Appends records to a per-user redis stream `usage:{user_id}`
+    pub struct RedisUsageReporter {
+        client: redis::Client,
+    }
+
+    impl RedisUsageReporter {
+        pub fn new(redis_url: &str) -> Result<Self> {
+            Ok(Self {
+                client: redis::Client::open(redis_url)?,
+            })
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl UsageReporter for RedisUsageReporter {
+        async fn report(&self, record: UsageRecord) -> Result<()> {
+            let user_id = record.user_id.as_deref().unwrap_or("anonymous");
+            let mut conn =
+                self.client.get_multiplexed_async_connection().await?;
+            let _: String = redis::cmd("XADD")
+                .arg(format!("usage:{}", user_id))
+                .arg("*")
+                .arg("record")
+                .arg(serde_json::to_string(&record)?)
+                .query_async(&mut conn)
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::message::UserContent;
+    use rig::OneOrMany;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+
+        let history = vec![Message::User {
+            content: OneOrMany::one(UserContent::text("a".repeat(400))),
+        }];
+        assert!(estimate_prompt_tokens("abcd", &history) > 100);
+    }
+
+    #[test]
+    fn test_token_budget_check() {
+        let budget = TokenBudget::new(100);
+        assert!(budget.check(100).is_ok());
+        budget.record(60);
+        assert_eq!(
+            budget.check(41),
+            Err(TokenBudgetExceeded {
+                used: 60,
+                budget: 100,
+                next: 41
+            })
+        );
+        assert!(budget.check(40).is_ok());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::http_client::HttpClient;
+use crate::reserves::PoolType;
 use crate::seller_service::SellRequest;
 use crate::util::healthz;
 use crate::{
@@ -73,6 +74,7 @@ async fn handle_buy(
                 output_mint: buy_request.input_mint,
                 lamports_spent: buy_request.amount,
                 insta: None,
+                pool_type: PoolType::Raydium,
             })
             .await
         {
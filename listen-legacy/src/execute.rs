@@ -12,8 +12,14 @@ use solana_sdk::{
     signature::Keypair,
 };
 
+use crate::jup::Jupiter;
+use crate::reserves::{self, PoolType};
 use crate::{buyer, constants, seller::Pool};
 
+/// how often the reserves of non-raydium pools are re-read
+const RESERVES_POLL_INTERVAL: tokio::time::Duration =
+    tokio::time::Duration::from_millis(400);
+
 #[derive(Debug)]
 pub struct Executor {
     pub lamports_in: u64,
@@ -21,7 +27,11 @@ pub struct Executor {
     pub remaining_token_balance: u64,
     pub funder: Keypair,
 
-    pub amm_keys: amm::AmmKeys,
+    /// only set for raydium pools, the other dexes are exited through
+    /// jupiter
+    pub amm_keys: Option<amm::AmmKeys>,
+    pub pool_type: PoolType,
+    pub token_mint: Pubkey,
 
     // denoted as pct, bool flag vec
     pub tp_levels: Vec<f64>,
@@ -40,20 +50,19 @@ impl Executor {
         pubsub_client: &PubsubClient,
         amm_pool: &Pubkey,
     ) -> Result<bool, Box<dyn std::error::Error>> {
-        let coin_mint_is_sol = self
-            .amm_keys
-            .amm_coin_mint
-            .eq(&constants::SOLANA_PROGRAM_ID);
+        if self.pool_type != PoolType::Raydium {
+            return self.execute_polling(rpc_client, amm_pool).await;
+        }
+        let amm_keys =
+            self.amm_keys.ok_or("amm keys are required for raydium")?;
+        let coin_mint_is_sol =
+            amm_keys.amm_coin_mint.eq(&constants::SOLANA_PROGRAM_ID);
         let (token_vault, sol_vault) = if coin_mint_is_sol {
-            (self.amm_keys.amm_pc_vault, self.amm_keys.amm_coin_vault)
-        } else {
-            (self.amm_keys.amm_coin_vault, self.amm_keys.amm_pc_vault)
-        };
-        let token_mint = if coin_mint_is_sol {
-            self.amm_keys.amm_pc_mint
+            (amm_keys.amm_pc_vault, amm_keys.amm_coin_vault)
         } else {
-            self.amm_keys.amm_coin_mint
+            (amm_keys.amm_coin_vault, amm_keys.amm_pc_vault)
         };
+        let token_mint = self.token_mint;
 
         let (mut token_stream, token_unsub) = pubsub_client
             .account_subscribe(
@@ -145,6 +154,62 @@ impl Executor {
         }
     }
 
+    /// whirlpool and meteora reserves are decoded from the pool state
+    /// rather than streamed from the vaults, so those are polled
+    async fn execute_polling(
+        &mut self,
+        rpc_client: &RpcClient,
+        pool: &Pubkey,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        info!(
+            "polling {:?} reserves for {}",
+            self.pool_type,
+            self.token_mint.to_string()
+        );
+        let deadline = tokio::time::Instant::now()
+            + tokio::time::Duration::from_secs(3000);
+        while tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(RESERVES_POLL_INTERVAL).await;
+            let reserves = match reserves::get_pool_reserves(
+                self.pool_type,
+                pool,
+                rpc_client,
+            )
+            .await
+            {
+                Ok(reserves) => reserves,
+                Err(e) => {
+                    warn!("could not get reserves: {}", e);
+                    continue;
+                }
+            };
+            if reserves.token == 0 || reserves.sol == 0 {
+                continue;
+            }
+            let mut state = Pool::default();
+            state.token_vault.amount = reserves.token;
+            state.sol_vault.amount = reserves.sol;
+            let lamports_out =
+                state.calculate_sol_amount_out(self.token_balance);
+            let sell_amount =
+                self.get_sell_amount(self.lamports_in, lamports_out);
+            if sell_amount != 0 {
+                let quote = Jupiter::fetch_quote(
+                    &self.token_mint.to_string(),
+                    &constants::SOLANA_PROGRAM_ID.to_string(),
+                    sell_amount,
+                    500,
+                )
+                .await?;
+                let signature = Jupiter::swap(quote, &self.funder).await?;
+                info!("sold {} ({})", sell_amount, signature);
+                self.remaining_token_balance -= sell_amount;
+            }
+        }
+        warn!("timeout");
+        Ok(true)
+    }
+
     pub fn get_sell_amount(
         &mut self,
         lamports_in: u64,
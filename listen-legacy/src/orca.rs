@@ -59,9 +59,20 @@ pub async fn get_whirpool(
 
     let account_data = raw_account.value.ok_or("No account data")?.data;
 
+    parse_whirlpool(&account_data)
+}
+
+/// decodes a raw whirlpool account, including the anchor discriminator
+pub fn parse_whirlpool(
+    account_data: &[u8],
+) -> Result<Whirlpool, Box<dyn Error>> {
     // Print the length of the account data
     debug!("Account data length: {}", account_data.len());
 
+    if account_data.len() < 8 {
+        return Err("Whirlpool account data too short".into());
+    }
+
     // Attempt to deserialize, but print more info if it fails
     match Whirlpool::try_from_slice(&account_data[8..]) {
         Ok(whirlpool) => Ok(whirlpool),
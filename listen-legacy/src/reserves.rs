@@ -0,0 +1,222 @@
+//! Per-DEX reserve reading for the seller exit logic; the lamports of the
+//! SOL vault are only the tradable liquidity for constant product pools,
+//! concentrated liquidity pools hold the liquidity of all ranges in their
+//! vaults and Meteora pools keep theirs in lending vaults
+use std::error::Error;
+
+use anchor_lang::AnchorDeserialize;
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::constants;
+use crate::orca::{parse_whirlpool, Whirlpool};
+use crate::seller_service::load_amm_keys;
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PoolType {
+    /// Raydium AMM v4, constant product over the vaults
+    #[default]
+    Raydium,
+    /// Orca Whirlpool, concentrated liquidity
+    Whirlpool,
+    /// Meteora dynamic AMM, vault backed
+    Meteora,
+}
+
+/// raw amounts, lamports for SOL
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PoolReserves {
+    pub token: u64,
+    pub sol: u64,
+}
+
+/// virtual reserves of the active range: x = L / sqrt(P), y = L * sqrt(P),
+/// which is what a swap at the current price actually trades against
+pub fn whirlpool_reserves(whirlpool: &Whirlpool) -> PoolReserves {
+    let liquidity = whirlpool.liquidity as f64;
+    let sqrt_price = whirlpool.sqrt_price as f64 / 2f64.powi(64);
+    if sqrt_price == 0. {
+        return PoolReserves::default();
+    }
+    let reserve_a = (liquidity / sqrt_price) as u64;
+    let reserve_b = (liquidity * sqrt_price) as u64;
+    if whirlpool.token_mint_a.eq(&constants::SOLANA_PROGRAM_ID) {
+        PoolReserves {
+            token: reserve_b,
+            sol: reserve_a,
+        }
+    } else {
+        PoolReserves {
+            token: reserve_a,
+            sol: reserve_b,
+        }
+    }
+}
+
+/// leading fields of the dynamic AMM `Pool` account
+#[derive(AnchorDeserialize, Debug)]
+pub struct MeteoraPool {
+    pub lp_mint: Pubkey,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub a_vault: Pubkey,
+    pub b_vault: Pubkey,
+    pub a_vault_lp: Pubkey,
+    pub b_vault_lp: Pubkey,
+}
+
+/// leading fields of the Meteora `Vault` account
+#[derive(AnchorDeserialize, Debug)]
+pub struct MeteoraVault {
+    pub enabled: u8,
+    pub vault_bump: u8,
+    pub token_vault_bump: u8,
+    pub total_amount: u64,
+    pub token_vault: Pubkey,
+    pub fee_vault: Pubkey,
+    pub token_mint: Pubkey,
+    pub lp_mint: Pubkey,
+}
+
+/// the pool owns `pool_lp` of the vault's `lp_supply` lp tokens
+pub fn meteora_vault_share(
+    vault_total_amount: u64,
+    pool_lp: u64,
+    lp_supply: u64,
+) -> u64 {
+    if lp_supply == 0 {
+        return 0;
+    }
+    (vault_total_amount as u128 * pool_lp as u128 / lp_supply as u128) as u64
+}
+
+async fn get_meteora_side(
+    rpc_client: &RpcClient,
+    vault: &Pubkey,
+    pool_lp: &Pubkey,
+) -> Result<u64, Box<dyn Error>> {
+    let data = rpc_client.get_account_data(vault).await?;
+    let vault = MeteoraVault::deserialize(&mut &data[8..])?;
+    let pool_lp = rpc_client
+        .get_token_account_balance(pool_lp)
+        .await?
+        .amount
+        .parse::<u64>()?;
+    let lp_supply = rpc_client
+        .get_token_supply(&vault.lp_mint)
+        .await?
+        .amount
+        .parse::<u64>()?;
+    Ok(meteora_vault_share(vault.total_amount, pool_lp, lp_supply))
+}
+
+pub async fn get_pool_reserves(
+    pool_type: PoolType,
+    pool: &Pubkey,
+    rpc_client: &RpcClient,
+) -> Result<PoolReserves, Box<dyn Error>> {
+    match pool_type {
+        PoolType::Raydium => {
+            let amm_keys = load_amm_keys(
+                rpc_client,
+                &constants::RAYDIUM_LIQUIDITY_POOL_V4_PUBKEY,
+                pool,
+            )
+            .await?;
+            let (token_vault, sol_vault) =
+                if amm_keys.amm_coin_mint.eq(&constants::SOLANA_PROGRAM_ID) {
+                    (amm_keys.amm_pc_vault, amm_keys.amm_coin_vault)
+                } else {
+                    (amm_keys.amm_coin_vault, amm_keys.amm_pc_vault)
+                };
+            let token = rpc_client
+                .get_token_account_balance(&token_vault)
+                .await?
+                .amount
+                .parse::<u64>()?;
+            let sol = rpc_client
+                .get_token_account_balance(&sol_vault)
+                .await?
+                .amount
+                .parse::<u64>()?;
+            Ok(PoolReserves { token, sol })
+        }
+        PoolType::Whirlpool => {
+            let data = rpc_client.get_account_data(pool).await?;
+            Ok(whirlpool_reserves(&parse_whirlpool(&data)?))
+        }
+        PoolType::Meteora => {
+            let data = rpc_client.get_account_data(pool).await?;
+            let meteora_pool = MeteoraPool::deserialize(&mut &data[8..])?;
+            let reserve_a = get_meteora_side(
+                rpc_client,
+                &meteora_pool.a_vault,
+                &meteora_pool.a_vault_lp,
+            )
+            .await?;
+            let reserve_b = get_meteora_side(
+                rpc_client,
+                &meteora_pool.b_vault,
+                &meteora_pool.b_vault_lp,
+            )
+            .await?;
+            if meteora_pool.token_a_mint.eq(&constants::SOLANA_PROGRAM_ID) {
+                Ok(PoolReserves {
+                    token: reserve_b,
+                    sol: reserve_a,
+                })
+            } else {
+                Ok(PoolReserves {
+                    token: reserve_a,
+                    sol: reserve_b,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[tokio::test]
+    async fn test_whirlpool_reserves_on_chain() {
+        let rpc_client =
+            RpcClient::new("https://api.mainnet-beta.solana.com".to_string());
+        // SOL/$neiro, the pool of `orca::tests::test_get_whirlpool`
+        let pool =
+            Pubkey::from_str("26nkKSE4YbYASbnvjSnrKkFxcJkfr7RxQ5tTPSd4jSoY")
+                .unwrap();
+        let data = rpc_client.get_account_data(&pool).await.unwrap();
+        let whirlpool = parse_whirlpool(&data).unwrap();
+        assert_eq!(whirlpool.token_mint_a, constants::SOLANA_PROGRAM_ID);
+        assert!(whirlpool.liquidity > 0);
+
+        // the pool stores its tick apart from the sqrt price, the price of
+        // the reserves has to be within the tick the pool says it is in
+        let reserves = whirlpool_reserves(&whirlpool);
+        let price = reserves.token as f64 / reserves.sol as f64;
+        let tick_price = 1.0001f64.powi(whirlpool.tick_current_index);
+        assert!(
+            (price / tick_price - 1.).abs() < 2e-4,
+            "reserves price {} tick price {}",
+            price,
+            tick_price
+        );
+    }
+
+    #[test]
+    fn test_meteora_vault_share() {
+        assert_eq!(meteora_vault_share(1_000, 250, 1_000), 250);
+        assert_eq!(meteora_vault_share(1_000, 250, 0), 0);
+        assert_eq!(
+            meteora_vault_share(u64::MAX, u64::MAX / 2, u64::MAX),
+            u64::MAX / 2
+        );
+    }
+}
@@ -1,14 +1,17 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use crate::execute::Executor;
 use crate::http_client::HttpClient;
+use crate::jup::Jupiter;
+use crate::reserves::PoolType;
 use crate::util::healthz;
 use crate::{
     buyer,
     util::{env, pubkey_to_string, string_to_pubkey},
 };
-use crate::{constants, seller};
+use crate::{constants, jito, raydium, seller};
 use actix_web::web::{self, Json};
 use actix_web::{get, post};
 use actix_web::{App, Error, HttpResponse, HttpServer};
@@ -25,7 +28,9 @@ use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Keypair;
 use solana_sdk::signer::{EncodableKey, Signer};
-use tokio::sync::RwLock;
+use solana_sdk::transaction::Transaction;
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::AbortHandle;
 
 #[derive(Deserialize, Serialize)]
 pub struct SellRequest {
@@ -46,17 +51,254 @@ pub struct SellRequest {
     pub output_mint: Pubkey,
     pub lamports_spent: u64,
     pub insta: Option<bool>,
+    /// determines how the exit logic reads the pool reserves
+    #[serde(default)]
+    pub pool_type: PoolType,
+}
+
+/// A position the seller service is currently managing (tp/sl or insta)
+#[derive(Debug, Clone, Serialize)]
+pub struct Position {
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub amm_pool: Pubkey,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub input_mint: Pubkey,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub output_mint: Pubkey,
+    pub lamports_spent: u64,
+    pub pool_type: PoolType,
+}
+
+impl From<&SellRequest> for Position {
+    fn from(sell_request: &SellRequest) -> Self {
+        Self {
+            amm_pool: sell_request.amm_pool,
+            input_mint: sell_request.input_mint,
+            output_mint: sell_request.output_mint,
+            lamports_spent: sell_request.lamports_spent,
+            pool_type: sell_request.pool_type,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PanicSell {
+    pub mint: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PanicFailure {
+    pub mint: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PanicResult {
+    pub sold: Vec<PanicSell>,
+    pub failed: Vec<PanicFailure>,
+}
+
+struct ActivePosition {
+    /// distinguishes re-registrations of the same mint
+    id: u64,
+    position: Position,
+    task: Option<AbortHandle>,
+}
+
+/// Active positions keyed by the token mint, along with the task running
+/// the tp/sl executor so it can be aborted on panic
+#[derive(Default)]
+pub struct PositionRegistry {
+    active: std::sync::Mutex<HashMap<Pubkey, ActivePosition>>,
+    next_id: AtomicU64,
+    /// mint -> signature of the panic sell, held for the whole panic so
+    /// that concurrent calls don't sell twice
+    panic_sold: Mutex<HashMap<Pubkey, String>>,
+}
+
+impl PositionRegistry {
+    pub fn register(&self, position: Position) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.active.lock().unwrap().insert(
+            position.input_mint,
+            ActivePosition {
+                id,
+                position,
+                task: None,
+            },
+        );
+        id
+    }
+
+    pub fn set_task(&self, mint: &Pubkey, id: u64, task: AbortHandle) {
+        if let Some(active) = self.active.lock().unwrap().get_mut(mint) {
+            if active.id == id {
+                active.task = Some(task);
+            }
+        }
+    }
+
+    /// no-op if the mint has been re-registered since
+    pub fn remove(&self, mint: &Pubkey, id: u64) {
+        let mut active = self.active.lock().unwrap();
+        if active.get(mint).is_some_and(|a| a.id == id) {
+            active.remove(mint);
+        }
+    }
+
+    pub fn active(&self) -> Vec<Position> {
+        self.active
+            .lock()
+            .unwrap()
+            .values()
+            .map(|active| active.position.clone())
+            .collect()
+    }
+
+    /// Stops the executors of all active positions and sells each of them
+    /// with `sell`; positions that fail to sell stay active so that the
+    /// panic can be retried, calling it again after a successful panic
+    /// returns the same signatures without selling anything
+    pub async fn panic_sell<F, Fut>(&self, sell: F) -> PanicResult
+    where
+        F: Fn(Position) -> Fut,
+        Fut: std::future::Future<Output = Result<String, String>>,
+    {
+        let mut panic_sold = self.panic_sold.lock().await;
+        let positions: Vec<ActivePosition> = self
+            .active
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, active)| active)
+            .collect();
+
+        let mut result = PanicResult::default();
+        for ActivePosition { position, task, .. } in positions {
+            if let Some(task) = task {
+                task.abort();
+            }
+            match sell(position.clone()).await {
+                Ok(signature) => {
+                    panic_sold.insert(position.input_mint, signature);
+                }
+                Err(e) => {
+                    error!(
+                        "panic sell failed for {}: {}",
+                        position.input_mint, e
+                    );
+                    result.failed.push(PanicFailure {
+                        mint: position.input_mint.to_string(),
+                        error: e,
+                    });
+                    self.register(position);
+                }
+            }
+        }
+
+        result.sold = panic_sold
+            .iter()
+            .map(|(mint, signature)| PanicSell {
+                mint: mint.to_string(),
+                signature: signature.clone(),
+            })
+            .collect();
+        result.sold.sort_by(|a, b| a.mint.cmp(&b.mint));
+        result
+    }
+}
+
+/// removes the position once its sell task finishes, whichever way it exits
+struct PositionGuard {
+    registry: web::Data<Arc<PositionRegistry>>,
+    mint: Pubkey,
+    id: u64,
+}
+
+impl Drop for PositionGuard {
+    fn drop(&mut self) {
+        self.registry.remove(&self.mint, self.id);
+    }
+}
+
+/// sells the whole token balance of the position to SOL at market
+pub async fn market_sell(
+    position: &Position,
+    wallet: &Keypair,
+    rpc_client: &RpcClient,
+) -> Result<String, String> {
+    let token_account =
+        spl_associated_token_account::get_associated_token_address(
+            &wallet.pubkey(),
+            &position.input_mint,
+        );
+    let balance = seller::get_spl_balance(rpc_client, &token_account)
+        .await
+        .map_err(|e| format!("could not fetch balance: {}", e))?;
+    if balance == 0 {
+        return Err("no balance to sell".to_string());
+    }
+    if position.pool_type != PoolType::Raydium {
+        let quote = Jupiter::fetch_quote(
+            &position.input_mint.to_string(),
+            &position.output_mint.to_string(),
+            balance,
+            500,
+        )
+        .await
+        .map_err(|e| format!("fetch quote: {}", e))?;
+        return Jupiter::swap(quote, wallet)
+            .await
+            .map_err(|e| format!("swap (jupiter): {}", e));
+    }
+    let swap_context = raydium::make_swap_context(
+        rpc_client,
+        position.amm_pool,
+        position.input_mint,
+        position.output_mint,
+        wallet,
+        0,
+        balance,
+    )
+    .await
+    .map_err(|e| format!("make swap context: {}", e))?;
+    let ixs = raydium::make_swap_ixs(rpc_client, wallet, &swap_context, true)
+        .await
+        .map_err(|e| format!("make swap ixs: {}", e))?;
+    let blockhash = rpc_client
+        .get_latest_blockhash()
+        .await
+        .map_err(|e| format!("get blockhash: {}", e))?;
+    let tx = Transaction::new_signed_with_payer(
+        ixs.as_slice(),
+        Some(&wallet.pubkey()),
+        &[wallet],
+        blockhash,
+    );
+    jito::send_jito_tx(tx)
+        .await
+        .map_err(|e| format!("send tx (jito): {}", e))
 }
 
 #[post("/sell")]
 async fn handle_sell(
     sell_request: Json<SellRequest>,
+    positions: web::Data<Arc<PositionRegistry>>,
 ) -> Result<HttpResponse, Error> {
     info!(
         "handling sell_request {}",
         serde_json::to_string_pretty(&sell_request)?
     );
-    actix_rt::spawn(async move {
+    let mint = sell_request.input_mint;
+    let id = positions.register(Position::from(&*sell_request));
+    let guard = PositionGuard {
+        registry: positions.clone(),
+        mint,
+        id,
+    };
+    let task = actix_rt::spawn(async move {
+        let _guard = guard;
         let Ok(wallet) = Keypair::read_from_file(env("FUND_KEYPAIR_PATH"))
         else {
             error!("Failed to read wallet");
@@ -86,24 +328,30 @@ async fn handle_sell(
         // rn I think the crucial thing is to get rid of the rugs where someone
         // even though all checks pass, some holder dumps $XXK and -99.9%s the token
         if !sell_request.insta.unwrap_or(false) {
-            // load amm keys
-            let amm_program = constants::RAYDIUM_LIQUIDITY_POOL_V4_PUBKEY;
-            let amm_keys = match load_amm_keys(
-                &rpc_client,
-                &amm_program,
-                &sell_request.amm_pool,
-            )
-            .await
-            {
-                Ok(amm_keys) => amm_keys,
-                Err(e) => {
-                    error!("could not load amm keys: {}", e);
-                    return;
+            // amm keys are only needed to stream the raydium vaults
+            let amm_keys = if sell_request.pool_type == PoolType::Raydium {
+                let amm_program = constants::RAYDIUM_LIQUIDITY_POOL_V4_PUBKEY;
+                match load_amm_keys(
+                    &rpc_client,
+                    &amm_program,
+                    &sell_request.amm_pool,
+                )
+                .await
+                {
+                    Ok(amm_keys) => Some(amm_keys),
+                    Err(e) => {
+                        error!("could not load amm keys: {}", e);
+                        return;
+                    }
                 }
+            } else {
+                None
             };
 
             let mut executor = Executor {
                 amm_keys,
+                pool_type: sell_request.pool_type,
+                token_mint: sell_request.input_mint,
                 funder: wallet,
                 lamports_in: sell_request.lamports_spent,
                 token_balance: balance,
@@ -133,7 +381,26 @@ async fn handle_sell(
                 warn!("could not fetch balance, exiting");
                 return;
             }
-            if let Err(e) = buyer::swap(
+            if sell_request.pool_type != PoolType::Raydium {
+                let signature = match Jupiter::fetch_quote(
+                    &sell_request.input_mint.to_string(),
+                    &sell_request.output_mint.to_string(),
+                    balance,
+                    500,
+                )
+                .await
+                {
+                    Ok(quote) => Jupiter::swap(quote, &wallet).await,
+                    Err(e) => Err(e),
+                };
+                match signature {
+                    Ok(signature) => info!("sold ({})", signature),
+                    Err(e) => {
+                        error!("could not swap: {}", e);
+                        return;
+                    }
+                }
+            } else if let Err(e) = buyer::swap(
                 &sell_request.amm_pool,
                 &sell_request.input_mint,
                 &sell_request.output_mint,
@@ -150,10 +417,35 @@ async fn handle_sell(
 
         drop(pubsub_client)
     });
+    positions.set_task(&mint, id, task.abort_handle());
 
     Ok(HttpResponse::Ok().json(json!({"status": "OK, triggered sell"})))
 }
 
+#[post("/panic")]
+async fn handle_panic(
+    positions: web::Data<Arc<PositionRegistry>>,
+) -> Result<HttpResponse, Error> {
+    warn!("panic: selling {} positions", positions.active().len());
+    let wallet =
+        Keypair::read_from_file(env("FUND_KEYPAIR_PATH")).map_err(|e| {
+            actix_web::error::ErrorInternalServerError(format!(
+                "could not read wallet: {}",
+                e
+            ))
+        })?;
+    let rpc_client = RpcClient::new(env("RPC_URL"));
+    let result = positions
+        .panic_sell(|position| {
+            let wallet = &wallet;
+            let rpc_client = &rpc_client;
+            async move { market_sell(&position, wallet, rpc_client).await }
+        })
+        .await;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct SimpleSellRequest {
     #[serde(
@@ -202,6 +494,7 @@ async fn handle_sell_simple(
             output_mint,
             lamports_spent: 0u64,
             insta: Some(true),
+            pool_type: PoolType::Raydium,
         })
         .await
         .map_err(|e| {
@@ -289,9 +582,12 @@ pub async fn run_seller_service() -> std::io::Result<()> {
     // tokio::spawn(async move {
     //     poll.track_lamports_balance(&wallet.pubkey()).await;
     // });
+    let positions = Arc::new(PositionRegistry::default());
     HttpServer::new(move || {
         App::new()
+            .app_data(web::Data::new(positions.clone()))
             .service(handle_sell)
+            .service(handle_panic)
             .service(handle_sell_simple)
             .service(handle_balance)
             .service(healthz)
@@ -367,3 +663,52 @@ where
     }
     Err(format!("could not get account {} after 6 retries", addr).into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    fn make_position() -> Position {
+        Position {
+            amm_pool: Pubkey::new_unique(),
+            input_mint: Pubkey::new_unique(),
+            output_mint: constants::SOLANA_PROGRAM_ID,
+            lamports_spent: 1_000_000,
+            pool_type: PoolType::Raydium,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_panic_sells_all_positions() {
+        let registry = PositionRegistry::default();
+        let first = make_position();
+        let second = make_position();
+        registry.register(first.clone());
+        registry.register(second.clone());
+
+        let sells = AtomicUsize::new(0);
+        let sell = |position: Position| {
+            sells.fetch_add(1, Ordering::SeqCst);
+            async move { Ok(format!("sig-{}", position.input_mint)) }
+        };
+
+        let result = registry.panic_sell(&sell).await;
+        assert_eq!(sells.load(Ordering::SeqCst), 2);
+        assert_eq!(result.sold.len(), 2);
+        assert!(result.failed.is_empty());
+        for position in [&first, &second] {
+            assert!(result
+                .sold
+                .iter()
+                .any(|s| s.mint == position.input_mint.to_string()
+                    && s.signature == format!("sig-{}", position.input_mint)));
+        }
+        assert!(registry.active().is_empty());
+
+        // idempotent, nothing is sold twice
+        let again = registry.panic_sell(&sell).await;
+        assert_eq!(sells.load(Ordering::SeqCst), 2);
+        assert_eq!(again.sold.len(), 2);
+    }
+}
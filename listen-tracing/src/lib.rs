@@ -1,5 +1,45 @@
-use tracing_subscriber::{filter::EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+use tracing::Subscriber;
+use tracing_subscriber::{
+    filter::EnvFilter, fmt::MakeWriter, layer::SubscriberExt, registry::LookupSpan,
+    util::SubscriberInitExt, Layer,
+};
 
+/// Output format of the logs, from `LOG_FORMAT`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Pretty,
+    /// one JSON object per line, for log ingestion
+    Json,
+}
+
+impl LogFormat {
+    /// `LOG_FORMAT=json|pretty`, pretty when unset or unknown
+    pub fn from_env() -> Self {
+        match std::env::var("LOG_FORMAT") {
+            Ok(format) if format.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Pretty,
+        }
+    }
+}
+
+/// JSON lines with the event fields at the top level next to `timestamp`,
+/// `level` and `target`
+pub fn json_layer<S, W>(writer: W) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt::layer()
+        .json()
+        .with_target(true)
+        .with_current_span(true)
+        .with_span_list(false)
+        .flatten_event(true)
+        .with_writer(writer)
+}
+
+/// Reads the levels from `RUST_LOG`, which takes per module directives, e.g.
+/// `info,listen_data=debug,hyper=warn`, and the format from `LOG_FORMAT`
 pub fn setup_tracing() {
     // Create an EnvFilter that reads from RUST_LOG with INFO as default
     let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
@@ -12,6 +52,11 @@ pub fn setup_tracing() {
             .with(journald_layer)
             .with(env_filter)
             .init();
+    } else if LogFormat::from_env() == LogFormat::Json {
+        tracing_subscriber::registry()
+            .with(json_layer(std::io::stdout))
+            .with(env_filter)
+            .init();
     } else {
         // Use standard formatting for non-systemd environments
         tracing_subscriber::fmt()
@@ -21,3 +66,65 @@ pub fn setup_tracing() {
             .init();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let buffer = Buffer::default();
+        let subscriber = tracing_subscriber::registry().with(json_layer(buffer.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("swap", slot = 42);
+            let _guard = span.enter();
+            tracing::info!(
+                mint = "So11111111111111111111111111111111111111112",
+                "price update"
+            );
+            tracing::warn!(attempt = 2, "retrying");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+
+        let info = &lines[0];
+        assert!(info["timestamp"].is_string());
+        assert_eq!(info["level"], "INFO");
+        assert_eq!(info["target"], "listen_tracing::tests");
+        assert_eq!(info["message"], "price update");
+        assert_eq!(info["mint"], "So11111111111111111111111111111111111111112");
+        assert_eq!(info["span"]["name"], "swap");
+        assert_eq!(info["span"]["slot"], 42);
+
+        assert_eq!(lines[1]["level"], "WARN");
+        assert_eq!(lines[1]["attempt"], 2);
+    }
+}
@@ -1,13 +1,21 @@
 pub mod auth;
 pub mod caip2;
 pub mod config;
+pub mod retry;
 pub mod tx;
 pub mod types;
 pub mod util;
 
+pub const PRIVY_API_URL: &str = "https://api.privy.io";
+
 pub struct Privy {
     pub config: config::PrivyConfig,
     pub client: reqwest::Client,
+    /// base of the wallet RPC endpoint, overridable for tests
+    pub api_url: String,
+    pub retry_policy: retry::RetryPolicy,
+    pub retry_metrics: std::sync::Arc<retry::RetryMetrics>,
+    retry_observer: Option<std::sync::Arc<dyn retry::RetryObserver>>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -25,6 +33,35 @@ pub enum PrivyError {
 impl Privy {
     pub fn new(config: config::PrivyConfig) -> Self {
         let client = util::create_privy_client(&config);
-        Self { config, client }
+        Self {
+            config,
+            client,
+            api_url: PRIVY_API_URL.to_string(),
+            retry_policy: retry::RetryPolicy::default(),
+            retry_metrics: Default::default(),
+            retry_observer: None,
+        }
+    }
+
+    pub fn with_api_url(mut self, api_url: impl Into<String>) -> Self {
+        self.api_url = api_url.into();
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: retry::RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn with_retry_observer(
+        mut self,
+        retry_observer: std::sync::Arc<dyn retry::RetryObserver>,
+    ) -> Self {
+        self.retry_observer = Some(retry_observer);
+        self
+    }
+
+    pub(crate) fn wallet_rpc_url(&self) -> String {
+        format!("{}/v1/wallets/rpc", self.api_url)
     }
 }
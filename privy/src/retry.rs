@@ -0,0 +1,142 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// header privy deduplicates wallet RPC requests on, a retried send with the
+/// same key returns the result of the first one instead of sending again
+pub const IDEMPOTENCY_KEY_HEADER: &str = "privy-idempotency-key";
+
+/// How rate limited (429) and failing (5xx) wallet RPC calls are retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// doubled on every retry, up to `max_backoff`
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+    /// total time spent on a call, including waits, after which the last
+    /// failure is returned
+    pub deadline: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(5),
+            deadline: Duration::from_secs(20),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// wait before retry number `attempt` (starting at 0), `Retry-After`
+    /// takes precedence but is still capped
+    pub fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        retry_after
+            .unwrap_or_else(|| {
+                self.base_backoff
+                    .saturating_mul(2u32.saturating_pow(attempt))
+            })
+            .min(self.max_backoff)
+    }
+}
+
+pub fn is_retryable(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// `Retry-After` in seconds, the http-date form is not used by privy
+pub fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// unique per send, kept across its retries
+pub fn idempotency_key() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!(
+        "{:x}-{:x}-{:x}",
+        nanos,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Counters of the retries made and of the calls that failed after them
+#[derive(Debug, Default)]
+pub struct RetryMetrics {
+    retries: AtomicU64,
+    failures: AtomicU64,
+}
+
+impl RetryMetrics {
+    pub fn retries(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record(&self, event: &RetryEvent) {
+        match event.backoff {
+            Some(_) => self.retries.fetch_add(1, Ordering::Relaxed),
+            None => self.failures.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryEvent {
+    /// wallet RPC method, e.g. `signAndSendTransaction`
+    pub method: String,
+    /// the failed attempt, starting at 1
+    pub attempt: u32,
+    /// `None` when the request didn't get a response
+    pub status: Option<u16>,
+    pub error: String,
+    /// wait before the next attempt, `None` once the call is given up on
+    pub backoff: Option<Duration>,
+}
+
+/// Notified of every retry and final failure, called on the task making
+/// the request
+pub trait RetryObserver: Send + Sync {
+    fn on_retry(&self, event: &RetryEvent);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0, None), Duration::from_millis(250));
+        assert_eq!(policy.backoff(2, None), Duration::from_secs(1));
+        assert_eq!(policy.backoff(10, None), Duration::from_secs(5));
+        assert_eq!(
+            policy.backoff(0, Some(Duration::from_secs(2))),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            policy.backoff(0, Some(Duration::from_secs(60))),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn test_idempotency_keys_are_unique() {
+        assert_ne!(idempotency_key(), idempotency_key());
+    }
+}
@@ -1,13 +1,17 @@
 use crate::{
+    caip2::Caip2,
+    retry::{idempotency_key, is_retryable, retry_after, RetryEvent, IDEMPOTENCY_KEY_HEADER},
     types::{
-        SignAndSendEvmTransactionParams, SignAndSendEvmTransactionRequest,
+        EvmTransaction, SignAndSendEvmTransactionParams, SignAndSendEvmTransactionRequest,
         SignAndSendTransactionParams, SignAndSendTransactionRequest,
-        SignAndSendTransactionResponse,
+        SignAndSendTransactionResponse, SignTransactionRequest, SignTransactionResponse,
+        SignTypedDataParams, SignTypedDataRequest, SignTypedDataResponse,
     },
     Privy,
 };
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use std::time::Instant;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PrivyTransaction {
@@ -38,9 +42,108 @@ pub enum PrivyTransactionError {
 
     #[error("[Privy] HTTP request failed: {0}")]
     RequestError(#[from] reqwest::Error),
+
+    #[error("[Privy] The user denied the request: {0}")]
+    UserDenied(String),
+
+    #[error("[Privy] The request was blocked by a wallet policy: {0}")]
+    PolicyBlocked(String),
+}
+
+/// rejections that retrying won't fix, `None` for other failures
+pub fn classify_rejection(
+    status: reqwest::StatusCode,
+    body: &str,
+) -> Option<PrivyTransactionError> {
+    let lowercase = body.to_lowercase();
+    if lowercase.contains("policy") {
+        Some(PrivyTransactionError::PolicyBlocked(body.to_string()))
+    } else if lowercase.contains("denied")
+        || lowercase.contains("rejected")
+        || lowercase.contains("not delegated")
+        || status == reqwest::StatusCode::UNAUTHORIZED
+        || status == reqwest::StatusCode::FORBIDDEN
+    {
+        Some(PrivyTransactionError::UserDenied(body.to_string()))
+    } else {
+        None
+    }
 }
 
 impl Privy {
+    /// posts to the wallet RPC endpoint, retrying rate limits, 5xx and
+    /// requests that never got a response as `retry_policy` allows; the
+    /// last failed response is returned as is once retries run out
+    ///
+    /// sends pass an `idempotency_key`, it is the same for every attempt so
+    /// privy executes a transaction at most once even when a response was
+    /// lost after it went through
+    async fn post_wallet_rpc<T: Serialize>(
+        &self,
+        method: &str,
+        request: &T,
+        idempotency_key: Option<&str>,
+    ) -> Result<reqwest::Response, PrivyTransactionError> {
+        let started = Instant::now();
+        let mut attempt = 0;
+        loop {
+            let mut builder = self.client.post(self.wallet_rpc_url()).json(request);
+            if let Some(key) = idempotency_key {
+                builder = builder.header(IDEMPOTENCY_KEY_HEADER, key);
+            }
+            let result = builder.send().await;
+            let (status, wait, error) = match &result {
+                Ok(response) if is_retryable(response.status()) => (
+                    Some(response.status().as_u16()),
+                    retry_after(response.headers()),
+                    response.status().to_string(),
+                ),
+                Err(e) if e.is_connect() || e.is_timeout() => (None, None, e.to_string()),
+                _ => return Ok(result?),
+            };
+
+            let backoff = self.retry_policy.backoff(attempt, wait);
+            attempt += 1;
+            let give_up = attempt > self.retry_policy.max_retries
+                || started.elapsed() + backoff > self.retry_policy.deadline;
+            self.record_retry(&RetryEvent {
+                method: method.to_string(),
+                attempt,
+                status,
+                error,
+                backoff: (!give_up).then_some(backoff),
+            });
+            if give_up {
+                return Ok(result?);
+            }
+            tokio::time::sleep(backoff).await;
+        }
+    }
+
+    fn record_retry(&self, event: &RetryEvent) {
+        self.retry_metrics.record(event);
+        match event.backoff {
+            Some(backoff) => tracing::warn!(
+                method = event.method,
+                attempt = event.attempt,
+                status = event.status,
+                ?backoff,
+                "Privy request failed, retrying: {}",
+                event.error
+            ),
+            None => tracing::error!(
+                method = event.method,
+                attempt = event.attempt,
+                status = event.status,
+                "Privy request failed, giving up: {}",
+                event.error
+            ),
+        }
+        if let Some(observer) = &self.retry_observer {
+            observer.on_retry(event);
+        }
+    }
+
     pub async fn execute_transaction(
         &self,
         transaction: PrivyTransaction,
@@ -87,18 +190,20 @@ impl Privy {
             params: SignAndSendEvmTransactionParams { transaction },
         };
 
+        let key = idempotency_key();
         let response = self
-            .client
-            .post("https://auth.privy.io/api/v1/wallets/rpc")
-            .json(&request)
-            .send()
+            .post_wallet_rpc(&request.method, &request, Some(&key))
             .await?;
 
-        if !response.status().is_success() {
-            return Err(PrivyTransactionError::ExecuteEvmTransactionError(anyhow!(
-                "Failed to send transaction: {}",
-                response.text().await?
-            )));
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(classify_rejection(status, &body).unwrap_or_else(|| {
+                PrivyTransactionError::ExecuteEvmTransactionError(anyhow!(
+                    "Failed to send transaction: {}",
+                    body
+                ))
+            }));
         }
 
         let result: SignAndSendTransactionResponse = response.json().await?;
@@ -111,6 +216,56 @@ impl Privy {
         Ok(result.data.hash)
     }
 
+    /// sends on the chain of `transaction.chain_id`
+    pub async fn send_evm_transaction(
+        &self,
+        address: String,
+        transaction: &EvmTransaction,
+    ) -> Result<String, PrivyTransactionError> {
+        let caip2 = Caip2::from_chain_id(transaction.chain_id).to_string();
+        let transaction = serde_json::to_value(transaction)
+            .map_err(|e| PrivyTransactionError::ExecuteEvmTransactionError(e.into()))?;
+        self.execute_evm_transaction(address, transaction, caip2)
+            .await
+    }
+
+    /// EIP-712 signature of `typed_data` (domain, types, primary_type,
+    /// message) by the embedded wallet
+    pub async fn sign_typed_data(
+        &self,
+        address: String,
+        typed_data: serde_json::Value,
+        caip2: String,
+    ) -> Result<String, PrivyTransactionError> {
+        tracing::info!(?address, "Signing typed data");
+        let request = SignTypedDataRequest {
+            address,
+            chain_type: "ethereum".to_string(),
+            method: "eth_signTypedData_v4".to_string(),
+            caip2,
+            params: SignTypedDataParams { typed_data },
+        };
+
+        let response = self
+            .post_wallet_rpc(&request.method, &request, None)
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(classify_rejection(status, &body).unwrap_or_else(|| {
+                PrivyTransactionError::ExecuteEvmTransactionError(anyhow!(
+                    "Failed to sign typed data: {}",
+                    body
+                ))
+            }));
+        }
+
+        let result: SignTypedDataResponse = response.json().await?;
+        tracing::info!(?result.method, ?result.data.encoding, "Typed data signed");
+        Ok(result.data.signature)
+    }
+
     pub async fn execute_solana_transaction(
         &self,
         address: String,
@@ -129,17 +284,20 @@ impl Privy {
             },
         };
 
+        let key = idempotency_key();
         let response = self
-            .client
-            .post("https://api.privy.io/v1/wallets/rpc")
-            .json(&request)
-            .send()
+            .post_wallet_rpc(&request.method, &request, Some(&key))
             .await?;
 
-        if !response.status().is_success() {
-            return Err(PrivyTransactionError::ExecuteSolanaTransactionError(
-                anyhow!("Failed to sign transaction: {}", response.text().await?),
-            ));
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(classify_rejection(status, &body).unwrap_or_else(|| {
+                PrivyTransactionError::ExecuteSolanaTransactionError(anyhow!(
+                    "Failed to sign transaction: {}",
+                    body
+                ))
+            }));
         }
 
         let result: SignAndSendTransactionResponse = response.json().await?;
@@ -151,17 +309,312 @@ impl Privy {
         );
         Ok(result.data.hash)
     }
+
+    /// signs a base64 Solana transaction without sending it, returns the
+    /// signed transaction in base64
+    pub async fn sign_solana_transaction(
+        &self,
+        address: String,
+        transaction: String,
+    ) -> Result<String, PrivyTransactionError> {
+        tracing::info!(?address, "Signing Solana transaction");
+        let request = SignTransactionRequest {
+            address,
+            chain_type: "solana".to_string(),
+            method: "signTransaction".to_string(),
+            params: SignAndSendTransactionParams {
+                transaction,
+                encoding: "base64".to_string(),
+            },
+        };
+
+        // nothing is sent, a retry can't land it twice
+        let response = self
+            .post_wallet_rpc(&request.method, &request, None)
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(classify_rejection(status, &body).unwrap_or_else(|| {
+                PrivyTransactionError::ExecuteSolanaTransactionError(anyhow!(
+                    "Failed to sign transaction: {}",
+                    body
+                ))
+            }));
+        }
+
+        let result: SignTransactionResponse = response.json().await?;
+        tracing::info!(?result.method, ?result.data.encoding, "Transaction signed");
+        Ok(result.data.signed_transaction)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::caip2::Caip2;
-    use crate::config::PrivyConfig;
+    use crate::{config::PrivyConfig, retry::RetryPolicy};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
     use super::*;
 
     const TEST_ADDRESS_EVM: &str = "0x123"; // fill in
 
+    const WALLET: &str = "0xfe86bbcA0048262853432e66c33F33dCAC331428";
+    const USDC_ARBITRUM: &str = "0xaf88d065e77c8cC2239327C5EDb3A432268e5831";
+
+    fn mock_privy(server: &mockito::Server) -> Privy {
+        Privy::new(PrivyConfig {
+            app_id: "app".to_string(),
+            app_secret: "secret".to_string(),
+            verification_key: String::new(),
+        })
+        .with_api_url(server.url())
+    }
+
+    /// `transfer(WALLET, 1 USDC)`
+    fn erc20_transfer() -> EvmTransaction {
+        EvmTransaction {
+            to: Some(USDC_ARBITRUM.to_string()),
+            chain_id: 42161,
+            data: Some(format!(
+                "0xa9059cbb{:0>64}{:064x}",
+                WALLET.trim_start_matches("0x").to_lowercase(),
+                1_000_000
+            )),
+            gas_limit: Some("0x186a0".to_string()),
+            tx_type: Some(2),
+            max_fee_per_gas: Some("0x5f5e100".to_string()),
+            max_priority_fee_per_gas: Some("0x0".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_erc20_transfer() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/wallets/rpc")
+            .match_header("privy-app-id", "app")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "address": WALLET,
+                "chain_type": "ethereum",
+                "method": "eth_sendTransaction",
+                "caip2": "eip155:42161",
+                "params": {
+                    "transaction": {
+                        "to": USDC_ARBITRUM,
+                        "chain_id": 42161,
+                        "data": "0xa9059cbb000000000000000000000000fe86bbca0048262853432e66c33f33dcac33142800000000000000000000000000000000000000000000000000000000000f4240",
+                        "gas_limit": "0x186a0",
+                        "type": 2,
+                        "max_fee_per_gas": "0x5f5e100",
+                        "max_priority_fee_per_gas": "0x0",
+                    }
+                }
+            })))
+            .with_body(
+                r#"{"method": "eth_sendTransaction", "data": {"hash": "0xabc", "caip2": "eip155:42161"}}"#,
+            )
+            .create_async()
+            .await;
+
+        let hash = mock_privy(&server)
+            .send_evm_transaction(WALLET.to_string(), &erc20_transfer())
+            .await
+            .unwrap();
+        assert_eq!(hash, "0xabc");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_rejections() {
+        let mut server = mockito::Server::new_async().await;
+        let privy = mock_privy(&server);
+
+        let mock = server
+            .mock("POST", "/v1/wallets/rpc")
+            .with_status(400)
+            .with_body(r#"{"error": "Transaction violates wallet policy"}"#)
+            .create_async()
+            .await;
+        let err = privy
+            .send_evm_transaction(WALLET.to_string(), &erc20_transfer())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, PrivyTransactionError::PolicyBlocked(_)));
+        mock.remove_async().await;
+
+        server
+            .mock("POST", "/v1/wallets/rpc")
+            .with_status(403)
+            .with_body(r#"{"error": "Wallet is not delegated"}"#)
+            .create_async()
+            .await;
+        let err = privy
+            .send_evm_transaction(WALLET.to_string(), &erc20_transfer())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, PrivyTransactionError::UserDenied(_)));
+    }
+
+    #[tokio::test]
+    async fn test_sign_typed_data() {
+        let mut server = mockito::Server::new_async().await;
+        let typed_data = serde_json::json!({
+            "domain": {"name": "Permit2", "chainId": 42161},
+            "types": {"EIP712Domain": [{"name": "name", "type": "string"}]},
+            "primary_type": "EIP712Domain",
+            "message": {"name": "Permit2"},
+        });
+        let mock = server
+            .mock("POST", "/v1/wallets/rpc")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "method": "eth_signTypedData_v4",
+                "caip2": "eip155:42161",
+                "params": {"typed_data": typed_data},
+            })))
+            .with_body(
+                r#"{"method": "eth_signTypedData_v4", "data": {"signature": "0xsig", "encoding": "hex"}}"#,
+            )
+            .create_async()
+            .await;
+
+        let signature = mock_privy(&server)
+            .sign_typed_data(WALLET.to_string(), typed_data, Caip2::ARBITRUM.to_string())
+            .await
+            .unwrap();
+        assert_eq!(signature, "0xsig");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_sign_solana_transaction() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/wallets/rpc")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "method": "signTransaction",
+                "chain_type": "solana",
+                "params": {"transaction": "dHg=", "encoding": "base64"},
+            })))
+            .with_body(
+                r#"{"method": "signTransaction", "data": {"signed_transaction": "c2lnbmVk", "encoding": "base64"}}"#,
+            )
+            .create_async()
+            .await;
+
+        let signed = mock_privy(&server)
+            .sign_solana_transaction(WALLET.to_string(), "dHg=".to_string())
+            .await
+            .unwrap();
+        assert_eq!(signed, "c2lnbmVk");
+        mock.assert_async().await;
+    }
+
+    /// mocks a failure with `status` followed by a success, recording the
+    /// idempotency key of every request
+    async fn failing_then_ok(
+        server: &mut mockito::Server,
+        status: usize,
+    ) -> (mockito::Mock, mockito::Mock, Arc<Mutex<Vec<String>>>) {
+        let keys = Arc::new(Mutex::new(Vec::new()));
+        let record = |keys: Arc<Mutex<Vec<String>>>, body: &'static str| {
+            move |request: &mockito::Request| {
+                let key = request.header(IDEMPOTENCY_KEY_HEADER);
+                keys.lock()
+                    .unwrap()
+                    .extend(key.iter().map(|key| key.to_str().unwrap().to_string()));
+                body.as_bytes().to_vec()
+            }
+        };
+        let failure = server
+            .mock("POST", "/v1/wallets/rpc")
+            .with_status(status)
+            .with_header("retry-after", "0")
+            .with_body_from_request(record(keys.clone(), r#"{"error": "slow down"}"#))
+            .expect(1)
+            .create_async()
+            .await;
+        let success = server
+            .mock("POST", "/v1/wallets/rpc")
+            .with_body_from_request(record(
+                keys.clone(),
+                r#"{"method": "signAndSendTransaction", "data": {"hash": "sig", "caip2": "solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp"}}"#,
+            ))
+            .expect(1)
+            .create_async()
+            .await;
+        (failure, success, keys)
+    }
+
+    #[tokio::test]
+    async fn test_retries_send_once() {
+        for status in [429, 500] {
+            let mut server = mockito::Server::new_async().await;
+            let (failure, success, keys) = failing_then_ok(&mut server, status).await;
+            let privy = mock_privy(&server);
+
+            let hash = privy
+                .execute_solana_transaction(
+                    WALLET.to_string(),
+                    "dHg=".to_string(),
+                    Caip2::SOLANA.to_string(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(hash, "sig");
+            failure.assert_async().await;
+            success.assert_async().await;
+
+            // both attempts are the same send to privy
+            let keys = keys.lock().unwrap();
+            assert_eq!(keys.len(), 2);
+            assert_eq!(keys[0], keys[1]);
+            assert_eq!(privy.retry_metrics.retries(), 1);
+            assert_eq!(privy.retry_metrics.failures(), 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_retries() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/wallets/rpc")
+            .with_status(503)
+            .with_body("unavailable")
+            .expect(3)
+            .create_async()
+            .await;
+        let privy = mock_privy(&server).with_retry_policy(RetryPolicy {
+            max_retries: 2,
+            base_backoff: Duration::from_millis(1),
+            ..Default::default()
+        });
+
+        let err = privy
+            .send_evm_transaction(WALLET.to_string(), &erc20_transfer())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("unavailable"));
+        mock.assert_async().await;
+        assert_eq!(privy.retry_metrics.retries(), 2);
+        assert_eq!(privy.retry_metrics.failures(), 1);
+
+        // the deadline cuts retries short of `max_retries`
+        let privy = mock_privy(&server).with_retry_policy(RetryPolicy {
+            base_backoff: Duration::from_secs(1),
+            deadline: Duration::from_millis(500),
+            ..Default::default()
+        });
+        assert!(privy
+            .send_evm_transaction(WALLET.to_string(), &erc20_transfer())
+            .await
+            .is_err());
+        assert_eq!(privy.retry_metrics.retries(), 0);
+        assert_eq!(privy.retry_metrics.failures(), 1);
+    }
+
     #[tokio::test]
     #[ignore = "change the TEST_ADDRESS_EVM based on your environment before running"]
     async fn test_execute_order_eth() {
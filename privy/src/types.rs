@@ -14,6 +14,60 @@ pub struct SignAndSendEvmTransactionParams {
     pub transaction: serde_json::Value,
 }
 
+/// `eth_sendTransaction` params as privy takes them, quantities are hex
+/// strings; fields left out are filled in by privy
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EvmTransaction {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    pub chain_id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_limit: Option<String>,
+    /// 0 legacy, 2 EIP-1559
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub tx_type: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_price: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_gas: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_priority_fee_per_gas: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SignTypedDataRequest {
+    pub address: String,
+    pub chain_type: String, // Always "ethereum"
+    pub method: String,     // Always "eth_signTypedData_v4"
+    pub caip2: String,
+    pub params: SignTypedDataParams,
+}
+
+#[derive(Serialize)]
+pub struct SignTypedDataParams {
+    pub typed_data: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+pub struct SignTypedDataResponse {
+    pub method: String,
+    pub data: SignTypedDataData,
+}
+
+#[derive(Deserialize)]
+pub struct SignTypedDataData {
+    pub signature: String,
+    pub encoding: String,
+}
+
 // Request types for signing transactions
 #[derive(Serialize)]
 pub struct SignAndSendTransactionRequest {
@@ -42,6 +96,27 @@ pub struct SignAndSendTransactionData {
     pub caip2: String,
 }
 
+/// `signTransaction`, signs without sending
+#[derive(Serialize)]
+pub struct SignTransactionRequest {
+    pub address: String,
+    pub chain_type: String,
+    pub method: String,
+    pub params: SignAndSendTransactionParams,
+}
+
+#[derive(Deserialize)]
+pub struct SignTransactionResponse {
+    pub method: String,
+    pub data: SignTransactionData,
+}
+
+#[derive(Deserialize)]
+pub struct SignTransactionData {
+    pub signed_transaction: String,
+    pub encoding: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PrivyClaims {
     #[serde(rename = "aud")]